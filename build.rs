@@ -16,6 +16,10 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
+    // Generate the C header when building the C API
+    #[cfg(feature = "capi")]
+    generate_c_header();
+
     // Only configure linking if turbojpeg feature is enabled
     if env::var("CARGO_FEATURE_TURBOJPEG").is_err() {
         return;
@@ -93,3 +97,24 @@ fn main() {
         }
     }
 }
+
+/// Generates `rustvncserver.h` in `OUT_DIR` from the `capi` module using cbindgen.
+///
+/// The build never writes to the source tree; the `c_header` test checks that the
+/// checked-in `include/rustvncserver.h` matches, and refreshes it when asked to.
+#[cfg(feature = "capi")]
+fn generate_c_header() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(PathBuf::from(&crate_dir).join("cbindgen.toml"))
+        .expect("Invalid cbindgen.toml");
+    cbindgen::Builder::new()
+        .with_src(PathBuf::from(&crate_dir).join("src/capi.rs"))
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate C header")
+        .write_to_file(PathBuf::from(out_dir).join("rustvncserver.h"));
+}
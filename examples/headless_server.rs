@@ -47,7 +47,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     tokio::spawn(async move {
         while let Some(event) = events.recv().await {
             match event {
-                rustvncserver::server::ServerEvent::ClientConnected { client_id } => {
+                rustvncserver::server::ServerEvent::ClientConnected { client_id, .. } => {
                     println!("Client {} connected", client_id);
                 }
                 rustvncserver::server::ServerEvent::ClientDisconnected { client_id } => {
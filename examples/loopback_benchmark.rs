@@ -0,0 +1,378 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loopback throughput benchmark.
+//!
+//! Starts a server with a synthetic animated workload, then connects a "null client"
+//! over loopback once per encoding. The client negotiates that single encoding, keeps
+//! requesting incremental updates as fast as it can parse them (without decoding
+//! pixels), and reports end-to-end frames per second, throughput and the server's
+//! encode latency. Run it before and after a pipeline change for a repeatable
+//! comparison.
+//!
+//! Usage:
+//!   cargo run --release --example loopback_benchmark -- [seconds per encoding]
+
+use rustvncserver::protocol::{
+    ENCODING_CORRE, ENCODING_HEXTILE, ENCODING_RAW, ENCODING_RRE, ENCODING_TIGHT,
+    ENCODING_TIGHTPNG, ENCODING_ZLIB, ENCODING_ZRLE, ENCODING_ZYWRLE,
+};
+use rustvncserver::VncServer;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::TcpStream;
+
+const WIDTH: u16 = 1280;
+const HEIGHT: u16 = 720;
+/// Bytes per pixel in the server's default (RGBA32) pixel format.
+const BPP: usize = 4;
+/// Bytes per Tight/ZRLE compact pixel for a 24-bit depth format.
+const CPIXEL: usize = 3;
+/// `DesktopSize` pseudo-encoding.
+const ENCODING_DESKTOP_SIZE: i32 = -223;
+/// `LastRect` pseudo-encoding.
+const ENCODING_LAST_RECT: i32 = -224;
+
+/// Encodings measured, in order.
+const ENCODINGS: [(&str, i32); 9] = [
+    ("Raw", ENCODING_RAW),
+    ("RRE", ENCODING_RRE),
+    ("CoRRE", ENCODING_CORRE),
+    ("Hextile", ENCODING_HEXTILE),
+    ("Zlib", ENCODING_ZLIB),
+    ("ZRLE", ENCODING_ZRLE),
+    ("ZYWRLE", ENCODING_ZYWRLE),
+    ("Tight", ENCODING_TIGHT),
+    ("TightPng", ENCODING_TIGHTPNG),
+];
+
+/// Result of measuring one encoding.
+struct Measurement {
+    frames: u64,
+    bytes: u64,
+    elapsed: Duration,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let seconds: u64 = match std::env::args().nth(1) {
+        Some(arg) => arg.parse()?,
+        None => 5,
+    };
+
+    let (server, mut events) = VncServer::new(WIDTH, HEIGHT, "Benchmark".to_string(), None);
+    let server = Arc::new(server);
+    tokio::spawn(async move { while events.recv().await.is_some() {} });
+    let port = server.listen_auto(5900..=5999).await?;
+    tokio::spawn(generate_workload(server.framebuffer().clone()));
+
+    println!("Loopback benchmark: {WIDTH}x{HEIGHT}, {seconds}s per encoding, port {port}");
+    println!(
+        "{:<10} {:>10} {:>10} {:>12} {:>12}",
+        "encoding", "frames/s", "MB/s", "encode p50", "encode p99"
+    );
+
+    for (name, encoding) in ENCODINGS {
+        server.encode_stats().reset();
+        let result = measure(port, encoding, Duration::from_secs(seconds)).await;
+        let encode = server.encode_stats().encode.summary();
+        match result {
+            Ok(m) => {
+                let secs = m.elapsed.as_secs_f64();
+                #[allow(clippy::cast_precision_loss)]
+                let (fps, mbps) = (
+                    m.frames as f64 / secs,
+                    m.bytes as f64 / secs / (1024.0 * 1024.0),
+                );
+                println!(
+                    "{name:<10} {fps:>10.1} {mbps:>10.2} {:>12?} {:>12?}",
+                    encode.p50, encode.p99
+                );
+            }
+            Err(e) => println!("{name:<10} failed: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Animates the framebuffer: a scrolling gradient on the top half (photographic
+/// content) and moving blocks of "text" on the bottom half (synthetic UI content).
+async fn generate_workload(framebuffer: rustvncserver::Framebuffer) {
+    let (width, height) = (usize::from(WIDTH), usize::from(HEIGHT));
+    let mut pixels = vec![0u8; width * height * BPP];
+    let mut interval = tokio::time::interval(Duration::from_millis(16));
+    let mut frame = 0usize;
+    loop {
+        interval.tick().await;
+        for y in 0..height {
+            for x in 0..width {
+                let offset = (y * width + x) * BPP;
+                #[allow(clippy::cast_possible_truncation)] // Values are reduced modulo 256
+                let pixel = if y < height / 2 {
+                    [(x + frame) as u8, (y + frame / 2) as u8, (x + y) as u8, 255]
+                } else {
+                    let glyph = ((x / 8) * 7 + (y / 16) * 13 + frame / 4).is_multiple_of(5);
+                    let ink = glyph && (x % 8 < 5) && (y % 16 > 3) && ((x ^ y) & 1 == 0);
+                    if ink {
+                        [20, 20, 20, 255]
+                    } else {
+                        [240, 240, 240, 255]
+                    }
+                };
+                pixels[offset..offset + BPP].copy_from_slice(&pixel);
+            }
+        }
+        if framebuffer
+            .update_cropped(&pixels, 0, 0, WIDTH, HEIGHT)
+            .await
+            .is_err()
+        {
+            return;
+        }
+        frame += 1;
+    }
+}
+
+/// Connects a null client using `encoding` and counts the updates it receives.
+async fn measure(
+    port: u16,
+    encoding: i32,
+    duration: Duration,
+) -> Result<Measurement, Box<dyn Error>> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream.set_nodelay(true)?;
+    let (read_half, mut writer) = stream.into_split();
+    let mut reader = CountingReader::new(read_half);
+
+    // Handshake: version, security type None, shared session, ServerInit
+    let mut version = [0u8; 12];
+    reader.read_exact(&mut version).await?;
+    writer.write_all(b"RFB 003.008\n").await?;
+    let count = reader.u8().await?;
+    reader.skip(usize::from(count)).await?;
+    writer.write_all(&[1]).await?;
+    if reader.u32().await? != 0 {
+        return Err("security handshake failed".into());
+    }
+    writer.write_all(&[1]).await?;
+    reader.skip(20).await?;
+    let name_length = reader.u32().await? as usize;
+    reader.skip(name_length).await?;
+
+    let mut set_encodings = vec![2, 0, 0, 1];
+    set_encodings.extend_from_slice(&encoding.to_be_bytes());
+    writer.write_all(&set_encodings).await?;
+
+    let request = |incremental: u8| {
+        let mut message = vec![3, incremental, 0, 0, 0, 0];
+        message.extend_from_slice(&WIDTH.to_be_bytes());
+        message.extend_from_slice(&HEIGHT.to_be_bytes());
+        message
+    };
+    writer.write_all(&request(0)).await?;
+
+    let start = Instant::now();
+    reader.bytes = 0;
+    let mut frames = 0;
+    while start.elapsed() < duration {
+        match reader.u8().await? {
+            0 => {
+                if read_update(&mut reader).await? > 0 {
+                    frames += 1;
+                }
+                writer.write_all(&request(1)).await?;
+            }
+            2 => {} // Bell
+            3 => {
+                reader.skip(3).await?;
+                let length = reader.u32().await? as usize;
+                reader.skip(length).await?;
+            }
+            other => return Err(format!("unexpected server message {other}").into()),
+        }
+    }
+
+    Ok(Measurement {
+        frames,
+        bytes: reader.bytes,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Reads the body of a `FramebufferUpdate` and returns its rectangle count.
+async fn read_update(reader: &mut CountingReader) -> Result<u16, Box<dyn Error>> {
+    reader.skip(1).await?;
+    let rects = reader.u16().await?;
+    for _ in 0..rects {
+        reader.skip(4).await?; // x, y
+        let width = usize::from(reader.u16().await?);
+        let height = usize::from(reader.u16().await?);
+        let encoding = reader.u32().await?.cast_signed();
+        match encoding {
+            ENCODING_RAW => reader.skip(width * height * BPP).await?,
+            1 => reader.skip(4).await?, // CopyRect
+            ENCODING_RRE | ENCODING_CORRE => {
+                let subrects = reader.u32().await? as usize;
+                let subrect_size = if encoding == ENCODING_RRE { 8 } else { 4 };
+                reader.skip(BPP + subrects * (BPP + subrect_size)).await?;
+            }
+            ENCODING_HEXTILE => skip_hextile(reader, width, height).await?,
+            ENCODING_ZLIB | ENCODING_ZRLE | ENCODING_ZYWRLE => {
+                let length = reader.u32().await? as usize;
+                reader.skip(length).await?;
+            }
+            ENCODING_TIGHT | ENCODING_TIGHTPNG => skip_tight(reader, width, height).await?,
+            ENCODING_DESKTOP_SIZE => {}
+            ENCODING_LAST_RECT => break,
+            other => return Err(format!("cannot parse encoding {other}").into()),
+        }
+    }
+    Ok(rects)
+}
+
+/// Skips a Hextile-encoded rectangle.
+async fn skip_hextile(
+    reader: &mut CountingReader,
+    width: usize,
+    height: usize,
+) -> Result<(), Box<dyn Error>> {
+    for tile_y in (0..height).step_by(16) {
+        for tile_x in (0..width).step_by(16) {
+            let (tile_w, tile_h) = ((width - tile_x).min(16), (height - tile_y).min(16));
+            let subencoding = reader.u8().await?;
+            if subencoding & 1 != 0 {
+                reader.skip(tile_w * tile_h * BPP).await?;
+                continue;
+            }
+            if subencoding & 2 != 0 {
+                reader.skip(BPP).await?; // background
+            }
+            if subencoding & 4 != 0 {
+                reader.skip(BPP).await?; // foreground
+            }
+            if subencoding & 8 != 0 {
+                let subrects = usize::from(reader.u8().await?);
+                let colored = subencoding & 16 != 0;
+                reader
+                    .skip(subrects * (2 + if colored { BPP } else { 0 }))
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Skips a Tight or `TightPng` encoded rectangle.
+async fn skip_tight(
+    reader: &mut CountingReader,
+    width: usize,
+    height: usize,
+) -> Result<(), Box<dyn Error>> {
+    let control = reader.u8().await?;
+    match control >> 4 {
+        0x08 => return Ok(reader.skip(CPIXEL).await?), // Fill
+        0x09 | 0x0a => {
+            // JPEG or PNG
+            let length = reader.compact_length().await?;
+            return Ok(reader.skip(length).await?);
+        }
+        _ => {}
+    }
+
+    let mut data_size = width * height * CPIXEL;
+    if control & 0x40 != 0 && reader.u8().await? == 1 {
+        // Palette filter
+        let colors = usize::from(reader.u8().await?) + 1;
+        reader.skip(colors * CPIXEL).await?;
+        data_size = if colors == 2 {
+            width.div_ceil(8) * height
+        } else {
+            width * height
+        };
+    }
+    if data_size >= 12 {
+        data_size = reader.compact_length().await?;
+    }
+    Ok(reader.skip(data_size).await?)
+}
+
+/// Buffered reader that counts the bytes it consumes.
+struct CountingReader {
+    inner: BufReader<OwnedReadHalf>,
+    bytes: u64,
+    scratch: Vec<u8>,
+}
+
+impl CountingReader {
+    fn new(stream: OwnedReadHalf) -> Self {
+        Self {
+            inner: BufReader::with_capacity(256 * 1024, stream),
+            bytes: 0,
+            scratch: vec![0; 64 * 1024],
+        }
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        self.inner.read_exact(buf).await?;
+        self.bytes += buf.len() as u64;
+        Ok(())
+    }
+
+    async fn skip(&mut self, mut length: usize) -> std::io::Result<()> {
+        while length > 0 {
+            let chunk = length.min(self.scratch.len());
+            let mut scratch = std::mem::take(&mut self.scratch);
+            let result = self.read_exact(&mut scratch[..chunk]).await;
+            self.scratch = scratch;
+            result?;
+            length -= chunk;
+        }
+        Ok(())
+    }
+
+    async fn u8(&mut self) -> std::io::Result<u8> {
+        let mut buf = [0u8; 1];
+        self.read_exact(&mut buf).await?;
+        Ok(buf[0])
+    }
+
+    async fn u16(&mut self) -> std::io::Result<u16> {
+        let mut buf = [0u8; 2];
+        self.read_exact(&mut buf).await?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    async fn u32(&mut self) -> std::io::Result<u32> {
+        let mut buf = [0u8; 4];
+        self.read_exact(&mut buf).await?;
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    /// Reads a Tight compact length (1-3 bytes, 7 bits each).
+    async fn compact_length(&mut self) -> std::io::Result<usize> {
+        let byte = self.u8().await?;
+        let mut length = usize::from(byte & 0x7f);
+        if byte & 0x80 != 0 {
+            let byte = self.u8().await?;
+            length |= usize::from(byte & 0x7f) << 7;
+            if byte & 0x80 != 0 {
+                length |= usize::from(self.u8().await?) << 14;
+            }
+        }
+        Ok(length)
+    }
+}
@@ -0,0 +1,230 @@
+/* Copyright 2025 Dustin McAfee. Licensed under the Apache License, Version 2.0. */
+
+#ifndef RUSTVNCSERVER_H
+#define RUSTVNCSERVER_H
+
+/* Generated with cbindgen:0.29.4 */
+
+/* Generated by cbindgen from src/capi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The call succeeded.
+ */
+#define RVNC_OK 0
+
+/**
+ * A required pointer argument was null.
+ */
+#define RVNC_ERR_NULL -1
+
+/**
+ * An argument was invalid (bad size, invalid UTF-8, ...).
+ */
+#define RVNC_ERR_INVALID -2
+
+/**
+ * An I/O or runtime error occurred.
+ */
+#define RVNC_ERR_IO -3
+
+/**
+ * Kind of event returned by `rvnc_server_poll_event`.
+ */
+typedef enum RvncEventKind {
+  /**
+   * A client connected (`client_id`, `text` = subject of its verified TLS certificate,
+   * or null).
+   */
+  RvncEventKind_ClientConnected = 0,
+  /**
+   * A client disconnected (`client_id`).
+   */
+  RvncEventKind_ClientDisconnected = 1,
+  /**
+   * A key was pressed or released (`client_id`, `down`, `key`).
+   */
+  RvncEventKind_KeyPress = 2,
+  /**
+   * The pointer moved or a button changed (`client_id`, `x`, `y`, `button_mask`).
+   */
+  RvncEventKind_PointerMove = 3,
+  /**
+   * Clipboard text was received (`client_id`, `text`).
+   */
+  RvncEventKind_CutText = 4,
+  /**
+   * A client completed authentication (`client_id`, `text` = address, `auth_method`,
+   * `success`).
+   */
+  RvncEventKind_AuthAttempt = 5,
+} RvncEventKind;
+
+/**
+ * Opaque server handle.
+ */
+typedef struct RvncServer RvncServer;
+
+/**
+ * An event received from a client.
+ *
+ * Only the fields documented for `kind` are meaningful; the others are zero.
+ */
+typedef struct RvncEvent {
+  /**
+   * The kind of event.
+   */
+  enum RvncEventKind kind;
+  /**
+   * The client the event belongs to.
+   */
+  uint64_t client_id;
+  /**
+   * `true` if the key was pressed, `false` if released.
+   */
+  bool down;
+  /**
+   * The X11 keysym of the key.
+   */
+  uint32_t key;
+  /**
+   * Pointer X coordinate.
+   */
+  uint16_t x;
+  /**
+   * Pointer Y coordinate.
+   */
+  uint16_t y;
+  /**
+   * Pointer button mask (bit 0 left, 1 middle, 2 right, 3-6 wheel).
+   */
+  uint8_t button_mask;
+  /**
+   * RFB security type number of the authentication method (1 = none, 2 = VNC auth,
+   * 5/6 = RSA-AES, 19 = `VeNCrypt`, 20 = SASL, 113 = MS-Logon II).
+   */
+  uint8_t auth_method;
+  /**
+   * `true` if authentication succeeded.
+   */
+  bool success;
+  /**
+   * NUL-terminated UTF-8 clipboard text, client address or certificate subject, owned
+   * by the server. Valid until the next call to `rvnc_server_poll_event` or
+   * `rvnc_server_free` on the same handle.
+   */
+  const char *text;
+} RvncEvent;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a new server with an RGBA32 framebuffer of the given size.
+ *
+ * Returns null if `name` or `password` is not valid UTF-8 or the runtime cannot start.
+ *
+ * # Safety
+ *
+ * `name` and `password` must each be null or point to a valid NUL-terminated string.
+ */
+struct RvncServer *rvnc_server_new(uint16_t width,
+                                   uint16_t height,
+                                   const char *name,
+                                   const char *password);
+
+/**
+ * Starts listening for connections on `port` in the background.
+ *
+ * Calling this again while already listening has no effect.
+ *
+ * # Safety
+ *
+ * `server` must be null or a handle returned by `rvnc_server_new`.
+ */
+int32_t rvnc_server_listen(struct RvncServer *server, uint16_t port);
+
+/**
+ * Replaces the framebuffer contents with tightly packed RGBA32 pixels.
+ *
+ * `len` must equal `width * height * 4` of the current framebuffer.
+ *
+ * # Safety
+ *
+ * `server` must be null or a live handle, and `data` must be null or point to `len`
+ * readable bytes.
+ */
+int32_t rvnc_server_update_framebuffer(struct RvncServer *server, const uint8_t *data, size_t len);
+
+/**
+ * Resizes the framebuffer. Clients are sent the new size if they support it.
+ *
+ * Returns `RVNC_ERR_INVALID` if a dimension is zero or larger than 8192.
+ *
+ * # Safety
+ *
+ * `server` must be null or a live handle.
+ */
+int32_t rvnc_server_resize(struct RvncServer *server, uint16_t width, uint16_t height);
+
+/**
+ * Sets or clears (`password == NULL`) the password required for new connections.
+ *
+ * # Safety
+ *
+ * `server` must be null or a live handle, and `password` must be null or point to a
+ * valid NUL-terminated string.
+ */
+int32_t rvnc_server_set_password(struct RvncServer *server, const char *password);
+
+/**
+ * Renames the desktop, telling connected clients that support it.
+ *
+ * # Safety
+ *
+ * `server` must be null or a live handle, and `name` must be null or point to a valid
+ * NUL-terminated string.
+ */
+int32_t rvnc_server_set_desktop_name(struct RvncServer *server, const char *name);
+
+/**
+ * Retrieves the next pending event without blocking.
+ *
+ * Returns 1 if `out` was filled, 0 if no event is pending, or a negative error code.
+ *
+ * # Safety
+ *
+ * `server` must be null or a live handle, and `out` must be null or point to writable
+ * memory for one `RvncEvent`.
+ */
+int32_t rvnc_server_poll_event(struct RvncServer *server, struct RvncEvent *out);
+
+/**
+ * Stops listening and disconnects all clients, keeping the handle usable.
+ *
+ * # Safety
+ *
+ * `server` must be null or a live handle.
+ */
+int32_t rvnc_server_shutdown(struct RvncServer *server);
+
+/**
+ * Shuts the server down and releases the handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * `server` must be null or a handle returned by `rvnc_server_new` that has not been freed.
+ */
+void rvnc_server_free(struct RvncServer *server);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RUSTVNCSERVER_H */
@@ -0,0 +1,395 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Content analysis heuristics used to choose between lossy and lossless encodings.
+//!
+//! This module ports standard VNC protocol's `DetectSmoothImage` heuristic from the Tight
+//! encoder. It samples diagonal runs of pixels and builds a histogram of the differences
+//! between horizontally adjacent color samples:
+//!
+//! - Text and UI content has mostly zero differences with occasional large jumps at
+//!   glyph and widget edges.
+//! - Photographic content has a smooth, monotonically decreasing spread of small
+//!   differences.
+//!
+//! Only regions classified as smooth should be sent as JPEG. Everything else stays on
+//! the palette/zlib paths so text remains sharp even at low quality settings.
+//!
+//! The same measurement decides whether lossless full-color data goes through Tight's
+//! gradient filter, which pays off for smooth gradients but inflates sharp content, and
+//! [`classify_content`] builds on it to choose the encoding of each region.
+
+use crate::protocol::PixelFormat;
+
+/// Width of each sampled sub-row (standard VNC protocol: `DETECT_SUBROW_WIDTH`).
+const DETECT_SUBROW_WIDTH: usize = 7;
+
+/// Minimum rectangle width for smooth detection (standard VNC protocol: `DETECT_MIN_WIDTH`).
+const DETECT_MIN_WIDTH: usize = 8;
+
+/// Minimum rectangle height for smooth detection (standard VNC protocol: `DETECT_MIN_HEIGHT`).
+const DETECT_MIN_HEIGHT: usize = 8;
+
+/// Minimum pixel count before a rectangle is considered for JPEG.
+///
+/// Smaller rectangles are cheaper to send losslessly, and the JPEG header overhead
+/// dominates their payload anyway.
+const SMOOTH_MIN_RECT_SIZE: usize = 4096;
+
+/// Smallest side of the tiles [`smooth_areas`] classifies, so every tile has the
+/// `SMOOTH_MIN_RECT_SIZE` pixels smooth detection needs.
+const SMOOTH_TILE_SIZE: usize = 64;
+
+/// Average squared error below which a 24/32bpp rectangle is smooth, indexed by quality level.
+///
+/// Matches the `jpegThreshold24` column of standard VNC protocol's `tightConf` table. Lower
+/// quality levels accept noisier content as "photographic" because JPEG artifacts are
+/// already expected there.
+const JPEG_THRESHOLD_24: [u64; 10] = [23000, 18000, 15000, 12000, 8000, 5000, 2500, 1200, 800, 500];
+
+/// Average squared error below which a 16bpp rectangle is smooth, indexed by quality level.
+///
+/// Matches the `jpegThreshold` column of standard VNC protocol's `tightConf` table.
+const JPEG_THRESHOLD_16: [u64; 10] = [10000, 8000, 6500, 4000, 3000, 2000, 1000, 1000, 500, 200];
+
+/// Average squared error below which a 24/32bpp rectangle suits the gradient filter,
+/// indexed by compression level.
+///
+/// Matches the `gradientThreshold24` column of standard VNC protocol's `tightConf` table;
+/// zero disables the filter, so fast compression levels never pay for it.
+const GRADIENT_THRESHOLD_24: [u64; 10] = [0, 0, 0, 0, 0, 380, 420, 450, 475, 500];
+
+/// Average squared error below which a 16bpp rectangle suits the gradient filter,
+/// indexed by compression level (`gradientThreshold` of `tightConf`).
+const GRADIENT_THRESHOLD_16: [u64; 10] = [0, 0, 0, 0, 0, 150, 170, 180, 190, 200];
+
+/// Minimum pixel count before a rectangle is considered for the gradient filter, indexed
+/// by compression level (`gradientMinRectSize` of `tightConf`).
+const GRADIENT_MIN_RECT_SIZE: [usize; 10] = [
+    65536, 65536, 65536, 65536, 65536, 4096, 4096, 4096, 8192, 8192,
+];
+
+/// What a rectangle shows, as far as choosing its encoding goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentClass {
+    /// A single color.
+    Solid,
+    /// Text, UI and other sharp-edged content that must stay lossless. Smooth content
+    /// is classified as text too when the quality level doesn't allow lossy encodings.
+    Text,
+    /// Smooth, photographic content that lossy encodings compress well.
+    Photo,
+}
+
+/// Classifies an RGBA32 rectangle for encoder selection.
+///
+/// A rectangle is photographic if [`detect_smooth_image`] would send it as JPEG.
+///
+/// # Arguments
+///
+/// * `pixels` - RGBA32 pixel data for the rectangle (4 bytes per pixel, row-major).
+/// * `width` - Width of the rectangle in pixels.
+/// * `height` - Height of the rectangle in pixels.
+/// * `quality_level` - VNC quality level (0-9). Values outside that range never classify
+///   a rectangle as photographic.
+/// * `client_format` - The pixel format negotiated by the client.
+#[must_use]
+pub fn classify_content(
+    pixels: &[u8],
+    width: u16,
+    height: u16,
+    quality_level: u8,
+    client_format: &PixelFormat,
+) -> ContentClass {
+    let mut colors = pixels.chunks_exact(4).map(|pixel| &pixel[..3]);
+    let first = colors.next();
+    if colors.all(|color| Some(color) == first) {
+        ContentClass::Solid
+    } else if detect_smooth_image(pixels, width, height, quality_level, client_format) {
+        ContentClass::Photo
+    } else {
+        ContentClass::Text
+    }
+}
+
+/// Returns `true` if an RGBA32 rectangle looks photographic enough to be sent as JPEG.
+///
+/// This is a port of standard VNC protocol's `DetectSmoothImage`. 8bpp clients never get
+/// JPEG, and rectangles smaller than `SMOOTH_MIN_RECT_SIZE` pixels are always treated as
+/// non-smooth since palette/zlib encoding wins on them anyway.
+///
+/// # Arguments
+///
+/// * `pixels` - RGBA32 pixel data for the rectangle (4 bytes per pixel, row-major).
+/// * `width` - Width of the rectangle in pixels.
+/// * `height` - Height of the rectangle in pixels.
+/// * `quality_level` - VNC quality level (0-9). Values outside that range disable JPEG.
+/// * `client_format` - The pixel format negotiated by the client.
+///
+/// # Returns
+///
+/// `true` if the rectangle should use JPEG, `false` if it should stay lossless.
+#[must_use]
+pub fn detect_smooth_image(
+    pixels: &[u8],
+    width: u16,
+    height: u16,
+    quality_level: u8,
+    client_format: &PixelFormat,
+) -> bool {
+    let w = width as usize;
+    let h = height as usize;
+
+    if quality_level > 9
+        || client_format.bits_per_pixel == 8
+        || w < DETECT_MIN_WIDTH
+        || h < DETECT_MIN_HEIGHT
+        || pixels.len() < w * h * 4
+    {
+        return false;
+    }
+
+    if w * h < SMOOTH_MIN_RECT_SIZE {
+        return false;
+    }
+
+    let Some(avg_error) = average_gradient_error(pixels, w, h) else {
+        return false;
+    };
+
+    let threshold = if client_format.bits_per_pixel >= 24 {
+        JPEG_THRESHOLD_24[usize::from(quality_level)]
+    } else {
+        JPEG_THRESHOLD_16[usize::from(quality_level)]
+    };
+
+    avg_error < threshold
+}
+
+/// Returns `true` if an RGBA32 rectangle is smooth enough for Tight's gradient filter.
+///
+/// Uses the same measurement as [`detect_smooth_image`] with the stricter gradient
+/// thresholds of standard VNC protocol's `tightConf` table. The filter is only used at
+/// compression levels 5 and above, and never for 8bpp clients.
+///
+/// # Arguments
+///
+/// * `pixels` - RGBA32 pixel data for the rectangle (4 bytes per pixel, row-major).
+/// * `width` - Width of the rectangle in pixels.
+/// * `height` - Height of the rectangle in pixels.
+/// * `compression_level` - VNC compression level (0-9).
+/// * `client_format` - The pixel format negotiated by the client.
+///
+/// # Returns
+///
+/// `true` if full-color data for the rectangle should be gradient filtered.
+#[must_use]
+pub fn detect_gradient_image(
+    pixels: &[u8],
+    width: u16,
+    height: u16,
+    compression_level: u8,
+    client_format: &PixelFormat,
+) -> bool {
+    let w = width as usize;
+    let h = height as usize;
+    let level = usize::from(compression_level.min(9));
+
+    let threshold = if client_format.bits_per_pixel >= 24 {
+        GRADIENT_THRESHOLD_24[level]
+    } else {
+        GRADIENT_THRESHOLD_16[level]
+    };
+
+    if threshold == 0
+        || client_format.bits_per_pixel == 8
+        || w < DETECT_MIN_WIDTH
+        || h < DETECT_MIN_HEIGHT
+        || pixels.len() < w * h * 4
+        || w * h < GRADIENT_MIN_RECT_SIZE[level]
+    {
+        return false;
+    }
+
+    average_gradient_error(pixels, w, h).is_some_and(|avg_error| avg_error < threshold)
+}
+
+/// Splits a rectangle into the areas that should and shouldn't be sent as JPEG.
+///
+/// The rectangle is divided into tiles of 64 to 127 pixels a side, each classified with
+/// [`detect_smooth_image`]. Adjacent tiles of a row with the same class are joined, and
+/// runs spanning the same columns in consecutive rows are joined too, so a photo in a
+/// window becomes a few areas while the text and UI around it stay lossless.
+///
+/// # Arguments
+///
+/// * `pixels` - RGBA32 pixel data for the rectangle (4 bytes per pixel, row-major).
+/// * `width` - Width of the rectangle in pixels.
+/// * `height` - Height of the rectangle in pixels.
+/// * `quality_level` - VNC quality level (0-9). Values outside that range disable JPEG.
+/// * `client_format` - The pixel format negotiated by the client.
+///
+/// # Returns
+///
+/// `(x, y, width, height, smooth)` for each area, relative to the rectangle; the areas
+/// cover it exactly. A rectangle of one class is a single area.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)] // Areas lie within the u16-sized rectangle
+pub fn smooth_areas(
+    pixels: &[u8],
+    width: u16,
+    height: u16,
+    quality_level: u8,
+    client_format: &PixelFormat,
+) -> Vec<(u16, u16, u16, u16, bool)> {
+    let w = width as usize;
+    let h = height as usize;
+    let columns = tile_spans(w);
+    let rows = tile_spans(h);
+    if columns.len() * rows.len() == 1 || pixels.len() < w * h * 4 {
+        let smooth = detect_smooth_image(pixels, width, height, quality_level, client_format);
+        return vec![(0, 0, width, height, smooth)];
+    }
+
+    let mut areas: Vec<(usize, usize, usize, usize, bool)> = Vec::new();
+    let mut row_above: Vec<usize> = Vec::new();
+    let mut tile = Vec::new();
+    for &(y, tile_h) in &rows {
+        let classes: Vec<bool> = columns
+            .iter()
+            .map(|&(x, tile_w)| {
+                tile.clear();
+                for row in y..y + tile_h {
+                    let start = (row * w + x) * 4;
+                    tile.extend_from_slice(&pixels[start..start + tile_w * 4]);
+                }
+                detect_smooth_image(
+                    &tile,
+                    tile_w as u16,
+                    tile_h as u16,
+                    quality_level,
+                    client_format,
+                )
+            })
+            .collect();
+
+        // Join runs of one class, extending the area above when it spans the same columns
+        let mut row_areas = Vec::new();
+        let mut start = 0;
+        for end in 1..=classes.len() {
+            if end < classes.len() && classes[end] == classes[start] {
+                continue;
+            }
+            let x = columns[start].0;
+            let run_w = columns[end - 1].0 + columns[end - 1].1 - x;
+            let smooth = classes[start];
+            let above = row_above.iter().copied().find(|&index| {
+                let (area_x, _, area_w, _, area_smooth) = areas[index];
+                (area_x, area_w, area_smooth) == (x, run_w, smooth)
+            });
+            if let Some(index) = above {
+                areas[index].3 += tile_h;
+                row_areas.push(index);
+            } else {
+                areas.push((x, y, run_w, tile_h, smooth));
+                row_areas.push(areas.len() - 1);
+            }
+            start = end;
+        }
+        row_above = row_areas;
+    }
+
+    areas
+        .into_iter()
+        .map(|(x, y, w, h, smooth)| (x as u16, y as u16, w as u16, h as u16, smooth))
+        .collect()
+}
+
+/// Splits `length` pixels into spans of `SMOOTH_TILE_SIZE` to twice that, or one
+/// shorter span, as `(start, length)`.
+fn tile_spans(length: usize) -> Vec<(usize, usize)> {
+    let count = (length / SMOOTH_TILE_SIZE).max(1);
+    (0..count)
+        .map(|i| {
+            let start = i * length / count;
+            (start, (i + 1) * length / count - start)
+        })
+        .collect()
+}
+
+/// Computes the average squared difference between neighbouring color samples.
+///
+/// Samples short horizontal sub-rows along diagonals of the rectangle (standard VNC protocol:
+/// `DetectSmoothImage24`). Returns `None` when the content is clearly not photographic:
+/// either it is dominated by identical neighbours (flat UI) or the difference histogram
+/// does not fall off smoothly for small errors (sharp edges).
+#[allow(clippy::many_single_char_names)] // Mirrors the variable names of the reference implementation
+fn average_gradient_error(pixels: &[u8], w: usize, h: usize) -> Option<u64> {
+    let mut diff_stat = [0u64; 256];
+    let mut pixel_count = 0u64;
+
+    let mut x = 0;
+    let mut y = 0;
+    while y < h && x < w {
+        let mut d = 0;
+        while d < h - y && d + DETECT_SUBROW_WIDTH < w - x {
+            let row_start = ((y + d) * w + x + d) * 4;
+            let mut left = [
+                pixels[row_start],
+                pixels[row_start + 1],
+                pixels[row_start + 2],
+            ];
+            for dx in 1..=DETECT_SUBROW_WIDTH {
+                let offset = row_start + dx * 4;
+                for (c, prev) in left.iter_mut().enumerate() {
+                    let pix = pixels[offset + c];
+                    diff_stat[usize::from(pix.abs_diff(*prev))] += 1;
+                    *prev = pix;
+                }
+                pixel_count += 1;
+            }
+            d += 1;
+        }
+
+        // Walk the diagonals across the longer dimension of the rectangle
+        if w > h {
+            x += h;
+            y = 0;
+        } else {
+            x = 0;
+            y += w;
+        }
+    }
+
+    if pixel_count == 0 || diff_stat[0] * 33 / pixel_count >= 95 {
+        return None;
+    }
+
+    let mut avg_error = 0u64;
+    for c in 1..8 {
+        avg_error += diff_stat[c] * (c as u64) * (c as u64);
+        if diff_stat[c] == 0 || diff_stat[c] > diff_stat[c - 1] * 2 {
+            return None;
+        }
+    }
+    for (c, &count) in diff_stat.iter().enumerate().skip(8) {
+        avg_error += count * (c as u64) * (c as u64);
+    }
+
+    let samples = pixel_count * 3 - diff_stat[0];
+    if samples == 0 {
+        return None;
+    }
+    Some(avg_error / samples)
+}
@@ -0,0 +1,602 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Android integration helpers.
+//!
+//! This module is enabled by the `android` feature and collects the glue that an Android
+//! screen-sharing app needs around the core server:
+//!
+//! - [`AndroidVncService`] owns a Tokio runtime and a [`VncServer`] and exposes blocking,
+//!   JNI-friendly lifecycle hooks (`start`, `stop`, `poll_event`) that map onto a
+//!   foreground service's `onStartCommand`/`onDestroy`.
+//! - Frame ingestion from `ImageReader` planes (direct `ByteBuffer`s) and from locked
+//!   `ANativeWindow`/`AHardwareBuffer` memory, both of which use a row stride that is
+//!   usually wider than the visible image.
+//! - Translation of [`ServerEvent`] input into Android `KeyEvent` and `MotionEvent`
+//!   conventions so the app can inject it through an accessibility service or
+//!   `Instrumentation`.
+//!
+//! None of the methods here may be called from the Android main thread, since they block
+//! on the embedded runtime; call them from a worker thread to avoid ANRs.
+
+use std::io;
+use std::sync::Arc;
+
+use jni::objects::JByteBuffer;
+use jni::JNIEnv;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+use crate::keysym;
+use crate::server::{ServerEvent, VncServer};
+
+/// Maximum time `stop()` waits for clients to disconnect before giving up.
+const STOP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Android `KeyEvent.META_SHIFT_ON`.
+pub const META_SHIFT_ON: i32 = 0x01;
+
+/// Android `MotionEvent.ACTION_DOWN`.
+pub const ACTION_DOWN: i32 = 0;
+/// Android `MotionEvent.ACTION_UP`.
+pub const ACTION_UP: i32 = 1;
+/// Android `MotionEvent.ACTION_MOVE`.
+pub const ACTION_MOVE: i32 = 2;
+/// Android `MotionEvent.ACTION_HOVER_MOVE`.
+pub const ACTION_HOVER_MOVE: i32 = 7;
+/// Android `MotionEvent.ACTION_SCROLL`.
+pub const ACTION_SCROLL: i32 = 8;
+
+/// Android `MotionEvent.BUTTON_PRIMARY`.
+pub const BUTTON_PRIMARY: i32 = 0x01;
+/// Android `MotionEvent.BUTTON_SECONDARY`.
+pub const BUTTON_SECONDARY: i32 = 0x02;
+/// Android `MotionEvent.BUTTON_TERTIARY`.
+pub const BUTTON_TERTIARY: i32 = 0x04;
+
+/// A VNC server wrapped for use from an Android foreground service.
+///
+/// The service owns its own multi-threaded Tokio runtime so that JNI callers can drive it
+/// with plain blocking calls. A typical integration stores the boxed service in a `jlong`
+/// field of the Java service object and forwards lifecycle callbacks to it.
+pub struct AndroidVncService {
+    runtime: Runtime,
+    server: Arc<VncServer>,
+    events: mpsc::UnboundedReceiver<ServerEvent>,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl AndroidVncService {
+    /// Creates a new service with its own runtime and framebuffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the VNC framebuffer (typically the display width).
+    /// * `height` - The height of the VNC framebuffer (typically the display height).
+    /// * `desktop_name` - The name of the desktop to be advertised to clients.
+    /// * `password` - An optional password for client authentication.
+    ///
+    /// # Returns
+    ///
+    /// The new service, not yet listening for connections.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the Tokio runtime cannot be created.
+    pub fn new(
+        width: u16,
+        height: u16,
+        desktop_name: String,
+        password: Option<String>,
+    ) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("vnc-worker")
+            .build()?;
+        let (server, events) = VncServer::new(width, height, desktop_name, password);
+
+        Ok(Self {
+            runtime,
+            server: Arc::new(server),
+            events,
+            listener: None,
+        })
+    }
+
+    /// Returns the wrapped server for operations not covered by this helper.
+    #[must_use]
+    pub fn server(&self) -> &Arc<VncServer> {
+        &self.server
+    }
+
+    /// Returns the runtime driving the server.
+    #[must_use]
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    /// Starts listening for incoming connections. Call from `onStartCommand`.
+    ///
+    /// Calling `start` while the service is already running is a no-op.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The TCP port on which the server will listen for connections.
+    pub fn start(&mut self, port: u16) {
+        if self.is_running() {
+            return;
+        }
+
+        let server = self.server.clone();
+        self.listener = Some(self.runtime.spawn(async move {
+            if let Err(e) = server.listen(port).await {
+                log::error!("VNC server listener on port {port} failed: {e}");
+            }
+        }));
+    }
+
+    /// Stops accepting connections and disconnects all clients. Call from `onDestroy`.
+    ///
+    /// Waits at most a few seconds for clients to disconnect so the service can always
+    /// finish tearing down.
+    pub fn stop(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.abort();
+        }
+
+        let server = self.server.clone();
+        self.runtime.block_on(async move {
+            if tokio::time::timeout(STOP_TIMEOUT, server.disconnect_all_clients())
+                .await
+                .is_err()
+            {
+                log::warn!("Timed out disconnecting VNC clients during stop");
+            }
+        });
+    }
+
+    /// Returns `true` if the listener is running.
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.listener
+            .as_ref()
+            .is_some_and(|listener| !listener.is_finished())
+    }
+
+    /// Returns the number of connected clients, suitable for a foreground notification.
+    #[must_use]
+    pub fn client_count(&self) -> usize {
+        self.server.get_client_ids().map_or(0, |ids| ids.len())
+    }
+
+    /// Returns the next pending server event without blocking.
+    ///
+    /// Android apps usually poll this from the thread that injects input.
+    pub fn poll_event(&mut self) -> Option<ServerEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// Blocks until the next server event arrives or the server is dropped.
+    pub fn wait_event(&mut self) -> Option<ServerEvent> {
+        self.runtime.block_on(self.events.recv())
+    }
+
+    /// Updates the framebuffer from `RGBA_8888` pixels with a row stride.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - RGBA pixel data, at least `row_stride * (height - 1) + width * 4` bytes.
+    /// * `width` - Width of the image in pixels; must match the framebuffer width.
+    /// * `height` - Height of the image in pixels; must match the framebuffer height.
+    /// * `row_stride` - Number of bytes between the start of consecutive rows.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the framebuffer was updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the dimensions do not match the framebuffer or the data is
+    /// too short for the given stride.
+    pub fn update_frame_strided(
+        &self,
+        data: &[u8],
+        width: u16,
+        height: u16,
+        row_stride: usize,
+    ) -> Result<(), String> {
+        let framebuffer = self.server.framebuffer();
+        if width != framebuffer.width() || height != framebuffer.height() {
+            return Err(format!(
+                "Frame size {width}x{height} does not match framebuffer {}x{}",
+                framebuffer.width(),
+                framebuffer.height()
+            ));
+        }
+
+        let packed = pack_strided_rows(data, width as usize, height as usize, row_stride)?;
+        self.runtime
+            .block_on(framebuffer.update_from_slice(&packed))
+    }
+
+    /// Updates the framebuffer from an `ImageReader` plane's direct `ByteBuffer`.
+    ///
+    /// This is the usual path for `MediaProjection` capture: pass `Image.getPlanes()[0]`'s
+    /// buffer and row stride for an image acquired in `PixelFormat.RGBA_8888`.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - The JNI environment of the calling thread.
+    /// * `buffer` - A direct `ByteBuffer` holding RGBA pixels.
+    /// * `width` - Width of the image in pixels.
+    /// * `height` - Height of the image in pixels.
+    /// * `row_stride` - The plane's row stride in bytes.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the framebuffer was updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the buffer is not direct or its contents do not match the
+    /// framebuffer.
+    pub fn update_frame_from_byte_buffer(
+        &self,
+        env: &mut JNIEnv,
+        buffer: &JByteBuffer,
+        width: u16,
+        height: u16,
+        row_stride: usize,
+    ) -> Result<(), String> {
+        let address = env
+            .get_direct_buffer_address(buffer)
+            .map_err(|e| format!("Not a direct ByteBuffer: {e}"))?;
+        let capacity = env
+            .get_direct_buffer_capacity(buffer)
+            .map_err(|e| format!("Not a direct ByteBuffer: {e}"))?;
+
+        // SAFETY: JNI guarantees a direct buffer's address is valid for `capacity` bytes
+        // while the Java object is alive, which `buffer` keeps it for this call.
+        let data = unsafe { std::slice::from_raw_parts(address, capacity) };
+        self.update_frame_strided(data, width, height, row_stride)
+    }
+
+    /// Updates the framebuffer from locked `ANativeWindow_Buffer` or `AHardwareBuffer` memory.
+    ///
+    /// Both NDK APIs report the stride in pixels rather than bytes. Only the `RGBA_8888` and
+    /// `RGBX_8888` formats are supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `bits` - The `bits` pointer from `ANativeWindow_lock` or `AHardwareBuffer_lock`.
+    /// * `width` - Width of the buffer in pixels.
+    /// * `height` - Height of the buffer in pixels.
+    /// * `stride_pixels` - The buffer stride in pixels.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the framebuffer was updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the pointer is null or the dimensions do not match the
+    /// framebuffer.
+    ///
+    /// # Safety
+    ///
+    /// `bits` must point to at least `stride_pixels * height * 4` readable bytes that stay
+    /// locked for the duration of the call.
+    pub unsafe fn update_frame_from_native_buffer(
+        &self,
+        bits: *const u8,
+        width: u16,
+        height: u16,
+        stride_pixels: usize,
+    ) -> Result<(), String> {
+        if bits.is_null() {
+            return Err("Native buffer pointer is null".to_string());
+        }
+
+        let row_stride = stride_pixels * 4;
+        // SAFETY: the caller guarantees the locked buffer covers `row_stride * height` bytes.
+        let data = unsafe { std::slice::from_raw_parts(bits, row_stride * height as usize) };
+        self.update_frame_strided(data, width, height, row_stride)
+    }
+
+    /// Resizes the framebuffer, e.g. after a display rotation.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the new dimensions are invalid.
+    pub fn resize(&self, width: u16, height: u16) -> Result<(), String> {
+        self.runtime.block_on(self.server.resize(width, height))
+    }
+}
+
+impl Drop for AndroidVncService {
+    fn drop(&mut self) {
+        if self.listener.is_some() {
+            self.stop();
+        }
+    }
+}
+
+/// Copies strided rows into a tightly packed RGBA buffer.
+fn pack_strided_rows(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    row_stride: usize,
+) -> Result<Vec<u8>, String> {
+    let row_bytes = width * 4;
+    if row_stride < row_bytes {
+        return Err(format!(
+            "Row stride {row_stride} is smaller than row size {row_bytes}"
+        ));
+    }
+    let required = if height == 0 {
+        0
+    } else {
+        row_stride * (height - 1) + row_bytes
+    };
+    if data.len() < required {
+        return Err(format!(
+            "Frame data too short: expected at least {required}, got {}",
+            data.len()
+        ));
+    }
+
+    if row_stride == row_bytes {
+        return Ok(data[..required].to_vec());
+    }
+
+    let mut packed = Vec::with_capacity(row_bytes * height);
+    for row in 0..height {
+        let start = row * row_stride;
+        packed.extend_from_slice(&data[start..start + row_bytes]);
+    }
+    Ok(packed)
+}
+
+/// An Android key event equivalent to a VNC keysym.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AndroidKey {
+    /// The Android `KeyEvent.KEYCODE_*` value.
+    pub keycode: i32,
+    /// The Android `KeyEvent.META_*` state required to produce the keysym.
+    pub meta_state: i32,
+}
+
+/// Maps an X11 keysym received from a VNC client to an Android key code.
+///
+/// Printable characters are mapped using a US keyboard layout; characters that need
+/// Shift on that layout report `META_SHIFT_ON`. Keysyms without an Android equivalent
+/// return `None` and are better injected as text.
+///
+/// # Arguments
+///
+/// * `keysym` - The X11 keysym from a `ServerEvent::KeyPress`.
+///
+/// # Returns
+///
+/// The Android key code and meta state, or `None` if the keysym has no key equivalent.
+#[must_use]
+#[allow(clippy::cast_possible_wrap)] // Keysym offsets within matched ranges are small
+pub fn keysym_to_android_key(keysym: u32) -> Option<AndroidKey> {
+    let plain = |keycode| {
+        Some(AndroidKey {
+            keycode,
+            meta_state: 0,
+        })
+    };
+    let shifted = |keycode| {
+        Some(AndroidKey {
+            keycode,
+            meta_state: META_SHIFT_ON,
+        })
+    };
+
+    match keysym {
+        // Lowercase and uppercase Latin letters: KEYCODE_A (29) .. KEYCODE_Z (54)
+        0x61..=0x7a => plain(29 + (keysym - 0x61) as i32),
+        0x41..=0x5a => shifted(29 + (keysym - 0x41) as i32),
+        // Digits: KEYCODE_0 (7) .. KEYCODE_9 (16)
+        0x30..=0x39 => plain(7 + (keysym - 0x30) as i32),
+
+        // Unshifted punctuation
+        0x20 => plain(62), // space -> KEYCODE_SPACE
+        0x27 => plain(75), // apostrophe -> KEYCODE_APOSTROPHE
+        0x2a => plain(17), // asterisk -> KEYCODE_STAR
+        0x2b => plain(81), // plus -> KEYCODE_PLUS
+        0x2c => plain(55), // comma -> KEYCODE_COMMA
+        0x2d => plain(69), // minus -> KEYCODE_MINUS
+        0x2e => plain(56), // period -> KEYCODE_PERIOD
+        0x2f => plain(76), // slash -> KEYCODE_SLASH
+        0x3b => plain(74), // semicolon -> KEYCODE_SEMICOLON
+        0x3d => plain(70), // equal -> KEYCODE_EQUALS
+        0x40 => plain(77), // at -> KEYCODE_AT
+        0x23 => plain(18), // numbersign -> KEYCODE_POUND
+        0x5b => plain(71), // bracketleft -> KEYCODE_LEFT_BRACKET
+        0x5c => plain(73), // backslash -> KEYCODE_BACKSLASH
+        0x5d => plain(72), // bracketright -> KEYCODE_RIGHT_BRACKET
+        0x60 => plain(68), // grave -> KEYCODE_GRAVE
+
+        // Shifted punctuation on a US layout
+        0x21 => shifted(8),  // exclam -> Shift+1
+        0x22 => shifted(75), // quotedbl -> Shift+'
+        0x24 => shifted(11), // dollar -> Shift+4
+        0x25 => shifted(12), // percent -> Shift+5
+        0x5e => shifted(13), // asciicircum -> Shift+6
+        0x26 => shifted(14), // ampersand -> Shift+7
+        0x28 => shifted(16), // parenleft -> Shift+9
+        0x29 => shifted(7),  // parenright -> Shift+0
+        0x3a => shifted(74), // colon -> Shift+;
+        0x3c => shifted(55), // less -> Shift+,
+        0x3e => shifted(56), // greater -> Shift+.
+        0x3f => shifted(76), // question -> Shift+/
+        0x5f => shifted(69), // underscore -> Shift+-
+        0x7b => shifted(71), // braceleft -> Shift+[
+        0x7c => shifted(73), // bar -> Shift+\
+        0x7d => shifted(72), // braceright -> Shift+]
+        0x7e => shifted(68), // asciitilde -> Shift+`
+
+        // Editing and navigation
+        keysym::BACKSPACE => plain(67), // KEYCODE_DEL
+        keysym::TAB => plain(61),       // KEYCODE_TAB
+        keysym::RETURN => plain(66),    // KEYCODE_ENTER
+        keysym::ESCAPE => plain(111),   // KEYCODE_ESCAPE
+        keysym::DELETE => plain(112),   // KEYCODE_FORWARD_DEL
+        keysym::HOME => plain(122),     // KEYCODE_MOVE_HOME
+        keysym::LEFT => plain(21),      // KEYCODE_DPAD_LEFT
+        keysym::UP => plain(19),        // KEYCODE_DPAD_UP
+        keysym::RIGHT => plain(22),     // KEYCODE_DPAD_RIGHT
+        keysym::DOWN => plain(20),      // KEYCODE_DPAD_DOWN
+        keysym::PAGE_UP => plain(92),   // KEYCODE_PAGE_UP
+        keysym::PAGE_DOWN => plain(93), // KEYCODE_PAGE_DOWN
+        keysym::END => plain(123),      // KEYCODE_MOVE_END
+        keysym::INSERT => plain(124),   // KEYCODE_INSERT
+        keysym::MENU => plain(82),      // KEYCODE_MENU
+
+        // Function keys: KEYCODE_F1 (131) .. KEYCODE_F12 (142)
+        keysym::F1..=keysym::F12 => plain(131 + (keysym - keysym::F1) as i32),
+
+        // Keypad: KEYCODE_NUMPAD_0 (144) .. KEYCODE_NUMPAD_9 (153)
+        keysym::KP_0..=keysym::KP_9 => plain(144 + (keysym - keysym::KP_0) as i32),
+        keysym::KP_DIVIDE => plain(154),   // KEYCODE_NUMPAD_DIVIDE
+        keysym::KP_MULTIPLY => plain(155), // KEYCODE_NUMPAD_MULTIPLY
+        keysym::KP_SUBTRACT => plain(156), // KEYCODE_NUMPAD_SUBTRACT
+        keysym::KP_ADD => plain(157),      // KEYCODE_NUMPAD_ADD
+        keysym::KP_DECIMAL => plain(158),  // KEYCODE_NUMPAD_DOT
+        keysym::KP_ENTER => plain(160),    // KEYCODE_NUMPAD_ENTER
+
+        // Modifiers
+        keysym::SHIFT_L => plain(59),    // KEYCODE_SHIFT_LEFT
+        keysym::SHIFT_R => plain(60),    // KEYCODE_SHIFT_RIGHT
+        keysym::CONTROL_L => plain(113), // KEYCODE_CTRL_LEFT
+        keysym::CONTROL_R => plain(114), // KEYCODE_CTRL_RIGHT
+        keysym::CAPS_LOCK => plain(115), // KEYCODE_CAPS_LOCK
+        keysym::META_L | keysym::SUPER_L => plain(117), // KEYCODE_META_LEFT
+        keysym::META_R | keysym::SUPER_R => plain(118), // KEYCODE_META_RIGHT
+        keysym::ALT_L => plain(57),      // KEYCODE_ALT_LEFT
+        keysym::ALT_R => plain(58),      // KEYCODE_ALT_RIGHT
+
+        _ => None,
+    }
+}
+
+/// A single Android `MotionEvent` produced from a VNC pointer event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AndroidMotion {
+    /// The Android `MotionEvent.ACTION_*` value.
+    pub action: i32,
+    /// The Android `MotionEvent.BUTTON_*` state after this event.
+    pub button_state: i32,
+    /// Pointer X coordinate in framebuffer pixels.
+    pub x: f32,
+    /// Pointer Y coordinate in framebuffer pixels.
+    pub y: f32,
+    /// Vertical scroll amount for `ACTION_SCROLL` (positive scrolls up).
+    pub vscroll: f32,
+    /// Horizontal scroll amount for `ACTION_SCROLL` (positive scrolls right).
+    pub hscroll: f32,
+}
+
+/// Converts VNC pointer button masks into Android `MotionEvent` sequences.
+///
+/// VNC reports the complete button state with every pointer event, while Android expects
+/// explicit down/move/up gestures plus separate scroll events. Keep one translator per
+/// client so that button transitions are tracked correctly.
+#[derive(Debug, Default, Clone)]
+pub struct PointerTranslator {
+    last_mask: u8,
+}
+
+impl PointerTranslator {
+    /// Creates a translator with no buttons pressed.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Translates a VNC pointer event into zero or more Android motion events.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Pointer X coordinate from `ServerEvent::PointerMove`.
+    /// * `y` - Pointer Y coordinate from `ServerEvent::PointerMove`.
+    /// * `button_mask` - VNC button mask (bit 0 left, 1 middle, 2 right, 3-6 wheel).
+    ///
+    /// # Returns
+    ///
+    /// The motion events to inject, in order.
+    pub fn translate(&mut self, x: u16, y: u16, button_mask: u8) -> Vec<AndroidMotion> {
+        let previous = self.last_mask;
+        self.last_mask = button_mask;
+
+        let buttons = |mask: u8| {
+            let mut state = 0;
+            if mask & 0x01 != 0 {
+                state |= BUTTON_PRIMARY;
+            }
+            if mask & 0x02 != 0 {
+                state |= BUTTON_TERTIARY;
+            }
+            if mask & 0x04 != 0 {
+                state |= BUTTON_SECONDARY;
+            }
+            state
+        };
+        let motion = |action, button_state, vscroll, hscroll| AndroidMotion {
+            action,
+            button_state,
+            x: f32::from(x),
+            y: f32::from(y),
+            vscroll,
+            hscroll,
+        };
+
+        let mut events = Vec::new();
+        let was_down = buttons(previous);
+        let now_down = buttons(button_mask);
+
+        if was_down == 0 && now_down != 0 {
+            events.push(motion(ACTION_DOWN, now_down, 0.0, 0.0));
+        } else if was_down != 0 && now_down == 0 {
+            events.push(motion(ACTION_UP, 0, 0.0, 0.0));
+        } else if now_down != 0 {
+            events.push(motion(ACTION_MOVE, now_down, 0.0, 0.0));
+        } else {
+            events.push(motion(ACTION_HOVER_MOVE, 0, 0.0, 0.0));
+        }
+
+        // Wheel buttons are reported as press/release pairs; scroll once per press
+        let pressed = button_mask & !previous;
+        if pressed & 0x08 != 0 {
+            events.push(motion(ACTION_SCROLL, now_down, 1.0, 0.0));
+        }
+        if pressed & 0x10 != 0 {
+            events.push(motion(ACTION_SCROLL, now_down, -1.0, 0.0));
+        }
+        if pressed & 0x20 != 0 {
+            events.push(motion(ACTION_SCROLL, now_down, 0.0, -1.0));
+        }
+        if pressed & 0x40 != 0 {
+            events.push(motion(ACTION_SCROLL, now_down, 0.0, 1.0));
+        }
+
+        events
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Connection audit log.
+//!
+//! An [`AuditLog`] set with
+//! [`VncServer::set_audit_log`](crate::VncServer::set_audit_log) records who connected,
+//! how they authenticated, when they left and what clipboard data and files crossed the
+//! connection. Entries go to a file, one line each, or to a channel the application
+//! reads [`AuditEntry`] values from:
+//!
+//! ```no_run
+//! use rustvncserver::audit::AuditLog;
+//! use rustvncserver::VncServer;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let (mut server, _events) = VncServer::new(800, 600, "Desktop".to_string(), None);
+//! server.set_audit_log(Some(AuditLog::to_file("/var/log/vnc-audit.log")?));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A file line looks like
+//! `2025-06-01T12:00:00.000Z client=3 address=192.0.2.7:51234 event=auth-failure method=vnc`.
+//! Clipboard and file entries record sizes, not contents.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::mpsc;
+
+use crate::server::AuthMethod;
+
+/// What happened to a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditEvent {
+    /// The client completed the handshake and its session started.
+    Connected {
+        /// The subject of the client's verified TLS certificate, if it presented one.
+        certificate_subject: Option<String>,
+    },
+    /// The client authenticated.
+    AuthSucceeded {
+        /// The method the client authenticated with.
+        method: AuthMethod,
+    },
+    /// The client failed to authenticate.
+    AuthFailed {
+        /// The method the client tried.
+        method: AuthMethod,
+    },
+    /// The client's session ended.
+    Disconnected,
+    /// Clipboard text from the client was delivered to the application.
+    ClipboardReceived {
+        /// Size of the text in bytes.
+        bytes: usize,
+    },
+    /// Clipboard text was sent to the client.
+    ClipboardSent {
+        /// Size of the text in bytes.
+        bytes: usize,
+    },
+    /// A file from the client was stored through `UltraVNC` file transfer.
+    FileReceived {
+        /// Where the file was stored.
+        path: String,
+        /// Size of the file in bytes.
+        bytes: u64,
+    },
+    /// A file was sent to the client through `UltraVNC` file transfer.
+    FileSent {
+        /// The file that was sent.
+        path: String,
+        /// Size of the file in bytes.
+        bytes: u64,
+    },
+}
+
+/// One audit record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// When the event happened.
+    pub time: SystemTime,
+    /// The unique identifier assigned to the connection.
+    pub client_id: usize,
+    /// The remote address of the client (IP:port), or the peer description of a custom
+    /// transport.
+    pub address: String,
+    /// What happened.
+    pub event: AuditEvent,
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_timestamp(f, self.time)?;
+        write!(f, " client={} address={}", self.client_id, self.address)?;
+        match &self.event {
+            AuditEvent::Connected {
+                certificate_subject,
+            } => {
+                write!(f, " event=connect")?;
+                if let Some(subject) = certificate_subject {
+                    write!(f, " certificate={subject:?}")?;
+                }
+                Ok(())
+            }
+            AuditEvent::AuthSucceeded { method } => {
+                write!(f, " event=auth-success method={}", method.name())
+            }
+            AuditEvent::AuthFailed { method } => {
+                write!(f, " event=auth-failure method={}", method.name())
+            }
+            AuditEvent::Disconnected => write!(f, " event=disconnect"),
+            AuditEvent::ClipboardReceived { bytes } => {
+                write!(f, " event=clipboard-received bytes={bytes}")
+            }
+            AuditEvent::ClipboardSent { bytes } => write!(f, " event=clipboard-sent bytes={bytes}"),
+            AuditEvent::FileReceived { path, bytes } => {
+                write!(f, " event=file-received path={path:?} bytes={bytes}")
+            }
+            AuditEvent::FileSent { path, bytes } => {
+                write!(f, " event=file-sent path={path:?} bytes={bytes}")
+            }
+        }
+    }
+}
+
+/// Where audit entries go.
+enum Sink {
+    /// Lines appended to a file.
+    File(Mutex<File>),
+    /// Entries sent to the application.
+    Channel(mpsc::UnboundedSender<AuditEntry>),
+}
+
+/// Destination of audit entries; cheap to clone.
+#[derive(Clone)]
+pub struct AuditLog {
+    sink: Arc<Sink>,
+}
+
+impl AuditLog {
+    /// Appends entries to a file, one line each, creating it if needed.
+    ///
+    /// Lines are written as events happen, without buffering, so the file is complete
+    /// even if the process dies.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The log file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the file cannot be opened for appending.
+    pub fn to_file(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            sink: Arc::new(Sink::File(Mutex::new(file))),
+        })
+    }
+
+    /// Sends entries to a channel the application reads them from.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - The sending half; entries are dropped once the receiver is gone.
+    #[must_use]
+    pub fn to_channel(tx: mpsc::UnboundedSender<AuditEntry>) -> Self {
+        Self {
+            sink: Arc::new(Sink::Channel(tx)),
+        }
+    }
+
+    /// Records an event that happens now.
+    pub(crate) fn record(&self, client_id: usize, address: &str, event: AuditEvent) {
+        let entry = AuditEntry {
+            time: SystemTime::now(),
+            client_id,
+            address: address.to_string(),
+            event,
+        };
+        match self.sink.as_ref() {
+            Sink::File(file) => {
+                let mut file = file
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner);
+                if let Err(e) = writeln!(file, "{entry}") {
+                    log::error!("Failed to write audit entry: {e}");
+                }
+            }
+            Sink::Channel(tx) => {
+                let _ = tx.send(entry);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sink = match self.sink.as_ref() {
+            Sink::File(_) => "file",
+            Sink::Channel(_) => "channel",
+        };
+        f.debug_struct("AuditLog").field("sink", &sink).finish()
+    }
+}
+
+/// Writes `time` as an RFC 3339 UTC timestamp with milliseconds.
+fn write_timestamp(f: &mut fmt::Formatter<'_>, time: SystemTime) -> fmt::Result {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let secs_of_day = secs % 86_400;
+    write!(
+        f,
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date in the proleptic
+/// Gregorian calendar (Howard Hinnant's `civil_from_days`).
+pub(crate) fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
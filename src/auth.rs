@@ -26,22 +26,43 @@
 //! 4. Client sends the encrypted result back to the server
 //! 5. Server verifies the response matches its own encryption of the challenge
 //!
+//! # Password Files
+//!
+//! [`read_password_file`] loads the password files written by `vncpasswd` (usually
+//! `~/.vnc/passwd`), so existing deployments can reuse them.
+//!
 //! # Security Note
 //!
 //! VNC Authentication is a legacy protocol and has known security limitations. It should only
 //! be used on trusted networks or in conjunction with TLS/SSL tunneling.
+//!
+//! The password and the DES keys derived from it are wiped from memory when dropped, and
+//! responses are compared in constant time so the comparison doesn't leak how many bytes
+//! matched.
 
-use des::cipher::{BlockEncrypt, KeyInit};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use des::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
 use des::Des;
-use rand::Rng;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+/// The fixed key `vncpasswd` obfuscates password files with, in the d3des bit order used
+/// by VNC implementations.
+const PASSWORD_FILE_KEY: [u8; 8] = [23, 82, 107, 6, 35, 78, 88, 7];
+
+/// Length of one obfuscated password entry in a password file.
+const PASSWORD_FILE_ENTRY_LEN: usize = 8;
 
 /// Handles VNC authentication, specifically the VNC Authentication scheme as defined in RFC 6143 Section 7.2.2.
 ///
-/// This struct is responsible for managing the VNC server's password, generating a secure challenge
-/// for clients, and verifying their responses using DES encryption with a VNC-specific bit reversal quirk.
+/// This struct is responsible for managing the VNC server's password and verifying client
+/// responses using DES encryption with a VNC-specific bit reversal quirk.
 pub struct VncAuth {
-    /// The VNC password, if set. Stored as an `Option<String>`.
-    password: Option<String>,
+    /// The VNC password, if set. Wiped from memory when dropped.
+    password: Option<Zeroizing<String>>,
 }
 
 impl VncAuth {
@@ -55,23 +76,9 @@ impl VncAuth {
     ///
     /// A new `VncAuth` object.
     pub fn new(password: Option<String>) -> Self {
-        Self { password }
-    }
-
-    /// Generates a cryptographically random 16-byte challenge for VNC authentication.
-    ///
-    /// This challenge is sent to the client, which must encrypt it with the shared secret (password)
-    /// and send the result back for verification.
-    ///
-    /// # Returns
-    ///
-    /// A `[u8; 16]` array containing the random challenge bytes.
-    #[allow(clippy::unused_self)] // Kept as method for API consistency with other VncAuthenticator methods
-    pub fn generate_challenge(&self) -> [u8; 16] {
-        let mut rng = rand::thread_rng();
-        let mut challenge = [0u8; 16];
-        rng.fill(&mut challenge);
-        challenge
+        Self {
+            password: password.map(Zeroizing::new),
+        }
     }
 
     /// Verifies a client's authentication response against the generated challenge and the server's password.
@@ -88,10 +95,11 @@ impl VncAuth {
     /// # Returns
     ///
     /// `true` if the response matches the expected encrypted challenge, `false` otherwise.
+    /// The comparison takes the same time wherever the response differs.
     pub fn verify_response(&self, response: &[u8], challenge: &[u8; 16]) -> bool {
         if let Some(ref password) = self.password {
             let expected = self.encrypt_challenge(challenge, password);
-            response == expected.as_slice()
+            response.ct_eq(expected.as_slice()).into()
         } else {
             false
         }
@@ -113,8 +121,8 @@ impl VncAuth {
     /// A `Vec<u8>` containing the 16-byte encrypted challenge.
     #[allow(clippy::unused_self)] // Kept as method for API consistency with other VncAuthenticator methods
     fn encrypt_challenge(&self, challenge: &[u8; 16], password: &str) -> Vec<u8> {
-        // Prepare VNC password key (8 bytes, bit-reversed)
-        let mut key = [0u8; 8];
+        // Prepare VNC password key (8 bytes, bit-reversed), wiped when dropped like the cipher
+        let mut key = Zeroizing::new([0u8; 8]);
         let pw_bytes = password.as_bytes();
 
         // Copy password bytes (up to 8), truncate or pad with zeros
@@ -123,7 +131,7 @@ impl VncAuth {
         }
 
         // Create DES cipher with the VNC key
-        let cipher = Des::new_from_slice(&key).expect("8-byte key");
+        let cipher = Des::new_from_slice(key.as_slice()).expect("8-byte key");
 
         // Encrypt the 16-byte challenge as two 8-byte blocks (DES ECB mode)
         let mut encrypted = vec![0u8; 16];
@@ -162,7 +170,7 @@ impl VncAuth {
 /// # Example
 ///
 /// `0b10110001` (177) becomes `0b10001101` (141).
-fn reverse_bits(byte: u8) -> u8 {
+pub(crate) fn reverse_bits(byte: u8) -> u8 {
     let mut result = 0u8;
     for i in 0..8 {
         if byte & (1 << i) != 0 {
@@ -171,3 +179,86 @@ fn reverse_bits(byte: u8) -> u8 {
     }
     result
 }
+
+/// The passwords stored in a `vncpasswd` password file.
+///
+/// The server has no view-only mode of its own; applications that offer one can check
+/// `view_only_password` in their [`Authenticator`](crate::authenticator::Authenticator).
+/// Both passwords are wiped from memory when dropped.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PasswordFile {
+    /// The full-access password.
+    pub password: Zeroizing<String>,
+    /// The view-only password, if the file has a second entry.
+    pub view_only_password: Option<Zeroizing<String>>,
+}
+
+impl fmt::Debug for PasswordFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PasswordFile")
+            .field("password", &"<redacted>")
+            .field(
+                "view_only_password",
+                &self.view_only_password.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// Reads a password file written by `vncpasswd`, such as `~/.vnc/passwd`.
+///
+/// The file holds the password, truncated to 8 bytes and DES-encrypted with a fixed key,
+/// optionally followed by a view-only password in the same format. Pass a copy of the
+/// password (`file.password.to_string()`) to `VncServer::set_password` to reuse it.
+///
+/// # Arguments
+///
+/// * `path` - The password file.
+///
+/// # Returns
+///
+/// The passwords stored in the file.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if the file cannot be read or is shorter than one entry.
+pub fn read_password_file(path: impl AsRef<Path>) -> Result<PasswordFile, io::Error> {
+    parse_password_file(&std::fs::read(path)?)
+}
+
+/// Decodes the contents of a `vncpasswd` password file (see [`read_password_file`]).
+///
+/// # Arguments
+///
+/// * `data` - The file contents.
+///
+/// # Returns
+///
+/// The passwords stored in the file.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` with `InvalidData` if `data` is shorter than one entry.
+pub fn parse_password_file(data: &[u8]) -> Result<PasswordFile, io::Error> {
+    let mut entries = data.chunks_exact(PASSWORD_FILE_ENTRY_LEN);
+    let password = entries.next().map(decrypt_password_entry).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "VNC password file is too short")
+    })?;
+    let view_only_password = entries.next().map(decrypt_password_entry);
+    Ok(PasswordFile {
+        password,
+        view_only_password,
+    })
+}
+
+/// Decrypts one password file entry, dropping the zero padding.
+fn decrypt_password_entry(entry: &[u8]) -> Zeroizing<String> {
+    let cipher = Des::new_from_slice(&PASSWORD_FILE_KEY.map(reverse_bits)).expect("8-byte key");
+    let mut block = Zeroizing::new(<[u8; 8]>::try_from(entry).expect("8-byte entry"));
+    cipher.decrypt_block((&mut *block).into());
+    let end = block
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(block.len());
+    Zeroizing::new(String::from_utf8_lossy(&block[..end]).into_owned())
+}
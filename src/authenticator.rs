@@ -0,0 +1,485 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pluggable client authentication.
+//!
+//! The server checks the credentials clients present during the security handshake with
+//! an [`Authenticator`] (see [`VncServer::set_authenticator`](crate::VncServer::set_authenticator)).
+//! The default, [`PasswordAuthenticator`], checks a single shared password, which is what
+//! `VncServer::new` and `set_password` configure. Applications can implement the trait to
+//! check credentials against a database, LDAP or per-user passwords.
+//!
+//! The credentials depend on the security type the client chose:
+//!
+//! | Security type                          | `username` | `response`            | `challenge`          |
+//! |----------------------------------------|------------|-----------------------|----------------------|
+//! | VNC Authentication (also inside TLS)   | empty      | 16-byte DES response  | 16-byte challenge    |
+//! | RSA-AES (`RA2`, `RA2ne`)               | if asked   | password              | empty                |
+//! | MS-Logon II                            | username   | password              | empty                |
+//!
+//! Use [`vnc_response_matches`] to check a VNC Authentication response against a stored
+//! password, and [`read_password_file`] to load a `vncpasswd` file such as
+//! `~/.vnc/passwd`. SASL mechanisms check credentials through Cyrus SASL instead.
+//!
+//! [`TokenAuthenticator`] accepts passwords the application mints for a single session or
+//! a limited time, such as a code read out to a support technician.
+//!
+//! Once a client has authenticated, a [`ConnectionApprover`] set in
+//! `ConnectionConfig::approver` can still refuse it or make it view-only, for example
+//! after asking the person at the host.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::collections::HashMap;
+//! use rustvncserver::authenticator::{AuthDecision, AuthFuture, Authenticator};
+//!
+//! struct Accounts(HashMap<String, String>);
+//!
+//! impl Authenticator for Accounts {
+//!     fn accepts_usernames(&self) -> bool {
+//!         true
+//!     }
+//!
+//!     fn accepts_vnc_challenge(&self) -> bool {
+//!         false
+//!     }
+//!
+//!     fn verify<'a>(
+//!         &'a self,
+//!         username: &'a str,
+//!         response: &'a [u8],
+//!         _challenge: &'a [u8],
+//!     ) -> AuthFuture<'a> {
+//!         Box::pin(async move {
+//!             match self.0.get(username) {
+//!                 Some(password) if password.as_bytes() == response => AuthDecision::Accept,
+//!                 _ => AuthDecision::Reject,
+//!             }
+//!         })
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use crate::auth::VncAuth;
+pub use crate::auth::{parse_password_file, read_password_file, PasswordFile};
+use crate::server::AuthMethod;
+
+/// The outcome of checking a client's credentials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthDecision {
+    /// The credentials are valid; the client may connect.
+    Accept,
+    /// The credentials are invalid; the handshake fails.
+    Reject,
+}
+
+/// The future returned by [`Authenticator::verify`].
+pub type AuthFuture<'a> = Pin<Box<dyn Future<Output = AuthDecision> + Send + 'a>>;
+
+/// Checks the credentials clients present during the security handshake.
+///
+/// The server offers the security types the authenticator can check: VNC Authentication
+/// if [`accepts_vnc_challenge`](Self::accepts_vnc_challenge), MS-Logon II and RSA-AES
+/// with a username if [`accepts_usernames`](Self::accepts_usernames), and the types without
+/// authentication only if authentication isn't [`required`](Self::required).
+pub trait Authenticator: Send + Sync {
+    /// Whether clients must authenticate. When `false`, the security types without
+    /// authentication are offered and `verify` is not called. Default: `true`.
+    fn required(&self) -> bool {
+        true
+    }
+
+    /// Whether `verify` can check VNC Authentication challenge responses, which requires
+    /// knowing the password in the clear. Default: `true`.
+    fn accepts_vnc_challenge(&self) -> bool {
+        true
+    }
+
+    /// Whether `verify` checks named accounts rather than a single shared password.
+    /// Enables MS-Logon II and asks RSA-AES clients for a username. Default: `false`.
+    fn accepts_usernames(&self) -> bool {
+        false
+    }
+
+    /// Checks a client's credentials.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - The account name, or empty for security types without one.
+    /// * `response` - The client's challenge response, or its password when `challenge`
+    ///   is empty.
+    /// * `challenge` - The challenge the client answered, or empty when the client sent
+    ///   its password (over an encrypted channel where the protocol has one).
+    ///
+    /// # Returns
+    ///
+    /// Whether to accept the client.
+    fn verify<'a>(
+        &'a self,
+        username: &'a str,
+        response: &'a [u8],
+        challenge: &'a [u8],
+    ) -> AuthFuture<'a>;
+}
+
+/// A connection that authenticated and waits for approval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionRequest {
+    /// The peer address (`IP:port`), or the description of a non-TCP transport.
+    pub address: String,
+    /// The repeater ID, for connections made through a repeater.
+    pub repeater_id: Option<String>,
+    /// The method the client authenticated with.
+    pub method: AuthMethod,
+}
+
+/// The answer to a [`ConnectionRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Approval {
+    /// Let the client connect.
+    Accept,
+    /// Let the client connect, but drop its keyboard, pointer and clipboard input and its
+    /// resize requests.
+    ViewOnly,
+    /// Refuse the client; the reason is sent to it in the failed `SecurityResult`.
+    Reject(String),
+}
+
+/// The future returned by a [`ConnectionApprover`].
+pub type ApprovalFuture = Pin<Box<dyn Future<Output = Approval> + Send>>;
+
+/// Decides whether an authenticated client may connect.
+///
+/// Called at the end of the security handshake, before the `SecurityResult` is sent.
+/// The wait counts towards `ConnectionConfig::handshake_timeout` and `security_timeout`,
+/// so raise them when the approver asks a person.
+pub type ConnectionApprover = Arc<dyn Fn(ConnectionRequest) -> ApprovalFuture + Send + Sync>;
+
+/// The default authenticator: a single shared password, or no authentication.
+///
+/// The password is wiped from memory when the authenticator is dropped.
+#[derive(Clone, Default)]
+pub struct PasswordAuthenticator {
+    /// The password; `None` means no authentication.
+    password: Option<Zeroizing<String>>,
+}
+
+impl PasswordAuthenticator {
+    /// Creates an authenticator for a shared password.
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - The password, or `None` to let clients connect without
+    ///   authentication.
+    #[must_use]
+    pub fn new(password: Option<String>) -> Self {
+        Self {
+            password: password.map(Zeroizing::new),
+        }
+    }
+}
+
+impl std::fmt::Debug for PasswordAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PasswordAuthenticator")
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Authenticator for PasswordAuthenticator {
+    fn required(&self) -> bool {
+        self.password.is_some()
+    }
+
+    fn verify<'a>(
+        &'a self,
+        _username: &'a str,
+        response: &'a [u8],
+        challenge: &'a [u8],
+    ) -> AuthFuture<'a> {
+        let accepted = match &self.password {
+            None => true,
+            Some(password) if challenge.is_empty() => password.as_bytes().ct_eq(response).into(),
+            Some(password) => vnc_response_matches(password, challenge, response),
+        };
+        Box::pin(async move {
+            if accepted {
+                AuthDecision::Accept
+            } else {
+                AuthDecision::Reject
+            }
+        })
+    }
+}
+
+/// Checks a VNC Authentication response: the 16-byte challenge DES-encrypted with the
+/// password (truncated to 8 bytes) as the key. The comparison runs in constant time.
+///
+/// # Arguments
+///
+/// * `password` - The password the client should have used.
+/// * `challenge` - The challenge sent to the client.
+/// * `response` - The client's response.
+///
+/// # Returns
+///
+/// `true` if the response matches.
+#[must_use]
+pub fn vnc_response_matches(password: &str, challenge: &[u8], response: &[u8]) -> bool {
+    let Ok(challenge) = <&[u8; 16]>::try_from(challenge) else {
+        return false;
+    };
+    VncAuth::new(Some(password.to_string())).verify_response(response, challenge)
+}
+
+/// Length of minted tokens. VNC Authentication only uses the first 8 characters of a
+/// password, so longer tokens would not be any stronger.
+const TOKEN_LEN: usize = 8;
+
+/// The limits on one access token.
+#[derive(Debug, Clone, Copy)]
+struct TokenLimits {
+    /// When the token stops being accepted, if it expires.
+    expires_at: Option<Instant>,
+    /// Whether the token is revoked after the first successful login.
+    single_use: bool,
+}
+
+impl TokenLimits {
+    /// Whether the token has expired.
+    fn expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+}
+
+/// An authenticator for single-use and time-limited access tokens.
+///
+/// The application mints a token with [`mint`](Self::mint), hands it to the person who
+/// should connect (for example a support technician), and the client uses it as its
+/// password. A single-use token is revoked as soon as a client logs in with it, and an
+/// expiring token is refused once its lifetime is over; later connections with either
+/// are rejected. Tokens work with every security type that checks a password, and the
+/// username, if any, is ignored.
+///
+/// Other credentials, such as the regular shared password, can still be accepted by
+/// passing a fallback authenticator to [`with_fallback`](Self::with_fallback).
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use rustvncserver::authenticator::TokenAuthenticator;
+/// # async fn example(server: &rustvncserver::VncServer) {
+/// let tokens = Arc::new(TokenAuthenticator::new());
+/// server.set_authenticator(tokens.clone()).await;
+/// let code = tokens.mint(Some(Duration::from_secs(600)), true);
+/// println!("Support code: {code}");
+/// # }
+/// ```
+#[derive(Default)]
+pub struct TokenAuthenticator {
+    /// The valid tokens and their limits.
+    tokens: Mutex<HashMap<String, TokenLimits>>,
+    /// The authenticator for credentials that are not tokens.
+    fallback: Option<Arc<dyn Authenticator>>,
+}
+
+impl TokenAuthenticator {
+    /// Creates an authenticator that only accepts tokens.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an authenticator that accepts tokens as well as the credentials `fallback`
+    /// accepts.
+    ///
+    /// # Arguments
+    ///
+    /// * `fallback` - Checks credentials that are not tokens, e.g. a
+    ///   [`PasswordAuthenticator`] for the regular password. If it does not require
+    ///   authentication, neither does this authenticator.
+    #[must_use]
+    pub fn with_fallback(fallback: Arc<dyn Authenticator>) -> Self {
+        Self {
+            tokens: Mutex::default(),
+            fallback: Some(fallback),
+        }
+    }
+
+    /// Mints a random token.
+    ///
+    /// # Arguments
+    ///
+    /// * `lifetime` - How long the token is accepted, or `None` for no time limit.
+    /// * `single_use` - Whether the token is revoked after the first successful login.
+    ///
+    /// # Returns
+    ///
+    /// The token, 8 alphanumeric characters, for the client to use as its password.
+    pub fn mint(&self, lifetime: Option<Duration>, single_use: bool) -> String {
+        let mut tokens = self.lock();
+        loop {
+            let token: String = rand::thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(TOKEN_LEN)
+                .map(char::from)
+                .collect();
+            if !tokens.contains_key(&token) {
+                tokens.insert(token.clone(), Self::limits(lifetime, single_use));
+                return token;
+            }
+        }
+    }
+
+    /// Adds a token chosen by the application, replacing the limits of an existing one.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token. Only its first 8 characters are checked by VNC
+    ///   Authentication.
+    /// * `lifetime` - How long the token is accepted, or `None` for no time limit.
+    /// * `single_use` - Whether the token is revoked after the first successful login.
+    pub fn insert(&self, token: impl Into<String>, lifetime: Option<Duration>, single_use: bool) {
+        self.lock()
+            .insert(token.into(), Self::limits(lifetime, single_use));
+    }
+
+    /// Revokes a token.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the token was valid.
+    pub fn revoke(&self, token: &str) -> bool {
+        let now = Instant::now();
+        self.lock()
+            .remove(token)
+            .is_some_and(|limits| !limits.expired(now))
+    }
+
+    /// Returns the number of tokens that can still be used.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        let mut tokens = self.lock();
+        Self::remove_expired(&mut tokens);
+        tokens.len()
+    }
+
+    /// Returns `true` if no token can be used.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Builds the limits for a new token.
+    fn limits(lifetime: Option<Duration>, single_use: bool) -> TokenLimits {
+        TokenLimits {
+            expires_at: lifetime.map(|lifetime| Instant::now() + lifetime),
+            single_use,
+        }
+    }
+
+    /// Drops tokens that have expired.
+    fn remove_expired(tokens: &mut HashMap<String, TokenLimits>) {
+        let now = Instant::now();
+        tokens.retain(|_, limits| !limits.expired(now));
+    }
+
+    /// Locks the token table, recovering it if another thread panicked while holding it.
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, TokenLimits>> {
+        self.tokens
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Finds the token matching the credentials and consumes it if it is single-use.
+    fn redeem(&self, response: &[u8], challenge: &[u8]) -> bool {
+        let mut tokens = self.lock();
+        Self::remove_expired(&mut tokens);
+        let matched = tokens
+            .iter()
+            .find(|(token, _)| {
+                if challenge.is_empty() {
+                    token.as_bytes().ct_eq(response).into()
+                } else {
+                    vnc_response_matches(token, challenge, response)
+                }
+            })
+            .map(|(token, limits)| (token.clone(), limits.single_use));
+        match matched {
+            Some((token, single_use)) => {
+                if single_use {
+                    tokens.remove(&token);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Authenticator for TokenAuthenticator {
+    fn required(&self) -> bool {
+        self.fallback
+            .as_ref()
+            .is_none_or(|fallback| fallback.required())
+    }
+
+    fn accepts_usernames(&self) -> bool {
+        self.fallback
+            .as_ref()
+            .is_some_and(|fallback| fallback.accepts_usernames())
+    }
+
+    fn verify<'a>(
+        &'a self,
+        username: &'a str,
+        response: &'a [u8],
+        challenge: &'a [u8],
+    ) -> AuthFuture<'a> {
+        let redeemed = self.redeem(response, challenge);
+        Box::pin(async move {
+            if redeemed {
+                return AuthDecision::Accept;
+            }
+            match &self.fallback {
+                Some(fallback) => fallback.verify(username, response, challenge).await,
+                None => AuthDecision::Reject,
+            }
+        })
+    }
+}
+
+impl std::fmt::Debug for TokenAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenAuthenticator")
+            .field("tokens", &self.len())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,525 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stable C API for embedding the server in C and C++ applications.
+//!
+//! This module is enabled by the `capi` feature. The matching header is generated into
+//! `include/rustvncserver.h` by the build script.
+//!
+//! The API is built around an opaque `RvncServer` handle that owns its own Tokio runtime,
+//! so C callers never deal with async Rust. All functions are thread-safe except that a
+//! handle must not be used after `rvnc_server_free`.
+//!
+//! ```c
+//! RvncServer *server = rvnc_server_new(1024, 768, "My Desktop", NULL);
+//! rvnc_server_listen(server, 5900);
+//! for (;;) {
+//!     rvnc_server_update_framebuffer(server, pixels, 1024 * 768 * 4);
+//!     RvncEvent event;
+//!     while (rvnc_server_poll_event(server, &event) == 1) {
+//!         handle_event(&event);
+//!     }
+//! }
+//! rvnc_server_free(server);
+//! ```
+//!
+//! Functions returning `int32_t` return `RVNC_OK` on success or one of the negative
+//! `RVNC_ERR_*` codes.
+
+use std::ffi::{c_char, CStr, CString};
+use std::sync::{Arc, Mutex};
+
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+use crate::server::{ServerEvent, VncServer};
+
+/// The call succeeded.
+pub const RVNC_OK: i32 = 0;
+/// A required pointer argument was null.
+pub const RVNC_ERR_NULL: i32 = -1;
+/// An argument was invalid (bad size, invalid UTF-8, ...).
+pub const RVNC_ERR_INVALID: i32 = -2;
+/// An I/O or runtime error occurred.
+pub const RVNC_ERR_IO: i32 = -3;
+
+/// Maximum time `rvnc_server_shutdown` waits for clients to disconnect.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Kind of event returned by `rvnc_server_poll_event`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RvncEventKind {
+    /// A client connected (`client_id`, `text` = subject of its verified TLS certificate,
+    /// or null).
+    ClientConnected = 0,
+    /// A client disconnected (`client_id`).
+    ClientDisconnected = 1,
+    /// A key was pressed or released (`client_id`, `down`, `key`).
+    KeyPress = 2,
+    /// The pointer moved or a button changed (`client_id`, `x`, `y`, `button_mask`).
+    PointerMove = 3,
+    /// Clipboard text was received (`client_id`, `text`).
+    CutText = 4,
+    /// A client completed authentication (`client_id`, `text` = address, `auth_method`,
+    /// `success`).
+    AuthAttempt = 5,
+}
+
+/// An event received from a client.
+///
+/// Only the fields documented for `kind` are meaningful; the others are zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RvncEvent {
+    /// The kind of event.
+    pub kind: RvncEventKind,
+    /// The client the event belongs to.
+    pub client_id: u64,
+    /// `true` if the key was pressed, `false` if released.
+    pub down: bool,
+    /// The X11 keysym of the key.
+    pub key: u32,
+    /// Pointer X coordinate.
+    pub x: u16,
+    /// Pointer Y coordinate.
+    pub y: u16,
+    /// Pointer button mask (bit 0 left, 1 middle, 2 right, 3-6 wheel).
+    pub button_mask: u8,
+    /// RFB security type number of the authentication method (1 = none, 2 = VNC auth,
+    /// 5/6 = RSA-AES, 19 = `VeNCrypt`, 20 = SASL, 113 = MS-Logon II).
+    pub auth_method: u8,
+    /// `true` if authentication succeeded.
+    pub success: bool,
+    /// NUL-terminated UTF-8 clipboard text, client address or certificate subject, owned
+    /// by the server. Valid until the next call to `rvnc_server_poll_event` or
+    /// `rvnc_server_free` on the same handle.
+    pub text: *const c_char,
+}
+
+/// Opaque server handle.
+pub struct RvncServer {
+    runtime: Runtime,
+    server: Arc<VncServer>,
+    events: Mutex<mpsc::UnboundedReceiver<ServerEvent>>,
+    listener: Mutex<Option<JoinHandle<()>>>,
+    last_text: Mutex<Option<CString>>,
+}
+
+/// Converts a Rust string into a C string.
+///
+/// Interior NULs cannot be represented in a C string, so the text is cut at the first one.
+fn to_c_string(text: String) -> CString {
+    CString::new(text).unwrap_or_else(|e| {
+        let end = e.nul_position();
+        let mut bytes = e.into_vec();
+        bytes.truncate(end);
+        CString::new(bytes).unwrap_or_default()
+    })
+}
+
+/// Converts a nullable C string into an optional Rust string.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a valid NUL-terminated string.
+unsafe fn optional_string(ptr: *const c_char) -> Result<Option<String>, i32> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: the caller guarantees `ptr` is a valid NUL-terminated string.
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map(|s| Some(s.to_string()))
+        .map_err(|_| RVNC_ERR_INVALID)
+}
+
+/// Creates a new server with an RGBA32 framebuffer of the given size.
+///
+/// Returns null if `name` or `password` is not valid UTF-8 or the runtime cannot start.
+///
+/// # Safety
+///
+/// `name` and `password` must each be null or point to a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rvnc_server_new(
+    width: u16,
+    height: u16,
+    name: *const c_char,
+    password: *const c_char,
+) -> *mut RvncServer {
+    // SAFETY: forwarded from this function's contract.
+    let Ok(name) = (unsafe { optional_string(name) }) else {
+        return std::ptr::null_mut();
+    };
+    // SAFETY: forwarded from this function's contract.
+    let Ok(password) = (unsafe { optional_string(password) }) else {
+        return std::ptr::null_mut();
+    };
+
+    let Ok(runtime) = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("rvnc-worker")
+        .build()
+    else {
+        return std::ptr::null_mut();
+    };
+
+    let (server, events) = VncServer::new(width, height, name.unwrap_or_default(), password);
+
+    Box::into_raw(Box::new(RvncServer {
+        runtime,
+        server: Arc::new(server),
+        events: Mutex::new(events),
+        listener: Mutex::new(None),
+        last_text: Mutex::new(None),
+    }))
+}
+
+/// Starts listening for connections on `port` in the background.
+///
+/// Calling this again while already listening has no effect.
+///
+/// # Safety
+///
+/// `server` must be null or a handle returned by `rvnc_server_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rvnc_server_listen(server: *mut RvncServer, port: u16) -> i32 {
+    // SAFETY: the caller guarantees `server` is null or a live handle.
+    let Some(handle) = (unsafe { server.as_ref() }) else {
+        return RVNC_ERR_NULL;
+    };
+
+    let Ok(mut listener) = handle.listener.lock() else {
+        return RVNC_ERR_IO;
+    };
+    if listener.as_ref().is_some_and(|l| !l.is_finished()) {
+        return RVNC_OK;
+    }
+
+    // Bind synchronously so the caller learns about errors such as the port being in use,
+    // then accept on the same socket so the port can't be taken in between
+    let bound = handle
+        .runtime
+        .block_on(tokio::net::TcpListener::bind(format!("0.0.0.0:{port}")));
+    let tcp_listener = match bound {
+        Ok(tcp_listener) => tcp_listener,
+        Err(e) => {
+            log::error!("Failed to bind VNC server to port {port}: {e}");
+            return RVNC_ERR_IO;
+        }
+    };
+    log::info!("VNC Server listening on port {port}");
+
+    let vnc_server = handle.server.clone();
+    *listener = Some(handle.runtime.spawn(async move {
+        if let Err(e) = vnc_server.listen_on(tcp_listener).await {
+            log::error!("VNC server listener on port {port} failed: {e}");
+        }
+    }));
+    RVNC_OK
+}
+
+/// Replaces the framebuffer contents with tightly packed RGBA32 pixels.
+///
+/// `len` must equal `width * height * 4` of the current framebuffer.
+///
+/// # Safety
+///
+/// `server` must be null or a live handle, and `data` must be null or point to `len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rvnc_server_update_framebuffer(
+    server: *mut RvncServer,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    // SAFETY: the caller guarantees `server` is null or a live handle.
+    let Some(handle) = (unsafe { server.as_ref() }) else {
+        return RVNC_ERR_NULL;
+    };
+    if data.is_null() {
+        return RVNC_ERR_NULL;
+    }
+    // SAFETY: the caller guarantees `data` points to `len` readable bytes.
+    let pixels = unsafe { std::slice::from_raw_parts(data, len) };
+
+    match handle
+        .runtime
+        .block_on(handle.server.framebuffer().update_from_slice(pixels))
+    {
+        Ok(()) => RVNC_OK,
+        Err(_) => RVNC_ERR_INVALID,
+    }
+}
+
+/// Resizes the framebuffer. Clients are sent the new size if they support it.
+///
+/// Returns `RVNC_ERR_INVALID` if a dimension is zero or larger than 8192.
+///
+/// # Safety
+///
+/// `server` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rvnc_server_resize(
+    server: *mut RvncServer,
+    width: u16,
+    height: u16,
+) -> i32 {
+    // SAFETY: the caller guarantees `server` is null or a live handle.
+    let Some(handle) = (unsafe { server.as_ref() }) else {
+        return RVNC_ERR_NULL;
+    };
+    match handle.runtime.block_on(handle.server.resize(width, height)) {
+        Ok(()) => RVNC_OK,
+        Err(_) => RVNC_ERR_INVALID,
+    }
+}
+
+/// Sets or clears (`password == NULL`) the password required for new connections.
+///
+/// # Safety
+///
+/// `server` must be null or a live handle, and `password` must be null or point to a
+/// valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rvnc_server_set_password(
+    server: *mut RvncServer,
+    password: *const c_char,
+) -> i32 {
+    // SAFETY: the caller guarantees `server` is null or a live handle.
+    let Some(handle) = (unsafe { server.as_ref() }) else {
+        return RVNC_ERR_NULL;
+    };
+    // SAFETY: forwarded from this function's contract.
+    let password = match unsafe { optional_string(password) } {
+        Ok(password) => password,
+        Err(code) => return code,
+    };
+
+    handle
+        .runtime
+        .block_on(handle.server.set_password(password));
+    RVNC_OK
+}
+
+/// Renames the desktop, telling connected clients that support it.
+///
+/// # Safety
+///
+/// `server` must be null or a live handle, and `name` must be null or point to a valid
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rvnc_server_set_desktop_name(
+    server: *mut RvncServer,
+    name: *const c_char,
+) -> i32 {
+    // SAFETY: the caller guarantees `server` is null or a live handle.
+    let Some(handle) = (unsafe { server.as_ref() }) else {
+        return RVNC_ERR_NULL;
+    };
+    // SAFETY: forwarded from this function's contract.
+    let name = match unsafe { optional_string(name) } {
+        Ok(Some(name)) => name,
+        Ok(None) => return RVNC_ERR_NULL,
+        Err(code) => return code,
+    };
+
+    handle
+        .runtime
+        .block_on(handle.server.set_desktop_name(name));
+    RVNC_OK
+}
+
+/// Converts a server event into its C representation.
+///
+/// Text is stored in `last_text`, which keeps it alive for the caller. Returns `None` for
+/// events the C API does not expose.
+#[allow(clippy::cast_possible_truncation)] // usize client IDs fit in u64 on all supported targets
+fn to_rvnc_event(event: ServerEvent, last_text: &mut Option<CString>) -> Option<RvncEvent> {
+    let mut result = RvncEvent {
+        kind: RvncEventKind::ClientConnected,
+        client_id: 0,
+        down: false,
+        key: 0,
+        x: 0,
+        y: 0,
+        button_mask: 0,
+        auth_method: 0,
+        success: false,
+        text: std::ptr::null(),
+    };
+    match event {
+        ServerEvent::ClientConnected {
+            client_id,
+            certificate_subject,
+        } => {
+            result.client_id = client_id as u64;
+            if let Some(subject) = certificate_subject {
+                let subject = to_c_string(subject);
+                result.text = subject.as_ptr();
+                *last_text = Some(subject);
+            }
+        }
+        ServerEvent::ClientDisconnected { client_id } => {
+            result.kind = RvncEventKind::ClientDisconnected;
+            result.client_id = client_id as u64;
+        }
+        ServerEvent::KeyPress {
+            client_id,
+            down,
+            key,
+        } => {
+            result.kind = RvncEventKind::KeyPress;
+            result.client_id = client_id as u64;
+            result.down = down;
+            result.key = key;
+        }
+        ServerEvent::PointerMove {
+            client_id,
+            x,
+            y,
+            button_mask,
+        } => {
+            result.kind = RvncEventKind::PointerMove;
+            result.client_id = client_id as u64;
+            result.x = x;
+            result.y = y;
+            result.button_mask = button_mask;
+        }
+        ServerEvent::CutText { client_id, text } => {
+            result.kind = RvncEventKind::CutText;
+            result.client_id = client_id as u64;
+            let text = to_c_string(text);
+            result.text = text.as_ptr();
+            *last_text = Some(text);
+        }
+        ServerEvent::AuthAttempt {
+            client_id,
+            address,
+            method,
+            success,
+        } => {
+            result.kind = RvncEventKind::AuthAttempt;
+            result.client_id = client_id as u64;
+            result.auth_method = method.security_type();
+            result.success = success;
+            let address = to_c_string(address);
+            result.text = address.as_ptr();
+            *last_text = Some(address);
+        }
+        // Not exposed through the C API; dropping a resize request's reply rejects it
+        ServerEvent::ReverseConnection { .. }
+        | ServerEvent::HandshakeTimeout { .. }
+        | ServerEvent::ResizeRequest { .. }
+        | ServerEvent::PseudoEncoding { .. }
+        | ServerEvent::UpdateTiming { .. }
+        | ServerEvent::Negotiated { .. }
+        | ServerEvent::ExtendedInput { .. }
+        | ServerEvent::Listening { .. }
+        | ServerEvent::DrainStarted { .. }
+        | ServerEvent::ClientClosing { .. }
+        | ServerEvent::DrainCompleted { .. } => return None,
+    }
+    Some(result)
+}
+
+/// Retrieves the next pending event without blocking.
+///
+/// Returns 1 if `out` was filled, 0 if no event is pending, or a negative error code.
+///
+/// # Safety
+///
+/// `server` must be null or a live handle, and `out` must be null or point to writable
+/// memory for one `RvncEvent`.
+#[no_mangle]
+pub unsafe extern "C" fn rvnc_server_poll_event(
+    server: *mut RvncServer,
+    out: *mut RvncEvent,
+) -> i32 {
+    // SAFETY: the caller guarantees `server` is null or a live handle.
+    let Some(handle) = (unsafe { server.as_ref() }) else {
+        return RVNC_ERR_NULL;
+    };
+    if out.is_null() {
+        return RVNC_ERR_NULL;
+    }
+
+    let Ok(mut events) = handle.events.lock() else {
+        return RVNC_ERR_IO;
+    };
+    let Ok(mut last_text) = handle.last_text.lock() else {
+        return RVNC_ERR_IO;
+    };
+    *last_text = None;
+
+    loop {
+        let Ok(event) = events.try_recv() else {
+            return 0;
+        };
+        if let Some(result) = to_rvnc_event(event, &mut last_text) {
+            // SAFETY: the caller guarantees `out` points to writable memory for one event.
+            unsafe { out.write(result) };
+            return 1;
+        }
+    }
+}
+
+/// Stops listening and disconnects all clients, keeping the handle usable.
+///
+/// # Safety
+///
+/// `server` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rvnc_server_shutdown(server: *mut RvncServer) -> i32 {
+    // SAFETY: the caller guarantees `server` is null or a live handle.
+    let Some(handle) = (unsafe { server.as_ref() }) else {
+        return RVNC_ERR_NULL;
+    };
+
+    if let Ok(mut listener) = handle.listener.lock() {
+        if let Some(listener) = listener.take() {
+            listener.abort();
+        }
+    }
+
+    let vnc_server = handle.server.clone();
+    handle.runtime.block_on(async move {
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, vnc_server.disconnect_all_clients())
+            .await
+            .is_err()
+        {
+            log::warn!("Timed out disconnecting VNC clients during shutdown");
+        }
+    });
+    RVNC_OK
+}
+
+/// Shuts the server down and releases the handle. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `server` must be null or a handle returned by `rvnc_server_new` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rvnc_server_free(server: *mut RvncServer) {
+    if server.is_null() {
+        return;
+    }
+    // SAFETY: forwarded from this function's contract.
+    unsafe { rvnc_server_shutdown(server) };
+    // SAFETY: `server` was created by `Box::into_raw` in `rvnc_server_new`.
+    drop(unsafe { Box::from_raw(server) });
+}
@@ -46,23 +46,92 @@ use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::sync::Notify;
 use tokio::sync::RwLock;
 
-use crate::auth::VncAuth;
+use crate::analysis::{self, ContentClass};
+use crate::audit::{AuditEvent, AuditLog};
+use crate::authenticator::Authenticator;
+use crate::config::{
+    ChromaSubsampling, ClipboardOversizePolicy, ColorMode, ResizePolicy, ServerConfig, Transform,
+};
+use crate::desktop_size::{ResizeOutcome, ResizeReply, ResizeStatus, Screen};
+use crate::encode_cache::{CacheKey, Lookup};
+use crate::encode_pool::EncodePool;
 use crate::encoding;
 use crate::encoding::tight::TightStreamCompressor;
-use crate::framebuffer::{DirtyRegion, Framebuffer};
+#[cfg(feature = "file-transfer")]
+use crate::file_transfer::{self, FileTransfer, Transferred};
+use crate::framebuffer::{
+    DirtyRegion, DirtyRegionReceiver, FrameSnapshot, Framebuffer, RectPixels,
+};
+use crate::gii::{self, GiiEvent};
+use crate::keysym::{KeyEvent, KeyNormalizer, LedState};
+#[cfg(feature = "file-transfer")]
+use crate::protocol::MSG_FILE_TRANSFER;
 use crate::protocol::{
     PixelFormat, Rectangle, ServerInit, CLIENT_MSG_CLIENT_CUT_TEXT,
-    CLIENT_MSG_FRAMEBUFFER_UPDATE_REQUEST, CLIENT_MSG_KEY_EVENT, CLIENT_MSG_POINTER_EVENT,
-    CLIENT_MSG_SET_ENCODINGS, CLIENT_MSG_SET_PIXEL_FORMAT, ENCODING_COMPRESS_LEVEL_0,
-    ENCODING_COMPRESS_LEVEL_9, ENCODING_COPYRECT, ENCODING_CORRE, ENCODING_HEXTILE,
-    ENCODING_QUALITY_LEVEL_0, ENCODING_QUALITY_LEVEL_9, ENCODING_RAW, ENCODING_RRE, ENCODING_TIGHT,
-    ENCODING_TIGHTPNG, ENCODING_ZLIB, ENCODING_ZLIBHEX, ENCODING_ZRLE, ENCODING_ZYWRLE,
-    PROTOCOL_VERSION, SECURITY_RESULT_FAILED, SECURITY_RESULT_OK, SECURITY_TYPE_NONE,
-    SECURITY_TYPE_VNC_AUTH, SERVER_MSG_FRAMEBUFFER_UPDATE, SERVER_MSG_SERVER_CUT_TEXT,
+    CLIENT_MSG_ENABLE_CONTINUOUS_UPDATES, CLIENT_MSG_FRAMEBUFFER_UPDATE_REQUEST,
+    CLIENT_MSG_KEY_EVENT, CLIENT_MSG_POINTER_EVENT, CLIENT_MSG_RESUME_SESSION,
+    CLIENT_MSG_SET_DESKTOP_SIZE, CLIENT_MSG_SET_ENCODINGS, CLIENT_MSG_SET_PIXEL_FORMAT,
+    DESKTOP_SIZE_REASON_CLIENT, DESKTOP_SIZE_REASON_SERVER, ENCODING_COMPRESS_LEVEL_0,
+    ENCODING_COMPRESS_LEVEL_9, ENCODING_CONTINUOUS_UPDATES, ENCODING_COPYRECT, ENCODING_CORRE,
+    ENCODING_DESKTOP_NAME, ENCODING_DESKTOP_SIZE, ENCODING_EXTENDED_DESKTOP_SIZE, ENCODING_GII,
+    ENCODING_HEXTILE, ENCODING_QEMU_LED_STATE, ENCODING_QUALITY_LEVEL_0, ENCODING_QUALITY_LEVEL_9,
+    ENCODING_RAW, ENCODING_RRE, ENCODING_SESSION_RESUME, ENCODING_TIGHT, ENCODING_TIGHTPNG,
+    ENCODING_TRLE, ENCODING_VMWARE_LED_STATE, ENCODING_ZLIB, ENCODING_ZLIBHEX, ENCODING_ZRLE,
+    ENCODING_ZSTD, ENCODING_ZYWRLE, MSG_GII, PROTOCOL_VERSION,
+    SERVER_MSG_END_OF_CONTINUOUS_UPDATES, SERVER_MSG_FRAMEBUFFER_UPDATE,
+    SERVER_MSG_SERVER_CUT_TEXT,
 };
-use rfb_encodings::translate;
+use crate::rect_encoder::{self, EncodeContext, EncodeStreams};
+use crate::region::{DamageQueue, Region};
+use crate::resume::{SessionStore, SessionToken, SuspendedSession};
+use crate::security::{Authenticated, Security};
+use crate::server::{AuthMethod, HandshakePhase, Negotiation};
+use crate::stats::{ClientMemoryUsage, EncodeStats, UpdateTiming};
+use crate::tight;
+use crate::transport::ClientStream;
+
+/// Read half of a client connection.
+type ClientReader = tokio::io::ReadHalf<Box<dyn ClientStream>>;
+
+/// Write half of a client connection, shared with the server for shutdown.
+pub(crate) type ClientWriter = tokio::io::WriteHalf<Box<dyn ClientStream>>;
+
+/// Shuts down a client's write half, giving up after [`CLOSE_TIMEOUT`]; the connection
+/// is then closed when the stream is dropped.
+pub(crate) async fn close_writer(stream: &mut ClientWriter) {
+    let _ = tokio::time::timeout(CLOSE_TIMEOUT, stream.shutdown()).await;
+}
+
+/// Estimated memory held by one persistent zlib compression stream (deflate window, hash
+/// chains and output buffer).
+const ZLIB_STREAM_MEMORY: usize = 256 * 1024;
+
+/// Consecutive truncated updates that may favor regions near the pointer before one update
+/// drains strictly oldest-first. Bounds how long any queued region can wait.
+const MAX_PRIORITIZED_UPDATES: u32 = 3;
+
+/// How often the message loop checks whether an update is due.
+const UPDATE_CHECK_INTERVAL: Duration = Duration::from_millis(16);
+
+/// How long shutting down a connection may take. Over TLS or RSA-AES it sends a closing
+/// message, which a peer that stopped reading never lets through.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Lossy regions tracked for the automatic lossless refresh before they are merged into
+/// their bounding box.
+const MAX_LOSSY_REGIONS: usize = 32;
+
+/// JPEG quality for each VNC quality level 0-9 (standard VNC protocol mapping).
+const TIGHT2TURBO_QUAL: [u8; 10] = [15, 29, 41, 42, 62, 77, 79, 86, 92, 100];
+
+/// Zlib compression level for each VNC quality level 0-9, used by the lossless encodings
+/// when the client sets a quality level but no compression level. Lower quality asks for
+/// less bandwidth, so it gets stronger compression; higher quality favors low latency.
+const QUALITY_TO_ZLIB_LEVEL: [u8; 10] = [9, 9, 8, 7, 6, 6, 5, 4, 3, 2];
 
 /// Represents various events that a VNC client can send to the server.
 /// These events typically correspond to user interactions like keyboard input,
@@ -80,10 +149,97 @@ pub enum ClientEvent {
     /// A client-side clipboard (cut text) update.
     /// - `text`: The textual content from the client's clipboard.
     CutText { text: String },
+    /// The client completed the security handshake, successfully or not.
+    /// - `address`: The remote address of the client.
+    /// - `method`: The authentication method the client chose.
+    /// - `success`: Whether authentication succeeded.
+    AuthAttempt {
+        address: String,
+        method: AuthMethod,
+        success: bool,
+    },
+    /// A handshake phase exceeded its timeout and the connection is dropped.
+    /// - `address`: The remote address of the client.
+    /// - `phase`: The phase that timed out.
+    HandshakeTimeout {
+        address: String,
+        phase: HandshakePhase,
+    },
+    /// The client asked to change the framebuffer size (`SetDesktopSize`).
+    /// - `width`, `height`: The requested framebuffer size.
+    /// - `screens`: The requested screen layout.
+    /// - `reply`: Handle for accepting or rejecting the request.
+    ResizeRequest {
+        width: u16,
+        height: u16,
+        screens: Vec<Screen>,
+        reply: ResizeReply,
+    },
+    /// A framebuffer update was sent (only with `UpdateConfig::report_timing`).
+    /// - `timing`: The update's pipeline timing.
+    UpdateTiming { timing: UpdateTiming },
+    /// The client advertised a pseudo-encoding registered in `ExtensionConfig`.
+    /// - `encoding`: The pseudo-encoding number.
+    PseudoEncoding { encoding: i32 },
+    /// Initialization completed, or the client changed its pixel format or encodings
+    /// afterwards.
+    /// - `negotiation`: What was negotiated.
+    Negotiated { negotiation: Negotiation },
+    /// A gii device registration or input event.
+    /// - `event`: The registration or event.
+    ExtendedInput { event: GiiEvent },
     /// Notification that the client has disconnected.
     Disconnected,
 }
 
+/// Commands sent from the server to a client's message loop.
+///
+/// The message loop owns the client for the lifetime of the connection, so server-wide
+/// operations reach a client through this channel instead of locking it.
+pub enum ClientCommand {
+    /// Send clipboard text to the client as a `ServerCutText` message.
+    CutText(String),
+    /// Override the JPEG quality (0-100) requested by the client, or `None` to go back
+    /// to the client's own quality level.
+    SetJpegQuality(Option<u8>),
+    /// Override the compression level (0-9) requested by the client, or `None` to go back
+    /// to the client's own compression level.
+    SetCompression(Option<u8>),
+    /// Override the color reduction of `UpdateConfig::color_mode`, or `None` to go back
+    /// to the server's setting.
+    SetColorMode(Option<ColorMode>),
+    /// Cap the client's updates at this many per second, or `None` to go back to
+    /// `UpdateConfig::min_update_interval`.
+    SetMaxFps(Option<u32>),
+    /// Report the memory currently attributable to the client.
+    QueryMemory(oneshot::Sender<ClientMemoryUsage>),
+    /// Show another framebuffer (virtual desktop), replying whether the switch happened.
+    SwitchFramebuffer {
+        /// The framebuffer to show.
+        framebuffer: Framebuffer,
+        /// Receives `false` if the client cannot be resized to the new framebuffer.
+        reply: oneshot::Sender<bool>,
+    },
+    /// Pause the client's view (showing the configured placeholder) or resume it.
+    SetPaused(bool),
+    /// Tell the client the desktop was renamed.
+    SetDesktopName(String),
+    /// Tell the client the lock key LEDs changed.
+    SetLedState(LedState),
+    /// Send the pending damage right away, then close the connection.
+    Close {
+        /// Why the connection is closed, for the log.
+        reason: String,
+    },
+    /// Add an application-encoded rectangle to the client's next framebuffer update.
+    SendRect {
+        /// The rectangle header (position, size and encoding).
+        rect: Rectangle,
+        /// The encoded rectangle data, written verbatim after the header.
+        payload: Vec<u8>,
+    },
+}
+
 /// Manages persistent zlib compression streams for Tight encoding.
 ///
 /// Per RFC 6143 Tight encoding specification, uses 4 separate zlib streams
@@ -151,6 +307,14 @@ impl TightZlibStreams {
         self.streams[stream_id].as_mut().unwrap()
     }
 
+    /// Returns the number of streams that have been initialized.
+    fn initialized_streams(&self) -> usize {
+        self.streams
+            .iter()
+            .filter(|stream| stream.is_some())
+            .count()
+    }
+
     /// Compresses data using the specified stream with `Z_SYNC_FLUSH`.
     ///
     /// Uses `Z_SYNC_FLUSH` to maintain the dictionary state for subsequent compressions
@@ -203,6 +367,56 @@ impl TightStreamCompressor for TightZlibStreams {
     }
 }
 
+/// A framebuffer update being assembled from encoded rectangles.
+///
+/// The rectangles go into the body of a `FramebufferUpdate` message whose header is
+/// filled in when the message is sent, so an update can be split into several messages
+/// without knowing its rectangle count in advance.
+struct UpdateBatch {
+    /// The message being built: header placeholder followed by rectangles.
+    body: BytesMut,
+    /// Rectangles in `body`.
+    pending_rects: u16,
+    /// Size budget of one message in bytes.
+    max_bytes: usize,
+    /// Rectangles in the whole update, sent or pending.
+    total_rects: usize,
+    /// Bytes of the update written so far.
+    bytes_sent: usize,
+    /// Time spent writing the update so far.
+    send_time: Duration,
+    /// Messages written so far.
+    #[cfg_attr(not(feature = "debug-logging"), allow(dead_code))]
+    messages: usize,
+}
+
+impl UpdateBatch {
+    /// Creates an empty update whose messages stay within `max_bytes` where possible.
+    fn new(max_bytes: usize) -> Self {
+        let mut batch = Self {
+            body: BytesMut::new(),
+            pending_rects: 0,
+            max_bytes: max_bytes.max(1),
+            total_rects: 0,
+            bytes_sent: 0,
+            send_time: Duration::ZERO,
+            messages: 0,
+        };
+        batch.start_message();
+        batch
+    }
+
+    /// Clears the body and writes the header of a new message (rectangle count is set
+    /// when it is sent).
+    fn start_message(&mut self) {
+        self.body.clear();
+        self.body.put_u8(SERVER_MSG_FRAMEBUFFER_UPDATE);
+        self.body.put_u8(0); // padding
+        self.body.put_u16(0); // number of rectangles
+        self.pending_rects = 0;
+    }
+}
+
 /// Manages a single VNC client connection, handling communication, framebuffer updates,
 /// and client input events.
 ///
@@ -210,11 +424,12 @@ impl TightStreamCompressor for TightZlibStreams {
 /// It is responsible for sending framebuffer updates to the client based on dirty regions,
 /// processing incoming client messages (e.g., key events, pointer events, pixel format requests),
 /// and managing client-specific settings like preferred encodings and JPEG quality.
+#[allow(clippy::struct_excessive_bools)] // Independent pieces of protocol state, not a state machine
 pub struct VncClient {
-    /// The read half of the TCP stream for receiving client messages.
-    read_stream: tokio::net::tcp::OwnedReadHalf,
-    /// The write half of the TCP stream for sending updates to the client.
-    write_stream: Arc<tokio::sync::Mutex<tokio::net::tcp::OwnedWriteHalf>>,
+    /// The read half of the stream for receiving client messages.
+    read_stream: ClientReader,
+    /// The write half of the stream for sending updates to the client.
+    write_stream: Arc<tokio::sync::Mutex<ClientWriter>>,
     /// A reference to the framebuffer, used to retrieve pixel data for updates.
     framebuffer: Framebuffer,
     /// The pixel format requested by the client, protected by a `RwLock` for concurrent access.
@@ -225,6 +440,89 @@ pub struct VncClient {
     encodings: RwLock<Vec<i32>>, // Protected - written by message handler, read by encoder
     /// Sender for client events (e.g., key presses, pointer movements) to be processed by other parts of the server.
     event_tx: mpsc::UnboundedSender<ClientEvent>,
+    /// Sender half of the command channel, handed out to the server.
+    command_tx: mpsc::UnboundedSender<ClientCommand>,
+    /// Receiver for commands from the server, drained by the message loop.
+    command_rx: mpsc::UnboundedReceiver<ClientCommand>,
+    /// Server configuration captured when the client connected.
+    config: Arc<ServerConfig>,
+    /// Latest clipboard text held back by `min_inbound_interval` throttling.
+    pending_cut_text: Option<String>,
+    /// The `Instant` the last clipboard event was delivered (None if none yet).
+    last_cut_text_at: Option<Instant>,
+    /// Server-side JPEG quality override (0-100), taking precedence over the client's
+    /// quality pseudo-encodings.
+    jpeg_quality_override: Option<u8>,
+    /// Server-side compression level override (0-9), taking precedence over the client's
+    /// compression pseudo-encodings.
+    compression_override: Option<u8>,
+    /// Server-side color reduction override, taking precedence over
+    /// `UpdateConfig::color_mode`.
+    color_mode_override: Option<ColorMode>,
+    /// Server-side frame-rate cap, as the minimum time between updates, taking
+    /// precedence over `UpdateConfig::min_update_interval`.
+    update_interval_override: Option<Duration>,
+    /// Whether the client sent a compression level pseudo-encoding.
+    compression_requested: bool,
+    /// The protocol version the client answered with (e.g. `003.008`).
+    protocol_version: String,
+    /// The security type the client chose, if it was one the server offered.
+    auth_method: Option<AuthMethod>,
+    /// Whether initialization has completed and `Negotiated` was reported.
+    negotiated: bool,
+    /// The real framebuffer while the client is paused; `framebuffer` then holds the
+    /// placeholder.
+    paused_framebuffer: Option<Framebuffer>,
+    /// The screen size (the framebuffer's, after the transform) the client was last
+    /// brought up to date with, to notice when the framebuffer is resized.
+    framebuffer_size: (u16, u16),
+    /// A desktop name the client could not be told about yet because it hasn't
+    /// advertised the `DesktopName` pseudo-encoding.
+    pending_desktop_name: Option<String>,
+    /// The server's lock key LEDs, once the application has reported them.
+    led_state: Option<LedState>,
+    /// The application's pending answer to a `SetDesktopSize` request, if any.
+    pending_resize: Option<oneshot::Receiver<ResizeOutcome>>,
+    /// The `Instant` of the last forced full refresh (non-incremental request).
+    last_full_refresh: Option<Instant>,
+    /// Number of consecutive non-incremental requests received within
+    /// `min_full_refresh_interval` of the last full refresh.
+    rapid_full_refreshes: u32,
+    /// A full refresh held back by refresh storm protection.
+    pending_full_refresh: Option<DirtyRegion>,
+    /// Whether refresh storm protection is currently throttling this client.
+    full_refresh_throttled: bool,
+    /// The last pointer position reported by the client, used to prioritize nearby regions.
+    pointer_position: Option<(u16, u16)>,
+    /// Latency histograms that encode and send durations are recorded into.
+    encode_stats: Arc<EncodeStats>,
+    /// Dedicated encoder threads, or `None` to encode on this task.
+    encode_pool: Option<Arc<EncodePool>>,
+    /// Limits this client's concurrent jobs on `encode_pool`.
+    encode_permits: tokio::sync::Semaphore,
+    /// Capacity of the buffer the last framebuffer update was assembled in.
+    send_buffer_capacity: usize,
+    /// Application-encoded rectangles waiting for the next framebuffer update.
+    pending_rects: Vec<(Rectangle, Vec<u8>)>,
+    /// Regions last sent with a lossy encoding, for the automatic lossless refresh.
+    lossy_regions: Vec<DirtyRegion>,
+    /// Whether the next update is an automatic lossless refresh.
+    lossless_refresh: bool,
+    /// Suspended sessions, where this session is kept when the client disconnects.
+    sessions: Option<Arc<SessionStore>>,
+    /// The token this session is kept under, once the client has been sent one.
+    session_token: Option<SessionToken>,
+    /// Whether the session was resumed, so the next update request needs no full refresh.
+    resumed: bool,
+    /// Bounding boxes of what was sent since the previous and since the last update
+    /// request: updates a viewer that disconnects may not have received.
+    unacknowledged: [Option<DirtyRegion>; 2],
+    /// Normalizes key events before they are reported.
+    key_normalizer: KeyNormalizer,
+    /// Arrival time of the oldest damage in `modified_regions`, set by the framebuffer.
+    first_damage: Arc<std::sync::Mutex<Option<Instant>>>,
+    /// Notified by the framebuffer when damage arrives, waking an idle update timer.
+    damage_wakeup: Arc<Notify>,
     /// The `Instant` when the last framebuffer update was sent to this client, protected by a `RwLock`.
     /// Used for rate limiting and deferral logic.
     last_update_sent: RwLock<Instant>, // Protected - written by update sender, read by rate limiter
@@ -235,48 +533,66 @@ pub struct VncClient {
     /// The VNC quality level (0-9, or 255 for unset = use JPEG).
     /// Stored as an `AtomicU8` for atomic access from multiple contexts.
     quality_level: AtomicU8, // Atomic - VNC quality level (0-9, 255=unset)
-    /// A flag indicating whether the client has requested continuous framebuffer updates, stored as an `AtomicBool`.
+    /// Whether updates may be sent to the client, stored as an `AtomicBool`.
+    ///
+    /// Clients using the `ContinuousUpdates` extension get exactly one update per request
+    /// unless they enabled continuous updates; for other clients the first request
+    /// enables updates for the rest of the session.
     continuous_updates: AtomicBool, // Atomic - simple bool flag
-    /// A shared, locked vector of `DirtyRegion`s specific to this client.
-    /// These regions represent areas of the framebuffer that have been modified and need to be sent to the client.
-    modified_regions: Arc<RwLock<Vec<DirtyRegion>>>, // Per-client dirty regions (standard VNC protocol style - receives pushes from framebuffer)
+    /// The area continuous updates were enabled for through the `ContinuousUpdates`
+    /// extension, if they are.
+    continuous_region: Option<DirtyRegion>,
+    /// A shared, locked queue of the areas of the framebuffer that have been modified and
+    /// need to be sent to this client, oldest first.
+    modified_regions: Arc<RwLock<DamageQueue>>, // Per-client dirty regions (standard VNC protocol style - receives pushes from framebuffer)
     /// The region specifically requested by the client for an update, protected by a `RwLock`.
     /// It is written by the message handler and read by the encoder.
     requested_region: RwLock<Option<DirtyRegion>>, // Protected - written by message handler, read by encoder
     /// `CopyRect` tracking (standard VNC protocol style): destination regions to be copied
-    copy_region: Arc<RwLock<Vec<DirtyRegion>>>, // Destination regions for CopyRect
+    copy_region: Arc<RwLock<Region>>, // Destination regions for CopyRect
     /// Translation vector for `CopyRect`: (dx, dy) where src = dest + (dx, dy)
-    copy_offset: RwLock<Option<(i16, i16)>>, // (dx, dy) translation for copy operations
-    /// The duration to defer sending updates, matching `standard VNC protocol`'s default.
-    defer_update_time: Duration, // Constant - set once at init
+    copy_offset: Arc<RwLock<Option<(i16, i16)>>>, // (dx, dy) translation for copy operations
+    /// The duration to defer sending updates. Starts at `UpdateConfig::defer_time` and
+    /// follows the damage rate when `adaptive_defer` is enabled.
+    defer_update_time: Duration,
+    /// Smoothed time between sending an update and the arrival of the next damage; short
+    /// gaps mean constant (video-like) damage, long gaps sporadic changes.
+    damage_gap: Option<Duration>,
     /// The timestamp (in nanoseconds since creation) when deferring of updates began (0 if not deferring).
     /// Stored as an `AtomicU64` for atomic access.
     start_deferring_nanos: AtomicU64, // Atomic - nanos since creation (0 = not deferring)
     /// The `Instant` when this `VncClient` instance was created, used for calculating elapsed time.
     creation_time: Instant, // Constant - for calculating elapsed time
-    /// The maximum number of rectangles to send in a single framebuffer update message, matching `standard VNC protocol`'s default.
-    max_rects_per_update: usize, // Constant - set once at init
-    /// A mutex used to ensure exclusive access to the client's `TcpStream` for sending data,
+    /// Consecutive truncated updates that drained regions near the pointer first.
+    prioritized_updates: u32,
+    /// A mutex used to ensure exclusive access to the client's stream for sending data,
     /// preventing interleaved writes from concurrent tasks.
     send_mutex: Arc<tokio::sync::Mutex<()>>,
-    /// Persistent zlib compressor for Zlib encoding (RFC 6143: one stream per connection).
-    /// Protected by `RwLock` since encoding happens during `send_batched_update`.
-    zlib_compressor: RwLock<Option<Compress>>,
-    /// Persistent zlib compressor for `ZlibHex` encoding (RFC 6143: one stream per connection).
-    /// Protected by `RwLock` since encoding happens during `send_batched_update`.
-    zlibhex_compressor: RwLock<Option<Compress>>,
-    /// Persistent zlib compressor for ZRLE encoding (RFC 6143: one stream per connection).
-    /// Protected by `RwLock` since encoding happens during `send_batched_update`.
-    #[allow(dead_code)]
-    zrle_compressor: RwLock<Option<Compress>>,
-    /// ZYWRLE quality level (0 = disabled, 1-3 = quality levels, higher = better quality).
-    /// Stored as `AtomicU8` for atomic access. Updated based on client's quality setting.
-    zywrle_level: AtomicU8, // Atomic - updated when ZYWRLE encoding is detected
+    /// Persistent compression streams of the Zlib, `ZlibHex`, ZRLE and zstd encodings.
+    encode_streams: EncodeStreams,
+    /// Output buffer of the rectangle encoders, reused across rectangles.
+    encode_buffer: BytesMut,
+    /// The frame the update being built is encoded from, so its rectangles don't mix
+    /// pixels of two frames.
+    frame: Option<FrameSnapshot>,
     /// Persistent zlib compression streams for Tight encoding (4 streams with dictionaries).
     /// Protected by `RwLock` since encoding happens during `send_batched_update`.
     tight_zlib_streams: RwLock<TightZlibStreams>,
     /// Remote host address (IP:port) of the connected client
     remote_host: String,
+    /// Subject of the client's verified TLS certificate, if it presented one
+    certificate_subject: Option<String>,
+    /// Whether the client's input is dropped (see `ConnectionConfig::approver`)
+    view_only: bool,
+    /// Records clipboard transfers, if auditing is enabled
+    audit: Option<AuditLog>,
+    /// Origins of the input devices the client registered through gii
+    gii_devices: Vec<u32>,
+    /// Origin assigned to the last gii device
+    next_gii_origin: u32,
+    /// `UltraVNC` file transfer state (None when no shared directory is configured)
+    #[cfg(feature = "file-transfer")]
+    file_transfer: Option<FileTransfer>,
     /// Destination port for repeater connections (None for direct connections)
     destination_port: Option<u16>,
     /// Repeater ID for repeater connections (None for direct connections)
@@ -297,8 +613,9 @@ impl VncClient {
     /// * `stream` - The `TcpStream` representing the established connection to the VNC client.
     /// * `framebuffer` - The `Framebuffer` instance that this client will receive updates from.
     /// * `desktop_name` - The name of the desktop to be sent to the client during `ServerInit`.
-    /// * `password` - An optional password for VNC authentication. If `Some`, VNC authentication
-    ///   will be offered. (Note: Current implementation uses a placeholder for authentication).
+    /// * `authenticator` - Checks the client's credentials; also decides which security
+    ///   types are offered.
+    /// * `config` - The server configuration (clipboard limits, etc.) to apply to this client.
     /// * `event_tx` - An `mpsc::UnboundedSender` for sending `ClientEvent`s generated by the client
     ///   (e.g., key presses, pointer movements) to other parts of the server.
     ///
@@ -308,10 +625,43 @@ impl VncClient {
     /// `Err(std::io::Error)` if an I/O error occurs during communication or handshake.
     pub async fn new(
         client_id: usize,
-        mut stream: TcpStream,
+        stream: TcpStream,
+        framebuffer: Framebuffer,
+        desktop_name: String,
+        authenticator: Arc<dyn Authenticator>,
+        config: Arc<ServerConfig>,
+        event_tx: mpsc::UnboundedSender<ClientEvent>,
+    ) -> Result<Self, std::io::Error> {
+        Self::from_tcp(
+            client_id,
+            stream,
+            None,
+            framebuffer,
+            desktop_name,
+            authenticator,
+            config,
+            event_tx,
+        )
+        .await
+    }
+
+    /// Performs the VNC handshake over a TCP connection, possibly made through a
+    /// repeater.
+    ///
+    /// # Arguments
+    ///
+    /// * `repeater_id` - The repeater ID, for connections made through a repeater.
+    ///
+    /// The other arguments are those of [`new`](Self::new).
+    #[allow(clippy::too_many_arguments)] // The handshake needs the connection, its peer and the server state
+    pub(crate) async fn from_tcp(
+        client_id: usize,
+        stream: TcpStream,
+        repeater_id: Option<String>,
         framebuffer: Framebuffer,
         desktop_name: String,
-        password: Option<String>,
+        authenticator: Arc<dyn Authenticator>,
+        config: Arc<ServerConfig>,
         event_tx: mpsc::UnboundedSender<ClientEvent>,
     ) -> Result<Self, std::io::Error> {
         // Capture remote host address before handshake
@@ -322,62 +672,152 @@ impl VncClient {
         // Disable Nagle's algorithm for immediate frame delivery
         stream.set_nodelay(true)?;
 
-        // Send protocol version
-        stream.write_all(PROTOCOL_VERSION.as_bytes()).await?;
+        Self::handshake(
+            client_id,
+            stream,
+            remote_host,
+            false,
+            repeater_id,
+            framebuffer,
+            desktop_name,
+            authenticator,
+            config,
+            event_tx,
+        )
+        .await
+    }
+
+    /// Creates a new `VncClient` over an arbitrary transport, performing the VNC handshake.
+    ///
+    /// This is [`new`](Self::new) for streams other than TCP; see the
+    /// [`transport`](crate::transport) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The unique client ID assigned by the server.
+    /// * `stream` - The established connection to the VNC client.
+    /// * `remote_host` - A description of the peer, used in events and logs.
+    /// * `secure_transport` - Whether the stream is encrypted by its transport (exempt
+    ///   from `ConnectionConfig::require_encryption`).
+    /// * `framebuffer` - The `Framebuffer` instance that this client will receive updates from.
+    /// * `desktop_name` - The name of the desktop to be sent to the client during `ServerInit`.
+    /// * `authenticator` - Checks the client's credentials.
+    /// * `config` - The server configuration to apply to this client.
+    /// * `event_tx` - An `mpsc::UnboundedSender` for the `ClientEvent`s generated by the client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok(VncClient)` on successful handshake and initialization, or
+    /// `Err(std::io::Error)` if an I/O error occurs during communication or handshake.
+    #[allow(clippy::too_many_arguments)] // The handshake needs the connection, its peer and the server state
+    pub async fn from_stream<S: ClientStream>(
+        client_id: usize,
+        stream: S,
+        remote_host: String,
+        secure_transport: bool,
+        framebuffer: Framebuffer,
+        desktop_name: String,
+        authenticator: Arc<dyn Authenticator>,
+        config: Arc<ServerConfig>,
+        event_tx: mpsc::UnboundedSender<ClientEvent>,
+    ) -> Result<Self, std::io::Error> {
+        Self::handshake(
+            client_id,
+            stream,
+            remote_host,
+            secure_transport,
+            None,
+            framebuffer,
+            desktop_name,
+            authenticator,
+            config,
+            event_tx,
+        )
+        .await
+    }
 
-        // Read client protocol version
-        let mut version_buf = vec![0u8; 12];
-        stream.read_exact(&mut version_buf).await?;
+    /// Performs the VNC handshake: version exchange, security and initialization.
+    #[allow(clippy::too_many_lines)] // RFB handshake covers version, security and init phases
+    #[allow(clippy::too_many_arguments)] // The handshake needs the connection, its peer and the server state
+    async fn handshake<S: ClientStream>(
+        client_id: usize,
+        mut stream: S,
+        remote_host: String,
+        secure_transport: bool,
+        repeater_id: Option<String>,
+        framebuffer: Framebuffer,
+        desktop_name: String,
+        authenticator: Arc<dyn Authenticator>,
+        config: Arc<ServerConfig>,
+        event_tx: mpsc::UnboundedSender<ClientEvent>,
+    ) -> Result<Self, std::io::Error> {
+        let connection = &config.connection;
+        let version_buf = handshake_phase(
+            HandshakePhase::Version,
+            connection.version_timeout,
+            &remote_host,
+            &event_tx,
+            async {
+                // Send protocol version
+                stream.write_all(PROTOCOL_VERSION.as_bytes()).await?;
+
+                // Read client protocol version
+                let mut version_buf = vec![0u8; 12];
+                stream.read_exact(&mut version_buf).await?;
+                Ok(version_buf)
+            },
+        )
+        .await?;
         #[cfg(feature = "debug-logging")]
         info!("Client version: {}", String::from_utf8_lossy(&version_buf));
-
-        // Send security types
-        if password.is_some() {
-            stream.write_all(&[1, SECURITY_TYPE_VNC_AUTH]).await?;
-        } else {
-            stream.write_all(&[1, SECURITY_TYPE_NONE]).await?;
-        }
-
-        // Read client's security type choice
-        let mut sec_type = [0u8; 1];
-        stream.read_exact(&mut sec_type).await?;
-
-        // Handle authentication
-        if sec_type[0] == SECURITY_TYPE_VNC_AUTH {
-            let auth = VncAuth::new(password.clone());
-            let challenge = auth.generate_challenge();
-            stream.write_all(&challenge).await?;
-
-            let mut response = vec![0u8; 16];
-            stream.read_exact(&mut response).await?;
-
-            if auth.verify_response(&response, &challenge) {
-                let mut buf = BytesMut::with_capacity(4);
-                buf.put_u32(SECURITY_RESULT_OK);
-                stream.write_all(&buf).await?;
-            } else {
-                let mut buf = BytesMut::with_capacity(4);
-                buf.put_u32(SECURITY_RESULT_FAILED);
-                stream.write_all(&buf).await?;
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::PermissionDenied,
-                    "VNC authentication failed",
-                ));
-            }
-        } else if sec_type[0] == SECURITY_TYPE_NONE {
-            let mut buf = BytesMut::with_capacity(4);
-            buf.put_u32(SECURITY_RESULT_OK);
-            stream.write_all(&buf).await?;
-        }
+        let protocol_version = String::from_utf8_lossy(&version_buf)
+            .trim_start_matches("RFB ")
+            .trim_end()
+            .to_string();
+
+        let stream: Box<dyn ClientStream> = Box::new(stream);
+        let security = Security::new(
+            &remote_host,
+            repeater_id.as_deref(),
+            authenticator.as_ref(),
+            &config,
+            secure_transport,
+            &event_tx,
+        );
+        let Authenticated {
+            mut stream,
+            method,
+            certificate_subject,
+            view_only,
+        } = handshake_phase(
+            HandshakePhase::Security,
+            connection.security_timeout,
+            &remote_host,
+            &event_tx,
+            security.negotiate(stream),
+        )
+        .await?;
+        let auth_method = Some(method);
 
         // Read ClientInit
         let mut shared = [0u8; 1];
-        stream.read_exact(&mut shared).await?;
-
-        // Send ServerInit
+        handshake_phase(
+            HandshakePhase::ClientInit,
+            connection.client_init_timeout,
+            &remote_host,
+            &event_tx,
+            stream.read_exact(&mut shared),
+        )
+        .await?;
+
+        // Send ServerInit; clients see the framebuffer through the configured transform
+        let (screen_width, screen_height) = config
+            .updates
+            .transform
+            .size(framebuffer.width(), framebuffer.height());
         let server_init = ServerInit {
-            framebuffer_width: framebuffer.width(),
-            framebuffer_height: framebuffer.height(),
+            framebuffer_width: screen_width,
+            framebuffer_height: screen_height,
             pixel_format: PixelFormat::rgba32(),
             name: desktop_name,
         };
@@ -388,10 +828,25 @@ impl VncClient {
 
         log::info!("VNC client handshake completed");
 
-        // Split stream into read/write halves for lock-free shutdown
-        let (read_stream, write_stream) = stream.into_split();
+        // Split stream into read/write halves so the server can shut down the write half
+        let (read_stream, write_stream) = tokio::io::split(stream);
 
         let creation_time = Instant::now();
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+
+        // Prime the client with the whole framebuffer so its first update request is
+        // answered with complete content, even an incremental one and even if the
+        // application hasn't updated the framebuffer since the client connected
+        let initial_region = DirtyRegion::new(0, 0, screen_width, screen_height);
+
+        let key_normalizer = KeyNormalizer::new(
+            config.input.keypad_as_main,
+            config.input.fold_dead_keys,
+            config.input.layout.clone(),
+        );
+        #[cfg(feature = "file-transfer")]
+        let file_transfer = FileTransfer::new(&config.file_transfer);
+        let defer_update_time = config.updates.defer_time;
 
         Ok(Self {
             read_stream,
@@ -400,32 +855,109 @@ impl VncClient {
             pixel_format: RwLock::new(PixelFormat::rgba32()),
             encodings: RwLock::new(vec![ENCODING_RAW]),
             event_tx,
+            command_tx,
+            command_rx,
+            config,
+            pending_cut_text: None,
+            last_cut_text_at: None,
+            jpeg_quality_override: None,
+            color_mode_override: None,
+            update_interval_override: None,
+            compression_override: None,
+            compression_requested: false,
+            protocol_version,
+            auth_method,
+            negotiated: false,
+            paused_framebuffer: None,
+            framebuffer_size: (initial_region.width, initial_region.height),
+            pending_desktop_name: None,
+            led_state: None,
+            pending_resize: None,
+            last_full_refresh: None,
+            rapid_full_refreshes: 0,
+            pending_full_refresh: None,
+            full_refresh_throttled: false,
+            pointer_position: None,
+            encode_stats: Arc::default(),
+            encode_pool: None,
+            encode_permits: tokio::sync::Semaphore::new(1),
+            send_buffer_capacity: 0,
+            pending_rects: Vec::new(),
+            lossy_regions: Vec::new(),
+            lossless_refresh: false,
+            sessions: None,
+            session_token: None,
+            resumed: false,
+            unacknowledged: [None; 2],
+            key_normalizer,
+            first_damage: Arc::default(),
+            damage_wakeup: Arc::default(),
             last_update_sent: RwLock::new(creation_time),
             jpeg_quality: AtomicU8::new(80),     // Default quality
             compression_level: AtomicU8::new(6), // Default zlib compression (balanced)
             quality_level: AtomicU8::new(255),   // 255 = unset (use JPEG by default)
             continuous_updates: AtomicBool::new(false),
-            modified_regions: Arc::new(RwLock::new(Vec::new())),
+            continuous_region: None,
+            modified_regions: Arc::new(RwLock::new(DamageQueue::from(initial_region))),
             requested_region: RwLock::new(None),
-            copy_region: Arc::new(RwLock::new(Vec::new())), // Initialize empty copy region
-            copy_offset: RwLock::new(None),                 // No copy offset initially
-            defer_update_time: Duration::from_millis(5),    // Match standard VNC protocol default
-            start_deferring_nanos: AtomicU64::new(0),       // 0 = not deferring
+            copy_region: Arc::new(RwLock::new(Region::new())), // Initialize empty copy region
+            copy_offset: Arc::new(RwLock::new(None)),          // No copy offset initially
+            defer_update_time,
+            damage_gap: None,
+            start_deferring_nanos: AtomicU64::new(0), // 0 = not deferring
             creation_time,
-            max_rects_per_update: 50, // Match standard VNC protocol default
+            prioritized_updates: 0,
             send_mutex: Arc::new(tokio::sync::Mutex::new(())),
-            zlib_compressor: RwLock::new(None), // Initialized lazily when first used
-            zlibhex_compressor: RwLock::new(None), // Initialized lazily when first used
-            zrle_compressor: RwLock::new(None), // Initialized lazily when first used
-            zywrle_level: AtomicU8::new(0), // Disabled by default, updated when ZYWRLE is requested
+            encode_streams: EncodeStreams::default(), // Streams are created when first used
+            encode_buffer: BytesMut::new(),
+            frame: None,
             tight_zlib_streams: RwLock::new(TightZlibStreams::new()), // 4 persistent streams for Tight encoding
             remote_host,
-            destination_port: None, // None for direct inbound connections
-            repeater_id: None,      // None for direct inbound connections
+            certificate_subject,
+            view_only,
+            audit: None,
+            gii_devices: Vec::new(),
+            next_gii_origin: 0,
+            #[cfg(feature = "file-transfer")]
+            file_transfer,
+            destination_port: None, // Set by `set_connection_metadata` for reverse connections
+            repeater_id,
             client_id,
         })
     }
 
+    /// Returns a receiver that pushes framebuffer damage into this client's
+    /// `modified_regions` and copies into its `copy_region`, and records when they arrived.
+    pub(crate) fn damage_receiver(&self) -> DirtyRegionReceiver {
+        DirtyRegionReceiver::new(Arc::downgrade(&self.modified_regions))
+            .with_damage_time(Arc::downgrade(&self.first_damage))
+            .with_wakeup(Arc::downgrade(&self.damage_wakeup))
+            .with_copy_regions(
+                Arc::downgrade(&self.copy_region),
+                Arc::downgrade(&self.copy_offset),
+            )
+            .with_transform(self.config.updates.transform)
+    }
+
+    /// Returns the size of the screen as the client sees it: the framebuffer's, rotated
+    /// by the configured transform.
+    fn screen_size(&self) -> (u16, u16) {
+        self.config
+            .updates
+            .transform
+            .size(self.framebuffer.width(), self.framebuffer.height())
+    }
+
+    /// Maps a region of the client's screen to the framebuffer.
+    fn framebuffer_region(&self, region: DirtyRegion) -> DirtyRegion {
+        let (width, height) = self.screen_size();
+        self.config
+            .updates
+            .transform
+            .inverse()
+            .map_region(region, width, height)
+    }
+
     /// Returns a clone of the `Arc` containing the client's `modified_regions`.
     ///
     /// This handle is used to register the client with the `Framebuffer` to receive
@@ -433,8 +965,8 @@ impl VncClient {
     ///
     /// # Returns
     ///
-    /// An `Arc<RwLock<Vec<DirtyRegion>>>` that can be used as a handle for the client's dirty regions.
-    pub fn get_receiver_handle(&self) -> Arc<RwLock<Vec<DirtyRegion>>> {
+    /// An `Arc<RwLock<DamageQueue>>` that can be used as a handle for the client's dirty regions.
+    pub fn get_receiver_handle(&self) -> Arc<RwLock<DamageQueue>> {
         self.modified_regions.clone()
     }
 
@@ -444,9 +976,9 @@ impl VncClient {
     ///
     /// # Returns
     ///
-    /// An `Arc<RwLock<Vec<DirtyRegion>>>` that can be used as a handle for the client's copy regions.
+    /// An `Arc<RwLock<Region>>` that can be used as a handle for the client's copy regions.
     #[allow(dead_code)]
-    pub fn get_copy_region_handle(&self) -> Arc<RwLock<Vec<DirtyRegion>>> {
+    pub fn get_copy_region_handle(&self) -> Arc<RwLock<Region>> {
         self.copy_region.clone()
     }
 
@@ -454,37 +986,28 @@ impl VncClient {
     ///
     /// This method adds a region to be sent using `CopyRect` encoding with the specified offset.
     /// According to standard VNC protocol's algorithm, if a copy operation with a different offset
-    /// already exists, the old copy region is treated as modified.
+    /// already exists, the old copy region is treated as modified, and so are the parts of
+    /// `region` whose source hasn't been sent yet.
     ///
     /// # Arguments
     ///
-    /// * `region` - The destination region to be copied.
+    /// * `region` - The destination region to be copied, in framebuffer coordinates.
     /// * `dx` - The X offset from destination to source (`src_x` = `dest_x` + dx).
     /// * `dy` - The Y offset from destination to source (`src_y` = `dest_y` + dy).
     pub async fn schedule_copy_region(&self, region: DirtyRegion, dx: i16, dy: i16) {
-        let mut copy_regions = self.copy_region.write().await;
-        let mut copy_offset = self.copy_offset.write().await;
-        let mut modified_regions = self.modified_regions.write().await;
-
-        // Check if we have an existing copy with a different offset
-        if let Some((existing_dx, existing_dy)) = *copy_offset {
-            if existing_dx != dx || existing_dy != dy {
-                // Different offset - treat existing copy region as modified
-                // This matches standard VNC protocol's behavior in rfbScheduleCopyRegion
-                modified_regions.extend(copy_regions.drain(..));
-                copy_regions.clear();
-            }
-        }
-
-        // Add the new region to copy_region
-        copy_regions.push(region);
-        *copy_offset = Some((dx, dy));
+        let (width, height) = (self.framebuffer.width(), self.framebuffer.height());
+        let Some(region) = region.clamp_to(width, height) else {
+            return;
+        };
+        self.damage_receiver()
+            .add_framebuffer_copy(region, (dx, dy), width, height)
+            .await;
     }
 
     /// Enters the main message loop for the `VncClient`, handling incoming data from the client
     /// and periodically sending framebuffer updates.
     ///
-    /// This function continuously reads from the client's `TcpStream` and processes VNC messages
+    /// This function continuously reads from the client's stream and processes VNC messages
     /// such as `SetPixelFormat`, `SetEncodings`, `FramebufferUpdateRequest`, `KeyEvent`,
     /// `PointerEvent`, and `ClientCutText`. It also uses a `tokio::time::interval` to
     /// periodically check if batched framebuffer updates should be sent to the client,
@@ -494,22 +1017,36 @@ impl VncClient {
     ///
     /// `Ok(())` if the client disconnects gracefully.
     /// Returns `Err(std::io::Error)` if an I/O error occurs or an invalid message is received.
+    pub async fn handle_messages(&mut self) -> Result<(), std::io::Error> {
+        let result = self.message_loop().await;
+        // Errors (including stalled writes) end the session just like a clean disconnect;
+        // the server stops forwarding events at the first `Disconnected`.
+        if result.is_err() {
+            let _ = self.event_tx.send(ClientEvent::Disconnected);
+        }
+        self.suspend_session().await;
+        result
+    }
+
+    /// The body of [`handle_messages`](Self::handle_messages).
     #[allow(clippy::too_many_lines)] // VNC protocol message handler requires complete state machine
     #[allow(clippy::cast_possible_truncation)] // VNC protocol message fields use u8/u16/u32 as specified in RFC 6143
     #[allow(clippy::cast_sign_loss)] // VNC pseudo-encoding values are negative i32, converted to positive u8/u16 offsets
-    pub async fn handle_messages(&mut self) -> Result<(), std::io::Error> {
-        // Use standard VNC quality mapping (TigerVNC compatible)
-        const TIGHT2TURBO_QUAL: [u8; 10] = [15, 29, 41, 42, 62, 77, 79, 86, 92, 100];
-        // Limit clipboard size to prevent memory exhaustion attacks
-        const MAX_CUT_TEXT: usize = 10 * 1024 * 1024; // 10MB limit
-
+    async fn message_loop(&mut self) -> Result<(), std::io::Error> {
         let mut buf = BytesMut::with_capacity(4096);
-        let mut check_interval = tokio::time::interval(tokio::time::Duration::from_millis(16)); // Check for updates ~60 times/sec
+        // Bytes of an oversized ClientCutText payload still to be skipped
+        let mut discard_remaining: usize = 0;
+        let mut check_interval = tokio::time::interval(UPDATE_CHECK_INTERVAL); // Check for updates ~60 times/sec
+                                                                               // While idle the timer is parked until damage arrives (or a keepalive is due)
+        let damage_wakeup = self.damage_wakeup.clone();
+        let mut parked = false;
+        let mut last_busy = Instant::now();
 
         loop {
             tokio::select! {
                 // Handle incoming client messages
                 result = self.read_stream.read_buf(&mut buf) => {
+                    parked = false;
                     if result? == 0 {
                         let _ = self.event_tx.send(ClientEvent::Disconnected);
                         return Ok(());
@@ -517,6 +1054,12 @@ impl VncClient {
 
                     // Process all available messages in the buffer
                     while !buf.is_empty() {
+                        if discard_remaining > 0 {
+                            let skip = discard_remaining.min(buf.len());
+                            buf.advance(skip);
+                            discard_remaining -= skip;
+                            continue;
+                        }
 
                         let msg_type = buf[0];
 
@@ -563,6 +1106,9 @@ impl VncClient {
                                         requested_format.is_compatible_with_rgba32()
                                     );
                                 }
+                                if self.negotiated {
+                                    self.report_negotiation().await;
+                                }
                             }
                             CLIENT_MSG_SET_ENCODINGS => {
                                 if buf.len() < 4 { // 1 + 1 padding + 2 count
@@ -596,13 +1142,66 @@ impl VncClient {
                                         let compression_level = (encoding - ENCODING_COMPRESS_LEVEL_0) as u8;
                                         // Use compression level directly (0=fastest, 9=best compression)
                                         self.compression_level.store(compression_level, Ordering::Relaxed);
+                                        self.compression_requested = true;
                                         #[cfg(feature = "debug-logging")]
                                         info!("Client requested compression level {compression_level}, using zlib level {compression_level}");
                                     }
                                 }
+                                // Report registered vendor pseudo-encodings the client newly advertises
+                                let wants_session_token = {
+                                    let previous = self.encodings.read().await;
+                                    for &encoding in &encodings_list {
+                                        if self.config.extensions.pseudo_encodings.contains(&encoding)
+                                            && !previous.contains(&encoding)
+                                        {
+                                            let _ = self.event_tx.send(ClientEvent::PseudoEncoding { encoding });
+                                        }
+                                    }
+                                    encodings_list.contains(&ENCODING_SESSION_RESUME)
+                                        && !previous.contains(&ENCODING_SESSION_RESUME)
+                                };
+                                if wants_session_token {
+                                    self.send_session_token();
+                                }
+                                let announce_led_state = [ENCODING_QEMU_LED_STATE, ENCODING_VMWARE_LED_STATE]
+                                    .iter()
+                                    .any(|encoding| encodings_list.contains(encoding))
+                                    && !self.supports_led_state().await;
+                                let announce_gii = encodings_list.contains(&ENCODING_GII)
+                                    && !self.encodings.read().await.contains(&ENCODING_GII);
+                                let announce_continuous_updates = encodings_list
+                                    .contains(&ENCODING_CONTINUOUS_UPDATES)
+                                    && !self.encodings.read().await.contains(&ENCODING_CONTINUOUS_UPDATES);
+                                let announce_extended_desktop_size = encodings_list
+                                    .contains(&ENCODING_EXTENDED_DESKTOP_SIZE)
+                                    && !self.encodings.read().await.contains(&ENCODING_EXTENDED_DESKTOP_SIZE);
                                 self.encodings.write().await.clone_from(&encodings_list);
                                 #[cfg(feature = "debug-logging")]
                                 info!("Client set {count} encodings: {encodings_list:?}");
+                                // The client may only send SetDesktopSize once it has been
+                                // sent the screen layout
+                                if announce_extended_desktop_size {
+                                    let (width, height) = self.framebuffer_size;
+                                    self.send_desktop_size(width, height, DESKTOP_SIZE_REASON_SERVER, ResizeStatus::Success).await?;
+                                }
+                                if let Some(name) = self.pending_desktop_name.take() {
+                                    self.set_desktop_name(name).await?;
+                                }
+                                if announce_led_state {
+                                    self.send_led_state().await?;
+                                }
+                                if announce_gii {
+                                    // Maximum and minimum supported version
+                                    let [high, low] = gii::VERSION.to_be_bytes();
+                                    self.send_gii(gii::SUBTYPE_VERSION, &[high, low, high, low]).await?;
+                                }
+                                // An EndOfContinuousUpdates tells the client the extension is supported
+                                if announce_continuous_updates {
+                                    self.send_end_of_continuous_updates().await?;
+                                }
+                                if self.negotiated {
+                                    self.report_negotiation().await;
+                                }
                             }
                             CLIENT_MSG_FRAMEBUFFER_UPDATE_REQUEST => {
                                 if buf.len() < 10 { // 1 + 1 incremental + 8 (x, y, w, h)
@@ -615,25 +1214,50 @@ impl VncClient {
                                 let width = buf.get_u16();
                                 let height = buf.get_u16();
 
+                                // The first update request ends initialization: by now the
+                                // client has sent its pixel format and encodings
+                                if !self.negotiated {
+                                    self.negotiated = true;
+                                    self.report_negotiation().await;
+                                }
+
                                 #[cfg(feature = "debug-logging")]
                                 info!("FramebufferUpdateRequest: incremental={incremental}, region=({x},{y} {width}x{height})");
 
+                                // Only the part inside the framebuffer can be sent; a request with
+                                // nothing inside it asks for nothing (as in the standard VNC protocol)
+                                let (screen_width, screen_height) = self.screen_size();
+                                let Some(requested) = DirtyRegion::new(x, y, width, height)
+                                    .clamp_to(screen_width, screen_height)
+                                else {
+                                    #[cfg(feature = "debug-logging")]
+                                    info!("Ignoring FramebufferUpdateRequest outside the framebuffer");
+                                    continue;
+                                };
+
+                                // While continuous updates are enabled, incremental requests are
+                                // ignored and a non-incremental one adds its area to the continuous one
+                                if self.continuous_region.is_some() && incremental {
+                                    continue;
+                                }
+                                let area = self.continuous_region.map_or(requested, |area| area.merge(&requested));
+
                                 // Track requested region (standard VNC protocol cl->requestedRegion)
-                                *self.requested_region.write().await = Some(DirtyRegion::new(x, y, width, height));
+                                *self.requested_region.write().await = Some(area);
 
                                 // Enable continuous updates for both incremental and non-incremental requests
                                 // The difference is handled below: non-incremental clears and adds full region
                                 self.continuous_updates.store(true, Ordering::Relaxed);
 
-                                // Handle non-incremental updates (full refresh)
-                                if !incremental {
-                                    // Clear existing regions and mark full requested region as dirty
-                                    let full_region = DirtyRegion::new(x, y, width, height);
-                                    let mut regions = self.modified_regions.write().await;
-                                    regions.clear();
-                                    regions.push(full_region);
-                                    #[cfg(feature = "debug-logging")]
-                                    info!("Non-incremental update: added full region to dirty list");
+                                // The viewer has processed an update, so only what was sent
+                                // since the previous request may still be in flight
+                                self.unacknowledged = [self.unacknowledged[1], None];
+
+                                // Handle non-incremental updates (full refresh); a resumed
+                                // viewer kept its screen and only needs what changed
+                                let resumed = std::mem::take(&mut self.resumed);
+                                if !incremental && !resumed {
+                                    self.request_full_refresh(requested).await;
                                 }
 
                                 // Start deferring if we have regions to send
@@ -642,14 +1266,74 @@ impl VncClient {
                                 // when the queue is already empty (harmless). Using a write lock here
                                 // would hurt performance on this hot path.
                                 {
+                                    let copies_pending = !self.copy_region.read().await.is_empty();
                                     let regions = self.modified_regions.read().await;
-                                    if !regions.is_empty() && self.start_deferring_nanos.load(Ordering::Relaxed) == 0 {
+                                    if (!regions.is_empty() || copies_pending) && self.start_deferring_nanos.load(Ordering::Relaxed) == 0 {
                                         // Not currently deferring, start now
                                         let nanos = Instant::now().duration_since(self.creation_time).as_nanos() as u64;
                                         self.start_deferring_nanos.store(nanos, Ordering::Relaxed);
                                     }
                                 }
                             }
+                            CLIENT_MSG_ENABLE_CONTINUOUS_UPDATES => {
+                                if buf.len() < 10 { // 1 + 1 enable + 8 (x, y, w, h)
+                                    break;
+                                }
+                                buf.advance(1); // message type
+                                let enable = buf.get_u8() != 0;
+                                let x = buf.get_u16();
+                                let y = buf.get_u16();
+                                let width = buf.get_u16();
+                                let height = buf.get_u16();
+
+                                #[cfg(feature = "debug-logging")]
+                                info!("EnableContinuousUpdates: enable={enable}, region=({x},{y} {width}x{height})");
+
+                                if enable {
+                                    let (screen_width, screen_height) = self.screen_size();
+                                    let Some(area) = DirtyRegion::new(x, y, width, height)
+                                        .clamp_to(screen_width, screen_height)
+                                    else {
+                                        continue;
+                                    };
+                                    self.continuous_region = Some(area);
+                                    *self.requested_region.write().await = Some(area);
+                                    self.continuous_updates.store(true, Ordering::Relaxed);
+                                } else {
+                                    // Back to one update per request, confirmed even if
+                                    // continuous updates were not enabled
+                                    self.continuous_region = None;
+                                    self.continuous_updates.store(false, Ordering::Relaxed);
+                                    self.send_end_of_continuous_updates().await?;
+                                }
+                            }
+                            #[cfg(feature = "file-transfer")]
+                            MSG_FILE_TRANSFER => {
+                                let Some((message, len)) = file_transfer::Message::parse(&buf)? else {
+                                    break;
+                                };
+                                buf.advance(len);
+
+                                let outcome = match &mut self.file_transfer {
+                                    Some(transfer) => transfer.handle(message, !self.view_only).await,
+                                    None => file_transfer::refuse(&message),
+                                };
+                                self.send_file_transfer(outcome).await?;
+                            }
+                            MSG_GII => {
+                                if buf.len() < 4 { // 1 + 1 endian and subtype + 2 length
+                                    break;
+                                }
+                                let big_endian = buf[1] & gii::BIG_ENDIAN_FLAG != 0;
+                                let len = gii::read_u16([buf[2], buf[3]], big_endian) as usize;
+                                if buf.len() < 4 + len {
+                                    break; // Need more data
+                                }
+                                let subtype = buf[1] & !gii::BIG_ENDIAN_FLAG;
+                                buf.advance(4);
+                                let body = buf.split_to(len);
+                                self.handle_gii(subtype, &body, big_endian).await?;
+                            }
                             CLIENT_MSG_KEY_EVENT => {
                                 if buf.len() < 8 { // 1 + 1 down + 2 padding + 4 key
                                     break;
@@ -659,7 +1343,15 @@ impl VncClient {
                                 buf.advance(2); // padding
                                 let key = buf.get_u32();
 
-                                let _ = self.event_tx.send(ClientEvent::KeyPress { down, key });
+                                // Paused clients can't see the screen and view-only clients mustn't act on it
+                                if self.accepts_input() {
+                                    for event in self.key_normalizer.process(KeyEvent { down, keysym: key }) {
+                                        let _ = self.event_tx.send(ClientEvent::KeyPress {
+                                            down: event.down,
+                                            key: event.keysym,
+                                        });
+                                    }
+                                }
                             }
                             CLIENT_MSG_POINTER_EVENT => {
                                 if buf.len() < 6 { // 1 + 1 button + 2 x + 2 y
@@ -669,38 +1361,147 @@ impl VncClient {
                                 let button_mask = buf.get_u8();
                                 let x = buf.get_u16();
                                 let y = buf.get_u16();
-
-                                let _ = self.event_tx.send(ClientEvent::PointerMove {
-                                    x,
-                                    y,
-                                    button_mask,
-                                });
+                                self.pointer_position = Some((x, y));
+
+                                if self.accepts_input() {
+                                    let transform = self.config.updates.transform;
+                                    let (x, y) = if transform == Transform::Identity {
+                                        (x, y)
+                                    } else {
+                                        let (width, height) = self.screen_size();
+                                        transform.inverse().map_point(x, y, width, height)
+                                    };
+                                    let _ = self.event_tx.send(ClientEvent::PointerMove {
+                                        x,
+                                        y,
+                                        button_mask,
+                                    });
+                                }
                             }
                             CLIENT_MSG_CLIENT_CUT_TEXT => {
                                 if buf.len() < 8 { // 1 + 3 padding + 4 length
                                     break;
                                 }
-                                buf.advance(1); // message type
-                                buf.advance(3); // padding
-                                let length = buf.get_u32() as usize;
+                                let length = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+                                let clipboard = &self.config.clipboard;
 
-                                if length > MAX_CUT_TEXT {
-                                    error!("Cut text too large: {length} bytes (max {MAX_CUT_TEXT}), disconnecting client");
-                                    let _ = self.event_tx.send(ClientEvent::Disconnected);
-                                    return Err(std::io::Error::new(
-                                        std::io::ErrorKind::InvalidData,
-                                        "Cut text too large"
-                                    ));
+                                // Number of payload bytes to keep; the rest is discarded
+                                let keep = if !clipboard.accept_inbound {
+                                    0
+                                } else if length > clipboard.max_inbound_bytes {
+                                    match clipboard.oversize_policy {
+                                        ClipboardOversizePolicy::Disconnect => {
+                                            error!("Cut text too large: {length} bytes (max {}), disconnecting client", clipboard.max_inbound_bytes);
+                                            let _ = self.event_tx.send(ClientEvent::Disconnected);
+                                            return Err(std::io::Error::new(
+                                                std::io::ErrorKind::InvalidData,
+                                                "Cut text too large"
+                                            ));
+                                        }
+                                        ClipboardOversizePolicy::Truncate => {
+                                            log::warn!("Cut text too large: {length} bytes (max {}), truncating", clipboard.max_inbound_bytes);
+                                            clipboard.max_inbound_bytes
+                                        }
+                                        ClipboardOversizePolicy::Drop => {
+                                            log::warn!("Cut text too large: {length} bytes (max {}), dropping", clipboard.max_inbound_bytes);
+                                            0
+                                        }
+                                    }
+                                } else {
+                                    length
+                                };
+
+                                if buf.len() < 8 + keep {
+                                    break; // Need more data
                                 }
+                                buf.advance(8); // message type, padding, length
+                                let text_bytes = buf.split_to(keep);
+                                discard_remaining = length - keep;
 
-                                if buf.len() < length {
+                                if keep == 0 && length > 0 {
+                                    continue; // Dropped
+                                }
+                                let text = match std::str::from_utf8(&text_bytes) {
+                                    Ok(text) => Some(text),
+                                    // Truncation may have split a multi-byte character
+                                    Err(e) if keep < length && e.error_len().is_none() => {
+                                        std::str::from_utf8(&text_bytes[..e.valid_up_to()]).ok()
+                                    }
+                                    Err(_) => None,
+                                };
+                                if let Some(text) = text {
+                                    self.receive_cut_text(text.to_string());
+                                }
+                            }
+                            CLIENT_MSG_SET_DESKTOP_SIZE => {
+                                if buf.len() < 8 { // 1 + 1 padding + 2 width + 2 height + 1 screens + 1 padding
+                                    break;
+                                }
+                                let screen_count = buf[6] as usize;
+                                if buf.len() < 8 + screen_count * 16 {
                                     break; // Need more data
                                 }
-                                let text_bytes = buf.split_to(length);
-                                if let Ok(text) = String::from_utf8(text_bytes.to_vec()) {
-                                    let _ = self.event_tx.send(ClientEvent::CutText { text });
+                                buf.advance(2); // message type, padding
+                                let width = buf.get_u16();
+                                let height = buf.get_u16();
+                                buf.advance(2); // number of screens, padding
+                                let screens: Vec<Screen> = (0..screen_count)
+                                    .map(|_| Screen {
+                                        id: buf.get_u32(),
+                                        x: buf.get_u16(),
+                                        y: buf.get_u16(),
+                                        width: buf.get_u16(),
+                                        height: buf.get_u16(),
+                                        flags: buf.get_u32(),
+                                    })
+                                    .collect();
+
+                                #[cfg(feature = "debug-logging")]
+                                info!("SetDesktopSize: {width}x{height} with {screen_count} screen(s)");
+
+                                if !valid_screen_layout(width, height, &screens) {
+                                    let (width, height) = self.framebuffer_size;
+                                    self.send_desktop_size(width, height, DESKTOP_SIZE_REASON_CLIENT, ResizeStatus::InvalidLayout).await?;
+                                    continue;
+                                }
+
+                                // The application sees the request in framebuffer coordinates
+                                let transform = self.config.updates.transform.inverse();
+                                let screens = screens
+                                    .into_iter()
+                                    .map(|screen| {
+                                        let area = transform.map_region(
+                                            DirtyRegion::new(screen.x, screen.y, screen.width, screen.height),
+                                            width,
+                                            height,
+                                        );
+                                        Screen { x: area.x, y: area.y, width: area.width, height: area.height, ..screen }
+                                    })
+                                    .collect();
+                                let (width, height) = transform.size(width, height);
+
+                                // The application decides; its answer arrives on `pending_resize`
+                                let (reply, outcome_rx) = ResizeReply::new();
+                                self.pending_resize = Some(outcome_rx);
+                                // Dropping the reply of a view-only client prohibits the resize
+                                if !self.view_only {
+                                    let _ = self.event_tx.send(ClientEvent::ResizeRequest {
+                                        width,
+                                        height,
+                                        screens,
+                                        reply,
+                                    });
                                 }
                             }
+                            CLIENT_MSG_RESUME_SESSION => {
+                                if buf.len() < 20 { // 1 + 3 padding + 16 token
+                                    break;
+                                }
+                                buf.advance(4); // message type, padding
+                                let mut token = SessionToken::default();
+                                buf.copy_to_slice(&mut token);
+                                self.resume_session(token).await;
+                            }
                             _ => {
                                 error!("Unknown message type: {msg_type}, disconnecting client");
                                 let _ = self.event_tx.send(ClientEvent::Disconnected);
@@ -711,17 +1512,114 @@ impl VncClient {
                             }
                         }
                     }
+
+                    self.enforce_memory_cap(&mut buf).await?;
+                }
+
+                // Handle the application's answer to a SetDesktopSize request
+                outcome = wait_for_resize_outcome(self.pending_resize.as_mut()), if self.pending_resize.is_some() => {
+                    parked = false;
+                    self.pending_resize = None;
+                    self.apply_resize_outcome(outcome).await?;
+                }
+
+                // Handle commands from the server
+                Some(command) = self.command_rx.recv() => {
+                    parked = false;
+                    match command {
+                        ClientCommand::CutText(text) => self.send_cut_text(&text).await?,
+                        ClientCommand::SetJpegQuality(quality) => {
+                            self.jpeg_quality_override = quality.map(|q| q.min(100));
+                        }
+                        ClientCommand::SetCompression(level) => {
+                            self.compression_override = level.map(|l| l.min(9));
+                        }
+                        ClientCommand::SetColorMode(mode) => {
+                            self.set_color_mode(mode).await;
+                        }
+                        ClientCommand::SetMaxFps(fps) => {
+                            self.update_interval_override =
+                                fps.map(|fps| Duration::from_secs(1) / fps.max(1));
+                        }
+                        ClientCommand::QueryMemory(reply) => {
+                            let _ = reply.send(self.memory_usage(&buf).await);
+                        }
+                        ClientCommand::SwitchFramebuffer { framebuffer, reply } => {
+                            let switched = self.show_framebuffer(framebuffer).await?;
+                            let _ = reply.send(switched);
+                        }
+                        ClientCommand::SetPaused(paused) => {
+                            self.set_paused(paused).await?;
+                        }
+                        ClientCommand::SetDesktopName(name) => {
+                            self.set_desktop_name(name).await?;
+                        }
+                        ClientCommand::SetLedState(state) => {
+                            self.led_state = Some(state);
+                            self.send_led_state().await?;
+                        }
+                        ClientCommand::SendRect { rect, payload } => {
+                            self.pending_rects.push((rect, payload));
+                        }
+                        ClientCommand::Close { reason } => {
+                            // Flush what the client is still owed, ignoring the deferral
+                            if self.continuous_updates.load(Ordering::Relaxed) {
+                                self.send_batched_update().await?;
+                            }
+                            close_writer(&mut *self.write_stream.lock().await).await;
+                            log::info!("Closing client {}: {reason}", self.client_id);
+                            let _ = self.event_tx.send(ClientEvent::Disconnected);
+                            return Ok(());
+                        }
+                    }
+                }
+
+                // Wake the update timer of an idle client
+                () = wait_for_damage(&damage_wakeup, self.config.connection.keepalive_interval), if parked => {
+                    parked = false;
+                    check_interval.reset_immediately();
                 }
 
                 // Periodically check if we should send updates (standard VNC protocol style)
-                _ = check_interval.tick() => {
+                _ = check_interval.tick(), if !parked => {
+                    self.flush_pending_cut_text();
+                    self.enforce_memory_cap(&mut buf).await?;
+
+                    #[cfg(feature = "file-transfer")]
+                    self.send_file_packets().await?;
+
                     let continuous = self.continuous_updates.load(Ordering::Relaxed);
                     if continuous {
+                        // Perform a full refresh held back by storm protection once it's due
+                        if let Some(region) = self.pending_full_refresh {
+                            let interval = self.config.updates.min_full_refresh_interval;
+                            if self.last_full_refresh.is_none_or(|last| last.elapsed() >= interval) {
+                                self.pending_full_refresh = None;
+                                self.apply_full_refresh(region).await;
+                            }
+                        }
+
+                        // Pull damage from a FrameSource-backed framebuffer (no-op otherwise)
+                        self.framebuffer.poll_source().await;
+
+                        // Resend lossy regions losslessly once the screen has been still
+                        self.schedule_lossless_refresh().await;
+
+                        if !self.follow_resize().await? {
+                            close_writer(&mut *self.write_stream.lock().await).await;
+                            let _ = self.event_tx.send(ClientEvent::Disconnected);
+                            return Ok(());
+                        }
+
                         // Check if we have regions and deferral time has elapsed
                         // Regions are already pushed to us by framebuffer (no merge needed!)
                         let should_send = {
+                            // Copies are locked before damage, as the framebuffer does
+                            let copies_pending = !self.copy_region.read().await.is_empty();
                             let regions = self.modified_regions.read().await;
-                            if regions.is_empty() {
+                            if !self.pending_rects.is_empty() {
+                                true // Application-encoded rectangles go out with the next update
+                            } else if regions.is_empty() && !copies_pending {
                                 false
                             } else {
                                 let defer_nanos = self.start_deferring_nanos.load(Ordering::Relaxed);
@@ -737,17 +1635,29 @@ impl VncClient {
                                     let elapsed = now.duration_since(defer_start);
                                     let last_sent = *self.last_update_sent.read().await;
                                     let time_since_last = now.duration_since(last_sent);
-                                    let min_interval = Duration::from_millis(33); // ~30 FPS max
+                                    // Send when the interval ends closer to this check than to the
+                                    // next one, so the rate averages out to the cap
+                                    let due = time_since_last + UPDATE_CHECK_INTERVAL / 2
+                                        >= self.min_update_interval();
 
-                                    elapsed >= self.defer_update_time && time_since_last >= min_interval
+                                    elapsed >= self.defer_update_time && due
                                 }
                             }
                         };
 
                         if should_send {
                             self.send_batched_update().await?;
+                        } else {
+                            self.send_keepalive_if_idle().await?;
                         }
                     }
+
+                    let idle_timeout = self.config.updates.idle_timeout;
+                    if !self.is_idle().await {
+                        last_busy = Instant::now();
+                    } else if !idle_timeout.is_zero() && last_busy.elapsed() >= idle_timeout {
+                        parked = true;
+                    }
                 }
             }
         }
@@ -767,6 +1677,7 @@ impl VncClient {
     /// `Err(std::io::Error)` if an I/O error occurs during encoding or sending.
     #[allow(clippy::too_many_lines)] // VNC framebuffer update encoding requires handling all encoding types
     #[allow(clippy::cast_possible_truncation)] // VNC protocol rectangle headers use u16 dimensions
+    #[cfg_attr(not(feature = "debug-logging"), allow(unused_assignments))] // Statistics are only read by debug logging
     async fn send_batched_update(&mut self) -> Result<(), std::io::Error> {
         // Get requested region (standard VNC protocol: requestedRegion)
         let requested = *self.requested_region.read().await;
@@ -774,6 +1685,18 @@ impl VncClient {
         #[cfg(feature = "debug-logging")]
         info!("send_batched_update called, requested region: {requested:?}");
 
+        // Clients that can't do CopyRect get copies (e.g. detected scrolls) as damage
+        if !self.encodings.read().await.contains(&ENCODING_COPYRECT) {
+            let copies = std::mem::take(&mut *self.copy_region.write().await);
+            if !copies.is_empty() {
+                *self.copy_offset.write().await = None;
+                let receiver = DirtyRegionReceiver::new(Arc::downgrade(&self.modified_regions));
+                for &region in copies.rects() {
+                    receiver.add_dirty_region(region).await;
+                }
+            }
+        }
+
         // STEP 1: Get copy regions to send (standard VNC protocol: copyRegion sent FIRST)
         let (copy_regions_to_send, copy_src_offset): (Vec<DirtyRegion>, Option<(i16, i16)>) = {
             let mut copy_regions = self.copy_region.write().await;
@@ -783,23 +1706,28 @@ impl VncClient {
                 (Vec::new(), None)
             } else {
                 let offset = *copy_offset;
-                let regions: Vec<DirtyRegion> = if let Some(req) = requested {
-                    // Filter and drain: only take regions that intersect with requested region
-                    // This preserves non-intersecting regions for later updates
-                    let mut result = Vec::new();
-                    copy_regions.retain(|region| {
-                        if let Some(intersection) = region.intersect(&req) {
-                            result.push(intersection);
-                            false // Remove from copy_regions (drained)
-                        } else {
-                            true // Keep in copy_regions for later
-                        }
-                    });
-                    result
+                let taken = if let Some(req) = requested {
+                    // Only take the part inside the requested region; the rest stays
+                    // pending for later updates
+                    let req = Region::from(req);
+                    let taken = copy_regions.intersect(&req);
+                    *copy_regions = copy_regions.subtract(&req);
+                    taken
                 } else {
-                    copy_regions.drain(..).collect()
+                    std::mem::take(&mut *copy_regions)
                 };
 
+                // Rectangles are copied one after another, so each one must be read
+                // before another is written over its source: start from the side the
+                // copy moves towards (standard VNC protocol's reverse region iterator)
+                let (dx, dy) = offset.unwrap_or_default();
+                let mut regions = taken.rects().to_vec();
+                regions.sort_by_key(|region| {
+                    let x = i32::from(region.x);
+                    let y = i32::from(region.y);
+                    (if dy < 0 { -y } else { y }, if dx < 0 { -x } else { x })
+                });
+
                 // If we drained all regions, clear the offset
                 if copy_regions.is_empty() {
                     *copy_offset = None;
@@ -810,6 +1738,7 @@ impl VncClient {
         };
 
         // STEP 2: Get modified regions to send (standard VNC protocol: modifiedRegion sent AFTER copyRegion)
+        let mut damaged_at = None;
         let modified_regions_to_send: Vec<DirtyRegion> = {
             let mut regions = self.modified_regions.write().await;
 
@@ -818,37 +1747,83 @@ impl VncClient {
             } else {
                 // Calculate how many regions we can send
                 let remaining_slots = self
+                    .config
+                    .updates
                     .max_rects_per_update
+                    .max(1)
                     .saturating_sub(copy_regions_to_send.len());
-                let num_rects = regions.len().min(remaining_slots);
-
-                if let Some(req) = requested {
-                    // Filter and drain: only take regions that intersect with requested region
-                    // This preserves non-intersecting regions for later updates
-                    let mut result = Vec::new();
-                    let mut drained_count = 0;
-
-                    regions.retain(|region| {
-                        if drained_count >= num_rects {
-                            true // Keep remaining regions (hit limit)
-                        } else if let Some(intersection) = region.intersect(&req) {
-                            result.push(intersection);
-                            drained_count += 1;
-                            false // Remove from regions (drained)
-                        } else {
-                            true // Keep in regions for later (doesn't intersect)
-                        }
-                    });
-                    result
+                let ages = regions.ages();
+
+                // The queue keeps the order of first damage. When not everything fits,
+                // send what the user is interacting with first, but every few updates drain
+                // strictly oldest-first so regions away from the pointer can't starve
+                let order = if ages.len() > remaining_slots
+                    && self.prioritized_updates < MAX_PRIORITIZED_UPDATES
+                {
+                    self.prioritized_updates += 1;
+                    prioritized_order(
+                        ages,
+                        self.pointer_position,
+                        self.config.updates.pointer_priority_radius,
+                    )
                 } else {
-                    // No requested region set, drain up to num_rects
-                    regions.drain(..num_rects).collect()
+                    self.prioritized_updates = 0;
+                    (0..ages.len()).collect()
+                };
+
+                // Only take damage inside the requested region (if any); the rest stays
+                // queued, in its original order, for later updates. Each age box goes out
+                // as its exact rectangles, or as one rectangle when they don't fit.
+                let requested = requested.map(Region::from);
+                let mut taken = Vec::new();
+                let mut sent = Region::new();
+                for index in order {
+                    let slots = remaining_slots.saturating_sub(taken.len());
+                    if slots == 0 {
+                        break;
+                    }
+                    let mut area = Region::from(ages[index]).intersect(regions.region());
+                    if let Some(requested) = &requested {
+                        area = area.intersect(requested);
+                    }
+                    if area.rects().len() > slots {
+                        area = area.bounds().map(Region::from).unwrap_or_default();
+                    }
+                    taken.extend_from_slice(area.rects());
+                    sent = sent.union(&area);
+                }
+                regions.subtract(&sent);
+
+                // Damage left behind may be as old as what was taken, so the arrival
+                // time is only cleared once everything has been taken
+                if !taken.is_empty() {
+                    if let Ok(mut first_damage) = self.first_damage.lock() {
+                        damaged_at = if regions.is_empty() {
+                            first_damage.take()
+                        } else {
+                            *first_damage
+                        };
+                    }
                 }
+                taken
             }
         };
 
+        // STEP 3: Take application-encoded rectangles (sent after all regions)
+        let custom_rects = std::mem::take(&mut self.pending_rects);
+
+        if self.session_token.is_some() {
+            for region in copy_regions_to_send.iter().chain(&modified_regions_to_send) {
+                let sent = &mut self.unacknowledged[1];
+                *sent = Some(sent.map_or(*region, |r| r.merge(region)));
+            }
+        }
+
         // If no regions to send at all, nothing to do
-        if copy_regions_to_send.is_empty() && modified_regions_to_send.is_empty() {
+        if copy_regions_to_send.is_empty()
+            && modified_regions_to_send.is_empty()
+            && custom_rects.is_empty()
+        {
             #[cfg(feature = "debug-logging")]
             info!(
                 "No regions to send (copy={}, modified={})",
@@ -858,37 +1833,45 @@ impl VncClient {
             return Ok(());
         }
 
-        #[cfg_attr(not(feature = "debug-logging"), allow(unused_variables))]
         let start = Instant::now();
 
-        // Calculate total rectangles including CoRRE tiles
-        // For CoRRE encoding, large rectangles are split into 255x255 tiles
-        let mut total_rects = copy_regions_to_send.len();
+        // Encode the whole update from one frame. Damage the application adds meanwhile
+        // is queued for the next update, so nothing written after the snapshot is lost
+        self.frame = self.framebuffer.snapshot().await;
 
-        // Determine preferred encoding from client's list
+        // Determine preferred encoding from client's list (or the server's override)
         // Select the first encoding that the server supports, skipping COPYRECT
-        let encodings = self.encodings.read().await;
-        let preferred_encoding = encodings
-            .iter()
-            .find(|&&enc| {
-                // Skip COPYRECT - it's only for copy operations, not general encoding
-                if enc == ENCODING_COPYRECT {
-                    return false;
+        let preferred_encoding = Self::select_encoding(
+            &self.encodings.read().await,
+            &self.config.updates.encoding_order,
+        );
+
+        // Regions can go out in another of the client's encodings that suits their content
+        let mut region_encodings = self
+            .region_encodings(&modified_regions_to_send, preferred_encoding)
+            .await;
+
+        // A lossless refresh sends ZYWRLE regions in a lossless encoding, and Tight
+        // regions without JPEG; in lossless-only mode every update does
+        let lossless =
+            std::mem::take(&mut self.lossless_refresh) || self.config.updates.lossless_only;
+        if lossless {
+            let encodings = self.encodings.read().await;
+            let replacement = match Self::select_encoding_for_content(
+                &encodings,
+                ENCODING_ZYWRLE,
+                ContentClass::Text,
+                false,
+            ) {
+                ENCODING_ZYWRLE => ENCODING_RAW,
+                encoding => encoding,
+            };
+            for encoding in &mut region_encodings {
+                if *encoding == ENCODING_ZYWRLE {
+                    *encoding = replacement;
                 }
-                // Check if this encoding is supported
-                // Either it has explicit handling in client.rs or get_encoder returns Some
-                matches!(
-                    enc,
-                    ENCODING_ZLIB
-                        | ENCODING_ZLIBHEX
-                        | ENCODING_ZRLE
-                        | ENCODING_ZYWRLE
-                        | ENCODING_TIGHT
-                ) || encoding::get_encoder(enc).is_some()
-            })
-            .copied()
-            .unwrap_or(ENCODING_RAW);
-        drop(encodings);
+            }
+        }
 
         #[cfg(feature = "debug-logging")]
         info!("DEBUG: preferred_encoding = {preferred_encoding}");
@@ -907,7 +1890,7 @@ impl VncClient {
 
         // For TIGHT encoding, pre-encode regions to determine rectangle count
         let mut tight_encoded_regions = Vec::new();
-        if preferred_encoding == ENCODING_TIGHT {
+        if region_encodings.contains(&ENCODING_TIGHT) {
             #[cfg(feature = "debug-logging")]
             info!(
                 "DEBUG: Entering TIGHT pre-encoding block, {} regions",
@@ -930,16 +1913,75 @@ impl VncClient {
             #[cfg(feature = "debug-logging")]
             info!("DEBUG: Acquired tight_zlib_streams lock");
 
-            for region in &modified_regions_to_send {
+            // With other clients watching, regions are encoded so they can be shared (see
+            // tight::FreshStreams); a lone client keeps the better compression of its
+            // persistent streams
+            let cache = self.framebuffer.encode_cache();
+            let cache_generation = self
+                .frame
+                .as_ref()
+                .map_or_else(|| cache.generation(), FrameSnapshot::generation);
+            let share = self.config.encoding.shared_cache_bytes > 0
+                && self.framebuffer.receiver_count().await > 1;
+
+            let tight_regions = modified_regions_to_send
+                .iter()
+                .zip(&region_encodings)
+                .filter(|&(_, &encoding)| encoding == ENCODING_TIGHT);
+            for (region, _) in tight_regions {
                 #[cfg(feature = "debug-logging")]
                 info!(
                     "DEBUG: Processing region {}x{} at ({}, {})",
                     region.width, region.height, region.x, region.y
                 );
 
+                let encode_start = Instant::now();
+                let quality_level = if lossless {
+                    255
+                } else {
+                    self.effective_quality_level()
+                };
+                let compression_level = self.effective_compression_level();
+                let (jpeg_quality, mut jpeg_config) =
+                    (self.effective_jpeg_quality(), self.config.jpeg);
+                // Grayscale rectangles have no chroma to encode
+                if matches!(self.color_mode(), ColorMode::Grayscale { .. }) {
+                    jpeg_config.subsampling = ChromaSubsampling::Gray;
+                }
+
+                // Another client with the same settings may already have encoded this
+                // region. The quality level follows from the JPEG quality when JPEG is
+                // used, and neither matters otherwise.
+                let cache_key = share.then(|| {
+                    let quality = if quality_level < 10 {
+                        jpeg_quality
+                    } else {
+                        255
+                    };
+                    CacheKey::new(
+                        self.framebuffer_region(*region),
+                        ENCODING_TIGHT,
+                        &client_format_clone,
+                        self.color_mode(),
+                        self.config.updates.transform,
+                        (quality, compression_level),
+                    )
+                });
+                let claim = match &cache_key {
+                    Some(key) => match cache.lookup(key, cache_generation).await {
+                        Lookup::Hit(data) => {
+                            tight_encoded_regions.push((region, tight::unpack_rects(data)));
+                            // The client resets its streams for these rectangles
+                            *tight_streams = TightZlibStreams::new();
+                            continue;
+                        }
+                        Lookup::Miss(claim) => claim,
+                    },
+                    None => None,
+                };
+
                 let pixel_data = match self
-                    .framebuffer
-                    .get_rect(region.x, region.y, region.width, region.height)
+                    .read_pixels(region.x, region.y, region.width, region.height)
                     .await
                 {
                     Ok(data) => {
@@ -962,15 +2004,62 @@ impl VncClient {
                     region.width, region.height, client_format_clone.bits_per_pixel
                 );
 
-                let sub_rects = encoding::tight::encode_tight_rects(
-                    &pixel_data,
-                    region.width,
-                    region.height,
-                    self.quality_level.load(Ordering::Relaxed),
-                    self.compression_level.load(Ordering::Relaxed),
-                    &client_format_clone,
-                    &mut *tight_streams,
-                );
+                // The streams move to the encoder thread with the job and come back with it
+                let mut streams = std::mem::replace(&mut *tight_streams, TightZlibStreams::new());
+                let format = client_format_clone.clone();
+                let (width, height) = (region.width, region.height);
+                let shared = cache_key.is_some();
+                let (sub_rects, streams) = self
+                    .run_encode(move || {
+                        let jpeg = (jpeg_quality, jpeg_config);
+                        let sub_rects = if shared {
+                            let mut rects = tight::encode_region(
+                                &pixel_data,
+                                width,
+                                height,
+                                quality_level,
+                                compression_level,
+                                jpeg,
+                                &format,
+                                &mut tight::FreshStreams,
+                            );
+                            for (.., payload) in &mut rects {
+                                tight::reset_streams(payload);
+                            }
+                            rects
+                        } else {
+                            tight::encode_region(
+                                &pixel_data,
+                                width,
+                                height,
+                                quality_level,
+                                compression_level,
+                                jpeg,
+                                &format,
+                                &mut streams,
+                            )
+                        };
+                        let sub_rects: Vec<_> = sub_rects
+                            .into_iter()
+                            .map(|(x, y, w, h, payload)| (x, y, w, h, payload.freeze()))
+                            .collect();
+                        (sub_rects, streams)
+                    })
+                    .await;
+                *tight_streams = streams;
+                if let Some(key) = cache_key {
+                    cache.insert(
+                        key,
+                        tight::pack_rects(&sub_rects),
+                        cache_generation,
+                        self.config.encoding.shared_cache_bytes,
+                    );
+                    // The client resets its streams for these rectangles
+                    *tight_streams = TightZlibStreams::new();
+                }
+                // Other clients waiting for this region can take it from the cache now
+                drop(claim);
+                self.encode_stats.encode.record(encode_start.elapsed());
 
                 #[cfg(feature = "debug-logging")]
                 info!(
@@ -986,38 +2075,19 @@ impl VncClient {
                     sub_rects.len()
                 );
 
-                total_rects += sub_rects.len();
                 tight_encoded_regions.push((region, sub_rects));
             }
             drop(tight_streams);
 
             #[cfg(feature = "debug-logging")]
-            info!("DEBUG: TIGHT pre-encoding complete, total_rects={total_rects}");
-        } else {
-            // Count rectangles for modified regions (accounting for CoRRE tiling)
-            for region in &modified_regions_to_send {
-                if preferred_encoding == ENCODING_CORRE
-                    && (region.width > 255 || region.height > 255)
-                {
-                    // Count how many tiles this region will be split into
-                    let num_tiles_x = region.width.div_ceil(255) as usize;
-                    let num_tiles_y = region.height.div_ceil(255) as usize;
-                    total_rects += num_tiles_x * num_tiles_y;
-                } else {
-                    total_rects += 1;
-                }
-            }
+            info!(
+                "DEBUG: TIGHT pre-encoding complete, {} regions",
+                tight_encoded_regions.len()
+            );
         }
 
-        let mut response = BytesMut::new();
-
-        // Message type
-        response.put_u8(SERVER_MSG_FRAMEBUFFER_UPDATE);
-        response.put_u8(0); // padding
-        response.put_u16(total_rects as u16); // number of rectangles
-
-        #[cfg(feature = "debug-logging")]
-        info!("Writing framebuffer update header: total_rects={total_rects}");
+        // Rectangles are sent in as many FramebufferUpdate messages as the size budget needs
+        let mut batch = UpdateBatch::new(self.config.updates.max_message_bytes);
 
         #[cfg_attr(
             not(feature = "debug-logging"),
@@ -1028,8 +2098,10 @@ impl VncClient {
             ENCODING_TIGHTPNG => "TIGHTPNG",
             ENCODING_ZYWRLE => "ZYWRLE",
             ENCODING_ZRLE => "ZRLE",
+            ENCODING_TRLE => "TRLE",
             ENCODING_ZLIBHEX => "ZLIBHEX",
             ENCODING_ZLIB => "ZLIB",
+            ENCODING_ZSTD => "ZSTD",
             ENCODING_HEXTILE => "HEXTILE",
             ENCODING_RRE => "RRE",
             ENCODING_CORRE => "CORRE",
@@ -1048,9 +2120,8 @@ impl VncClient {
         let mut copy_rect_count = 0;
 
         // Load quality/compression settings atomically
-        let jpeg_quality = self.jpeg_quality.load(Ordering::Relaxed);
-        let compression_level = self.compression_level.load(Ordering::Relaxed);
-        let _quality_level = self.quality_level.load(Ordering::Relaxed);
+        let jpeg_quality = self.effective_jpeg_quality();
+        let compression_level = self.effective_compression_level();
 
         // STEP 1: Send copy regions FIRST (standard VNC protocol style)
         if let Some((dx, dy)) = copy_src_offset {
@@ -1072,11 +2143,12 @@ impl VncClient {
                     height: region.height,
                     encoding: ENCODING_COPYRECT,
                 };
-                rect.write_header(&mut response);
-
                 // CopyRect data is just src_x and src_y
-                response.put_u16(src_x);
-                response.put_u16(src_y);
+                let mut src = [0u8; 4];
+                src[..2].copy_from_slice(&src_x.to_be_bytes());
+                src[2..].copy_from_slice(&src_y.to_be_bytes());
+                self.queue_rect(&mut batch, &rect, &[&src]).await?;
+                self.record_copy(*region, dx, dy);
 
                 total_pixels += u64::from(region.width) * u64::from(region.height);
                 copy_rect_count += 1;
@@ -1089,9 +2161,7 @@ impl VncClient {
         info!("DEBUG: Starting STEP 2 - Send modified regions");
 
         // Handle TIGHT encoding separately (already pre-encoded)
-        if preferred_encoding == ENCODING_TIGHT {
-            use crate::protocol::UPDATE_BUF_SIZE;
-
+        if !tight_encoded_regions.is_empty() {
             #[cfg(feature = "debug-logging")]
             info!(
                 "DEBUG: In TIGHT output section, tight_encoded_regions.len()={}",
@@ -1111,24 +2181,6 @@ impl VncClient {
                 );
 
                 for (rel_x, rel_y, w, h, encoded) in sub_rects {
-                    // Calculate size of this rectangle (header + data)
-                    let rect_size = 12 + encoded.len(); // 12 bytes header + encoded data
-
-                    // Check if adding this rectangle would exceed buffer limit
-                    if response.len() + rect_size > UPDATE_BUF_SIZE {
-                        #[cfg(feature = "debug-logging")]
-                        info!("DEBUG: Buffer limit reached ({} bytes), flushing to continue streaming", response.len());
-
-                        // Send current buffer chunk
-                        let mut send_mutex = self.write_stream.lock().await;
-                        send_mutex.write_all(&response).await?;
-                        drop(send_mutex);
-
-                        // Clear buffer and continue streaming rectangles
-                        // Header was already sent in first flush, subsequent flushes are just raw rectangle data
-                        response.clear();
-                    }
-
                     // Sub-rectangle coordinates are relative to region origin
                     // Convert to absolute screen coordinates
                     let rect = Rectangle {
@@ -1140,11 +2192,17 @@ impl VncClient {
                     };
 
                     #[cfg(feature = "debug-logging")]
-                    info!("RECT #{}: {}x{} at ({},{}), TIGHT data={} bytes, response_size_before={}, response_size_after={}",
-                        rect_count, w, h, region.x + rel_x, region.y + rel_y, encoded.len(), response.len(), response.len() + rect_size);
+                    info!(
+                        "RECT #{}: {}x{} at ({},{}), TIGHT data={} bytes",
+                        rect_count,
+                        w,
+                        h,
+                        region.x + rel_x,
+                        region.y + rel_y,
+                        encoded.len()
+                    );
 
-                    rect.write_header(&mut response);
-                    response.extend_from_slice(encoded);
+                    self.queue_rect(&mut batch, &rect, &[encoded]).await?;
 
                     total_pixels += u64::from(*w) * u64::from(*h);
 
@@ -1153,113 +2211,84 @@ impl VncClient {
                         rect_count += 1;
                     }
                 }
+
+                let jpeg_rects = sub_rects
+                    .iter()
+                    .filter(|(.., encoded)| tight::is_jpeg(encoded))
+                    .map(|&(rel_x, rel_y, w, h, _)| {
+                        DirtyRegion::new(region.x + rel_x, region.y + rel_y, w, h)
+                    });
+                self.record_sent(**region, jpeg_rects);
             }
 
             #[cfg(feature = "debug-logging")]
-            info!(
-                "DEBUG: TIGHT output complete, wrote {} rectangle headers, response.len()={}",
-                rect_count,
-                response.len()
-            );
-        } else {
-            // Handle other encodings
-            for region in &modified_regions_to_send {
-                // For CoRRE encoding: split large rectangles into 255x255 tiles
-                // (CoRRE uses u8 coordinates, so dimensions must be ≤255)
-                if preferred_encoding == ENCODING_CORRE
-                    && (region.width > 255 || region.height > 255)
-                {
-                    #[cfg(feature = "debug-logging")]
-                    info!(
-                        "CoRRE: Splitting {}x{} region into 255x255 tiles",
-                        region.width, region.height
-                    );
-                    // Split rectangle into tiles ≤255x255 per RFC 6143 CoRRE specification
-                    let mut y = 0;
-                    while y < region.height {
-                        let tile_height = std::cmp::min(255, region.height - y);
-                        let mut x = 0;
-                        while x < region.width {
-                            let tile_width = std::cmp::min(255, region.width - x);
-                            #[cfg(feature = "debug-logging")]
-                            info!(
-                                "CoRRE: Encoding tile at ({},{}) size {}x{}",
-                                region.x + x,
-                                region.y + y,
-                                tile_width,
-                                tile_height
-                            );
-
-                            // Get pixel data for this tile
-                            let tile_pixel_data = match self
-                                .framebuffer
-                                .get_rect(region.x + x, region.y + y, tile_width, tile_height)
-                                .await
-                            {
-                                Ok(data) => data,
-                                Err(e) => {
-                                    error!(
-                                        "Failed to get rectangle ({}, {}, {}, {}): {}",
-                                        region.x + x,
-                                        region.y + y,
-                                        tile_width,
-                                        tile_height,
-                                        e
-                                    );
-                                    x += tile_width;
-                                    continue;
-                                }
-                            };
-
-                            // Encode this tile with CoRRE
-                            if let Some(encoder) = encoding::get_encoder(ENCODING_CORRE) {
-                                let encoded = encoder.encode(
-                                    &tile_pixel_data,
-                                    tile_width,
-                                    tile_height,
-                                    jpeg_quality,
-                                    compression_level,
-                                );
-
-                                // Calculate nSubrects from encoded buffer size
-                                // Encoder returns: bgColor(4) + subrects, each subrect is 8 bytes
-                                let n_subrects = if encoded.len() >= 4 {
-                                    (encoded.len() - 4) / 8
-                                } else {
-                                    0
-                                };
-
-                                // Write rectangle header for this tile
-                                let rect = Rectangle {
-                                    x: region.x + x,
-                                    y: region.y + y,
-                                    width: tile_width,
-                                    height: tile_height,
-                                    encoding: ENCODING_CORRE,
-                                };
-                                rect.write_header(&mut response);
-
-                                // Write RRE header (nSubrects in big-endian) - protocol layer responsibility
-                                // CoRRE uses same header structure as RRE per RFC 6143
-                                response.put_u32(n_subrects as u32);
-
-                                // Write encoder output (background color + subrectangle data)
-                                response.extend_from_slice(&encoded);
-
-                                total_pixels += u64::from(tile_width) * u64::from(tile_height);
-                            }
+            info!("DEBUG: TIGHT output complete, wrote {rect_count} rectangle headers");
+        }
 
-                            x += tile_width;
-                        }
-                        y += tile_height;
+        // Handle other encodings, each region in the one selected for its content, in
+        // bands and in tiles if the encoding limits the rectangle size
+        let other_regions: Vec<(DirtyRegion, i32)> = modified_regions_to_send
+            .iter()
+            .zip(&region_encodings)
+            .filter(|&(_, &encoding)| encoding != ENCODING_TIGHT)
+            .flat_map(|(region, &encoding)| {
+                let encoder = rect_encoder::for_encoding(encoding).unwrap_or(rect_encoder::RAW);
+                rect_encoder::split(*region, encoder).map(move |piece| (piece, encoding))
+            })
+            .collect();
+        for (region, preferred_encoding) in other_regions {
+            // Regions in an encoding that isn't encoded here are sent Raw
+            let (encoding, encoder) =
+                if let Some(encoder) = rect_encoder::for_encoding(preferred_encoding) {
+                    (preferred_encoding, encoder)
+                } else {
+                    error!("Encoding {preferred_encoding} not available, falling back to RAW");
+                    #[cfg(feature = "debug-logging")]
+                    {
+                        encoding_name = "RAW"; // Update encoding name to reflect fallback
                     }
-                    continue; // Skip normal encoding path for this region
-                }
-
-                // Get pixel data
-                let pixel_data = match self
-                    .framebuffer
-                    .get_rect(region.x, region.y, region.width, region.height)
+                    (ENCODING_RAW, rect_encoder::RAW)
+                };
+            let mut context = EncodeContext {
+                format: self.pixel_format.read().await.clone(),
+                quality_level: self.effective_quality_level(),
+                jpeg_quality,
+                compression_level,
+                streams: EncodeStreams::default(),
+            };
+
+            // Another client with the same settings may already have encoded this rectangle
+            let encode_start = Instant::now();
+            let cache = self.framebuffer.encode_cache();
+            let cache_generation = self
+                .frame
+                .as_ref()
+                .map_or_else(|| cache.generation(), FrameSnapshot::generation);
+            let cache_key = (self.config.encoding.shared_cache_bytes > 0).then(|| {
+                CacheKey::new(
+                    self.framebuffer_region(region),
+                    encoding,
+                    &context.format,
+                    self.color_mode(),
+                    self.config.updates.transform,
+                    encoder.settings(&context),
+                )
+            });
+            let (cached, claim) = match &cache_key {
+                Some(key) => match cache.lookup(key, cache_generation).await {
+                    Lookup::Hit(data) => (Some(data), None),
+                    Lookup::Miss(claim) => (None, claim),
+                },
+                None => (None, None),
+            };
+            let cache_hit = cached.is_some();
+
+            // Get pixel data, unless only the compression is left to do
+            let pixel_data = if cache_hit {
+                RectPixels::default()
+            } else {
+                match self
+                    .read_pixels(region.x, region.y, region.width, region.height)
                     .await
                 {
                     Ok(data) => data,
@@ -1270,479 +2299,1761 @@ impl VncClient {
                         );
                         continue; // Skip this invalid rectangle
                     }
-                };
-
-                // Apply pixel format translation and encode
-                // Translation happens before encoding per RFC 6143
-                let client_pixel_format = self.pixel_format.read().await;
-                let server_format = PixelFormat::rgba32();
-
-                let (actual_encoding, encoded) = if preferred_encoding == ENCODING_RAW {
-                    // For Raw encoding: translation IS the encoding (like standard VNC protocol)
-                    // Just translate and send directly, no additional processing
-                    let translated = if client_pixel_format.is_compatible_with_rgba32() {
-                        // Fast path: no translation, but still need to strip alpha
-                        let mut buf = BytesMut::with_capacity(
-                            (region.width as usize * region.height as usize) * 4,
-                        );
-                        for chunk in pixel_data.chunks_exact(4) {
-                            buf.put_u8(chunk[0]); // R
-                            buf.put_u8(chunk[1]); // G
-                            buf.put_u8(chunk[2]); // B
-                            buf.put_u8(0); // Padding (not alpha)
-                        }
-                        buf
-                    } else {
-                        // Translate from server format (RGBA32) to client's requested format
-                        translate::translate_pixels(
-                            &pixel_data,
-                            &server_format,
-                            &client_pixel_format,
-                        )
-                    };
-                    (ENCODING_RAW, translated)
-                } else if preferred_encoding == ENCODING_ZLIB {
-                    // Translate pixels to client format first
-                    let translated = if client_pixel_format.is_compatible_with_rgba32() {
-                        // Fast path: no translation, but still need to strip alpha
-                        let mut buf = BytesMut::with_capacity(
-                            (region.width as usize * region.height as usize) * 4,
-                        );
-                        for chunk in pixel_data.chunks_exact(4) {
-                            buf.put_u8(chunk[0]); // R
-                            buf.put_u8(chunk[1]); // G
-                            buf.put_u8(chunk[2]); // B
-                            buf.put_u8(0); // Padding (not alpha)
-                        }
-                        buf
-                    } else {
-                        // Translate from server format (RGBA32) to client's requested format
-                        translate::translate_pixels(
-                            &pixel_data,
-                            &server_format,
-                            &client_pixel_format,
-                        )
-                    };
-
-                    // Initialize ZLIB compressor lazily on first use
-                    let mut zlib_lock = self.zlib_compressor.write().await;
-                    if zlib_lock.is_none() {
-                        *zlib_lock = Some(Compress::new(
-                            Compression::new(u32::from(compression_level)),
-                            true,
-                        ));
-                        #[cfg(feature = "debug-logging")]
-                        info!("Initialized ZLIB compressor with level {compression_level}");
-                    }
-                    let zlib_comp = zlib_lock.as_mut().unwrap();
-
-                    match encoding::encode_zlib_persistent(&translated, zlib_comp) {
-                        Ok(data) => (ENCODING_ZLIB, BytesMut::from(&data[..])),
-                        Err(e) => {
-                            error!("ZLIB encoding failed: {e}, falling back to RAW");
-                            #[cfg(feature = "debug-logging")]
-                            {
-                                encoding_name = "RAW";
-                            }
-                            // translated already contains the correctly formatted data
-                            (ENCODING_RAW, translated)
-                        }
-                    }
-                } else if preferred_encoding == ENCODING_ZLIBHEX {
-                    // Translate pixels to client format first
-                    let translated = if client_pixel_format.is_compatible_with_rgba32() {
-                        // Fast path: no translation, but still need to strip alpha
-                        let mut buf = BytesMut::with_capacity(
-                            (region.width as usize * region.height as usize) * 4,
-                        );
-                        for chunk in pixel_data.chunks_exact(4) {
-                            buf.put_u8(chunk[0]); // R
-                            buf.put_u8(chunk[1]); // G
-                            buf.put_u8(chunk[2]); // B
-                            buf.put_u8(0); // Padding (not alpha)
-                        }
-                        buf
-                    } else {
-                        // Translate from server format (RGBA32) to client's requested format
-                        translate::translate_pixels(
-                            &pixel_data,
-                            &server_format,
-                            &client_pixel_format,
-                        )
-                    };
-
-                    // Initialize ZLIBHEX compressor lazily on first use
-                    let mut zlibhex_lock = self.zlibhex_compressor.write().await;
-                    if zlibhex_lock.is_none() {
-                        *zlibhex_lock = Some(Compress::new(
-                            Compression::new(u32::from(compression_level)),
-                            true,
-                        ));
-                        #[cfg(feature = "debug-logging")]
-                        info!("Initialized ZLIBHEX compressor with level {compression_level}");
-                    }
-                    let zlibhex_comp = zlibhex_lock.as_mut().unwrap();
-
-                    match encoding::encode_zlibhex_persistent(
-                        &translated,
-                        region.width,
-                        region.height,
-                        zlibhex_comp,
-                    ) {
-                        Ok(data) => (ENCODING_ZLIBHEX, BytesMut::from(&data[..])),
-                        Err(e) => {
-                            error!("ZLIBHEX encoding failed: {e}, falling back to RAW");
-                            #[cfg(feature = "debug-logging")]
-                            {
-                                encoding_name = "RAW";
-                            }
-                            // translated already contains the correctly formatted data
-                            (ENCODING_RAW, translated)
-                        }
-                    }
-                } else if preferred_encoding == ENCODING_ZRLE {
-                    // Translate pixels to client format first
-                    let translated = if client_pixel_format.is_compatible_with_rgba32() {
-                        // Fast path: no translation, but still need to strip alpha
-                        let mut buf = BytesMut::with_capacity(
-                            (region.width as usize * region.height as usize) * 4,
-                        );
-                        for chunk in pixel_data.chunks_exact(4) {
-                            buf.put_u8(chunk[0]); // R
-                            buf.put_u8(chunk[1]); // G
-                            buf.put_u8(chunk[2]); // B
-                            buf.put_u8(0); // Padding (not alpha)
-                        }
-                        buf
+                }
+            };
+
+            // The compression streams and the output buffer move to the encoder thread
+            // with the job and come back
+            context.streams = std::mem::take(&mut self.encode_streams);
+            let mut buffer = std::mem::take(&mut self.encode_buffer);
+            let (width, height) = (region.width, region.height);
+            let (shared, payload, mut context, buffer) = self
+                .run_encode(move || {
+                    let shared = cached.unwrap_or_else(|| {
+                        encoder.encode(&pixel_data, width, height, &context, &mut buffer);
+                        buffer.split().freeze()
+                    });
+                    let payload = if encoder.has_stream() {
+                        encoder
+                            .compress(&shared, width, height, &mut context, &mut buffer)
+                            .map(|()| buffer.split().freeze())
                     } else {
-                        // Translate from server format (RGBA32) to client's requested format
-                        translate::translate_pixels(
-                            &pixel_data,
-                            &server_format,
-                            &client_pixel_format,
-                        )
+                        Ok(shared.clone())
                     };
+                    (shared, payload, context, buffer)
+                })
+                .await;
+            self.encode_streams = std::mem::take(&mut context.streams);
+            self.encode_buffer = buffer;
+
+            if !cache_hit {
+                if let Some(key) = cache_key {
+                    cache.insert(
+                        key,
+                        shared,
+                        cache_generation,
+                        self.config.encoding.shared_cache_bytes,
+                    );
+                }
+            }
+            // Other clients waiting for this rectangle can take it from the cache now
+            drop(claim);
 
-                    // Initialize ZRLE compressor lazily on first use
-                    let mut zrle_lock = self.zrle_compressor.write().await;
-                    if zrle_lock.is_none() {
-                        *zrle_lock = Some(Compress::new(
-                            Compression::new(u32::from(compression_level)),
-                            true,
-                        ));
-                        #[cfg(feature = "debug-logging")]
-                        info!("Initialized ZRLE compressor with level {compression_level}");
-                    }
-                    let zrle_comp = zrle_lock.as_mut().unwrap();
-
-                    // Use client's pixel format for encoding
-                    match encoding::encode_zrle_persistent(
-                        &translated,
-                        region.width,
-                        region.height,
-                        &client_pixel_format,
-                        zrle_comp,
-                    ) {
-                        Ok(data) => (ENCODING_ZRLE, BytesMut::from(&data[..])),
-                        Err(e) => {
-                            error!("ZRLE encoding failed: {e}, falling back to RAW");
-                            #[cfg(feature = "debug-logging")]
-                            {
-                                encoding_name = "RAW";
-                            }
-                            // translated already contains the correctly formatted data
-                            (ENCODING_RAW, translated)
-                        }
-                    }
-                } else if preferred_encoding == ENCODING_ZYWRLE {
-                    // ZYWRLE: Apply wavelet preprocessing then use ZRLE encoder
-                    let level = self.zywrle_level.load(Ordering::Relaxed) as usize;
-
-                    // Allocate coefficient buffer for wavelet transform
-                    let buf_size = (region.width as usize) * (region.height as usize);
-                    let mut coeff_buf = vec![0i32; buf_size];
-
-                    // Apply ZYWRLE wavelet preprocessing
-                    let result = if let Some(transformed_data) = encoding::zywrle_analyze(
-                        &pixel_data,
-                        region.width as usize,
-                        region.height as usize,
-                        level,
-                        &mut coeff_buf,
-                    ) {
-                        // Translate the wavelet-transformed data to client format
-                        let translated = if client_pixel_format.is_compatible_with_rgba32() {
-                            // Fast path: no translation, but still need to strip alpha
-                            let mut buf = BytesMut::with_capacity(
-                                (region.width as usize * region.height as usize) * 4,
-                            );
-                            for chunk in transformed_data.chunks_exact(4) {
-                                buf.put_u8(chunk[0]); // R
-                                buf.put_u8(chunk[1]); // G
-                                buf.put_u8(chunk[2]); // B
-                                buf.put_u8(0); // Padding (not alpha)
-                            }
-                            buf
-                        } else {
-                            // Translate from server format (RGBA32) to client's requested format
-                            translate::translate_pixels(
-                                &transformed_data,
-                                &server_format,
-                                &client_pixel_format,
-                            )
-                        };
-
-                        // Now encode the translated data with ZRLE (shares the ZRLE compressor)
-                        let mut zrle_lock = self.zrle_compressor.write().await;
-                        if zrle_lock.is_none() {
-                            *zrle_lock = Some(Compress::new(
-                                Compression::new(u32::from(compression_level)),
-                                true,
-                            ));
-                            #[cfg(feature = "debug-logging")]
-                            info!(
-                            "Initialized ZRLE compressor for ZYWRLE with level {compression_level}"
-                        );
-                        }
-                        let zrle_comp = zrle_lock.as_mut().unwrap();
-
-                        // Use client's pixel format for encoding
-                        match encoding::encode_zrle_persistent(
-                            &translated,
-                            region.width,
-                            region.height,
-                            &client_pixel_format,
-                            zrle_comp,
-                        ) {
-                            Ok(data) => (ENCODING_ZYWRLE, BytesMut::from(&data[..])),
-                            Err(e) => {
-                                error!("ZYWRLE encoding failed: {e}, falling back to RAW");
-                                #[cfg(feature = "debug-logging")]
-                                {
-                                    encoding_name = "RAW";
-                                }
-                                // translated already contains the correctly formatted data
-                                (ENCODING_RAW, translated)
-                            }
-                        }
-                    } else {
-                        // Analysis failed (dimensions too small), fall back to RAW with translation
-                        error!(
-                            "ZYWRLE analysis failed (dimensions too small), falling back to RAW"
-                        );
-                        #[cfg(feature = "debug-logging")]
-                        {
-                            encoding_name = "RAW";
-                        }
-                        // Translate original pixel_data for RAW fallback
-                        let translated = if client_pixel_format.is_compatible_with_rgba32() {
-                            let mut buf = BytesMut::with_capacity(
-                                (region.width as usize * region.height as usize) * 4,
-                            );
-                            for chunk in pixel_data.chunks_exact(4) {
-                                buf.put_u8(chunk[0]); // R
-                                buf.put_u8(chunk[1]); // G
-                                buf.put_u8(chunk[2]); // B
-                                buf.put_u8(0); // Padding
-                            }
-                            buf
-                        } else {
-                            translate::translate_pixels(
-                                &pixel_data,
-                                &server_format,
-                                &client_pixel_format,
-                            )
-                        };
-                        (ENCODING_RAW, translated)
-                    };
-                    result
-                } else if let Some(encoder) = encoding::get_encoder(preferred_encoding) {
-                    // For other encodings (TightPng, Hextile): translate first then encode
-                    let translated = if client_pixel_format.is_compatible_with_rgba32() {
-                        // Fast path: no translation, but still need to strip alpha
-                        let mut buf = BytesMut::with_capacity(
-                            (region.width as usize * region.height as usize) * 4,
-                        );
-                        for chunk in pixel_data.chunks_exact(4) {
-                            buf.put_u8(chunk[0]); // R
-                            buf.put_u8(chunk[1]); // G
-                            buf.put_u8(chunk[2]); // B
-                            buf.put_u8(0); // Padding (not alpha)
-                        }
-                        buf
-                    } else {
-                        // Translate from server format (RGBA32) to client's requested format
-                        translate::translate_pixels(
-                            &pixel_data,
-                            &server_format,
-                            &client_pixel_format,
-                        )
-                    };
-                    (
-                        preferred_encoding,
-                        encoder.encode(
-                            &translated,
-                            region.width,
-                            region.height,
-                            jpeg_quality,
-                            compression_level,
-                        ),
-                    )
-                } else {
-                    // Fallback to RAW encoding if preferred encoding is not available
-                    error!("Encoding {preferred_encoding} not available, falling back to RAW");
+            let (actual_encoding, payload) = match payload {
+                Ok(payload) => (encoding, payload),
+                Err(e) => {
+                    error!("Encoding {encoding} failed: {e}, falling back to RAW");
                     #[cfg(feature = "debug-logging")]
                     {
-                        encoding_name = "RAW"; // Update encoding name to reflect fallback
+                        encoding_name = "RAW";
                     }
-                    // Translate for RAW fallback
-                    let translated = if client_pixel_format.is_compatible_with_rgba32() {
-                        let mut buf = BytesMut::with_capacity(
-                            (region.width as usize * region.height as usize) * 4,
-                        );
-                        for chunk in pixel_data.chunks_exact(4) {
-                            buf.put_u8(chunk[0]); // R
-                            buf.put_u8(chunk[1]); // G
-                            buf.put_u8(chunk[2]); // B
-                            buf.put_u8(0); // Padding
+                    let pixel_data = match self.read_pixels(region.x, region.y, width, height).await
+                    {
+                        Ok(data) => data,
+                        Err(e) => {
+                            error!(
+                                "Failed to get rectangle ({}, {}, {}, {}): {}",
+                                region.x, region.y, width, height, e
+                            );
+                            continue;
                         }
-                        buf
-                    } else {
-                        translate::translate_pixels(
-                            &pixel_data,
-                            &server_format,
-                            &client_pixel_format,
-                        )
                     };
-                    (ENCODING_RAW, translated)
-                };
+                    let mut raw = BytesMut::new();
+                    rect_encoder::RAW.encode(&pixel_data, width, height, &context, &mut raw);
+                    (ENCODING_RAW, raw.freeze())
+                }
+            };
+            self.encode_stats.encode.record(encode_start.elapsed());
+
+            // Write rectangle header with actual encoding used
+            let rect = Rectangle {
+                x: region.x,
+                y: region.y,
+                width: region.width,
+                height: region.height,
+                encoding: actual_encoding,
+            };
+            self.queue_rect(&mut batch, &rect, &[&payload]).await?;
+            self.record_sent(
+                region,
+                (actual_encoding == ENCODING_ZYWRLE).then_some(region),
+            );
 
-                // Write rectangle header with actual encoding used
-                let rect = Rectangle {
-                    x: region.x,
-                    y: region.y,
-                    width: region.width,
-                    height: region.height,
-                    encoding: actual_encoding,
-                };
-                rect.write_header(&mut response);
-                response.extend_from_slice(&encoded);
+            total_pixels += u64::from(region.width) * u64::from(region.height);
+        }
+        // Let the application write to the framebuffer without copying it
+        self.frame = None;
 
-                total_pixels += u64::from(region.width) * u64::from(region.height);
-            }
+        // STEP 3: Append application-encoded rectangles verbatim
+        for (rect, payload) in &custom_rects {
+            self.queue_rect(&mut batch, rect, &[payload]).await?;
         }
 
-        // Acquire send mutex to prevent interleaved writes
         #[cfg(feature = "debug-logging")]
-        info!("DEBUG: About to send response, total_rects={}, response.len()={}, copy_rect_count={}, modified_regions={}",
-            total_rects, response.len(), copy_rect_count, modified_regions_to_send.len());
-
-        let lock = self.send_mutex.lock().await;
+        info!("DEBUG: About to send final message, total_rects={}, pending bytes={}, copy_rect_count={}, modified_regions={}",
+            batch.total_rects, batch.body.len(), copy_rect_count, modified_regions_to_send.len());
+
+        self.flush_update(&mut batch).await?;
+        self.send_buffer_capacity = batch.body.capacity();
+        self.record_update_timing(
+            batch.total_rects,
+            batch.bytes_sent,
+            damaged_at,
+            start,
+            batch.send_time,
+        );
 
         #[cfg(feature = "debug-logging")]
         info!(
-            "DEBUG: Acquired send_mutex, calling write_all with {} bytes",
-            response.len()
+            "DEBUG: write completed successfully ({} messages)",
+            batch.messages
         );
 
-        self.write_stream.lock().await.write_all(&response).await?;
-
-        #[cfg(feature = "debug-logging")]
-        info!("DEBUG: write_all completed successfully");
-
-        drop(lock);
+        if self.config.updates.adaptive_defer {
+            if let Some(damaged_at) = damaged_at {
+                let previous_send = *self.last_update_sent.read().await;
+                self.adapt_defer_time(damaged_at.saturating_duration_since(previous_send));
+            }
+        }
 
         // Reset deferral timer and update last sent time
         self.start_deferring_nanos.store(0, Ordering::Relaxed); // Reset deferral
         *self.last_update_sent.write().await = Instant::now();
 
+        // A client using the ContinuousUpdates extension without continuous updates
+        // enabled gets one update per request
+        if self.continuous_region.is_none()
+            && self
+                .encodings
+                .read()
+                .await
+                .contains(&ENCODING_CONTINUOUS_UPDATES)
+        {
+            self.continuous_updates.store(false, Ordering::Relaxed);
+        }
+
         #[cfg(feature = "debug-logging")]
         {
             let elapsed = start.elapsed();
             info!(
                 "Sent {} rects ({} CopyRect + {} encoded, {} pixels total) using {} ({} bytes, {}ms encode+send)",
-                total_rects, copy_rect_count, modified_regions_to_send.len(), total_pixels, encoding_name, response.len(), elapsed.as_millis()
+                batch.total_rects, copy_rect_count, modified_regions_to_send.len(), total_pixels, encoding_name, batch.bytes_sent, elapsed.as_millis()
             );
         }
 
         Ok(())
     }
 
-    /// Sends a `ServerCutText` message to the client, updating its clipboard.
+    /// Adds a rectangle to an update, first sending the pending rectangles as a complete
+    /// `FramebufferUpdate` if the rectangle would take the message past its size budget or
+    /// past the 65535 rectangles a message can count.
     ///
     /// # Arguments
     ///
-    /// * `text` - The string to be sent as the clipboard content.
+    /// * `batch` - The update being assembled.
+    /// * `rect` - The rectangle header.
+    /// * `payload` - The encoded rectangle data, in pieces that are written back to back.
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// `Ok(())` on successful transmission, or `Err(std::io::Error)` if an I/O error occurs.
-    #[allow(clippy::cast_possible_truncation)] // Clipboard text length limited to u32 per VNC protocol
-    pub async fn send_cut_text(&mut self, text: String) -> Result<(), std::io::Error> {
-        let mut msg = BytesMut::new();
-        msg.put_u8(SERVER_MSG_SERVER_CUT_TEXT);
-        msg.put_bytes(0, 3); // padding
-        msg.put_u32(text.len() as u32);
-        msg.put_slice(text.as_bytes());
+    /// Returns `Err(std::io::Error)` if sending the pending rectangles fails.
+    async fn queue_rect(
+        &self,
+        batch: &mut UpdateBatch,
+        rect: &Rectangle,
+        payload: &[&[u8]],
+    ) -> Result<(), std::io::Error> {
+        let rect_size = 12 + payload.iter().map(|part| part.len()).sum::<usize>();
+        if batch.pending_rects == u16::MAX
+            || (batch.pending_rects > 0 && batch.body.len() + rect_size > batch.max_bytes)
+        {
+            self.flush_update(batch).await?;
+        }
+
+        rect.write_header(&mut batch.body);
+        for part in payload {
+            batch.body.extend_from_slice(part);
+        }
+        batch.pending_rects += 1;
+        batch.total_rects += 1;
+        Ok(())
+    }
+
+    /// Sends the pending rectangles of an update as one `FramebufferUpdate` message.
+    ///
+    /// Does nothing if no rectangles are pending.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the write fails or stalls.
+    async fn flush_update(&self, batch: &mut UpdateBatch) -> Result<(), std::io::Error> {
+        if batch.pending_rects == 0 {
+            return Ok(());
+        }
+        batch.body[2..4].copy_from_slice(&batch.pending_rects.to_be_bytes());
 
         // Acquire send mutex to prevent interleaved writes
         let _lock = self.send_mutex.lock().await;
-        self.write_stream.lock().await.write_all(&msg).await?;
+        let mut stream = self.write_stream.lock().await;
+        let send_start = Instant::now();
+        self.write_with_watchdog(&mut stream, &batch.body).await?;
+        let elapsed = send_start.elapsed();
+        drop(stream);
+
+        self.encode_stats.send.record(elapsed);
+        batch.send_time += elapsed;
+        batch.bytes_sent += batch.body.len();
+        batch.messages += 1;
+        batch.start_message();
         Ok(())
     }
 
-    /// Returns the unique client ID assigned by the server.
-    pub fn get_client_id(&self) -> usize {
-        self.client_id
+    /// Adapts the update deferral to how soon damage followed the previous update.
+    ///
+    /// Damage that keeps arriving right after each update is treated as video-like and
+    /// batched for `max_defer_time`; damage after a long quiet period is sent after
+    /// `min_defer_time`. Gaps in between interpolate linearly.
+    ///
+    /// # Arguments
+    ///
+    /// * `gap` - Time between the previous update and the oldest damage in this one.
+    fn adapt_defer_time(&mut self, gap: Duration) {
+        /// Gaps up to this long mean damage is constant.
+        const CONSTANT_DAMAGE_GAP: Duration = Duration::from_millis(5);
+        /// Gaps at least this long mean damage is sporadic.
+        const SPORADIC_DAMAGE_GAP: Duration = Duration::from_millis(100);
+
+        // Cap the gap so a long idle period doesn't delay adapting once damage is constant
+        let gap = gap.min(SPORADIC_DAMAGE_GAP);
+        let smoothed = self
+            .damage_gap
+            .map_or(gap, |previous| (previous * 3 + gap) / 4);
+        self.damage_gap = Some(smoothed);
+
+        let min = self.config.updates.min_defer_time;
+        let max = self.config.updates.max_defer_time.max(min);
+        self.defer_update_time = if smoothed <= CONSTANT_DAMAGE_GAP {
+            max
+        } else if smoothed >= SPORADIC_DAMAGE_GAP {
+            min
+        } else {
+            let position = smoothed.saturating_sub(CONSTANT_DAMAGE_GAP).as_secs_f64()
+                / SPORADIC_DAMAGE_GAP
+                    .saturating_sub(CONSTANT_DAMAGE_GAP)
+                    .as_secs_f64();
+            max.saturating_sub(max.saturating_sub(min).mul_f64(position))
+        };
     }
 
-    /// Returns a clone of the Arc containing the write half of the TCP stream.
+    /// Reads a rectangle of the client's screen for encoding, applying the configured
+    /// transform and color reduction.
     ///
-    /// This allows external code to close the write half directly for shutdown,
-    /// which will cause reads on the read half to fail naturally.
-    pub fn get_write_stream_handle(
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the rectangle is out of the framebuffer's bounds.
+    async fn read_pixels(
         &self,
-    ) -> Arc<tokio::sync::Mutex<tokio::net::tcp::OwnedWriteHalf>> {
-        self.write_stream.clone()
-    }
-
-    /// Returns the remote host address of the connected client.
-    pub fn get_remote_host(&self) -> &str {
-        &self.remote_host
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+    ) -> Result<RectPixels, String> {
+        let source = self.framebuffer_region(DirtyRegion::new(x, y, width, height));
+        let pixels = match &self.frame {
+            Some(frame) => frame.get_rect(source.x, source.y, source.width, source.height)?,
+            None => self
+                .framebuffer
+                .get_rect(source.x, source.y, source.width, source.height)
+                .await?
+                .into(),
+        };
+        let (transform, color_mode) = (self.config.updates.transform, self.color_mode());
+        if transform == Transform::Identity && color_mode == ColorMode::Full {
+            // Pixels shared with the frame snapshot are encoded without a copy
+            return Ok(pixels);
+        }
+        let mut pixels = transform.apply(pixels.into_vec(), source.width, source.height);
+        color_mode.apply(&mut pixels);
+        Ok(pixels.into())
     }
 
-    /// Returns the destination port for repeater connections.
-    /// Returns -1 for direct connections (not using a repeater).
-    pub fn get_destination_port(&self) -> i32 {
-        self.destination_port.map_or(-1, i32::from)
+    /// Returns the color reduction applied to the client's updates: its override, or the
+    /// server's `UpdateConfig::color_mode`.
+    fn color_mode(&self) -> ColorMode {
+        self.color_mode_override
+            .unwrap_or(self.config.updates.color_mode)
     }
 
-    /// Returns the repeater ID if this client is connected via a repeater.
-    /// Returns None for direct connections.
-    pub fn get_repeater_id(&self) -> Option<&str> {
-        self.repeater_id.as_deref()
+    /// Returns the minimum time between the client's updates: its frame-rate cap, or the
+    /// server's `UpdateConfig::min_update_interval`.
+    fn min_update_interval(&self) -> Duration {
+        self.update_interval_override
+            .unwrap_or(self.config.updates.min_update_interval)
     }
 
-    /// Sets the connection metadata for reverse connections.
-    pub fn set_connection_metadata(&mut self, destination_port: Option<u16>) {
-        self.destination_port = destination_port;
+    /// Overrides the client's color reduction, resending the whole screen if that
+    /// changes what the client sees.
+    async fn set_color_mode(&mut self, mode: Option<ColorMode>) {
+        let previous = self.color_mode();
+        self.color_mode_override = mode;
+        if self.color_mode() != previous {
+            let (width, height) = self.screen_size();
+            self.damage_receiver()
+                .add_dirty_region(DirtyRegion::new(0, 0, width, height))
+                .await;
+        }
     }
 
-    /// Sets the repeater metadata for repeater connections.
-    pub fn set_repeater_metadata(&mut self, repeater_id: String, destination_port: Option<u16>) {
-        self.repeater_id = Some(repeater_id);
-        self.destination_port = destination_port;
+    /// Selects the encoding for framebuffer updates: the first one in the client's list
+    /// that the server supports, skipping `CopyRect`, or Raw if there is none.
+    ///
+    /// Encodings in `server_order` (`UpdateConfig::encoding_order`) that the client listed
+    /// take precedence over the client's own order.
+    fn select_encoding(encodings: &[i32], server_order: &[i32]) -> i32 {
+        server_order
+            .iter()
+            .filter(|enc| encodings.contains(enc))
+            .chain(encodings)
+            .find(|&&enc| {
+                // Skip COPYRECT - it's only for copy operations, not general encoding
+                if enc == ENCODING_COPYRECT {
+                    return false;
+                }
+                // Check if this encoding is supported
+                // Either it has explicit handling in client.rs or get_encoder returns Some
+                matches!(
+                    enc,
+                    ENCODING_ZLIB
+                        | ENCODING_ZLIBHEX
+                        | ENCODING_ZRLE
+                        | ENCODING_TRLE
+                        | ENCODING_ZYWRLE
+                        | ENCODING_TIGHT
+                ) || (cfg!(feature = "zstd") && enc == ENCODING_ZSTD)
+                    || encoding::get_encoder(enc).is_some()
+            })
+            .copied()
+            .unwrap_or(ENCODING_RAW)
+    }
+
+    /// Selects the encoding of a region from its content, among the encodings the client
+    /// listed, or returns `preferred` if none suits the content better.
+    ///
+    /// Photographic regions go to Tight when it can send them as JPEG (`jpeg`), or to
+    /// ZYWRLE. Text and UI is kept off ZYWRLE, whose wavelet blurs sharp edges. Solid
+    /// regions avoid Raw and the plain zlib encodings, which have no fill.
+    fn select_encoding_for_content(
+        encodings: &[i32],
+        preferred: i32,
+        content: ContentClass,
+        jpeg: bool,
+    ) -> i32 {
+        let offered = |candidates: &[i32]| {
+            encodings
+                .iter()
+                .copied()
+                .find(|encoding| candidates.contains(encoding))
+        };
+        let choice = match content {
+            ContentClass::Photo if preferred != ENCODING_TIGHT && preferred != ENCODING_ZYWRLE => {
+                if jpeg {
+                    offered(&[ENCODING_TIGHT, ENCODING_ZYWRLE])
+                } else {
+                    offered(&[ENCODING_ZYWRLE])
+                }
+            }
+            ContentClass::Text if preferred == ENCODING_ZYWRLE => offered(&[
+                ENCODING_ZRLE,
+                ENCODING_TIGHT,
+                ENCODING_TRLE,
+                ENCODING_HEXTILE,
+                ENCODING_ZLIB,
+            ]),
+            ContentClass::Solid
+                if matches!(preferred, ENCODING_RAW | ENCODING_ZLIB | ENCODING_ZSTD) =>
+            {
+                offered(&[
+                    ENCODING_TIGHT,
+                    ENCODING_ZRLE,
+                    ENCODING_TRLE,
+                    ENCODING_HEXTILE,
+                    ENCODING_RRE,
+                    ENCODING_ZLIBHEX,
+                    ENCODING_CORRE,
+                ])
+            }
+            _ => None,
+        };
+        choice.unwrap_or(preferred)
+    }
+
+    /// Returns the encoding of each modified region (see `select_encoding_for_content`).
+    ///
+    /// Classifying a region reads its pixels, so regions are only classified when
+    /// `UpdateConfig::content_adaptive` is set and the client's encodings offer an
+    /// alternative to `preferred` for some kind of content.
+    async fn region_encodings(&self, regions: &[DirtyRegion], preferred: i32) -> Vec<i32> {
+        let encodings = self.encodings.read().await.clone();
+        let client_format = self.pixel_format.read().await.clone();
+        let quality_level = self.effective_quality_level();
+        let jpeg = cfg!(feature = "turbojpeg")
+            && tight::uses_tpixel(&client_format)
+            && !self.config.updates.lossless_only;
+
+        // Photographic content needs a quality level (`detect_smooth_image`)
+        let classes: &[ContentClass] = if quality_level < 10 {
+            &[ContentClass::Solid, ContentClass::Text, ContentClass::Photo]
+        } else {
+            &[ContentClass::Solid, ContentClass::Text]
+        };
+        let has_alternative = classes.iter().any(|&content| {
+            Self::select_encoding_for_content(&encodings, preferred, content, jpeg) != preferred
+        });
+        if !self.config.updates.content_adaptive || !has_alternative {
+            return vec![preferred; regions.len()];
+        }
+
+        let mut selected = Vec::with_capacity(regions.len());
+        for region in regions {
+            let content = match self
+                .read_pixels(region.x, region.y, region.width, region.height)
+                .await
+            {
+                Ok(pixels) => analysis::classify_content(
+                    &pixels,
+                    region.width,
+                    region.height,
+                    quality_level,
+                    &client_format,
+                ),
+                // The encoding pass reports the error and skips the region
+                Err(_) => ContentClass::Text,
+            };
+            selected.push(Self::select_encoding_for_content(
+                &encodings, preferred, content, jpeg,
+            ));
+        }
+        selected
+    }
+
+    /// Reports the current negotiation state as a `Negotiated` event.
+    async fn report_negotiation(&self) {
+        let encodings = self.encodings.read().await.clone();
+        let pseudo_encodings = encodings
+            .iter()
+            .copied()
+            .filter(|encoding| {
+                *encoding == ENCODING_DESKTOP_SIZE
+                    || *encoding == ENCODING_EXTENDED_DESKTOP_SIZE
+                    || *encoding == ENCODING_DESKTOP_NAME
+                    || *encoding == ENCODING_CONTINUOUS_UPDATES
+                    || *encoding == ENCODING_GII
+                    || *encoding == ENCODING_QEMU_LED_STATE
+                    || *encoding == ENCODING_VMWARE_LED_STATE
+                    || (ENCODING_QUALITY_LEVEL_0..=ENCODING_QUALITY_LEVEL_9).contains(encoding)
+                    || (ENCODING_COMPRESS_LEVEL_0..=ENCODING_COMPRESS_LEVEL_9).contains(encoding)
+                    || self.config.extensions.pseudo_encodings.contains(encoding)
+            })
+            .collect();
+        let negotiation = Negotiation {
+            protocol_version: self.protocol_version.clone(),
+            auth_method: self.auth_method,
+            pixel_format: self.pixel_format.read().await.clone(),
+            encoding: Self::select_encoding(&encodings, &self.config.updates.encoding_order),
+            encodings,
+            pseudo_encodings,
+        };
+        log::info!(
+            "Client negotiated RFB {}, encoding {}, {}bpp",
+            negotiation.protocol_version,
+            negotiation.encoding,
+            negotiation.pixel_format.bits_per_pixel
+        );
+        let _ = self.event_tx.send(ClientEvent::Negotiated { negotiation });
+    }
+
+    /// Records the pipeline timing of an update that was just written, and reports it if
+    /// `report_timing` is enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `rects` - Number of rectangles in the update.
+    /// * `bytes` - Bytes written for the update.
+    /// * `damaged_at` - Arrival time of the oldest damage in the update, if known.
+    /// * `encode_start` - When encoding of the update started.
+    /// * `send_time` - Total time spent writing the update.
+    fn record_update_timing(
+        &self,
+        rects: usize,
+        bytes: usize,
+        damaged_at: Option<Instant>,
+        encode_start: Instant,
+        send_time: Duration,
+    ) {
+        let queued = damaged_at.map(|at| encode_start.saturating_duration_since(at));
+        let timing = UpdateTiming {
+            rects,
+            bytes,
+            queued,
+            encode: encode_start.elapsed().saturating_sub(send_time),
+            send: send_time,
+        };
+        if let Some(queued) = queued {
+            self.encode_stats.queue.record(queued);
+            self.encode_stats.total.record(timing.total());
+        }
+        if self.config.updates.report_timing {
+            let _ = self.event_tx.send(ClientEvent::UpdateTiming { timing });
+        }
+    }
+
+    /// Filters clipboard text received from the client and delivers it as a `CutText` event.
+    ///
+    /// Control characters are stripped and the configured filter applied; if the previous
+    /// clipboard event was delivered less than `min_inbound_interval` ago, the text is held
+    /// back (replacing any text already held) until `flush_pending_cut_text` delivers it.
+    fn receive_cut_text(&mut self, mut text: String) {
+        if !self.accepts_input() {
+            return; // Input from paused and view-only clients is dropped
+        }
+        let clipboard = &self.config.clipboard;
+        if clipboard.strip_control_chars {
+            text.retain(|c| !c.is_control() || matches!(c, '\t' | '\r' | '\n'));
+        }
+        if let Some(filter) = &clipboard.filter {
+            match filter(text) {
+                Some(filtered) => text = filtered,
+                None => return,
+            }
+        }
+
+        let throttled = self
+            .last_cut_text_at
+            .is_some_and(|last| last.elapsed() < clipboard.min_inbound_interval);
+        if throttled {
+            self.pending_cut_text = Some(text);
+        } else {
+            self.pending_cut_text = None;
+            self.last_cut_text_at = Some(Instant::now());
+            self.audit_event(AuditEvent::ClipboardReceived { bytes: text.len() });
+            let _ = self.event_tx.send(ClientEvent::CutText { text });
+        }
+    }
+
+    /// Records an event about this client in the audit log, if auditing is enabled.
+    fn audit_event(&self, event: AuditEvent) {
+        if let Some(audit) = &self.audit {
+            audit.record(self.client_id, &self.remote_host, event);
+        }
+    }
+
+    /// Returns whether the client's keyboard, pointer and clipboard input is delivered:
+    /// not while it is paused or view-only.
+    fn accepts_input(&self) -> bool {
+        self.paused_framebuffer.is_none() && !self.view_only
+    }
+
+    /// Delivers clipboard text held back by throttling once `min_inbound_interval` has passed.
+    fn flush_pending_cut_text(&mut self) {
+        let due = self
+            .last_cut_text_at
+            .is_none_or(|last| last.elapsed() >= self.config.clipboard.min_inbound_interval);
+        if due {
+            if let Some(text) = self.pending_cut_text.take() {
+                self.last_cut_text_at = Some(Instant::now());
+                self.audit_event(AuditEvent::ClipboardReceived { bytes: text.len() });
+                let _ = self.event_tx.send(ClientEvent::CutText { text });
+            }
+        }
+    }
+
+    /// Returns whether the update timer has nothing to do until new damage arrives.
+    async fn is_idle(&self) -> bool {
+        #[cfg(feature = "file-transfer")]
+        if self
+            .file_transfer
+            .as_ref()
+            .is_some_and(FileTransfer::is_sending)
+        {
+            return false;
+        }
+        !self.framebuffer.is_source_backed()
+            && self.pending_cut_text.is_none()
+            && self.pending_full_refresh.is_none()
+            && self.pending_rects.is_empty()
+            && self.copy_region.read().await.is_empty()
+            && self.modified_regions.read().await.is_empty()
+            && (self.lossy_regions.is_empty()
+                || self.config.updates.lossless_refresh_delay.is_zero())
+    }
+
+    /// Queues the regions last sent lossily for a lossless refresh once no update has
+    /// been sent for `UpdateConfig::lossless_refresh_delay` (automatic lossless refresh).
+    async fn schedule_lossless_refresh(&mut self) {
+        let delay = self.config.updates.lossless_refresh_delay;
+        if delay.is_zero() || self.lossy_regions.is_empty() || self.lossless_refresh {
+            return;
+        }
+        if self.last_update_sent.read().await.elapsed() < delay
+            || !self.modified_regions.read().await.is_empty()
+        {
+            return;
+        }
+
+        let (width, height) = self.screen_size();
+        let receiver = DirtyRegionReceiver::new(Arc::downgrade(&self.modified_regions));
+        for region in std::mem::take(&mut self.lossy_regions) {
+            if let Some(region) = region.clamp_to(width, height) {
+                receiver.add_dirty_region(region).await;
+            }
+        }
+        self.lossless_refresh = true;
+    }
+
+    /// Records that `region` was sent, with the parts in `lossy` sent lossily, for the
+    /// automatic lossless refresh.
+    fn record_sent(&mut self, region: DirtyRegion, lossy: impl IntoIterator<Item = DirtyRegion>) {
+        if self.config.updates.lossless_refresh_delay.is_zero() {
+            return;
+        }
+        self.lossy_regions
+            .retain(|sent| sent.intersect(&region) != Some(*sent));
+        self.lossy_regions.extend(lossy);
+        if self.lossy_regions.len() > MAX_LOSSY_REGIONS {
+            let merged = self
+                .lossy_regions
+                .iter()
+                .fold(self.lossy_regions[0], |merged, region| merged.merge(region));
+            self.lossy_regions = vec![merged];
+        }
+    }
+
+    /// Records a `CopyRect` to `region` from (`x + dx`, `y + dy`): the copy is lossy if
+    /// its source was.
+    fn record_copy(&mut self, region: DirtyRegion, dx: i16, dy: i16) {
+        let copies_lossy = region.translate(dx, dy).is_some_and(|source| {
+            self.lossy_regions
+                .iter()
+                .any(|lossy| lossy.intersects(&source))
+        });
+        self.record_sent(region, copies_lossy.then_some(region));
+    }
+
+    /// Handles a non-incremental update request for `region`.
+    ///
+    /// Some broken clients send non-incremental requests every frame, forcing a full
+    /// re-encode each time. Once more than `full_refresh_burst` such requests arrive in
+    /// quick succession, full refreshes are limited to one per `min_full_refresh_interval`;
+    /// in between, the client is served incremental updates.
+    async fn request_full_refresh(&mut self, region: DirtyRegion) {
+        let updates = &self.config.updates;
+        let rapid = self
+            .last_full_refresh
+            .is_some_and(|last| last.elapsed() < updates.min_full_refresh_interval);
+        if rapid {
+            self.rapid_full_refreshes = self.rapid_full_refreshes.saturating_add(1);
+        } else {
+            self.rapid_full_refreshes = 0;
+        }
+
+        if rapid && self.rapid_full_refreshes >= updates.full_refresh_burst {
+            if !self.full_refresh_throttled {
+                self.full_refresh_throttled = true;
+                log::warn!(
+                    "Client {} keeps requesting full refreshes; limiting them to one per {:?}",
+                    self.client_id,
+                    updates.min_full_refresh_interval
+                );
+            }
+            self.pending_full_refresh = Some(region);
+            return;
+        }
+
+        if self.full_refresh_throttled && !rapid {
+            self.full_refresh_throttled = false;
+            log::info!(
+                "Client {} stopped requesting full refreshes continuously",
+                self.client_id
+            );
+        }
+        self.pending_full_refresh = None;
+        self.apply_full_refresh(region).await;
+    }
+
+    /// Replaces the client's dirty regions with `region` (a forced full refresh).
+    async fn apply_full_refresh(&mut self, region: DirtyRegion) {
+        // Clear existing regions and mark full requested region as dirty
+        *self.modified_regions.write().await = DamageQueue::from(region);
+        self.last_full_refresh = Some(Instant::now());
+        #[cfg(feature = "debug-logging")]
+        info!("Non-incremental update: added full region to dirty list");
+    }
+
+    /// Applies the application's answer to a `SetDesktopSize` request and sends the
+    /// client the resulting status and framebuffer size.
+    ///
+    /// An accepted request resizes the framebuffer, which marks the whole screen dirty for
+    /// every client. The other clients are told about the new size by
+    /// [`follow_resize`](Self::follow_resize).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if sending the reply fails.
+    async fn apply_resize_outcome(&mut self, outcome: ResizeOutcome) -> Result<(), std::io::Error> {
+        let status = match outcome {
+            ResizeOutcome::Accepted { width, height } => {
+                if width == self.framebuffer.width() && height == self.framebuffer.height() {
+                    ResizeStatus::Success
+                } else {
+                    match self.framebuffer.resize(width, height).await {
+                        Ok(()) => ResizeStatus::Success,
+                        Err(e) => {
+                            error!("Failed to resize framebuffer to {width}x{height}: {e}");
+                            ResizeStatus::OutOfResources
+                        }
+                    }
+                }
+            }
+            ResizeOutcome::Rejected(status) => status,
+        };
+
+        log::info!(
+            "SetDesktopSize request from client {} answered with status {}",
+            self.client_id,
+            status.code()
+        );
+
+        let (width, height) = self.screen_size();
+        if (width, height) != self.framebuffer_size {
+            self.framebuffer_size = (width, height);
+            let full = DirtyRegion::new(0, 0, width, height);
+            *self.copy_region.write().await = Region::new();
+            *self.copy_offset.write().await = None;
+            *self.modified_regions.write().await = DamageQueue::from(full);
+            *self.requested_region.write().await = Some(full);
+            self.continuous_region = self.continuous_region.map(|_| full);
+        }
+        let (width, height) = self.framebuffer_size;
+        self.send_desktop_size(width, height, DESKTOP_SIZE_REASON_CLIENT, status)
+            .await
+    }
+
+    /// Returns the JPEG quality (0-100) to encode with, honoring the server-side override.
+    fn effective_jpeg_quality(&self) -> u8 {
+        self.jpeg_quality_override
+            .unwrap_or_else(|| self.jpeg_quality.load(Ordering::Relaxed))
+    }
+
+    /// Returns the VNC quality level (0-9, or 255 for unset) to encode with.
+    ///
+    /// A JPEG quality override maps to the lowest quality level whose JPEG quality is at
+    /// least the override.
+    #[allow(clippy::cast_possible_truncation)] // Index into a 10-element table
+    fn effective_quality_level(&self) -> u8 {
+        match self.jpeg_quality_override {
+            Some(quality) => TIGHT2TURBO_QUAL
+                .iter()
+                .position(|&q| q >= quality)
+                .unwrap_or(9) as u8,
+            None => self.quality_level.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the compression level (0-9) to encode with, honoring the server-side override.
+    ///
+    /// Clients that only send a quality level get a compression level derived from it, so
+    /// the quality setting also affects the lossless encodings (Zlib, `ZlibHex`, ZRLE).
+    /// The zlib streams are created on first use, so the level in effect at that point is
+    /// kept for the rest of the connection.
+    fn effective_compression_level(&self) -> u8 {
+        if let Some(level) = self.compression_override {
+            return level;
+        }
+        if !self.compression_requested {
+            let quality_level = self.effective_quality_level();
+            if let Some(&level) = QUALITY_TO_ZLIB_LEVEL.get(usize::from(quality_level)) {
+                return level;
+            }
+        }
+        self.compression_level.load(Ordering::Relaxed)
+    }
+
+    /// Sends a `ServerCutText` message to the client, updating its clipboard.
+    ///
+    /// Text longer than the configured `max_outbound_bytes` is truncated at a character
+    /// boundary. The message is written whole, holding off other output to the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The string to be sent as the clipboard content.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on successful transmission, or `Err(std::io::Error)` if an I/O error occurs.
+    #[allow(clippy::cast_possible_truncation)] // Clipboard text length limited to u32 per VNC protocol
+    pub async fn send_cut_text(&self, text: &str) -> Result<(), std::io::Error> {
+        let mut len = text.len().min(self.config.clipboard.max_outbound_bytes);
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+        let payload = &text.as_bytes()[..len];
+
+        let mut message = BytesMut::with_capacity(8 + payload.len());
+        message.put_u8(SERVER_MSG_SERVER_CUT_TEXT);
+        message.put_bytes(0, 3); // padding
+        message.put_u32(payload.len() as u32);
+        message.put_slice(payload);
+
+        // Acquire send mutex to prevent interleaved writes
+        let _lock = self.send_mutex.lock().await;
+        let mut stream = self.write_stream.lock().await;
+        self.write_with_watchdog(&mut stream, &message).await?;
+        self.audit_event(AuditEvent::ClipboardSent { bytes: len });
+        Ok(())
+    }
+
+    /// Sends an empty framebuffer update if nothing has been sent for `keepalive_interval`.
+    ///
+    /// A `FramebufferUpdate` with zero rectangles is valid in the standard VNC protocol and
+    /// has no visible effect, but the traffic keeps NAT and proxy mappings alive.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the write fails or stalls.
+    async fn send_keepalive_if_idle(&self) -> Result<(), std::io::Error> {
+        let interval = self.config.connection.keepalive_interval;
+        if interval.is_zero() || self.last_update_sent.read().await.elapsed() < interval {
+            return Ok(());
+        }
+
+        let mut message = BytesMut::with_capacity(4);
+        message.put_u8(SERVER_MSG_FRAMEBUFFER_UPDATE);
+        message.put_u8(0); // padding
+        message.put_u16(0); // number of rectangles
+
+        let _lock = self.send_mutex.lock().await;
+        let mut stream = self.write_stream.lock().await;
+        self.write_with_watchdog(&mut stream, &message).await?;
+        drop(stream);
+
+        *self.last_update_sent.write().await = Instant::now();
+        Ok(())
+    }
+
+    /// Writes `data` to the client, aborting if the socket stops accepting data.
+    ///
+    /// Each write must make progress within `ConnectionConfig::write_stall_timeout`. If it
+    /// doesn't, the peer is treated as dead: the write half is shut down and an error of
+    /// kind `TimedOut` is returned, which ends the client's message loop.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the write fails or stalls.
+    async fn write_with_watchdog(
+        &self,
+        stream: &mut ClientWriter,
+        data: &[u8],
+    ) -> Result<(), std::io::Error> {
+        let deadline = self.config.connection.write_stall_timeout;
+        if deadline.is_zero() {
+            return stream.write_all(data).await;
+        }
+
+        let mut written = 0;
+        while written < data.len() {
+            match tokio::time::timeout(deadline, stream.write(&data[written..])).await {
+                Ok(Ok(0)) => return Err(std::io::ErrorKind::WriteZero.into()),
+                Ok(Ok(n)) => written += n,
+                Ok(Err(e)) => return Err(e),
+                Err(_) => {
+                    log::warn!(
+                        "Client {} write stalled for {:?} with {} bytes pending, disconnecting",
+                        self.client_id,
+                        deadline,
+                        data.len() - written
+                    );
+                    // Shutting down a secure stream writes too, so it is bounded as well
+                    close_writer(stream).await;
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "socket write stalled",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the client to another framebuffer and sends it in full.
+    ///
+    /// If the new framebuffer has a different size, the client is told through the
+    /// `DesktopSize` pseudo-encoding. Clients that didn't advertise it stay where they are.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the client was switched, `Ok(false)` if it cannot be resized.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if sending the new size fails.
+    async fn switch_framebuffer(
+        &mut self,
+        framebuffer: Framebuffer,
+    ) -> Result<bool, std::io::Error> {
+        let (width, height) = self
+            .config
+            .updates
+            .transform
+            .size(framebuffer.width(), framebuffer.height());
+        let resized = (width, height) != self.screen_size();
+        if resized && !self.supports_desktop_size().await {
+            log::warn!(
+                "Client {} does not support DesktopSize, cannot switch to a {}x{} framebuffer",
+                self.client_id,
+                width,
+                height
+            );
+            return Ok(false);
+        }
+
+        self.framebuffer
+            .unregister_receiver(&self.modified_regions)
+            .await;
+        framebuffer.register_receiver(self.damage_receiver()).await;
+        self.framebuffer = framebuffer;
+        self.framebuffer_size = (width, height);
+
+        let full = DirtyRegion::new(0, 0, width, height);
+        *self.copy_region.write().await = Region::new();
+        *self.copy_offset.write().await = None;
+        *self.modified_regions.write().await = DamageQueue::from(full);
+        if resized {
+            *self.requested_region.write().await = Some(full);
+            self.continuous_region = self.continuous_region.map(|_| full);
+            self.send_desktop_size(
+                width,
+                height,
+                DESKTOP_SIZE_REASON_SERVER,
+                ResizeStatus::Success,
+            )
+            .await?;
+        }
+
+        #[cfg(feature = "debug-logging")]
+        info!(
+            "Client {} switched to a {width}x{height} framebuffer",
+            self.client_id
+        );
+        Ok(true)
+    }
+
+    /// Brings the client up to date after the framebuffer was resized.
+    ///
+    /// Clients that advertised the `DesktopSize` pseudo-encoding are sent the new size;
+    /// the others are handled according to `UpdateConfig::resize_policy`. Either way the
+    /// whole framebuffer is sent again.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(false)` if the client must be disconnected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if sending the new size fails.
+    async fn follow_resize(&mut self) -> Result<bool, std::io::Error> {
+        let (width, height) = self.screen_size();
+        if (width, height) == self.framebuffer_size {
+            return Ok(true);
+        }
+        self.framebuffer_size = (width, height);
+
+        let supports_desktop_size = self.supports_desktop_size().await;
+        if !supports_desktop_size && self.config.updates.resize_policy == ResizePolicy::Disconnect {
+            log::info!(
+                "Disconnecting client {}: framebuffer resized to {width}x{height} and the client does not support DesktopSize",
+                self.client_id
+            );
+            return Ok(false);
+        }
+
+        let full = DirtyRegion::new(0, 0, width, height);
+        *self.copy_region.write().await = Region::new();
+        *self.copy_offset.write().await = None;
+        *self.modified_regions.write().await = DamageQueue::from(full);
+        if supports_desktop_size {
+            *self.requested_region.write().await = Some(full);
+            self.continuous_region = self.continuous_region.map(|_| full);
+            self.send_desktop_size(
+                width,
+                height,
+                DESKTOP_SIZE_REASON_SERVER,
+                ResizeStatus::Success,
+            )
+            .await?;
+        } else {
+            // The client keeps its size; its update requests limit what it is sent
+            let mut requested = self.requested_region.write().await;
+            *requested = requested.and_then(|region| region.clamp_to(width, height));
+            self.continuous_region = self
+                .continuous_region
+                .map(|area| area.clamp_to(width, height).unwrap_or(full));
+        }
+        Ok(true)
+    }
+
+    /// Switches the client to another framebuffer, keeping the placeholder on screen if
+    /// the client is paused.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the client cannot be resized to the framebuffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if telling the client about a size change fails.
+    async fn show_framebuffer(&mut self, framebuffer: Framebuffer) -> Result<bool, std::io::Error> {
+        if self.paused_framebuffer.is_none() {
+            return self.switch_framebuffer(framebuffer).await;
+        }
+        let placeholder = self
+            .placeholder_framebuffer(framebuffer.width(), framebuffer.height())
+            .await;
+        let switched = self.switch_framebuffer(placeholder).await?;
+        if switched {
+            self.paused_framebuffer = Some(framebuffer);
+        }
+        Ok(switched)
+    }
+
+    /// Pauses the client's view, replacing the framebuffer with the configured
+    /// placeholder, or resumes it. Input from a paused client is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if telling the client about a size change fails.
+    pub(crate) async fn set_paused(&mut self, paused: bool) -> Result<(), std::io::Error> {
+        if paused == self.paused_framebuffer.is_some() {
+            return Ok(());
+        }
+
+        if paused {
+            let real = self.framebuffer.clone();
+            let placeholder = self
+                .placeholder_framebuffer(real.width(), real.height())
+                .await;
+            self.switch_framebuffer(placeholder).await?;
+            self.paused_framebuffer = Some(real);
+        } else if let Some(real) = self.paused_framebuffer.take() {
+            if !self.switch_framebuffer(real.clone()).await? {
+                // The real framebuffer was resized and the client can't follow
+                self.paused_framebuffer = Some(real);
+                return Ok(());
+            }
+        }
+
+        #[cfg(feature = "debug-logging")]
+        info!(
+            "Client {} {}",
+            self.client_id,
+            if paused { "paused" } else { "resumed" }
+        );
+        Ok(())
+    }
+
+    /// Creates a framebuffer showing the configured placeholder.
+    async fn placeholder_framebuffer(&self, width: u16, height: u16) -> Framebuffer {
+        let framebuffer = Framebuffer::new(width, height);
+        // Rendered the way the client sees the screen, then turned back so the transform
+        // shows it upright
+        let transform = self.config.updates.transform;
+        let (screen_width, screen_height) = transform.size(width, height);
+        let pixels = transform.inverse().apply(
+            self.config
+                .updates
+                .placeholder
+                .render(screen_width, screen_height),
+            screen_width,
+            screen_height,
+        );
+        if let Err(e) = framebuffer.update_from_slice(&pixels).await {
+            error!("Failed to render placeholder: {e}");
+        }
+        framebuffer
+    }
+
+    /// Tells the client the desktop was renamed with a `DesktopName` pseudo-rectangle.
+    ///
+    /// For clients that haven't advertised the pseudo-encoding, the name is kept and sent
+    /// if they advertise it later.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the write fails or stalls.
+    async fn set_desktop_name(&mut self, name: String) -> Result<(), std::io::Error> {
+        if !self.encodings.read().await.contains(&ENCODING_DESKTOP_NAME) {
+            self.pending_desktop_name = Some(name);
+            return Ok(());
+        }
+
+        let mut message = BytesMut::with_capacity(20 + name.len());
+        message.put_u8(SERVER_MSG_FRAMEBUFFER_UPDATE);
+        message.put_u8(0); // padding
+        message.put_u16(1); // number of rectangles
+        Rectangle {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            encoding: ENCODING_DESKTOP_NAME,
+        }
+        .write_header(&mut message);
+        #[allow(clippy::cast_possible_truncation)] // Names are far below 4 GB
+        message.put_u32(name.len() as u32);
+        message.put_slice(name.as_bytes());
+
+        let _lock = self.send_mutex.lock().await;
+        let mut stream = self.write_stream.lock().await;
+        self.write_with_watchdog(&mut stream, &message).await
+    }
+
+    /// Sets the lock key LED state to report once the client advertises an LED state
+    /// pseudo-encoding. Later changes arrive as [`ClientCommand::SetLedState`].
+    pub(crate) fn set_led_state(&mut self, state: Option<LedState>) {
+        self.led_state = state;
+    }
+
+    /// Returns whether the client advertised either LED state pseudo-encoding.
+    async fn supports_led_state(&self) -> bool {
+        let encodings = self.encodings.read().await;
+        encodings.contains(&ENCODING_QEMU_LED_STATE)
+            || encodings.contains(&ENCODING_VMWARE_LED_STATE)
+    }
+
+    /// Tells the client the lock key LED state, in the QEMU format if it advertised that,
+    /// otherwise in the `VMware` one. Nothing is sent before the state is known or to
+    /// clients that support neither.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the write fails or stalls.
+    async fn send_led_state(&self) -> Result<(), std::io::Error> {
+        let Some(state) = self.led_state else {
+            return Ok(());
+        };
+        let encoding = {
+            let encodings = self.encodings.read().await;
+            if encodings.contains(&ENCODING_QEMU_LED_STATE) {
+                ENCODING_QEMU_LED_STATE
+            } else if encodings.contains(&ENCODING_VMWARE_LED_STATE) {
+                ENCODING_VMWARE_LED_STATE
+            } else {
+                return Ok(());
+            }
+        };
+
+        let mut message = BytesMut::with_capacity(20);
+        message.put_u8(SERVER_MSG_FRAMEBUFFER_UPDATE);
+        message.put_u8(0); // padding
+        message.put_u16(1); // number of rectangles
+        Rectangle {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            encoding,
+        }
+        .write_header(&mut message);
+        if encoding == ENCODING_QEMU_LED_STATE {
+            message.put_u8(state.bits());
+        } else {
+            message.put_u32(u32::from(state.bits()));
+        }
+
+        let _lock = self.send_mutex.lock().await;
+        let mut stream = self.write_stream.lock().await;
+        self.write_with_watchdog(&mut stream, &message).await
+    }
+
+    /// Sends an `EndOfContinuousUpdates` message.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the write fails or stalls.
+    async fn send_end_of_continuous_updates(&self) -> Result<(), std::io::Error> {
+        let _lock = self.send_mutex.lock().await;
+        let mut stream = self.write_stream.lock().await;
+        self.write_with_watchdog(&mut stream, &[SERVER_MSG_END_OF_CONTINUOUS_UPDATES])
+            .await
+    }
+
+    /// Sends the next blocks of a file the client is downloading.
+    #[cfg(feature = "file-transfer")]
+    async fn send_file_packets(&mut self) -> Result<(), std::io::Error> {
+        // 32 blocks of 8 KB per tick is about 16 MB/s at the default tick rate
+        const PACKETS_PER_TICK: usize = 32;
+
+        let Some(transfer) = self.file_transfer.as_mut().filter(|t| t.is_sending()) else {
+            return Ok(());
+        };
+        let outcome = transfer.next_packets(PACKETS_PER_TICK).await;
+        self.send_file_transfer(outcome).await
+    }
+
+    /// Sends file transfer replies and records a completed transfer in the audit log.
+    #[cfg(feature = "file-transfer")]
+    async fn send_file_transfer(
+        &self,
+        outcome: file_transfer::Outcome,
+    ) -> Result<(), std::io::Error> {
+        match outcome.transferred {
+            Some(Transferred::Sent { path, bytes }) => {
+                log::info!(
+                    "Client {} downloaded {} ({bytes} bytes)",
+                    self.client_id,
+                    path.display()
+                );
+                self.audit_event(AuditEvent::FileSent {
+                    path: path.display().to_string(),
+                    bytes,
+                });
+            }
+            Some(Transferred::Received { path, bytes }) => {
+                log::info!(
+                    "Client {} uploaded {} ({bytes} bytes)",
+                    self.client_id,
+                    path.display()
+                );
+                self.audit_event(AuditEvent::FileReceived {
+                    path: path.display().to_string(),
+                    bytes,
+                });
+            }
+            None => {}
+        }
+        if outcome.replies.is_empty() {
+            return Ok(());
+        }
+
+        let mut message = BytesMut::new();
+        for reply in &outcome.replies {
+            reply.write_to(&mut message);
+        }
+        let _lock = self.send_mutex.lock().await;
+        let mut stream = self.write_stream.lock().await;
+        self.write_with_watchdog(&mut stream, &message).await
+    }
+
+    /// Handles a gii message: registers and removes the client's input devices and
+    /// delivers their events.
+    async fn handle_gii(
+        &mut self,
+        subtype: u8,
+        body: &[u8],
+        big_endian: bool,
+    ) -> Result<(), std::io::Error> {
+        // Registrations beyond this are refused
+        const MAX_GII_DEVICES: usize = 32;
+
+        match subtype {
+            gii::SUBTYPE_DEVICE_CREATION => {
+                let origin = match gii::parse_device(body, big_endian) {
+                    Some(mut device) if self.gii_devices.len() < MAX_GII_DEVICES => {
+                        self.next_gii_origin = self.next_gii_origin.wrapping_add(1).max(1);
+                        device.origin = self.next_gii_origin;
+                        self.gii_devices.push(device.origin);
+                        #[cfg(feature = "debug-logging")]
+                        info!("gii device {} created: {:?}", device.origin, device.name);
+                        let origin = device.origin;
+                        let _ = self.event_tx.send(ClientEvent::ExtendedInput {
+                            event: GiiEvent::DeviceCreated { device },
+                        });
+                        origin
+                    }
+                    _ => 0, // Tells the client the device was refused
+                };
+                self.send_gii(gii::SUBTYPE_DEVICE_CREATION, &origin.to_be_bytes())
+                    .await?;
+            }
+            gii::SUBTYPE_DEVICE_DESTRUCTION => {
+                let Some(origin) = gii::parse_destruction(body, big_endian) else {
+                    return Ok(());
+                };
+                if let Some(index) = self.gii_devices.iter().position(|&o| o == origin) {
+                    self.gii_devices.remove(index);
+                    let _ = self.event_tx.send(ClientEvent::ExtendedInput {
+                        event: GiiEvent::DeviceDestroyed { origin },
+                    });
+                }
+            }
+            gii::SUBTYPE_EVENTS => {
+                if !self.accepts_input() {
+                    return Ok(());
+                }
+                for event in gii::parse_events(body, big_endian) {
+                    if self.gii_devices.contains(&event.origin()) {
+                        let _ = self.event_tx.send(ClientEvent::ExtendedInput { event });
+                    }
+                }
+            }
+            // There is only one version, so the client's choice needs no answer
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Sends a big-endian gii message.
+    async fn send_gii(&self, subtype: u8, payload: &[u8]) -> Result<(), std::io::Error> {
+        let mut message = BytesMut::with_capacity(4 + payload.len());
+        message.put_u8(MSG_GII);
+        message.put_u8(gii::BIG_ENDIAN_FLAG | subtype);
+        #[allow(clippy::cast_possible_truncation)] // Payloads are a few bytes
+        message.put_u16(payload.len() as u16);
+        message.put_slice(payload);
+
+        let _lock = self.send_mutex.lock().await;
+        let mut stream = self.write_stream.lock().await;
+        self.write_with_watchdog(&mut stream, &message).await
+    }
+
+    /// Returns whether the client can be told about framebuffer size changes.
+    async fn supports_desktop_size(&self) -> bool {
+        let encodings = self.encodings.read().await;
+        encodings.contains(&ENCODING_EXTENDED_DESKTOP_SIZE)
+            || encodings.contains(&ENCODING_DESKTOP_SIZE)
+    }
+
+    /// Tells the client the framebuffer size with an `ExtendedDesktopSize`
+    /// pseudo-rectangle, or a `DesktopSize` one if it only supports that.
+    ///
+    /// The screen layout is always a single screen covering the framebuffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The framebuffer width.
+    /// * `height` - The framebuffer height.
+    /// * `reason` - Why the size is sent (`DESKTOP_SIZE_REASON_*`); `ExtendedDesktopSize` only.
+    /// * `status` - The outcome of the client's request; `ExtendedDesktopSize` only.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the write fails or stalls.
+    async fn send_desktop_size(
+        &self,
+        width: u16,
+        height: u16,
+        reason: u16,
+        status: ResizeStatus,
+    ) -> Result<(), std::io::Error> {
+        let extended = self
+            .encodings
+            .read()
+            .await
+            .contains(&ENCODING_EXTENDED_DESKTOP_SIZE);
+        if !extended && reason == DESKTOP_SIZE_REASON_CLIENT {
+            // Only ExtendedDesktopSize can answer a request
+            return Ok(());
+        }
+
+        let mut message = BytesMut::with_capacity(36);
+        message.put_u8(SERVER_MSG_FRAMEBUFFER_UPDATE);
+        message.put_u8(0); // padding
+        message.put_u16(1); // number of rectangles
+        if extended {
+            Rectangle {
+                x: reason,
+                y: status.code(),
+                width,
+                height,
+                encoding: ENCODING_EXTENDED_DESKTOP_SIZE,
+            }
+            .write_header(&mut message);
+            message.put_u8(1); // number of screens
+            message.put_bytes(0, 3); // padding
+            message.put_u32(0); // screen ID
+            message.put_u16(0); // x
+            message.put_u16(0); // y
+            message.put_u16(width);
+            message.put_u16(height);
+            message.put_u32(0); // flags
+        } else {
+            Rectangle {
+                x: 0,
+                y: 0,
+                width,
+                height,
+                encoding: ENCODING_DESKTOP_SIZE,
+            }
+            .write_header(&mut message);
+        }
+
+        let _lock = self.send_mutex.lock().await;
+        let mut stream = self.write_stream.lock().await;
+        self.write_with_watchdog(&mut stream, &message).await
+    }
+
+    /// Returns the memory currently attributable to this client.
+    ///
+    /// # Arguments
+    ///
+    /// * `receive_buffer` - The message loop's buffer of unprocessed client data.
+    async fn memory_usage(&self, receive_buffer: &BytesMut) -> ClientMemoryUsage {
+        let region_size = std::mem::size_of::<DirtyRegion>();
+        let queued_regions = {
+            let damage = self.modified_regions.read().await;
+            damage.region().rects().len() + damage.ages().len()
+        } + self.copy_region.read().await.rects().len();
+        let queued_rects: usize = self
+            .pending_rects
+            .iter()
+            .map(|(_, payload)| std::mem::size_of::<Rectangle>() + payload.capacity())
+            .sum();
+
+        let zlib_streams = self.tight_zlib_streams.read().await.initialized_streams()
+            + self.encode_streams.initialized();
+
+        ClientMemoryUsage {
+            receive_buffer: receive_buffer.capacity(),
+            send_buffer: self.send_buffer_capacity,
+            queued_damage: queued_regions * region_size + queued_rects,
+            clipboard: self.pending_cut_text.as_ref().map_or(0, String::capacity),
+            compressors: zlib_streams * ZLIB_STREAM_MEMORY,
+        }
+    }
+
+    /// Keeps the client within `ConnectionConfig::max_client_memory`.
+    ///
+    /// When the client is over the cap, memory that can be given back without breaking the
+    /// session is released first: an empty receive buffer is shrunk, the send buffer is
+    /// forgotten, held-back clipboard text is dropped and queued damage is collapsed into
+    /// its bounding box. If that isn't enough, the client is disconnected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the client is still over the cap after eviction.
+    async fn enforce_memory_cap(
+        &mut self,
+        receive_buffer: &mut BytesMut,
+    ) -> Result<(), std::io::Error> {
+        let cap = self.config.connection.max_client_memory;
+        if cap == 0 || self.memory_usage(receive_buffer).await.total() <= cap {
+            return Ok(());
+        }
+
+        if receive_buffer.is_empty() {
+            *receive_buffer = BytesMut::with_capacity(4096);
+        }
+        self.send_buffer_capacity = 0;
+        self.pending_cut_text = None;
+        self.modified_regions.write().await.coarsen();
+
+        let usage = self.memory_usage(receive_buffer).await;
+        if usage.total() <= cap {
+            log::warn!(
+                "Client {} exceeded memory cap of {} bytes, released buffers",
+                self.client_id,
+                cap
+            );
+            return Ok(());
+        }
+
+        log::warn!(
+            "Client {} uses {} bytes, over the memory cap of {} bytes, disconnecting: {:?}",
+            self.client_id,
+            usage.total(),
+            cap,
+            usage
+        );
+        Err(std::io::Error::new(
+            std::io::ErrorKind::OutOfMemory,
+            "client memory cap exceeded",
+        ))
+    }
+
+    /// Sets the histograms encode and send durations are recorded into.
+    pub(crate) fn set_encode_stats(&mut self, stats: Arc<EncodeStats>) {
+        self.encode_stats = stats;
+    }
+
+    /// Sets the encoder threads this client's rectangles are encoded on.
+    pub(crate) fn set_encode_pool(&mut self, pool: Option<Arc<EncodePool>>) {
+        self.encode_pool = pool;
+        self.encode_permits =
+            tokio::sync::Semaphore::new(self.config.encoding.max_jobs_per_client.max(1));
+    }
+
+    /// Sets the audit log this client's clipboard transfers are recorded in.
+    pub(crate) fn set_audit_log(&mut self, audit: Option<AuditLog>) {
+        self.audit = audit;
+    }
+
+    /// Sets the store this client's session is kept in when it disconnects.
+    pub(crate) fn set_session_store(&mut self, sessions: Arc<SessionStore>) {
+        self.sessions = Some(sessions);
+    }
+
+    /// Queues the session token for the next update, if session resume is enabled.
+    fn send_session_token(&mut self) {
+        if self.sessions.is_none() || self.config.connection.session_resume_timeout.is_zero() {
+            return;
+        }
+        let token = *self
+            .session_token
+            .get_or_insert_with(SessionStore::new_token);
+        let rect = Rectangle {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            encoding: ENCODING_SESSION_RESUME,
+        };
+        self.pending_rects.push((rect, token.to_vec()));
+    }
+
+    /// Resumes the session suspended under `token`, if it showed the same desktop, and
+    /// sends the client the token of its session.
+    async fn resume_session(&mut self, token: SessionToken) {
+        let timeout = self.config.connection.session_resume_timeout;
+        let session = match &self.sessions {
+            Some(sessions) if !timeout.is_zero() => sessions.resume(&token, timeout),
+            _ => None,
+        };
+        match session {
+            Some(session)
+                if self.paused_framebuffer.is_none()
+                    && session.framebuffer.same_as(&self.framebuffer) =>
+            {
+                // The suspended list was registered with the framebuffer before ours, so
+                // damage reaches it first; locking ours first means none is lost here
+                let mut regions = self.modified_regions.write().await;
+                regions.clone_from(&*session.damage.read().await);
+                drop(regions);
+                self.jpeg_quality_override = session.jpeg_quality_override;
+                self.compression_override = session.compression_override;
+                self.color_mode_override = session.color_mode_override;
+                self.update_interval_override = session.update_interval_override;
+                self.session_token = Some(token);
+                self.resumed = true;
+                log::info!(
+                    "Client {} resumed a session suspended {:?} ago",
+                    self.client_id,
+                    session.suspended_at.elapsed()
+                );
+            }
+            _ => log::info!(
+                "Client {} could not resume its session (unknown, expired or on another desktop)",
+                self.client_id
+            ),
+        }
+        self.send_session_token();
+    }
+
+    /// Keeps the session of a disconnected client that was sent a session token, so a
+    /// reconnecting viewer can resume it.
+    ///
+    /// The damage queue stays registered with the framebuffer; everything the viewer may
+    /// not have received is added to it.
+    async fn suspend_session(&mut self) {
+        let timeout = self.config.connection.session_resume_timeout;
+        let (Some(sessions), Some(token)) = (&self.sessions, self.session_token) else {
+            return;
+        };
+        // A paused client shows the placeholder, not the desktop
+        if timeout.is_zero() || self.paused_framebuffer.is_some() {
+            return;
+        }
+
+        let receiver = self.damage_receiver();
+        let copied = self.copy_region.read().await.rects().to_vec();
+        let unseen = copied
+            .into_iter()
+            .chain(self.unacknowledged.iter().flatten().copied())
+            .chain(self.pending_full_refresh);
+        for region in unseen {
+            receiver.add_dirty_region(region).await;
+        }
+
+        sessions.suspend(
+            token,
+            SuspendedSession {
+                framebuffer: self.framebuffer.clone(),
+                damage: self.modified_regions.clone(),
+                jpeg_quality_override: self.jpeg_quality_override,
+                compression_override: self.compression_override,
+                color_mode_override: self.color_mode_override,
+                update_interval_override: self.update_interval_override,
+                suspended_at: Instant::now(),
+            },
+            timeout,
+        );
+        #[cfg(feature = "debug-logging")]
+        info!("Client {} session suspended", self.client_id);
+    }
+
+    /// Runs CPU-heavy encoding work on the encoder threads, or inline if there are none.
+    async fn run_encode<R, F>(&self, job: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce() -> R + Send + 'static,
+    {
+        let Some(pool) = &self.encode_pool else {
+            return job();
+        };
+        // The semaphore is never closed, so acquiring only fails if it was
+        let _permit = self.encode_permits.acquire().await;
+        pool.run(job).await
+    }
+
+    /// Returns a sender for queueing commands to this client's message loop.
+    pub fn command_sender(&self) -> mpsc::UnboundedSender<ClientCommand> {
+        self.command_tx.clone()
+    }
+
+    /// Returns the unique client ID assigned by the server.
+    pub fn get_client_id(&self) -> usize {
+        self.client_id
+    }
+
+    /// Returns a clone of the Arc containing the write half of the stream.
+    ///
+    /// This allows external code to close the write half directly for shutdown,
+    /// which will cause reads on the read half to fail naturally.
+    pub fn get_write_stream_handle(&self) -> Arc<tokio::sync::Mutex<ClientWriter>> {
+        self.write_stream.clone()
+    }
+
+    /// Returns the remote host address of the connected client.
+    pub fn get_remote_host(&self) -> &str {
+        &self.remote_host
+    }
+
+    /// Returns the subject of the client's verified TLS certificate (e.g.
+    /// `CN=kiosk-3,O=Example`), or `None` if it did not present one.
+    pub fn certificate_subject(&self) -> Option<&str> {
+        self.certificate_subject.as_deref()
+    }
+
+    /// Returns whether the client is view-only: its keyboard, pointer and clipboard input
+    /// and its resize requests are dropped (see `ConnectionConfig::approver`).
+    pub fn is_view_only(&self) -> bool {
+        self.view_only
+    }
+
+    /// Returns the destination port for repeater connections.
+    /// Returns -1 for direct connections (not using a repeater).
+    pub fn get_destination_port(&self) -> i32 {
+        self.destination_port.map_or(-1, i32::from)
+    }
+
+    /// Returns the repeater ID if this client is connected via a repeater.
+    /// Returns None for direct connections.
+    pub fn get_repeater_id(&self) -> Option<&str> {
+        self.repeater_id.as_deref()
+    }
+
+    /// Sets the connection metadata for reverse connections.
+    pub fn set_connection_metadata(&mut self, destination_port: Option<u16>) {
+        self.destination_port = destination_port;
+    }
+
+    /// Sets the repeater metadata for repeater connections.
+    pub fn set_repeater_metadata(&mut self, repeater_id: String, destination_port: Option<u16>) {
+        self.repeater_id = Some(repeater_id);
+        self.destination_port = destination_port;
+    }
+}
+
+/// Returns the order in which to drain queued dirty regions so the most interactive
+/// ones go first.
+///
+/// Regions within `radius` pixels of the pointer come first, then the remaining regions;
+/// within each group, the oldest regions (those queued first) come first.
+fn prioritized_order(
+    regions: &[DirtyRegion],
+    pointer: Option<(u16, u16)>,
+    radius: u16,
+) -> Vec<usize> {
+    let near_pointer =
+        |region: &DirtyRegion| pointer.is_some_and(|(x, y)| region.distance_to(x, y) <= radius);
+    let mut order: Vec<usize> = (0..regions.len()).collect();
+    order.sort_by_key(|&index| !near_pointer(&regions[index]));
+    order
+}
+
+/// Waits until damage is signaled on `wakeup`, or for at most `timeout` unless it is zero.
+async fn wait_for_damage(wakeup: &Notify, timeout: Duration) {
+    if timeout.is_zero() {
+        wakeup.notified().await;
+    } else {
+        let _ = tokio::time::timeout(timeout, wakeup.notified()).await;
+    }
+}
+
+/// Checks a `SetDesktopSize` screen layout: at least one screen, each non-empty and
+/// inside the requested framebuffer, with distinct IDs.
+fn valid_screen_layout(width: u16, height: u16, screens: &[Screen]) -> bool {
+    !screens.is_empty()
+        && width > 0
+        && height > 0
+        && screens.iter().enumerate().all(|(index, screen)| {
+            screen.width > 0
+                && screen.height > 0
+                && u32::from(screen.x) + u32::from(screen.width) <= u32::from(width)
+                && u32::from(screen.y) + u32::from(screen.height) <= u32::from(height)
+                && screens[..index].iter().all(|other| other.id != screen.id)
+        })
+}
+
+/// Waits for the application's answer to a `SetDesktopSize` request.
+///
+/// A dropped reply handle counts as a rejection with [`ResizeStatus::Prohibited`].
+async fn wait_for_resize_outcome(
+    pending: Option<&mut oneshot::Receiver<ResizeOutcome>>,
+) -> ResizeOutcome {
+    match pending {
+        Some(outcome_rx) => outcome_rx
+            .await
+            .unwrap_or(ResizeOutcome::Rejected(ResizeStatus::Prohibited)),
+        None => std::future::pending().await,
+    }
+}
+
+/// Runs one handshake phase, dropping the connection if it takes longer than `timeout`
+/// (unless zero) and reporting the timeout as a `HandshakeTimeout` event.
+async fn handshake_phase<T>(
+    phase: HandshakePhase,
+    timeout: Duration,
+    remote_host: &str,
+    event_tx: &mpsc::UnboundedSender<ClientEvent>,
+    step: impl std::future::Future<Output = Result<T, std::io::Error>>,
+) -> Result<T, std::io::Error> {
+    if timeout.is_zero() {
+        return step.await;
+    }
+    if let Ok(result) = tokio::time::timeout(timeout, step).await {
+        return result;
     }
+    log::warn!(
+        "Client {remote_host} exceeded the {} handshake timeout of {timeout:?}",
+        phase.name()
+    );
+    let _ = event_tx.send(ClientEvent::HandshakeTimeout {
+        address: remote_host.to_string(),
+        phase,
+    });
+    Err(std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        format!("{} handshake phase timed out", phase.name()),
+    ))
 }
 
 /// Ensures proper cleanup when `VncClient` is dropped.
 ///
-/// When `VncClient` is dropped, the read half of the TCP stream (`read_stream: OwnedReadHalf`)
-/// is automatically closed because it's an owned field. This completes the client disconnect
+/// When `VncClient` is dropped, the read half of the stream (`read_stream: ClientReader`)
+/// is automatically dropped because it's an owned field. This completes the client disconnect
 /// sequence after the write half has been closed separately during shutdown.
 ///
 /// The log message helps diagnose the shutdown sequence by confirming when `VncClient`
-/// objects are actually being dropped and their TCP read streams are closing.
+/// objects are actually being dropped and their read streams are closing.
 impl Drop for VncClient {
     fn drop(&mut self) {
         #[cfg(feature = "debug-logging")]
@@ -0,0 +1,212 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Host clipboard bridge.
+//!
+//! Keeps the clipboard of the machine running the server in sync with connected
+//! clients: clipboard text from clients is written to the host clipboard, and text
+//! copied on the host is sent to all clients. Requires the `clipboard` feature, which
+//! uses [`arboard`] for clipboard access.
+//!
+//! The bridge sits between the server and the application's event loop. It watches the
+//! event stream for `CutText` events and passes every event through unchanged:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use rustvncserver::clipboard::{self, ClipboardBridgeConfig};
+//! use rustvncserver::VncServer;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let (server, events) = VncServer::new(1280, 720, "Desktop".to_string(), None);
+//! let server = Arc::new(server);
+//! let mut events = clipboard::bridge(server.clone(), events, ClipboardBridgeConfig::default())?;
+//! while let Some(event) = events.recv().await {
+//!     // Handle input events as usual
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Text is never bounced back to where it came from: the bridge remembers the last text
+//! it synchronized and ignores a client sending it back, or the host clipboard still
+//! holding it, so viewers that echo the server's clipboard don't cause loops.
+
+use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::server::{ServerEvent, VncServer};
+
+/// Which way clipboard text flows between clients and the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClipboardSync {
+    /// Synchronize in both directions.
+    #[default]
+    Bidirectional,
+    /// Only write client clipboard text to the host clipboard.
+    ClientToHost,
+    /// Only send host clipboard changes to clients.
+    HostToClient,
+    /// Don't synchronize; events are still passed through.
+    Disabled,
+}
+
+impl ClipboardSync {
+    fn client_to_host(self) -> bool {
+        matches!(self, Self::Bidirectional | Self::ClientToHost)
+    }
+
+    fn host_to_client(self) -> bool {
+        matches!(self, Self::Bidirectional | Self::HostToClient)
+    }
+}
+
+/// Settings for the host clipboard bridge.
+#[derive(Debug, Clone)]
+pub struct ClipboardBridgeConfig {
+    /// Direction of synchronization. Default: `Bidirectional`.
+    pub sync: ClipboardSync,
+    /// How often the host clipboard is checked for changes. Default: 500 ms.
+    pub poll_interval: Duration,
+}
+
+impl Default for ClipboardBridgeConfig {
+    fn default() -> Self {
+        Self {
+            sync: ClipboardSync::Bidirectional,
+            poll_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Starts bridging the host clipboard with the clients of `server`.
+///
+/// Clipboard access is blocking, so it runs on a dedicated thread. The bridge stops when
+/// the server's event channel closes or the returned receiver is dropped.
+///
+/// # Arguments
+///
+/// * `server` - The server whose clients are synchronized.
+/// * `events` - The event receiver returned by `VncServer::new`.
+/// * `config` - Direction and polling settings.
+///
+/// # Returns
+///
+/// A receiver yielding every server event, including the `CutText` events the bridge
+/// handled, for the application to process as before.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if the host clipboard cannot be opened (for example
+/// when no display server is available).
+pub fn bridge(
+    server: Arc<VncServer>,
+    mut events: mpsc::UnboundedReceiver<ServerEvent>,
+    config: ClipboardBridgeConfig,
+) -> Result<mpsc::UnboundedReceiver<ServerEvent>, std::io::Error> {
+    let (to_host_tx, to_host_rx) = std_mpsc::channel::<String>();
+    let (from_host_tx, mut from_host_rx) = mpsc::unbounded_channel::<String>();
+    let (ready_tx, ready_rx) = std_mpsc::channel();
+
+    std::thread::Builder::new()
+        .name("clipboard-bridge".to_string())
+        .spawn(move || host_clipboard_thread(&config, &to_host_rx, &from_host_tx, &ready_tx))?;
+    ready_rx
+        .recv()
+        .map_err(|_| std::io::Error::other("Clipboard thread exited during startup"))??;
+
+    // Host changes reach clients through a weak reference, so the bridge doesn't keep
+    // the server (and with it the event channel that ends the bridge) alive
+    let weak_server: Weak<VncServer> = Arc::downgrade(&server);
+    drop(server);
+    tokio::spawn(async move {
+        while let Some(text) = from_host_rx.recv().await {
+            let Some(server) = weak_server.upgrade() else {
+                break;
+            };
+            let _ = server.send_cut_text_to_all(text).await;
+        }
+    });
+
+    let (out_tx, out_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if let ServerEvent::CutText { text, .. } = &event {
+                let _ = to_host_tx.send(text.clone());
+            }
+            if out_tx.send(event).is_err() {
+                break;
+            }
+        }
+        // Dropping `to_host_tx` stops the clipboard thread
+    });
+
+    Ok(out_rx)
+}
+
+/// Owns the host clipboard: applies client text and polls for host changes.
+fn host_clipboard_thread(
+    config: &ClipboardBridgeConfig,
+    to_host: &std_mpsc::Receiver<String>,
+    from_host: &mpsc::UnboundedSender<String>,
+    ready: &std_mpsc::Sender<Result<(), std::io::Error>>,
+) {
+    let mut clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => {
+            let _ = ready.send(Ok(()));
+            clipboard
+        }
+        Err(e) => {
+            let _ = ready.send(Err(std::io::Error::other(e)));
+            return;
+        }
+    };
+
+    // The text last synchronized in either direction; seeing it again is an echo
+    let mut last_synced = clipboard.get_text().ok();
+    let poll_interval = config.poll_interval.max(Duration::from_millis(10));
+
+    loop {
+        match to_host.recv_timeout(poll_interval) {
+            Ok(text) => {
+                if !config.sync.client_to_host() || last_synced.as_deref() == Some(text.as_str()) {
+                    continue;
+                }
+                if let Err(e) = clipboard.set_text(text.clone()) {
+                    log::warn!("Failed to write host clipboard: {e}");
+                    continue;
+                }
+                last_synced = Some(text);
+            }
+            Err(std_mpsc::RecvTimeoutError::Timeout) => {
+                if !config.sync.host_to_client() {
+                    continue;
+                }
+                // Non-text contents and transient access errors are not changes
+                let Ok(text) = clipboard.get_text() else {
+                    continue;
+                };
+                if last_synced.as_deref() == Some(text.as_str()) {
+                    continue;
+                }
+                last_synced = Some(text.clone());
+                if from_host.send(text).is_err() {
+                    return;
+                }
+            }
+            Err(std_mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
@@ -0,0 +1,892 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Server configuration.
+//!
+//! [`ServerConfig`] collects the tunable limits and policies of the server. All fields
+//! have sensible defaults, so applications only need to override what they care about:
+//!
+//! ```
+//! use rustvncserver::config::{ClipboardOversizePolicy, ServerConfig};
+//! use rustvncserver::VncServer;
+//!
+//! let (mut server, _events) = VncServer::new(800, 600, "Desktop".to_string(), None);
+//! let mut config = ServerConfig::default();
+//! config.clipboard.max_inbound_bytes = 1024 * 1024;
+//! config.clipboard.oversize_policy = ClipboardOversizePolicy::Truncate;
+//! server.set_config(config);
+//! ```
+//!
+//! The configuration is read when a client connects; changing it does not affect
+//! clients that are already connected.
+
+use rand::Rng;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::authenticator::ConnectionApprover;
+use crate::framebuffer::DirtyRegion;
+use crate::network::IpNetwork;
+
+/// Configuration for a `VncServer`.
+#[derive(Debug, Clone, Default)]
+pub struct ServerConfig {
+    /// Clipboard (cut text) limits and policies.
+    pub clipboard: ClipboardConfig,
+    /// Framebuffer update scheduling.
+    pub updates: UpdateConfig,
+    /// Connection health limits.
+    pub connection: ConnectionConfig,
+    /// Application-defined protocol extensions.
+    pub extensions: ExtensionConfig,
+    /// Keyboard input normalization.
+    pub input: InputConfig,
+    /// Encoder threads.
+    pub encoding: EncodeConfig,
+    /// Security types offered to clients.
+    pub security: SecurityConfig,
+    /// `UltraVNC` file transfer.
+    pub file_transfer: FileTransferConfig,
+    /// JPEG compression of Tight rectangles.
+    pub jpeg: JpegConfig,
+}
+
+/// JPEG compression of photographic Tight rectangles. Requires the `turbojpeg` feature;
+/// ignored without it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JpegConfig {
+    /// Chroma subsampling. Default: `ChromaSubsampling::Yuv422`.
+    pub subsampling: ChromaSubsampling,
+    /// Use `TurboJPEG`'s fast integer DCT, trading a little quality for speed.
+    /// Default: `false`.
+    pub fast_dct: bool,
+    /// Layout of the pixels handed to `TurboJPEG`. Default: `JpegPixelFormat::Rgbx`.
+    pub pixel_format: JpegPixelFormat,
+    /// Lowest JPEG quality (1-100) used, whatever quality level the client asks for.
+    /// Default: 1.
+    pub min_quality: u8,
+    /// Highest JPEG quality (1-100) used, e.g. to cap bandwidth for clients that ask
+    /// for the best quality. Default: 100.
+    pub max_quality: u8,
+}
+
+impl Default for JpegConfig {
+    fn default() -> Self {
+        Self {
+            subsampling: ChromaSubsampling::Yuv422,
+            fast_dct: false,
+            pixel_format: JpegPixelFormat::Rgbx,
+            min_quality: 1,
+            max_quality: 100,
+        }
+    }
+}
+
+impl JpegConfig {
+    /// Clamps a JPEG quality to the configured range (and to 1-100).
+    #[must_use]
+    pub fn clamp_quality(&self, quality: u8) -> u8 {
+        let max = self.max_quality.clamp(1, 100);
+        quality.clamp(self.min_quality.clamp(1, max), max)
+    }
+}
+
+/// Chroma subsampling of JPEG rectangles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    /// No subsampling: sharpest colored edges, largest output.
+    Yuv444,
+    /// Half horizontal chroma resolution.
+    Yuv422,
+    /// Half horizontal and vertical chroma resolution: smallest color output.
+    Yuv420,
+    /// No chroma at all; rectangles are sent in grayscale.
+    Gray,
+}
+
+/// Layout of the pixels handed to `TurboJPEG`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JpegPixelFormat {
+    /// Pass the framebuffer's 4-byte pixels directly, ignoring the fourth byte.
+    Rgbx,
+    /// Copy the pixels to packed 3-byte RGB first, for `TurboJPEG` builds that are
+    /// faster with packed input.
+    Rgb,
+}
+
+/// `UltraVNC` file transfer, for viewers' file manager and drag-and-drop. Requires the
+/// `file-transfer` feature; ignored without it.
+///
+/// Viewers see `root` as drive `C:` and cannot reach anything outside it. View-only
+/// clients are refused.
+#[derive(Debug, Clone, Default)]
+pub struct FileTransferConfig {
+    /// Directory shared with viewers. Default: `None` (file transfer disabled).
+    pub root: Option<PathBuf>,
+    /// Only allow downloads and directory listings: no uploads, new directories,
+    /// deletions or renames. Default: `false`.
+    pub read_only: bool,
+}
+
+/// Security types offered to clients, in addition to the plain None or VNC
+/// Authentication type chosen by whether the server has a password.
+#[derive(Debug, Clone, Default)]
+#[allow(clippy::struct_excessive_bools)] // Independent security types, each offered or not
+pub struct SecurityConfig {
+    /// Offer `VeNCrypt` anonymous TLS (`TLSNone`, or `TLSVnc` when a password is set), so
+    /// viewers such as `TigerVNC` get an encrypted session without certificates. The key
+    /// exchange is unauthenticated, which protects against eavesdropping but not against an
+    /// active man in the middle. Sessions using it count as encrypted for
+    /// `ConnectionConfig::require_encryption`. Requires the `tls` feature; ignored without
+    /// it. Default: `false`.
+    pub anonymous_tls: bool,
+    /// PEM file with the server's certificate chain. Together with `tls_private_key` this
+    /// offers the `VeNCrypt` X.509 subtypes (`X509None`, or `X509Vnc` when a password is
+    /// set), which count as encrypted like anonymous TLS. The files are read for every
+    /// connection, so renewed certificates take effect without a restart. Requires the
+    /// `tls` feature. Default: `None`.
+    pub tls_certificate: Option<PathBuf>,
+    /// PEM file with the private key of `tls_certificate`. Default: `None`.
+    pub tls_private_key: Option<PathBuf>,
+    /// PEM bundle of the CAs client certificates are verified against. When set, clients
+    /// on the X.509 subtypes are asked for a certificate, and one that doesn't verify
+    /// fails the handshake. The subject of a verified certificate is reported in
+    /// `ServerEvent::ClientConnected`. Default: `None` (no client certificates).
+    pub client_ca: Option<PathBuf>,
+    /// Require every client to present a certificate that verifies against `client_ca`,
+    /// e.g. for kiosks where password authentication is not acceptable. Only the X.509
+    /// subtypes are offered then, to every peer including `encryption_exempt` networks
+    /// and streams from `VncServer::serve_secure_stream`. Default: `false`.
+    pub require_client_certificate: bool,
+    /// Offer the RSA-AES security types used by `RealVNC` Viewer: `RA2`, which encrypts the
+    /// whole session and counts as encrypted for `ConnectionConfig::require_encryption`,
+    /// and `RA2ne`, which only protects the credentials and is offered like the plain
+    /// types. Viewers log in with the server password; any password is accepted when
+    /// none is set. Not offered when `require_client_certificate` is set. Requires the
+    /// `rsa-aes` feature; ignored without it. Default: `false`.
+    pub rsa_aes: bool,
+    /// PEM file with the RSA private key for RSA-AES (PKCS#8 or PKCS#1). Viewers remember
+    /// the key to warn when it changes, so production servers should keep a fixed one.
+    /// Default: `None` (a 2048-bit key is generated on first use and kept until the
+    /// process exits).
+    pub rsa_private_key: Option<PathBuf>,
+    /// Offer SASL authentication through Cyrus SASL, with the mechanisms configured in
+    /// `vnc.conf` in the SASL configuration directory, e.g. `GSSAPI` for Kerberos or
+    /// `PLAIN` checked against PAM by `saslauthd`. The types without authentication are
+    /// no longer offered; with a password set, clients may still use it instead. Inside
+    /// TLS it is offered as the `VeNCrypt` subtypes `TLSSASL` and `X509SASL` (see
+    /// `anonymous_tls` and `tls_certificate`); on its own (security type 20) it is offered
+    /// like the plain types and cleartext password mechanisms are disabled. Requires the `sasl` feature; ignored without it. Default: `false`.
+    pub sasl: bool,
+    /// The SASL service name, which selects the Kerberos principal (`<service>/<host>`).
+    /// Default: `None` (`vnc`).
+    pub sasl_service: Option<String>,
+}
+
+/// Encoder threads.
+///
+/// Unlike the rest of the configuration, the thread pool is shared by all clients; it is
+/// rebuilt by `VncServer::set_config` when these settings change, and clients that are
+/// already connected keep using the previous pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeConfig {
+    /// Number of dedicated encoder threads. With zero, rectangles are encoded on each
+    /// client's Tokio task, sharing the runtime with the application. Default: 0.
+    pub threads: usize,
+    /// Nice value of the encoder threads, from -20 (highest priority) to 19 (lowest),
+    /// so encoding yields to the application under load. Linux and Android only.
+    /// Default: `None` (inherit).
+    pub nice: Option<i32>,
+    /// CPU cores the encoder threads may run on. Linux and Android only.
+    /// Default: empty (any core).
+    pub cpu_affinity: Vec<usize>,
+    /// Maximum number of encode jobs one client may have running on the encoder threads
+    /// at once, so a single busy client can't occupy all of them. Default: 1.
+    pub max_jobs_per_client: usize,
+    /// Memory, in bytes, for encoded rectangles shared between clients. Clients with the
+    /// same pixel format, encoding and quality settings reuse each other's encodings of
+    /// the same rectangle instead of encoding it again. Encodings with a compression
+    /// stream share the data before compression. Tight regions are shared whole while
+    /// more than one client is watching, at the cost of compressing each rectangle on
+    /// its own instead of through the client's streams. Zero disables sharing.
+    /// Default: 16 MiB.
+    pub shared_cache_bytes: usize,
+}
+
+impl Default for EncodeConfig {
+    fn default() -> Self {
+        Self {
+            threads: 0,
+            nice: None,
+            cpu_affinity: Vec::new(),
+            max_jobs_per_client: 1,
+            shared_cache_bytes: 16 << 20,
+        }
+    }
+}
+
+/// Keyboard input normalization, applied to key events before they are emitted as
+/// `ServerEvent::KeyPress` (see [`KeyNormalizer`](crate::keysym::KeyNormalizer)).
+#[derive(Debug, Clone, Default)]
+pub struct InputConfig {
+    /// Report keypad keys as their main keyboard equivalents (`KP_1` as `1`, `KP_Enter`
+    /// as `Return`, `KP_Home` as `Home`, ...). Default: `false`.
+    pub keypad_as_main: bool,
+    /// Combine dead keys with the following key into the accented character
+    /// (`dead_acute`, `e` is reported as `é`). Default: `false`.
+    pub fold_dead_keys: bool,
+    /// Keysyms to replace, applied after the other options, e.g. to translate between
+    /// keyboard layouts. Default: empty.
+    pub layout: HashMap<u32, u32>,
+}
+
+/// Application-defined protocol extensions.
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionConfig {
+    /// Vendor pseudo-encoding numbers the application understands. When a client lists
+    /// one of them in `SetEncodings`, the server emits `ServerEvent::PseudoEncoding` so the
+    /// application can answer with its own rectangles through `VncServer::send_rect`.
+    /// Default: empty.
+    pub pseudo_encodings: Vec<i32>,
+}
+
+/// Connection health limits.
+#[derive(Clone)]
+pub struct ConnectionConfig {
+    /// Maximum time a socket write may go without making progress. A client whose
+    /// connection stops accepting data for this long is considered dead: the write is
+    /// aborted and the client is disconnected instead of leaving its task blocked.
+    /// Zero disables the watchdog. Default: 30 seconds.
+    pub write_stall_timeout: Duration,
+    /// Send an empty framebuffer update to clients that haven't been sent anything for
+    /// this long, so stateful NATs and proxies don't drop idle sessions. Zero disables
+    /// keepalives. Default: zero.
+    pub keepalive_interval: Duration,
+    /// Maximum memory in bytes a single client may hold (see
+    /// [`ClientMemoryUsage`](crate::stats::ClientMemoryUsage)). When a client exceeds it,
+    /// idle buffers and held-back clipboard text are released and queued damage is
+    /// collapsed; if it is still over the cap, it is disconnected. Zero disables the cap.
+    /// Default: 256 MB.
+    pub max_client_memory: usize,
+    /// Start every client paused, showing `UpdateConfig::placeholder` until the
+    /// application resumes it with
+    /// [`VncServer::set_client_paused`](crate::VncServer::set_client_paused), e.g. once
+    /// the host approved the viewer. Default: `false`.
+    pub start_paused: bool,
+    /// Maximum time an incoming connection may take from being accepted to completing
+    /// the handshake (version exchange, security and `ClientInit`). Connections that
+    /// stall are dropped. Zero disables the limit. Default: 30 seconds.
+    pub handshake_timeout: Duration,
+    /// Maximum time a client may take to answer the server's protocol version. Unlike
+    /// `handshake_timeout`, the phase timeouts also apply to reverse and repeater
+    /// connections; a client that exceeds one is dropped and reported as
+    /// `ServerEvent::HandshakeTimeout`. Zero disables the limit. Default: 10 seconds.
+    pub version_timeout: Duration,
+    /// Maximum time the security phase may take: choosing a security type,
+    /// authenticating and waiting for the `approver`. Zero disables the limit.
+    /// Default: 30 seconds.
+    pub security_timeout: Duration,
+    /// Maximum time a client may take to send `ClientInit` after the security phase.
+    /// Zero disables the limit. Default: 10 seconds.
+    pub client_init_timeout: Duration,
+    /// Maximum number of accepted connections that may be in the handshake at once.
+    /// Further connections are closed immediately until a slot frees up, so stalled
+    /// connections can't pin an unbounded number of tasks. Zero disables the limit.
+    /// Default: 64.
+    pub max_pending_handshakes: usize,
+    /// Refuse to offer unencrypted security types to peers outside `encryption_exempt`,
+    /// so a misconfiguration can't expose plaintext sessions on a public interface. Such
+    /// peers are only offered TLS (see `SecurityConfig`); without it they are sent an empty
+    /// security type list with a reason and disconnected.
+    /// Streams handed to
+    /// [`VncServer::serve_secure_stream`](crate::VncServer::serve_secure_stream) and QUIC
+    /// connections (`VncServer::listen_quic`) are encrypted by their transport and always
+    /// allowed. Default: `false`.
+    pub require_encryption: bool,
+    /// Networks exempt from `require_encryption`. Default: loopback (`127.0.0.0/8` and
+    /// `::1`).
+    pub encryption_exempt: Vec<IpNetwork>,
+    /// How long a disconnected client's session is kept for a viewer that supports
+    /// session resume (see the `ENCODING_SESSION_RESUME` pseudo-encoding). A viewer that
+    /// reconnects within this time is sent only what changed instead of a full refresh.
+    /// Zero disables session resume. Default: zero.
+    pub session_resume_timeout: Duration,
+    /// Decides whether each authenticated client may connect, may only watch, or is
+    /// refused with a reason. `None` accepts every authenticated client. Default: `None`.
+    pub approver: Option<ConnectionApprover>,
+}
+
+impl fmt::Debug for ConnectionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionConfig")
+            .field("write_stall_timeout", &self.write_stall_timeout)
+            .field("keepalive_interval", &self.keepalive_interval)
+            .field("max_client_memory", &self.max_client_memory)
+            .field("start_paused", &self.start_paused)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("version_timeout", &self.version_timeout)
+            .field("security_timeout", &self.security_timeout)
+            .field("client_init_timeout", &self.client_init_timeout)
+            .field("max_pending_handshakes", &self.max_pending_handshakes)
+            .field("require_encryption", &self.require_encryption)
+            .field("encryption_exempt", &self.encryption_exempt)
+            .field("session_resume_timeout", &self.session_resume_timeout)
+            .field("approver", &self.approver.as_ref().map(|_| "<fn>"))
+            .finish()
+    }
+}
+
+impl ConnectionConfig {
+    /// Returns whether a peer may use an unencrypted connection: encryption isn't
+    /// required, or the peer is on an exempt network.
+    pub(crate) fn allows_plaintext(&self, peer: Option<std::net::IpAddr>) -> bool {
+        !self.require_encryption
+            || peer.is_some_and(|ip| {
+                self.encryption_exempt
+                    .iter()
+                    .any(|network| network.contains(ip))
+            })
+    }
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            write_stall_timeout: Duration::from_secs(30),
+            keepalive_interval: Duration::ZERO,
+            max_client_memory: 256 * 1024 * 1024,
+            start_paused: false,
+            handshake_timeout: Duration::from_secs(30),
+            version_timeout: Duration::from_secs(10),
+            security_timeout: Duration::from_secs(30),
+            client_init_timeout: Duration::from_secs(10),
+            max_pending_handshakes: 64,
+            require_encryption: false,
+            encryption_exempt: IpNetwork::loopback(),
+            session_resume_timeout: Duration::ZERO,
+            approver: None,
+        }
+    }
+}
+
+/// Framebuffer update scheduling.
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)] // Independent switches, each on or off
+pub struct UpdateConfig {
+    /// Minimum time between two forced full refreshes (non-incremental update requests)
+    /// once a client is sending them continuously. Requests arriving sooner are answered
+    /// incrementally and the full refresh is performed when the interval has passed.
+    /// Zero disables the protection. Default: 500 ms.
+    pub min_full_refresh_interval: Duration,
+    /// Number of back-to-back non-incremental requests (each arriving within
+    /// `min_full_refresh_interval` of the last full refresh) that are served before
+    /// throttling engages. Default: 3.
+    pub full_refresh_burst: u32,
+    /// When more dirty regions are queued than fit in one update, regions within this
+    /// many pixels of the client's pointer are sent first, followed by the others, oldest
+    /// damage first. Every fourth such update ignores the pointer and sends strictly the
+    /// oldest damage, so no region waits indefinitely. Zero only prioritizes regions
+    /// under the pointer. Default: 128.
+    pub pointer_priority_radius: u16,
+    /// Emit a `ServerEvent::UpdateTiming` with the pipeline timing of every framebuffer
+    /// update. The timings are always recorded in the server's statistics. Default: `false`.
+    pub report_timing: bool,
+    /// Size budget in bytes of one `FramebufferUpdate` message. Updates with more data
+    /// are split across several messages so peers and proxies never have to buffer one
+    /// huge message; a single rectangle larger than the budget is sent on its own.
+    /// Default: 4 MB.
+    pub max_message_bytes: usize,
+    /// Adapt each client's update deferral to how its damage arrives: sporadic changes
+    /// (typing, menus) go out after `min_defer_time` for low latency, while constant
+    /// damage (video, animations) is batched for up to `max_defer_time` so more of it is
+    /// compressed together. When `false`, updates are always deferred by `defer_time`.
+    /// Default: `true`.
+    pub adaptive_defer: bool,
+    /// How long damage is collected before an update is sent, when `adaptive_defer` is
+    /// off; with it on, the deferral each client starts with. Default: 5 ms.
+    pub defer_time: Duration,
+    /// Shortest deferral used for sporadic damage. Default: 1 ms.
+    pub min_defer_time: Duration,
+    /// Longest deferral used for constant damage. Default: 20 ms.
+    pub max_defer_time: Duration,
+    /// Minimum time between two updates sent on their own (continuous damage), which
+    /// caps each client's frame rate: 33 ms is about 30 updates per second. Lower it
+    /// for low-latency interactive use; zero sends as soon as the deferral has passed.
+    /// Default: 33 ms.
+    pub min_update_interval: Duration,
+    /// Maximum number of rectangles in one update; damage beyond it waits for the next
+    /// update (see `pointer_priority_radius` for which goes first). Higher values send
+    /// busy screens in fewer round trips, lower ones keep each update small. Values
+    /// below 1 are treated as 1. Default: 50.
+    pub max_rects_per_update: usize,
+    /// What paused clients see instead of the framebuffer. Default: solid black.
+    pub placeholder: Placeholder,
+    /// Color reduction applied to every update before encoding, whatever pixel format
+    /// the client asked for; `VncServer::set_client_color_mode` overrides it per client.
+    /// In grayscale, JPEG rectangles are compressed without chroma
+    /// (`ChromaSubsampling::Gray`). Default: `ColorMode::Full`.
+    pub color_mode: ColorMode,
+    /// Rotation or mirroring applied to every update, e.g. for a display mounted in
+    /// portrait. Clients see the transformed size and their pointer events are mapped
+    /// back to framebuffer coordinates. Default: `Transform::Identity`.
+    pub transform: Transform,
+    /// After this long without damage, a client's update timer stops until new damage
+    /// arrives, so servers showing a static screen don't wake up every few milliseconds.
+    /// Clients of a `FrameSource`-backed framebuffer keep polling it. Zero keeps the
+    /// timer running. Default: 1 second.
+    pub idle_timeout: Duration,
+    /// What happens to clients that didn't advertise the `DesktopSize` pseudo-encoding
+    /// when the framebuffer is resized. Default: `ResizePolicy::Refresh`.
+    pub resize_policy: ResizePolicy,
+    /// Choose the encoding of each region from its content, among the encodings the
+    /// client listed: photographic regions go to Tight JPEG or ZYWRLE when the client
+    /// set a quality level, text and UI stay on lossless encodings, and solid regions
+    /// avoid encodings without a fill. When `false`, every region uses the client's
+    /// preferred encoding. Default: `true`.
+    pub content_adaptive: bool,
+    /// Automatic lossless refresh: once no update has been sent for this long, regions
+    /// last sent with a lossy encoding (Tight JPEG or ZYWRLE) are sent again losslessly,
+    /// so content that stops changing ends up pixel-perfect. Zero disables the refresh.
+    /// Default: 1 s.
+    pub lossless_refresh_delay: Duration,
+    /// Never send lossy rectangles, whatever quality the client asks for: Tight is sent
+    /// without JPEG and ZYWRLE is replaced by a lossless encoding, for installations
+    /// that must never show compression artifacts. `color_mode` still applies.
+    /// Default: `false`.
+    pub lossless_only: bool,
+    /// Encodings the server prefers, most preferred first. Updates normally use the
+    /// first supported encoding in the client's `SetEncodings` list, as RFC 6143 asks;
+    /// with this set, the first of these encodings the client listed wins instead, and
+    /// the client's order applies only if it listed none of them. Set `content_adaptive`
+    /// to `false` as well to send every region in that encoding. Default: empty.
+    pub encoding_order: Vec<i32>,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            min_full_refresh_interval: Duration::from_millis(500),
+            full_refresh_burst: 3,
+            pointer_priority_radius: 128,
+            report_timing: false,
+            max_message_bytes: 4 * 1024 * 1024,
+            adaptive_defer: true,
+            defer_time: Duration::from_millis(5),
+            min_defer_time: Duration::from_millis(1),
+            max_defer_time: Duration::from_millis(20),
+            min_update_interval: Duration::from_millis(33),
+            max_rects_per_update: 50,
+            placeholder: Placeholder::default(),
+            color_mode: ColorMode::Full,
+            transform: Transform::Identity,
+            idle_timeout: Duration::from_secs(1),
+            resize_policy: ResizePolicy::Refresh,
+            content_adaptive: true,
+            lossless_refresh_delay: Duration::from_secs(1),
+            lossless_only: false,
+            encoding_order: Vec::new(),
+        }
+    }
+}
+
+/// Handling of clients that cannot be told about a framebuffer resize.
+///
+/// Clients that advertised the `DesktopSize` pseudo-encoding are always sent the new
+/// size; this only applies to the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizePolicy {
+    /// Keep the client at the size it connected with and send it a full refresh of the
+    /// part of the framebuffer that fits; anything outside is not shown.
+    #[default]
+    Refresh,
+    /// Close the connection, so the viewer reconnects and learns the new size.
+    Disconnect,
+}
+
+/// Color reduction applied on the server before encoding.
+///
+/// Fewer distinct colors make the palette and run-length encodings (Tight, ZRLE,
+/// Hextile) much more effective, which cuts bandwidth for monitoring-only sessions;
+/// grayscale also suits e-ink style displays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Send colors unchanged.
+    #[default]
+    Full,
+    /// Convert to grayscale (ITU-R BT.601 luma) with `2^bits` gray levels (`bits`
+    /// 1-8; 8 keeps all 256 levels).
+    Grayscale {
+        /// Bits per gray level.
+        bits: u8,
+    },
+    /// Keep `bits` bits of each red, green and blue channel (`bits` 1-8), e.g. 2 for
+    /// 64 colors.
+    ReducedColor {
+        /// Bits per color channel.
+        bits: u8,
+    },
+}
+
+impl ColorMode {
+    /// Applies the color reduction to RGBA32 pixels in place.
+    pub(crate) fn apply(self, pixels: &mut [u8]) {
+        match self {
+            Self::Full => {}
+            Self::Grayscale { bits } => {
+                for pixel in pixels.chunks_exact_mut(4) {
+                    let luma = (u32::from(pixel[0]) * 299
+                        + u32::from(pixel[1]) * 587
+                        + u32::from(pixel[2]) * 114)
+                        / 1000;
+                    #[allow(clippy::cast_possible_truncation)]
+                    // Weights sum to 1000, so luma <= 255
+                    let gray = quantize(luma as u8, bits);
+                    pixel[..3].fill(gray);
+                }
+            }
+            Self::ReducedColor { bits } => {
+                for pixel in pixels.chunks_exact_mut(4) {
+                    for channel in &mut pixel[..3] {
+                        *channel = quantize(*channel, bits);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Reduces an 8-bit value to `bits` bits and scales it back to the full 0-255 range.
+fn quantize(value: u8, bits: u8) -> u8 {
+    let bits = bits.clamp(1, 8);
+    if bits == 8 {
+        return value;
+    }
+    let levels = (1u16 << bits) - 1;
+    let level = u16::from(value) >> (8 - bits);
+    #[allow(clippy::cast_possible_truncation)] // level <= levels, so the result is <= 255
+    let scaled = (level * 255 / levels) as u8;
+    scaled
+}
+
+/// Rotation or mirroring applied to the framebuffer before it is sent, for screens
+/// mounted in another orientation than the one the application draws in.
+///
+/// Clients see the transformed screen: its size, the rectangles they are sent and the
+/// pointer positions they report are all in transformed coordinates, and pointer events
+/// are mapped back to the framebuffer before they reach the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transform {
+    /// Send the framebuffer as is.
+    #[default]
+    Identity,
+    /// Rotate 90 degrees clockwise.
+    Rotate90,
+    /// Rotate 180 degrees.
+    Rotate180,
+    /// Rotate 270 degrees clockwise (90 degrees counterclockwise).
+    Rotate270,
+    /// Mirror left to right.
+    FlipHorizontal,
+    /// Mirror top to bottom.
+    FlipVertical,
+}
+
+impl Transform {
+    /// Returns the transform that undoes this one.
+    pub(crate) fn inverse(self) -> Self {
+        match self {
+            Self::Rotate90 => Self::Rotate270,
+            Self::Rotate270 => Self::Rotate90,
+            other => other,
+        }
+    }
+
+    /// Returns the size of a `width` x `height` image once transformed.
+    pub(crate) fn size(self, width: u16, height: u16) -> (u16, u16) {
+        match self {
+            Self::Rotate90 | Self::Rotate270 => (height, width),
+            _ => (width, height),
+        }
+    }
+
+    /// Maps a point of a `width` x `height` image to the transformed image; points
+    /// outside the image are clamped to its edges.
+    pub(crate) fn map_point(self, x: u16, y: u16, width: u16, height: u16) -> (u16, u16) {
+        let (width, height) = (width.max(1), height.max(1));
+        let (x, y) = self.position(
+            usize::from(x.min(width - 1)),
+            usize::from(y.min(height - 1)),
+            usize::from(width),
+            usize::from(height),
+        );
+        #[allow(clippy::cast_possible_truncation)] // Positions within the image fit in u16
+        (x as u16, y as u16)
+    }
+
+    /// Maps the offset between two points, e.g. from a copy's destination to its source.
+    pub(crate) fn map_offset(self, dx: i16, dy: i16) -> (i16, i16) {
+        match self {
+            Self::Identity => (dx, dy),
+            Self::Rotate90 => (dy.saturating_neg(), dx),
+            Self::Rotate180 => (dx.saturating_neg(), dy.saturating_neg()),
+            Self::Rotate270 => (dy, dx.saturating_neg()),
+            Self::FlipHorizontal => (dx.saturating_neg(), dy),
+            Self::FlipVertical => (dx, dy.saturating_neg()),
+        }
+    }
+
+    /// Maps a non-empty rectangle of a `width` x `height` image to the transformed image.
+    pub(crate) fn map_region(self, region: DirtyRegion, width: u16, height: u16) -> DirtyRegion {
+        if self == Self::Identity {
+            return region;
+        }
+        let (width, height) = (usize::from(width), usize::from(height));
+        let (left, top) = (usize::from(region.x), usize::from(region.y));
+        let right = left + usize::from(region.width.max(1)) - 1;
+        let bottom = top + usize::from(region.height.max(1)) - 1;
+        let (x1, y1) = self.position(left, top, width, height);
+        let (x2, y2) = self.position(right, bottom, width, height);
+        #[allow(clippy::cast_possible_truncation)] // Positions within the image fit in u16
+        DirtyRegion::new(
+            x1.min(x2) as u16,
+            y1.min(y2) as u16,
+            (x1.abs_diff(x2) + 1) as u16,
+            (y1.abs_diff(y2) + 1) as u16,
+        )
+    }
+
+    /// Transforms the RGBA32 pixels of a `width` x `height` image.
+    pub(crate) fn apply(self, pixels: Vec<u8>, width: u16, height: u16) -> Vec<u8> {
+        if self == Self::Identity {
+            return pixels;
+        }
+        let (width, height) = (usize::from(width), usize::from(height));
+        let out_width = match self {
+            Self::Rotate90 | Self::Rotate270 => height,
+            _ => width,
+        };
+        let mut out = vec![0; pixels.len()];
+        for (index, pixel) in pixels.chunks_exact(4).enumerate() {
+            let (x, y) = self.position(index % width, index / width, width, height);
+            let offset = (y * out_width + x) * 4;
+            out[offset..offset + 4].copy_from_slice(pixel);
+        }
+        out
+    }
+
+    /// Maps a position inside a `width` x `height` image to the transformed image.
+    fn position(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Self::Identity => (x, y),
+            Self::Rotate90 => (height - 1 - y, x),
+            Self::Rotate180 => (width - 1 - x, height - 1 - y),
+            Self::Rotate270 => (y, width - 1 - x),
+            Self::FlipHorizontal => (width - 1 - x, y),
+            Self::FlipVertical => (x, height - 1 - y),
+        }
+    }
+}
+
+/// Image shown to paused clients (see
+/// [`VncServer::set_client_paused`](crate::VncServer::set_client_paused)) in place of
+/// the framebuffer, so they don't keep the last real frame on screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placeholder {
+    /// Fill the screen with one RGB color.
+    Solid([u8; 3]),
+    /// An RGBA32 image centered on a solid RGB background. Parts of the image that don't
+    /// fit the framebuffer are cropped evenly from both sides.
+    Image {
+        /// Width of the image in pixels.
+        width: u16,
+        /// Height of the image in pixels.
+        height: u16,
+        /// RGBA32 pixel data, row-major (4 bytes per pixel).
+        pixels: Arc<Vec<u8>>,
+        /// RGB color around the image.
+        background: [u8; 3],
+    },
+}
+
+impl Default for Placeholder {
+    fn default() -> Self {
+        Self::Solid([0, 0, 0])
+    }
+}
+
+impl Placeholder {
+    /// Renders the placeholder as RGBA32 pixels for a framebuffer of the given size.
+    pub(crate) fn render(&self, width: u16, height: u16) -> Vec<u8> {
+        let (width, height) = (usize::from(width), usize::from(height));
+        let background = match self {
+            Self::Solid(color)
+            | Self::Image {
+                background: color, ..
+            } => *color,
+        };
+        let mut rendered =
+            [background[0], background[1], background[2], 255].repeat(width * height);
+
+        if let Self::Image {
+            width: image_width,
+            height: image_height,
+            pixels,
+            ..
+        } = self
+        {
+            let (image_width, image_height) =
+                (usize::from(*image_width), usize::from(*image_height));
+            if pixels.len() < image_width * image_height * 4 {
+                log::warn!("Placeholder image is smaller than {image_width}x{image_height}, showing background only");
+                return rendered;
+            }
+            let copy_width = image_width.min(width);
+            let copy_height = image_height.min(height);
+            let (dest_x, dest_y) = ((width - copy_width) / 2, (height - copy_height) / 2);
+            let (src_x, src_y) = (
+                (image_width - copy_width) / 2,
+                (image_height - copy_height) / 2,
+            );
+            for row in 0..copy_height {
+                let src = ((src_y + row) * image_width + src_x) * 4;
+                let dest = ((dest_y + row) * width + dest_x) * 4;
+                rendered[dest..dest + copy_width * 4]
+                    .copy_from_slice(&pixels[src..src + copy_width * 4]);
+            }
+        }
+        rendered
+    }
+}
+
+/// What to do when a client sends more clipboard text than `max_inbound_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClipboardOversizePolicy {
+    /// Disconnect the client (the historical behavior).
+    #[default]
+    Disconnect,
+    /// Deliver the first `max_inbound_bytes` bytes and discard the rest.
+    Truncate,
+    /// Discard the whole clipboard update but keep the client connected.
+    Drop,
+}
+
+/// Filter applied to clipboard text received from clients.
+///
+/// Returns the text to deliver as `ServerEvent::CutText`, or `None` to discard it. The
+/// filter runs on the client's task, so it should be cheap.
+pub type ClipboardFilter = Arc<dyn Fn(String) -> Option<String> + Send + Sync>;
+
+/// Clipboard (cut text) limits and policies.
+#[derive(Clone)]
+pub struct ClipboardConfig {
+    /// Whether clipboard text from clients is accepted at all. When `false`, inbound
+    /// `ClientCutText` messages are read and discarded. Default: `true`.
+    pub accept_inbound: bool,
+    /// Maximum size in bytes of clipboard text accepted from a client. Default: 10 MB.
+    pub max_inbound_bytes: usize,
+    /// What to do with clipboard updates larger than `max_inbound_bytes`.
+    pub oversize_policy: ClipboardOversizePolicy,
+    /// Maximum size in bytes of clipboard text sent to clients; longer text is truncated
+    /// at a character boundary. A `ServerCutText` message is written in one go while
+    /// other output to the client waits, so this also bounds how long a clipboard update
+    /// can hold up framebuffer updates. Default: 10 MB.
+    pub max_outbound_bytes: usize,
+    /// Minimum time between two clipboard events from the same client. Updates arriving
+    /// sooner are coalesced: only the latest one is delivered once the interval has
+    /// passed. Default: zero (no throttling).
+    pub min_inbound_interval: Duration,
+    /// Remove control characters other than tab, carriage return and line feed from
+    /// inbound clipboard text. Default: `false`.
+    pub strip_control_chars: bool,
+    /// Optional filter applied to inbound clipboard text after `strip_control_chars`.
+    pub filter: Option<ClipboardFilter>,
+}
+
+impl fmt::Debug for ClipboardConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClipboardConfig")
+            .field("accept_inbound", &self.accept_inbound)
+            .field("max_inbound_bytes", &self.max_inbound_bytes)
+            .field("oversize_policy", &self.oversize_policy)
+            .field("max_outbound_bytes", &self.max_outbound_bytes)
+            .field("min_inbound_interval", &self.min_inbound_interval)
+            .field("strip_control_chars", &self.strip_control_chars)
+            .field("filter", &self.filter.as_ref().map(|_| "<fn>"))
+            .finish()
+    }
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            accept_inbound: true,
+            max_inbound_bytes: 10 * 1024 * 1024,
+            oversize_policy: ClipboardOversizePolicy::Disconnect,
+            max_outbound_bytes: 10 * 1024 * 1024,
+            min_inbound_interval: Duration::ZERO,
+            strip_control_chars: false,
+            filter: None,
+        }
+    }
+}
+
+/// Backoff and retry budget for persistent reverse connections.
+///
+/// The delay before retry `n` (counting consecutive failures from 1) is
+/// `initial_delay * multiplier^(n - 1)`, capped at `max_delay`, then randomly scaled by
+/// up to `±jitter` so that many machines dropped at once don't reconnect in lockstep.
+/// After a successful connection the failure count starts over.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay before the first retry, and before reconnecting after a session ends.
+    /// Default: 1 second.
+    pub initial_delay: Duration,
+    /// Upper bound for the delay. Default: 60 seconds.
+    pub max_delay: Duration,
+    /// Factor the delay grows by after each consecutive failure. Default: 2.0.
+    pub multiplier: f64,
+    /// Relative random variation applied to each delay, between 0.0 and 1.0. Default: 0.2.
+    pub jitter: f64,
+    /// Maximum number of consecutive failed retries before giving up, or `None` to retry
+    /// forever. Default: `None`.
+    pub max_retries: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    /// Returns the delay to wait after `failures` consecutive failed attempts.
+    #[must_use]
+    pub fn delay(&self, failures: u32) -> Duration {
+        let exponent = i32::try_from(failures.saturating_sub(1)).unwrap_or(i32::MAX);
+        let base = self.initial_delay.as_secs_f64() * self.multiplier.max(1.0).powi(exponent);
+        let base = base.min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + rand::thread_rng().gen_range(-jitter..=jitter);
+        Duration::try_from_secs_f64(base * factor).unwrap_or(self.max_delay)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_retries: None,
+        }
+    }
+}
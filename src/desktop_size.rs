@@ -0,0 +1,133 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client-requested desktop resizes.
+//!
+//! Viewers supporting the `ExtendedDesktopSize` extension can ask the server to change
+//! the framebuffer size with a `SetDesktopSize` message. The server does not decide on
+//! its own: each request is delivered to the application as
+//! `ServerEvent::ResizeRequest` together with a [`ResizeReply`]. The application either
+//! accepts it with the geometry it actually applied, or rejects it with a
+//! [`ResizeStatus`]. The client's message loop keeps running while the application
+//! decides.
+//!
+//! The answer goes back to the client as an `ExtendedDesktopSize` rectangle with the
+//! status and the resulting framebuffer size. Requests with an invalid screen layout
+//! (no screens, or screens outside the requested size) are answered with
+//! [`ResizeStatus::InvalidLayout`] without reaching the application.
+//!
+//! ```no_run
+//! # async fn example(mut events: tokio::sync::mpsc::UnboundedReceiver<rustvncserver::server::ServerEvent>) {
+//! use rustvncserver::desktop_size::ResizeStatus;
+//! use rustvncserver::server::ServerEvent;
+//!
+//! while let Some(event) = events.recv().await {
+//!     if let ServerEvent::ResizeRequest { width, height, reply, .. } = event {
+//!         if width <= 3840 && height <= 2160 {
+//!             reply.accept(width, height);
+//!         } else {
+//!             reply.reject(ResizeStatus::OutOfResources);
+//!         }
+//!     }
+//! }
+//! # }
+//! ```
+
+use tokio::sync::oneshot;
+
+/// A screen of a multi-head layout, as sent in `SetDesktopSize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Screen {
+    /// Screen identifier chosen by the server.
+    pub id: u32,
+    /// X position of the screen within the framebuffer.
+    pub x: u16,
+    /// Y position of the screen within the framebuffer.
+    pub y: u16,
+    /// Width of the screen.
+    pub width: u16,
+    /// Height of the screen.
+    pub height: u16,
+    /// Screen flags (currently unused by the protocol).
+    pub flags: u32,
+}
+
+/// Status codes for answering a client-requested resize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeStatus {
+    /// The resize was performed.
+    Success,
+    /// Resizing is administratively prohibited.
+    Prohibited,
+    /// The server is out of resources for the requested size.
+    OutOfResources,
+    /// The requested screen layout is invalid.
+    InvalidLayout,
+}
+
+impl ResizeStatus {
+    /// Returns the status code sent to the client in the `ExtendedDesktopSize` reply.
+    #[must_use]
+    pub fn code(self) -> u16 {
+        match self {
+            Self::Success => 0,
+            Self::Prohibited => 1,
+            Self::OutOfResources => 2,
+            Self::InvalidLayout => 3,
+        }
+    }
+}
+
+/// The application's answer to a resize request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeOutcome {
+    /// The resize was accepted with the given framebuffer size, which may differ from the
+    /// requested one.
+    Accepted {
+        /// The new framebuffer width.
+        width: u16,
+        /// The new framebuffer height.
+        height: u16,
+    },
+    /// The resize was rejected.
+    Rejected(ResizeStatus),
+}
+
+/// Reply handle for a client-requested resize.
+///
+/// Dropping the handle without answering rejects the request with
+/// [`ResizeStatus::Prohibited`].
+#[derive(Debug)]
+pub struct ResizeReply {
+    tx: oneshot::Sender<ResizeOutcome>,
+}
+
+impl ResizeReply {
+    /// Creates a reply handle and the receiver the client waits on.
+    pub(crate) fn new() -> (Self, oneshot::Receiver<ResizeOutcome>) {
+        let (tx, rx) = oneshot::channel();
+        (Self { tx }, rx)
+    }
+
+    /// Accepts the request. The server resizes the framebuffer to `width` x `height`
+    /// (keeping its contents where they overlap) before acknowledging the client.
+    pub fn accept(self, width: u16, height: u16) {
+        let _ = self.tx.send(ResizeOutcome::Accepted { width, height });
+    }
+
+    /// Rejects the request with the given status.
+    pub fn reject(self, status: ResizeStatus) {
+        let _ = self.tx.send(ResizeOutcome::Rejected(status));
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encoded rectangles shared between clients.
+//!
+//! Clients watching the same framebuffer are usually sent the same rectangles. When
+//! they also negotiated the same pixel format, encoding and settings, the first client
+//! to encode a rectangle stores the result here and the others send it as is.
+//!
+//! Only output that doesn't depend on the connection can be shared: what
+//! [`RectEncoder::encode`](crate::rect_encoder::RectEncoder::encode) produces. Raw, RRE,
+//! `CoRRE`, Hextile, TRLE and `TightPng` rectangles are stored whole. Zlib, `ZlibHex`,
+//! ZRLE, ZYWRLE and Zstd rectangles are stored before compression, and every client
+//! then compresses them through its own stream: RFB decodes each of these as one
+//! continuous zlib stream per connection, so their compressed bytes can't be shared.
+//! Tight lets a rectangle reset the client's streams, so Tight regions are stored whole
+//! when more than one client is watching, compressed with streams of their own (see
+//! [`FreshStreams`](crate::tight::FreshStreams)).
+//!
+//! The framebuffer drops the entries of every region it marks as changed. A generation
+//! counter, advanced at the same time, rejects rectangles whose pixels were read before
+//! a change, so an entry always matches the framebuffer's current contents.
+//!
+//! Clients usually reach the same rectangle at about the same time, right after the
+//! framebuffer changed. So that it is still encoded once, the first client to miss
+//! claims the rectangle, and the others wait for it to be stored instead of encoding it
+//! too.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+
+use crate::config::{ColorMode, Transform};
+use crate::framebuffer::DirtyRegion;
+use crate::protocol::PixelFormat;
+
+/// What an encoded rectangle depends on besides the framebuffer's pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CacheKey {
+    region: DirtyRegion,
+    encoding: i32,
+    /// The client's pixel format, field by field.
+    format: [u16; 10],
+    color_mode: ColorMode,
+    transform: Transform,
+    /// Encoder settings that change the output, e.g. quality and compression levels.
+    params: (u8, u8),
+}
+
+impl CacheKey {
+    /// Creates the key of a rectangle.
+    ///
+    /// # Arguments
+    ///
+    /// * `region` - The rectangle, in framebuffer coordinates.
+    /// * `encoding` - The encoding it is sent in.
+    /// * `format` - The client's pixel format.
+    /// * `color_mode` - The color reduction applied before encoding.
+    /// * `transform` - The rotation or mirroring applied before encoding.
+    /// * `params` - The encoder settings the output depends on; zero for unused ones.
+    pub(crate) fn new(
+        region: DirtyRegion,
+        encoding: i32,
+        format: &PixelFormat,
+        color_mode: ColorMode,
+        transform: Transform,
+        params: (u8, u8),
+    ) -> Self {
+        Self {
+            region,
+            encoding,
+            format: [
+                u16::from(format.bits_per_pixel),
+                u16::from(format.depth),
+                u16::from(format.big_endian_flag),
+                u16::from(format.true_colour_flag),
+                format.red_max,
+                format.green_max,
+                format.blue_max,
+                u16::from(format.red_shift),
+                u16::from(format.green_shift),
+                u16::from(format.blue_shift),
+            ],
+            color_mode,
+            transform,
+            params,
+        }
+    }
+}
+
+/// Encoded rectangles of the current frame, oldest first.
+#[derive(Default)]
+struct CacheState {
+    generation: u64,
+    entries: VecDeque<(CacheKey, Bytes)>,
+    bytes: usize,
+    /// Rectangles a client is encoding right now (see [`Claim`]).
+    in_flight: Vec<CacheKey>,
+}
+
+/// A cache of encoded rectangles, shared by all clients of a framebuffer.
+#[derive(Default)]
+pub(crate) struct EncodeCache {
+    state: Mutex<CacheState>,
+    /// Signaled whenever a claim ends.
+    released: Notify,
+}
+
+/// Result of looking up a rectangle in the cache.
+pub(crate) enum Lookup<'a> {
+    /// Another client encoded the rectangle.
+    Hit(Bytes),
+    /// The rectangle has to be encoded. With a claim, other clients wait for it to be
+    /// stored; without one (the pixels are older than the cache), it can't be stored.
+    Miss(Option<Claim<'a>>),
+}
+
+/// The right to encode a rectangle for the cache, released when dropped.
+pub(crate) struct Claim<'a> {
+    cache: &'a EncodeCache,
+    key: CacheKey,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.cache.state.lock() {
+            state.in_flight.retain(|key| *key != self.key);
+        }
+        self.cache.released.notify_waiters();
+    }
+}
+
+impl EncodeCache {
+    /// Returns the current generation; read it before reading the pixels to encode.
+    pub(crate) fn generation(&self) -> u64 {
+        self.state.lock().map_or(0, |state| state.generation)
+    }
+
+    /// Returns the encoded rectangle stored for `key`, waiting for it if another client
+    /// is encoding it, or else claims it for the caller to encode.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The rectangle to look up.
+    /// * `generation` - The generation read before the pixels to encode were; rectangles
+    ///   read before a change are neither claimed nor waited for.
+    pub(crate) async fn lookup(&self, key: &CacheKey, generation: u64) -> Lookup<'_> {
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            {
+                let Ok(mut state) = self.state.lock() else {
+                    return Lookup::Miss(None);
+                };
+                if let Some((_, data)) = state.entries.iter().find(|(entry, _)| entry == key) {
+                    return Lookup::Hit(data.clone());
+                }
+                if state.generation != generation {
+                    return Lookup::Miss(None);
+                }
+                if !state.in_flight.contains(key) {
+                    state.in_flight.push(*key);
+                    return Lookup::Miss(Some(Claim {
+                        cache: self,
+                        key: *key,
+                    }));
+                }
+                // Listen before unlocking, so the claim can't end unnoticed in between
+                released.as_mut().enable();
+            }
+            released.await;
+        }
+    }
+
+    /// Stores an encoded rectangle, evicting the oldest entries beyond `limit` bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - What the data was encoded from.
+    /// * `data` - The encoded rectangle.
+    /// * `generation` - The generation read before the pixels were; the data is dropped
+    ///   if the framebuffer changed since.
+    /// * `limit` - Maximum size of the cache in bytes.
+    pub(crate) fn insert(&self, key: CacheKey, data: Bytes, generation: u64, limit: usize) {
+        if data.len() > limit {
+            return;
+        }
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if state.generation != generation || state.entries.iter().any(|(entry, _)| *entry == key) {
+            return;
+        }
+        while state.bytes + data.len() > limit {
+            let Some((_, evicted)) = state.entries.pop_front() else {
+                break;
+            };
+            state.bytes -= evicted.len();
+        }
+        state.bytes += data.len();
+        state.entries.push_back((key, data));
+    }
+
+    /// Drops the entries that overlap a changed region, or all of them for `None`.
+    pub(crate) fn invalidate(&self, region: Option<&DirtyRegion>) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.generation = state.generation.wrapping_add(1);
+        let CacheState { entries, bytes, .. } = &mut *state;
+        entries.retain(|(key, data)| {
+            let keep = region.is_some_and(|region| !key.region.intersects(region));
+            if !keep {
+                *bytes -= data.len();
+            }
+            keep
+        });
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Dedicated encoder threads.
+//!
+//! By default rectangles are encoded on each client's Tokio task, which shares the
+//! runtime's worker threads with the host application. When
+//! [`EncodeConfig::threads`](crate::config::EncodeConfig::threads) is set, encoding runs
+//! on a fixed set of threads instead, optionally at a lower scheduling priority or pinned
+//! to specific cores, so heavy updates can't starve the application the server is
+//! embedded in.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tokio::sync::oneshot;
+
+use crate::config::EncodeConfig;
+
+/// A unit of work queued on the pool.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads running encode jobs in submission order.
+///
+/// The threads exit once the pool and every clone of it have been dropped.
+pub(crate) struct EncodePool {
+    jobs: std_mpsc::Sender<Job>,
+}
+
+impl EncodePool {
+    /// Starts the encoder threads described by `config`.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` if `config.threads` is zero (encode on the client tasks).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if a thread cannot be spawned.
+    pub(crate) fn new(config: &EncodeConfig) -> Result<Option<Arc<Self>>, std::io::Error> {
+        if config.threads == 0 {
+            return Ok(None);
+        }
+
+        let (jobs, receiver) = std_mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..config.threads {
+            let receiver = receiver.clone();
+            let config = config.clone();
+            thread::Builder::new()
+                .name(format!("vnc-encode-{index}"))
+                .spawn(move || {
+                    configure_thread(&config);
+                    loop {
+                        // Hold the lock only while waiting, so idle threads queue up for jobs
+                        let job = match receiver.lock() {
+                            Ok(receiver) => receiver.recv(),
+                            Err(_) => return,
+                        };
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => return, // Pool dropped
+                        }
+                    }
+                })?;
+        }
+
+        Ok(Some(Arc::new(Self { jobs })))
+    }
+
+    /// Runs `job` on one of the pool's threads and waits for its result.
+    ///
+    /// A panic in `job` is resumed on the caller, as if the job had run inline.
+    pub(crate) async fn run<R, F>(&self, job: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce() -> R + Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = result_tx.send(panic::catch_unwind(AssertUnwindSafe(job)));
+        });
+        if let Err(std_mpsc::SendError(job)) = self.jobs.send(job) {
+            // All threads are gone; run the job here rather than lose it
+            job();
+        }
+
+        match result_rx.await.expect("encode job dropped without running") {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+/// Applies the configured priority and CPU affinity to the calling encoder thread.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn configure_thread(config: &EncodeConfig) {
+    if let Some(nice) = config.nice {
+        // On Linux, PRIO_PROCESS with id 0 sets the nice value of the calling thread only
+        // SAFETY: setpriority has no memory safety requirements
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+            log::warn!(
+                "Failed to set encoder thread nice value to {nice}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
+    if !config.cpu_affinity.is_empty() {
+        // SAFETY: cpu_set_t is a plain bit set for which all zeroes is a valid (empty) value
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        for &cpu in &config.cpu_affinity {
+            // SAFETY: CPU_SET bounds-checks `cpu` against the size of the set
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+        // SAFETY: `set` is a valid cpu_set_t and the size passed matches it
+        let result = unsafe {
+            libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &raw const set)
+        };
+        if result != 0 {
+            log::warn!(
+                "Failed to pin encoder thread to CPUs {:?}: {}",
+                config.cpu_affinity,
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+/// Applies the configured priority and CPU affinity to the calling encoder thread.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn configure_thread(config: &EncodeConfig) {
+    if config.nice.is_some() || !config.cpu_affinity.is_empty() {
+        log::warn!("Encoder thread priority and affinity are only supported on Linux and Android");
+    }
+}
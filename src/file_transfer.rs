@@ -0,0 +1,806 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `UltraVNC` file transfer (requires the `file-transfer` feature).
+//!
+//! `UltraVNC` viewers move files with message type 7 in both directions. Each message
+//! carries a content type, a parameter, a 32-bit size and a data block. The server side
+//! implemented here:
+//!
+//! - answers the viewer's access request, refusing view-only clients;
+//! - lists the shared directory, presented to the viewer as drive `C:`, with Windows
+//!   `WIN32_FIND_DATA` records;
+//! - sends files in 8 KB packets, a few per update tick, so screen updates keep flowing;
+//! - stores uploaded files, inflating packets the viewer compressed;
+//! - creates directories and deletes or renames entries, unless the share is read-only.
+//!
+//! Paths are resolved inside `FileTransferConfig::root`; requests with `..` or drive
+//! components, or leading outside the root through symbolic links, are refused, so
+//! viewers cannot leave it. Hidden entries (starting with `.`) are not listed. Only the
+//! uncompressed packet format is sent; every `UltraVNC` viewer reads it.
+
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::{BufMut, BytesMut};
+use flate2::read::ZlibDecoder;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::audit::civil_from_days;
+use crate::config::FileTransferConfig;
+use crate::protocol::MSG_FILE_TRANSFER;
+
+/// Client asks for a directory listing or the drive list.
+const DIR_CONTENT_REQUEST: u8 = 1;
+/// Server sends a directory listing entry.
+const DIR_PACKET: u8 = 2;
+/// Client asks for a file.
+const FILE_TRANSFER_REQUEST: u8 = 3;
+/// Server announces a requested file; the client confirms or cancels with the same type.
+const FILE_HEADER: u8 = 4;
+/// A block of file data.
+const FILE_PACKET: u8 = 5;
+/// The file is complete.
+const END_OF_FILE: u8 = 6;
+/// The transfer was cancelled.
+const ABORT_FILE_TRANSFER: u8 = 7;
+/// Client offers a file to upload.
+const FILE_TRANSFER_OFFER: u8 = 8;
+/// Server accepts or refuses an upload.
+const FILE_ACCEPT_HEADER: u8 = 9;
+/// Client asks to create, delete or rename an entry.
+const COMMAND: u8 = 10;
+/// Server answers a command.
+const COMMAND_RETURN: u8 = 11;
+/// Client asks whether file transfer is allowed; the server answers with the same type.
+const FILE_TRANSFER_ACCESS: u8 = 14;
+
+/// `DIR_CONTENT_REQUEST` parameter: list a directory.
+const REQUEST_DIR_CONTENT: u8 = 1;
+/// `DIR_CONTENT_REQUEST` parameter: list the drives.
+const REQUEST_DRIVES_LIST: u8 = 2;
+/// `DIR_PACKET` parameter: a directory listing entry.
+const ANSWER_DIRECTORY: u8 = 1;
+/// `DIR_PACKET` parameter: the drive list.
+const ANSWER_DRIVES_LIST: u8 = 3;
+
+/// `COMMAND` parameter: create a directory.
+const COMMAND_DIR_CREATE: u8 = 1;
+/// `COMMAND` parameter: delete a file or an empty directory.
+const COMMAND_FILE_DELETE: u8 = 4;
+/// `COMMAND` parameter: rename an entry (`old*new`).
+const COMMAND_FILE_RENAME: u8 = 5;
+/// `COMMAND_RETURN` parameter: a directory was created.
+const RETURN_DIR_CREATE: u8 = 1;
+/// `COMMAND_RETURN` parameter: a directory was deleted.
+const RETURN_DIR_DELETE: u8 = 2;
+/// `COMMAND_RETURN` parameter: a file was deleted.
+const RETURN_FILE_DELETE: u8 = 4;
+/// `COMMAND_RETURN` parameter: an entry was renamed.
+const RETURN_FILE_RENAME: u8 = 5;
+
+/// Size of the file data blocks.
+const BLOCK_SIZE: usize = 8192;
+
+/// Largest data block accepted from a client.
+const MAX_DATA_LEN: usize = 64 * 1024;
+
+/// Size value signalling an error or a refusal.
+const FAILED: u32 = u32::MAX;
+
+/// Windows attribute of directories in listing entries.
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+
+/// Windows attribute of plain files in listing entries.
+const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+
+/// Seconds from the Windows `FILETIME` epoch (1601) to the Unix epoch.
+const FILETIME_UNIX_OFFSET: u64 = 11_644_473_600;
+
+/// One file transfer message.
+pub(crate) struct Message {
+    content_type: u8,
+    content_param: u8,
+    size: u32,
+    data: Vec<u8>,
+    /// Upper 32 bits of the file size, which follow the data of file headers and offers.
+    size_high: Option<u32>,
+}
+
+impl Message {
+    fn new(content_type: u8, content_param: u8, size: u32, data: Vec<u8>) -> Self {
+        Self {
+            content_type,
+            content_param,
+            size,
+            data,
+            size_high: None,
+        }
+    }
+
+    /// Parses a client message from the start of `buf`.
+    ///
+    /// # Returns
+    ///
+    /// The message and the number of bytes it took, or `None` if more data is needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the data block is larger than the server accepts.
+    pub(crate) fn parse(buf: &[u8]) -> Result<Option<(Self, usize)>, io::Error> {
+        if buf.len() < 12 {
+            return Ok(None);
+        }
+        let size = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        let len = u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]) as usize;
+        if len > MAX_DATA_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("File transfer message too long: {len} bytes"),
+            ));
+        }
+        let has_size_high = buf[1] == FILE_TRANSFER_OFFER;
+        let total = 12 + len + if has_size_high { 4 } else { 0 };
+        if buf.len() < total {
+            return Ok(None);
+        }
+        let size_high = has_size_high.then(|| {
+            let at = 12 + len;
+            u32::from_be_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+        });
+        let message = Self {
+            content_type: buf[1],
+            content_param: buf[2],
+            size,
+            data: buf[12..12 + len].to_vec(),
+            size_high,
+        };
+        Ok(Some((message, total)))
+    }
+
+    /// Appends the message to `out`.
+    pub(crate) fn write_to(&self, out: &mut BytesMut) {
+        out.reserve(16 + self.data.len());
+        out.put_u8(MSG_FILE_TRANSFER);
+        out.put_u8(self.content_type);
+        out.put_u8(self.content_param);
+        out.put_u8(0); // padding
+        out.put_u32(self.size);
+        #[allow(clippy::cast_possible_truncation)] // Data blocks are far below 4 GB
+        out.put_u32(self.data.len() as u32);
+        out.put_slice(&self.data);
+        if let Some(size_high) = self.size_high {
+            out.put_u32(size_high);
+        }
+    }
+}
+
+/// A file that crossed the connection.
+pub(crate) enum Transferred {
+    /// A file was sent to the client.
+    Sent {
+        /// The file that was sent.
+        path: PathBuf,
+        /// Its size in bytes.
+        bytes: u64,
+    },
+    /// A file from the client was stored.
+    Received {
+        /// Where it was stored.
+        path: PathBuf,
+        /// Its size in bytes.
+        bytes: u64,
+    },
+}
+
+/// What handling a message produced.
+#[derive(Default)]
+pub(crate) struct Outcome {
+    /// Messages to send to the client, in order.
+    pub(crate) replies: Vec<Message>,
+    /// A transfer that completed.
+    pub(crate) transferred: Option<Transferred>,
+}
+
+impl Outcome {
+    fn reply(message: Message) -> Self {
+        Self {
+            replies: vec![message],
+            transferred: None,
+        }
+    }
+}
+
+/// Answers a message while file transfer is disabled: access requests are refused and
+/// everything else is ignored.
+pub(crate) fn refuse(message: &Message) -> Outcome {
+    if message.content_type == FILE_TRANSFER_ACCESS {
+        Outcome::reply(Message::new(FILE_TRANSFER_ACCESS, 0, FAILED, Vec::new()))
+    } else {
+        Outcome::default()
+    }
+}
+
+/// A file being sent to the client.
+struct Download {
+    file: File,
+    path: PathBuf,
+    sent: u64,
+    /// Whether the client confirmed the file header.
+    started: bool,
+}
+
+/// A file being received from the client.
+struct Upload {
+    file: File,
+    path: PathBuf,
+    received: u64,
+}
+
+/// One client's file transfer state.
+pub(crate) struct FileTransfer {
+    root: PathBuf,
+    read_only: bool,
+    download: Option<Download>,
+    upload: Option<Upload>,
+}
+
+impl FileTransfer {
+    /// Creates the state for a client, or `None` if file transfer is disabled.
+    pub(crate) fn new(config: &FileTransferConfig) -> Option<Self> {
+        Some(Self {
+            root: config.root.clone()?,
+            read_only: config.read_only,
+            download: None,
+            upload: None,
+        })
+    }
+
+    /// Returns whether file data is waiting to be sent with
+    /// [`next_packets`](Self::next_packets).
+    pub(crate) fn is_sending(&self) -> bool {
+        self.download
+            .as_ref()
+            .is_some_and(|download| download.started)
+    }
+
+    /// Handles a message from the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message.
+    /// * `permitted` - Whether the client may use file transfer; view-only clients may not.
+    pub(crate) async fn handle(&mut self, message: Message, permitted: bool) -> Outcome {
+        if !permitted {
+            return refuse(&message);
+        }
+
+        match message.content_type {
+            FILE_TRANSFER_ACCESS => {
+                Outcome::reply(Message::new(FILE_TRANSFER_ACCESS, 0, 1, Vec::new()))
+            }
+            DIR_CONTENT_REQUEST => match message.content_param {
+                REQUEST_DRIVES_LIST => Outcome::reply(Message::new(
+                    DIR_PACKET,
+                    ANSWER_DRIVES_LIST,
+                    0,
+                    b"C:l\0\0".to_vec(),
+                )),
+                REQUEST_DIR_CONTENT => Outcome {
+                    replies: self.list_directory(message.data).await,
+                    transferred: None,
+                },
+                _ => Outcome::default(),
+            },
+            FILE_TRANSFER_REQUEST => Outcome::reply(self.start_download(message.data).await),
+            FILE_HEADER => {
+                if message.size == FAILED {
+                    self.download = None;
+                } else if let Some(download) = &mut self.download {
+                    download.started = true;
+                }
+                Outcome::default()
+            }
+            FILE_TRANSFER_OFFER => Outcome::reply(self.start_upload(&message).await),
+            FILE_PACKET => self.receive_packet(&message).await,
+            END_OF_FILE => self.finish_upload().await,
+            ABORT_FILE_TRANSFER => {
+                self.download = None;
+                if let Some(upload) = self.upload.take() {
+                    drop(upload.file);
+                    let _ = fs::remove_file(&upload.path).await;
+                }
+                Outcome::default()
+            }
+            COMMAND => self.run_command(&message).await,
+            _ => Outcome::default(),
+        }
+    }
+
+    /// Reads the next blocks of the file being sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_packets` - Maximum number of data blocks to read.
+    pub(crate) async fn next_packets(&mut self, max_packets: usize) -> Outcome {
+        let mut outcome = Outcome::default();
+        let Some(download) = self.download.as_mut().filter(|download| download.started) else {
+            return outcome;
+        };
+
+        let mut block = vec![0u8; BLOCK_SIZE];
+        for _ in 0..max_packets {
+            match download.file.read(&mut block).await {
+                Ok(0) => {
+                    outcome
+                        .replies
+                        .push(Message::new(END_OF_FILE, 0, 0, Vec::new()));
+                    let download = self.download.take().expect("download in progress");
+                    outcome.transferred = Some(Transferred::Sent {
+                        path: download.path,
+                        bytes: download.sent,
+                    });
+                    break;
+                }
+                Ok(n) => {
+                    download.sent += n as u64;
+                    outcome
+                        .replies
+                        .push(Message::new(FILE_PACKET, 0, 0, block[..n].to_vec()));
+                }
+                Err(e) => {
+                    log::warn!("Failed to read {}: {e}", download.path.display());
+                    outcome
+                        .replies
+                        .push(Message::new(ABORT_FILE_TRANSFER, 0, 0, Vec::new()));
+                    self.download = None;
+                    break;
+                }
+            }
+        }
+        outcome
+    }
+
+    /// Maps a viewer path (`C:\dir\file`) into the shared directory, without looking
+    /// at the file system; see [`confine`](Self::confine).
+    ///
+    /// # Returns
+    ///
+    /// `None` if the path has a component other than a plain name: `..`, or a drive or
+    /// root (anything with a `:`), which would replace the path built so far on Windows.
+    fn resolve(&self, path: &[u8]) -> Option<PathBuf> {
+        let path = String::from_utf8_lossy(path);
+        let path = path.trim_end_matches('\0');
+        // The viewer was told the share is drive C:
+        let path = match path.as_bytes() {
+            [letter, b':', ..] if letter.is_ascii_alphabetic() => &path[2..],
+            _ => path,
+        };
+
+        let mut resolved = self.root.clone();
+        for component in path.split(['\\', '/']) {
+            if component.contains(':') {
+                return None;
+            }
+            for part in Path::new(component).components() {
+                match part {
+                    Component::Normal(name) => resolved.push(name),
+                    Component::CurDir => {}
+                    Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(resolved)
+    }
+
+    /// Checks that a resolved path stays inside the shared directory once symbolic links
+    /// are followed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path from [`resolve`](Self::resolve).
+    /// * `follow` - Whether the last component is used through a link (reading or
+    ///   writing a file) rather than as an entry itself (deleting or renaming it).
+    ///
+    /// # Returns
+    ///
+    /// The canonical path to use. A missing last component is allowed (it is about to be
+    /// created), as long as its directory is inside.
+    async fn confine(&self, path: &Path, follow: bool) -> Result<PathBuf, io::Error> {
+        let root = fs::canonicalize(&self.root).await?;
+        if follow {
+            match fs::canonicalize(path).await {
+                Ok(canonical) if canonical.starts_with(&root) => return Ok(canonical),
+                Ok(_) => return Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        };
+        let parent = fs::canonicalize(parent).await?;
+        if !parent.starts_with(&root) {
+            return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        }
+        Ok(parent.join(name))
+    }
+
+    /// Resolves a viewer path and confines it to the shared directory.
+    async fn locate(&self, path: &[u8], follow: bool) -> Result<PathBuf, io::Error> {
+        match self.resolve(path) {
+            Some(resolved) => self.confine(&resolved, follow).await,
+            None => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+        }
+    }
+
+    /// Lists a directory: the requested path, one entry per file or directory, and an
+    /// empty packet ending the listing. A directory that cannot be read gets only the
+    /// path packet, without data.
+    async fn list_directory(&self, request: Vec<u8>) -> Vec<Message> {
+        let Ok(dir) = self.locate(&request, true).await else {
+            return vec![Message::new(DIR_PACKET, ANSWER_DIRECTORY, 0, Vec::new())];
+        };
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            return vec![Message::new(DIR_PACKET, ANSWER_DIRECTORY, 0, Vec::new())];
+        };
+
+        let mut replies = vec![Message::new(DIR_PACKET, ANSWER_DIRECTORY, 0, request)];
+        if fs::canonicalize(&self.root).await.ok().as_ref() != Some(&dir) {
+            if let Ok(metadata) = fs::metadata(&dir).await {
+                replies.push(Message::new(
+                    DIR_PACKET,
+                    ANSWER_DIRECTORY,
+                    0,
+                    find_data("..", &metadata),
+                ));
+            }
+        }
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') {
+                continue;
+            }
+            // Follows symbolic links, so they are listed as what they point to
+            let Ok(metadata) = fs::metadata(entry.path()).await else {
+                continue;
+            };
+            replies.push(Message::new(
+                DIR_PACKET,
+                ANSWER_DIRECTORY,
+                0,
+                find_data(&name, &metadata),
+            ));
+        }
+        replies.push(Message::new(DIR_PACKET, 0, 0, Vec::new()));
+        replies
+    }
+
+    /// Opens a requested file and answers with its header: the path with the
+    /// modification time appended and the size, or a failed size.
+    async fn start_download(&mut self, request: Vec<u8>) -> Message {
+        self.download = None;
+        let opened = match self.locate(&request, true).await {
+            Ok(path) => open_file(&path)
+                .await
+                .map(|(file, metadata)| (path, file, metadata)),
+            Err(e) => Err(e),
+        };
+        let (path, file, metadata) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                log::warn!("File transfer request refused: {e}");
+                return Message::new(FILE_HEADER, 0, FAILED, request);
+            }
+        };
+
+        let mut data = request;
+        while data.last() == Some(&0) {
+            data.pop();
+        }
+        data.push(b',');
+        data.extend_from_slice(file_time(metadata.modified().ok()).as_bytes());
+
+        let size = metadata.len();
+        self.download = Some(Download {
+            file,
+            path,
+            sent: 0,
+            started: false,
+        });
+        #[allow(clippy::cast_possible_truncation)] // Split into low and high halves
+        Message {
+            size_high: Some((size >> 32) as u32),
+            ..Message::new(FILE_HEADER, 0, size as u32, data)
+        }
+    }
+
+    /// Creates the file the client offers (`path,time`) and accepts or refuses it.
+    async fn start_upload(&mut self, offer: &Message) -> Message {
+        self.upload = None;
+        let end = offer
+            .data
+            .iter()
+            .rposition(|&byte| byte == b',')
+            .unwrap_or(offer.data.len());
+        let name = offer.data[..end].to_vec();
+
+        let created = match self.writable_path(&name, true).await {
+            Ok(path) => File::create(&path).await.map(|file| (path, file)),
+            Err(e) => Err(e),
+        };
+        match created {
+            Ok((path, file)) => {
+                self.upload = Some(Upload {
+                    file,
+                    path,
+                    received: 0,
+                });
+                Message::new(FILE_ACCEPT_HEADER, 0, 0, name)
+            }
+            Err(e) => {
+                log::warn!("File upload refused: {e}");
+                Message::new(FILE_ACCEPT_HEADER, 0, FAILED, name)
+            }
+        }
+    }
+
+    /// Writes a block of the file being uploaded, inflating it if it is compressed.
+    async fn receive_packet(&mut self, packet: &Message) -> Outcome {
+        let Some(upload) = &mut self.upload else {
+            return Outcome::default();
+        };
+
+        let result = if packet.content_param == 1 {
+            inflate(&packet.data)
+        } else {
+            Ok(packet.data.clone())
+        };
+        let result = match result {
+            Ok(data) => upload.file.write_all(&data).await.map(|()| data.len()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(written) => {
+                upload.received += written as u64;
+                Outcome::default()
+            }
+            Err(e) => {
+                log::warn!("Failed to write {}: {e}", upload.path.display());
+                if let Some(upload) = self.upload.take() {
+                    drop(upload.file);
+                    let _ = fs::remove_file(&upload.path).await;
+                }
+                Outcome::reply(Message::new(ABORT_FILE_TRANSFER, 0, 0, Vec::new()))
+            }
+        }
+    }
+
+    /// Completes the upload in progress.
+    async fn finish_upload(&mut self) -> Outcome {
+        let Some(mut upload) = self.upload.take() else {
+            return Outcome::default();
+        };
+        if let Err(e) = upload.file.flush().await {
+            log::warn!("Failed to write {}: {e}", upload.path.display());
+            return Outcome::default();
+        }
+        Outcome {
+            replies: Vec::new(),
+            transferred: Some(Transferred::Received {
+                path: upload.path,
+                bytes: upload.received,
+            }),
+        }
+    }
+
+    /// Creates a directory, or deletes or renames an entry, and reports the result.
+    async fn run_command(&self, command: &Message) -> Outcome {
+        let data = command.data.clone();
+        let (param, result) = match command.content_param {
+            COMMAND_DIR_CREATE => (
+                RETURN_DIR_CREATE,
+                match self.writable_path(&data, true).await {
+                    Ok(path) => fs::create_dir(path).await,
+                    Err(e) => Err(e),
+                },
+            ),
+            COMMAND_FILE_DELETE => match self.writable_path(&data, false).await {
+                Ok(path) => match fs::symlink_metadata(&path).await {
+                    Ok(metadata) if metadata.is_dir() => {
+                        (RETURN_DIR_DELETE, fs::remove_dir(&path).await)
+                    }
+                    Ok(_) => (RETURN_FILE_DELETE, fs::remove_file(&path).await),
+                    Err(e) => (RETURN_FILE_DELETE, Err(e)),
+                },
+                Err(e) => (RETURN_FILE_DELETE, Err(e)),
+            },
+            COMMAND_FILE_RENAME => {
+                let separator = data.iter().position(|&byte| byte == b'*');
+                let paths = separator.map(|at| (&data[..at], &data[at + 1..]));
+                let result = match paths {
+                    Some((from, to)) => match (
+                        self.writable_path(from, false).await,
+                        self.writable_path(to, false).await,
+                    ) {
+                        (Ok(from), Ok(to)) => fs::rename(from, to).await,
+                        (Err(e), _) | (_, Err(e)) => Err(e),
+                    },
+                    None => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+                };
+                (RETURN_FILE_RENAME, result)
+            }
+            _ => return Outcome::default(),
+        };
+
+        if let Err(e) = &result {
+            log::warn!(
+                "File transfer command {} failed: {e}",
+                command.content_param
+            );
+        }
+        let size = if result.is_ok() { 0 } else { FAILED };
+        Outcome::reply(Message::new(COMMAND_RETURN, param, size, data))
+    }
+
+    /// Resolves a path the client wants to modify; see [`confine`](Self::confine).
+    async fn writable_path(&self, path: &[u8], follow: bool) -> Result<PathBuf, io::Error> {
+        match self
+            .resolve(path)
+            .filter(|path| !self.read_only && *path != self.root)
+        {
+            Some(resolved) => self.confine(&resolved, follow).await,
+            None => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+        }
+    }
+}
+
+/// Opens a regular file for reading.
+async fn open_file(path: &Path) -> Result<(File, std::fs::Metadata), io::Error> {
+    let file = File::open(path).await?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not a file"));
+    }
+    Ok((file, metadata))
+}
+
+/// Inflates a zlib-compressed block.
+fn inflate(data: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut inflated = Vec::with_capacity(BLOCK_SIZE);
+    ZlibDecoder::new(data)
+        .take(MAX_DATA_LEN as u64)
+        .read_to_end(&mut inflated)?;
+    Ok(inflated)
+}
+
+/// Builds a directory listing entry: a `WIN32_FIND_DATA` record (little-endian) cut
+/// after the file name.
+fn find_data(name: &str, metadata: &std::fs::Metadata) -> Vec<u8> {
+    let mut data = Vec::with_capacity(44 + name.len());
+    let attributes = if metadata.is_dir() {
+        FILE_ATTRIBUTE_DIRECTORY
+    } else {
+        FILE_ATTRIBUTE_NORMAL
+    };
+    data.extend_from_slice(&attributes.to_le_bytes());
+    for time in [metadata.created(), metadata.accessed(), metadata.modified()] {
+        data.extend_from_slice(&filetime(time.ok()).to_le_bytes());
+    }
+    let size = if metadata.is_dir() { 0 } else { metadata.len() };
+    #[allow(clippy::cast_possible_truncation)] // Split into high and low halves
+    {
+        data.extend_from_slice(&((size >> 32) as u32).to_le_bytes());
+        data.extend_from_slice(&(size as u32).to_le_bytes());
+    }
+    data.extend_from_slice(&[0; 8]); // reserved
+    data.extend_from_slice(name.as_bytes());
+    data
+}
+
+/// Converts a time to a Windows `FILETIME` (100 ns intervals since 1601).
+fn filetime(time: Option<SystemTime>) -> u64 {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since_epoch| {
+            (since_epoch.as_secs() + FILETIME_UNIX_OFFSET) * 10_000_000
+                + u64::from(since_epoch.subsec_nanos() / 100)
+        })
+}
+
+/// Formats a time as `MM/DD/YYYY HH:MM` (UTC), the way file headers carry it.
+fn file_time(time: Option<SystemTime>) -> String {
+    let secs = time
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{month:02}/{day:02}/{year:04} {:02}:{:02}",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(root: PathBuf) -> FileTransfer {
+        FileTransfer {
+            root,
+            read_only: false,
+            download: None,
+            upload: None,
+        }
+    }
+
+    #[test]
+    fn resolve_stays_inside_root() {
+        let root = PathBuf::from("share");
+        let transfer = transfer(root.clone());
+
+        assert_eq!(
+            transfer.resolve(b"C:\\docs\\.\\a.txt\0"),
+            Some(root.join("docs").join("a.txt"))
+        );
+        // Separators at the start are not a root of their own
+        assert_eq!(
+            transfer.resolve(b"/etc/passwd"),
+            Some(root.join("etc").join("passwd"))
+        );
+        assert_eq!(transfer.resolve(b"C:\\docs\\..\\..\\secret"), None);
+        assert_eq!(transfer.resolve(b"C:\\x\\D:\\Windows\\win.ini"), None);
+        assert_eq!(transfer.resolve(b"C:\\x\\D:Windows"), None);
+        assert_eq!(transfer.resolve(b"\\\\?\\C:\\Windows"), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symbolic_links_out_of_root_are_refused() {
+        let base = std::env::temp_dir().join(format!("rvnc-ft-{}", std::process::id()));
+        let root = base.join("share");
+        let outside = base.join("outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret"), b"secret").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        std::fs::write(root.join("file"), b"file").unwrap();
+
+        let transfer = transfer(root.clone());
+        let canonical_root = std::fs::canonicalize(&root).unwrap();
+        assert_eq!(
+            transfer.locate(b"C:\\file", true).await.unwrap(),
+            canonical_root.join("file")
+        );
+        assert!(transfer.locate(b"C:\\link\\secret", true).await.is_err());
+        assert!(transfer.locate(b"C:\\link", true).await.is_err());
+        assert!(transfer
+            .writable_path(b"C:\\link\\new", true)
+            .await
+            .is_err());
+        // The link itself can still be removed or renamed
+        assert_eq!(
+            transfer.writable_path(b"C:\\link", false).await.unwrap(),
+            canonical_root.join("link")
+        );
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pull-model frame sources.
+//!
+//! By default the application pushes complete frames into the server's [`Framebuffer`],
+//! which keeps its own RGBA32 copy of the screen (plus a second copy for `CopyRect`
+//! detection). Capture backends that already maintain a screen buffer, such as DRM/KMS
+//! or compositor capture, can instead implement [`FrameSource`] and let the server pull
+//! pixels and damage on demand:
+//!
+//! - Clients that are waiting for updates poll [`FrameSource::poll_damage`] roughly every
+//!   16 ms and merge the returned regions into their dirty lists.
+//! - Sources that learn about changes as they happen (from compositor callbacks or a
+//!   capture thread) can instead report them to the [`DamageSink`] handed to
+//!   [`FrameSource::subscribe`], and leave `poll_damage` to its default.
+//! - When a client encodes an update, only the rectangles it sends are read through
+//!   [`FrameSource::read_rect`].
+//!
+//! Use [`VncServer::with_frame_source`](crate::VncServer::with_frame_source) to create a
+//! server backed by a source. The push-model framebuffer methods (`update_from_slice`,
+//! `update_cropped`, `resize`, `do_copy_region`) return an error on such a framebuffer.
+//!
+//! [`Framebuffer`]: crate::Framebuffer
+
+use std::sync::{Arc, Mutex};
+
+use crate::framebuffer::DirtyRegion;
+
+/// Reported regions kept before they are merged into their bounding box.
+const MAX_PENDING_DAMAGE: usize = 64;
+
+/// A source of framebuffer pixels that the server reads on demand.
+///
+/// Implementations are called from the server's async tasks and must not block for long;
+/// return cached damage and copy pixels from memory rather than capturing synchronously.
+pub trait FrameSource: Send + Sync {
+    /// Returns the current dimensions of the frame as `(width, height)`.
+    ///
+    /// A change in dimensions is treated like a framebuffer resize: the whole frame is
+    /// marked dirty.
+    fn dimensions(&self) -> (u16, u16);
+
+    /// Returns the regions that changed since the previous call.
+    ///
+    /// The server calls this from every client that is waiting for an update, so the
+    /// implementation should hand out each change once and return an empty list when
+    /// nothing changed. Regions extending beyond the frame are clipped.
+    ///
+    /// The default returns no damage, for sources that report it through
+    /// [`subscribe`](Self::subscribe) instead.
+    fn poll_damage(&self) -> Vec<DirtyRegion> {
+        Vec::new()
+    }
+
+    /// Hands the source the sink to report damage to as it happens.
+    ///
+    /// Called once, when the framebuffer backed by this source is created. The default
+    /// drops the sink, for sources that only answer [`poll_damage`](Self::poll_damage).
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Where to report changed regions; it can be kept and used from any thread.
+    fn subscribe(&self, sink: DamageSink) {
+        let _ = sink;
+    }
+
+    /// Copies a rectangle of RGBA32 pixels into `out`.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The X coordinate of the top-left corner of the rectangle.
+    /// * `y` - The Y coordinate of the top-left corner of the rectangle.
+    /// * `width` - The width of the rectangle.
+    /// * `height` - The height of the rectangle.
+    /// * `out` - An empty buffer to append `width * height * 4` bytes of row-major RGBA32
+    ///   pixel data to.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the pixels cannot be read.
+    fn read_rect(
+        &self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        out: &mut Vec<u8>,
+    ) -> Result<(), String>;
+}
+
+/// Collects damage reported by a [`FrameSource`] until the framebuffer it backs polls.
+///
+/// Clones share the same pending damage. Reports are cheap and never block on the
+/// server's async tasks.
+#[derive(Clone, Default)]
+pub struct DamageSink {
+    pending: Arc<Mutex<Vec<DirtyRegion>>>,
+}
+
+impl DamageSink {
+    /// Reports that a region of the frame changed.
+    ///
+    /// Regions extending beyond the frame are clipped. If many regions pile up before the
+    /// next poll, they are merged into their bounding box.
+    ///
+    /// # Arguments
+    ///
+    /// * `region` - The changed region.
+    pub fn report(&self, region: DirtyRegion) {
+        let Ok(mut pending) = self.pending.lock() else {
+            return;
+        };
+        pending.push(region);
+        if pending.len() > MAX_PENDING_DAMAGE {
+            let first = pending[0];
+            let bounds = pending[1..]
+                .iter()
+                .fold(first, |acc, region| acc.merge(region));
+            *pending = vec![bounds];
+        }
+    }
+
+    /// Takes the regions reported since the previous call.
+    pub(crate) fn take(&self) -> Vec<DirtyRegion> {
+        self.pending
+            .lock()
+            .map(|mut pending| std::mem::take(&mut *pending))
+            .unwrap_or_default()
+    }
+}
@@ -33,10 +33,146 @@
 //! 2. Creates a `DirtyRegion` representing this change
 //! 3. Pushes this region to all registered client receivers
 //! 4. Clients merge and batch these regions for efficient transmission
+//!
+//! Vertical scrolls within the changed area are detected by comparing rows with the
+//! previous frame (see `Framebuffer::set_scroll_detection`); the scrolled rows are pushed
+//! to receivers as copies, sent as `CopyRect`, and only the rest of the area as damage.
 
+use std::borrow::Cow;
+use std::ops::{Deref, Range};
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::sync::Weak;
-use tokio::sync::RwLock;
+use std::time::Instant;
+use tokio::sync::{Notify, RwLock};
+
+use crate::config::Transform;
+use crate::encode_cache::EncodeCache;
+use crate::frame_source::{DamageSink, FrameSource};
+use crate::region::{DamageQueue, Region};
+use crate::scroll::{self, Scroll};
+
+/// Error returned by push-model operations on a framebuffer backed by a `FrameSource`.
+const SOURCE_BACKED_ERROR: &str = "Framebuffer is backed by a FrameSource and cannot be written";
+
+/// Layout of pixel data handed to the framebuffer's `*_with_format` update methods.
+///
+/// The framebuffer stores RGBA32 (or its [`StorageFormat`]); other layouts are converted
+/// on the way in, so applications can pass their capture buffers as they come.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    /// 4 bytes per pixel: red, green, blue, alpha.
+    #[default]
+    Rgba32,
+    /// 4 bytes per pixel: blue, green, red, alpha (e.g. Windows and `CoreGraphics`
+    /// captures).
+    Bgra32,
+    /// 4 bytes per pixel: red, green, blue and an unused byte.
+    Rgbx32,
+    /// 3 bytes per pixel: red, green, blue.
+    Rgb24,
+    /// 2 bytes per pixel, a little-endian `u16` with 5 bits of red (highest), 6 of
+    /// green and 5 of blue.
+    Rgb565,
+}
+
+impl InputFormat {
+    /// Returns the size of one pixel in bytes.
+    #[must_use]
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgba32 | Self::Bgra32 | Self::Rgbx32 => 4,
+            Self::Rgb24 => 3,
+            Self::Rgb565 => 2,
+        }
+    }
+
+    /// Converts pixels in this layout to RGBA32, borrowing data that already is.
+    fn to_rgba32(self, data: &[u8]) -> Cow<'_, [u8]> {
+        let pixels = data.chunks_exact(self.bytes_per_pixel());
+        let converted: Vec<u8> = match self {
+            Self::Rgba32 => return Cow::Borrowed(data),
+            Self::Bgra32 => pixels.flat_map(|p| [p[2], p[1], p[0], p[3]]).collect(),
+            Self::Rgbx32 | Self::Rgb24 => pixels.flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            Self::Rgb565 => pixels
+                .flat_map(|p| rgb565_to_rgba32(u16::from_le_bytes([p[0], p[1]])))
+                .collect(),
+        };
+        Cow::Owned(converted)
+    }
+}
+
+/// Layout of the pixels a framebuffer stores (see [`Framebuffer::with_storage`]).
+///
+/// Pixels are read back as RGBA32 whatever the storage, so encoders and the translation
+/// to each client's pixel format are unchanged; only the stored copy of the screen is
+/// smaller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageFormat {
+    /// 4 bytes per pixel: red, green, blue, alpha.
+    #[default]
+    Rgba32,
+    /// 2 bytes per pixel, as [`InputFormat::Rgb565`]: half the memory of RGBA32, for
+    /// memory-constrained targets. Colors keep 5 bits of red and blue and 6 of green,
+    /// which clients with a 16-bit pixel format receive losslessly.
+    Rgb565,
+}
+
+impl StorageFormat {
+    /// Returns the size of one stored pixel in bytes.
+    #[must_use]
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgba32 => 4,
+            Self::Rgb565 => 2,
+        }
+    }
+
+    /// Converts RGBA32 pixels to this layout, borrowing data that already is.
+    fn store_rgba32(self, data: &[u8]) -> Cow<'_, [u8]> {
+        match self {
+            Self::Rgba32 => Cow::Borrowed(data),
+            Self::Rgb565 => Cow::Owned(
+                data.chunks_exact(4)
+                    .flat_map(|p| {
+                        let value = u16::from(p[0] >> 3) << 11
+                            | u16::from(p[1] >> 2) << 5
+                            | u16::from(p[2] >> 3);
+                        value.to_le_bytes()
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Appends pixels in this layout to `out` as RGBA32.
+    fn extend_rgba32(self, data: &[u8], out: &mut Vec<u8>) {
+        match self {
+            Self::Rgba32 => out.extend_from_slice(data),
+            Self::Rgb565 => out.extend(
+                data.chunks_exact(2)
+                    .flat_map(|p| rgb565_to_rgba32(u16::from_le_bytes([p[0], p[1]]))),
+            ),
+        }
+    }
+}
+
+/// Widens an RGB565 pixel to RGBA32, repeating the high bits of each channel in the low
+/// ones so that black and white stay exact.
+fn rgb565_to_rgba32(value: u16) -> [u8; 4] {
+    #[allow(clippy::cast_possible_truncation)] // Each channel has at most 6 bits
+    let [red, green, blue] = [
+        (value >> 11) as u8,
+        (value >> 5 & 0x3f) as u8,
+        (value & 0x1f) as u8,
+    ];
+    [
+        red << 3 | red >> 2,
+        green << 2 | green >> 4,
+        blue << 3 | blue >> 2,
+        255,
+    ]
+}
 
 /// Represents a rectangular region of the framebuffer that has been modified.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -132,6 +268,23 @@ impl DirtyRegion {
         x1 < x2 && y1 < y2
     }
 
+    /// Returns the Chebyshev distance in pixels from the point (`x`, `y`) to this region.
+    ///
+    /// The distance is zero if the point lies inside the region.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The X coordinate of the point.
+    /// * `y` - The Y coordinate of the point.
+    #[must_use]
+    pub fn distance_to(&self, x: u16, y: u16) -> u16 {
+        let right = self.x.saturating_add(self.width).saturating_sub(1);
+        let bottom = self.y.saturating_add(self.height).saturating_sub(1);
+        let dx = self.x.saturating_sub(x).max(x.saturating_sub(right));
+        let dy = self.y.saturating_sub(y).max(y.saturating_sub(bottom));
+        dx.max(dy)
+    }
+
     /// Computes the intersection of two `DirtyRegion`s.
     ///
     /// This function returns a new `DirtyRegion` representing the overlapping area
@@ -171,6 +324,81 @@ impl DirtyRegion {
             None
         }
     }
+
+    /// Clips this `DirtyRegion` to a framebuffer of the given size.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the framebuffer.
+    /// * `height` - The height of the framebuffer.
+    ///
+    /// # Returns
+    ///
+    /// The part of the region inside the framebuffer, or `None` if the region is empty
+    /// or lies entirely outside it.
+    #[must_use]
+    pub fn clamp_to(&self, width: u16, height: u16) -> Option<DirtyRegion> {
+        self.intersect(&DirtyRegion::new(0, 0, width, height))
+    }
+
+    /// Splits the region into tiles of at most `size` x `size` pixels, left to right and
+    /// top to bottom.
+    pub fn tiles(&self, size: u16) -> impl Iterator<Item = DirtyRegion> {
+        let region = *self;
+        let size = size.max(1);
+        (0..region.height)
+            .step_by(usize::from(size))
+            .flat_map(move |y| {
+                (0..region.width).step_by(usize::from(size)).map(move |x| {
+                    DirtyRegion::new(
+                        region.x + x,
+                        region.y + y,
+                        size.min(region.width - x),
+                        size.min(region.height - y),
+                    )
+                })
+            })
+    }
+
+    /// Splits the region into full-width bands of at most `rows` rows, top to bottom.
+    pub fn bands(&self, rows: u16) -> impl Iterator<Item = DirtyRegion> {
+        let region = *self;
+        let rows = rows.max(1);
+        (0..region.height).step_by(usize::from(rows)).map(move |y| {
+            DirtyRegion::new(
+                region.x,
+                region.y + y,
+                region.width,
+                rows.min(region.height - y),
+            )
+        })
+    }
+
+    /// Moves the region by (`dx`, `dy`).
+    ///
+    /// # Returns
+    ///
+    /// The moved region, clipped to the coordinate range, or `None` if nothing of it
+    /// remains.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Clamped to 0..=u16::MAX
+    pub fn translate(&self, dx: i16, dy: i16) -> Option<DirtyRegion> {
+        let left = (i32::from(self.x) + i32::from(dx)).max(0);
+        let top = (i32::from(self.y) + i32::from(dy)).max(0);
+        let right =
+            (i32::from(self.x) + i32::from(self.width) + i32::from(dx)).min(i32::from(u16::MAX));
+        let bottom =
+            (i32::from(self.y) + i32::from(self.height) + i32::from(dy)).min(i32::from(u16::MAX));
+        if right <= left || bottom <= top {
+            return None;
+        }
+        Some(DirtyRegion::new(
+            left as u16,
+            top as u16,
+            (right - left) as u16,
+            (bottom - top) as u16,
+        ))
+    }
 }
 
 /// A struct for receiving notifications about dirty (modified) regions in the framebuffer.
@@ -179,78 +407,371 @@ impl DirtyRegion {
 /// push-based update model, similar to how standard VNC protocol handles dirty region updates.
 #[derive(Clone)]
 pub struct DirtyRegionReceiver {
-    /// A `Weak` reference to a `RwLock`-protected vector of `DirtyRegion`s.
-    regions: Weak<RwLock<Vec<DirtyRegion>>>,
+    /// A `Weak` reference to the owner's `RwLock`-protected damage queue.
+    regions: Weak<RwLock<DamageQueue>>,
+    /// When set, records the arrival time of the oldest damage not yet taken by the owner.
+    first_damage: Option<Weak<Mutex<Option<Instant>>>>,
+    /// When set, notified whenever damage arrives.
+    wakeup: Option<Weak<Notify>>,
+    /// When set, the owner's pending copy destinations and their offset, so copies are
+    /// sent as `CopyRect` instead of damage.
+    copies: Option<CopyTracking>,
+    /// Maps framebuffer coordinates to the owner's.
+    transform: Transform,
 }
 
+/// `Weak` references to an owner's pending copy destinations and their `(dx, dy)` offset.
+type CopyTracking = (Weak<RwLock<Region>>, Weak<RwLock<Option<(i16, i16)>>>);
+
 impl DirtyRegionReceiver {
     /// Creates a new `DirtyRegionReceiver`.
     ///
     /// # Arguments
     ///
-    /// * `regions` - A `Weak` reference to the vector of `DirtyRegion`s to be updated.
+    /// * `regions` - A `Weak` reference to the damage queue to be updated.
     ///
     /// # Returns
     ///
     /// A new `DirtyRegionReceiver` instance.
     #[must_use]
-    pub fn new(regions: Weak<RwLock<Vec<DirtyRegion>>>) -> Self {
-        Self { regions }
+    pub fn new(regions: Weak<RwLock<DamageQueue>>) -> Self {
+        Self {
+            regions,
+            first_damage: None,
+            wakeup: None,
+            copies: None,
+            transform: Transform::Identity,
+        }
+    }
+
+    /// Records when damage arrives, for measuring how long it waits before being sent.
+    ///
+    /// The first region added while `first_damage` is `None` stores the current time;
+    /// the owner takes the value when it sends the damage.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_damage` - A `Weak` reference to the timestamp slot.
+    ///
+    /// # Returns
+    ///
+    /// The receiver with damage timing enabled.
+    #[must_use]
+    pub fn with_damage_time(mut self, first_damage: Weak<Mutex<Option<Instant>>>) -> Self {
+        self.first_damage = Some(first_damage);
+        self
     }
 
-    /// Adds a new dirty region to the receiver's list.
+    /// Wakes the owner whenever damage arrives, so it can stop polling while idle.
     ///
-    /// This function handles merging the new region with any existing intersecting regions
-    /// to maintain a minimal set of non-overlapping dirty regions. It also includes logic
-    /// to prevent memory exhaustion by merging all regions if the list grows too large.
-    /// This behavior is modeled after standard VNC protocol's region merging.
+    /// # Arguments
+    ///
+    /// * `wakeup` - A `Weak` reference to the notification to signal.
+    ///
+    /// # Returns
+    ///
+    /// The receiver with wakeups enabled.
+    #[must_use]
+    pub fn with_wakeup(mut self, wakeup: Weak<Notify>) -> Self {
+        self.wakeup = Some(wakeup);
+        self
+    }
+
+    /// Receives copies (see [`add_copy_region`](Self::add_copy_region)) as pending
+    /// `CopyRect`s rather than damage.
+    ///
+    /// # Arguments
+    ///
+    /// * `copy_regions` - A `Weak` reference to the owner's pending copy destinations.
+    /// * `copy_offset` - A `Weak` reference to the `(dx, dy)` offset of those copies.
+    ///
+    /// # Returns
+    ///
+    /// The receiver with copy tracking enabled.
+    #[must_use]
+    pub fn with_copy_regions(
+        mut self,
+        copy_regions: Weak<RwLock<Region>>,
+        copy_offset: Weak<RwLock<Option<(i16, i16)>>>,
+    ) -> Self {
+        self.copies = Some((copy_regions, copy_offset));
+        self
+    }
+
+    /// Maps the regions reported by the framebuffer through `transform`, for owners that
+    /// show it rotated or mirrored.
+    ///
+    /// Only damage and copies reported by the framebuffer are mapped;
+    /// [`add_dirty_region`](Self::add_dirty_region) and
+    /// [`add_copy_region`](Self::add_copy_region) take the owner's coordinates.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - The transform applied to the framebuffer.
+    ///
+    /// # Returns
+    ///
+    /// The receiver with the transform set.
+    #[must_use]
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Adds damage reported by a `width` x `height` framebuffer, in the owner's
+    /// coordinates.
+    pub(crate) async fn add_framebuffer_region(
+        &self,
+        region: DirtyRegion,
+        width: u16,
+        height: u16,
+    ) {
+        self.add_dirty_region(self.transform.map_region(region, width, height))
+            .await;
+    }
+
+    /// Adds a copy reported by a `width` x `height` framebuffer, in the owner's
+    /// coordinates.
+    pub(crate) async fn add_framebuffer_copy(
+        &self,
+        region: DirtyRegion,
+        (dx, dy): (i16, i16),
+        width: u16,
+        height: u16,
+    ) {
+        let (dx, dy) = self.transform.map_offset(dx, dy);
+        self.add_copy_region(self.transform.map_region(region, width, height), dx, dy)
+            .await;
+    }
+
+    /// Adds a new dirty region to the receiver's queue (see [`DamageQueue::add`]).
+    ///
+    /// With copy tracking, the region is also removed from the pending copies: it is
+    /// sent after them and would overwrite what they copied.
     ///
     /// # Arguments
     ///
     /// * `region` - The `DirtyRegion` to add.
     pub async fn add_dirty_region(&self, region: DirtyRegion) {
-        // Limit number of regions and total pixel count to prevent memory exhaustion
-        // These limits ensure bounded memory usage even with rapid screen changes
-        const MAX_REGIONS: usize = 10;
-        const MAX_TOTAL_PIXELS: usize = 1920 * 1080 * 2; // Approximately 2 Full HD screens
-
+        // Copies are locked before damage, as in `add_copy_region`
+        let copies_arc = self
+            .copies
+            .as_ref()
+            .and_then(|(copies, _)| copies.upgrade());
+        let mut copies = match &copies_arc {
+            Some(copies) => Some(copies.write().await),
+            None => None,
+        };
         if let Some(regions_arc) = self.regions.upgrade() {
             let mut regions = regions_arc.write().await;
+            regions.add(region);
+            if let Some(copies) = copies.as_mut().filter(|copies| !copies.is_empty()) {
+                **copies = copies.subtract(&region.into());
+            }
+            drop((regions, copies));
+            self.notify();
+        }
+    }
 
-            // Merge with ALL intersecting regions (not just first)
-            // This matches standard VNC protocol's proper region merging behavior
-            let mut merged_region = region;
-            regions.retain(|existing| {
-                if existing.intersects(&merged_region) {
-                    merged_region = existing.merge(&merged_region);
-                    false // Remove this region, we've merged it
-                } else {
-                    true // Keep this region
-                }
-            });
+    /// Schedules a copy of the pixels at (`x + dx`, `y + dy`) to `region`.
+    ///
+    /// Receivers created with [`with_copy_regions`](Self::with_copy_regions) queue it for
+    /// `CopyRect`; others receive `region` as damage. As in standard VNC protocol's
+    /// `rfbScheduleCopyRegion`, pending copies with a different offset become damage, and
+    /// the parts of `region` whose source the owner hasn't sent yet (pending damage, or the
+    /// destination of a pending copy) are marked as damage too, so they are resent after
+    /// the copy. The rest of the pending damage inside `region` is dropped, since the
+    /// copy brings the owner up to date there.
+    ///
+    /// # Arguments
+    ///
+    /// * `region` - The destination of the copy.
+    /// * `dx` - The X offset from destination to source (`src_x` = `dest_x` + dx).
+    /// * `dy` - The Y offset from destination to source (`src_y` = `dest_y` + dy).
+    pub async fn add_copy_region(&self, region: DirtyRegion, dx: i16, dy: i16) {
+        let Some((copy_regions, copy_offset)) = &self.copies else {
+            self.add_dirty_region(region).await;
+            return;
+        };
+        let (Some(copies_arc), Some(offset_arc), Some(regions_arc)) = (
+            copy_regions.upgrade(),
+            copy_offset.upgrade(),
+            self.regions.upgrade(),
+        ) else {
+            return;
+        };
+        let mut copies = copies_arc.write().await;
+        let mut offset = offset_arc.write().await;
+        let mut regions = regions_arc.write().await;
 
-            // Add the final merged region
-            regions.push(merged_region);
-
-            let total_pixels: usize = regions
-                .iter()
-                .map(|r| (r.width as usize) * (r.height as usize))
-                .sum();
-
-            if regions.len() > MAX_REGIONS || total_pixels > MAX_TOTAL_PIXELS {
-                // If limits exceeded, merge all regions into one to prevent unbounded growth
-                // This trades granularity for memory safety
-                if let Some(first) = regions.first().copied() {
-                    let merged = regions.iter().skip(1).fold(first, |acc, r| acc.merge(r));
-                    regions.clear();
-                    regions.push(merged);
-                }
+        if offset.is_some_and(|offset| offset != (dx, dy)) {
+            for &pending in std::mem::take(&mut *copies).rects() {
+                regions.add(pending);
             }
         }
+        let destination = Region::from(region);
+        let stale = copies
+            .union(regions.region())
+            .translate(dx.saturating_neg(), dy.saturating_neg())
+            .intersect(&destination);
+        // The copy replaces the destination's pending damage, except where its source
+        // is stale too (standard VNC protocol's modifiedRegion minus copyRegion)
+        regions.subtract(&destination);
+        for &stale in stale.rects() {
+            regions.add(stale);
+        }
+        *copies = copies.union(&destination);
+        *offset = Some((dx, dy));
+        drop((copies, offset, regions));
+        self.notify();
+    }
+
+    /// Records the arrival of damage and wakes the owner.
+    fn notify(&self) {
+        if let Some(first_damage) = self.first_damage.as_ref().and_then(Weak::upgrade) {
+            if let Ok(mut first_damage) = first_damage.lock() {
+                first_damage.get_or_insert_with(Instant::now);
+            }
+        }
+        if let Some(wakeup) = self.wakeup.as_ref().and_then(Weak::upgrade) {
+            wakeup.notify_one();
+        }
+    }
+}
+
+/// RGBA32 pixels of a rectangle, row by row.
+///
+/// Rectangles read from a [`FrameSnapshot`] whose rows are contiguous in the frame (the
+/// full width of an RGBA32 frame, or a single row) share the snapshot's pixels instead of
+/// copying them. Either way the pixels can be moved to another thread cheaply.
+#[derive(Clone, Default)]
+pub struct RectPixels {
+    pixels: Arc<Vec<u8>>,
+    range: Range<usize>,
+}
+
+impl RectPixels {
+    /// Returns the pixels as an owned `Vec`, copying them only if they are shared.
+    #[must_use]
+    pub fn into_vec(self) -> Vec<u8> {
+        if self.range == (0..self.pixels.len()) {
+            Arc::try_unwrap(self.pixels).unwrap_or_else(|pixels| pixels.to_vec())
+        } else {
+            self.pixels[self.range].to_vec()
+        }
+    }
+}
+
+impl From<Vec<u8>> for RectPixels {
+    fn from(pixels: Vec<u8>) -> Self {
+        let range = 0..pixels.len();
+        Self {
+            pixels: Arc::new(pixels),
+            range,
+        }
+    }
+}
+
+impl Deref for RectPixels {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.pixels[self.range.clone()]
+    }
+}
+
+/// The framebuffer's pixels at one point in time (see [`Framebuffer::snapshot`]).
+///
+/// Holding a snapshot doesn't block the framebuffer: the next update while it is held
+/// writes to a copy of the pixels.
+pub struct FrameSnapshot {
+    pixels: Arc<Vec<u8>>,
+    storage: StorageFormat,
+    width: u16,
+    height: u16,
+    /// The framebuffer's encode cache generation when the snapshot was taken.
+    generation: u64,
+}
+
+impl FrameSnapshot {
+    /// Returns the encode cache generation when the snapshot was taken, for storing
+    /// rectangles encoded from it.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the width of the snapshot.
+    #[must_use]
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Returns the height of the snapshot.
+    #[must_use]
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Retrieves the pixel data of a rectangle of the snapshot, as RGBA32.
+    ///
+    /// Rectangles whose rows are contiguous in the frame share the snapshot's pixels;
+    /// others are copied (see [`RectPixels`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The X coordinate of the top-left corner of the rectangle.
+    /// * `y` - The Y coordinate of the top-left corner of the rectangle.
+    /// * `width` - The width of the rectangle.
+    /// * `height` - The height of the rectangle.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the rectangle is out of the snapshot's bounds.
+    pub fn get_rect(&self, x: u16, y: u16, width: u16, height: u16) -> Result<RectPixels, String> {
+        if x.saturating_add(width) > self.width || y.saturating_add(height) > self.height {
+            return Err(format!(
+                "Rectangle out of bounds: ({}, {}, {}, {}) exceeds ({}, {})",
+                x, y, width, height, self.width, self.height
+            ));
+        }
+        if self.storage == StorageFormat::Rgba32 && (width == self.width || height <= 1) {
+            let start = (usize::from(y) * usize::from(self.width) + usize::from(x)) * 4;
+            let end = start + usize::from(width) * usize::from(height) * 4;
+            return Ok(RectPixels {
+                pixels: Arc::clone(&self.pixels),
+                range: start..end,
+            });
+        }
+        let mut result = Vec::with_capacity(usize::from(width) * usize::from(height) * 4);
+        copy_rect(
+            &self.pixels,
+            self.storage,
+            self.width,
+            (x, y, width, height),
+            &mut result,
+        );
+        Ok(result.into())
+    }
+}
+
+/// Appends the rows of the rectangle (`x`, `y`, `width`, `height`) of pixels stored as
+/// `storage`, `stride` pixels wide, to `out` as RGBA32.
+fn copy_rect(
+    data: &[u8],
+    storage: StorageFormat,
+    stride: u16,
+    (x, y, width, height): (u16, u16, u16, u16),
+    out: &mut Vec<u8>,
+) {
+    let bpp = storage.bytes_per_pixel();
+    for row in y..(y + height) {
+        let start = ((row as usize) * (stride as usize) + (x as usize)) * bpp;
+        let end = start + (width as usize) * bpp;
+        storage.extend_rgba32(&data[start..end], out);
     }
 }
 
-use std::sync::atomic::{AtomicU16, Ordering as AtomicOrdering};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering as AtomicOrdering};
 
 /// Represents the VNC server's framebuffer.
 ///
@@ -263,12 +784,23 @@ pub struct Framebuffer {
     width: Arc<AtomicU16>,
     /// The height of the framebuffer in pixels (uses atomic for interior mutability).
     height: Arc<AtomicU16>,
-    /// The raw pixel data of the framebuffer, stored as an `Arc<RwLock<Vec<u8>>>` for shared, mutable access.
-    data: Arc<RwLock<Vec<u8>>>,
+    /// The raw pixel data of the framebuffer. The inner `Arc` is shared with snapshots
+    /// (see `snapshot`); writes copy the pixels first while one is still held.
+    data: Arc<RwLock<Arc<Vec<u8>>>>,
     /// A list of `DirtyRegionReceiver`s to be notified when parts of the framebuffer are modified.
     receivers: Arc<RwLock<Vec<DirtyRegionReceiver>>>,
     /// A copy of the previous framebuffer data, used for detecting `CopyRect` encoding opportunities.
     prev_data: Arc<RwLock<Vec<u8>>>,
+    /// Layout of the pixels in `data` and `prev_data`.
+    storage: StorageFormat,
+    /// Optional pull-model source; when set, pixels are read from it instead of `data`.
+    source: Option<Arc<dyn FrameSource>>,
+    /// Damage the source reported as it happened, collected by `poll_source`.
+    source_damage: DamageSink,
+    /// Whether updates are checked for vertical scrolls (see `set_scroll_detection`).
+    scroll_detection: Arc<AtomicBool>,
+    /// Encoded rectangles shared between the clients of this framebuffer.
+    encode_cache: Arc<EncodeCache>,
 }
 
 impl Framebuffer {
@@ -284,13 +816,144 @@ impl Framebuffer {
     /// A new `Framebuffer` instance.
     #[must_use]
     pub fn new(width: u16, height: u16) -> Self {
-        let size = (width as usize) * (height as usize) * 4; // RGBA32
+        Self::with_storage(width, height, StorageFormat::Rgba32)
+    }
+
+    /// Creates a new `Framebuffer` that stores its pixels in `storage`.
+    ///
+    /// Updates still take RGBA32 (or any [`InputFormat`]) and reads still return RGBA32;
+    /// pixels are converted on the way in and out. With [`StorageFormat::Rgb565`] the
+    /// framebuffer needs half the memory, e.g. 16 MB instead of 32 MB per copy of a 4K
+    /// screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the framebuffer in pixels.
+    /// * `height` - The height of the framebuffer in pixels.
+    /// * `storage` - The layout of the stored pixels.
+    ///
+    /// # Returns
+    ///
+    /// A new `Framebuffer` instance.
+    #[must_use]
+    pub fn with_storage(width: u16, height: u16, storage: StorageFormat) -> Self {
+        let size = (width as usize) * (height as usize) * storage.bytes_per_pixel();
         Self {
             width: Arc::new(AtomicU16::new(width)),
             height: Arc::new(AtomicU16::new(height)),
-            data: Arc::new(RwLock::new(vec![0; size])),
+            data: Arc::new(RwLock::new(Arc::new(vec![0; size]))),
             receivers: Arc::new(RwLock::new(Vec::new())),
             prev_data: Arc::new(RwLock::new(vec![0; size])),
+            storage,
+            source: None,
+            source_damage: DamageSink::default(),
+            scroll_detection: Arc::new(AtomicBool::new(true)),
+            encode_cache: Arc::new(EncodeCache::default()),
+        }
+    }
+
+    /// Creates a `Framebuffer` that reads pixels and damage from a `FrameSource`.
+    ///
+    /// No pixel storage is allocated; `get_rect` reads directly from the source and damage
+    /// is collected by `poll_source`. The source is subscribed to a [`DamageSink`] here.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source that provides the frame contents.
+    ///
+    /// # Returns
+    ///
+    /// A new `Framebuffer` instance backed by `source`.
+    #[must_use]
+    pub fn from_source(source: Arc<dyn FrameSource>) -> Self {
+        let (width, height) = source.dimensions();
+        let source_damage = DamageSink::default();
+        source.subscribe(source_damage.clone());
+        Self {
+            width: Arc::new(AtomicU16::new(width)),
+            height: Arc::new(AtomicU16::new(height)),
+            data: Arc::new(RwLock::new(Arc::new(Vec::new()))),
+            receivers: Arc::new(RwLock::new(Vec::new())),
+            prev_data: Arc::new(RwLock::new(Vec::new())),
+            storage: StorageFormat::Rgba32,
+            source: Some(source),
+            source_damage,
+            scroll_detection: Arc::new(AtomicBool::new(true)),
+            encode_cache: Arc::new(EncodeCache::default()),
+        }
+    }
+
+    /// Returns whether `other` is a handle to this same framebuffer.
+    pub(crate) fn same_as(&self, other: &Framebuffer) -> bool {
+        Arc::ptr_eq(&self.receivers, &other.receivers)
+    }
+
+    /// Returns the cache of encoded rectangles shared by this framebuffer's clients.
+    pub(crate) fn encode_cache(&self) -> &EncodeCache {
+        &self.encode_cache
+    }
+
+    /// Returns the layout of the stored pixels.
+    #[must_use]
+    pub fn storage(&self) -> StorageFormat {
+        self.storage
+    }
+
+    /// Returns `true` if this framebuffer is backed by a `FrameSource`.
+    #[must_use]
+    pub fn is_source_backed(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Enables or disables scroll detection. Default: enabled.
+    ///
+    /// With scroll detection, `update_from_slice` and `update_cropped` compare the rows of
+    /// the changed area with the previous frame. Rows that moved vertically are sent to
+    /// clients as `CopyRect` and only the rest of the area is re-encoded, which saves most
+    /// of the bandwidth of scrolling documents and lists. Applications that scroll through
+    /// `VncServer::do_copy_rect` themselves, or whose content never scrolls, can disable it
+    /// to save the row comparison.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to detect scrolls.
+    pub fn set_scroll_detection(&self, enabled: bool) {
+        self.scroll_detection
+            .store(enabled, AtomicOrdering::Relaxed);
+    }
+
+    /// Collects damage from the `FrameSource`, if any, polled or reported to its
+    /// [`DamageSink`], and notifies all receivers.
+    ///
+    /// A change in the source's dimensions marks the whole frame dirty. Does nothing for
+    /// push-model framebuffers.
+    pub async fn poll_source(&self) {
+        let Some(source) = &self.source else {
+            return;
+        };
+
+        let (width, height) = source.dimensions();
+        if width != self.width() || height != self.height() {
+            self.width.store(width, AtomicOrdering::Release);
+            self.height.store(height, AtomicOrdering::Release);
+            // The full-frame region below supersedes any pending damage
+            let _ = source.poll_damage();
+            let _ = self.source_damage.take();
+            self.encode_cache.invalidate(None);
+            self.mark_dirty_region(0, 0, width, height).await;
+            return;
+        }
+
+        let bounds = DirtyRegion::new(0, 0, width, height);
+        for region in source
+            .poll_damage()
+            .into_iter()
+            .chain(self.source_damage.take())
+        {
+            if let Some(clipped) = region.intersect(&bounds) {
+                self.mark_dirty_region(clipped.x, clipped.y, clipped.width, clipped.height)
+                    .await;
+            }
         }
     }
 
@@ -307,6 +970,26 @@ impl Framebuffer {
         receivers.push(receiver);
     }
 
+    /// Stops notifying the receiver backed by `regions`.
+    ///
+    /// # Arguments
+    ///
+    /// * `regions` - The damage queue a `DirtyRegionReceiver` was created for.
+    pub async fn unregister_receiver(&self, regions: &Arc<RwLock<DamageQueue>>) {
+        let target = Arc::downgrade(regions);
+        let mut receivers = self.receivers.write().await;
+        receivers.retain(|r| !r.regions.ptr_eq(&target));
+    }
+
+    /// Returns the number of clients registered for dirty region notifications.
+    pub(crate) async fn receiver_count(&self) -> usize {
+        let receivers = self.receivers.read().await;
+        receivers
+            .iter()
+            .filter(|r| r.regions.strong_count() > 0)
+            .count()
+    }
+
     /// Removes dead `Weak` references from the list of `DirtyRegionReceiver`s.
     ///
     /// This function is called periodically to clean up receivers for clients that have disconnected.
@@ -318,6 +1001,7 @@ impl Framebuffer {
     /// Marks a rectangular region of the framebuffer as dirty and notifies all registered receivers.
     ///
     /// This behavior is analogous to standard VNC protocol's `rfbMarkRegionAsModified` function.
+    /// The region is clipped to the framebuffer; regions entirely outside it are ignored.
     ///
     /// # Arguments
     ///
@@ -326,7 +1010,12 @@ impl Framebuffer {
     /// * `width` - The width of the dirty region.
     /// * `height` - The height of the dirty region.
     pub async fn mark_dirty_region(&self, x: u16, y: u16, width: u16, height: u16) {
-        let region = DirtyRegion::new(x, y, width, height);
+        let Some(region) =
+            DirtyRegion::new(x, y, width, height).clamp_to(self.width(), self.height())
+        else {
+            return;
+        };
+        self.encode_cache.invalidate(Some(&region));
 
         // Clone receivers while holding lock briefly to prevent deadlock
         // (standard VNC protocol uses client iterator for similar thread safety)
@@ -338,13 +1027,83 @@ impl Framebuffer {
         // Notify all receivers without holding receivers lock
         // This prevents deadlock if add_dirty_region acquires other locks
         for receiver in &receivers_copy {
-            receiver.add_dirty_region(region).await;
+            receiver
+                .add_framebuffer_region(region, self.width(), self.height())
+                .await;
         }
 
         // Clean up dead receivers
         self.cleanup_receivers().await;
     }
 
+    /// Notifies all receivers that `region` was copied from (`x + dx`, `y + dy`).
+    ///
+    /// Receivers that track copies send it as `CopyRect`; the others see it as damage.
+    pub(crate) async fn mark_copy_region(&self, region: DirtyRegion, dx: i16, dy: i16) {
+        self.encode_cache.invalidate(Some(&region));
+        let receivers_copy = {
+            let receivers = self.receivers.read().await;
+            receivers.clone()
+        };
+        for receiver in &receivers_copy {
+            receiver
+                .add_framebuffer_copy(region, (dx, dy), self.width(), self.height())
+                .await;
+        }
+        self.cleanup_receivers().await;
+    }
+
+    /// Marks a changed area dirty, except for the rows `scroll` moved, which are marked as
+    /// a copy.
+    #[allow(clippy::cast_possible_truncation)] // Scroll rows and offsets lie within the area
+    async fn mark_changed(&self, region: DirtyRegion, scroll: Option<Scroll>) {
+        let Some(scroll) = scroll else {
+            self.mark_dirty_region(region.x, region.y, region.width, region.height)
+                .await;
+            return;
+        };
+
+        // The copy goes first, so the remaining damage isn't counted as a stale source
+        let top = region.y + scroll.top as u16;
+        let bottom = top + scroll.rows as u16;
+        let copied = DirtyRegion::new(region.x, top, region.width, scroll.rows as u16);
+        self.mark_copy_region(copied, 0, scroll.dy as i16).await;
+        if top > region.y {
+            self.mark_dirty_region(region.x, region.y, region.width, top - region.y)
+                .await;
+        }
+        let region_bottom = region.y + region.height;
+        if bottom < region_bottom {
+            self.mark_dirty_region(region.x, bottom, region.width, region_bottom - bottom)
+                .await;
+        }
+    }
+
+    /// Looks for a vertical scroll of the area (`x`, `y`, `width`, `height`) between the
+    /// current contents of the framebuffer and `new_row(y)`, the new contents of row `y`
+    /// of the area, if scroll detection is enabled.
+    fn detect_scroll<'a>(
+        &self,
+        current: &'a [u8],
+        region: DirtyRegion,
+        new_row: impl Fn(usize) -> &'a [u8],
+    ) -> Option<Scroll> {
+        if !self.scroll_detection.load(AtomicOrdering::Relaxed) {
+            return None;
+        }
+        let bpp = self.storage.bytes_per_pixel();
+        let row_bytes = self.width() as usize * bpp;
+        let (start, end) = (
+            region.x as usize * bpp,
+            (region.x + region.width) as usize * bpp,
+        );
+        let old_rows: Vec<&[u8]> = (region.y as usize..(region.y + region.height) as usize)
+            .map(|y| &current[y * row_bytes + start..y * row_bytes + end])
+            .collect();
+        let new_rows: Vec<&[u8]> = (0..region.height as usize).map(new_row).collect();
+        scroll::detect_vertical_scroll(&old_rows, &new_rows)
+    }
+
     /// Returns the width of the framebuffer.
     #[must_use]
     pub fn width(&self) -> u16 {
@@ -366,6 +1125,7 @@ impl Framebuffer {
     /// # Arguments
     ///
     /// * `data` - A slice containing the new RGBA32 pixel data for the entire framebuffer.
+    ///   It is converted first if the framebuffer stores another layout.
     ///
     /// # Returns
     ///
@@ -380,6 +1140,10 @@ impl Framebuffer {
     /// May panic if the framebuffer dimensions are invalid or if internal state is corrupted.
     #[allow(clippy::cast_possible_truncation)] // Intentional: converting row indices to u16 coordinates
     pub async fn update_from_slice(&self, data: &[u8]) -> Result<(), String> {
+        if self.source.is_some() {
+            return Err(SOURCE_BACKED_ERROR.to_string());
+        }
+
         let expected_size = (self.width() as usize) * (self.height() as usize) * 4;
         if data.len() != expected_size {
             return Err(format!(
@@ -389,10 +1153,12 @@ impl Framebuffer {
             ));
         }
 
+        let data = self.storage.store_rgba32(data);
+        let bpp = self.storage.bytes_per_pixel();
         let mut fb = self.data.write().await;
 
         let width_usize = self.width() as usize;
-        let row_bytes = width_usize * 4;
+        let row_bytes = width_usize * bpp;
         let mut changed = false;
         let mut min_y = 0;
         let mut max_y = 0;
@@ -419,8 +1185,8 @@ impl Framebuffer {
             // Now find the exact horizontal bounds but only within the changed rows
             for y in min_y..=max_y {
                 for x in 0..self.width() {
-                    let offset = ((y as usize) * width_usize + (x as usize)) * 4;
-                    if fb[offset..offset + 4] != data[offset..offset + 4] {
+                    let offset = ((y as usize) * width_usize + (x as usize)) * bpp;
+                    if fb[offset..offset + bpp] != data[offset..offset + bpp] {
                         min_x = min_x.min(x);
                         max_x = max_x.max(x);
                     }
@@ -429,22 +1195,65 @@ impl Framebuffer {
         }
 
         if changed {
-            fb.copy_from_slice(data);
+            // Mark only the changed rectangle as dirty
+            // Calculate proper width and height (inclusive to exclusive conversion)
+            let region = DirtyRegion::new(min_x, min_y, max_x - min_x + 1, max_y - min_y + 1);
+            let (start, end) = (min_x as usize * bpp, (max_x as usize + 1) * bpp);
+            let scroll = self.detect_scroll(&fb, region, |row| {
+                let offset = (min_y as usize + row) * row_bytes;
+                &data[offset + start..offset + end]
+            });
+
+            // A snapshot still being encoded keeps the previous frame's buffer
+            match Arc::get_mut(&mut fb) {
+                Some(pixels) => pixels.copy_from_slice(&data),
+                None => *fb = Arc::new(data.into_owned()),
+            }
             drop(fb); // Release lock before other operations
 
             // Save state for CopyRect detection
             self.save_state().await;
 
-            // Mark only the changed rectangle as dirty
-            // Calculate proper width and height (inclusive to exclusive conversion)
-            let width = max_x - min_x + 1;
-            let height = max_y - min_y + 1;
-            self.mark_dirty_region(min_x, min_y, width, height).await;
+            self.mark_changed(region, scroll).await;
         }
 
         Ok(())
     }
 
+    /// Updates the entire framebuffer from pixel data in another layout than RGBA32.
+    ///
+    /// The data is converted to RGBA32 and handed to
+    /// [`update_from_slice`](Self::update_from_slice).
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The new pixel data for the entire framebuffer, row by row.
+    /// * `format` - The layout of `data`.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the update is successful.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the provided data slice has an incorrect size.
+    pub async fn update_from_slice_with_format(
+        &self,
+        data: &[u8],
+        format: InputFormat,
+    ) -> Result<(), String> {
+        let expected_size =
+            usize::from(self.width()) * usize::from(self.height()) * format.bytes_per_pixel();
+        if data.len() != expected_size {
+            return Err(format!(
+                "Invalid data size: expected {}, got {}",
+                expected_size,
+                data.len()
+            ));
+        }
+        self.update_from_slice(&format.to_rgba32(data)).await
+    }
+
     /// Retrieves the pixel data for a specific rectangular region of the framebuffer.
     ///
     /// # Arguments
@@ -481,26 +1290,121 @@ impl Framebuffer {
             ));
         }
 
-        let data = self.data.read().await;
         let mut result = Vec::with_capacity((width as usize) * (height as usize) * 4);
 
-        for row in y..(y + height) {
-            let start = ((row as usize) * (self.width() as usize) + (x as usize)) * 4;
-            let end = start + (width as usize) * 4;
-            result.extend_from_slice(&data[start..end]);
+        if let Some(source) = &self.source {
+            source.read_rect(x, y, width, height, &mut result)?;
+            if result.len() != result.capacity() {
+                return Err(format!(
+                    "FrameSource returned {} bytes for a {}x{} rectangle",
+                    result.len(),
+                    width,
+                    height
+                ));
+            }
+            return Ok(result);
         }
 
+        let data = self.data.read().await;
+        copy_rect(
+            &data,
+            self.storage,
+            self.width(),
+            (x, y, width, height),
+            &mut result,
+        );
         Ok(result)
     }
 
+    /// Takes a snapshot of the current frame, so that all rectangles of an update are
+    /// encoded from the same frame while the application writes the next one.
+    ///
+    /// The snapshot shares the framebuffer's pixels; the next write while it is held goes
+    /// to a copy. Returns `None` for a framebuffer backed by a `FrameSource`, which is
+    /// read directly.
+    pub async fn snapshot(&self) -> Option<FrameSnapshot> {
+        if self.source.is_some() {
+            return None;
+        }
+        // Read before the pixels, like the generation of a rectangle read directly
+        let generation = self.encode_cache.generation();
+        let pixels = Arc::clone(&*self.data.read().await);
+        let (width, height) = (self.width(), self.height());
+        // A resize in progress has replaced the pixels but not the size yet
+        let size = usize::from(width) * usize::from(height) * self.storage.bytes_per_pixel();
+        (pixels.len() == size).then_some(FrameSnapshot {
+            pixels,
+            storage: self.storage,
+            width,
+            height,
+            generation,
+        })
+    }
+
     /// Returns a copy of the entire framebuffer's pixel data.
     ///
     /// # Returns
     ///
-    /// A `Vec<u8>` containing the full framebuffer data.
+    /// A `Vec<u8>` containing the full framebuffer data, as RGBA32.
     #[allow(dead_code)]
     pub async fn get_full_data(&self) -> Vec<u8> {
-        self.data.read().await.clone()
+        if self.source.is_some() || self.storage != StorageFormat::Rgba32 {
+            return self
+                .get_rect(0, 0, self.width(), self.height())
+                .await
+                .unwrap_or_default();
+        }
+        self.data.read().await.to_vec()
+    }
+
+    /// Updates a cropped region of the framebuffer from pixel data in another layout than
+    /// RGBA32.
+    ///
+    /// The data is converted to RGBA32 and handed to
+    /// [`update_cropped`](Self::update_cropped).
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The new pixel data for the cropped region, row by row.
+    /// * `format` - The layout of `data`.
+    /// * `crop_x` - The X coordinate of the top-left corner of the crop region.
+    /// * `crop_y` - The Y coordinate of the top-left corner of the crop region.
+    /// * `crop_width` - The width of the crop region.
+    /// * `crop_height` - The height of the crop region.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the update is successful.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the crop region is out of bounds or the data size is incorrect.
+    pub async fn update_cropped_with_format(
+        &self,
+        data: &[u8],
+        format: InputFormat,
+        crop_x: u16,
+        crop_y: u16,
+        crop_width: u16,
+        crop_height: u16,
+    ) -> Result<(), String> {
+        let expected_size =
+            usize::from(crop_width) * usize::from(crop_height) * format.bytes_per_pixel();
+        if data.len() != expected_size {
+            return Err(format!(
+                "Invalid crop data size: expected {}, got {}",
+                expected_size,
+                data.len()
+            ));
+        }
+        self.update_cropped(
+            &format.to_rgba32(data),
+            crop_x,
+            crop_y,
+            crop_width,
+            crop_height,
+        )
+        .await
     }
 
     /// Updates a specified cropped region of the framebuffer with new data.
@@ -512,7 +1416,8 @@ impl Framebuffer {
     ///
     /// # Arguments
     ///
-    /// * `data` - A slice containing the new RGBA32 pixel data for the cropped region.
+    /// * `data` - A slice containing the new RGBA32 pixel data for the cropped region. It
+    ///   is converted first if the framebuffer stores another layout.
     /// * `crop_x` - The X coordinate of the top-left corner of the crop region.
     /// * `crop_y` - The Y coordinate of the top-left corner of the crop region.
     /// * `crop_width` - The width of the crop region.
@@ -533,6 +1438,10 @@ impl Framebuffer {
         crop_width: u16,
         crop_height: u16,
     ) -> Result<(), String> {
+        if self.source.is_some() {
+            return Err(SOURCE_BACKED_ERROR.to_string());
+        }
+
         // Validate crop region with overflow protection
         if crop_x.saturating_add(crop_width) > self.width() {
             return Err(format!(
@@ -560,6 +1469,8 @@ impl Framebuffer {
             ));
         }
 
+        let data = self.storage.store_rgba32(data);
+        let bpp = self.storage.bytes_per_pixel();
         let mut fb = self.data.write().await;
 
         let mut changed = false;
@@ -571,10 +1482,10 @@ impl Framebuffer {
         let frame_width_usize = self.width() as usize;
 
         for y in 0..crop_height {
-            let src_offset = (y as usize) * crop_width_usize * 4;
-            let dst_offset = ((crop_y + y) as usize * frame_width_usize + crop_x as usize) * 4;
-            let src_row = &data[src_offset..src_offset + crop_width_usize * 4];
-            let dst_row = &fb[dst_offset..dst_offset + crop_width_usize * 4];
+            let src_offset = (y as usize) * crop_width_usize * bpp;
+            let dst_offset = ((crop_y + y) as usize * frame_width_usize + crop_x as usize) * bpp;
+            let src_row = &data[src_offset..src_offset + crop_width_usize * bpp];
+            let dst_row = &fb[dst_offset..dst_offset + crop_width_usize * bpp];
 
             if src_row != dst_row {
                 let abs_y = crop_y + y;
@@ -582,16 +1493,13 @@ impl Framebuffer {
                 max_y = max_y.max(abs_y);
 
                 for x in 0..crop_width {
-                    let px_offset = x as usize * 4;
-                    if src_row[px_offset..px_offset + 4] != dst_row[px_offset..px_offset + 4] {
+                    let px_offset = x as usize * bpp;
+                    if src_row[px_offset..px_offset + bpp] != dst_row[px_offset..px_offset + bpp] {
                         let abs_x = crop_x + x;
                         min_x = min_x.min(abs_x);
                         max_x = max_x.max(abs_x);
                     }
                 }
-
-                // Update the framebuffer row
-                fb[dst_offset..dst_offset + crop_width_usize * 4].copy_from_slice(src_row);
                 changed = true;
             }
         }
@@ -599,12 +1507,31 @@ impl Framebuffer {
         if changed {
             let width = (max_x - min_x + 1).min(self.width() - min_x);
             let height = (max_y - min_y + 1).min(self.height() - min_y);
+            let region = DirtyRegion::new(min_x, min_y, width, height);
+
+            // The previous frame is still in place for scroll detection
+            let start = (min_x - crop_x) as usize * bpp;
+            let end = start + width as usize * bpp;
+            let scroll = self.detect_scroll(&fb, region, |row| {
+                let offset = (min_y - crop_y) as usize * crop_width_usize * bpp
+                    + row * crop_width_usize * bpp;
+                &data[offset + start..offset + end]
+            });
+
+            // Update the changed framebuffer rows
+            let pixels = Arc::make_mut(&mut fb);
+            for y in min_y..=max_y {
+                let src_offset = (y - crop_y) as usize * crop_width_usize * bpp;
+                let dst_offset = (y as usize * frame_width_usize + crop_x as usize) * bpp;
+                pixels[dst_offset..dst_offset + crop_width_usize * bpp]
+                    .copy_from_slice(&data[src_offset..src_offset + crop_width_usize * bpp]);
+            }
             drop(fb); // Release lock before marking dirty
 
             // Save state for CopyRect detection
             self.save_state().await;
 
-            self.mark_dirty_region(min_x, min_y, width, height).await;
+            self.mark_changed(region, scroll).await;
         }
 
         Ok(())
@@ -634,6 +1561,9 @@ impl Framebuffer {
     pub async fn detect_copy_rect(&self, region: &DirtyRegion) -> Option<(u16, u16)> {
         // Don't detect copy for very small regions (not worth the CPU cost)
         const MIN_COPY_SIZE: u16 = 64;
+        if self.source.is_some() {
+            return None;
+        }
         if region.width < MIN_COPY_SIZE || region.height < MIN_COPY_SIZE {
             return None;
         }
@@ -650,10 +1580,11 @@ impl Framebuffer {
             }
 
             // Extract current region data (what we're comparing)
+            let bpp = self.storage.bytes_per_pixel();
             let mut current = Vec::new();
             for row in region.y..(region.y + region.height) {
-                let start = ((row as usize) * (self.width() as usize) + (region.x as usize)) * 4;
-                let end = start + (region.width as usize) * 4;
+                let start = ((row as usize) * (self.width() as usize) + (region.x as usize)) * bpp;
+                let end = start + (region.width as usize) * bpp;
                 current.extend_from_slice(&data[start..end]);
             }
 
@@ -720,13 +1651,14 @@ impl Framebuffer {
                 let src_row = (src_y as u16) + row_idx;
 
                 // Calculate offset in current_region_data
-                let current_row_start = (row_idx as usize) * (region.width as usize) * 4;
-                let current_row_end = current_row_start + (region.width as usize) * 4;
+                let bpp = self.storage.bytes_per_pixel();
+                let current_row_start = (row_idx as usize) * (region.width as usize) * bpp;
+                let current_row_end = current_row_start + (region.width as usize) * bpp;
 
                 // Calculate offset in prev_full_data
                 let prev_row_start =
-                    ((src_row as usize) * (self.width() as usize) + (src_x as usize)) * 4;
-                let prev_row_end = prev_row_start + (region.width as usize) * 4;
+                    ((src_row as usize) * (self.width() as usize) + (src_x as usize)) * bpp;
+                let prev_row_end = prev_row_start + (region.width as usize) * bpp;
 
                 // Bounds check for prev_full_data
                 if prev_row_end > prev_full_data.len() {
@@ -758,6 +1690,9 @@ impl Framebuffer {
     /// `detect_copy_rect` to identify scrolling and copying operations. Should be called
     /// after each framebuffer update to maintain accurate copy detection.
     pub async fn save_state(&self) {
+        if self.source.is_some() {
+            return;
+        }
         let data = self.data.read().await;
         let mut prev = self.prev_data.write().await;
         prev.copy_from_slice(&data);
@@ -791,6 +1726,10 @@ impl Framebuffer {
     pub async fn resize(&self, new_width: u16, new_height: u16) -> Result<(), String> {
         const MAX_DIMENSION: u16 = 8192;
 
+        if self.source.is_some() {
+            return Err(SOURCE_BACKED_ERROR.to_string());
+        }
+
         // Validate dimensions
         if new_width == 0 || new_height == 0 {
             return Err("Framebuffer dimensions must be greater than zero".to_string());
@@ -803,7 +1742,8 @@ impl Framebuffer {
         }
 
         // Calculate new size
-        let new_size = (new_width as usize) * (new_height as usize) * 4;
+        let bpp = self.storage.bytes_per_pixel();
+        let new_size = (new_width as usize) * (new_height as usize) * bpp;
 
         let old_width = self.width();
         let old_height = self.height();
@@ -823,9 +1763,9 @@ impl Framebuffer {
             let copy_height = old_height.min(new_height) as usize;
 
             for y in 0..copy_height {
-                let old_offset = y * (old_width as usize) * 4;
-                let new_offset = y * (new_width as usize) * 4;
-                let len = copy_width * 4;
+                let old_offset = y * (old_width as usize) * bpp;
+                let new_offset = y * (new_width as usize) * bpp;
+                let len = copy_width * bpp;
 
                 new_data[new_offset..new_offset + len]
                     .copy_from_slice(&old_data[old_offset..old_offset + len]);
@@ -835,7 +1775,7 @@ impl Framebuffer {
         // Replace the old data with new data
         {
             let mut data = self.data.write().await;
-            *data = new_data;
+            *data = Arc::new(new_data);
         }
 
         // Update dimensions atomically
@@ -847,6 +1787,7 @@ impl Framebuffer {
             let mut prev = self.prev_data.write().await;
             *prev = vec![0u8; new_size];
         }
+        self.encode_cache.invalidate(None);
 
         // Mark entire framebuffer as dirty after resize
         self.mark_dirty_region(0, 0, new_width, new_height).await;
@@ -857,7 +1798,8 @@ impl Framebuffer {
     /// Performs a copy rectangle operation within the framebuffer (standard VNC protocol style).
     ///
     /// This method copies pixel data from one region of the framebuffer to another,
-    /// handling overlapping regions correctly by choosing the appropriate iteration direction.
+    /// handling overlapping regions correctly by choosing the appropriate iteration direction,
+    /// and schedules the copy for the framebuffer's clients, which send it as `CopyRect`.
     /// This is the equivalent of standard VNC protocol's `rfbDoCopyRegion` function.
     ///
     /// # Arguments
@@ -887,6 +1829,10 @@ impl Framebuffer {
         dx: i16,
         dy: i16,
     ) -> Result<(), String> {
+        if self.source.is_some() {
+            return Err(SOURCE_BACKED_ERROR.to_string());
+        }
+
         // Calculate source coordinates
         let src_x = (i32::from(dest_x) + i32::from(dx)) as u16;
         let src_y = (i32::from(dest_y) + i32::from(dy)) as u16;
@@ -920,38 +1866,260 @@ impl Framebuffer {
             ));
         }
 
-        let mut data = self.data.write().await;
+        let mut fb = self.data.write().await;
+        let data = Arc::make_mut(&mut fb);
         let fb_width = self.width() as usize;
-        let row_bytes = width as usize * 4;
+        let bpp = self.storage.bytes_per_pixel();
+        let row_bytes = width as usize * bpp;
 
         // Copy rectangle within framebuffer
         // Choose iteration direction based on dx/dy to handle overlapping regions correctly
         // (standard VNC protocol uses sraRgnGetReverseIterator for this)
-        if dy < 0 {
-            // Copy top to bottom (forward)
+        if dy >= 0 {
+            // Source at or below the destination: copy top to bottom (forward)
             for row in 0..height {
-                let src_offset = ((src_y + row) as usize * fb_width + src_x as usize) * 4;
-                let dest_offset = ((dest_y + row) as usize * fb_width + dest_x as usize) * 4;
+                let src_offset = ((src_y + row) as usize * fb_width + src_x as usize) * bpp;
+                let dest_offset = ((dest_y + row) as usize * fb_width + dest_x as usize) * bpp;
 
                 // Use copy_within for safe overlapping copies
                 data.copy_within(src_offset..src_offset + row_bytes, dest_offset);
             }
         } else {
-            // Copy bottom to top (reverse)
+            // Source above the destination: copy bottom to top (reverse)
             for row in (0..height).rev() {
-                let src_offset = ((src_y + row) as usize * fb_width + src_x as usize) * 4;
-                let dest_offset = ((dest_y + row) as usize * fb_width + dest_x as usize) * 4;
+                let src_offset = ((src_y + row) as usize * fb_width + src_x as usize) * bpp;
+                let dest_offset = ((dest_y + row) as usize * fb_width + dest_x as usize) * bpp;
 
                 // Use copy_within for safe overlapping copies
                 data.copy_within(src_offset..src_offset + row_bytes, dest_offset);
             }
         }
 
-        drop(data); // Release lock before save_state
+        drop(fb); // Release lock before save_state
 
         // Update prev_data for future copy detection
         self.save_state().await;
 
+        self.mark_copy_region(DirtyRegion::new(dest_x, dest_y, width, height), dx, dy)
+            .await;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_to_clips_partially_outside_region() {
+        let region = DirtyRegion::new(90, 40, 50, 50);
+        assert_eq!(
+            region.clamp_to(100, 60),
+            Some(DirtyRegion::new(90, 40, 10, 20))
+        );
+    }
+
+    #[test]
+    fn clamp_to_rejects_zero_size_region() {
+        assert_eq!(DirtyRegion::new(10, 10, 0, 5).clamp_to(100, 60), None);
+        assert_eq!(DirtyRegion::new(10, 10, 5, 0).clamp_to(100, 60), None);
+    }
+
+    #[test]
+    fn clamp_to_rejects_region_outside_framebuffer() {
+        assert_eq!(DirtyRegion::new(100, 0, 10, 10).clamp_to(100, 60), None);
+        assert_eq!(DirtyRegion::new(0, 60, 10, 10).clamp_to(100, 60), None);
+        assert_eq!(
+            DirtyRegion::new(u16::MAX, u16::MAX, u16::MAX, u16::MAX).clamp_to(100, 60),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn mark_dirty_region_clamps_to_bounds() {
+        let framebuffer = Framebuffer::new(100, 60);
+        let regions = Arc::new(RwLock::new(DamageQueue::new()));
+        framebuffer
+            .register_receiver(DirtyRegionReceiver::new(Arc::downgrade(&regions)))
+            .await;
+
+        framebuffer.mark_dirty_region(200, 200, 10, 10).await;
+        framebuffer.mark_dirty_region(5, 5, 0, 0).await;
+        assert!(regions.read().await.is_empty());
+
+        framebuffer.mark_dirty_region(80, 50, 100, 100).await;
+        assert_eq!(
+            regions.read().await.region().rects(),
+            [DirtyRegion::new(80, 50, 20, 10)]
+        );
+    }
+
+    #[tokio::test]
+    async fn update_with_format_converts_to_rgba32() {
+        let framebuffer = Framebuffer::new(2, 1);
+        framebuffer
+            .update_from_slice_with_format(&[0x00, 0xf8, 0xe0, 0x07], InputFormat::Rgb565)
+            .await
+            .unwrap();
+        assert_eq!(
+            framebuffer.get_full_data().await,
+            [255, 0, 0, 255, 0, 255, 0, 255]
+        );
+
+        framebuffer
+            .update_cropped_with_format(&[3, 2, 1, 9], InputFormat::Bgra32, 1, 0, 1, 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            framebuffer.get_full_data().await,
+            [255, 0, 0, 255, 1, 2, 3, 9]
+        );
+        assert!(framebuffer
+            .update_from_slice_with_format(&[0; 8], InputFormat::Rgb24)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn rgb565_storage_reads_back_rgba32() {
+        let framebuffer = Framebuffer::with_storage(2, 2, StorageFormat::Rgb565);
+        let regions = Arc::new(RwLock::new(DamageQueue::new()));
+        framebuffer
+            .register_receiver(DirtyRegionReceiver::new(Arc::downgrade(&regions)))
+            .await;
+
+        // Colors with 5/6/5 significant bits survive the narrower storage
+        let red = [255, 0, 0, 255];
+        let white = [255, 255, 255, 255];
+        framebuffer
+            .update_cropped(&[red, white].concat(), 0, 1, 2, 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            regions.read().await.region().rects(),
+            [DirtyRegion::new(0, 1, 2, 1)]
+        );
+        assert_eq!(
+            framebuffer.get_rect(0, 1, 2, 1).await.unwrap(),
+            [red, white].concat()
+        );
+
+        framebuffer.do_copy_region(0, 0, 2, 1, 0, 1).await.unwrap();
+        assert_eq!(
+            framebuffer.get_full_data().await,
+            [red, white, red, white].concat()
+        );
+    }
+
+    #[tokio::test]
+    async fn snapshot_shares_contiguous_rows() {
+        let framebuffer = Framebuffer::new(2, 2);
+        let frame: Vec<u8> = (0..16).collect();
+        framebuffer.update_from_slice(&frame).await.unwrap();
+        let snapshot = framebuffer.snapshot().await.unwrap();
+
+        // The next frame goes to a copy; the snapshot keeps the one it was taken of
+        framebuffer.update_from_slice(&[0; 16]).await.unwrap();
+        let rows = snapshot.get_rect(0, 1, 2, 1).unwrap();
+        assert!(Arc::ptr_eq(&rows.pixels, &snapshot.pixels));
+        assert_eq!(&*rows, &frame[8..]);
+        assert_eq!(
+            snapshot.get_rect(1, 0, 1, 2).unwrap().into_vec(),
+            [&frame[4..8], &frame[12..]].concat()
+        );
+    }
+
+    #[tokio::test]
+    async fn merged_region_keeps_oldest_queue_position() {
+        let regions = Arc::new(RwLock::new(DamageQueue::new()));
+        let receiver = DirtyRegionReceiver::new(Arc::downgrade(&regions));
+        receiver
+            .add_dirty_region(DirtyRegion::new(0, 0, 10, 10))
+            .await;
+        receiver
+            .add_dirty_region(DirtyRegion::new(50, 0, 10, 10))
+            .await;
+        receiver
+            .add_dirty_region(DirtyRegion::new(0, 50, 10, 10))
+            .await;
+
+        // Touches the second region only: the merge stays in second place
+        receiver
+            .add_dirty_region(DirtyRegion::new(55, 5, 10, 10))
+            .await;
+        assert_eq!(
+            regions.read().await.ages(),
+            [
+                DirtyRegion::new(0, 0, 10, 10),
+                DirtyRegion::new(50, 0, 15, 15),
+                DirtyRegion::new(0, 50, 10, 10),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn scroll_is_sent_as_copy() {
+        let (width, height) = (64u16, 64u16);
+        let row_bytes = usize::from(width) * 4;
+        // Every row distinct, so the scroll offset is unambiguous
+        let frame = |offset: u32| {
+            let mut data = vec![0u8; row_bytes * usize::from(height)];
+            for (y, row) in (0u32..).zip(data.chunks_exact_mut(row_bytes)) {
+                for (x, pixel) in (0u32..).zip(row.chunks_exact_mut(4)) {
+                    let value = (y + offset) * 31 + x;
+                    pixel.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            data
+        };
+
+        let framebuffer = Framebuffer::new(width, height);
+        framebuffer.update_from_slice(&frame(0)).await.unwrap();
+
+        let regions = Arc::new(RwLock::new(DamageQueue::new()));
+        let copies = Arc::new(RwLock::new(Region::new()));
+        let offset = Arc::new(RwLock::new(None));
+        framebuffer
+            .register_receiver(
+                DirtyRegionReceiver::new(Arc::downgrade(&regions))
+                    .with_copy_regions(Arc::downgrade(&copies), Arc::downgrade(&offset)),
+            )
+            .await;
+
+        // Scroll up by 8 rows: the bottom 8 rows are new content
+        framebuffer.update_from_slice(&frame(8)).await.unwrap();
+        assert_eq!(
+            copies.read().await.rects(),
+            [DirtyRegion::new(0, 0, 64, 56)]
+        );
+        assert_eq!(*offset.read().await, Some((0, 8)));
+        assert_eq!(
+            regions.read().await.region().rects(),
+            [DirtyRegion::new(0, 56, 64, 8)]
+        );
+    }
+
+    #[tokio::test]
+    async fn copies_keep_damage_queue_bounded() {
+        let regions = Arc::new(RwLock::new(DamageQueue::new()));
+        let copies = Arc::new(RwLock::new(Region::new()));
+        let offset = Arc::new(RwLock::new(None));
+        let receiver = DirtyRegionReceiver::new(Arc::downgrade(&regions))
+            .with_copy_regions(Arc::downgrade(&copies), Arc::downgrade(&offset));
+
+        // Scattered damage, then copies that each cut through all of it
+        for i in 0..10 {
+            receiver
+                .add_dirty_region(DirtyRegion::new(i * 40, i * 30, 30, 20))
+                .await;
+        }
+        for i in 0..50 {
+            receiver
+                .add_copy_region(DirtyRegion::new(i * 7 % 400, 0, 3, 400), 0, 1)
+                .await;
+        }
+        let regions = regions.read().await;
+        assert!(regions.ages().len() <= DamageQueue::MAX_AGES);
+        assert!(regions.region().rects().len() <= DamageQueue::MAX_RECTS);
+    }
+}
@@ -0,0 +1,397 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Extended input through the gii (General Input Interface) extension.
+//!
+//! Plain `PointerEvent` messages only carry a position and eight buttons. Viewers
+//! supporting gii can register input devices such as pen tablets and touch screens,
+//! each with a set of valuators (pressure, tilt, touch contacts, ...), and inject their
+//! events. Every registration and event is delivered to the application as
+//! `ServerEvent::ExtendedInput` carrying a [`GiiEvent`]; the server does not interpret
+//! them. Events from view-only or paused clients, and from devices that were never
+//! registered, are dropped.
+//!
+//! The meaning of a [`GiiEvent::Valuators`] value comes from the [`Valuator`] with the
+//! same index in the device's [`GiiDevice::valuators`]:
+//!
+//! ```no_run
+//! # async fn example(mut events: tokio::sync::mpsc::UnboundedReceiver<rustvncserver::server::ServerEvent>) {
+//! use std::collections::HashMap;
+//!
+//! use rustvncserver::gii::GiiEvent;
+//! use rustvncserver::server::ServerEvent;
+//!
+//! let mut devices = HashMap::new();
+//! while let Some(event) = events.recv().await {
+//!     let ServerEvent::ExtendedInput { client_id, event } = event else {
+//!         continue;
+//!     };
+//!     match event {
+//!         GiiEvent::DeviceCreated { device } => {
+//!             devices.insert((client_id, device.origin), device);
+//!         }
+//!         GiiEvent::Valuators { origin, first, values, .. } => {
+//!             let Some(device) = devices.get(&(client_id, origin)) else {
+//!                 continue;
+//!             };
+//!             for (index, value) in (first..).zip(values) {
+//!                 if let Some(valuator) = device.valuators.iter().find(|v| v.index == index) {
+//!                     println!("{} = {value}", valuator.long_name);
+//!                 }
+//!             }
+//!         }
+//!         _ => {}
+//!     }
+//! }
+//! # }
+//! ```
+
+/// gii message subtype: injected events.
+pub(crate) const SUBTYPE_EVENTS: u8 = 0;
+/// gii message subtype: version negotiation.
+pub(crate) const SUBTYPE_VERSION: u8 = 1;
+/// gii message subtype: device creation (client) or its answer (server).
+pub(crate) const SUBTYPE_DEVICE_CREATION: u8 = 2;
+/// gii message subtype: device destruction.
+pub(crate) const SUBTYPE_DEVICE_DESTRUCTION: u8 = 3;
+/// Set in the subtype byte when the message is big-endian.
+pub(crate) const BIG_ENDIAN_FLAG: u8 = 0x80;
+
+/// The only gii version there is.
+pub(crate) const VERSION: u16 = 1;
+
+/// Length of the fixed part of a device creation message.
+const DEVICE_LEN: usize = 56;
+/// Length of one valuator description.
+const VALUATOR_LEN: usize = 116;
+/// Length of the device name field (31 characters and a NUL).
+const DEVICE_NAME_LEN: usize = 32;
+
+const EVENT_KEY_PRESS: u8 = 5;
+const EVENT_KEY_RELEASE: u8 = 6;
+const EVENT_KEY_REPEAT: u8 = 7;
+const EVENT_POINTER_RELATIVE: u8 = 8;
+const EVENT_POINTER_ABSOLUTE: u8 = 9;
+const EVENT_BUTTON_PRESS: u8 = 10;
+const EVENT_BUTTON_RELEASE: u8 = 11;
+const EVENT_VALUATOR_RELATIVE: u8 = 12;
+const EVENT_VALUATOR_ABSOLUTE: u8 = 13;
+
+/// An input device registered by a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GiiDevice {
+    /// Identifier the server assigned to the device; events name it as their origin.
+    pub origin: u32,
+    /// Device name.
+    pub name: String,
+    /// Vendor identifier.
+    pub vendor_id: u32,
+    /// Product identifier.
+    pub product_id: u32,
+    /// Mask of the event types the device generates (bit `n` for event type `n`).
+    pub event_mask: u32,
+    /// Number of buttons.
+    pub buttons: u32,
+    /// The device's valuators (axes).
+    pub valuators: Vec<Valuator>,
+}
+
+/// One axis of a device, such as pressure or tilt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Valuator {
+    /// Index of the valuator, as used by [`GiiEvent::Valuators`].
+    pub index: u32,
+    /// Descriptive name, e.g. `Pressure`.
+    pub long_name: String,
+    /// Abbreviated name, e.g. `P`.
+    pub short_name: String,
+    /// Smallest value.
+    pub min: i32,
+    /// Value at rest.
+    pub center: i32,
+    /// Largest value.
+    pub max: i32,
+    /// Physical unit (gii `SI-unit` code, 0 when unknown).
+    pub si_unit: u32,
+    /// Conversion to the physical unit:
+    /// `(value + si_add) * si_mul / si_div * 2^si_shift`.
+    pub si_add: i32,
+    /// See [`si_add`](Self::si_add).
+    pub si_mul: i32,
+    /// See [`si_add`](Self::si_add).
+    pub si_div: i32,
+    /// See [`si_add`](Self::si_add).
+    pub si_shift: i32,
+}
+
+/// Whether a key event is a press, a release or an auto-repeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    /// The key was pressed.
+    Press,
+    /// The key was released.
+    Release,
+    /// The key is held and repeating.
+    Repeat,
+}
+
+/// A gii device registration or input event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GiiEvent {
+    /// The client registered an input device.
+    DeviceCreated {
+        /// The device.
+        device: GiiDevice,
+    },
+    /// The client removed an input device.
+    DeviceDestroyed {
+        /// The device's origin.
+        origin: u32,
+    },
+    /// A key event.
+    Key {
+        /// The device's origin.
+        origin: u32,
+        /// Press, release or repeat.
+        action: KeyAction,
+        /// Modifier state.
+        modifiers: u32,
+        /// Key symbol.
+        symbol: u32,
+        /// Key label.
+        label: u32,
+        /// Device-specific key code.
+        button: u32,
+    },
+    /// Pointer motion.
+    Pointer {
+        /// The device's origin.
+        origin: u32,
+        /// Whether the values are offsets from the previous position.
+        relative: bool,
+        /// X coordinate or offset.
+        x: i32,
+        /// Y coordinate or offset.
+        y: i32,
+        /// Z coordinate or offset.
+        z: i32,
+        /// Wheel position or offset.
+        wheel: i32,
+    },
+    /// A pointer button changed state.
+    Button {
+        /// The device's origin.
+        origin: u32,
+        /// Whether the button was pressed (`true`) or released (`false`).
+        pressed: bool,
+        /// Button number.
+        button: u32,
+    },
+    /// Valuator values, for example pen pressure and tilt or touch contacts.
+    Valuators {
+        /// The device's origin.
+        origin: u32,
+        /// Whether the values are offsets from the previous ones.
+        relative: bool,
+        /// Index of the valuator of the first value; the others follow in order.
+        first: u32,
+        /// The values.
+        values: Vec<i32>,
+    },
+}
+
+impl GiiEvent {
+    /// Returns the origin of the device the event belongs to.
+    #[must_use]
+    pub fn origin(&self) -> u32 {
+        match self {
+            Self::DeviceCreated { device } => device.origin,
+            Self::DeviceDestroyed { origin }
+            | Self::Key { origin, .. }
+            | Self::Pointer { origin, .. }
+            | Self::Button { origin, .. }
+            | Self::Valuators { origin, .. } => *origin,
+        }
+    }
+}
+
+/// Reads numbers in the byte order a gii message declares.
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], big_endian: bool) -> Self {
+        Self { data, big_endian }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes: [u8; 4] = self.take(4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.u32().map(u32::cast_signed)
+    }
+
+    /// Reads a NUL-terminated string from a fixed-size field.
+    fn string(&mut self, len: usize) -> Option<String> {
+        let field = self.take(len)?;
+        let end = field.iter().position(|&byte| byte == 0).unwrap_or(len);
+        Some(String::from_utf8_lossy(&field[..end]).into_owned())
+    }
+}
+
+/// Reads a 16-bit number in the byte order a gii message declares.
+pub(crate) fn read_u16(bytes: [u8; 2], big_endian: bool) -> u16 {
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+/// Parses the body of a device creation message. The device gets origin 0 until the
+/// server assigns one.
+///
+/// # Returns
+///
+/// `None` if the body is shorter than its valuator count requires.
+pub(crate) fn parse_device(body: &[u8], big_endian: bool) -> Option<GiiDevice> {
+    let mut reader = Reader::new(body, big_endian);
+    let name = reader.string(DEVICE_NAME_LEN)?;
+    let vendor_id = reader.u32()?;
+    let product_id = reader.u32()?;
+    let event_mask = reader.u32()?;
+    let _registers = reader.u32()?;
+    let valuator_count = reader.u32()? as usize;
+    let buttons = reader.u32()?;
+    if body.len() < DEVICE_LEN + valuator_count.checked_mul(VALUATOR_LEN)? {
+        return None;
+    }
+
+    let mut valuators = Vec::with_capacity(valuator_count);
+    for _ in 0..valuator_count {
+        valuators.push(Valuator {
+            index: reader.u32()?,
+            long_name: reader.string(75)?,
+            short_name: reader.string(5)?,
+            min: reader.i32()?,
+            center: reader.i32()?,
+            max: reader.i32()?,
+            si_unit: reader.u32()?,
+            si_add: reader.i32()?,
+            si_mul: reader.i32()?,
+            si_div: reader.i32()?,
+            si_shift: reader.i32()?,
+        });
+    }
+    Some(GiiDevice {
+        origin: 0,
+        name,
+        vendor_id,
+        product_id,
+        event_mask,
+        buttons,
+        valuators,
+    })
+}
+
+/// Parses the body of a device destruction message into the device's origin.
+pub(crate) fn parse_destruction(body: &[u8], big_endian: bool) -> Option<u32> {
+    Reader::new(body, big_endian).u32()
+}
+
+/// Parses the events of an event injection message. Unknown event types are skipped;
+/// parsing stops at the first malformed event.
+pub(crate) fn parse_events(body: &[u8], big_endian: bool) -> Vec<GiiEvent> {
+    let mut events = Vec::new();
+    let mut rest = body;
+    while let [size, event_type, ..] = *rest {
+        let size = usize::from(size);
+        if size < 8 || size > rest.len() {
+            break;
+        }
+        let (event, next) = rest.split_at(size);
+        rest = next;
+
+        // Size, type and two bytes of padding precede the device origin
+        let mut reader = Reader::new(&event[4..], big_endian);
+        if let Some(event) = parse_event(event_type, &mut reader) {
+            events.push(event);
+        }
+    }
+    events
+}
+
+/// Parses one event after its header.
+fn parse_event(event_type: u8, reader: &mut Reader<'_>) -> Option<GiiEvent> {
+    let origin = reader.u32()?;
+    let event = match event_type {
+        EVENT_KEY_PRESS | EVENT_KEY_RELEASE | EVENT_KEY_REPEAT => GiiEvent::Key {
+            origin,
+            action: match event_type {
+                EVENT_KEY_PRESS => KeyAction::Press,
+                EVENT_KEY_RELEASE => KeyAction::Release,
+                _ => KeyAction::Repeat,
+            },
+            modifiers: reader.u32()?,
+            symbol: reader.u32()?,
+            label: reader.u32()?,
+            button: reader.u32()?,
+        },
+        EVENT_POINTER_RELATIVE | EVENT_POINTER_ABSOLUTE => GiiEvent::Pointer {
+            origin,
+            relative: event_type == EVENT_POINTER_RELATIVE,
+            x: reader.i32()?,
+            y: reader.i32()?,
+            z: reader.i32()?,
+            wheel: reader.i32()?,
+        },
+        EVENT_BUTTON_PRESS | EVENT_BUTTON_RELEASE => GiiEvent::Button {
+            origin,
+            pressed: event_type == EVENT_BUTTON_PRESS,
+            button: reader.u32()?,
+        },
+        EVENT_VALUATOR_RELATIVE | EVENT_VALUATOR_ABSOLUTE => {
+            let first = reader.u32()?;
+            let count = reader.u32()?;
+            let values = (0..count)
+                .map(|_| reader.i32())
+                .collect::<Option<Vec<_>>>()?;
+            GiiEvent::Valuators {
+                origin,
+                relative: event_type == EVENT_VALUATOR_RELATIVE,
+                first,
+                values,
+            }
+        }
+        _ => return None,
+    };
+    Some(event)
+}
@@ -0,0 +1,241 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hextile (encoding 5), also the tile layer of `ZlibHex` (encoding 8).
+//!
+//! The rectangle is split into 16x16 tiles, sent left to right and top to bottom. Each
+//! tile starts with a subencoding byte of flags and is either raw pixels, or a
+//! background color with subrectangles of one foreground color or of their own colors.
+//! Background and foreground colors carry over to the next tile when they don't change.
+//!
+//! `ZlibHex` compresses tiles with two zlib streams that live for the whole connection:
+//! raw tiles are replaced by their compressed pixels ([`HEXTILE_ZLIB_RAW`]) from one
+//! stream, and the data of other tiles after the subencoding byte is compressed
+//! ([`HEXTILE_ZLIB`]) with the other. Each compressed tile carries a `u16` length.
+//!
+//! The Hextile encoder in `rfb-encodings` always writes 32-bit pixels, so this one is
+//! used instead: pixels follow the size and byte order the client negotiated.
+
+use std::convert::Infallible;
+use std::io;
+
+use bytes::{BufMut, BytesMut};
+use flate2::Compress;
+
+use crate::protocol::{
+    PixelFormat, HEXTILE_ANY_SUBRECTS, HEXTILE_BACKGROUND_SPECIFIED, HEXTILE_FOREGROUND_SPECIFIED,
+    HEXTILE_RAW, HEXTILE_SUBRECTS_COLOURED, HEXTILE_ZLIB, HEXTILE_ZLIB_RAW,
+};
+use crate::rect_encoder;
+use crate::rle_tile::{self, CPixel};
+use crate::rre;
+
+/// Width and height of Hextile tiles.
+const TILE_SIZE: usize = 16;
+
+/// Tiles whose data after the subencoding byte is shorter than this are sent
+/// uncompressed by `ZlibHex`; the zlib framing would cost more than it saves.
+const MIN_ZLIB_TILE: usize = 32;
+
+/// Colors carried over from the previous tile.
+#[derive(Default)]
+struct TileState {
+    background: Option<u32>,
+    foreground: Option<u32>,
+}
+
+/// Encodes a rectangle with Hextile.
+///
+/// # Arguments
+///
+/// * `data` - The rectangle's pixels, already translated to the client's pixel format.
+/// * `width`, `height` - Size of the rectangle.
+/// * `format` - The client's pixel format.
+/// * `buf` - Receives the encoded rectangle.
+pub(crate) fn encode_hextile(
+    data: &[u8],
+    width: u16,
+    height: u16,
+    format: &PixelFormat,
+    buf: &mut BytesMut,
+) {
+    let Ok(()) = encode_tiles(data, width, height, format, buf, |_, _| {
+        Ok::<_, Infallible>(())
+    });
+}
+
+/// Encodes a rectangle with `ZlibHex`.
+///
+/// # Arguments
+///
+/// * `data` - The rectangle's pixels, already translated to the client's pixel format.
+/// * `width`, `height` - Size of the rectangle.
+/// * `format` - The client's pixel format.
+/// * `streams` - The connection's raw and encoded `ZlibHex` zlib streams.
+/// * `buf` - Receives the encoded rectangle.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if zlib compression fails.
+pub(crate) fn encode_zlibhex(
+    data: &[u8],
+    width: u16,
+    height: u16,
+    format: &PixelFormat,
+    (raw_stream, encoded_stream): (&mut Compress, &mut Compress),
+    buf: &mut BytesMut,
+) -> io::Result<()> {
+    let mut tile = Vec::with_capacity(TILE_SIZE * TILE_SIZE * 4);
+    encode_tiles(data, width, height, format, buf, |buf, start| {
+        let subencoding = buf[start];
+        let (flags, stream) = if subencoding & HEXTILE_RAW != 0 {
+            (HEXTILE_ZLIB_RAW, &mut *raw_stream)
+        } else if buf.len() - start > MIN_ZLIB_TILE {
+            (subencoding | HEXTILE_ZLIB, &mut *encoded_stream)
+        } else {
+            return Ok(());
+        };
+        tile.clear();
+        tile.extend_from_slice(&buf[start + 1..]);
+        buf.truncate(start);
+        buf.put_u8(flags);
+        compress_tile(&tile, stream, buf)
+    })
+}
+
+/// Compresses a tile's data and appends its `u16` length and the compressed data.
+fn compress_tile(tile: &[u8], stream: &mut Compress, buf: &mut BytesMut) -> io::Result<()> {
+    let header = buf.len();
+    buf.put_u16(0);
+    rect_encoder::deflate(tile, stream, buf)?;
+    let length = u16::try_from(buf.len() - header - 2).map_err(io::Error::other)?;
+    buf[header..header + 2].copy_from_slice(&length.to_be_bytes());
+    Ok(())
+}
+
+/// Encodes the tiles of a rectangle, handing each one to `finish_tile` right after it
+/// is written so it can be rewritten.
+///
+/// # Arguments
+///
+/// * `data` - The rectangle's pixels, already translated to the client's pixel format.
+/// * `width`, `height` - Size of the rectangle.
+/// * `format` - The client's pixel format.
+/// * `buf` - Receives the encoded rectangle.
+/// * `finish_tile` - Called with `buf` and the offset of the tile's subencoding byte.
+fn encode_tiles<E>(
+    data: &[u8],
+    width: u16,
+    height: u16,
+    format: &PixelFormat,
+    buf: &mut BytesMut,
+    mut finish_tile: impl FnMut(&mut BytesMut, usize) -> Result<(), E>,
+) -> Result<(), E> {
+    let (width, height) = (usize::from(width), usize::from(height));
+    let pixels = rle_tile::read_pixels(data, format);
+    let pixel = CPixel::full(format);
+
+    buf.reserve(pixels.len() * pixel.size() / 2 + 64);
+    let mut state = TileState::default();
+    let mut tile = Vec::with_capacity(TILE_SIZE * TILE_SIZE);
+    for y in (0..height).step_by(TILE_SIZE) {
+        for x in (0..width).step_by(TILE_SIZE) {
+            let tile_width = (width - x).min(TILE_SIZE);
+            let tile_height = (height - y).min(TILE_SIZE);
+            rle_tile::extract_tile(&pixels, width, (x, y, tile_width, tile_height), &mut tile);
+            let start = buf.len();
+            encode_tile(buf, &tile, tile_width, pixel, &mut state);
+            finish_tile(buf, start)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encodes one tile, as subrectangles on the most common color or raw if that is
+/// smaller.
+fn encode_tile(
+    buf: &mut BytesMut,
+    tile: &[u32],
+    width: usize,
+    pixel: CPixel,
+    state: &mut TileState,
+) {
+    let height = tile.len() / width.max(1);
+
+    // Tiles have at most 256 pixels, so counting colors in a list is cheap
+    let mut colors: Vec<(u32, usize)> = Vec::new();
+    for &value in tile {
+        match colors.iter_mut().find(|(color, _)| *color == value) {
+            Some((_, count)) => *count += 1,
+            None => colors.push((value, 1)),
+        }
+    }
+    let background = colors
+        .iter()
+        .max_by_key(|&&(_, count)| count)
+        .map_or(0, |&(color, _)| color);
+    let coloured = colors.len() > 2;
+
+    let size = pixel.size();
+    let raw_bytes = tile.len() * size;
+    let subrect_bytes = if coloured { size + 2 } else { 2 };
+    let subrects = if colors.len() == 1 {
+        Some(Vec::new())
+    } else {
+        let limit = (raw_bytes / subrect_bytes).min(255);
+        rre::find_subrects(tile, width, height, background, limit)
+    };
+    let Some(subrects) = subrects else {
+        buf.put_u8(HEXTILE_RAW);
+        for &value in tile {
+            pixel.write(buf, value);
+        }
+        // Decoders don't carry colors over a raw tile
+        *state = TileState::default();
+        return;
+    };
+
+    let start = buf.len();
+    buf.put_u8(0);
+    let mut subencoding = 0;
+    if state.background != Some(background) {
+        subencoding |= HEXTILE_BACKGROUND_SPECIFIED;
+        pixel.write(buf, background);
+        state.background = Some(background);
+    }
+    if !subrects.is_empty() {
+        subencoding |= HEXTILE_ANY_SUBRECTS;
+        if coloured {
+            subencoding |= HEXTILE_SUBRECTS_COLOURED;
+            state.foreground = None;
+        } else {
+            let foreground = subrects[0].4;
+            if state.foreground != Some(foreground) {
+                subencoding |= HEXTILE_FOREGROUND_SPECIFIED;
+                pixel.write(buf, foreground);
+                state.foreground = Some(foreground);
+            }
+        }
+        #[allow(clippy::cast_possible_truncation)] // The count is limited to 255 above
+        buf.put_u8(subrects.len() as u8);
+        for &(x, y, w, h, color) in &subrects {
+            if coloured {
+                pixel.write(buf, color);
+            }
+            #[allow(clippy::cast_possible_truncation)] // Tile coordinates are below 16
+            buf.put_slice(&[(x << 4 | y) as u8, ((w - 1) << 4 | (h - 1)) as u8]);
+        }
+    }
+    buf[start] = subencoding;
+}
@@ -0,0 +1,181 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Configurable `TurboJPEG` compression for Tight rectangles.
+//!
+//! The encoder in `rfb-encodings` always copies pixels to packed RGB and compresses with
+//! 4:2:2 subsampling. This one follows a [`JpegConfig`]: it can hand the framebuffer's
+//! RGBX pixels to `TurboJPEG` without a copy, and exposes the subsampling, the fast DCT
+//! and a quality range.
+
+use std::ffi::c_void;
+use std::os::raw::{c_char, c_int, c_uchar, c_ulong};
+
+use crate::config::{ChromaSubsampling, JpegConfig, JpegPixelFormat};
+
+/// `TurboJPEG` pixel format: packed RGB.
+const TJPF_RGB: c_int = 0;
+
+/// `TurboJPEG` pixel format: RGB followed by an unused byte.
+const TJPF_RGBX: c_int = 2;
+
+/// `TurboJPEG` flag: use the fast integer DCT.
+const TJFLAG_FASTDCT: c_int = 2048;
+
+type TjHandle = *mut c_void;
+
+#[link(name = "turbojpeg")]
+extern "C" {
+    fn tjInitCompress() -> TjHandle;
+    fn tjDestroy(handle: TjHandle) -> c_int;
+    fn tjCompress2(
+        handle: TjHandle,
+        src_buf: *const c_uchar,
+        width: c_int,
+        pitch: c_int,
+        height: c_int,
+        pixel_format: c_int,
+        jpeg_buf: *mut *mut c_uchar,
+        jpeg_size: *mut c_ulong,
+        jpeg_subsamp: c_int,
+        jpeg_qual: c_int,
+        flags: c_int,
+    ) -> c_int;
+    fn tjFree(buffer: *mut c_uchar);
+    fn tjGetErrorStr2(handle: TjHandle) -> *const c_char;
+}
+
+/// A `TurboJPEG` compressor.
+pub(crate) struct JpegEncoder {
+    handle: TjHandle,
+}
+
+impl JpegEncoder {
+    /// Creates a compressor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `TurboJPEG` cannot be initialized.
+    pub(crate) fn new() -> Result<Self, String> {
+        let handle = unsafe { tjInitCompress() };
+        if handle.is_null() {
+            return Err("Failed to initialize TurboJPEG compressor".to_string());
+        }
+        Ok(Self { handle })
+    }
+
+    /// Compresses RGBA32 pixels to a JPEG image.
+    ///
+    /// # Arguments
+    ///
+    /// * `rgba` - RGBA32 pixel data (4 bytes per pixel, row-major); alpha is ignored.
+    /// * `width`, `height` - Size of the image.
+    /// * `quality` - JPEG quality (1-100), clamped to the configured range.
+    /// * `config` - Subsampling, DCT and input layout.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data size doesn't match the image or compression fails.
+    #[allow(clippy::cast_possible_truncation)] // c_ulong sizes of in-memory buffers fit usize
+    pub(crate) fn compress(
+        &mut self,
+        rgba: &[u8],
+        width: u16,
+        height: u16,
+        quality: u8,
+        config: &JpegConfig,
+    ) -> Result<Vec<u8>, String> {
+        let pixels = usize::from(width) * usize::from(height);
+        if rgba.len() != pixels * 4 {
+            return Err(format!(
+                "Invalid RGBA data size: expected {}, got {}",
+                pixels * 4,
+                rgba.len()
+            ));
+        }
+
+        let packed;
+        let (source, pixel_format) = match config.pixel_format {
+            JpegPixelFormat::Rgbx => (rgba, TJPF_RGBX),
+            JpegPixelFormat::Rgb => {
+                packed = rgba
+                    .chunks_exact(4)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                    .collect::<Vec<u8>>();
+                (packed.as_slice(), TJPF_RGB)
+            }
+        };
+        let subsampling: c_int = match config.subsampling {
+            ChromaSubsampling::Yuv444 => 0,
+            ChromaSubsampling::Yuv422 => 1,
+            ChromaSubsampling::Yuv420 => 2,
+            ChromaSubsampling::Gray => 3,
+        };
+        let flags = if config.fast_dct { TJFLAG_FASTDCT } else { 0 };
+
+        let mut jpeg_buf: *mut c_uchar = std::ptr::null_mut();
+        let mut jpeg_size: c_ulong = 0;
+        let result = unsafe {
+            tjCompress2(
+                self.handle,
+                source.as_ptr(),
+                c_int::from(width),
+                0, // pitch = 0 means width * pixel size
+                c_int::from(height),
+                pixel_format,
+                &raw mut jpeg_buf,
+                &raw mut jpeg_size,
+                subsampling,
+                c_int::from(config.clamp_quality(quality)),
+                flags,
+            )
+        };
+
+        if result != 0 {
+            return Err(format!(
+                "TurboJPEG compression failed: {}",
+                self.error_string()
+            ));
+        }
+        if jpeg_buf.is_null() {
+            return Err("TurboJPEG returned null buffer".to_string());
+        }
+
+        let jpeg = unsafe { std::slice::from_raw_parts(jpeg_buf, jpeg_size as usize).to_vec() };
+        unsafe { tjFree(jpeg_buf) };
+        Ok(jpeg)
+    }
+
+    /// Returns the last `TurboJPEG` error message.
+    fn error_string(&self) -> String {
+        let message = unsafe { tjGetErrorStr2(self.handle) };
+        if message.is_null() {
+            return "Unknown error".to_string();
+        }
+        unsafe { std::ffi::CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl Drop for JpegEncoder {
+    fn drop(&mut self) {
+        unsafe {
+            tjDestroy(self.handle);
+        }
+    }
+}
+
+// The handle is only used through `&mut self`
+unsafe impl Send for JpegEncoder {}
@@ -0,0 +1,804 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! X11 keysym constants and Unicode conversion.
+//!
+//! VNC key events (RFC 6143 §7.5.4) carry X11 keysyms. This module provides:
+//!
+//! - Constants for the function, cursor, keypad and modifier keysyms that
+//!   applications most commonly need to recognize.
+//! - [`keysym_to_char`] and [`char_to_keysym`] for converting between keysyms and
+//!   Unicode characters, covering Latin-1, the legacy keysym sets from X.Org's
+//!   `keysymdef.h` (Latin-2/3/4/8/9, Kana, Arabic, Cyrillic, Greek, Hebrew, Thai,
+//!   Korean, ...) and the `0x01000000 + codepoint` Unicode keysym range.
+//! - [`text_to_key_events`] for decomposing a string into the press/release sequence a
+//!   VNC client would send when typing it.
+//! - [`KeyNormalizer`] for mapping keypad keys to their main keyboard equivalents,
+//!   folding dead keys into accented characters and applying a layout table. The server
+//!   runs one per client as configured by [`InputConfig`](crate::config::InputConfig).
+//! - [`LedState`] for the lock key LEDs the server reports to viewers with
+//!   [`VncServer::set_led_state`](crate::VncServer::set_led_state).
+
+use std::collections::HashMap;
+
+/// `BackSpace` key.
+pub const BACKSPACE: u32 = 0xff08;
+/// `Tab` key.
+pub const TAB: u32 = 0xff09;
+/// `Linefeed` key.
+pub const LINEFEED: u32 = 0xff0a;
+/// `Clear` key.
+pub const CLEAR: u32 = 0xff0b;
+/// `Return` (Enter) key.
+pub const RETURN: u32 = 0xff0d;
+/// `Pause` key.
+pub const PAUSE: u32 = 0xff13;
+/// `Scroll_Lock` key.
+pub const SCROLL_LOCK: u32 = 0xff14;
+/// `Sys_Req` key.
+pub const SYS_REQ: u32 = 0xff15;
+/// `Escape` key.
+pub const ESCAPE: u32 = 0xff1b;
+/// `Delete` key.
+pub const DELETE: u32 = 0xffff;
+
+/// `Home` key.
+pub const HOME: u32 = 0xff50;
+/// `Left` arrow key.
+pub const LEFT: u32 = 0xff51;
+/// `Up` arrow key.
+pub const UP: u32 = 0xff52;
+/// `Right` arrow key.
+pub const RIGHT: u32 = 0xff53;
+/// `Down` arrow key.
+pub const DOWN: u32 = 0xff54;
+/// `Page_Up` (Prior) key.
+pub const PAGE_UP: u32 = 0xff55;
+/// `Page_Down` (Next) key.
+pub const PAGE_DOWN: u32 = 0xff56;
+/// `End` key.
+pub const END: u32 = 0xff57;
+/// `Begin` key.
+pub const BEGIN: u32 = 0xff58;
+
+/// `Select` key.
+pub const SELECT: u32 = 0xff60;
+/// `Print` (Print Screen) key.
+pub const PRINT: u32 = 0xff61;
+/// `Execute` key.
+pub const EXECUTE: u32 = 0xff62;
+/// `Insert` key.
+pub const INSERT: u32 = 0xff63;
+/// `Undo` key.
+pub const UNDO: u32 = 0xff65;
+/// `Redo` key.
+pub const REDO: u32 = 0xff66;
+/// `Menu` key.
+pub const MENU: u32 = 0xff67;
+/// `Find` key.
+pub const FIND: u32 = 0xff68;
+/// `Cancel` key.
+pub const CANCEL: u32 = 0xff69;
+/// `Help` key.
+pub const HELP: u32 = 0xff6a;
+/// `Break` key.
+pub const BREAK: u32 = 0xff6b;
+/// `Mode_switch` key.
+pub const MODE_SWITCH: u32 = 0xff7e;
+/// `Num_Lock` key.
+pub const NUM_LOCK: u32 = 0xff7f;
+
+/// Keypad `Space`.
+pub const KP_SPACE: u32 = 0xff80;
+/// Keypad `Tab`.
+pub const KP_TAB: u32 = 0xff89;
+/// Keypad `Enter`.
+pub const KP_ENTER: u32 = 0xff8d;
+/// Keypad `Home`.
+pub const KP_HOME: u32 = 0xff95;
+/// Keypad `Left`.
+pub const KP_LEFT: u32 = 0xff96;
+/// Keypad `Up`.
+pub const KP_UP: u32 = 0xff97;
+/// Keypad `Right`.
+pub const KP_RIGHT: u32 = 0xff98;
+/// Keypad `Down`.
+pub const KP_DOWN: u32 = 0xff99;
+/// Keypad `Page_Up`.
+pub const KP_PAGE_UP: u32 = 0xff9a;
+/// Keypad `Page_Down`.
+pub const KP_PAGE_DOWN: u32 = 0xff9b;
+/// Keypad `End`.
+pub const KP_END: u32 = 0xff9c;
+/// Keypad `Begin`.
+pub const KP_BEGIN: u32 = 0xff9d;
+/// Keypad `Insert`.
+pub const KP_INSERT: u32 = 0xff9e;
+/// Keypad `Delete`.
+pub const KP_DELETE: u32 = 0xff9f;
+/// Keypad `=`.
+pub const KP_EQUAL: u32 = 0xffbd;
+/// Keypad `*`.
+pub const KP_MULTIPLY: u32 = 0xffaa;
+/// Keypad `+`.
+pub const KP_ADD: u32 = 0xffab;
+/// Keypad separator (`,`).
+pub const KP_SEPARATOR: u32 = 0xffac;
+/// Keypad `-`.
+pub const KP_SUBTRACT: u32 = 0xffad;
+/// Keypad decimal point.
+pub const KP_DECIMAL: u32 = 0xffae;
+/// Keypad `/`.
+pub const KP_DIVIDE: u32 = 0xffaf;
+/// Keypad `0`; `KP_1` through `KP_9` follow consecutively.
+pub const KP_0: u32 = 0xffb0;
+/// Keypad `9`.
+pub const KP_9: u32 = 0xffb9;
+
+/// `F1`; `F2` through `F35` follow consecutively.
+pub const F1: u32 = 0xffbe;
+/// `F2`.
+pub const F2: u32 = 0xffbf;
+/// `F3`.
+pub const F3: u32 = 0xffc0;
+/// `F4`.
+pub const F4: u32 = 0xffc1;
+/// `F5`.
+pub const F5: u32 = 0xffc2;
+/// `F6`.
+pub const F6: u32 = 0xffc3;
+/// `F7`.
+pub const F7: u32 = 0xffc4;
+/// `F8`.
+pub const F8: u32 = 0xffc5;
+/// `F9`.
+pub const F9: u32 = 0xffc6;
+/// `F10`.
+pub const F10: u32 = 0xffc7;
+/// `F11`.
+pub const F11: u32 = 0xffc8;
+/// `F12`.
+pub const F12: u32 = 0xffc9;
+/// `F35`, the last function key keysym.
+pub const F35: u32 = 0xffe0;
+
+/// Left `Shift` key.
+pub const SHIFT_L: u32 = 0xffe1;
+/// Right `Shift` key.
+pub const SHIFT_R: u32 = 0xffe2;
+/// Left `Control` key.
+pub const CONTROL_L: u32 = 0xffe3;
+/// Right `Control` key.
+pub const CONTROL_R: u32 = 0xffe4;
+/// `Caps_Lock` key.
+pub const CAPS_LOCK: u32 = 0xffe5;
+/// `Shift_Lock` key.
+pub const SHIFT_LOCK: u32 = 0xffe6;
+/// Left `Meta` key.
+pub const META_L: u32 = 0xffe7;
+/// Right `Meta` key.
+pub const META_R: u32 = 0xffe8;
+/// Left `Alt` key.
+pub const ALT_L: u32 = 0xffe9;
+/// Right `Alt` key.
+pub const ALT_R: u32 = 0xffea;
+/// Left `Super` (Windows/Command) key.
+pub const SUPER_L: u32 = 0xffeb;
+/// Right `Super` (Windows/Command) key.
+pub const SUPER_R: u32 = 0xffec;
+/// Left `Hyper` key.
+pub const HYPER_L: u32 = 0xffed;
+/// Right `Hyper` key.
+pub const HYPER_R: u32 = 0xffee;
+/// `ISO_Level3_Shift` (`AltGr`) key.
+pub const ISO_LEVEL3_SHIFT: u32 = 0xfe03;
+/// `ISO_Left_Tab` (Shift+Tab) key.
+pub const ISO_LEFT_TAB: u32 = 0xfe20;
+
+/// `dead_grave` key.
+pub const DEAD_GRAVE: u32 = 0xfe50;
+/// `dead_acute` key.
+pub const DEAD_ACUTE: u32 = 0xfe51;
+/// `dead_circumflex` key.
+pub const DEAD_CIRCUMFLEX: u32 = 0xfe52;
+/// `dead_tilde` key.
+pub const DEAD_TILDE: u32 = 0xfe53;
+/// `dead_macron` key.
+pub const DEAD_MACRON: u32 = 0xfe54;
+/// `dead_breve` key.
+pub const DEAD_BREVE: u32 = 0xfe55;
+/// `dead_abovedot` key.
+pub const DEAD_ABOVEDOT: u32 = 0xfe56;
+/// `dead_diaeresis` key.
+pub const DEAD_DIAERESIS: u32 = 0xfe57;
+/// `dead_abovering` key.
+pub const DEAD_ABOVERING: u32 = 0xfe58;
+/// `dead_doubleacute` key.
+pub const DEAD_DOUBLEACUTE: u32 = 0xfe59;
+/// `dead_caron` key.
+pub const DEAD_CARON: u32 = 0xfe5a;
+/// `dead_cedilla` key.
+pub const DEAD_CEDILLA: u32 = 0xfe5b;
+/// `dead_ogonek` key.
+pub const DEAD_OGONEK: u32 = 0xfe5c;
+
+/// Offset added to a Unicode code point to form its keysym.
+pub const UNICODE_OFFSET: u32 = 0x0100_0000;
+
+/// A single key press or release, as sent in a VNC `KeyEvent` message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// `true` for a press, `false` for a release.
+    pub down: bool,
+    /// The X11 keysym.
+    pub keysym: u32,
+}
+
+/// State of the keyboard lock LEDs on the server.
+///
+/// Viewers that support the QEMU or `VMware` LED state pseudo-encoding use it to keep their
+/// own lock keys in step with the remote desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LedState {
+    /// Scroll Lock is on.
+    pub scroll_lock: bool,
+    /// Num Lock is on.
+    pub num_lock: bool,
+    /// Caps Lock is on.
+    pub caps_lock: bool,
+}
+
+impl LedState {
+    /// Returns the LED bits as both pseudo-encodings send them: bit 0 Scroll Lock,
+    /// bit 1 Num Lock, bit 2 Caps Lock.
+    pub(crate) fn bits(self) -> u8 {
+        u8::from(self.scroll_lock) | u8::from(self.num_lock) << 1 | u8::from(self.caps_lock) << 2
+    }
+}
+
+/// Returns `true` if the keysym is a modifier key (Shift, Control, Alt, Meta, ...).
+#[must_use]
+pub fn is_modifier(keysym: u32) -> bool {
+    (SHIFT_L..=HYPER_R).contains(&keysym)
+        || keysym == MODE_SWITCH
+        || keysym == NUM_LOCK
+        || (0xfe01..=0xfe13).contains(&keysym) // ISO lock and level shift keys
+}
+
+/// Returns `true` if the keysym is on the numeric keypad.
+#[must_use]
+pub fn is_keypad(keysym: u32) -> bool {
+    (KP_SPACE..=KP_EQUAL).contains(&keysym)
+}
+
+/// Converts a keysym to the Unicode character it produces.
+///
+/// # Arguments
+///
+/// * `keysym` - The X11 keysym from a key event.
+///
+/// # Returns
+///
+/// The character typed by the key, or `None` for keysyms that do not produce text
+/// (cursor keys, modifiers, function keys, ...). `BackSpace`, `Tab`, `Return`,
+/// `Escape` and `Delete` map to their ASCII control characters, and keypad keys map to
+/// the characters printed on them.
+#[must_use]
+pub fn keysym_to_char(keysym: u32) -> Option<char> {
+    match keysym {
+        0x20..=0x7e | 0xa0..=0xff => char::from_u32(keysym),
+        0x0100_0100..=0x0110_ffff => char::from_u32(keysym - UNICODE_OFFSET),
+        BACKSPACE => Some('\u{8}'),
+        TAB | KP_TAB => Some('\t'),
+        LINEFEED => Some('\n'),
+        RETURN | KP_ENTER => Some('\r'),
+        ESCAPE => Some('\u{1b}'),
+        DELETE => Some('\u{7f}'),
+        KP_SPACE => Some(' '),
+        KP_EQUAL => Some('='),
+        KP_MULTIPLY => Some('*'),
+        KP_ADD => Some('+'),
+        KP_SEPARATOR => Some(','),
+        KP_SUBTRACT => Some('-'),
+        KP_DECIMAL => Some('.'),
+        KP_DIVIDE => Some('/'),
+        KP_0..=KP_9 => char::from_digit(keysym - KP_0, 10),
+        0x100..=0xffff => {
+            let keysym = u16::try_from(keysym).ok()?;
+            LEGACY_KEYSYMS
+                .binary_search_by_key(&keysym, |&(k, _)| k)
+                .ok()
+                .and_then(|i| char::from_u32(u32::from(LEGACY_KEYSYMS[i].1)))
+        }
+        _ => None,
+    }
+}
+
+/// Converts a Unicode character to the keysym a VNC client would send for it.
+///
+/// Latin-1 characters map to their identical keysyms and characters with a legacy
+/// keysym use it, since older servers and applications recognize those better.
+/// Everything else uses the `0x01000000 + codepoint` Unicode keysym. The control
+/// characters `\b`, `\t`, `\n`, `\r`, `ESC` and `DEL` map to the corresponding keys.
+///
+/// # Arguments
+///
+/// * `c` - The character to convert.
+///
+/// # Returns
+///
+/// The keysym, or `None` for control characters without a key equivalent.
+#[must_use]
+pub fn char_to_keysym(c: char) -> Option<u32> {
+    let cp = u32::from(c);
+    match c {
+        '\u{8}' => Some(BACKSPACE),
+        '\t' => Some(TAB),
+        '\n' | '\r' => Some(RETURN),
+        '\u{1b}' => Some(ESCAPE),
+        '\u{7f}' => Some(DELETE),
+        '\u{20}'..='\u{7e}' | '\u{a0}'..='\u{ff}' => Some(cp),
+        _ if c.is_control() => None,
+        _ => {
+            let legacy = u16::try_from(cp).ok().and_then(|cp| {
+                LEGACY_KEYSYMS
+                    .iter()
+                    .find(|&&(_, ucs)| ucs == cp)
+                    .map(|&(keysym, _)| u32::from(keysym))
+            });
+            Some(legacy.unwrap_or(cp + UNICODE_OFFSET))
+        }
+    }
+}
+
+/// Decomposes text into the key press/release sequence that would type it.
+///
+/// Each character becomes a press followed by a release of its keysym (see
+/// [`char_to_keysym`]); `\r\n` is collapsed into a single `Return`. Characters without
+/// a keysym are skipped. No modifier keys are generated: VNC keysyms already encode the
+/// shifted character (for example `A` is `0x41`), and servers are expected to synthesize
+/// any modifiers their keyboard layout requires.
+///
+/// # Arguments
+///
+/// * `text` - The text to type.
+///
+/// # Returns
+///
+/// The key events in the order they should be sent.
+#[must_use]
+pub fn text_to_key_events(text: &str) -> Vec<KeyEvent> {
+    let mut events = Vec::with_capacity(text.len() * 2);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\r' && chars.peek() == Some(&'\n') {
+            continue;
+        }
+        if let Some(keysym) = char_to_keysym(c) {
+            events.push(KeyEvent { down: true, keysym });
+            events.push(KeyEvent {
+                down: false,
+                keysym,
+            });
+        }
+    }
+    events
+}
+
+/// Returns `true` if the keysym is a dead (accent) key.
+#[must_use]
+pub fn is_dead_key(keysym: u32) -> bool {
+    (DEAD_GRAVE..=0xfe8f).contains(&keysym)
+}
+
+/// Maps a keypad keysym to the equivalent key on the main keyboard.
+///
+/// Keypad digits and operators become the plain characters printed on them, `KP_Enter`
+/// becomes `Return` and the keypad navigation keys become the cursor block keys.
+///
+/// # Arguments
+///
+/// * `keysym` - The X11 keysym from a key event.
+///
+/// # Returns
+///
+/// The main keyboard keysym, or `keysym` unchanged if it is not a keypad key.
+#[must_use]
+pub fn keypad_to_main(keysym: u32) -> u32 {
+    match keysym {
+        KP_SPACE => 0x20,
+        KP_TAB => TAB,
+        KP_ENTER => RETURN,
+        KP_HOME => HOME,
+        KP_LEFT => LEFT,
+        KP_UP => UP,
+        KP_RIGHT => RIGHT,
+        KP_DOWN => DOWN,
+        KP_PAGE_UP => PAGE_UP,
+        KP_PAGE_DOWN => PAGE_DOWN,
+        KP_END => END,
+        KP_BEGIN => BEGIN,
+        KP_INSERT => INSERT,
+        KP_DELETE => DELETE,
+        _ if is_keypad(keysym) => keysym_to_char(keysym).map_or(keysym, u32::from),
+        _ => keysym,
+    }
+}
+
+/// Returns the spacing accent a dead key types when it is not combined with a letter
+/// (for example `dead_acute` followed by `space`).
+///
+/// # Arguments
+///
+/// * `keysym` - A dead key keysym.
+///
+/// # Returns
+///
+/// The keysym of the spacing accent, or `None` if `keysym` is not a supported dead key.
+#[must_use]
+pub fn dead_key_to_spacing(keysym: u32) -> Option<u32> {
+    match keysym {
+        DEAD_GRAVE => Some(0x60),
+        DEAD_ACUTE => Some(0xb4),
+        DEAD_CIRCUMFLEX => Some(0x5e),
+        DEAD_TILDE => Some(0x7e),
+        DEAD_MACRON => Some(0xaf),
+        DEAD_BREVE => Some(0x1a2),
+        DEAD_ABOVEDOT => Some(0x1ff),
+        DEAD_DIAERESIS => Some(0xa8),
+        DEAD_ABOVERING => Some(0xb0),
+        DEAD_DOUBLEACUTE => Some(0x1bd),
+        DEAD_CARON => Some(0x1b7),
+        DEAD_CEDILLA => Some(0xb8),
+        DEAD_OGONEK => Some(0x1b2),
+        _ => None,
+    }
+}
+
+/// Combines a dead key with the key typed after it.
+///
+/// Covers the accented Latin letters Unicode composes from the supported dead keys
+/// (grave, acute, circumflex, tilde, macron, breve, dot above, diaeresis, ring,
+/// double acute, caron, cedilla and ogonek).
+///
+/// # Arguments
+///
+/// * `dead_key` - The dead key keysym.
+/// * `keysym` - The keysym typed after the dead key.
+///
+/// # Returns
+///
+/// The keysym of the accented character (see [`char_to_keysym`]), or `None` if the pair
+/// does not compose.
+#[must_use]
+pub fn compose_dead_key(dead_key: u32, keysym: u32) -> Option<u32> {
+    let dead_key = u16::try_from(dead_key).ok()?;
+    let base = u8::try_from(keysym).ok()?;
+    DEAD_KEY_COMPOSITIONS
+        .binary_search_by_key(&(dead_key, base), |&(d, b, _)| (d, b))
+        .ok()
+        .and_then(|i| char::from_u32(u32::from(DEAD_KEY_COMPOSITIONS[i].2)))
+        .and_then(char_to_keysym)
+}
+
+/// Normalizes the key events of one client before they reach the application.
+///
+/// Depending on its options, the normalizer maps keypad keys to their main keyboard
+/// equivalents, folds dead keys into the following key, and finally translates keysyms
+/// through a layout table. Releases are translated to match their presses, so a key
+/// released after the options changed its meaning is still released correctly.
+#[derive(Debug, Default)]
+pub struct KeyNormalizer {
+    keypad_as_main: bool,
+    fold_dead_keys: bool,
+    layout: HashMap<u32, u32>,
+    /// Dead key waiting for the next key press.
+    pending_dead_key: Option<u32>,
+    /// Keys currently held down, mapped to the keysym their press was reported as.
+    pressed: HashMap<u32, u32>,
+}
+
+impl KeyNormalizer {
+    /// Creates a normalizer.
+    ///
+    /// # Arguments
+    ///
+    /// * `keypad_as_main` - Map keypad keys with [`keypad_to_main`].
+    /// * `fold_dead_keys` - Combine dead keys with the next key (see [`compose_dead_key`]).
+    ///   A dead key followed by a key it doesn't compose with types the spacing accent
+    ///   first; followed by `space`, it types just the accent.
+    /// * `layout` - Keysyms to replace after the other steps; keysyms not in the table are
+    ///   passed through.
+    #[must_use]
+    pub fn new(keypad_as_main: bool, fold_dead_keys: bool, layout: HashMap<u32, u32>) -> Self {
+        Self {
+            keypad_as_main,
+            fold_dead_keys,
+            layout,
+            pending_dead_key: None,
+            pressed: HashMap::new(),
+        }
+    }
+
+    /// Returns `true` if the normalizer passes every event through unchanged.
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        !self.keypad_as_main && !self.fold_dead_keys && self.layout.is_empty()
+    }
+
+    /// Normalizes one key event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The key event received from the client.
+    ///
+    /// # Returns
+    ///
+    /// The events to deliver in its place: none while a dead key is held back, one in
+    /// the common case, or several when a dead key that doesn't compose is flushed.
+    pub fn process(&mut self, event: KeyEvent) -> Vec<KeyEvent> {
+        if self.is_identity() {
+            return vec![event];
+        }
+        let original = event.keysym;
+
+        if !event.down {
+            return match self.pressed.remove(&original) {
+                Some(keysym) => vec![KeyEvent {
+                    down: false,
+                    keysym,
+                }],
+                // Releases of held-back dead keys are swallowed with their press
+                None if self.fold_dead_keys && is_dead_key(original) => Vec::new(),
+                None => vec![KeyEvent {
+                    down: false,
+                    keysym: self.translate(original),
+                }],
+            };
+        }
+
+        let mut keysym = original;
+        if self.keypad_as_main {
+            keysym = keypad_to_main(keysym);
+        }
+
+        let mut events = Vec::with_capacity(1);
+        if self.fold_dead_keys {
+            if is_dead_key(keysym) && dead_key_to_spacing(keysym).is_some() {
+                // A second dead key flushes the first one as a spacing accent
+                if let Some(previous) = self.pending_dead_key.replace(keysym) {
+                    self.push_spacing(previous, &mut events);
+                }
+                return events;
+            }
+            if !is_modifier(keysym) {
+                if let Some(dead_key) = self.pending_dead_key.take() {
+                    if let Some(composed) = compose_dead_key(dead_key, keysym) {
+                        keysym = composed;
+                    } else if keysym == 0x20 {
+                        keysym = dead_key_to_spacing(dead_key).unwrap_or(keysym);
+                    } else {
+                        self.push_spacing(dead_key, &mut events);
+                    }
+                }
+            }
+        }
+
+        let keysym = self.translate(keysym);
+        self.pressed.insert(original, keysym);
+        events.push(KeyEvent { down: true, keysym });
+        events
+    }
+
+    /// Applies the layout table.
+    fn translate(&self, keysym: u32) -> u32 {
+        self.layout.get(&keysym).copied().unwrap_or(keysym)
+    }
+
+    /// Types the spacing accent of a dead key as a press and release.
+    fn push_spacing(&self, dead_key: u32, events: &mut Vec<KeyEvent>) {
+        if let Some(spacing) = dead_key_to_spacing(dead_key) {
+            let keysym = self.translate(spacing);
+            events.push(KeyEvent { down: true, keysym });
+            events.push(KeyEvent {
+                down: false,
+                keysym,
+            });
+        }
+    }
+}
+
+/// Legacy (non-Latin-1) keysyms and their Unicode code points, sorted by keysym.
+///
+/// Generated from the `U+` annotations in X.Org's `keysymdef.h`.
+#[rustfmt::skip]
+const LEGACY_KEYSYMS: [(u16, u16); 762] = [
+    (0x01a1, 0x0104), (0x01a2, 0x02d8), (0x01a3, 0x0141), (0x01a5, 0x013d), (0x01a6, 0x015a), (0x01a9, 0x0160),
+    (0x01aa, 0x015e), (0x01ab, 0x0164), (0x01ac, 0x0179), (0x01ae, 0x017d), (0x01af, 0x017b), (0x01b1, 0x0105),
+    (0x01b2, 0x02db), (0x01b3, 0x0142), (0x01b5, 0x013e), (0x01b6, 0x015b), (0x01b7, 0x02c7), (0x01b9, 0x0161),
+    (0x01ba, 0x015f), (0x01bb, 0x0165), (0x01bc, 0x017a), (0x01bd, 0x02dd), (0x01be, 0x017e), (0x01bf, 0x017c),
+    (0x01c0, 0x0154), (0x01c3, 0x0102), (0x01c5, 0x0139), (0x01c6, 0x0106), (0x01c8, 0x010c), (0x01ca, 0x0118),
+    (0x01cc, 0x011a), (0x01cf, 0x010e), (0x01d0, 0x0110), (0x01d1, 0x0143), (0x01d2, 0x0147), (0x01d5, 0x0150),
+    (0x01d8, 0x0158), (0x01d9, 0x016e), (0x01db, 0x0170), (0x01de, 0x0162), (0x01e0, 0x0155), (0x01e3, 0x0103),
+    (0x01e5, 0x013a), (0x01e6, 0x0107), (0x01e8, 0x010d), (0x01ea, 0x0119), (0x01ec, 0x011b), (0x01ef, 0x010f),
+    (0x01f0, 0x0111), (0x01f1, 0x0144), (0x01f2, 0x0148), (0x01f5, 0x0151), (0x01f8, 0x0159), (0x01f9, 0x016f),
+    (0x01fb, 0x0171), (0x01fe, 0x0163), (0x01ff, 0x02d9), (0x02a1, 0x0126), (0x02a6, 0x0124), (0x02a9, 0x0130),
+    (0x02ab, 0x011e), (0x02ac, 0x0134), (0x02b1, 0x0127), (0x02b6, 0x0125), (0x02b9, 0x0131), (0x02bb, 0x011f),
+    (0x02bc, 0x0135), (0x02c5, 0x010a), (0x02c6, 0x0108), (0x02d5, 0x0120), (0x02d8, 0x011c), (0x02dd, 0x016c),
+    (0x02de, 0x015c), (0x02e5, 0x010b), (0x02e6, 0x0109), (0x02f5, 0x0121), (0x02f8, 0x011d), (0x02fd, 0x016d),
+    (0x02fe, 0x015d), (0x03a2, 0x0138), (0x03a3, 0x0156), (0x03a5, 0x0128), (0x03a6, 0x013b), (0x03aa, 0x0112),
+    (0x03ab, 0x0122), (0x03ac, 0x0166), (0x03b3, 0x0157), (0x03b5, 0x0129), (0x03b6, 0x013c), (0x03ba, 0x0113),
+    (0x03bb, 0x0123), (0x03bc, 0x0167), (0x03bd, 0x014a), (0x03bf, 0x014b), (0x03c0, 0x0100), (0x03c7, 0x012e),
+    (0x03cc, 0x0116), (0x03cf, 0x012a), (0x03d1, 0x0145), (0x03d2, 0x014c), (0x03d3, 0x0136), (0x03d9, 0x0172),
+    (0x03dd, 0x0168), (0x03de, 0x016a), (0x03e0, 0x0101), (0x03e7, 0x012f), (0x03ec, 0x0117), (0x03ef, 0x012b),
+    (0x03f1, 0x0146), (0x03f2, 0x014d), (0x03f3, 0x0137), (0x03f9, 0x0173), (0x03fd, 0x0169), (0x03fe, 0x016b),
+    (0x047e, 0x203e), (0x04a1, 0x3002), (0x04a2, 0x300c), (0x04a3, 0x300d), (0x04a4, 0x3001), (0x04a5, 0x30fb),
+    (0x04a6, 0x30f2), (0x04a7, 0x30a1), (0x04a8, 0x30a3), (0x04a9, 0x30a5), (0x04aa, 0x30a7), (0x04ab, 0x30a9),
+    (0x04ac, 0x30e3), (0x04ad, 0x30e5), (0x04ae, 0x30e7), (0x04af, 0x30c3), (0x04b0, 0x30fc), (0x04b1, 0x30a2),
+    (0x04b2, 0x30a4), (0x04b3, 0x30a6), (0x04b4, 0x30a8), (0x04b5, 0x30aa), (0x04b6, 0x30ab), (0x04b7, 0x30ad),
+    (0x04b8, 0x30af), (0x04b9, 0x30b1), (0x04ba, 0x30b3), (0x04bb, 0x30b5), (0x04bc, 0x30b7), (0x04bd, 0x30b9),
+    (0x04be, 0x30bb), (0x04bf, 0x30bd), (0x04c0, 0x30bf), (0x04c1, 0x30c1), (0x04c2, 0x30c4), (0x04c3, 0x30c6),
+    (0x04c4, 0x30c8), (0x04c5, 0x30ca), (0x04c6, 0x30cb), (0x04c7, 0x30cc), (0x04c8, 0x30cd), (0x04c9, 0x30ce),
+    (0x04ca, 0x30cf), (0x04cb, 0x30d2), (0x04cc, 0x30d5), (0x04cd, 0x30d8), (0x04ce, 0x30db), (0x04cf, 0x30de),
+    (0x04d0, 0x30df), (0x04d1, 0x30e0), (0x04d2, 0x30e1), (0x04d3, 0x30e2), (0x04d4, 0x30e4), (0x04d5, 0x30e6),
+    (0x04d6, 0x30e8), (0x04d7, 0x30e9), (0x04d8, 0x30ea), (0x04d9, 0x30eb), (0x04da, 0x30ec), (0x04db, 0x30ed),
+    (0x04dc, 0x30ef), (0x04dd, 0x30f3), (0x04de, 0x309b), (0x04df, 0x309c), (0x05ac, 0x060c), (0x05bb, 0x061b),
+    (0x05bf, 0x061f), (0x05c1, 0x0621), (0x05c2, 0x0622), (0x05c3, 0x0623), (0x05c4, 0x0624), (0x05c5, 0x0625),
+    (0x05c6, 0x0626), (0x05c7, 0x0627), (0x05c8, 0x0628), (0x05c9, 0x0629), (0x05ca, 0x062a), (0x05cb, 0x062b),
+    (0x05cc, 0x062c), (0x05cd, 0x062d), (0x05ce, 0x062e), (0x05cf, 0x062f), (0x05d0, 0x0630), (0x05d1, 0x0631),
+    (0x05d2, 0x0632), (0x05d3, 0x0633), (0x05d4, 0x0634), (0x05d5, 0x0635), (0x05d6, 0x0636), (0x05d7, 0x0637),
+    (0x05d8, 0x0638), (0x05d9, 0x0639), (0x05da, 0x063a), (0x05e0, 0x0640), (0x05e1, 0x0641), (0x05e2, 0x0642),
+    (0x05e3, 0x0643), (0x05e4, 0x0644), (0x05e5, 0x0645), (0x05e6, 0x0646), (0x05e7, 0x0647), (0x05e8, 0x0648),
+    (0x05e9, 0x0649), (0x05ea, 0x064a), (0x05eb, 0x064b), (0x05ec, 0x064c), (0x05ed, 0x064d), (0x05ee, 0x064e),
+    (0x05ef, 0x064f), (0x05f0, 0x0650), (0x05f1, 0x0651), (0x05f2, 0x0652), (0x06a1, 0x0452), (0x06a2, 0x0453),
+    (0x06a3, 0x0451), (0x06a4, 0x0454), (0x06a5, 0x0455), (0x06a6, 0x0456), (0x06a7, 0x0457), (0x06a8, 0x0458),
+    (0x06a9, 0x0459), (0x06aa, 0x045a), (0x06ab, 0x045b), (0x06ac, 0x045c), (0x06ad, 0x0491), (0x06ae, 0x045e),
+    (0x06af, 0x045f), (0x06b0, 0x2116), (0x06b1, 0x0402), (0x06b2, 0x0403), (0x06b3, 0x0401), (0x06b4, 0x0404),
+    (0x06b5, 0x0405), (0x06b6, 0x0406), (0x06b7, 0x0407), (0x06b8, 0x0408), (0x06b9, 0x0409), (0x06ba, 0x040a),
+    (0x06bb, 0x040b), (0x06bc, 0x040c), (0x06bd, 0x0490), (0x06be, 0x040e), (0x06bf, 0x040f), (0x06c0, 0x044e),
+    (0x06c1, 0x0430), (0x06c2, 0x0431), (0x06c3, 0x0446), (0x06c4, 0x0434), (0x06c5, 0x0435), (0x06c6, 0x0444),
+    (0x06c7, 0x0433), (0x06c8, 0x0445), (0x06c9, 0x0438), (0x06ca, 0x0439), (0x06cb, 0x043a), (0x06cc, 0x043b),
+    (0x06cd, 0x043c), (0x06ce, 0x043d), (0x06cf, 0x043e), (0x06d0, 0x043f), (0x06d1, 0x044f), (0x06d2, 0x0440),
+    (0x06d3, 0x0441), (0x06d4, 0x0442), (0x06d5, 0x0443), (0x06d6, 0x0436), (0x06d7, 0x0432), (0x06d8, 0x044c),
+    (0x06d9, 0x044b), (0x06da, 0x0437), (0x06db, 0x0448), (0x06dc, 0x044d), (0x06dd, 0x0449), (0x06de, 0x0447),
+    (0x06df, 0x044a), (0x06e0, 0x042e), (0x06e1, 0x0410), (0x06e2, 0x0411), (0x06e3, 0x0426), (0x06e4, 0x0414),
+    (0x06e5, 0x0415), (0x06e6, 0x0424), (0x06e7, 0x0413), (0x06e8, 0x0425), (0x06e9, 0x0418), (0x06ea, 0x0419),
+    (0x06eb, 0x041a), (0x06ec, 0x041b), (0x06ed, 0x041c), (0x06ee, 0x041d), (0x06ef, 0x041e), (0x06f0, 0x041f),
+    (0x06f1, 0x042f), (0x06f2, 0x0420), (0x06f3, 0x0421), (0x06f4, 0x0422), (0x06f5, 0x0423), (0x06f6, 0x0416),
+    (0x06f7, 0x0412), (0x06f8, 0x042c), (0x06f9, 0x042b), (0x06fa, 0x0417), (0x06fb, 0x0428), (0x06fc, 0x042d),
+    (0x06fd, 0x0429), (0x06fe, 0x0427), (0x06ff, 0x042a), (0x07a1, 0x0386), (0x07a2, 0x0388), (0x07a3, 0x0389),
+    (0x07a4, 0x038a), (0x07a5, 0x03aa), (0x07a7, 0x038c), (0x07a8, 0x038e), (0x07a9, 0x03ab), (0x07ab, 0x038f),
+    (0x07ae, 0x0385), (0x07af, 0x2015), (0x07b1, 0x03ac), (0x07b2, 0x03ad), (0x07b3, 0x03ae), (0x07b4, 0x03af),
+    (0x07b5, 0x03ca), (0x07b6, 0x0390), (0x07b7, 0x03cc), (0x07b8, 0x03cd), (0x07b9, 0x03cb), (0x07ba, 0x03b0),
+    (0x07bb, 0x03ce), (0x07c1, 0x0391), (0x07c2, 0x0392), (0x07c3, 0x0393), (0x07c4, 0x0394), (0x07c5, 0x0395),
+    (0x07c6, 0x0396), (0x07c7, 0x0397), (0x07c8, 0x0398), (0x07c9, 0x0399), (0x07ca, 0x039a), (0x07cb, 0x039b),
+    (0x07cc, 0x039c), (0x07cd, 0x039d), (0x07ce, 0x039e), (0x07cf, 0x039f), (0x07d0, 0x03a0), (0x07d1, 0x03a1),
+    (0x07d2, 0x03a3), (0x07d4, 0x03a4), (0x07d5, 0x03a5), (0x07d6, 0x03a6), (0x07d7, 0x03a7), (0x07d8, 0x03a8),
+    (0x07d9, 0x03a9), (0x07e1, 0x03b1), (0x07e2, 0x03b2), (0x07e3, 0x03b3), (0x07e4, 0x03b4), (0x07e5, 0x03b5),
+    (0x07e6, 0x03b6), (0x07e7, 0x03b7), (0x07e8, 0x03b8), (0x07e9, 0x03b9), (0x07ea, 0x03ba), (0x07eb, 0x03bb),
+    (0x07ec, 0x03bc), (0x07ed, 0x03bd), (0x07ee, 0x03be), (0x07ef, 0x03bf), (0x07f0, 0x03c0), (0x07f1, 0x03c1),
+    (0x07f2, 0x03c3), (0x07f3, 0x03c2), (0x07f4, 0x03c4), (0x07f5, 0x03c5), (0x07f6, 0x03c6), (0x07f7, 0x03c7),
+    (0x07f8, 0x03c8), (0x07f9, 0x03c9), (0x08a1, 0x23b7), (0x08a2, 0x250c), (0x08a3, 0x2500), (0x08a4, 0x2320),
+    (0x08a5, 0x2321), (0x08a6, 0x2502), (0x08a7, 0x23a1), (0x08a8, 0x23a3), (0x08a9, 0x23a4), (0x08aa, 0x23a6),
+    (0x08ab, 0x239b), (0x08ac, 0x239d), (0x08ad, 0x239e), (0x08ae, 0x23a0), (0x08af, 0x23a8), (0x08b0, 0x23ac),
+    (0x08bc, 0x2264), (0x08bd, 0x2260), (0x08be, 0x2265), (0x08bf, 0x222b), (0x08c0, 0x2234), (0x08c1, 0x221d),
+    (0x08c2, 0x221e), (0x08c5, 0x2207), (0x08c8, 0x223c), (0x08c9, 0x2243), (0x08cd, 0x21d4), (0x08ce, 0x21d2),
+    (0x08cf, 0x2261), (0x08d6, 0x221a), (0x08da, 0x2282), (0x08db, 0x2283), (0x08dc, 0x2229), (0x08dd, 0x222a),
+    (0x08de, 0x2227), (0x08df, 0x2228), (0x08ef, 0x2202), (0x08f6, 0x0192), (0x08fb, 0x2190), (0x08fc, 0x2191),
+    (0x08fd, 0x2192), (0x08fe, 0x2193), (0x09e0, 0x25c6), (0x09e1, 0x2592), (0x09e2, 0x2409), (0x09e3, 0x240c),
+    (0x09e4, 0x240d), (0x09e5, 0x240a), (0x09e8, 0x2424), (0x09e9, 0x240b), (0x09ea, 0x2518), (0x09eb, 0x2510),
+    (0x09ec, 0x250c), (0x09ed, 0x2514), (0x09ee, 0x253c), (0x09ef, 0x23ba), (0x09f0, 0x23bb), (0x09f1, 0x2500),
+    (0x09f2, 0x23bc), (0x09f3, 0x23bd), (0x09f4, 0x251c), (0x09f5, 0x2524), (0x09f6, 0x2534), (0x09f7, 0x252c),
+    (0x09f8, 0x2502), (0x0aa1, 0x2003), (0x0aa2, 0x2002), (0x0aa3, 0x2004), (0x0aa4, 0x2005), (0x0aa5, 0x2007),
+    (0x0aa6, 0x2008), (0x0aa7, 0x2009), (0x0aa8, 0x200a), (0x0aa9, 0x2014), (0x0aaa, 0x2013), (0x0aac, 0x2423),
+    (0x0aae, 0x2026), (0x0aaf, 0x2025), (0x0ab0, 0x2153), (0x0ab1, 0x2154), (0x0ab2, 0x2155), (0x0ab3, 0x2156),
+    (0x0ab4, 0x2157), (0x0ab5, 0x2158), (0x0ab6, 0x2159), (0x0ab7, 0x215a), (0x0ab8, 0x2105), (0x0abb, 0x2012),
+    (0x0abc, 0x2329), (0x0abd, 0x002e), (0x0abe, 0x232a), (0x0ac3, 0x215b), (0x0ac4, 0x215c), (0x0ac5, 0x215d),
+    (0x0ac6, 0x215e), (0x0ac9, 0x2122), (0x0aca, 0x2613), (0x0acc, 0x25c1), (0x0acd, 0x25b7), (0x0ace, 0x25cb),
+    (0x0acf, 0x25af), (0x0ad0, 0x2018), (0x0ad1, 0x2019), (0x0ad2, 0x201c), (0x0ad3, 0x201d), (0x0ad4, 0x211e),
+    (0x0ad5, 0x2030), (0x0ad6, 0x2032), (0x0ad7, 0x2033), (0x0ad9, 0x271d), (0x0adb, 0x25ac), (0x0adc, 0x25c0),
+    (0x0add, 0x25b6), (0x0ade, 0x25cf), (0x0adf, 0x25ae), (0x0ae0, 0x25e6), (0x0ae1, 0x25ab), (0x0ae2, 0x25ad),
+    (0x0ae3, 0x25b3), (0x0ae4, 0x25bd), (0x0ae5, 0x2606), (0x0ae6, 0x2022), (0x0ae7, 0x25aa), (0x0ae8, 0x25b2),
+    (0x0ae9, 0x25bc), (0x0aea, 0x261c), (0x0aeb, 0x261e), (0x0aec, 0x2663), (0x0aed, 0x2666), (0x0aee, 0x2665),
+    (0x0af0, 0x2720), (0x0af1, 0x2020), (0x0af2, 0x2021), (0x0af3, 0x2713), (0x0af4, 0x2717), (0x0af5, 0x266f),
+    (0x0af6, 0x266d), (0x0af7, 0x2642), (0x0af8, 0x2640), (0x0af9, 0x260e), (0x0afa, 0x2315), (0x0afb, 0x2117),
+    (0x0afc, 0x2038), (0x0afd, 0x201a), (0x0afe, 0x201e), (0x0ba3, 0x003c), (0x0ba6, 0x003e), (0x0ba8, 0x2228),
+    (0x0ba9, 0x2227), (0x0bc0, 0x00af), (0x0bc2, 0x22a4), (0x0bc3, 0x2229), (0x0bc4, 0x230a), (0x0bc6, 0x005f),
+    (0x0bca, 0x2218), (0x0bcc, 0x2395), (0x0bce, 0x22a5), (0x0bcf, 0x25cb), (0x0bd3, 0x2308), (0x0bd6, 0x222a),
+    (0x0bd8, 0x2283), (0x0bda, 0x2282), (0x0bdc, 0x22a3), (0x0bfc, 0x22a2), (0x0cdf, 0x2017), (0x0ce0, 0x05d0),
+    (0x0ce1, 0x05d1), (0x0ce2, 0x05d2), (0x0ce3, 0x05d3), (0x0ce4, 0x05d4), (0x0ce5, 0x05d5), (0x0ce6, 0x05d6),
+    (0x0ce7, 0x05d7), (0x0ce8, 0x05d8), (0x0ce9, 0x05d9), (0x0cea, 0x05da), (0x0ceb, 0x05db), (0x0cec, 0x05dc),
+    (0x0ced, 0x05dd), (0x0cee, 0x05de), (0x0cef, 0x05df), (0x0cf0, 0x05e0), (0x0cf1, 0x05e1), (0x0cf2, 0x05e2),
+    (0x0cf3, 0x05e3), (0x0cf4, 0x05e4), (0x0cf5, 0x05e5), (0x0cf6, 0x05e6), (0x0cf7, 0x05e7), (0x0cf8, 0x05e8),
+    (0x0cf9, 0x05e9), (0x0cfa, 0x05ea), (0x0da1, 0x0e01), (0x0da2, 0x0e02), (0x0da3, 0x0e03), (0x0da4, 0x0e04),
+    (0x0da5, 0x0e05), (0x0da6, 0x0e06), (0x0da7, 0x0e07), (0x0da8, 0x0e08), (0x0da9, 0x0e09), (0x0daa, 0x0e0a),
+    (0x0dab, 0x0e0b), (0x0dac, 0x0e0c), (0x0dad, 0x0e0d), (0x0dae, 0x0e0e), (0x0daf, 0x0e0f), (0x0db0, 0x0e10),
+    (0x0db1, 0x0e11), (0x0db2, 0x0e12), (0x0db3, 0x0e13), (0x0db4, 0x0e14), (0x0db5, 0x0e15), (0x0db6, 0x0e16),
+    (0x0db7, 0x0e17), (0x0db8, 0x0e18), (0x0db9, 0x0e19), (0x0dba, 0x0e1a), (0x0dbb, 0x0e1b), (0x0dbc, 0x0e1c),
+    (0x0dbd, 0x0e1d), (0x0dbe, 0x0e1e), (0x0dbf, 0x0e1f), (0x0dc0, 0x0e20), (0x0dc1, 0x0e21), (0x0dc2, 0x0e22),
+    (0x0dc3, 0x0e23), (0x0dc4, 0x0e24), (0x0dc5, 0x0e25), (0x0dc6, 0x0e26), (0x0dc7, 0x0e27), (0x0dc8, 0x0e28),
+    (0x0dc9, 0x0e29), (0x0dca, 0x0e2a), (0x0dcb, 0x0e2b), (0x0dcc, 0x0e2c), (0x0dcd, 0x0e2d), (0x0dce, 0x0e2e),
+    (0x0dcf, 0x0e2f), (0x0dd0, 0x0e30), (0x0dd1, 0x0e31), (0x0dd2, 0x0e32), (0x0dd3, 0x0e33), (0x0dd4, 0x0e34),
+    (0x0dd5, 0x0e35), (0x0dd6, 0x0e36), (0x0dd7, 0x0e37), (0x0dd8, 0x0e38), (0x0dd9, 0x0e39), (0x0dda, 0x0e3a),
+    (0x0ddf, 0x0e3f), (0x0de0, 0x0e40), (0x0de1, 0x0e41), (0x0de2, 0x0e42), (0x0de3, 0x0e43), (0x0de4, 0x0e44),
+    (0x0de5, 0x0e45), (0x0de6, 0x0e46), (0x0de7, 0x0e47), (0x0de8, 0x0e48), (0x0de9, 0x0e49), (0x0dea, 0x0e4a),
+    (0x0deb, 0x0e4b), (0x0dec, 0x0e4c), (0x0ded, 0x0e4d), (0x0df0, 0x0e50), (0x0df1, 0x0e51), (0x0df2, 0x0e52),
+    (0x0df3, 0x0e53), (0x0df4, 0x0e54), (0x0df5, 0x0e55), (0x0df6, 0x0e56), (0x0df7, 0x0e57), (0x0df8, 0x0e58),
+    (0x0df9, 0x0e59), (0x0ea1, 0x3131), (0x0ea2, 0x3132), (0x0ea3, 0x3133), (0x0ea4, 0x3134), (0x0ea5, 0x3135),
+    (0x0ea6, 0x3136), (0x0ea7, 0x3137), (0x0ea8, 0x3138), (0x0ea9, 0x3139), (0x0eaa, 0x313a), (0x0eab, 0x313b),
+    (0x0eac, 0x313c), (0x0ead, 0x313d), (0x0eae, 0x313e), (0x0eaf, 0x313f), (0x0eb0, 0x3140), (0x0eb1, 0x3141),
+    (0x0eb2, 0x3142), (0x0eb3, 0x3143), (0x0eb4, 0x3144), (0x0eb5, 0x3145), (0x0eb6, 0x3146), (0x0eb7, 0x3147),
+    (0x0eb8, 0x3148), (0x0eb9, 0x3149), (0x0eba, 0x314a), (0x0ebb, 0x314b), (0x0ebc, 0x314c), (0x0ebd, 0x314d),
+    (0x0ebe, 0x314e), (0x0ebf, 0x314f), (0x0ec0, 0x3150), (0x0ec1, 0x3151), (0x0ec2, 0x3152), (0x0ec3, 0x3153),
+    (0x0ec4, 0x3154), (0x0ec5, 0x3155), (0x0ec6, 0x3156), (0x0ec7, 0x3157), (0x0ec8, 0x3158), (0x0ec9, 0x3159),
+    (0x0eca, 0x315a), (0x0ecb, 0x315b), (0x0ecc, 0x315c), (0x0ecd, 0x315d), (0x0ece, 0x315e), (0x0ecf, 0x315f),
+    (0x0ed0, 0x3160), (0x0ed1, 0x3161), (0x0ed2, 0x3162), (0x0ed3, 0x3163), (0x0ed4, 0x11a8), (0x0ed5, 0x11a9),
+    (0x0ed6, 0x11aa), (0x0ed7, 0x11ab), (0x0ed8, 0x11ac), (0x0ed9, 0x11ad), (0x0eda, 0x11ae), (0x0edb, 0x11af),
+    (0x0edc, 0x11b0), (0x0edd, 0x11b1), (0x0ede, 0x11b2), (0x0edf, 0x11b3), (0x0ee0, 0x11b4), (0x0ee1, 0x11b5),
+    (0x0ee2, 0x11b6), (0x0ee3, 0x11b7), (0x0ee4, 0x11b8), (0x0ee5, 0x11b9), (0x0ee6, 0x11ba), (0x0ee7, 0x11bb),
+    (0x0ee8, 0x11bc), (0x0ee9, 0x11bd), (0x0eea, 0x11be), (0x0eeb, 0x11bf), (0x0eec, 0x11c0), (0x0eed, 0x11c1),
+    (0x0eee, 0x11c2), (0x0eef, 0x316d), (0x0ef0, 0x3171), (0x0ef1, 0x3178), (0x0ef2, 0x317f), (0x0ef3, 0x3181),
+    (0x0ef4, 0x3184), (0x0ef5, 0x3186), (0x0ef6, 0x318d), (0x0ef7, 0x318e), (0x0ef8, 0x11eb), (0x0ef9, 0x11f0),
+    (0x0efa, 0x11f9), (0x0eff, 0x20a9), (0x13bc, 0x0152), (0x13bd, 0x0153), (0x13be, 0x0178), (0x20ac, 0x20ac),
+];
+
+/// Dead key, base letter and composed character, sorted by dead key and base letter.
+///
+/// Generated from Unicode canonical composition of the base letter with the combining
+/// mark of each dead key.
+#[rustfmt::skip]
+const DEAD_KEY_COMPOSITIONS: [(u16, u8, u16); 251] = [
+    (0xfe50, 0x41, 0x00c0), (0xfe50, 0x45, 0x00c8), (0xfe50, 0x49, 0x00cc), (0xfe50, 0x4e, 0x01f8), (0xfe50, 0x4f, 0x00d2), (0xfe50, 0x55, 0x00d9),
+    (0xfe50, 0x57, 0x1e80), (0xfe50, 0x59, 0x1ef2), (0xfe50, 0x61, 0x00e0), (0xfe50, 0x65, 0x00e8), (0xfe50, 0x69, 0x00ec), (0xfe50, 0x6e, 0x01f9),
+    (0xfe50, 0x6f, 0x00f2), (0xfe50, 0x75, 0x00f9), (0xfe50, 0x77, 0x1e81), (0xfe50, 0x79, 0x1ef3), (0xfe51, 0x41, 0x00c1), (0xfe51, 0x43, 0x0106),
+    (0xfe51, 0x45, 0x00c9), (0xfe51, 0x47, 0x01f4), (0xfe51, 0x49, 0x00cd), (0xfe51, 0x4b, 0x1e30), (0xfe51, 0x4c, 0x0139), (0xfe51, 0x4d, 0x1e3e),
+    (0xfe51, 0x4e, 0x0143), (0xfe51, 0x4f, 0x00d3), (0xfe51, 0x50, 0x1e54), (0xfe51, 0x52, 0x0154), (0xfe51, 0x53, 0x015a), (0xfe51, 0x55, 0x00da),
+    (0xfe51, 0x57, 0x1e82), (0xfe51, 0x59, 0x00dd), (0xfe51, 0x5a, 0x0179), (0xfe51, 0x61, 0x00e1), (0xfe51, 0x63, 0x0107), (0xfe51, 0x65, 0x00e9),
+    (0xfe51, 0x67, 0x01f5), (0xfe51, 0x69, 0x00ed), (0xfe51, 0x6b, 0x1e31), (0xfe51, 0x6c, 0x013a), (0xfe51, 0x6d, 0x1e3f), (0xfe51, 0x6e, 0x0144),
+    (0xfe51, 0x6f, 0x00f3), (0xfe51, 0x70, 0x1e55), (0xfe51, 0x72, 0x0155), (0xfe51, 0x73, 0x015b), (0xfe51, 0x75, 0x00fa), (0xfe51, 0x77, 0x1e83),
+    (0xfe51, 0x79, 0x00fd), (0xfe51, 0x7a, 0x017a), (0xfe52, 0x41, 0x00c2), (0xfe52, 0x43, 0x0108), (0xfe52, 0x45, 0x00ca), (0xfe52, 0x47, 0x011c),
+    (0xfe52, 0x48, 0x0124), (0xfe52, 0x49, 0x00ce), (0xfe52, 0x4a, 0x0134), (0xfe52, 0x4f, 0x00d4), (0xfe52, 0x53, 0x015c), (0xfe52, 0x55, 0x00db),
+    (0xfe52, 0x57, 0x0174), (0xfe52, 0x59, 0x0176), (0xfe52, 0x5a, 0x1e90), (0xfe52, 0x61, 0x00e2), (0xfe52, 0x63, 0x0109), (0xfe52, 0x65, 0x00ea),
+    (0xfe52, 0x67, 0x011d), (0xfe52, 0x68, 0x0125), (0xfe52, 0x69, 0x00ee), (0xfe52, 0x6a, 0x0135), (0xfe52, 0x6f, 0x00f4), (0xfe52, 0x73, 0x015d),
+    (0xfe52, 0x75, 0x00fb), (0xfe52, 0x77, 0x0175), (0xfe52, 0x79, 0x0177), (0xfe52, 0x7a, 0x1e91), (0xfe53, 0x41, 0x00c3), (0xfe53, 0x45, 0x1ebc),
+    (0xfe53, 0x49, 0x0128), (0xfe53, 0x4e, 0x00d1), (0xfe53, 0x4f, 0x00d5), (0xfe53, 0x55, 0x0168), (0xfe53, 0x56, 0x1e7c), (0xfe53, 0x59, 0x1ef8),
+    (0xfe53, 0x61, 0x00e3), (0xfe53, 0x65, 0x1ebd), (0xfe53, 0x69, 0x0129), (0xfe53, 0x6e, 0x00f1), (0xfe53, 0x6f, 0x00f5), (0xfe53, 0x75, 0x0169),
+    (0xfe53, 0x76, 0x1e7d), (0xfe53, 0x79, 0x1ef9), (0xfe54, 0x41, 0x0100), (0xfe54, 0x45, 0x0112), (0xfe54, 0x47, 0x1e20), (0xfe54, 0x49, 0x012a),
+    (0xfe54, 0x4f, 0x014c), (0xfe54, 0x55, 0x016a), (0xfe54, 0x59, 0x0232), (0xfe54, 0x61, 0x0101), (0xfe54, 0x65, 0x0113), (0xfe54, 0x67, 0x1e21),
+    (0xfe54, 0x69, 0x012b), (0xfe54, 0x6f, 0x014d), (0xfe54, 0x75, 0x016b), (0xfe54, 0x79, 0x0233), (0xfe55, 0x41, 0x0102), (0xfe55, 0x45, 0x0114),
+    (0xfe55, 0x47, 0x011e), (0xfe55, 0x49, 0x012c), (0xfe55, 0x4f, 0x014e), (0xfe55, 0x55, 0x016c), (0xfe55, 0x61, 0x0103), (0xfe55, 0x65, 0x0115),
+    (0xfe55, 0x67, 0x011f), (0xfe55, 0x69, 0x012d), (0xfe55, 0x6f, 0x014f), (0xfe55, 0x75, 0x016d), (0xfe56, 0x41, 0x0226), (0xfe56, 0x42, 0x1e02),
+    (0xfe56, 0x43, 0x010a), (0xfe56, 0x44, 0x1e0a), (0xfe56, 0x45, 0x0116), (0xfe56, 0x46, 0x1e1e), (0xfe56, 0x47, 0x0120), (0xfe56, 0x48, 0x1e22),
+    (0xfe56, 0x49, 0x0130), (0xfe56, 0x4d, 0x1e40), (0xfe56, 0x4e, 0x1e44), (0xfe56, 0x4f, 0x022e), (0xfe56, 0x50, 0x1e56), (0xfe56, 0x52, 0x1e58),
+    (0xfe56, 0x53, 0x1e60), (0xfe56, 0x54, 0x1e6a), (0xfe56, 0x57, 0x1e86), (0xfe56, 0x58, 0x1e8a), (0xfe56, 0x59, 0x1e8e), (0xfe56, 0x5a, 0x017b),
+    (0xfe56, 0x61, 0x0227), (0xfe56, 0x62, 0x1e03), (0xfe56, 0x63, 0x010b), (0xfe56, 0x64, 0x1e0b), (0xfe56, 0x65, 0x0117), (0xfe56, 0x66, 0x1e1f),
+    (0xfe56, 0x67, 0x0121), (0xfe56, 0x68, 0x1e23), (0xfe56, 0x6d, 0x1e41), (0xfe56, 0x6e, 0x1e45), (0xfe56, 0x6f, 0x022f), (0xfe56, 0x70, 0x1e57),
+    (0xfe56, 0x72, 0x1e59), (0xfe56, 0x73, 0x1e61), (0xfe56, 0x74, 0x1e6b), (0xfe56, 0x77, 0x1e87), (0xfe56, 0x78, 0x1e8b), (0xfe56, 0x79, 0x1e8f),
+    (0xfe56, 0x7a, 0x017c), (0xfe57, 0x41, 0x00c4), (0xfe57, 0x45, 0x00cb), (0xfe57, 0x48, 0x1e26), (0xfe57, 0x49, 0x00cf), (0xfe57, 0x4f, 0x00d6),
+    (0xfe57, 0x55, 0x00dc), (0xfe57, 0x57, 0x1e84), (0xfe57, 0x58, 0x1e8c), (0xfe57, 0x59, 0x0178), (0xfe57, 0x61, 0x00e4), (0xfe57, 0x65, 0x00eb),
+    (0xfe57, 0x68, 0x1e27), (0xfe57, 0x69, 0x00ef), (0xfe57, 0x6f, 0x00f6), (0xfe57, 0x74, 0x1e97), (0xfe57, 0x75, 0x00fc), (0xfe57, 0x77, 0x1e85),
+    (0xfe57, 0x78, 0x1e8d), (0xfe57, 0x79, 0x00ff), (0xfe58, 0x41, 0x00c5), (0xfe58, 0x55, 0x016e), (0xfe58, 0x61, 0x00e5), (0xfe58, 0x75, 0x016f),
+    (0xfe58, 0x77, 0x1e98), (0xfe58, 0x79, 0x1e99), (0xfe59, 0x4f, 0x0150), (0xfe59, 0x55, 0x0170), (0xfe59, 0x6f, 0x0151), (0xfe59, 0x75, 0x0171),
+    (0xfe5a, 0x41, 0x01cd), (0xfe5a, 0x43, 0x010c), (0xfe5a, 0x44, 0x010e), (0xfe5a, 0x45, 0x011a), (0xfe5a, 0x47, 0x01e6), (0xfe5a, 0x48, 0x021e),
+    (0xfe5a, 0x49, 0x01cf), (0xfe5a, 0x4b, 0x01e8), (0xfe5a, 0x4c, 0x013d), (0xfe5a, 0x4e, 0x0147), (0xfe5a, 0x4f, 0x01d1), (0xfe5a, 0x52, 0x0158),
+    (0xfe5a, 0x53, 0x0160), (0xfe5a, 0x54, 0x0164), (0xfe5a, 0x55, 0x01d3), (0xfe5a, 0x5a, 0x017d), (0xfe5a, 0x61, 0x01ce), (0xfe5a, 0x63, 0x010d),
+    (0xfe5a, 0x64, 0x010f), (0xfe5a, 0x65, 0x011b), (0xfe5a, 0x67, 0x01e7), (0xfe5a, 0x68, 0x021f), (0xfe5a, 0x69, 0x01d0), (0xfe5a, 0x6a, 0x01f0),
+    (0xfe5a, 0x6b, 0x01e9), (0xfe5a, 0x6c, 0x013e), (0xfe5a, 0x6e, 0x0148), (0xfe5a, 0x6f, 0x01d2), (0xfe5a, 0x72, 0x0159), (0xfe5a, 0x73, 0x0161),
+    (0xfe5a, 0x74, 0x0165), (0xfe5a, 0x75, 0x01d4), (0xfe5a, 0x7a, 0x017e), (0xfe5b, 0x43, 0x00c7), (0xfe5b, 0x44, 0x1e10), (0xfe5b, 0x45, 0x0228),
+    (0xfe5b, 0x47, 0x0122), (0xfe5b, 0x48, 0x1e28), (0xfe5b, 0x4b, 0x0136), (0xfe5b, 0x4c, 0x013b), (0xfe5b, 0x4e, 0x0145), (0xfe5b, 0x52, 0x0156),
+    (0xfe5b, 0x53, 0x015e), (0xfe5b, 0x54, 0x0162), (0xfe5b, 0x63, 0x00e7), (0xfe5b, 0x64, 0x1e11), (0xfe5b, 0x65, 0x0229), (0xfe5b, 0x67, 0x0123),
+    (0xfe5b, 0x68, 0x1e29), (0xfe5b, 0x6b, 0x0137), (0xfe5b, 0x6c, 0x013c), (0xfe5b, 0x6e, 0x0146), (0xfe5b, 0x72, 0x0157), (0xfe5b, 0x73, 0x015f),
+    (0xfe5b, 0x74, 0x0163), (0xfe5c, 0x41, 0x0104), (0xfe5c, 0x45, 0x0118), (0xfe5c, 0x49, 0x012e), (0xfe5c, 0x4f, 0x01ea), (0xfe5c, 0x55, 0x0172),
+    (0xfe5c, 0x61, 0x0105), (0xfe5c, 0x65, 0x0119), (0xfe5c, 0x69, 0x012f), (0xfe5c, 0x6f, 0x01eb), (0xfe5c, 0x75, 0x0173),
+];
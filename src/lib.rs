@@ -23,8 +23,8 @@
 //!
 //! ## Features
 //!
-//! - **11 encoding types**: Raw, `CopyRect`, RRE, `CoRRE`, Hextile, Zlib, `ZlibHex`,
-//!   Tight, `TightPng`, ZRLE, ZYWRLE
+//! - **12 encoding types**: Raw, `CopyRect`, RRE, `CoRRE`, Hextile, Zlib, `ZlibHex`,
+//!   Tight, `TightPng`, TRLE, ZRLE, ZYWRLE
 //! - **All pixel formats**: 8/16/24/32-bit color depths
 //! - **Tight encoding**: All 5 production modes (solid fill, mono rect, indexed
 //!   palette, full-color zlib, JPEG)
@@ -52,7 +52,7 @@
 //!     tokio::spawn(async move {
 //!         while let Some(event) = event_rx.recv().await {
 //!             match event {
-//!                 ServerEvent::ClientConnected { client_id } => {
+//!                 ServerEvent::ClientConnected { client_id, .. } => {
 //!                     println!("Client {} connected", client_id);
 //!                 }
 //!                 ServerEvent::ClientDisconnected { client_id } => {
@@ -111,24 +111,75 @@
 #![warn(clippy::all)]
 #![warn(clippy::pedantic)]
 
+#[cfg(feature = "android")]
+pub mod android;
+pub mod audit;
+pub mod authenticator;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod config;
+pub mod desktop_size;
 pub mod error;
 pub mod events;
+pub mod frame_source;
 pub mod framebuffer;
+pub mod gii;
+pub mod keysym;
+#[cfg(feature = "mjpeg")]
+pub mod mjpeg;
+pub mod network;
 pub mod protocol;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod region;
 pub mod server;
+pub mod stats;
+pub mod transport;
 
 // Internal modules
+mod analysis;
 mod auth;
 mod client;
+mod encode_cache;
+mod encode_pool;
+#[cfg(feature = "file-transfer")]
+mod file_transfer;
+mod hextile;
+#[cfg(feature = "turbojpeg")]
+mod jpeg;
+mod ms_logon;
+mod rect_encoder;
 mod repeater;
+mod resume;
+mod rle_tile;
+mod rre;
+#[cfg(feature = "rsa-aes")]
+mod rsa_aes;
+#[cfg(feature = "sasl")]
+mod sasl;
+mod scroll;
+mod security;
+mod tight;
+mod tightpng;
+#[cfg(feature = "tls")]
+mod tls;
+mod trle;
+mod zrle;
+#[cfg(feature = "zstd")]
+mod zstd_rect;
+mod zywrle;
 
 // Re-export encodings from rfb-encodings crate
 pub use rfb_encodings as encoding;
 
 // Re-exports
+pub use config::ServerConfig;
 pub use encoding::Encoding;
 pub use error::{Result, VncError};
 pub use events::ServerEvent;
+pub use frame_source::{DamageSink, FrameSource};
 pub use framebuffer::Framebuffer;
 pub use protocol::PixelFormat;
 pub use server::VncServer;
@@ -0,0 +1,244 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read-only MJPEG preview over HTTP.
+//!
+//! Dashboards and browsers that can't run an RFB client can still show the screen as a
+//! `multipart/x-mixed-replace` stream of JPEG frames, which every browser renders in a
+//! plain `<img>` tag. Each viewer gets its own damage receiver, so a new frame is only
+//! encoded when the screen changed, and never more often than
+//! [`MjpegConfig::min_frame_interval`].
+//!
+//! The preview carries no input and performs no authentication, so bind it to a trusted
+//! network only, e.g. a loopback or internal address. The server's access list applies
+//! to preview viewers too. The preview is never encrypted: under
+//! `ConnectionConfig::require_encryption` it only serves peers on the
+//! `encryption_exempt` networks, and with `SecurityConfig::require_client_certificate`
+//! it serves no one. Requires the `mjpeg` feature (which enables `turbojpeg`).
+//!
+//! ```no_run
+//! use rustvncserver::mjpeg::MjpegConfig;
+//! use rustvncserver::VncServer;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let (server, _events) = VncServer::new(1280, 720, "Desktop".to_string(), None);
+//! // Open http://127.0.0.1:8080/ in a browser on this host
+//! server
+//!     .serve_mjpeg("127.0.0.1:8080".parse().unwrap(), MjpegConfig::default())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+
+use crate::config::ServerConfig;
+use crate::encoding::jpeg::TurboJpegEncoder;
+use crate::framebuffer::{DirtyRegion, DirtyRegionReceiver, Framebuffer};
+use crate::network::AccessList;
+use crate::region::DamageQueue;
+
+/// Multipart boundary separating the frames of the stream.
+const BOUNDARY: &str = "rustvncserver-frame";
+
+/// Maximum size of an HTTP request head accepted from a preview viewer.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// Time a preview viewer has to send its HTTP request head.
+const REQUEST_HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Settings for the MJPEG preview stream.
+#[derive(Debug, Clone)]
+pub struct MjpegConfig {
+    /// JPEG quality (1-100). Default: 70.
+    pub quality: u8,
+    /// Minimum time between two frames sent to a viewer. Default: 100 ms (10 fps).
+    pub min_frame_interval: Duration,
+    /// Maximum time writing a frame may take before the viewer is dropped.
+    /// Default: 10 seconds.
+    pub write_timeout: Duration,
+}
+
+impl Default for MjpegConfig {
+    fn default() -> Self {
+        Self {
+            quality: 70,
+            min_frame_interval: Duration::from_millis(100),
+            write_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Serves `framebuffer` as an MJPEG stream on `addr`.
+///
+/// # Arguments
+///
+/// * `framebuffer` - The screen to show.
+/// * `addr` - The address to listen on.
+/// * `config` - Frame rate, quality and timeout settings.
+/// * `access_list` - Hosts allowed or denied to connect.
+/// * `server_config` - The server's settings, for its encryption requirements.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if the address cannot be bound.
+pub(crate) async fn serve(
+    framebuffer: Framebuffer,
+    addr: SocketAddr,
+    config: MjpegConfig,
+    access_list: Arc<RwLock<AccessList>>,
+    server_config: Arc<ServerConfig>,
+) -> Result<(), std::io::Error> {
+    let listener = TcpListener::bind(addr).await?;
+    log::info!("MJPEG preview listening on {}", listener.local_addr()?);
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::error!("Error accepting MJPEG preview connection: {e}");
+                continue;
+            }
+        };
+        if !access_list.read().await.permits(addr.ip()) {
+            log::warn!(
+                "Host {} is not permitted, closing MJPEG preview connection",
+                addr.ip()
+            );
+            continue;
+        }
+        // The preview is plain HTTP, refused wherever plain RFB sessions are
+        if server_config.security.require_client_certificate
+            || !server_config.connection.allows_plaintext(Some(addr.ip()))
+        {
+            log::warn!("Encryption is required, closing MJPEG preview connection from {addr}");
+            continue;
+        }
+
+        let framebuffer = framebuffer.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_viewer(stream, framebuffer, config).await {
+                #[cfg(feature = "debug-logging")]
+                log::info!("MJPEG viewer {addr} disconnected: {e}");
+                #[cfg(not(feature = "debug-logging"))]
+                let _ = (addr, e);
+            }
+        });
+    }
+}
+
+/// Answers one HTTP request and streams frames until the viewer goes away.
+async fn serve_viewer(
+    mut stream: TcpStream,
+    framebuffer: Framebuffer,
+    config: MjpegConfig,
+) -> Result<(), std::io::Error> {
+    let request_line = tokio::time::timeout(REQUEST_HEAD_TIMEOUT, read_request_head(&mut stream))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "request timed out"))??;
+    if !request_line.starts_with("GET ") {
+        stream
+            .write_all(b"HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(());
+    }
+
+    let header = format!(
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: multipart/x-mixed-replace; boundary={BOUNDARY}\r\n\
+         Cache-Control: no-cache, no-store\r\n\
+         Pragma: no-cache\r\n\
+         Connection: close\r\n\r\n"
+    );
+    stream.write_all(header.as_bytes()).await?;
+
+    // Start with the whole screen dirty so the first frame goes out immediately
+    let full = DirtyRegion::new(0, 0, framebuffer.width(), framebuffer.height());
+    let damage = Arc::new(RwLock::new(DamageQueue::from(full)));
+    framebuffer
+        .register_receiver(DirtyRegionReceiver::new(Arc::downgrade(&damage)))
+        .await;
+
+    let mut encoder = TurboJpegEncoder::new().map_err(std::io::Error::other)?;
+    let mut interval =
+        tokio::time::interval(config.min_frame_interval.max(Duration::from_millis(1)));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        framebuffer.poll_source().await;
+        if std::mem::take(&mut *damage.write().await).is_empty() {
+            continue;
+        }
+
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+        let rgba = framebuffer
+            .get_rect(0, 0, width, height)
+            .await
+            .map_err(std::io::Error::other)?;
+        // Compressing a whole frame takes long enough to stall other tasks on this worker
+        let quality = config.quality.clamp(1, 100);
+        let (returned, jpeg) = tokio::task::spawn_blocking(move || {
+            let rgb: Vec<u8> = rgba
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect();
+            let jpeg = encoder.compress_rgb(&rgb, width, height, quality);
+            (encoder, jpeg)
+        })
+        .await
+        .map_err(std::io::Error::other)?;
+        encoder = returned;
+        let jpeg = jpeg.map_err(std::io::Error::other)?;
+
+        let mut part = format!(
+            "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            jpeg.len()
+        )
+        .into_bytes();
+        part.extend_from_slice(&jpeg);
+        part.extend_from_slice(b"\r\n");
+
+        tokio::time::timeout(config.write_timeout, stream.write_all(&part))
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "write stalled"))??;
+    }
+}
+
+/// Reads the HTTP request head and returns its request line.
+async fn read_request_head(stream: &mut TcpStream) -> Result<String, std::io::Error> {
+    let mut head = Vec::with_capacity(512);
+    let mut buf = [0u8; 512];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "HTTP request head too large",
+            ));
+        }
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let line_end = head.iter().position(|&b| b == b'\r').unwrap_or(head.len());
+    Ok(String::from_utf8_lossy(&head[..line_end]).into_owned())
+}
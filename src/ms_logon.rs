@@ -0,0 +1,214 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `UltraVNC` MS-Logon II authentication (security type 113).
+//!
+//! # Protocol
+//!
+//! 1. Server sends a Diffie-Hellman generator, modulus and its public key (8 bytes each)
+//! 2. Client sends its public key (8 bytes)
+//! 3. Client sends a 256-byte username and a 64-byte password field, NUL-terminated and
+//!    encrypted separately with DES-CBC, using the shared key as both the key (with the
+//!    VNC bit reversal) and the IV
+//!
+//! # Security Note
+//!
+//! The key exchange uses 31-bit numbers as `UltraVNC` does, so it only hides the credentials
+//! from casual observers. Like VNC Authentication, MS-Logon II should only be used on
+//! trusted networks or inside an encrypted transport.
+
+use des::cipher::{BlockDecrypt, KeyInit};
+use des::Des;
+use rand::Rng;
+use zeroize::Zeroizing;
+
+use crate::auth::reverse_bits;
+
+/// Length of the encrypted username field.
+pub(crate) const USERNAME_LEN: usize = 256;
+
+/// Length of the encrypted password field.
+pub(crate) const PASSWORD_LEN: usize = 64;
+
+/// Upper bound (exclusive) of the key exchange numbers, as used by `UltraVNC`.
+const MAX_DH: u64 = 1 << 31;
+
+/// The server side of the MS-Logon II Diffie-Hellman exchange.
+pub(crate) struct KeyExchange {
+    /// The generator sent to the client.
+    pub(crate) generator: u64,
+    /// The prime modulus sent to the client.
+    pub(crate) modulus: u64,
+    /// The server's private exponent.
+    private: u64,
+}
+
+impl KeyExchange {
+    /// Generates fresh parameters and a private key.
+    pub(crate) fn new() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut generator = random_prime(&mut rng);
+        let mut modulus = random_prime(&mut rng);
+        while modulus == generator {
+            modulus = random_prime(&mut rng);
+        }
+        if generator > modulus {
+            std::mem::swap(&mut generator, &mut modulus);
+        }
+        Self {
+            generator,
+            modulus,
+            private: rng.gen_range(1..MAX_DH),
+        }
+    }
+
+    /// Returns the server's public key.
+    pub(crate) fn public_key(&self) -> u64 {
+        pow_mod(self.generator, self.private, self.modulus)
+    }
+
+    /// Derives the shared DES key from the client's public key.
+    pub(crate) fn shared_key(&self, client_public: u64) -> [u8; 8] {
+        pow_mod(client_public % self.modulus, self.private, self.modulus).to_be_bytes()
+    }
+}
+
+/// Decrypts a username or password field and returns the text before its NUL terminator.
+/// The key schedule and the decrypted bytes are wiped from memory afterwards.
+///
+/// # Arguments
+///
+/// * `field` - The encrypted field; a multiple of 8 bytes long.
+/// * `key` - The shared key from [`KeyExchange::shared_key`].
+pub(crate) fn decrypt_field(field: &[u8], key: [u8; 8]) -> String {
+    let des_key = Zeroizing::new(key.map(reverse_bits));
+    let cipher = Des::new_from_slice(des_key.as_slice()).expect("8-byte key");
+
+    let mut plain = Zeroizing::new(Vec::with_capacity(field.len()));
+    let mut previous = Zeroizing::new(key);
+    for block in field.chunks_exact(8) {
+        let mut decrypted = <[u8; 8]>::try_from(block).expect("8-byte block").into();
+        cipher.decrypt_block(&mut decrypted);
+        plain.extend(decrypted.iter().zip(*previous).map(|(byte, iv)| byte ^ iv));
+        previous.copy_from_slice(block);
+    }
+
+    let end = plain
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(plain.len());
+    String::from_utf8_lossy(&plain[..end]).into_owned()
+}
+
+/// Computes `base^exponent mod modulus`.
+fn pow_mod(mut base: u64, mut exponent: u64, modulus: u64) -> u64 {
+    let mut result = 1u64;
+    base %= modulus;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, modulus);
+        }
+        base = mul_mod(base, base, modulus);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Computes `a * b mod modulus` without overflowing.
+#[allow(clippy::cast_possible_truncation)] // The result is below the 64-bit modulus
+fn mul_mod(a: u64, b: u64, modulus: u64) -> u64 {
+    (u128::from(a) * u128::from(b) % u128::from(modulus)) as u64
+}
+
+/// Picks a random prime in the upper half of the key exchange range.
+fn random_prime(rng: &mut impl Rng) -> u64 {
+    loop {
+        let candidate = rng.gen_range(MAX_DH / 2..MAX_DH) | 1;
+        if is_prime(candidate) {
+            return candidate;
+        }
+    }
+}
+
+/// Trial division, fast enough for 31-bit numbers.
+fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    let mut divisor = 2;
+    while divisor * divisor <= n {
+        if n.is_multiple_of(divisor) {
+            return false;
+        }
+        divisor += 1;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Parameters of the known-answer test, with the client's private exponent 987654321.
+    fn key_exchange() -> KeyExchange {
+        KeyExchange {
+            generator: 1_000_000_007,
+            modulus: 2_147_483_647,
+            private: 123_456_789,
+        }
+    }
+
+    #[test]
+    fn key_exchange_matches_known_answer() {
+        let exchange = key_exchange();
+        assert_eq!(exchange.public_key(), 1_158_864_931);
+        assert_eq!(
+            exchange.shared_key(2_039_193_239),
+            [0, 0, 0, 0, 0x62, 0xc8, 0x89, 0xab]
+        );
+    }
+
+    #[test]
+    fn decrypt_field_matches_known_answer() {
+        let key = key_exchange().shared_key(2_039_193_239);
+        let password = unhex(concat!(
+            "ad3503bc976a80eecccd822646a9cd9fc0e176e3e08ce5af35e5f7e7c56af983",
+            "47d173c3d08d22af9919dad756397fdd455dc9b5e283bc981bfe010c2d30e018",
+        ));
+        assert_eq!(password.len(), PASSWORD_LEN);
+        assert_eq!(decrypt_field(&password, key), "s3cret");
+    }
+
+    #[test]
+    fn decrypt_field_handles_missing_terminator_and_invalid_utf8() {
+        let key = key_exchange().shared_key(2_039_193_239);
+        // No NUL: the whole field is the text
+        assert_eq!(
+            decrypt_field(&unhex("f86074ef138607fff3ce61d8b8e7fd55"), key),
+            "abcdefghijklmnop"
+        );
+        // Latin-1 "café", as older clients send it
+        assert_eq!(
+            decrypt_field(&unhex("c6f2119db48d3843"), key),
+            "caf\u{fffd}"
+        );
+    }
+}
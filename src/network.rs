@@ -0,0 +1,294 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! IP networks for address-based connection policies.
+//!
+//! An [`IpNetwork`] is written in CIDR notation (`192.168.0.0/16`, `fd00::/8`); a bare
+//! address stands for that single host:
+//!
+//! ```
+//! use rustvncserver::network::IpNetwork;
+//!
+//! let lan: IpNetwork = "192.168.0.0/16".parse().unwrap();
+//! assert!(lan.contains("192.168.1.20".parse().unwrap()));
+//! assert!(!lan.contains("10.0.0.1".parse().unwrap()));
+//! ```
+//!
+//! IPv4 networks also match IPv4-mapped IPv6 addresses (`::ffff:192.168.1.20`), as seen
+//! on dual-stack listeners.
+//!
+//! An [`AccessList`] combines allowed and denied networks into the host-based access
+//! control applied to incoming connections (see `VncServer::allow_network`).
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Error returned when parsing an [`IpNetwork`] fails.
+#[derive(Debug, Error)]
+#[error("Invalid IP network: {0}")]
+pub struct InvalidNetwork(String);
+
+/// A block of IP addresses: an address and the number of leading bits that must match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    /// Creates a network from an address and prefix length. Host bits in `addr` are
+    /// ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(InvalidNetwork)` if `prefix_len` is longer than the address (32 bits
+    /// for IPv4, 128 for IPv6).
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, InvalidNetwork> {
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_len {
+            return Err(InvalidNetwork(format!("{addr}/{prefix_len}")));
+        }
+        Ok(Self { addr, prefix_len })
+    }
+
+    /// The IPv4 and IPv6 loopback networks (`127.0.0.0/8` and `::1/128`).
+    #[must_use]
+    pub fn loopback() -> Vec<Self> {
+        vec![
+            Self {
+                addr: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)),
+                prefix_len: 8,
+            },
+            Self {
+                addr: IpAddr::V6(Ipv6Addr::LOCALHOST),
+                prefix_len: 128,
+            },
+        ]
+    }
+
+    /// Returns whether `addr` belongs to this network.
+    #[must_use]
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+            IpAddr::V4(_) => addr,
+        };
+        match (self.addr, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => prefix_matches(
+                u128::from(u32::from(network)),
+                u128::from(u32::from(addr)),
+                32,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                prefix_matches(u128::from(network), u128::from(addr), 128, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Allowed and denied networks deciding which hosts may connect.
+///
+/// A host is refused if it belongs to a denied network, or if any networks are allowed
+/// and it belongs to none of them. An empty list admits every host.
+///
+/// ```
+/// use rustvncserver::network::AccessList;
+///
+/// let mut access = AccessList::default();
+/// access.allow("192.168.0.0/16".parse().unwrap());
+/// access.deny("192.168.66.0/24".parse().unwrap());
+/// assert!(access.permits("192.168.1.20".parse().unwrap()));
+/// assert!(!access.permits("192.168.66.6".parse().unwrap()));
+/// assert!(!access.permits("10.0.0.1".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessList {
+    allowed: Vec<IpNetwork>,
+    denied: Vec<IpNetwork>,
+}
+
+impl AccessList {
+    /// Returns whether a host at `addr` may connect.
+    #[must_use]
+    pub fn permits(&self, addr: IpAddr) -> bool {
+        !self.denied.iter().any(|network| network.contains(addr))
+            && (self.allowed.is_empty()
+                || self.allowed.iter().any(|network| network.contains(addr)))
+    }
+
+    /// Adds an allowed network. Once any network is allowed, hosts outside the allowed
+    /// networks are refused.
+    pub fn allow(&mut self, network: IpNetwork) {
+        if !self.allowed.contains(&network) {
+            self.allowed.push(network);
+        }
+    }
+
+    /// Adds a denied network.
+    pub fn deny(&mut self, network: IpNetwork) {
+        if !self.denied.contains(&network) {
+            self.denied.push(network);
+        }
+    }
+
+    /// Removes an allowed network.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the network was allowed.
+    pub fn remove_allowed(&mut self, network: IpNetwork) -> bool {
+        let len = self.allowed.len();
+        self.allowed.retain(|allowed| *allowed != network);
+        self.allowed.len() != len
+    }
+
+    /// Removes a denied network.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the network was denied.
+    pub fn remove_denied(&mut self, network: IpNetwork) -> bool {
+        let len = self.denied.len();
+        self.denied.retain(|denied| *denied != network);
+        self.denied.len() != len
+    }
+
+    /// Returns the allowed networks.
+    #[must_use]
+    pub fn allowed(&self) -> &[IpNetwork] {
+        &self.allowed
+    }
+
+    /// Returns the denied networks.
+    #[must_use]
+    pub fn denied(&self) -> &[IpNetwork] {
+        &self.denied
+    }
+}
+
+/// Compares the leading `prefix_len` bits of two `bits`-wide addresses.
+fn prefix_matches(network: u128, addr: u128, bits: u8, prefix_len: u8) -> bool {
+    if prefix_len == 0 {
+        return true;
+    }
+    let shift = u32::from(bits - prefix_len);
+    (network >> shift) == (addr >> shift)
+}
+
+impl FromStr for IpNetwork {
+    type Err = InvalidNetwork;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidNetwork(s.to_string());
+        if let Some((addr, prefix_len)) = s.split_once('/') {
+            Self::new(
+                addr.trim().parse().map_err(|_| invalid())?,
+                prefix_len.trim().parse().map_err(|_| invalid())?,
+            )
+        } else {
+            let addr: IpAddr = s.trim().parse().map_err(|_| invalid())?;
+            Self::new(addr, if addr.is_ipv4() { 32 } else { 128 })
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Extracts the IP address from a peer description such as `192.0.2.7:5900`.
+///
+/// Returns `None` for descriptions that are not socket or IP addresses (custom
+/// transports).
+pub(crate) fn peer_ip(peer: &str) -> Option<IpAddr> {
+    peer.parse::<std::net::SocketAddr>()
+        .map(|addr| addr.ip())
+        .or_else(|_| peer.parse::<IpAddr>())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net(s: &str) -> IpNetwork {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn zero_prefix_matches_whole_family() {
+        assert!(net("0.0.0.0/0").contains(ip("255.255.255.255")));
+        assert!(net("10.0.0.0/0").contains(ip("192.0.2.7")));
+        assert!(net("0.0.0.0/0").contains(ip("::ffff:192.0.2.7")));
+        assert!(!net("0.0.0.0/0").contains(ip("2001:db8::1")));
+        assert!(net("::/0").contains(ip("2001:db8::1")));
+        assert!(!net("::/0").contains(ip("192.0.2.7")));
+    }
+
+    #[test]
+    fn full_prefix_matches_single_host() {
+        assert!(net("192.0.2.7/32").contains(ip("192.0.2.7")));
+        assert!(!net("192.0.2.7/32").contains(ip("192.0.2.6")));
+        assert!(net("2001:db8::1/128").contains(ip("2001:db8::1")));
+        assert!(!net("2001:db8::1/128").contains(ip("2001:db8::2")));
+        assert_eq!(net("192.0.2.7"), net("192.0.2.7/32"));
+        assert_eq!(net("2001:db8::1"), net("2001:db8::1/128"));
+    }
+
+    #[test]
+    fn ipv4_networks_match_mapped_peers() {
+        let lan = net("192.168.0.0/16");
+        assert!(lan.contains(ip("::ffff:192.168.1.20")));
+        assert!(!lan.contains(ip("::ffff:10.0.0.1")));
+        assert_eq!(
+            peer_ip("[::ffff:192.168.1.20]:5900"),
+            Some(ip("::ffff:192.168.1.20"))
+        );
+
+        let mut access = AccessList::default();
+        access.allow(lan);
+        assert!(access.permits(ip("::ffff:192.168.1.20")));
+        assert!(!access.permits(ip("::ffff:10.0.0.1")));
+    }
+
+    #[test]
+    fn rejects_prefix_longer_than_address() {
+        assert!("192.0.2.0/33".parse::<IpNetwork>().is_err());
+        assert!("2001:db8::/129".parse::<IpNetwork>().is_err());
+        assert!(IpNetwork::new(ip("192.0.2.0"), 33).is_err());
+        assert!(IpNetwork::new(ip("::"), 129).is_err());
+        assert!(IpNetwork::new(ip("::"), 128).is_ok());
+    }
+
+    #[test]
+    fn deny_overrides_allow() {
+        let mut access = AccessList::default();
+        access.allow(net("192.0.2.7/32"));
+        access.deny(net("0.0.0.0/0"));
+        assert!(!access.permits(ip("192.0.2.7")));
+        assert!(access.remove_denied(net("0.0.0.0/0")));
+        assert!(access.permits(ip("192.0.2.7")));
+        assert!(!access.permits(ip("192.0.2.8")));
+    }
+}
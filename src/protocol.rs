@@ -105,6 +105,35 @@ pub const CLIENT_MSG_POINTER_EVENT: u8 = 5;
 /// Allows the client to transfer clipboard contents to the server.
 pub const CLIENT_MSG_CLIENT_CUT_TEXT: u8 = 6;
 
+/// Message type: Client requests a change of the framebuffer size and screen layout.
+///
+/// Part of the `ExtendedDesktopSize` extension.
+pub const CLIENT_MSG_SET_DESKTOP_SIZE: u8 = 251;
+
+/// Message type: `UltraVNC` file transfer, in both directions.
+///
+/// Carries directory listings, file data and file commands (see the `file_transfer`
+/// configuration).
+pub const MSG_FILE_TRANSFER: u8 = 7;
+
+/// Message type: Client enables or disables continuous updates for an area.
+///
+/// Part of the `ContinuousUpdates` extension.
+pub const CLIENT_MSG_ENABLE_CONTINUOUS_UPDATES: u8 = 150;
+
+/// Message type: Client asks to resume a suspended session (experimental, private).
+///
+/// Carries the session token the server sent with
+/// [`ENCODING_SESSION_RESUME`]. Not a registered RFB message type; see the `resume`
+/// module docs.
+pub const CLIENT_MSG_RESUME_SESSION: u8 = 200;
+
+/// Message type: gii (General Input Interface) extension message, in both directions.
+///
+/// Clients register input devices and inject tablet, multitouch and valuator events; the
+/// server announces the extension version and answers device registrations.
+pub const MSG_GII: u8 = 253;
+
 // Server-to-Client Message Types
 
 /// Message type: Server sends a framebuffer update.
@@ -131,6 +160,11 @@ pub const SERVER_MSG_BELL: u8 = 2;
 /// Allows the server to transfer clipboard contents to the client.
 pub const SERVER_MSG_SERVER_CUT_TEXT: u8 = 3;
 
+/// Message type: Server confirms that continuous updates have stopped.
+///
+/// Part of the `ContinuousUpdates` extension; also sent once to announce support for it.
+pub const SERVER_MSG_END_OF_CONTINUOUS_UPDATES: u8 = 150;
+
 // Encoding Types
 //
 // Note: Most encoding type constants are re-exported from rfb-encodings at the top of this file.
@@ -145,9 +179,7 @@ pub const ENCODING_COPYRECT: i32 = 1;
 
 /// Encoding type: Tile Run-Length Encoding.
 ///
-/// An efficient encoding for palettized and run-length compressed data.
-/// Note: Not currently implemented in rfb-encodings.
-#[allow(dead_code)]
+/// ZRLE's tile sub-encodings on 16x16 tiles, without zlib compression.
 pub const ENCODING_TRLE: i32 = 15;
 
 /// Encoding type: H.264 video encoding.
@@ -159,6 +191,20 @@ pub const ENCODING_TRLE: i32 = 15;
 #[allow(dead_code)]
 pub const ENCODING_H264: i32 = 0x4832_3634;
 
+/// Encoding type: Zstandard (experimental, private).
+///
+/// Raw pixels in the client's pixel format compressed with a persistent zstd stream,
+/// framed like Zlib encoding. Not a registered RFB encoding; only offered to clients
+/// that list it, and only with the `zstd` feature (see the `zstd_rect` module docs).
+pub const ENCODING_ZSTD: i32 = 0x5a53_5444; // "ZSTD"
+
+/// Pseudo-encoding: Session resume (experimental, private).
+///
+/// Clients list it to receive a session token they can later present with
+/// [`CLIENT_MSG_RESUME_SESSION`] to resume the session after a reconnect. Not a
+/// registered RFB encoding; see the `resume` module docs.
+pub const ENCODING_SESSION_RESUME: i32 = 0x5253_4d45; // "RSME"
+
 /// Pseudo-encoding: Rich Cursor.
 ///
 /// Allows the server to send cursor shape and hotspot information.
@@ -171,6 +217,45 @@ pub const ENCODING_CURSOR: i32 = -239;
 #[allow(dead_code)]
 pub const ENCODING_DESKTOP_SIZE: i32 = -223;
 
+/// Pseudo-encoding: Extended Desktop Size.
+///
+/// Notifies the client of framebuffer size and screen layout changes, and answers its
+/// `SetDesktopSize` requests. A client advertising it may send `SetDesktopSize`.
+pub const ENCODING_EXTENDED_DESKTOP_SIZE: i32 = -308;
+
+/// Pseudo-encoding: Continuous Updates.
+///
+/// Clients list it to push-enable framebuffer updates with
+/// [`CLIENT_MSG_ENABLE_CONTINUOUS_UPDATES`] instead of requesting each one.
+pub const ENCODING_CONTINUOUS_UPDATES: i32 = -313;
+
+/// Pseudo-encoding: QEMU LED State.
+///
+/// Tells the client the state of the lock key LEDs in one byte.
+pub const ENCODING_QEMU_LED_STATE: i32 = -261;
+
+/// Pseudo-encoding: `VMware` LED State.
+///
+/// Tells the client the state of the lock key LEDs in a 32-bit value, with the same bits
+/// as [`ENCODING_QEMU_LED_STATE`].
+pub const ENCODING_VMWARE_LED_STATE: i32 = 0x574d_5668;
+
+/// Pseudo-encoding: gii.
+///
+/// Tells the server the client can send [`MSG_GII`] messages.
+pub const ENCODING_GII: i32 = -305;
+
+/// Pseudo-encoding: Desktop Name.
+///
+/// Notifies the client that the desktop name changed.
+pub const ENCODING_DESKTOP_NAME: i32 = -307;
+
+/// `ExtendedDesktopSize` reason: the server changed the framebuffer size.
+pub const DESKTOP_SIZE_REASON_SERVER: u16 = 0;
+
+/// `ExtendedDesktopSize` reason: answer to this client's `SetDesktopSize` request.
+pub const DESKTOP_SIZE_REASON_CLIENT: u16 = 1;
+
 /// Pseudo-encoding: JPEG Quality Level 0 (lowest quality, highest compression).
 ///
 /// When included in the client's encoding list, this requests the server
@@ -198,6 +283,14 @@ pub const ENCODING_COMPRESS_LEVEL_9: i32 = -247;
 // Note: Hextile and Tight subencoding constants are re-exported from rfb-encodings
 // at the top of this file.
 
+/// `ZlibHex` tile subencoding flag: the tile's raw pixels follow, compressed with the
+/// raw zlib stream (a `u16` length and the zlib data). The other flags are ignored.
+pub const HEXTILE_ZLIB_RAW: u8 = 32;
+
+/// `ZlibHex` tile subencoding flag: the rest of the Hextile tile follows, compressed
+/// with the encoded zlib stream (a `u16` length and the zlib data).
+pub const HEXTILE_ZLIB: u8 = 64;
+
 // Security Types
 
 /// Security type: Invalid/Unknown.
@@ -219,6 +312,54 @@ pub const SECURITY_TYPE_NONE: u8 = 1;
 /// the password and returns.
 pub const SECURITY_TYPE_VNC_AUTH: u8 = 2;
 
+/// Security type: `RA2` (RSA-AES).
+///
+/// RSA key exchange followed by an AES-EAX encrypted session in which the client sends
+/// a username and password.
+pub const SECURITY_TYPE_RA2: u8 = 5;
+
+/// Security type: `RA2ne` (RSA-AES without session encryption).
+///
+/// Like `RA2`, but only the credentials are encrypted; the session continues in the clear.
+pub const SECURITY_TYPE_RA2NE: u8 = 6;
+
+/// Security type: `VeNCrypt`.
+///
+/// A wrapper that negotiates a subtype, typically a TLS handshake followed by an inner
+/// authentication running over the encrypted stream.
+pub const SECURITY_TYPE_VENCRYPT: u8 = 19;
+
+/// Security type: SASL.
+///
+/// Authentication through a SASL mechanism such as `GSSAPI` (Kerberos) or `PLAIN`, as
+/// implemented by QEMU and libvirt. The server sends its mechanism list and the client
+/// and server exchange length-prefixed messages until the mechanism completes.
+pub const SECURITY_TYPE_SASL: u8 = 20;
+
+/// `VeNCrypt` subtype: anonymous TLS without authentication.
+pub const VENCRYPT_TLS_NONE: u32 = 257;
+
+/// `VeNCrypt` subtype: anonymous TLS followed by VNC Authentication.
+pub const VENCRYPT_TLS_VNC: u32 = 258;
+
+/// `VeNCrypt` subtype: certificate-authenticated TLS without further authentication.
+pub const VENCRYPT_X509_NONE: u32 = 260;
+
+/// `VeNCrypt` subtype: certificate-authenticated TLS followed by VNC Authentication.
+pub const VENCRYPT_X509_VNC: u32 = 261;
+
+/// `VeNCrypt` subtype: certificate-authenticated TLS followed by SASL authentication.
+pub const VENCRYPT_X509_SASL: u32 = 263;
+
+/// `VeNCrypt` subtype: anonymous TLS followed by SASL authentication.
+pub const VENCRYPT_TLS_SASL: u32 = 264;
+
+/// Security type: `UltraVNC` MS-Logon II.
+///
+/// A Diffie-Hellman key exchange followed by a DES-encrypted username and password, used
+/// by `UltraVNC` viewers to log in with an account instead of the shared VNC password.
+pub const SECURITY_TYPE_MS_LOGON_II: u8 = 113;
+
 // Security Results
 
 /// Security result: Authentication successful.
@@ -0,0 +1,446 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Python bindings.
+//!
+//! This module is enabled by the `python` feature and builds an extension module named
+//! `rustvncserver` (see `pyproject.toml`, build with `maturin develop --features python`).
+//!
+//! ```python
+//! import numpy as np
+//! import rustvncserver
+//!
+//! server = rustvncserver.VncServer(640, 480, "Test Desktop")
+//! server.listen(5900)
+//! server.update(np.zeros((480, 640, 3), dtype=np.uint8))
+//! for event in server:
+//!     print(event.kind, event.client_id)
+//! ```
+//!
+//! The server runs on its own Tokio runtime, and blocking calls release the GIL so other
+//! Python threads keep running.
+
+use std::sync::{Arc, Mutex};
+
+use numpy::{PyReadonlyArray3, PyUntypedArrayMethods};
+use pyo3::exceptions::{PyOSError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+use crate::server::{ReverseConnectionStatus, ServerEvent, VncServer};
+
+/// How often a blocking event wait wakes up to check for `KeyboardInterrupt`.
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Maximum time `shutdown()` waits for clients to disconnect.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// An event received from a VNC client.
+///
+/// `kind` is one of `"client_connected"`, `"client_disconnected"`, `"key_press"`,
+/// `"pointer_move"`, `"cut_text"`, `"auth_attempt"` or `"reverse_connection"`; fields
+/// that don't apply to the kind are `None`.
+#[pyclass(name = "Event", module = "rustvncserver", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct PyEvent {
+    kind: &'static str,
+    client_id: usize,
+    down: Option<bool>,
+    key: Option<u32>,
+    x: Option<u16>,
+    y: Option<u16>,
+    button_mask: Option<u8>,
+    text: Option<String>,
+    address: Option<String>,
+    method: Option<&'static str>,
+    success: Option<bool>,
+    status: Option<&'static str>,
+}
+
+#[pymethods]
+impl PyEvent {
+    fn __repr__(&self) -> String {
+        format!("{self:?}").replacen("PyEvent", "Event", 1)
+    }
+}
+
+impl PyEvent {
+    /// Converts a server event, returning `None` for events not exposed to Python.
+    ///
+    /// Resize requests are not exposed; dropping their reply handle rejects them.
+    fn from_server_event(event: ServerEvent) -> Option<Self> {
+        let mut result = Self {
+            kind: "",
+            client_id: 0,
+            down: None,
+            key: None,
+            x: None,
+            y: None,
+            button_mask: None,
+            text: None,
+            address: None,
+            method: None,
+            success: None,
+            status: None,
+        };
+        match event {
+            ServerEvent::ClientConnected {
+                client_id,
+                certificate_subject,
+            } => {
+                result.kind = "client_connected";
+                result.client_id = client_id;
+                result.text = certificate_subject;
+            }
+            ServerEvent::ClientDisconnected { client_id } => {
+                result.kind = "client_disconnected";
+                result.client_id = client_id;
+            }
+            ServerEvent::KeyPress {
+                client_id,
+                down,
+                key,
+            } => {
+                result.kind = "key_press";
+                result.client_id = client_id;
+                result.down = Some(down);
+                result.key = Some(key);
+            }
+            ServerEvent::PointerMove {
+                client_id,
+                x,
+                y,
+                button_mask,
+            } => {
+                result.kind = "pointer_move";
+                result.client_id = client_id;
+                result.x = Some(x);
+                result.y = Some(y);
+                result.button_mask = Some(button_mask);
+            }
+            ServerEvent::CutText { client_id, text } => {
+                result.kind = "cut_text";
+                result.client_id = client_id;
+                result.text = Some(text);
+            }
+            ServerEvent::AuthAttempt {
+                client_id,
+                address,
+                method,
+                success,
+            } => {
+                result.kind = "auth_attempt";
+                result.client_id = client_id;
+                result.address = Some(address);
+                result.method = Some(method.name());
+                result.success = Some(success);
+            }
+            ServerEvent::ReverseConnection { host, port, status } => {
+                result.kind = "reverse_connection";
+                result.address = Some(format!("{host}:{port}"));
+                result.status = Some(status.name());
+                match status {
+                    ReverseConnectionStatus::Connected { client_id }
+                    | ReverseConnectionStatus::Disconnected { client_id } => {
+                        result.client_id = client_id;
+                    }
+                    ReverseConnectionStatus::Failed { error, .. } => result.text = Some(error),
+                    _ => {}
+                }
+            }
+            ServerEvent::HandshakeTimeout { .. }
+            | ServerEvent::ResizeRequest { .. }
+            | ServerEvent::PseudoEncoding { .. }
+            | ServerEvent::UpdateTiming { .. }
+            | ServerEvent::Negotiated { .. }
+            | ServerEvent::ExtendedInput { .. }
+            | ServerEvent::Listening { .. }
+            | ServerEvent::DrainStarted { .. }
+            | ServerEvent::ClientClosing { .. }
+            | ServerEvent::DrainCompleted { .. } => return None,
+        }
+        Some(result)
+    }
+}
+
+/// Result of waiting for a server event.
+enum Received {
+    Event(ServerEvent),
+    Timeout,
+    Closed,
+}
+
+/// A VNC server usable from Python.
+///
+/// Iterating over the server yields events as they arrive, blocking between them.
+#[pyclass(name = "VncServer", module = "rustvncserver")]
+pub struct PyVncServer {
+    runtime: Runtime,
+    server: Arc<VncServer>,
+    events: Mutex<mpsc::UnboundedReceiver<ServerEvent>>,
+    listener: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl PyVncServer {
+    /// Waits up to `timeout` for the next event; a zero timeout polls without blocking.
+    fn recv(&self, timeout: Duration) -> PyResult<Received> {
+        let mut events = self
+            .events
+            .lock()
+            .map_err(|_| PyRuntimeError::new_err("Event queue lock poisoned"))?;
+
+        let received = if timeout.is_zero() {
+            match events.try_recv() {
+                Ok(event) => Received::Event(event),
+                Err(mpsc::error::TryRecvError::Empty) => Received::Timeout,
+                Err(mpsc::error::TryRecvError::Disconnected) => Received::Closed,
+            }
+        } else {
+            match self
+                .runtime
+                .block_on(async { tokio::time::timeout(timeout, events.recv()).await })
+            {
+                Ok(Some(event)) => Received::Event(event),
+                Ok(None) => Received::Closed,
+                Err(_) => Received::Timeout,
+            }
+        };
+        Ok(received)
+    }
+
+    /// Replaces the framebuffer with tightly packed RGBA32 bytes.
+    fn update_bytes_inner(&self, data: &[u8]) -> PyResult<()> {
+        self.runtime
+            .block_on(self.server.framebuffer().update_from_slice(data))
+            .map_err(PyValueError::new_err)
+    }
+}
+
+#[pymethods]
+impl PyVncServer {
+    /// Creates a server with a black RGBA32 framebuffer of the given size.
+    #[new]
+    #[pyo3(signature = (width, height, name = "rustvncserver".to_string(), password = None))]
+    fn new(width: u16, height: u16, name: String, password: Option<String>) -> PyResult<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("vnc-worker")
+            .build()
+            .map_err(|e| PyOSError::new_err(e.to_string()))?;
+        let (server, events) = VncServer::new(width, height, name, password);
+
+        Ok(Self {
+            runtime,
+            server: Arc::new(server),
+            events: Mutex::new(events),
+            listener: Mutex::new(None),
+        })
+    }
+
+    /// Framebuffer width in pixels.
+    #[getter]
+    fn width(&self) -> u16 {
+        self.server.framebuffer().width()
+    }
+
+    /// Framebuffer height in pixels.
+    #[getter]
+    fn height(&self) -> u16 {
+        self.server.framebuffer().height()
+    }
+
+    /// Starts listening for connections on `port` in the background.
+    fn listen(&self, port: u16) -> PyResult<()> {
+        let mut listener = self
+            .listener
+            .lock()
+            .map_err(|_| PyRuntimeError::new_err("Listener lock poisoned"))?;
+        if listener.as_ref().is_some_and(|l| !l.is_finished()) {
+            return Err(PyRuntimeError::new_err("Server is already listening"));
+        }
+
+        let server = self.server.clone();
+        *listener = Some(self.runtime.spawn(async move {
+            if let Err(e) = server.listen(port).await {
+                log::error!("VNC server listener on port {port} failed: {e}");
+            }
+        }));
+        Ok(())
+    }
+
+    /// Replaces the framebuffer with a `(height, width, 3|4)` `uint8` `NumPy` array.
+    ///
+    /// Three-channel arrays are treated as RGB and padded to RGBA.
+    #[allow(clippy::needless_pass_by_value)] // pyo3 extracts arguments by value
+    fn update(&self, py: Python<'_>, frame: PyReadonlyArray3<'_, u8>) -> PyResult<()> {
+        let [height, width, channels] = *frame.shape() else {
+            unreachable!("PyReadonlyArray3 always has three dimensions");
+        };
+        let framebuffer = self.server.framebuffer();
+        if width != usize::from(framebuffer.width()) || height != usize::from(framebuffer.height())
+        {
+            return Err(PyValueError::new_err(format!(
+                "Frame shape ({height}, {width}) does not match framebuffer ({}, {})",
+                framebuffer.height(),
+                framebuffer.width()
+            )));
+        }
+
+        let data: Vec<u8> = match (channels, frame.as_slice()) {
+            (4, Ok(slice)) => slice.to_vec(),
+            (4, Err(_)) => frame.as_array().iter().copied().collect(),
+            (3, _) => {
+                let array = frame.as_array();
+                let mut rgba = Vec::with_capacity(width * height * 4);
+                for pixel in array.rows() {
+                    rgba.extend([pixel[0], pixel[1], pixel[2], 255]);
+                }
+                rgba
+            }
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Expected 3 or 4 channels, got {channels}"
+                )))
+            }
+        };
+
+        py.detach(|| self.update_bytes_inner(&data))
+    }
+
+    /// Replaces the framebuffer with tightly packed RGBA32 bytes.
+    fn update_bytes(&self, py: Python<'_>, data: &[u8]) -> PyResult<()> {
+        py.detach(|| self.update_bytes_inner(data))
+    }
+
+    /// Resizes the framebuffer.
+    fn resize(&self, py: Python<'_>, width: u16, height: u16) -> PyResult<()> {
+        py.detach(|| {
+            self.runtime
+                .block_on(self.server.resize(width, height))
+                .map_err(PyValueError::new_err)
+        })
+    }
+
+    /// Sets or clears (`None`) the password required for new connections.
+    #[pyo3(signature = (password))]
+    fn set_password(&self, py: Python<'_>, password: Option<String>) {
+        py.detach(|| self.runtime.block_on(self.server.set_password(password)));
+    }
+
+    /// Renames the desktop, telling connected clients that support it.
+    fn set_desktop_name(&self, py: Python<'_>, name: String) {
+        py.detach(|| self.runtime.block_on(self.server.set_desktop_name(name)));
+    }
+
+    /// Sends clipboard text to all connected clients.
+    fn send_cut_text(&self, py: Python<'_>, text: String) -> PyResult<()> {
+        py.detach(|| {
+            self.runtime
+                .block_on(self.server.send_cut_text_to_all(text))
+                .map_err(|e| PyOSError::new_err(e.to_string()))
+        })
+    }
+
+    /// Returns the IDs of connected clients.
+    fn client_ids(&self) -> Vec<usize> {
+        self.server.get_client_ids().unwrap_or_default()
+    }
+
+    /// Returns the next event, or `None` if none arrives within `timeout` seconds.
+    ///
+    /// A `timeout` of `None` waits indefinitely; `0` polls without blocking.
+    #[pyo3(signature = (timeout = None))]
+    fn poll_event(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<PyEvent>> {
+        let timeout = timeout
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        // Wait in short slices so Ctrl+C is honoured
+        let mut remaining = timeout;
+        loop {
+            let slice = remaining.map_or(SIGNAL_CHECK_INTERVAL, |r| r.min(SIGNAL_CHECK_INTERVAL));
+            match py.detach(|| self.recv(slice))? {
+                Received::Event(event) => {
+                    if let Some(event) = PyEvent::from_server_event(event) {
+                        return Ok(Some(event));
+                    }
+                }
+                Received::Closed => return Ok(None),
+                Received::Timeout => {}
+            }
+            py.check_signals()?;
+            if let Some(r) = remaining.as_mut() {
+                *r = r.saturating_sub(slice);
+                if r.is_zero() {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Stops listening and disconnects all clients.
+    fn shutdown(&self, py: Python<'_>) {
+        if let Ok(mut listener) = self.listener.lock() {
+            if let Some(listener) = listener.take() {
+                listener.abort();
+            }
+        }
+        py.detach(|| {
+            self.runtime.block_on(async {
+                let _ =
+                    tokio::time::timeout(SHUTDOWN_TIMEOUT, self.server.disconnect_all_clients())
+                        .await;
+            });
+        });
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyEvent>> {
+        loop {
+            match py.detach(|| self.recv(SIGNAL_CHECK_INTERVAL))? {
+                Received::Event(event) => {
+                    if let Some(event) = PyEvent::from_server_event(event) {
+                        return Ok(Some(event));
+                    }
+                }
+                Received::Closed => return Ok(None),
+                Received::Timeout => py.check_signals()?,
+            }
+        }
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&self, py: Python<'_>, _args: &Bound<'_, pyo3::types::PyTuple>) {
+        self.shutdown(py);
+    }
+}
+
+/// The `rustvncserver` Python extension module.
+#[pymodule]
+fn rustvncserver(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyVncServer>()?;
+    m.add_class::<PyEvent>()?;
+    Ok(())
+}
@@ -0,0 +1,558 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rectangle encoders used for framebuffer updates.
+//!
+//! The [`Encoding`](crate::encoding::Encoding) trait of `rfb-encodings` returns a new
+//! buffer for every rectangle and knows nothing about the client, so every caller had to
+//! translate pixels and keep compression streams itself. A [`RectEncoder`] appends to a
+//! buffer the caller reuses, and gets an [`EncodeContext`] with the client's pixel
+//! format, quality and compression settings and the connection's persistent compression
+//! streams.
+//!
+//! Encoding happens in two stages. [`RectEncoder::encode`] produces everything that
+//! depends only on the pixels and the settings, which the
+//! [`EncodeCache`](crate::encode_cache::EncodeCache) can share between clients. Encoders
+//! with a compression stream then pass that through [`RectEncoder::compress`]. Tight,
+//! which splits rectangles and has a set of four streams, is encoded separately (see
+//! [`tight::encode_region`](crate::tight::encode_region)).
+//!
+//! Regions are handed to the encoders in pieces ([`split`]): no larger than the
+//! encoding allows, and in horizontal bands of a bounded number of pixels, so a full
+//! 4K update never needs one huge pixel and output buffer and its first bands go out
+//! while the rest are encoded.
+
+use std::io;
+
+use bytes::{BufMut, BytesMut};
+use flate2::{Compress, Compression, FlushCompress};
+
+use crate::framebuffer::DirtyRegion;
+#[cfg(feature = "zstd")]
+use crate::protocol::ENCODING_ZSTD;
+use crate::protocol::{
+    PixelFormat, ENCODING_CORRE, ENCODING_HEXTILE, ENCODING_RAW, ENCODING_RRE, ENCODING_TIGHTPNG,
+    ENCODING_TRLE, ENCODING_ZLIB, ENCODING_ZLIBHEX, ENCODING_ZRLE, ENCODING_ZYWRLE,
+};
+#[cfg(feature = "zstd")]
+use crate::zstd_rect::ZstdStream;
+use crate::{hextile, rre, tightpng, trle, zrle, zywrle};
+use rfb_encodings::translate;
+
+/// A client's encoding settings and compression streams.
+pub(crate) struct EncodeContext {
+    /// The client's pixel format.
+    pub(crate) format: PixelFormat,
+    /// Quality level (0-9), which also selects the ZYWRLE wavelet level.
+    pub(crate) quality_level: u8,
+    /// JPEG quality (1-100).
+    pub(crate) jpeg_quality: u8,
+    /// Compression level (0-9), applied to streams when they are created.
+    pub(crate) compression_level: u8,
+    /// The connection's persistent compression streams.
+    pub(crate) streams: EncodeStreams,
+}
+
+/// Compression streams that live for the whole connection (RFC 6143: one stream per
+/// encoding), each created on first use.
+#[derive(Default)]
+pub(crate) struct EncodeStreams {
+    zlib: Option<Compress>,
+    /// `ZlibHex` stream for raw tiles.
+    zlibhex_raw: Option<Compress>,
+    /// `ZlibHex` stream for Hextile-encoded tiles.
+    zlibhex_encoded: Option<Compress>,
+    /// Shared by ZRLE and ZYWRLE.
+    zrle: Option<Compress>,
+    #[cfg(feature = "zstd")]
+    zstd: Option<ZstdStream>,
+}
+
+impl EncodeStreams {
+    /// Returns the number of streams created so far. A zstd context at the levels used
+    /// here is about the size of a zlib stream, so both count the same.
+    pub(crate) fn initialized(&self) -> usize {
+        let count = [
+            &self.zlib,
+            &self.zlibhex_raw,
+            &self.zlibhex_encoded,
+            &self.zrle,
+        ]
+        .iter()
+        .filter(|stream| stream.is_some())
+        .count();
+        #[cfg(feature = "zstd")]
+        let count = count + usize::from(self.zstd.is_some());
+        count
+    }
+}
+
+/// Encodes rectangles in one encoding.
+pub(crate) trait RectEncoder: Sync {
+    /// Appends the part of a rectangle that depends only on its pixels and the
+    /// settings: the whole payload, or for encoders with a stream the data to compress.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixels` - The rectangle's pixels in the server's RGBA32 format.
+    /// * `width`, `height` - Size of the rectangle.
+    /// * `context` - The client's settings.
+    /// * `out` - Receives the output.
+    fn encode(
+        &self,
+        pixels: &[u8],
+        width: u16,
+        height: u16,
+        context: &EncodeContext,
+        out: &mut BytesMut,
+    );
+
+    /// Returns whether the output of [`encode`](Self::encode) goes through one of the
+    /// connection's compression streams before it is sent.
+    fn has_stream(&self) -> bool {
+        false
+    }
+
+    /// Compresses the output of [`encode`](Self::encode) through the connection's stream
+    /// and appends the rectangle payload to `out`. The default appends `data` unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if compression fails.
+    fn compress(
+        &self,
+        data: &[u8],
+        width: u16,
+        height: u16,
+        context: &mut EncodeContext,
+        out: &mut BytesMut,
+    ) -> io::Result<()> {
+        let _ = (width, height, context);
+        out.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// Returns the largest width and height of a rectangle in this encoding. Larger
+    /// regions are sent as tiles of at most this size.
+    fn max_size(&self) -> u16 {
+        u16::MAX
+    }
+
+    /// Returns the settings besides the pixel format that the output of
+    /// [`encode`](Self::encode) depends on, zero for unused ones.
+    fn settings(&self, context: &EncodeContext) -> (u8, u8) {
+        (context.jpeg_quality, context.compression_level)
+    }
+}
+
+/// Regions with more pixels than this are encoded in horizontal bands.
+const MAX_BAND_PIXELS: usize = 512 * 1024;
+
+/// Band heights are a multiple of this, so bands end on the tile rows of the tiled
+/// encodings (64 for ZRLE and TRLE, 16 for Hextile).
+const BAND_ALIGN: usize = 64;
+
+/// The Raw encoder, also used when another encoder fails.
+pub(crate) const RAW: &dyn RectEncoder = &Raw;
+
+/// Returns the encoder for `encoding`, or `None` if it isn't encoded here (Tight, or an
+/// encoding the server doesn't support).
+pub(crate) fn for_encoding(encoding: i32) -> Option<&'static dyn RectEncoder> {
+    Some(match encoding {
+        ENCODING_RAW => RAW,
+        ENCODING_ZLIB => &Zlib,
+        ENCODING_ZLIBHEX => &ZlibHex,
+        ENCODING_ZRLE => &Zrle { zywrle: false },
+        ENCODING_ZYWRLE => &Zrle { zywrle: true },
+        ENCODING_TRLE => &Trle,
+        ENCODING_TIGHTPNG => &TightPng,
+        #[cfg(feature = "zstd")]
+        ENCODING_ZSTD => &Zstd,
+        ENCODING_HEXTILE => &Hextile,
+        ENCODING_RRE => &Rre,
+        ENCODING_CORRE => &Corre,
+        _ => return None,
+    })
+}
+
+/// Splits a region into the rectangles `encoder` sends it as: tiles no larger than its
+/// [`max_size`](RectEncoder::max_size), each cut into bands of at most
+/// `MAX_BAND_PIXELS` pixels, top to bottom.
+pub(crate) fn split(
+    region: DirtyRegion,
+    encoder: &dyn RectEncoder,
+) -> impl Iterator<Item = DirtyRegion> {
+    region.tiles(encoder.max_size()).flat_map(|tile| {
+        let rows = (MAX_BAND_PIXELS / usize::from(tile.width).max(1)) / BAND_ALIGN * BAND_ALIGN;
+        let rows = u16::try_from(rows.max(BAND_ALIGN)).unwrap_or(u16::MAX);
+        tile.bands(rows)
+    })
+}
+
+/// Appends pixels translated from the server's RGBA32 format to the client's.
+fn translate_into(pixels: &[u8], format: &PixelFormat, out: &mut BytesMut) {
+    if format.is_compatible_with_rgba32() {
+        // Fast path: no translation, but the alpha byte is sent as padding
+        out.reserve(pixels.len());
+        for chunk in pixels.chunks_exact(4) {
+            out.put_slice(&[chunk[0], chunk[1], chunk[2], 0]);
+        }
+    } else {
+        out.extend_from_slice(&translate::translate_pixels(
+            pixels,
+            &PixelFormat::rgba32(),
+            format,
+        ));
+    }
+}
+
+/// Returns a zlib stream, creating it at `level` on first use.
+fn zlib_stream(stream: &mut Option<Compress>, level: u8) -> &mut Compress {
+    stream.get_or_insert_with(|| Compress::new(Compression::new(u32::from(level)), true))
+}
+
+/// Compresses `data` through a persistent zlib stream, flushed at the end, and appends
+/// the compressed length and data to `out`. This is the payload of Zlib and ZRLE
+/// rectangles.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if zlib compression fails.
+pub(crate) fn compress_zlib(
+    data: &[u8],
+    stream: &mut Compress,
+    out: &mut BytesMut,
+) -> io::Result<()> {
+    let header = out.len();
+    out.put_u32(0);
+    deflate(data, stream, out)?;
+    let length = u32::try_from(out.len() - header - 4).map_err(io::Error::other)?;
+    out[header..header + 4].copy_from_slice(&length.to_be_bytes());
+    Ok(())
+}
+
+/// Compresses `data` through a persistent zlib stream, flushed at the end, and appends
+/// the compressed data to `out`.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if zlib compression fails.
+pub(crate) fn deflate(data: &[u8], stream: &mut Compress, out: &mut BytesMut) -> io::Result<()> {
+    let start_in = stream.total_in();
+    loop {
+        // A sync flush of incompressible data can slightly exceed the input size, so
+        // grow the output until the stream stops filling it
+        #[allow(clippy::cast_possible_truncation)] // Bounded by the input length
+        let consumed = (stream.total_in() - start_in) as usize;
+        let filled = out.len();
+        let space = data.len() - consumed + data.len() / 16 + 64;
+        out.resize(filled + space, 0);
+        let start_out = stream.total_out();
+        let status = stream.compress(&data[consumed..], &mut out[filled..], FlushCompress::Sync);
+        #[allow(clippy::cast_possible_truncation)] // Bounded by the output space
+        let written = (stream.total_out() - start_out) as usize;
+        out.truncate(filled + written);
+        status.map_err(io::Error::other)?;
+        if stream.total_in() - start_in == data.len() as u64 && written < space {
+            return Ok(());
+        }
+    }
+}
+
+/// Raw (encoding 0): the pixels in the client's format.
+struct Raw;
+
+impl RectEncoder for Raw {
+    fn encode(&self, pixels: &[u8], _: u16, _: u16, context: &EncodeContext, out: &mut BytesMut) {
+        translate_into(pixels, &context.format, out);
+    }
+
+    fn settings(&self, _: &EncodeContext) -> (u8, u8) {
+        (0, 0)
+    }
+}
+
+/// Zlib (encoding 6): Raw pixels through the connection's Zlib stream.
+struct Zlib;
+
+impl RectEncoder for Zlib {
+    fn encode(&self, pixels: &[u8], _: u16, _: u16, context: &EncodeContext, out: &mut BytesMut) {
+        translate_into(pixels, &context.format, out);
+    }
+
+    fn has_stream(&self) -> bool {
+        true
+    }
+
+    fn compress(
+        &self,
+        data: &[u8],
+        _: u16,
+        _: u16,
+        context: &mut EncodeContext,
+        out: &mut BytesMut,
+    ) -> io::Result<()> {
+        let stream = zlib_stream(&mut context.streams.zlib, context.compression_level);
+        compress_zlib(data, stream, out)
+    }
+
+    fn settings(&self, _: &EncodeContext) -> (u8, u8) {
+        (0, 0)
+    }
+}
+
+/// Hextile (encoding 5): 16x16 tiles of subrectangles.
+struct Hextile;
+
+impl RectEncoder for Hextile {
+    fn encode(
+        &self,
+        pixels: &[u8],
+        width: u16,
+        height: u16,
+        context: &EncodeContext,
+        out: &mut BytesMut,
+    ) {
+        let mut translated = BytesMut::new();
+        translate_into(pixels, &context.format, &mut translated);
+        hextile::encode_hextile(&translated, width, height, &context.format, out);
+    }
+
+    fn settings(&self, _: &EncodeContext) -> (u8, u8) {
+        (0, 0)
+    }
+}
+
+/// `ZlibHex` (encoding 8): Hextile tiles through the connection's two `ZlibHex`
+/// streams. Tiles are compressed one by one, so the shared data is the pixels.
+struct ZlibHex;
+
+impl RectEncoder for ZlibHex {
+    fn encode(&self, pixels: &[u8], _: u16, _: u16, context: &EncodeContext, out: &mut BytesMut) {
+        translate_into(pixels, &context.format, out);
+    }
+
+    fn has_stream(&self) -> bool {
+        true
+    }
+
+    fn compress(
+        &self,
+        data: &[u8],
+        width: u16,
+        height: u16,
+        context: &mut EncodeContext,
+        out: &mut BytesMut,
+    ) -> io::Result<()> {
+        let level = context.compression_level;
+        let streams = (
+            zlib_stream(&mut context.streams.zlibhex_raw, level),
+            zlib_stream(&mut context.streams.zlibhex_encoded, level),
+        );
+        hextile::encode_zlibhex(data, width, height, &context.format, streams, out)
+    }
+
+    fn settings(&self, _: &EncodeContext) -> (u8, u8) {
+        (0, 0)
+    }
+}
+
+/// ZRLE (encoding 16) and ZYWRLE (encoding 17): tiles through the connection's ZRLE
+/// stream, which ZYWRLE shares.
+struct Zrle {
+    zywrle: bool,
+}
+
+impl Zrle {
+    /// Returns the ZYWRLE wavelet level, or 0 for plain ZRLE.
+    fn level(&self, context: &EncodeContext) -> usize {
+        if self.zywrle {
+            zywrle::level_for_quality(context.quality_level)
+        } else {
+            0
+        }
+    }
+}
+
+impl RectEncoder for Zrle {
+    fn encode(
+        &self,
+        pixels: &[u8],
+        width: u16,
+        height: u16,
+        context: &EncodeContext,
+        out: &mut BytesMut,
+    ) {
+        let mut translated = BytesMut::new();
+        translate_into(pixels, &context.format, &mut translated);
+        zrle::encode_tiles(
+            &translated,
+            width,
+            height,
+            &context.format,
+            self.level(context),
+            out,
+        );
+    }
+
+    fn has_stream(&self) -> bool {
+        true
+    }
+
+    fn compress(
+        &self,
+        data: &[u8],
+        _: u16,
+        _: u16,
+        context: &mut EncodeContext,
+        out: &mut BytesMut,
+    ) -> io::Result<()> {
+        let stream = zlib_stream(&mut context.streams.zrle, context.compression_level);
+        compress_zlib(data, stream, out)
+    }
+
+    #[allow(clippy::cast_possible_truncation)] // Wavelet levels are 0-3
+    fn settings(&self, context: &EncodeContext) -> (u8, u8) {
+        (self.level(context) as u8, 0)
+    }
+}
+
+/// TRLE (encoding 15): uncompressed tiles.
+struct Trle;
+
+impl RectEncoder for Trle {
+    fn encode(
+        &self,
+        pixels: &[u8],
+        width: u16,
+        height: u16,
+        context: &EncodeContext,
+        out: &mut BytesMut,
+    ) {
+        let mut translated = BytesMut::new();
+        translate_into(pixels, &context.format, &mut translated);
+        trle::encode_trle(&translated, width, height, &context.format, out);
+    }
+
+    fn settings(&self, _: &EncodeContext) -> (u8, u8) {
+        (0, 0)
+    }
+}
+
+/// `TightPng` (encoding -260): always RGB PNG data, independent of the client's pixel
+/// format, so it is encoded straight from the server's pixels.
+struct TightPng;
+
+impl RectEncoder for TightPng {
+    fn encode(
+        &self,
+        pixels: &[u8],
+        width: u16,
+        height: u16,
+        context: &EncodeContext,
+        out: &mut BytesMut,
+    ) {
+        out.extend_from_slice(&tightpng::encode_tightpng(
+            pixels,
+            width,
+            height,
+            context.jpeg_quality,
+            context.compression_level,
+        ));
+    }
+}
+
+/// The experimental zstd encoding: Raw pixels through the connection's zstd stream.
+#[cfg(feature = "zstd")]
+struct Zstd;
+
+#[cfg(feature = "zstd")]
+impl RectEncoder for Zstd {
+    fn encode(&self, pixels: &[u8], _: u16, _: u16, context: &EncodeContext, out: &mut BytesMut) {
+        translate_into(pixels, &context.format, out);
+    }
+
+    fn has_stream(&self) -> bool {
+        true
+    }
+
+    fn compress(
+        &self,
+        data: &[u8],
+        _: u16,
+        _: u16,
+        context: &mut EncodeContext,
+        out: &mut BytesMut,
+    ) -> io::Result<()> {
+        let stream = match &mut context.streams.zstd {
+            Some(stream) => stream,
+            stream => stream.insert(ZstdStream::new(context.compression_level)?),
+        };
+        out.extend_from_slice(&stream.encode(data)?);
+        Ok(())
+    }
+
+    fn settings(&self, _: &EncodeContext) -> (u8, u8) {
+        (0, 0)
+    }
+}
+
+/// RRE (encoding 2): a background color and subrectangles.
+struct Rre;
+
+impl RectEncoder for Rre {
+    fn encode(
+        &self,
+        pixels: &[u8],
+        width: u16,
+        height: u16,
+        context: &EncodeContext,
+        out: &mut BytesMut,
+    ) {
+        let mut translated = BytesMut::new();
+        translate_into(pixels, &context.format, &mut translated);
+        rre::encode_rre(&translated, width, height, &context.format, out);
+    }
+
+    fn settings(&self, _: &EncodeContext) -> (u8, u8) {
+        (0, 0)
+    }
+}
+
+/// `CoRRE` (encoding 4): RRE with 8-bit subrectangle positions and sizes, so
+/// rectangles are at most 255 pixels wide and high.
+struct Corre;
+
+impl RectEncoder for Corre {
+    fn encode(
+        &self,
+        pixels: &[u8],
+        width: u16,
+        height: u16,
+        context: &EncodeContext,
+        out: &mut BytesMut,
+    ) {
+        let mut translated = BytesMut::new();
+        translate_into(pixels, &context.format, &mut translated);
+        rre::encode_corre(&translated, width, height, &context.format, out);
+    }
+
+    fn max_size(&self) -> u16 {
+        255
+    }
+
+    fn settings(&self, _: &EncodeContext) -> (u8, u8) {
+        (0, 0)
+    }
+}
@@ -0,0 +1,456 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sets of pixels made of rectangles, with union, subtraction and intersection.
+//!
+//! This plays the part of standard VNC protocol's `sraRegion` (pixman regions in newer
+//! servers). A [`Region`] is stored as non-overlapping rectangles in y-x banded form:
+//! the area is cut into horizontal bands, each band holds the rectangles covering it
+//! from left to right, and vertically adjacent bands with the same rectangles are
+//! merged. Any set of pixels has exactly one such representation, so two regions are
+//! equal exactly when they cover the same pixels.
+//!
+//! Regions hold the exact areas of pending copies and damage. A [`DamageQueue`] adds
+//! an age index to a region, so owners can send the oldest damage first.
+
+use crate::framebuffer::DirtyRegion;
+
+/// A horizontal span `[left, right)`.
+type Span = (u32, u32);
+
+/// A set of pixels, stored as non-overlapping rectangles in y-x banded form.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Region {
+    rects: Vec<DirtyRegion>,
+}
+
+impl Region {
+    /// Creates an empty region.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether the region covers no pixels.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// Returns the region's rectangles, top to bottom and left to right.
+    #[must_use]
+    pub fn rects(&self) -> &[DirtyRegion] {
+        &self.rects
+    }
+
+    /// Returns the number of pixels the region covers.
+    #[must_use]
+    pub fn area(&self) -> u64 {
+        self.rects
+            .iter()
+            .map(|rect| u64::from(rect.width) * u64::from(rect.height))
+            .sum()
+    }
+
+    /// Returns the smallest rectangle containing the region, or `None` if it is empty.
+    #[must_use]
+    pub fn bounds(&self) -> Option<DirtyRegion> {
+        let first = *self.rects.first()?;
+        Some(
+            self.rects[1..]
+                .iter()
+                .fold(first, |acc, rect| acc.merge(rect)),
+        )
+    }
+
+    /// Returns the pixels in `self`, in `other`, or in both.
+    #[must_use]
+    pub fn union(&self, other: &Region) -> Region {
+        self.combine(other, |a, b| a || b)
+    }
+
+    /// Returns the pixels in `self` that are not in `other`.
+    #[must_use]
+    pub fn subtract(&self, other: &Region) -> Region {
+        self.combine(other, |a, b| a && !b)
+    }
+
+    /// Returns the pixels in both `self` and `other`.
+    #[must_use]
+    pub fn intersect(&self, other: &Region) -> Region {
+        self.combine(other, |a, b| a && b)
+    }
+
+    /// Moves the region by (`dx`, `dy`), dropping what leaves the coordinate range.
+    #[must_use]
+    pub fn translate(&self, dx: i16, dy: i16) -> Region {
+        self.rects
+            .iter()
+            .filter_map(|rect| rect.translate(dx, dy))
+            .collect()
+    }
+
+    /// Applies a set operation band by band: `keep` tells, for a pixel in `self` and/or
+    /// in `other`, whether it belongs to the result.
+    fn combine(&self, other: &Region, keep: impl Fn(bool, bool) -> bool) -> Region {
+        let mut edges: Vec<u32> = self
+            .rects
+            .iter()
+            .chain(&other.rects)
+            .flat_map(|rect| {
+                let top = u32::from(rect.y);
+                [top, top + u32::from(rect.height)]
+            })
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+
+        let mut bands: Vec<(u32, u32, Vec<Span>)> = Vec::new();
+        for pair in edges.windows(2) {
+            let (top, bottom) = (pair[0], pair[1]);
+            let spans = combine_spans(
+                &spans_at(&self.rects, top),
+                &spans_at(&other.rects, top),
+                &keep,
+            );
+            if spans.is_empty() {
+                continue;
+            }
+            // Coalesce with the band above when it is adjacent and identical
+            match bands.last_mut() {
+                Some((_, last_bottom, last_spans))
+                    if *last_bottom == top && *last_spans == spans =>
+                {
+                    *last_bottom = bottom;
+                }
+                _ => bands.push((top, bottom, spans)),
+            }
+        }
+
+        let rects = bands
+            .into_iter()
+            .flat_map(|(top, bottom, spans)| {
+                spans
+                    .into_iter()
+                    .map(move |(left, right)| rect_from_edges(left, top, right, bottom))
+            })
+            .collect();
+        Region { rects }
+    }
+}
+
+impl From<DirtyRegion> for Region {
+    fn from(rect: DirtyRegion) -> Self {
+        if rect.width == 0 || rect.height == 0 {
+            return Self::new();
+        }
+        Self { rects: vec![rect] }
+    }
+}
+
+impl FromIterator<DirtyRegion> for Region {
+    /// Builds the union of the rectangles.
+    fn from_iter<I: IntoIterator<Item = DirtyRegion>>(iter: I) -> Self {
+        let rects: Vec<DirtyRegion> = iter
+            .into_iter()
+            .filter(|rect| rect.width > 0 && rect.height > 0)
+            .collect();
+        // Rectangles may overlap, so go through a set operation to normalize them
+        Region { rects }.union(&Region::new())
+    }
+}
+
+/// Pending damage: the exact area to send, and the order it arrived in.
+///
+/// Next to the area, an age index of bounding boxes is kept in order of first damage.
+/// A new rectangle is merged with the boxes it intersects and takes the place of the
+/// oldest of them, as in standard VNC protocol's region merging. The boxes cover the
+/// area, and may cover more.
+///
+/// Both are bounded: past [`MAX_AGES`](Self::MAX_AGES) boxes or
+/// [`MAX_AGE_PIXELS`](Self::MAX_AGE_PIXELS) pixels of boxes, the boxes are merged into
+/// one, and past [`MAX_RECTS`](Self::MAX_RECTS) rectangles the area is coarsened to the
+/// boxes. This trades granularity (pixels that didn't change may be sent) for bounded
+/// memory under rapid screen changes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DamageQueue {
+    region: Region,
+    ages: Vec<DirtyRegion>,
+}
+
+impl DamageQueue {
+    /// Maximum number of boxes in the age index.
+    pub const MAX_AGES: usize = 10;
+    /// Maximum number of pixels covered by the boxes of the age index, counting overlaps
+    /// (approximately 2 Full HD screens).
+    pub const MAX_AGE_PIXELS: u64 = 1920 * 1080 * 2;
+    /// Maximum number of rectangles in the area.
+    pub const MAX_RECTS: usize = 64;
+
+    /// Creates an empty queue.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether no damage is pending.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.region.is_empty()
+    }
+
+    /// Returns the damaged area.
+    #[must_use]
+    pub fn region(&self) -> &Region {
+        &self.region
+    }
+
+    /// Returns the age index: bounding boxes of the damage, oldest first.
+    #[must_use]
+    pub fn ages(&self) -> &[DirtyRegion] {
+        &self.ages
+    }
+
+    /// Adds damage.
+    pub fn add(&mut self, rect: DirtyRegion) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        self.region = self.region.union(&rect.into());
+
+        // Merge with all intersecting boxes, not just the first
+        let mut merged = rect;
+        let mut oldest = None;
+        let mut index = 0;
+        self.ages.retain(|age| {
+            index += 1;
+            if age.intersects(&merged) {
+                merged = age.merge(&merged);
+                oldest.get_or_insert(index - 1);
+                false
+            } else {
+                true
+            }
+        });
+        match oldest {
+            Some(position) => self.ages.insert(position, merged),
+            None => self.ages.push(merged),
+        }
+
+        let age_pixels: u64 = self
+            .ages
+            .iter()
+            .map(|age| u64::from(age.width) * u64::from(age.height))
+            .sum();
+        if self.ages.len() > Self::MAX_AGES || age_pixels > Self::MAX_AGE_PIXELS {
+            if let Some(bounds) = self.ages.iter().copied().reduce(|a, b| a.merge(&b)) {
+                self.ages = vec![bounds];
+            }
+        }
+        self.limit_rects(&Region::new());
+    }
+
+    /// Removes damage, e.g. once it has been sent. Boxes of the age index shrink to what
+    /// remains of the area inside them and keep their place.
+    pub fn subtract(&mut self, other: &Region) {
+        if other.is_empty() || self.region.is_empty() {
+            return;
+        }
+        self.region = self.region.subtract(other);
+        let region = &self.region;
+        self.ages = self
+            .ages
+            .iter()
+            .filter_map(|&age| Region::from(age).intersect(region).bounds())
+            .collect();
+        self.limit_rects(other);
+    }
+
+    /// Collapses the damage into its bounding box.
+    pub fn coarsen(&mut self) {
+        *self = self.region.bounds().map(Self::from).unwrap_or_default();
+    }
+
+    /// Coarsens the area to the boxes of the age index, less what was just `removed`,
+    /// when it has too many rectangles; to a single box if that is still too many.
+    fn limit_rects(&mut self, removed: &Region) {
+        if self.region.rects().len() <= Self::MAX_RECTS {
+            return;
+        }
+        self.region = self
+            .ages
+            .iter()
+            .copied()
+            .collect::<Region>()
+            .subtract(removed);
+        if self.region.rects().len() > Self::MAX_RECTS {
+            if let Some(bounds) = self.ages.iter().copied().reduce(|a, b| a.merge(&b)) {
+                self.ages = vec![bounds];
+                self.region = bounds.into();
+            }
+        }
+    }
+}
+
+impl From<DirtyRegion> for DamageQueue {
+    fn from(rect: DirtyRegion) -> Self {
+        let mut queue = Self::new();
+        queue.add(rect);
+        queue
+    }
+}
+
+/// Returns the merged horizontal spans of the rectangles covering row `y`, left to right.
+fn spans_at(rects: &[DirtyRegion], y: u32) -> Vec<Span> {
+    let mut spans: Vec<Span> = rects
+        .iter()
+        .filter(|rect| u32::from(rect.y) <= y && y < u32::from(rect.y) + u32::from(rect.height))
+        .map(|rect| {
+            let left = u32::from(rect.x);
+            (left, left + u32::from(rect.width))
+        })
+        .collect();
+    spans.sort_unstable();
+    let mut merged: Vec<Span> = Vec::with_capacity(spans.len());
+    for (left, right) in spans {
+        match merged.last_mut() {
+            Some((_, last_right)) if left <= *last_right => *last_right = (*last_right).max(right),
+            _ => merged.push((left, right)),
+        }
+    }
+    merged
+}
+
+/// Combines two lists of merged spans with a set operation.
+fn combine_spans(a: &[Span], b: &[Span], keep: impl Fn(bool, bool) -> bool) -> Vec<Span> {
+    let mut edges: Vec<u32> = a
+        .iter()
+        .chain(b)
+        .flat_map(|&(left, right)| [left, right])
+        .collect();
+    edges.sort_unstable();
+    edges.dedup();
+
+    let covers = |spans: &[Span], x: u32| spans.iter().any(|&(left, right)| left <= x && x < right);
+    let mut result: Vec<Span> = Vec::new();
+    for pair in edges.windows(2) {
+        let (left, right) = (pair[0], pair[1]);
+        if !keep(covers(a, left), covers(b, left)) {
+            continue;
+        }
+        match result.last_mut() {
+            Some((_, last_right)) if *last_right == left => *last_right = right,
+            _ => result.push((left, right)),
+        }
+    }
+    result
+}
+
+/// Builds a rectangle from its edges, which come from `u16` rectangles.
+fn rect_from_edges(left: u32, top: u32, right: u32, bottom: u32) -> DirtyRegion {
+    let coordinate = |value: u32| u16::try_from(value).unwrap_or(u16::MAX);
+    DirtyRegion::new(
+        coordinate(left),
+        coordinate(top),
+        coordinate(right - left),
+        coordinate(bottom - top),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations_produce_banded_rectangles() {
+        let a = Region::from(DirtyRegion::new(0, 0, 10, 10));
+        let b = Region::from(DirtyRegion::new(5, 5, 10, 10));
+
+        assert_eq!(
+            a.union(&b).rects(),
+            [
+                DirtyRegion::new(0, 0, 10, 5),
+                DirtyRegion::new(0, 5, 15, 5),
+                DirtyRegion::new(5, 10, 10, 5),
+            ]
+        );
+        assert_eq!(
+            a.subtract(&b).rects(),
+            [DirtyRegion::new(0, 0, 10, 5), DirtyRegion::new(0, 5, 5, 5)]
+        );
+        assert_eq!(a.intersect(&b).rects(), [DirtyRegion::new(5, 5, 5, 5)]);
+        assert_eq!(a.union(&b).area(), 175);
+        assert_eq!(a.union(&b).bounds(), Some(DirtyRegion::new(0, 0, 15, 15)));
+    }
+
+    #[test]
+    fn equal_areas_have_equal_representations() {
+        let halves: Region = [DirtyRegion::new(0, 0, 4, 8), DirtyRegion::new(4, 0, 4, 8)]
+            .into_iter()
+            .collect();
+        let stacked: Region = [DirtyRegion::new(0, 0, 8, 3), DirtyRegion::new(0, 3, 8, 5)]
+            .into_iter()
+            .collect();
+        assert_eq!(halves, stacked);
+        assert_eq!(halves, Region::from(DirtyRegion::new(0, 0, 8, 8)));
+        assert!(halves.subtract(&stacked).is_empty());
+    }
+
+    #[test]
+    fn damage_queue_keeps_exact_area_in_age_order() {
+        let mut queue = DamageQueue::new();
+        queue.add(DirtyRegion::new(0, 0, 10, 10));
+        queue.add(DirtyRegion::new(50, 0, 10, 10));
+        queue.add(DirtyRegion::new(5, 5, 10, 10));
+        assert_eq!(
+            queue.ages(),
+            [
+                DirtyRegion::new(0, 0, 15, 15),
+                DirtyRegion::new(50, 0, 10, 10)
+            ]
+        );
+        assert_eq!(queue.region().area(), 275);
+
+        // Sending part of the oldest box shrinks it in place
+        queue.subtract(&DirtyRegion::new(0, 0, 15, 10).into());
+        assert_eq!(
+            queue.ages(),
+            [
+                DirtyRegion::new(5, 10, 10, 5),
+                DirtyRegion::new(50, 0, 10, 10)
+            ]
+        );
+        queue.subtract(&DirtyRegion::new(0, 0, 100, 100).into());
+        assert!(queue.is_empty());
+        assert!(queue.ages().is_empty());
+    }
+
+    #[test]
+    fn damage_queue_stays_bounded() {
+        let mut queue = DamageQueue::new();
+        for i in 0..100 {
+            queue.add(DirtyRegion::new(i * 20, i * 3, 10, 2));
+        }
+        assert!(queue.ages().len() <= DamageQueue::MAX_AGES);
+        assert!(queue.region().rects().len() <= DamageQueue::MAX_RECTS);
+
+        // Punching holes can't grow the area past the cap either
+        let mut queue = DamageQueue::from(DirtyRegion::new(0, 0, 1000, 1000));
+        for i in 0..100 {
+            queue.subtract(&DirtyRegion::new(i * 10 + 1, i * 10 + 1, 2, 2).into());
+        }
+        assert!(queue.region().rects().len() <= DamageQueue::MAX_RECTS);
+        assert!(queue.ages().len() <= DamageQueue::MAX_AGES);
+    }
+}
@@ -37,11 +37,14 @@ use log::error;
 #[cfg(feature = "debug-logging")]
 use log::info;
 use std::io;
+use std::sync::Arc;
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 
+use crate::authenticator::Authenticator;
 use crate::client::{ClientEvent, VncClient};
+use crate::config::ServerConfig;
 use crate::framebuffer::Framebuffer;
 
 /// Connects to a VNC repeater using the UltraVNC-style repeater protocol.
@@ -58,7 +61,8 @@ use crate::framebuffer::Framebuffer;
 /// * `repeater_id` - The unique ID string to send to the repeater for session identification.
 /// * `framebuffer` - The VNC framebuffer instance to be used for the session.
 /// * `desktop_name` - The desktop name to be advertised to the connected viewer.
-/// * `password` - An optional password for VNC authentication.
+/// * `authenticator` - Checks the viewer's credentials.
+/// * `config` - The server configuration to apply to the client.
 /// * `event_tx` - An `mpsc::UnboundedSender<ClientEvent>` to send client-related events.
 ///
 /// # Returns
@@ -75,7 +79,8 @@ pub async fn connect_repeater(
     repeater_id: String,
     framebuffer: Framebuffer,
     desktop_name: String,
-    password: Option<String>,
+    authenticator: Arc<dyn Authenticator>,
+    config: Arc<ServerConfig>,
     event_tx: mpsc::UnboundedSender<ClientEvent>,
 ) -> Result<VncClient, io::Error> {
     #[cfg(feature = "debug-logging")]
@@ -125,12 +130,14 @@ pub async fn connect_repeater(
     info!("Repeater ID sent, proceeding with VNC handshake");
 
     // Now proceed with normal VNC client handshake
-    let mut client = VncClient::new(
+    let mut client = VncClient::from_tcp(
         client_id,
         stream,
+        Some(repeater_id.clone()),
         framebuffer,
         desktop_name,
-        password,
+        authenticator,
+        config,
         event_tx,
     )
     .await?;
@@ -0,0 +1,126 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Session resume across reconnects (experimental, private).
+//!
+//! A private (unregistered) extension for deployments where both ends run this stack.
+//! When [`ConnectionConfig::session_resume_timeout`](crate::config::ConnectionConfig::session_resume_timeout)
+//! is set and a client lists
+//! [`ENCODING_SESSION_RESUME`](crate::protocol::ENCODING_SESSION_RESUME) in
+//! `SetEncodings`, the server sends it a session token in the next update, as a
+//! pseudo-rectangle with that encoding, zero size and a 16-byte payload.
+//!
+//! When the session ends, the server keeps collecting damage for it, along with its
+//! server-side quality and compression overrides, until the timeout. A viewer that kept
+//! its screen contents reconnects, lists the pseudo-encoding again and sends
+//!
+//! ```text
+//! u8     message type (CLIENT_MSG_RESUME_SESSION)
+//! u8[3]  padding
+//! u8[16] token
+//! ```
+//!
+//! before its first `FramebufferUpdateRequest`. If the token is known and the session
+//! was showing the same desktop, that request's full refresh is replaced by the damage
+//! collected while the viewer was away, plus the areas of the last updates it may not
+//! have received. Either way the server answers with a token pseudo-rectangle: the same
+//! token if the session was resumed, a new one otherwise.
+//!
+//! A session is only kept once the server has noticed the old connection is gone (see
+//! `ConnectionConfig::write_stall_timeout` and `keepalive_interval`), and a token can be
+//! used once.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tokio::sync::RwLock;
+
+use crate::config::ColorMode;
+use crate::framebuffer::Framebuffer;
+use crate::region::DamageQueue;
+
+/// Identifies a suspended session.
+pub(crate) type SessionToken = [u8; 16];
+
+/// Maximum number of sessions kept at once; the longest-suspended ones are dropped first.
+const MAX_SUSPENDED_SESSIONS: usize = 256;
+
+/// The state kept for a client between its disconnect and its resume.
+pub(crate) struct SuspendedSession {
+    /// The desktop the client was showing.
+    pub(crate) framebuffer: Framebuffer,
+    /// The client's damage queue, still registered with `framebuffer` so it keeps
+    /// collecting damage.
+    pub(crate) damage: Arc<RwLock<DamageQueue>>,
+    /// Server-side JPEG quality override.
+    pub(crate) jpeg_quality_override: Option<u8>,
+    /// Server-side compression level override.
+    pub(crate) compression_override: Option<u8>,
+    /// Server-side color reduction override.
+    pub(crate) color_mode_override: Option<ColorMode>,
+    /// Server-side frame-rate cap.
+    pub(crate) update_interval_override: Option<Duration>,
+    /// When the session was suspended.
+    pub(crate) suspended_at: Instant,
+}
+
+/// Suspended sessions awaiting a reconnect, shared by all clients of a server.
+#[derive(Default)]
+pub(crate) struct SessionStore {
+    sessions: Mutex<HashMap<SessionToken, SuspendedSession>>,
+}
+
+impl SessionStore {
+    /// Generates a new random session token.
+    pub(crate) fn new_token() -> SessionToken {
+        rand::thread_rng().gen()
+    }
+
+    /// Keeps `session` until it is resumed or `timeout` passes.
+    pub(crate) fn suspend(
+        &self,
+        token: SessionToken,
+        session: SuspendedSession,
+        timeout: Duration,
+    ) {
+        let Ok(mut sessions) = self.sessions.lock() else {
+            return;
+        };
+        // Dropping a session releases its damage queue, which unregisters it
+        sessions.retain(|_, s| s.suspended_at.elapsed() < timeout);
+        if sessions.len() >= MAX_SUSPENDED_SESSIONS {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, s)| s.suspended_at)
+                .map(|(token, _)| *token);
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        sessions.insert(token, session);
+    }
+
+    /// Takes the session suspended under `token`, unless it has expired.
+    pub(crate) fn resume(
+        &self,
+        token: &SessionToken,
+        timeout: Duration,
+    ) -> Option<SuspendedSession> {
+        let mut sessions = self.sessions.lock().ok()?;
+        sessions.retain(|_, s| s.suspended_at.elapsed() < timeout);
+        sessions.remove(token)
+    }
+}
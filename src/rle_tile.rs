@@ -0,0 +1,303 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tile sub-encoders shared by the run-length tile encodings (TRLE and ZRLE).
+//!
+//! Both encodings split a rectangle into tiles and send each tile with the cheapest of
+//! the same sub-encodings:
+//!
+//! - raw `CPIXEL`s;
+//! - a solid color;
+//! - a packed palette of 2-16 colors, with 1, 2 or 4 bits per pixel and every row
+//!   padded to whole bytes;
+//! - plain run-length encoding: a `CPIXEL` and the run length for every run;
+//! - palette run-length encoding with up to 127 colors.
+//!
+//! Pixels are given in the client's pixel format. A `CPIXEL` is a pixel in that format,
+//! shortened to 3 bytes when the format has 32 bits per pixel but a depth of at most 24
+//! that fits in the low or high three bytes (RFC 6143, section 7.7.5).
+
+use bytes::{BufMut, BytesMut};
+
+use crate::protocol::PixelFormat;
+
+/// Sub-encoding: raw `CPIXEL`s.
+pub(crate) const SUBENCODING_RAW: u8 = 0;
+
+/// Sub-encoding: the whole tile has one color.
+const SUBENCODING_SOLID: u8 = 1;
+
+/// Sub-encoding: plain run-length encoding. Palette run-length encoding adds the
+/// palette size to this value.
+const SUBENCODING_RLE: u8 = 128;
+
+/// Largest palette of the packed palette sub-encoding.
+const MAX_PACKED_PALETTE: usize = 16;
+
+/// Largest palette of the palette run-length sub-encoding.
+const MAX_RLE_PALETTE: usize = 127;
+
+/// Writes pixels as `CPIXEL`s of a client's pixel format.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CPixel {
+    /// Bytes per `CPIXEL`.
+    size: usize,
+    big_endian: bool,
+    /// Whether a 3-byte `CPIXEL` holds the high three bytes of the pixel value.
+    high_bytes: bool,
+}
+
+impl CPixel {
+    /// Returns the `CPIXEL` layout of `format`.
+    pub(crate) fn new(format: &PixelFormat) -> Self {
+        let channels = [
+            (format.red_max, format.red_shift),
+            (format.green_max, format.green_shift),
+            (format.blue_max, format.blue_shift),
+        ];
+        let fits_low = channels
+            .iter()
+            .all(|&(max, shift)| u64::from(max) << shift < 1 << 24);
+        let fits_high = channels.iter().all(|&(_, shift)| shift > 7);
+        let compact = format.true_colour_flag != 0
+            && format.bits_per_pixel == 32
+            && format.depth <= 24
+            && (fits_low || fits_high);
+        Self {
+            size: if compact {
+                3
+            } else {
+                usize::from(format.bits_per_pixel / 8)
+            },
+            big_endian: format.big_endian_flag != 0,
+            high_bytes: compact && !fits_low,
+        }
+    }
+
+    /// Returns a layout that writes whole pixels of `format`, as Hextile does.
+    pub(crate) fn full(format: &PixelFormat) -> Self {
+        Self {
+            size: usize::from(format.bits_per_pixel / 8).max(1),
+            big_endian: format.big_endian_flag != 0,
+            high_bytes: false,
+        }
+    }
+
+    /// Returns the number of bytes per `CPIXEL`.
+    pub(crate) fn size(self) -> usize {
+        self.size
+    }
+
+    /// Appends `pixel` to `buf`.
+    #[allow(clippy::cast_possible_truncation)] // Pixels are truncated to the CPIXEL size
+    pub(crate) fn write(self, buf: &mut BytesMut, pixel: u32) {
+        match (self.size, self.big_endian) {
+            (1, _) => buf.put_u8(pixel as u8),
+            (2, true) => buf.put_u16(pixel as u16),
+            (2, false) => buf.put_u16_le(pixel as u16),
+            (3, big_endian) => {
+                let value = if self.high_bytes {
+                    pixel >> 8
+                } else {
+                    pixel & 0x00ff_ffff
+                };
+                if big_endian {
+                    buf.put_slice(&value.to_be_bytes()[1..]);
+                } else {
+                    buf.put_slice(&value.to_le_bytes()[..3]);
+                }
+            }
+            (_, true) => buf.put_u32(pixel),
+            (_, false) => buf.put_u32_le(pixel),
+        }
+    }
+}
+
+/// Reads pixel data in `format` (`bits_per_pixel / 8` bytes per pixel) as pixel values.
+pub(crate) fn read_pixels(data: &[u8], format: &PixelFormat) -> Vec<u32> {
+    let big_endian = format.big_endian_flag != 0;
+    let bytes_per_pixel = usize::from(format.bits_per_pixel / 8).max(1);
+    data.chunks_exact(bytes_per_pixel)
+        .map(|bytes| {
+            let value = bytes
+                .iter()
+                .fold(0u32, |value, &byte| value << 8 | u32::from(byte));
+            if big_endian {
+                value
+            } else {
+                // The fold read the bytes most significant first
+                value.swap_bytes() >> (8 * (4 - bytes.len()))
+            }
+        })
+        .collect()
+}
+
+/// Copies the tile at (`x`, `y`) out of a rectangle's pixels into `tile`.
+///
+/// # Arguments
+///
+/// * `pixels` - The rectangle's pixels, row by row.
+/// * `stride` - Width of the rectangle in pixels.
+/// * `x`, `y`, `width`, `height` - The tile within the rectangle.
+/// * `tile` - Receives the tile's pixels, row by row.
+pub(crate) fn extract_tile(
+    pixels: &[u32],
+    stride: usize,
+    (x, y, width, height): (usize, usize, usize, usize),
+    tile: &mut Vec<u32>,
+) {
+    tile.clear();
+    for row in y..y + height {
+        let start = row * stride + x;
+        tile.extend_from_slice(&pixels[start..start + width]);
+    }
+}
+
+/// Encodes one tile with the cheapest sub-encoding.
+///
+/// # Arguments
+///
+/// * `buf` - Receives the sub-encoding byte and the tile data.
+/// * `tile` - The tile's pixels, row by row.
+/// * `width` - Width of the tile in pixels.
+/// * `cpixel` - How pixels are written.
+pub(crate) fn encode_tile(buf: &mut BytesMut, tile: &[u32], width: usize, cpixel: CPixel) {
+    let height = tile.len() / width.max(1);
+
+    // Count runs and collect the palette in order of appearance; a tile with more
+    // colors than a palette holds has no usable palette
+    let mut runs = 0;
+    let mut single_pixels = 0;
+    let mut palette = Vec::with_capacity(MAX_PACKED_PALETTE);
+    let mut palette_full = false;
+    for run in tile.chunk_by(|a, b| a == b) {
+        if run.len() == 1 {
+            single_pixels += 1;
+        } else {
+            runs += 1;
+        }
+        if !palette_full && !palette.contains(&run[0]) {
+            palette_full = palette.len() == MAX_RLE_PALETTE;
+            palette.push(run[0]);
+        }
+    }
+
+    if palette.len() == 1 {
+        buf.put_u8(SUBENCODING_SOLID);
+        cpixel.write(buf, palette[0]);
+        return;
+    }
+
+    let size = cpixel.size();
+    let raw_bytes = tile.len() * size;
+    let rle_bytes = (size + 1) * (runs + single_pixels);
+    let (palette_rle_bytes, packed_bytes) = if palette_full {
+        (usize::MAX, usize::MAX)
+    } else {
+        let palette_bytes = size * palette.len();
+        let packed_bytes = if palette.len() <= MAX_PACKED_PALETTE {
+            let packed_row = (width * packed_bits(palette.len())).div_ceil(8);
+            palette_bytes + packed_row * height
+        } else {
+            usize::MAX
+        };
+        (palette_bytes + 2 * runs + single_pixels, packed_bytes)
+    };
+
+    let cheapest = raw_bytes
+        .min(rle_bytes)
+        .min(palette_rle_bytes)
+        .min(packed_bytes);
+    if cheapest == raw_bytes {
+        buf.put_u8(SUBENCODING_RAW);
+        for &pixel in tile {
+            cpixel.write(buf, pixel);
+        }
+    } else if cheapest == rle_bytes {
+        buf.put_u8(SUBENCODING_RLE);
+        for run in tile.chunk_by(|a, b| a == b) {
+            cpixel.write(buf, run[0]);
+            put_run_length(buf, run.len());
+        }
+    } else if cheapest == palette_rle_bytes {
+        write_palette(buf, SUBENCODING_RLE, &palette, cpixel);
+        for run in tile.chunk_by(|a, b| a == b) {
+            let index = palette_index(&palette, run[0]);
+            if run.len() == 1 {
+                buf.put_u8(index);
+            } else {
+                buf.put_u8(index | 0x80);
+                put_run_length(buf, run.len());
+            }
+        }
+    } else {
+        write_palette(buf, 0, &palette, cpixel);
+        let bits = packed_bits(palette.len());
+        for row in tile.chunks(width) {
+            let mut byte = 0u8;
+            let mut filled = 0;
+            for &pixel in row {
+                byte = byte << bits | palette_index(&palette, pixel);
+                filled += bits;
+                if filled == 8 {
+                    buf.put_u8(byte);
+                    byte = 0;
+                    filled = 0;
+                }
+            }
+            // Rows end on a byte boundary, padded at the low end
+            if filled > 0 {
+                buf.put_u8(byte << (8 - filled));
+            }
+        }
+    }
+}
+
+/// Returns the bits per pixel of a packed palette with `colors` entries.
+fn packed_bits(colors: usize) -> usize {
+    match colors {
+        0..=2 => 1,
+        3..=4 => 2,
+        _ => 4,
+    }
+}
+
+/// Writes a palette sub-encoding byte (`base` plus the palette size) and the palette.
+#[allow(clippy::cast_possible_truncation)] // Palettes have at most 127 colors
+fn write_palette(buf: &mut BytesMut, base: u8, palette: &[u32], cpixel: CPixel) {
+    buf.put_u8(base | palette.len() as u8);
+    for &color in palette {
+        cpixel.write(buf, color);
+    }
+}
+
+/// Returns the index of `pixel` in the tile's palette.
+#[allow(clippy::cast_possible_truncation)] // Palettes have at most 127 colors
+fn palette_index(palette: &[u32], pixel: u32) -> u8 {
+    palette
+        .iter()
+        .position(|&color| color == pixel)
+        .unwrap_or_default() as u8
+}
+
+/// Writes a run length: `length - 1` as a sum of bytes, where 255 means more follow.
+#[allow(clippy::cast_possible_truncation)] // The remainder is below 255
+fn put_run_length(buf: &mut BytesMut, length: usize) {
+    let mut remaining = length - 1;
+    while remaining >= 255 {
+        buf.put_u8(255);
+        remaining -= 255;
+    }
+    buf.put_u8(remaining as u8);
+}
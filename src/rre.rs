@@ -0,0 +1,171 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! RRE (Rise-and-Run-length Encoding, encoding 2) and `CoRRE` (encoding 4).
+//!
+//! A rectangle is its most common color followed by subrectangles of other colors:
+//!
+//! ```text
+//! u32    number of subrectangles
+//! PIXEL  background color
+//! then for every subrectangle:
+//! PIXEL  color
+//! u16    x, y, width, height (u8 for CoRRE)
+//! ```
+//!
+//! `CoRRE` positions and sizes fit in a byte, so its rectangles are at most 255 pixels
+//! wide and high. The encoders in `rfb-encodings` always write 32-bit little-endian
+//! pixels, so these are used instead: pixels follow the size and byte order the client
+//! negotiated.
+
+use std::collections::HashMap;
+
+use bytes::{BufMut, BytesMut};
+
+use crate::protocol::PixelFormat;
+use crate::rle_tile::{self, CPixel};
+
+/// A subrectangle: `(x, y, width, height, color)`.
+pub(crate) type Subrect = (usize, usize, usize, usize, u32);
+
+/// Encodes a rectangle with RRE.
+///
+/// # Arguments
+///
+/// * `data` - The rectangle's pixels, already translated to the client's pixel format.
+/// * `width`, `height` - Size of the rectangle.
+/// * `format` - The client's pixel format.
+/// * `buf` - Receives the encoded rectangle.
+pub(crate) fn encode_rre(
+    data: &[u8],
+    width: u16,
+    height: u16,
+    format: &PixelFormat,
+    buf: &mut BytesMut,
+) {
+    encode(data, width, height, format, buf, |buf, value| {
+        #[allow(clippy::cast_possible_truncation)] // Positions and sizes within the rectangle
+        buf.put_u16(value as u16);
+    });
+}
+
+/// Encodes a rectangle of at most 255x255 pixels with `CoRRE`.
+///
+/// # Arguments
+///
+/// * `data` - The rectangle's pixels, already translated to the client's pixel format.
+/// * `width`, `height` - Size of the rectangle.
+/// * `format` - The client's pixel format.
+/// * `buf` - Receives the encoded rectangle.
+pub(crate) fn encode_corre(
+    data: &[u8],
+    width: u16,
+    height: u16,
+    format: &PixelFormat,
+    buf: &mut BytesMut,
+) {
+    debug_assert!(width <= 255 && height <= 255);
+    encode(data, width, height, format, buf, |buf, value| {
+        #[allow(clippy::cast_possible_truncation)] // The rectangle is at most 255x255
+        buf.put_u8(value as u8);
+    });
+}
+
+/// Encodes a rectangle, writing subrectangle positions and sizes with `put_value`.
+fn encode(
+    data: &[u8],
+    width: u16,
+    height: u16,
+    format: &PixelFormat,
+    buf: &mut BytesMut,
+    put_value: impl Fn(&mut BytesMut, usize),
+) {
+    let (width, height) = (usize::from(width), usize::from(height));
+    let pixels = rle_tile::read_pixels(data, format);
+    let pixel = CPixel::full(format);
+
+    let mut counts = HashMap::new();
+    for &value in &pixels {
+        *counts.entry(value).or_insert(0usize) += 1;
+    }
+    let background = counts
+        .into_iter()
+        .max_by_key(|&(_, count)| count)
+        .map_or(0, |(color, _)| color);
+    let subrects =
+        find_subrects(&pixels, width, height, background, usize::MAX).unwrap_or_default();
+
+    #[allow(clippy::cast_possible_truncation)] // At most one subrectangle per pixel
+    buf.put_u32(subrects.len() as u32);
+    pixel.write(buf, background);
+    for &(x, y, w, h, color) in &subrects {
+        pixel.write(buf, color);
+        for value in [x, y, w, h] {
+            put_value(buf, value);
+        }
+    }
+}
+
+/// Covers the pixels that differ from `background` with single-color subrectangles,
+/// each a run on one row extended over the rows below that repeat it.
+///
+/// # Arguments
+///
+/// * `pixels` - The rectangle's pixels, row by row.
+/// * `width`, `height` - Size of the rectangle.
+/// * `background` - The color left uncovered.
+/// * `limit` - Maximum number of subrectangles.
+///
+/// # Returns
+///
+/// The subrectangles, or `None` if more than `limit` are needed.
+pub(crate) fn find_subrects(
+    pixels: &[u32],
+    width: usize,
+    height: usize,
+    background: u32,
+    limit: usize,
+) -> Option<Vec<Subrect>> {
+    let mut covered = vec![false; pixels.len()];
+    let mut subrects = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let color = pixels[y * width + x];
+            if color == background || covered[y * width + x] {
+                continue;
+            }
+            if subrects.len() == limit {
+                return None;
+            }
+
+            let run = pixels[y * width + x..(y + 1) * width]
+                .iter()
+                .take_while(|&&value| value == color)
+                .count();
+            let rows = 1
+                + (y + 1..height)
+                    .take_while(|&row| {
+                        pixels[row * width + x..row * width + x + run]
+                            .iter()
+                            .all(|&value| value == color)
+                    })
+                    .count();
+            for row in y..y + rows {
+                covered[row * width + x..row * width + x + run].fill(true);
+            }
+            subrects.push((x, y, run, rows, color));
+        }
+    }
+    Some(subrects)
+}
@@ -0,0 +1,528 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! RSA-AES security types (`RA2` and `RA2ne`, requires the `rsa-aes` feature).
+//!
+//! The handshake, as implemented by `RealVNC` and `TigerVNC`:
+//!
+//! 1. Both sides send their RSA public key: `u32` key length in bits, then the modulus
+//!    and the public exponent, each as many bytes as the modulus.
+//! 2. Both sides send 16 random bytes encrypted with the other's key (RSAES-PKCS1-v1.5),
+//!    prefixed with a `u16` length.
+//! 3. Each direction is encrypted with AES-128-EAX under its own key: client-to-server
+//!    with `SHA1(server random || client random)`, server-to-client with
+//!    `SHA1(client random || server random)`, truncated to 16 bytes.
+//! 4. Over the encrypted channel, each side sends the SHA-1 of its own key followed by
+//!    the other's (as sent in step 1), proving both saw the same keys.
+//! 5. The server sends the credential subtype and the client answers with a username
+//!    and password.
+//!
+//! Encrypted messages are framed as a `u16` plaintext length (authenticated but not
+//! encrypted), the ciphertext and a 16-byte tag, with a little-endian 128-bit counter
+//! as nonce that starts at zero in each direction. With `RA2` the channel stays in place
+//! for the rest of the session as an [`AesEaxStream`]; with `RA2ne` it only protects the
+//! credentials.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll};
+
+use aes::Aes128;
+use eax::aead::generic_array::GenericArray;
+use eax::aead::{AeadInPlace, KeyInit};
+use eax::Eax;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::traits::PublicKeyParts;
+use rsa::{BigUint, Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use crate::config::SecurityConfig;
+use crate::transport::ClientStream;
+
+/// Smallest client key accepted, in bits.
+const MIN_KEY_BITS: u32 = 1024;
+
+/// Largest client key accepted, in bits.
+const MAX_KEY_BITS: u32 = 8192;
+
+/// Size of the server key generated when none is configured, in bits.
+const GENERATED_KEY_BITS: usize = 2048;
+
+/// Size of each side's random and of the AES keys, in bytes.
+const RANDOM_SIZE: usize = 16;
+
+/// Largest plaintext sent in one encrypted message.
+const MAX_MESSAGE_SIZE: usize = 8192;
+
+/// Size of the EAX authentication tag.
+const TAG_SIZE: usize = 16;
+
+/// Returns the server's RSA key: the configured one, or one generated on first use.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if the configured key cannot be read or parsed, or key
+/// generation fails.
+pub(crate) async fn server_key(config: &SecurityConfig) -> Result<Arc<RsaPrivateKey>, io::Error> {
+    static GENERATED: OnceLock<Arc<RsaPrivateKey>> = OnceLock::new();
+
+    if let Some(path) = &config.rsa_private_key {
+        let invalid = |e: &dyn std::fmt::Display| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Failed to load RSA key {}: {e}", path.display()),
+            )
+        };
+        let pem = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        let key = RsaPrivateKey::from_pkcs8_pem(&pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&pem))
+            .map_err(|e| invalid(&e))?;
+        return Ok(Arc::new(key));
+    }
+
+    if let Some(key) = GENERATED.get() {
+        return Ok(key.clone());
+    }
+    let key = tokio::task::spawn_blocking(|| {
+        RsaPrivateKey::new(&mut rand::thread_rng(), GENERATED_KEY_BITS)
+    })
+    .await
+    .map_err(io::Error::other)?
+    .map_err(io::Error::other)?;
+    log::info!("Generated a {GENERATED_KEY_BITS}-bit RSA key for RSA-AES");
+    Ok(GENERATED.get_or_init(|| Arc::new(key)).clone())
+}
+
+/// Encodes a public key as sent in the handshake.
+fn encode_public_key(key: &impl PublicKeyParts) -> Vec<u8> {
+    let size = key.size();
+    let mut encoded = Vec::with_capacity(4 + 2 * size);
+    #[allow(clippy::cast_possible_truncation)] // Keys are at most MAX_KEY_BITS
+    encoded.extend_from_slice(&((size * 8) as u32).to_be_bytes());
+    for value in [key.n(), key.e()] {
+        let bytes = value.to_bytes_be();
+        encoded.resize(encoded.len() + size - bytes.len(), 0);
+        encoded.extend_from_slice(&bytes);
+    }
+    encoded
+}
+
+/// SHA-1 of the concatenation of `parts`.
+fn sha1(parts: &[&[u8]]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Returns an error for a malformed or inconsistent handshake.
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Runs the RSA key exchange and sets up the encrypted channel (steps 1 to 4).
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if the client's key or random is invalid, the key
+/// hashes don't match (the keys were tampered with), or the stream fails.
+pub(crate) async fn handshake(
+    mut stream: Box<dyn ClientStream>,
+    server_key: &RsaPrivateKey,
+) -> Result<AesEaxStream<Box<dyn ClientStream>>, io::Error> {
+    let server_public = encode_public_key(server_key);
+    stream.write_all(&server_public).await?;
+
+    let client_bits = stream.read_u32().await?;
+    if !(MIN_KEY_BITS..=MAX_KEY_BITS).contains(&client_bits) {
+        return Err(invalid_data(format!(
+            "Unsupported RSA-AES client key length {client_bits}"
+        )));
+    }
+    let size = (client_bits as usize).div_ceil(8);
+    let mut client_public = vec![0u8; 4 + 2 * size];
+    client_public[..4].copy_from_slice(&client_bits.to_be_bytes());
+    stream.read_exact(&mut client_public[4..]).await?;
+    let client_key = RsaPublicKey::new_with_max_size(
+        BigUint::from_bytes_be(&client_public[4..4 + size]),
+        BigUint::from_bytes_be(&client_public[4 + size..]),
+        MAX_KEY_BITS as usize,
+    )
+    .map_err(|e| invalid_data(format!("Invalid RSA-AES client key: {e}")))?;
+
+    let (server_random, encrypted) = {
+        let mut rng = rand::thread_rng();
+        let server_random: [u8; RANDOM_SIZE] = rand::Rng::gen(&mut rng);
+        let encrypted = client_key
+            .encrypt(&mut rng, Pkcs1v15Encrypt, &server_random)
+            .map_err(io::Error::other)?;
+        (server_random, encrypted)
+    };
+    #[allow(clippy::cast_possible_truncation)] // At most MAX_KEY_BITS / 8 bytes
+    stream.write_u16(encrypted.len() as u16).await?;
+    stream.write_all(&encrypted).await?;
+
+    let length = usize::from(stream.read_u16().await?);
+    if length != server_key.size() {
+        return Err(invalid_data(format!(
+            "RSA-AES client random has length {length}, expected {}",
+            server_key.size()
+        )));
+    }
+    let mut encrypted = vec![0u8; length];
+    stream.read_exact(&mut encrypted).await?;
+    let client_random = server_key
+        .decrypt(Pkcs1v15Encrypt, &encrypted)
+        .map_err(|_| invalid_data("Failed to decrypt RSA-AES client random"))?;
+    if client_random.len() != RANDOM_SIZE {
+        return Err(invalid_data("RSA-AES client random has the wrong size"));
+    }
+
+    let (server_to_client, client_to_server) = session_keys(&server_random, &client_random);
+    let mut channel = AesEaxStream::new(stream, &server_to_client, &client_to_server);
+
+    channel
+        .write_all(&sha1(&[&server_public, &client_public]))
+        .await?;
+    let mut client_hash = [0u8; 20];
+    channel.read_exact(&mut client_hash).await?;
+    if client_hash != sha1(&[&client_public, &server_public]) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "RSA-AES key hash mismatch",
+        ));
+    }
+    Ok(channel)
+}
+
+/// Derives the channel's AES keys from the two randoms (step 4): the first 16 bytes of
+/// SHA-1 of the randoms, the receiver's first.
+///
+/// # Returns
+///
+/// The server-to-client key and the client-to-server key.
+fn session_keys(
+    server_random: &[u8],
+    client_random: &[u8],
+) -> ([u8; RANDOM_SIZE], [u8; RANDOM_SIZE]) {
+    let key = |first: &[u8], second: &[u8]| {
+        let mut key = [0u8; RANDOM_SIZE];
+        key.copy_from_slice(&sha1(&[first, second])[..RANDOM_SIZE]);
+        key
+    };
+    (
+        key(client_random, server_random),
+        key(server_random, client_random),
+    )
+}
+
+/// A stream encrypted with AES-128-EAX messages, as set up by [`handshake`].
+///
+/// Like TLS streams, a write that returned `Pending` must be retried with the same data.
+pub(crate) struct AesEaxStream<S> {
+    inner: S,
+    encryptor: Eax<Aes128>,
+    decryptor: Eax<Aes128>,
+    write_nonce: [u8; 16],
+    read_nonce: [u8; 16],
+    /// Bytes read from `inner` that don't form a complete message yet.
+    incoming: Vec<u8>,
+    /// Decrypted bytes not yet returned to the reader, from `plaintext_pos`.
+    plaintext: Vec<u8>,
+    plaintext_pos: usize,
+    /// The encrypted message being written, from `outgoing_pos`.
+    outgoing: Vec<u8>,
+    outgoing_pos: usize,
+    /// Number of caller bytes `outgoing` carries.
+    outgoing_len: usize,
+}
+
+impl<S> AesEaxStream<S> {
+    /// Wraps `inner`, encrypting with `write_key` and decrypting with `read_key`.
+    fn new(inner: S, write_key: &[u8], read_key: &[u8]) -> Self {
+        Self {
+            inner,
+            encryptor: Eax::new(GenericArray::from_slice(write_key)),
+            decryptor: Eax::new(GenericArray::from_slice(read_key)),
+            write_nonce: [0; 16],
+            read_nonce: [0; 16],
+            incoming: Vec::new(),
+            plaintext: Vec::new(),
+            plaintext_pos: 0,
+            outgoing: Vec::new(),
+            outgoing_pos: 0,
+            outgoing_len: 0,
+        }
+    }
+
+    /// Returns the underlying stream, for `RA2ne` sessions that continue unencrypted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the client already sent data that was meant for
+    /// the encrypted channel.
+    pub(crate) fn into_inner(self) -> Result<S, io::Error> {
+        if !self.incoming.is_empty() || self.plaintext_pos < self.plaintext.len() {
+            return Err(invalid_data("Unexpected data on the RSA-AES channel"));
+        }
+        Ok(self.inner)
+    }
+
+    /// Encrypts `data` as the next outgoing message.
+    fn encrypt_message(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        #[allow(clippy::cast_possible_truncation)] // At most MAX_MESSAGE_SIZE
+        let header = (data.len() as u16).to_be_bytes();
+        let mut message = Vec::with_capacity(2 + data.len() + TAG_SIZE);
+        message.extend_from_slice(&header);
+        message.extend_from_slice(data);
+        let tag = self
+            .encryptor
+            .encrypt_in_place_detached(
+                GenericArray::from_slice(&self.write_nonce),
+                &header,
+                &mut message[2..],
+            )
+            .map_err(|_| io::Error::other("RSA-AES encryption failed"))?;
+        message.extend_from_slice(&tag);
+        increment(&mut self.write_nonce);
+
+        self.outgoing = message;
+        self.outgoing_pos = 0;
+        self.outgoing_len = data.len();
+        Ok(())
+    }
+
+    /// Decrypts the first message in `incoming` if it is complete.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if a message was decrypted into `plaintext`.
+    fn decrypt_message(&mut self) -> Result<bool, io::Error> {
+        if self.incoming.len() < 2 {
+            return Ok(false);
+        }
+        let length = usize::from(u16::from_be_bytes([self.incoming[0], self.incoming[1]]));
+        let end = 2 + length + TAG_SIZE;
+        if self.incoming.len() < end {
+            return Ok(false);
+        }
+
+        let header = [self.incoming[0], self.incoming[1]];
+        let mut data = self.incoming[2..2 + length].to_vec();
+        self.decryptor
+            .decrypt_in_place_detached(
+                GenericArray::from_slice(&self.read_nonce),
+                &header,
+                &mut data,
+                GenericArray::from_slice(&self.incoming[2 + length..end]),
+            )
+            .map_err(|_| invalid_data("RSA-AES message failed authentication"))?;
+        increment(&mut self.read_nonce);
+        self.incoming.drain(..end);
+        self.plaintext = data;
+        self.plaintext_pos = 0;
+        Ok(true)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AesEaxStream<S> {
+    /// Writes the rest of the outgoing message to `inner`.
+    fn poll_write_outgoing(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        while self.outgoing_pos < self.outgoing.len() {
+            let written = ready!(
+                Pin::new(&mut self.inner).poll_write(cx, &self.outgoing[self.outgoing_pos..])
+            )?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.outgoing_pos += written;
+        }
+        self.outgoing.clear();
+        self.outgoing_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Increments a little-endian 128-bit nonce.
+fn increment(nonce: &mut [u8; 16]) {
+    for byte in nonce {
+        *byte = byte.wrapping_add(1);
+        if *byte != 0 {
+            break;
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for AesEaxStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.plaintext_pos < this.plaintext.len() {
+                let available = &this.plaintext[this.plaintext_pos..];
+                let count = available.len().min(buf.remaining());
+                buf.put_slice(&available[..count]);
+                this.plaintext_pos += count;
+                return Poll::Ready(Ok(()));
+            }
+            if this.decrypt_message()? {
+                continue;
+            }
+
+            let mut chunk = [0u8; 4096];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                if this.incoming.is_empty() {
+                    return Poll::Ready(Ok(())); // Clean end of stream
+                }
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            this.incoming.extend_from_slice(chunk_buf.filled());
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for AesEaxStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.outgoing.is_empty() {
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            this.encrypt_message(&buf[..buf.len().min(MAX_MESSAGE_SIZE)])?;
+        }
+        ready!(this.poll_write_outgoing(cx))?;
+        Poll::Ready(Ok(this.outgoing_len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_outgoing(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_outgoing(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys derived from the randoms `00..0f` (server) and `10..1f` (client).
+    fn test_keys() -> ([u8; RANDOM_SIZE], [u8; RANDOM_SIZE]) {
+        let server_random: Vec<u8> = (0..16).collect();
+        let client_random: Vec<u8> = (16..32).collect();
+        session_keys(&server_random, &client_random)
+    }
+
+    fn unhex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn session_keys_match_known_answer() {
+        let (server_to_client, client_to_server) = test_keys();
+        assert_eq!(
+            server_to_client[..],
+            unhex("b97cd424c4711eb518790ce07939ebac")[..]
+        );
+        assert_eq!(
+            client_to_server[..],
+            unhex("ae5bd8efea5322c4d9986d06680a7813")[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn messages_match_known_answer() {
+        let (server_to_client, client_to_server) = test_keys();
+        let (server_end, mut client_end) = tokio::io::duplex(1024);
+        let mut server = AesEaxStream::new(server_end, &server_to_client, &client_to_server);
+
+        // Length, ciphertext and tag; the nonce counts messages
+        server.write_all(b"RFB").await.unwrap();
+        server.write_all(b"RFB").await.unwrap();
+        let mut wire = [0u8; 2 * (2 + 3 + TAG_SIZE)];
+        client_end.read_exact(&mut wire).await.unwrap();
+        assert_eq!(
+            wire[..21],
+            unhex("00037121f8604f44b1b69fdbd4df260a0c2496ee2f")[..]
+        );
+        assert_eq!(
+            wire[21..],
+            unhex("0003af193d7e9f0a0fee75c034a3baeee78d680161")[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn large_writes_round_trip() {
+        let (server_to_client, client_to_server) = test_keys();
+        let (server_end, client_end) = tokio::io::duplex(4096);
+        let mut server = AesEaxStream::new(server_end, &server_to_client, &client_to_server);
+        let mut client = AesEaxStream::new(client_end, &client_to_server, &server_to_client);
+
+        // More than one message's worth in each direction
+        let data: Vec<u8> = (0..=255).cycle().take(3 * MAX_MESSAGE_SIZE + 5).collect();
+        let mut received = vec![0u8; data.len()];
+        let (written, read) =
+            tokio::join!(client.write_all(&data), server.read_exact(&mut received));
+        written.unwrap();
+        read.unwrap();
+        assert_eq!(received, data);
+
+        let (written, read) =
+            tokio::join!(server.write_all(&data), client.read_exact(&mut received));
+        written.unwrap();
+        read.unwrap();
+        assert_eq!(received, data);
+    }
+
+    #[tokio::test]
+    async fn tampered_message_is_rejected() {
+        let (server_to_client, client_to_server) = test_keys();
+        let (server_end, mut wire_end) = tokio::io::duplex(1024);
+        let mut server = AesEaxStream::new(server_end, &server_to_client, &client_to_server);
+        server.write_all(b"RFB").await.unwrap();
+        let mut message = [0u8; 2 + 3 + TAG_SIZE];
+        wire_end.read_exact(&mut message).await.unwrap();
+
+        // The length (authenticated as the header), the ciphertext and the tag
+        for index in [1, 3, message.len() - 1] {
+            let mut tampered = message;
+            tampered[index] ^= 1;
+            let mut client = AesEaxStream::new(&tampered[..], &client_to_server, &server_to_client);
+            let mut plaintext = [0u8; 3];
+            let error = client.read_exact(&mut plaintext).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SASL authentication through Cyrus SASL (requires the `sasl` feature).
+//!
+//! The mechanisms, and where they check credentials, come from the system's Cyrus SASL
+//! configuration: the application name is `vnc`, so the settings are read from
+//! `vnc.conf` in the SASL configuration directory (usually `/etc/sasl2`). `GSSAPI`
+//! authenticates Kerberos principals with the `vnc/<host>` service key from the keytab,
+//! and `PLAIN` checks passwords through `saslauthd`, typically against PAM.
+//!
+//! SASL security layers are not negotiated, so a session is only encrypted when it runs
+//! inside TLS. Cyrus SASL calls may block (a keytab lookup, a PAM module delaying a
+//! failed login), so they run on Tokio's blocking thread pool.
+
+use std::ffi::{c_char, c_uint, c_void, CStr, CString};
+use std::io;
+use std::ptr;
+use std::sync::OnceLock;
+
+use sasl2_sys::sasl::{
+    sasl_conn_t, sasl_dispose, sasl_errdetail, sasl_errstring, sasl_getprop, sasl_listmech,
+    sasl_security_properties_t, sasl_server_init, sasl_server_new, sasl_server_start,
+    sasl_server_step, sasl_setprop, SASL_CONTINUE, SASL_OK, SASL_SEC_NOANONYMOUS,
+    SASL_SEC_NOPLAINTEXT, SASL_SEC_PROPS, SASL_USERNAME,
+};
+
+/// The application name Cyrus SASL reads its configuration file for.
+const APP_NAME: &CStr = c"vnc";
+
+/// The outcome of one step of a SASL exchange.
+pub(crate) enum Step {
+    /// The mechanism needs another message from the client; holds the challenge to send.
+    Continue(Vec<u8>),
+    /// The client is authenticated; holds the final data to send, if any.
+    Done(Vec<u8>),
+    /// Authentication failed; holds the reason.
+    Failed(String),
+}
+
+/// A Cyrus SASL server connection.
+struct Connection(*mut sasl_conn_t);
+
+// The connection is only used by one thread at a time
+unsafe impl Send for Connection {}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // SAFETY: The pointer came from `sasl_server_new` and is disposed once
+        unsafe { sasl_dispose(&raw mut self.0) };
+    }
+}
+
+impl Connection {
+    /// The last error of this connection, with details from the mechanism.
+    fn error_detail(&self) -> String {
+        // SAFETY: The connection is valid; the string is owned by it
+        unsafe { CStr::from_ptr(sasl_errdetail(self.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Converts the result of `sasl_server_start` or `sasl_server_step`.
+    fn step_result(&self, result: i32, out: *const c_char, out_len: c_uint) -> Step {
+        let data = if out.is_null() {
+            Vec::new()
+        } else {
+            // SAFETY: Cyrus SASL returned `out_len` bytes at `out`, valid until the next call
+            unsafe { std::slice::from_raw_parts(out.cast::<u8>(), out_len as usize) }.to_vec()
+        };
+        match result {
+            SASL_OK => Step::Done(data),
+            SASL_CONTINUE => Step::Continue(data),
+            _ => Step::Failed(self.error_detail()),
+        }
+    }
+}
+
+/// Initializes the Cyrus SASL server library once per process.
+fn init() -> Result<(), io::Error> {
+    static INIT: OnceLock<Result<(), String>> = OnceLock::new();
+    INIT.get_or_init(|| {
+        // SAFETY: No callbacks are registered; the name is a static C string
+        let result = unsafe { sasl_server_init(ptr::null(), APP_NAME.as_ptr()) };
+        if result == SASL_OK {
+            Ok(())
+        } else {
+            // SAFETY: `sasl_errstring` returns a static string for any code
+            let reason =
+                unsafe { CStr::from_ptr(sasl_errstring(result, ptr::null(), ptr::null_mut())) };
+            Err(format!(
+                "Cyrus SASL initialization failed: {}",
+                reason.to_string_lossy()
+            ))
+        }
+    })
+    .clone()
+    .map_err(io::Error::other)
+}
+
+/// One client's SASL exchange.
+pub(crate) struct SaslServer {
+    /// The connection, moved to the blocking thread pool during each call.
+    connection: Option<Connection>,
+}
+
+impl SaslServer {
+    /// Starts a SASL exchange for the given service.
+    ///
+    /// # Arguments
+    ///
+    /// * `service` - The service name, e.g. `vnc` for the `vnc/<host>` Kerberos principal.
+    /// * `encrypted` - Whether the stream is encrypted; without it, mechanisms that send
+    ///   passwords in the clear (`PLAIN`, `LOGIN`) are disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if Cyrus SASL cannot be initialized or the service
+    /// name is invalid.
+    pub(crate) fn new(service: &str, encrypted: bool) -> Result<Self, io::Error> {
+        init()?;
+        let service = CString::new(service)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid SASL service"))?;
+        let mut conn = ptr::null_mut();
+        // SAFETY: All strings are valid C strings or null; `conn` receives the connection
+        let result = unsafe {
+            sasl_server_new(
+                service.as_ptr(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                0,
+                &raw mut conn,
+            )
+        };
+        if result != SASL_OK || conn.is_null() {
+            return Err(io::Error::other(format!(
+                "Cyrus SASL connection setup failed (code {result})"
+            )));
+        }
+        let connection = Connection(conn);
+
+        let properties = sasl_security_properties_t {
+            min_ssf: 0,
+            // No security layer; confidentiality comes from TLS, if any
+            max_ssf: 0,
+            maxbufsize: 0,
+            security_flags: if encrypted {
+                SASL_SEC_NOANONYMOUS
+            } else {
+                SASL_SEC_NOANONYMOUS | SASL_SEC_NOPLAINTEXT
+            },
+            property_names: ptr::null_mut(),
+            property_values: ptr::null_mut(),
+        };
+        // SAFETY: Cyrus SASL copies the properties
+        let result = unsafe {
+            sasl_setprop(
+                connection.0,
+                SASL_SEC_PROPS.cast_signed(),
+                (&raw const properties).cast::<c_void>(),
+            )
+        };
+        if result != SASL_OK {
+            return Err(io::Error::other(connection.error_detail()));
+        }
+        Ok(Self {
+            connection: Some(connection),
+        })
+    }
+
+    /// Runs `f` on the connection in the blocking thread pool.
+    async fn blocking<R: Send + 'static>(
+        &mut self,
+        f: impl FnOnce(&Connection) -> R + Send + 'static,
+    ) -> Result<R, io::Error> {
+        let connection = self
+            .connection
+            .take()
+            .ok_or_else(|| io::Error::other("SASL connection lost"))?;
+        let (connection, result) = tokio::task::spawn_blocking(move || {
+            let result = f(&connection);
+            (connection, result)
+        })
+        .await
+        .map_err(io::Error::other)?;
+        self.connection = Some(connection);
+        Ok(result)
+    }
+
+    /// Returns the mechanisms available to this client, separated by commas.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if Cyrus SASL cannot list them.
+    pub(crate) async fn mechanisms(&mut self) -> Result<String, io::Error> {
+        self.blocking(|connection| {
+            let mut list = ptr::null();
+            let mut len = 0;
+            let mut count = 0;
+            // SAFETY: The connection is valid; `list` is owned by it
+            let result = unsafe {
+                sasl_listmech(
+                    connection.0,
+                    ptr::null(),
+                    c"".as_ptr(),
+                    c",".as_ptr(),
+                    c"".as_ptr(),
+                    &raw mut list,
+                    &raw mut len,
+                    &raw mut count,
+                )
+            };
+            if result != SASL_OK || list.is_null() {
+                return Err(io::Error::other(connection.error_detail()));
+            }
+            // SAFETY: `list` is a NUL-terminated string owned by the connection
+            Ok(unsafe { CStr::from_ptr(list) }
+                .to_string_lossy()
+                .into_owned())
+        })
+        .await?
+    }
+
+    /// Starts authentication with `mechanism`, using the client's initial response.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the blocking call cannot run.
+    pub(crate) async fn start(
+        &mut self,
+        mechanism: String,
+        input: Option<Vec<u8>>,
+    ) -> Result<Step, io::Error> {
+        self.blocking(move |connection| {
+            let Ok(mechanism) = CString::new(mechanism) else {
+                return Step::Failed("Invalid SASL mechanism name".into());
+            };
+            let (input, input_len) = input_ptr(input.as_deref());
+            let mut out = ptr::null();
+            let mut out_len = 0;
+            // SAFETY: The input and mechanism outlive the call; the output is owned by the
+            // connection
+            let result = unsafe {
+                sasl_server_start(
+                    connection.0,
+                    mechanism.as_ptr(),
+                    input,
+                    input_len,
+                    &raw mut out,
+                    &raw mut out_len,
+                )
+            };
+            connection.step_result(result, out, out_len)
+        })
+        .await
+    }
+
+    /// Continues authentication with the client's next response.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the blocking call cannot run.
+    pub(crate) async fn step(&mut self, input: Option<Vec<u8>>) -> Result<Step, io::Error> {
+        self.blocking(move |connection| {
+            let (input, input_len) = input_ptr(input.as_deref());
+            let mut out = ptr::null();
+            let mut out_len = 0;
+            // SAFETY: The input outlives the call; the output is owned by the connection
+            let result = unsafe {
+                sasl_server_step(
+                    connection.0,
+                    input,
+                    input_len,
+                    &raw mut out,
+                    &raw mut out_len,
+                )
+            };
+            connection.step_result(result, out, out_len)
+        })
+        .await
+    }
+
+    /// Returns the name the client authenticated as, once authentication is done.
+    pub(crate) fn username(&self) -> Option<String> {
+        let connection = self.connection.as_ref()?;
+        let mut value: *const c_void = ptr::null();
+        // SAFETY: The connection is valid; the value is a string owned by it
+        let result =
+            unsafe { sasl_getprop(connection.0, SASL_USERNAME.cast_signed(), &raw mut value) };
+        if result != SASL_OK || value.is_null() {
+            return None;
+        }
+        // SAFETY: `SASL_USERNAME` is a NUL-terminated string
+        Some(
+            unsafe { CStr::from_ptr(value.cast::<c_char>()) }
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
+
+/// The pointer and length to pass for optional client data.
+fn input_ptr(input: Option<&[u8]>) -> (*const c_char, c_uint) {
+    match input {
+        #[allow(clippy::cast_possible_truncation)] // Limited by the protocol reader
+        Some(data) => (data.as_ptr().cast::<c_char>(), data.len() as c_uint),
+        None => (ptr::null(), 0),
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scroll detection for automatic `CopyRect`.
+//!
+//! When the framebuffer is updated, the rows of the changed band are hashed in the
+//! previous and the new frame. Rows that occur only once in the previous band vote for
+//! the vertical offset at which they reappear; repeated rows such as blank lines would
+//! vote for every offset. The winning offset is verified byte by byte, and its longest
+//! run of matching rows is sent as a `CopyRect` while the rest of the band stays dirty.
+
+use std::collections::HashMap;
+
+/// Fewest rows a scroll must move to be sent as a copy.
+const MIN_SCROLL_ROWS: usize = 16;
+
+/// Narrowest band, in bytes, checked for scrolling (32 RGBA32 pixels).
+const MIN_SCROLL_ROW_BYTES: usize = 32 * 4;
+
+/// Fewest unique rows that must agree on an offset.
+const MIN_VOTES: usize = 4;
+
+/// A vertical scroll found in a band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Scroll {
+    /// First destination row, relative to the band.
+    pub(crate) top: usize,
+    /// Number of rows copied.
+    pub(crate) rows: usize,
+    /// Offset from destination to source rows (`src_y = dest_y + dy`).
+    pub(crate) dy: isize,
+}
+
+/// Looks for a vertical scroll between two versions of a band.
+///
+/// # Arguments
+///
+/// * `old_rows` - The band's rows in the previous frame.
+/// * `new_rows` - The same rows in the new frame.
+///
+/// # Returns
+///
+/// The largest block of rows the new frame copies from another position of the previous
+/// one, or `None` if the band didn't scroll.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)] // Row indices of a u16-sized band
+pub(crate) fn detect_vertical_scroll(old_rows: &[&[u8]], new_rows: &[&[u8]]) -> Option<Scroll> {
+    let height = old_rows.len();
+    if height <= MIN_SCROLL_ROWS
+        || new_rows.len() != height
+        || old_rows[0].len() < MIN_SCROLL_ROW_BYTES
+    {
+        return None;
+    }
+
+    let old_hashes: Vec<u64> = old_rows.iter().map(|row| row_hash(row)).collect();
+    let new_hashes: Vec<u64> = new_rows.iter().map(|row| row_hash(row)).collect();
+
+    let mut positions: HashMap<u64, Option<usize>> = HashMap::with_capacity(height);
+    for (y, &hash) in old_hashes.iter().enumerate() {
+        positions
+            .entry(hash)
+            .and_modify(|position| *position = None)
+            .or_insert(Some(y));
+    }
+    let mut votes: HashMap<isize, usize> = HashMap::new();
+    for (y, hash) in new_hashes.iter().enumerate() {
+        if let Some(&Some(source)) = positions.get(hash) {
+            if source != y {
+                *votes.entry(source as isize - y as isize).or_default() += 1;
+            }
+        }
+    }
+    // Ties go to the smaller offset
+    let (dy, count) = votes
+        .into_iter()
+        .max_by_key(|&(dy, count)| (count, -dy.abs()))?;
+    if count < MIN_VOTES {
+        return None;
+    }
+
+    let mut best = Scroll {
+        top: 0,
+        rows: 0,
+        dy,
+    };
+    let mut run_start = None;
+    for y in 0..=height {
+        let source = y as isize + dy;
+        let matches = y < height
+            && (0..height as isize).contains(&source)
+            && new_hashes[y] == old_hashes[source as usize]
+            && new_rows[y] == old_rows[source as usize];
+        match (matches, run_start) {
+            (true, None) => run_start = Some(y),
+            (false, Some(start)) => {
+                if y - start > best.rows {
+                    best.top = start;
+                    best.rows = y - start;
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+    (best.rows >= MIN_SCROLL_ROWS).then_some(best)
+}
+
+/// Hashes a row of pixels; collisions only cost a byte comparison.
+fn row_hash(row: &[u8]) -> u64 {
+    const SEED: u64 = 0x517c_c1b7_2722_0a95;
+    let mut words = row.chunks_exact(8);
+    let mut hash = 0u64;
+    for word in &mut words {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(word);
+        hash = (hash.rotate_left(5) ^ u64::from_le_bytes(bytes)).wrapping_mul(SEED);
+    }
+    for &byte in words.remainder() {
+        hash = (hash.rotate_left(5) ^ u64::from(byte)).wrapping_mul(SEED);
+    }
+    hash
+}
@@ -0,0 +1,731 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Security type negotiation (RFC 6143 Section 7.1.2).
+//!
+//! The security phase of the handshake runs as a state machine over the client stream.
+//! Each state performs one step of a security type and names the next one, so a type
+//! that wraps another, such as `VeNCrypt` upgrading the stream to TLS before
+//! authenticating, is a chain of states rather than a nested branch:
+//!
+//! ```text
+//! Offer ──► Authenticate(None | VncAuth) ───────────────────────────► Result ──► Done
+//!   │                                                                   ▲
+//!   ├──► VeNCrypt ──► Tls ──► Authenticate(TlsNone | TlsVnc | X509*) ───┤
+//!   │                  │                                                │
+//!   │                  └────► Sasl(TlsSasl | X509Sasl) ─────────────────┤
+//!   │                                                                   │
+//!   ├──► Sasl(Sasl) ────────────────────────────────────────────────────┤
+//!   │                                                                   │
+//!   ├──► MsLogon ───────────────────────────────────────────────────────┤
+//!   │                                                                   │
+//!   └──► RsaAes(Ra2 | Ra2ne) ───────────────────────────────────────────┘
+//! ```
+//!
+//! A state may replace the stream (a TLS handshake or the RA2 channel wraps it), and
+//! every later state and the rest of the session run over the replacement. Once the
+//! client has authenticated, `ConnectionConfig::approver` may still refuse it or make it
+//! view-only. Failures send a failed `SecurityResult` with a reason where the protocol has
+//! one and end the handshake with `PermissionDenied`.
+
+use bytes::{BufMut, BytesMut};
+#[cfg(feature = "tls")]
+use openssl::ssl::SslContext;
+use rand::Rng;
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use zeroize::Zeroizing;
+
+use crate::authenticator::{Approval, AuthDecision, Authenticator, ConnectionRequest};
+use crate::client::ClientEvent;
+use crate::config::ServerConfig;
+use crate::ms_logon::{self, KeyExchange};
+use crate::network;
+#[cfg(feature = "sasl")]
+use crate::protocol::SECURITY_TYPE_SASL;
+use crate::protocol::{SECURITY_RESULT_FAILED, SECURITY_RESULT_OK, SECURITY_TYPE_MS_LOGON_II};
+#[cfg(feature = "rsa-aes")]
+use crate::protocol::{SECURITY_TYPE_RA2, SECURITY_TYPE_RA2NE};
+#[cfg(feature = "tls")]
+use crate::protocol::{
+    SECURITY_TYPE_VENCRYPT, VENCRYPT_TLS_NONE, VENCRYPT_TLS_SASL, VENCRYPT_TLS_VNC,
+    VENCRYPT_X509_NONE, VENCRYPT_X509_SASL, VENCRYPT_X509_VNC,
+};
+#[cfg(feature = "rsa-aes")]
+use crate::rsa_aes;
+#[cfg(feature = "sasl")]
+use crate::sasl::{SaslServer, Step};
+use crate::server::AuthMethod;
+#[cfg(feature = "tls")]
+use crate::tls;
+use crate::transport::ClientStream;
+
+/// A step of the security handshake.
+enum State {
+    /// Send the offered security types and read the client's choice.
+    Offer,
+    /// Negotiate the `VeNCrypt` version and subtype (security type 19).
+    #[cfg(feature = "tls")]
+    VeNCrypt,
+    /// Upgrade the stream with a TLS handshake for the given `VeNCrypt` method (anonymous
+    /// or with the server certificate), then authenticate with it inside TLS.
+    #[cfg(feature = "tls")]
+    Tls(AuthMethod),
+    /// Exchange RSA keys, set up the AES channel and check the credentials sent over it
+    /// (security types 5 and 6).
+    #[cfg(feature = "rsa-aes")]
+    RsaAes(AuthMethod),
+    /// Run a SASL exchange through Cyrus SASL (security type 20, or inside TLS).
+    #[cfg(feature = "sasl")]
+    Sasl(AuthMethod),
+    /// Exchange keys and check the account login of MS-Logon II (security type 113).
+    MsLogon,
+    /// Run the challenge-response authentication of the given method, if any.
+    Authenticate(AuthMethod),
+    /// Report the outcome of authentication and send the `SecurityResult`.
+    Result {
+        /// The method the client authenticated with.
+        method: AuthMethod,
+        /// Whether authentication succeeded.
+        success: bool,
+    },
+    /// The client is authenticated and approved.
+    Done {
+        /// The method the client authenticated with.
+        method: AuthMethod,
+        /// Whether the approver made the client view-only.
+        view_only: bool,
+    },
+}
+
+/// The result of a successful security handshake.
+pub(crate) struct Authenticated {
+    /// The stream the session continues on, possibly wrapped in TLS.
+    pub(crate) stream: Box<dyn ClientStream>,
+    /// The method the client authenticated with.
+    pub(crate) method: AuthMethod,
+    /// The subject of the client's verified TLS certificate, if it presented one.
+    pub(crate) certificate_subject: Option<String>,
+    /// Whether the client's input is dropped.
+    pub(crate) view_only: bool,
+}
+
+/// The parameters of one client's security handshake.
+pub(crate) struct Security<'a> {
+    /// Peer description, for policy checks, logs and events.
+    remote_host: &'a str,
+    /// The repeater ID, for connections made through a repeater.
+    repeater_id: Option<&'a str>,
+    /// Checks the client's credentials and decides which types can be offered.
+    authenticator: &'a dyn Authenticator,
+    /// Server configuration.
+    config: &'a ServerConfig,
+    /// Whether the transport below the stream is already encrypted.
+    secure_transport: bool,
+    /// Channel for `AuthAttempt` events.
+    event_tx: &'a mpsc::UnboundedSender<ClientEvent>,
+    /// TLS context with the server certificate, if one is configured and loads.
+    #[cfg(feature = "tls")]
+    certificate: Option<SslContext>,
+}
+
+impl<'a> Security<'a> {
+    /// Prepares the security handshake of a client, loading the configured TLS
+    /// certificate.
+    ///
+    /// # Arguments
+    ///
+    /// * `remote_host` - A description of the peer, used for policy checks, logs and events.
+    /// * `repeater_id` - The repeater ID, for connections made through a repeater.
+    /// * `authenticator` - Checks the client's credentials.
+    /// * `config` - Server configuration.
+    /// * `secure_transport` - Whether the stream is already encrypted by its transport.
+    /// * `event_tx` - Channel for `AuthAttempt` events.
+    pub(crate) fn new(
+        remote_host: &'a str,
+        repeater_id: Option<&'a str>,
+        authenticator: &'a dyn Authenticator,
+        config: &'a ServerConfig,
+        secure_transport: bool,
+        event_tx: &'a mpsc::UnboundedSender<ClientEvent>,
+    ) -> Self {
+        #[cfg(feature = "tls")]
+        let certificate = tls::certificate_context(&config.security).unwrap_or_else(|e| {
+            log::error!("{e}; not offering the X.509 security types");
+            None
+        });
+        Self {
+            remote_host,
+            repeater_id,
+            authenticator,
+            config,
+            secure_transport,
+            event_tx,
+            #[cfg(feature = "tls")]
+            certificate,
+        }
+    }
+
+    /// Runs the security handshake to completion.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the client fails authentication or is refused by
+    /// the approver, picks a security type that was not offered, no security type can be offered under the encryption
+    /// policy, or the stream fails.
+    pub(crate) async fn negotiate(
+        &self,
+        mut stream: Box<dyn ClientStream>,
+    ) -> Result<Authenticated, io::Error> {
+        #[allow(unused_mut)] // Only set by TLS states
+        let mut certificate_subject = None;
+        let mut state = State::Offer;
+        loop {
+            state = match state {
+                State::Offer => self.offer(&mut stream).await?,
+                #[cfg(feature = "tls")]
+                State::VeNCrypt => self.vencrypt(&mut stream).await?,
+                #[cfg(feature = "tls")]
+                State::Tls(method) => {
+                    let context = match method {
+                        AuthMethod::X509None | AuthMethod::X509Vnc => {
+                            self.certificate.as_ref().ok_or_else(|| {
+                                io::Error::other("X.509 subtype chosen without a certificate")
+                            })?
+                        }
+                        _ => tls::anonymous_context()?,
+                    };
+                    let (tls_stream, subject) = tls::accept(context, stream).await?;
+                    stream = tls_stream;
+                    if subject.is_none() && self.config.security.require_client_certificate {
+                        return Err(reject(&mut stream, "Client certificate required".into()).await);
+                    }
+                    certificate_subject = subject;
+                    match method {
+                        #[cfg(feature = "sasl")]
+                        AuthMethod::TlsSasl | AuthMethod::X509Sasl => State::Sasl(method),
+                        _ => State::Authenticate(method),
+                    }
+                }
+                #[cfg(feature = "rsa-aes")]
+                State::RsaAes(method) => {
+                    let (new_stream, next) = self.rsa_aes(stream, method).await?;
+                    stream = new_stream;
+                    next
+                }
+                #[cfg(feature = "sasl")]
+                State::Sasl(method) => self.sasl(&mut stream, method).await?,
+                State::MsLogon => self.ms_logon(&mut stream).await?,
+                State::Authenticate(method) => self.authenticate(&mut stream, method).await?,
+                State::Result { method, success } => {
+                    self.result(&mut stream, method, success).await?
+                }
+                State::Done { method, view_only } => {
+                    return Ok(Authenticated {
+                        stream,
+                        method,
+                        certificate_subject,
+                        view_only,
+                    })
+                }
+            };
+        }
+    }
+
+    /// The method of the plain (unencrypted) security type, if one can be offered: VNC
+    /// Authentication if the authenticator can check it, or None when authentication isn't
+    /// required and SASL, which None would bypass, isn't enabled.
+    fn plain_method(&self) -> Option<AuthMethod> {
+        if self.authenticator.required() {
+            self.authenticator
+                .accepts_vnc_challenge()
+                .then_some(AuthMethod::VncAuth)
+        } else {
+            (!self.sasl_enabled()).then_some(AuthMethod::None)
+        }
+    }
+
+    /// Whether SASL authentication is offered.
+    fn sasl_enabled(&self) -> bool {
+        cfg!(feature = "sasl") && self.config.security.sasl
+    }
+
+    /// Whether RSA-AES may be offered: it accepts any credentials when authentication
+    /// isn't required, which would bypass SASL.
+    #[cfg(feature = "rsa-aes")]
+    fn rsa_aes_allowed(&self) -> bool {
+        let security = &self.config.security;
+        security.rsa_aes
+            && !security.require_client_certificate
+            && (self.authenticator.required() || !self.sasl_enabled())
+    }
+
+    /// Checks credentials with the authenticator, unless authentication isn't required.
+    async fn verify(&self, username: &str, response: &[u8], challenge: &[u8]) -> bool {
+        !self.authenticator.required()
+            || self
+                .authenticator
+                .verify(username, response, challenge)
+                .await
+                == AuthDecision::Accept
+    }
+
+    /// Whether the security policy allows offering plain security types to this peer.
+    fn plaintext_allowed(&self) -> bool {
+        let connection = &self.config.connection;
+        if self.config.security.require_client_certificate {
+            return false;
+        }
+        self.secure_transport || connection.allows_plaintext(network::peer_ip(self.remote_host))
+    }
+
+    /// The `VeNCrypt` subtypes offered to this peer, in order of preference.
+    ///
+    /// Besides SASL, only the subtypes matching the plain method are offered, so the
+    /// `None` variants are never available when authentication is required, nor when SASL
+    /// is enabled. The SASL variants are preferred. Streams that are already encrypted aren't wrapped
+    /// a second time unless a client certificate is required.
+    #[cfg(feature = "tls")]
+    fn vencrypt_subtypes(&self) -> Vec<(u32, AuthMethod)> {
+        let security = &self.config.security;
+        let mut subtypes = Vec::with_capacity(4);
+        if self.secure_transport && !security.require_client_certificate {
+            return subtypes;
+        }
+        let sasl = self.sasl_enabled();
+        let plain = self.plain_method();
+        if self.certificate.is_some() {
+            if sasl {
+                subtypes.push((VENCRYPT_X509_SASL, AuthMethod::X509Sasl));
+            }
+            match plain {
+                Some(AuthMethod::VncAuth) => {
+                    subtypes.push((VENCRYPT_X509_VNC, AuthMethod::X509Vnc));
+                }
+                Some(_) => subtypes.push((VENCRYPT_X509_NONE, AuthMethod::X509None)),
+                None => {}
+            }
+        }
+        if security.anonymous_tls && !security.require_client_certificate {
+            if sasl {
+                subtypes.push((VENCRYPT_TLS_SASL, AuthMethod::TlsSasl));
+            }
+            match plain {
+                Some(AuthMethod::VncAuth) => subtypes.push((VENCRYPT_TLS_VNC, AuthMethod::TlsVnc)),
+                Some(_) => subtypes.push((VENCRYPT_TLS_NONE, AuthMethod::TlsNone)),
+                None => {}
+            }
+        }
+        subtypes
+    }
+
+    /// Sends the security type list and reads the client's choice.
+    async fn offer(&self, stream: &mut Box<dyn ClientStream>) -> Result<State, io::Error> {
+        let mut offered = Vec::with_capacity(6);
+        #[cfg(feature = "tls")]
+        if !self.vencrypt_subtypes().is_empty() {
+            offered.push(SECURITY_TYPE_VENCRYPT);
+        }
+        #[cfg(feature = "rsa-aes")]
+        let rsa_aes = self.rsa_aes_allowed();
+        #[cfg(feature = "rsa-aes")]
+        if rsa_aes {
+            offered.push(SECURITY_TYPE_RA2);
+        }
+        if self.plaintext_allowed() {
+            #[cfg(feature = "rsa-aes")]
+            if rsa_aes {
+                offered.push(SECURITY_TYPE_RA2NE);
+            }
+            #[cfg(feature = "sasl")]
+            if self.sasl_enabled() {
+                offered.push(SECURITY_TYPE_SASL);
+            }
+            if self.authenticator.required() && self.authenticator.accepts_usernames() {
+                offered.push(SECURITY_TYPE_MS_LOGON_II);
+            }
+            if let Some(method) = self.plain_method() {
+                offered.push(method.security_type());
+            }
+        }
+
+        if offered.is_empty() {
+            let reason = if self.config.security.require_client_certificate {
+                "Client certificate required"
+            } else {
+                "Encryption required"
+            };
+            let mut buf = BytesMut::with_capacity(5 + reason.len());
+            buf.put_u8(0); // No security types
+            #[allow(clippy::cast_possible_truncation)] // Short constant string
+            buf.put_u32(reason.len() as u32);
+            buf.put_slice(reason.as_bytes());
+            stream.write_all(&buf).await?;
+            log::warn!("Refused connection from {}: {reason}", self.remote_host);
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{reason} but none can be offered"),
+            ));
+        }
+
+        let mut buf = BytesMut::with_capacity(1 + offered.len());
+        #[allow(clippy::cast_possible_truncation)] // At most six types
+        buf.put_u8(offered.len() as u8);
+        buf.put_slice(&offered);
+        stream.write_all(&buf).await?;
+
+        let choice = stream.read_u8().await?;
+        if !offered.contains(&choice) {
+            // Anything else was never offered; continuing would skip authentication
+            return Err(reject(
+                stream,
+                format!("Client chose unsupported security type {choice}"),
+            )
+            .await);
+        }
+
+        #[cfg(feature = "tls")]
+        if choice == SECURITY_TYPE_VENCRYPT {
+            return Ok(State::VeNCrypt);
+        }
+        #[cfg(feature = "rsa-aes")]
+        if choice == SECURITY_TYPE_RA2 {
+            return Ok(State::RsaAes(AuthMethod::Ra2));
+        }
+        #[cfg(feature = "rsa-aes")]
+        if choice == SECURITY_TYPE_RA2NE {
+            return Ok(State::RsaAes(AuthMethod::Ra2ne));
+        }
+        #[cfg(feature = "sasl")]
+        if choice == SECURITY_TYPE_SASL {
+            return Ok(State::Sasl(AuthMethod::Sasl));
+        }
+        if choice == SECURITY_TYPE_MS_LOGON_II {
+            return Ok(State::MsLogon);
+        }
+        // The only other type offered
+        self.plain_method()
+            .map(State::Authenticate)
+            .ok_or_else(|| io::Error::other("No plain security type was offered"))
+    }
+
+    /// Negotiates the `VeNCrypt` version and subtype.
+    #[cfg(feature = "tls")]
+    async fn vencrypt(&self, stream: &mut Box<dyn ClientStream>) -> Result<State, io::Error> {
+        stream.write_all(&[0, 2]).await?;
+        let mut version = [0u8; 2];
+        stream.read_exact(&mut version).await?;
+        if version != [0, 2] {
+            stream.write_all(&[1]).await?; // Version not supported
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "Client requested unsupported VeNCrypt version {}.{}",
+                    version[0], version[1]
+                ),
+            ));
+        }
+        stream.write_all(&[0]).await?; // Version accepted
+
+        let subtypes = self.vencrypt_subtypes();
+        let mut buf = BytesMut::with_capacity(1 + 4 * subtypes.len());
+        #[allow(clippy::cast_possible_truncation)] // At most four subtypes
+        buf.put_u8(subtypes.len() as u8);
+        for &(subtype, _) in &subtypes {
+            buf.put_u32(subtype);
+        }
+        stream.write_all(&buf).await?;
+
+        let choice = stream.read_u32().await?;
+        let Some(&(_, method)) = subtypes.iter().find(|(subtype, _)| *subtype == choice) else {
+            stream.write_all(&[0]).await?; // Subtype rejected
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Client chose unsupported VeNCrypt subtype {choice}"),
+            ));
+        };
+        stream.write_all(&[1]).await?; // Subtype accepted, TLS handshake follows
+        Ok(State::Tls(method))
+    }
+
+    /// Runs the RSA-AES exchange and reads the credentials.
+    ///
+    /// # Returns
+    ///
+    /// The stream to continue on (the encrypted channel for `RA2`, the original stream for
+    /// `RA2ne`) and the next state.
+    #[cfg(feature = "rsa-aes")]
+    async fn rsa_aes(
+        &self,
+        stream: Box<dyn ClientStream>,
+        method: AuthMethod,
+    ) -> Result<(Box<dyn ClientStream>, State), io::Error> {
+        /// Credential subtype: the client sends a username and password.
+        const SUBTYPE_USERNAME_PASSWORD: u8 = 1;
+        /// Credential subtype: the client sends a password (and an empty username).
+        const SUBTYPE_PASSWORD: u8 = 2;
+
+        let key = rsa_aes::server_key(&self.config.security).await?;
+        let mut channel = rsa_aes::handshake(stream, &key).await?;
+        let subtype = if self.authenticator.accepts_usernames() {
+            SUBTYPE_USERNAME_PASSWORD
+        } else {
+            SUBTYPE_PASSWORD
+        };
+        channel.write_all(&[subtype]).await?;
+
+        let username_len = channel.read_u8().await?;
+        let mut username = vec![0u8; usize::from(username_len)];
+        channel.read_exact(&mut username).await?;
+        let password_len = channel.read_u8().await?;
+        let mut password = Zeroizing::new(vec![0u8; usize::from(password_len)]);
+        channel.read_exact(&mut password).await?;
+
+        let username = String::from_utf8_lossy(&username);
+        let success = self.verify(&username, &password, &[]).await;
+        let stream: Box<dyn ClientStream> = if method == AuthMethod::Ra2 {
+            Box::new(channel)
+        } else {
+            Box::new(channel.into_inner()?)
+        };
+        Ok((stream, State::Result { method, success }))
+    }
+
+    /// Runs a SASL exchange: sends the mechanism list, then relays the client's messages
+    /// to Cyrus SASL and its challenges back until the mechanism completes.
+    #[cfg(feature = "sasl")]
+    async fn sasl(
+        &self,
+        stream: &mut Box<dyn ClientStream>,
+        method: AuthMethod,
+    ) -> Result<State, io::Error> {
+        /// Longest mechanism name the client may send.
+        const MAX_MECHANISM_LEN: u32 = 100;
+
+        let service = self
+            .config
+            .security
+            .sasl_service
+            .as_deref()
+            .unwrap_or("vnc");
+        let encrypted = method != AuthMethod::Sasl || self.secure_transport;
+        let mut server = SaslServer::new(service, encrypted)?;
+        let mechanisms = server.mechanisms().await?;
+        let mut buf = BytesMut::with_capacity(4 + mechanisms.len());
+        #[allow(clippy::cast_possible_truncation)] // Short list of mechanism names
+        buf.put_u32(mechanisms.len() as u32);
+        buf.put_slice(mechanisms.as_bytes());
+        stream.write_all(&buf).await?;
+
+        let mechanism_len = stream.read_u32().await?;
+        if mechanism_len == 0 || mechanism_len > MAX_MECHANISM_LEN {
+            return Err(reject(
+                stream,
+                format!("Invalid SASL mechanism length {mechanism_len}"),
+            )
+            .await);
+        }
+        let mut mechanism = vec![0u8; mechanism_len as usize];
+        stream.read_exact(&mut mechanism).await?;
+        let mechanism = String::from_utf8_lossy(&mechanism).into_owned();
+        if !mechanisms.split(',').any(|offered| offered == mechanism) {
+            return Err(reject(
+                stream,
+                format!("Client chose unsupported SASL mechanism {mechanism}"),
+            )
+            .await);
+        }
+
+        let mut step = server
+            .start(mechanism, read_sasl_data(stream).await?)
+            .await?;
+        loop {
+            let (data, complete) = match step {
+                Step::Continue(data) => (data, false),
+                Step::Done(data) => (data, true),
+                Step::Failed(reason) => {
+                    log::warn!(
+                        "SASL authentication of {} failed: {reason}",
+                        self.remote_host
+                    );
+                    return Ok(State::Result {
+                        method,
+                        success: false,
+                    });
+                }
+            };
+            let mut buf = BytesMut::with_capacity(data.len() + 6);
+            if data.is_empty() {
+                buf.put_u32(0);
+            } else {
+                // Sent with a terminating NUL, as Cyrus SASL clients expect
+                #[allow(clippy::cast_possible_truncation)] // Mechanism messages are small
+                buf.put_u32(data.len() as u32 + 1);
+                buf.put_slice(&data);
+                buf.put_u8(0);
+            }
+            buf.put_u8(u8::from(complete));
+            stream.write_all(&buf).await?;
+            if complete {
+                break;
+            }
+            step = server.step(read_sasl_data(stream).await?).await?;
+        }
+
+        if let Some(username) = server.username() {
+            log::info!("{} authenticated as {username} via SASL", self.remote_host);
+        }
+        Ok(State::Result {
+            method,
+            success: true,
+        })
+    }
+
+    /// Runs the MS-Logon II key exchange, reads the encrypted credentials and checks them
+    /// with the authenticator.
+    async fn ms_logon(&self, stream: &mut Box<dyn ClientStream>) -> Result<State, io::Error> {
+        let method = AuthMethod::MsLogonII;
+        let exchange = KeyExchange::new();
+        let mut buf = BytesMut::with_capacity(24);
+        buf.put_u64(exchange.generator);
+        buf.put_u64(exchange.modulus);
+        buf.put_u64(exchange.public_key());
+        stream.write_all(&buf).await?;
+
+        let key = Zeroizing::new(exchange.shared_key(stream.read_u64().await?));
+        let mut username = [0u8; ms_logon::USERNAME_LEN];
+        stream.read_exact(&mut username).await?;
+        let mut password = [0u8; ms_logon::PASSWORD_LEN];
+        stream.read_exact(&mut password).await?;
+        let username = ms_logon::decrypt_field(&username, *key);
+        let password = Zeroizing::new(ms_logon::decrypt_field(&password, *key));
+
+        let success = self.verify(&username, password.as_bytes(), &[]).await;
+        log::info!(
+            "MS-Logon II login of {} as {username} {}",
+            self.remote_host,
+            if success { "succeeded" } else { "failed" }
+        );
+        Ok(State::Result { method, success })
+    }
+
+    /// Runs the challenge-response part of `method`, if it has one.
+    async fn authenticate(
+        &self,
+        stream: &mut Box<dyn ClientStream>,
+        method: AuthMethod,
+    ) -> Result<State, io::Error> {
+        let success = match method {
+            AuthMethod::VncAuth | AuthMethod::TlsVnc | AuthMethod::X509Vnc => {
+                let challenge: [u8; 16] = rand::thread_rng().gen();
+                stream.write_all(&challenge).await?;
+
+                let mut response = [0u8; 16];
+                stream.read_exact(&mut response).await?;
+                self.verify("", &response, &challenge).await
+            }
+            // Only offered when authentication isn't required
+            AuthMethod::None | AuthMethod::TlsNone | AuthMethod::X509None => {
+                !self.authenticator.required()
+            }
+            // Checked by the RsaAes, Sasl and MsLogon states, which never lead here
+            AuthMethod::Ra2
+            | AuthMethod::Ra2ne
+            | AuthMethod::Sasl
+            | AuthMethod::TlsSasl
+            | AuthMethod::X509Sasl
+            | AuthMethod::MsLogonII => false,
+        };
+        Ok(State::Result { method, success })
+    }
+
+    /// Emits the `AuthAttempt` event, asks the approver about authenticated clients and
+    /// sends the `SecurityResult`.
+    async fn result(
+        &self,
+        stream: &mut Box<dyn ClientStream>,
+        method: AuthMethod,
+        success: bool,
+    ) -> Result<State, io::Error> {
+        let _ = self.event_tx.send(ClientEvent::AuthAttempt {
+            address: self.remote_host.to_string(),
+            method,
+            success,
+        });
+
+        if !success {
+            return Err(reject(stream, format!("{} authentication failed", method.name())).await);
+        }
+        let approval = match &self.config.connection.approver {
+            Some(approver) => {
+                approver(ConnectionRequest {
+                    address: self.remote_host.to_string(),
+                    repeater_id: self.repeater_id.map(str::to_string),
+                    method,
+                })
+                .await
+            }
+            None => Approval::Accept,
+        };
+        let view_only = match approval {
+            Approval::Accept => false,
+            Approval::ViewOnly => true,
+            Approval::Reject(reason) => {
+                log::warn!("Refused connection from {}: {reason}", self.remote_host);
+                return Err(reject(stream, reason).await);
+            }
+        };
+        let mut buf = BytesMut::with_capacity(4);
+        buf.put_u32(SECURITY_RESULT_OK);
+        stream.write_all(&buf).await?;
+        Ok(State::Done { method, view_only })
+    }
+}
+
+/// Reads one SASL message from the client: a length, then the data with a terminating
+/// NUL, which is dropped. A zero length means no data.
+#[cfg(feature = "sasl")]
+async fn read_sasl_data(stream: &mut Box<dyn ClientStream>) -> Result<Option<Vec<u8>>, io::Error> {
+    /// Largest SASL message the client may send.
+    const MAX_DATA_LEN: u32 = 1024 * 1024;
+
+    let len = stream.read_u32().await?;
+    if len == 0 {
+        return Ok(None);
+    }
+    if len > MAX_DATA_LEN {
+        return Err(reject(stream, format!("SASL message of {len} bytes is too long")).await);
+    }
+    let mut data = vec![0u8; len as usize];
+    stream.read_exact(&mut data).await?;
+    if data.last() == Some(&0) {
+        data.pop();
+    }
+    Ok(Some(data))
+}
+
+/// Sends a failed `SecurityResult` with the reason and returns the error ending the
+/// handshake.
+async fn reject(stream: &mut Box<dyn ClientStream>, reason: String) -> io::Error {
+    let mut buf = BytesMut::with_capacity(8 + reason.len());
+    buf.put_u32(SECURITY_RESULT_FAILED);
+    #[allow(clippy::cast_possible_truncation)] // Reasons are short messages
+    buf.put_u32(reason.len() as u32);
+    buf.put_slice(reason.as_bytes());
+    if let Err(e) = stream.write_all(&buf).await {
+        return e;
+    }
+    io::Error::new(io::ErrorKind::PermissionDenied, reason)
+}
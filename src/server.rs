@@ -31,14 +31,31 @@
 use log::error;
 #[cfg(feature = "debug-logging")]
 use log::info;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{mpsc, RwLock};
-
-use crate::client::{ClientEvent, VncClient};
-use crate::framebuffer::{DirtyRegionReceiver, Framebuffer};
+use tokio::sync::{mpsc, oneshot, watch, Notify, OwnedSemaphorePermit, RwLock, Semaphore};
+use tokio::time::Duration;
+
+use crate::audit::{AuditEvent, AuditLog};
+use crate::authenticator::{Authenticator, PasswordAuthenticator};
+use crate::client::{close_writer, ClientCommand, ClientEvent, ClientWriter, VncClient};
+use crate::config::{ColorMode, ReconnectPolicy, ServerConfig};
+use crate::desktop_size::{ResizeReply, Screen};
+use crate::encode_pool::EncodePool;
+use crate::frame_source::FrameSource;
+use crate::framebuffer::{DirtyRegion, Framebuffer, StorageFormat};
+use crate::gii::GiiEvent;
+use crate::keysym::LedState;
+use crate::network::{AccessList, IpNetwork};
+use crate::protocol::{PixelFormat, Rectangle};
 use crate::repeater;
+use crate::resume::SessionStore;
+use crate::stats::{ClientMemoryUsage, EncodeStats, UpdateTiming};
+use crate::transport::ClientStream;
 
 /// Global atomic counter for assigning unique client IDs.
 ///
@@ -46,6 +63,25 @@ use crate::repeater;
 /// each client has a unique identifier throughout the server's lifetime.
 static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
 
+/// How long [`VncServer::drain`] waits for closed clients to go away before forcing
+/// their connections shut.
+const DRAIN_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Allocates a unique client ID for a new connection.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if the client ID counter overflows.
+#[allow(clippy::cast_possible_truncation)] // Client ID counter limited to u64::MAX, safe on 64-bit platforms
+fn allocate_client_id() -> Result<usize, std::io::Error> {
+    // Safely increment client ID counter and check for overflow
+    let client_id_raw = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
+    if client_id_raw == 0 || client_id_raw >= u64::MAX - 1000 {
+        return Err(std::io::Error::other("Client ID counter overflow"));
+    }
+    Ok(client_id_raw as usize)
+}
+
 /// Represents a VNC server instance.
 ///
 /// This struct manages the VNC framebuffer, connected clients, and handles server-wide events.
@@ -53,14 +89,13 @@ pub struct VncServer {
     /// The VNC framebuffer, representing the remote desktop screen.
     framebuffer: Framebuffer,
     /// The name of the desktop, displayed to connected clients.
-    desktop_name: String,
-    /// Optional password for client authentication.
-    password: Option<String>,
+    desktop_name: Arc<RwLock<String>>,
+    /// Checks client credentials, read when each client connects.
+    authenticator: Arc<RwLock<Arc<dyn Authenticator>>>,
     /// A list of currently connected VNC clients, protected by a `RwLock` for concurrent access.
     clients: Arc<RwLock<Vec<Arc<RwLock<VncClient>>>>>,
     /// Write stream handles for direct socket shutdown
-    client_write_streams:
-        Arc<RwLock<Vec<Arc<tokio::sync::Mutex<tokio::net::tcp::OwnedWriteHalf>>>>>,
+    client_write_streams: Arc<RwLock<Vec<Arc<tokio::sync::Mutex<ClientWriter>>>>>,
     /// Task handles for waiting on client threads to exit
     client_tasks: Arc<RwLock<Vec<tokio::task::JoinHandle<()>>>>,
     /// List of active client IDs for fast lookup during shutdown without locking `VncClient` objects.
@@ -69,16 +104,411 @@ pub struct VncServer {
     /// to quickly retrieve all client IDs without acquiring locks on potentially busy `VncClient`
     /// objects, which could cause delays or deadlocks during server shutdown.
     client_ids: Arc<RwLock<Vec<usize>>>,
+    /// Notified whenever `client_ids` changes, to wake the client lifecycle futures.
+    client_changes: Arc<Notify>,
+    /// Command channels of the connected clients, keyed by client ID.
+    client_commands: Arc<RwLock<HashMap<usize, mpsc::UnboundedSender<ClientCommand>>>>,
+    /// Server configuration, captured by each client when it connects.
+    config: Arc<ServerConfig>,
+    /// Encode and send latency histograms, shared by all clients.
+    encode_stats: Arc<EncodeStats>,
+    /// Dedicated encoder threads, if configured (see `EncodeConfig`).
+    encode_pool: Option<Arc<EncodePool>>,
+    /// Sessions of disconnected clients kept for session resume.
+    sessions: Arc<SessionStore>,
+    /// Set once the server starts draining (see [`drain`](Self::drain)).
+    draining: Arc<watch::Sender<bool>>,
+    /// Virtual desktops clients can be assigned to, keyed by desktop ID. The primary
+    /// framebuffer is registered as [`PRIMARY_DESKTOP`].
+    desktops: Arc<RwLock<HashMap<usize, Framebuffer>>>,
+    /// The desktop each connected client is assigned to, keyed by client ID.
+    client_desktops: Arc<RwLock<HashMap<usize, usize>>>,
+    /// ID handed out by the next `add_desktop` call.
+    next_desktop_id: AtomicUsize,
+    /// Chooses the desktop for incoming connections (primary desktop if unset).
+    desktop_assigner: Option<DesktopAssigner>,
+    /// Hosts allowed or denied to connect, checked when a connection is accepted.
+    access_list: Arc<RwLock<AccessList>>,
+    /// Records connections, authentication and clipboard transfers, if set.
+    audit: Option<AuditLog>,
+    /// The keyboard lock LEDs last reported by the application.
+    led_state: Arc<RwLock<Option<LedState>>>,
     /// Sender for server-wide events, used to notify external components of VNC server activity.
     event_tx: mpsc::UnboundedSender<ServerEvent>,
 }
 
+/// Shared server state handed to the task running a client session.
+#[derive(Clone)]
+struct SessionContext {
+    framebuffer: Framebuffer,
+    desktop_id: usize,
+    client_desktops: Arc<RwLock<HashMap<usize, usize>>>,
+    config: Arc<ServerConfig>,
+    encode_stats: Arc<EncodeStats>,
+    encode_pool: Option<Arc<EncodePool>>,
+    sessions: Arc<SessionStore>,
+    audit: Option<AuditLog>,
+    led_state: Arc<RwLock<Option<LedState>>>,
+    clients: Arc<RwLock<Vec<Arc<RwLock<VncClient>>>>>,
+    client_write_streams: Arc<RwLock<Vec<Arc<tokio::sync::Mutex<ClientWriter>>>>>,
+    client_tasks: Arc<RwLock<Vec<tokio::task::JoinHandle<()>>>>,
+    client_ids: Arc<RwLock<Vec<usize>>>,
+    client_changes: Arc<Notify>,
+    client_commands: Arc<RwLock<HashMap<usize, mpsc::UnboundedSender<ClientCommand>>>>,
+    event_tx: mpsc::UnboundedSender<ServerEvent>,
+    /// Whether the session's transport is encrypted (see `serve_secure_stream`).
+    secure_transport: bool,
+    /// Becomes `true` when the server starts draining.
+    draining: watch::Receiver<bool>,
+}
+
+/// State of an accept loop, detached from the `VncServer` so it can run in its own task.
+#[derive(Clone)]
+struct Acceptor {
+    desktop_name: Arc<RwLock<String>>,
+    authenticator: Arc<RwLock<Arc<dyn Authenticator>>>,
+    desktops: Arc<RwLock<HashMap<usize, Framebuffer>>>,
+    desktop_assigner: Option<DesktopAssigner>,
+    access_list: Arc<RwLock<AccessList>>,
+    context: SessionContext,
+}
+
+impl Acceptor {
+    /// Accepts connections on `listener` and spawns a session task for each client.
+    ///
+    /// Returns `Ok(())`, closing the listener, once the server starts draining.
+    ///
+    /// # Errors
+    ///
+    /// Currently never returns an error; accept errors are logged and the loop continues.
+    async fn run(self, listener: TcpListener) -> Result<(), std::io::Error> {
+        let max_pending = self.context.config.connection.max_pending_handshakes;
+        let handshake_slots = (max_pending > 0).then(|| Arc::new(Semaphore::new(max_pending)));
+        let mut draining = self.context.draining.clone();
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = draining.wait_for(|draining| *draining) => {
+                    log::info!("Server is draining, no longer accepting connections");
+                    return Ok(());
+                }
+            };
+            match accepted {
+                Ok((stream, addr)) => {
+                    #[cfg(feature = "debug-logging")]
+                    info!("New VNC client connection from: {addr}");
+
+                    if !self.access_list.read().await.permits(addr.ip()) {
+                        log::warn!("Host {} is not permitted, closing connection", addr.ip());
+                        continue;
+                    }
+
+                    // Refuse new connections while too many are stuck in the handshake
+                    let handshake_permit = match &handshake_slots {
+                        Some(slots) => {
+                            if let Ok(permit) = slots.clone().try_acquire_owned() {
+                                Some(permit)
+                            } else {
+                                log::warn!(
+                                    "Too many pending handshakes, closing connection from {addr}"
+                                );
+                                continue;
+                            }
+                        }
+                        None => None,
+                    };
+
+                    // Disable Nagle's algorithm for immediate frame delivery
+                    if let Err(e) = stream.set_nodelay(true) {
+                        error!("Failed to set TCP_NODELAY for {addr}: {e}");
+                        continue;
+                    }
+                    self.spawn_session(async move { Ok(stream) }, addr, false, handshake_permit)
+                        .await;
+                }
+                Err(e) => {
+                    error!("Error accepting connection: {e}");
+                }
+            }
+        }
+    }
+
+    /// Accepts QUIC connections on `endpoint` and runs a session for each on a
+    /// bidirectional stream the server opens, so the RFB greeting reaches the viewer.
+    ///
+    /// Returns `Ok(())` once the server starts draining or the endpoint is closed.
+    ///
+    /// # Errors
+    ///
+    /// Currently never returns an error; failed QUIC handshakes are logged.
+    #[cfg(feature = "quic")]
+    async fn run_quic(self, endpoint: quinn::Endpoint) -> Result<(), std::io::Error> {
+        let max_pending = self.context.config.connection.max_pending_handshakes;
+        let handshake_slots = (max_pending > 0).then(|| Arc::new(Semaphore::new(max_pending)));
+        let mut draining = self.context.draining.clone();
+        loop {
+            let incoming = tokio::select! {
+                incoming = endpoint.accept() => incoming,
+                _ = draining.wait_for(|draining| *draining) => {
+                    log::info!("Server is draining, no longer accepting QUIC connections");
+                    return Ok(());
+                }
+            };
+            let Some(incoming) = incoming else {
+                return Ok(());
+            };
+            let addr = incoming.remote_address();
+
+            if !self.access_list.read().await.permits(addr.ip()) {
+                log::warn!(
+                    "Host {} is not permitted, refusing QUIC connection",
+                    addr.ip()
+                );
+                incoming.refuse();
+                continue;
+            }
+            let handshake_permit = match &handshake_slots {
+                Some(slots) => {
+                    if let Ok(permit) = slots.clone().try_acquire_owned() {
+                        Some(permit)
+                    } else {
+                        log::warn!(
+                            "Too many pending handshakes, refusing QUIC connection from {addr}"
+                        );
+                        incoming.refuse();
+                        continue;
+                    }
+                }
+                None => None,
+            };
+
+            let stream = async move {
+                let connection = incoming.await?;
+                let (send, recv) = connection.open_bi().await?;
+                Ok(tokio::io::join(recv, send))
+            };
+            self.spawn_session(stream, addr, true, handshake_permit)
+                .await;
+        }
+    }
+
+    /// Spawns the task running the session of an accepted connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - Completes the transport setup and yields the stream to the client.
+    /// * `addr` - The client's address.
+    /// * `secure_transport` - Whether the transport is encrypted (see `serve_secure_stream`).
+    /// * `handshake_permit` - Pending-handshake slot, released once the handshake ends.
+    async fn spawn_session<S, F>(
+        &self,
+        stream: F,
+        addr: SocketAddr,
+        secure_transport: bool,
+        handshake_permit: Option<OwnedSemaphorePermit>,
+    ) where
+        S: ClientStream,
+        F: Future<Output = Result<S, std::io::Error>> + Send + 'static,
+    {
+        let client_id = match allocate_client_id() {
+            Ok(id) => id,
+            Err(e) => {
+                error!("{e}, rejecting connection from {addr}");
+                return;
+            }
+        };
+
+        let desktop_name = self.desktop_name.read().await.clone();
+        let authenticator = self.authenticator.read().await.clone();
+        let mut context = self.context_for_peer(addr).await;
+        context.secure_transport = secure_transport;
+        let client_tasks = context.client_tasks.clone();
+
+        let handle = tokio::spawn(async move {
+            let result = match stream.await {
+                Ok(stream) => {
+                    VncServer::handle_client(
+                        stream,
+                        addr.to_string(),
+                        client_id,
+                        desktop_name,
+                        authenticator,
+                        context,
+                        handshake_permit,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("Client {client_id} error: {e}");
+            }
+        });
+
+        // Store the handle_client task handle for joining later, dropping the handles of
+        // finished sessions so failed handshakes don't accumulate
+        let mut client_tasks = client_tasks.write().await;
+        client_tasks.retain(|task| !task.is_finished());
+        client_tasks.push(handle);
+    }
+
+    /// Builds the session context for a client connecting from `addr`, placing it on the
+    /// desktop chosen by the desktop assigner.
+    async fn context_for_peer(&self, addr: SocketAddr) -> SessionContext {
+        let mut context = self.context.clone();
+        if let Some(assigner) = &self.desktop_assigner {
+            let desktop_id = assigner(addr);
+            if let Some(framebuffer) = self.desktops.read().await.get(&desktop_id) {
+                context.framebuffer = framebuffer.clone();
+                context.desktop_id = desktop_id;
+            } else {
+                log::warn!(
+                    "Desktop {desktop_id} assigned to {addr} does not exist, using the primary desktop"
+                );
+            }
+        }
+        context
+    }
+}
+
+/// ID of the desktop backed by the server's own framebuffer.
+pub const PRIMARY_DESKTOP: usize = 0;
+
+/// Chooses the desktop for an incoming connection from its peer address.
+///
+/// See [`VncServer::set_desktop_assigner`].
+pub type DesktopAssigner = Arc<dyn Fn(SocketAddr) -> usize + Send + Sync>;
+
+/// Authentication method chosen by a client during the security handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// No authentication (security type 1).
+    None,
+    /// VNC challenge-response authentication (security type 2).
+    VncAuth,
+    /// Password authentication over RSA-AES, encrypting the whole session (security
+    /// type 5).
+    Ra2,
+    /// Password authentication over RSA-AES, with the session in the clear (security
+    /// type 6).
+    Ra2ne,
+    /// No authentication inside anonymous TLS (security type 19, `VeNCrypt` subtype
+    /// `TLSNone`).
+    TlsNone,
+    /// VNC challenge-response authentication inside anonymous TLS (security type 19,
+    /// `VeNCrypt` subtype `TLSVnc`).
+    TlsVnc,
+    /// No authentication inside TLS with a server certificate (security type 19,
+    /// `VeNCrypt` subtype `X509None`).
+    X509None,
+    /// VNC challenge-response authentication inside TLS with a server certificate
+    /// (security type 19, `VeNCrypt` subtype `X509Vnc`).
+    X509Vnc,
+    /// SASL authentication (security type 20).
+    Sasl,
+    /// SASL authentication inside anonymous TLS (security type 19, `VeNCrypt` subtype
+    /// `TLSSASL`).
+    TlsSasl,
+    /// SASL authentication inside TLS with a server certificate (security type 19,
+    /// `VeNCrypt` subtype `X509SASL`).
+    X509Sasl,
+    /// `UltraVNC` MS-Logon II, checking a username and password with the server's
+    /// [`Authenticator`] (security type 113).
+    MsLogonII,
+}
+
+impl AuthMethod {
+    /// Returns the RFB security type number of this method.
+    #[must_use]
+    pub fn security_type(self) -> u8 {
+        match self {
+            Self::None => 1,
+            Self::VncAuth => 2,
+            Self::Ra2 => 5,
+            Self::Ra2ne => 6,
+            Self::TlsNone
+            | Self::TlsVnc
+            | Self::TlsSasl
+            | Self::X509None
+            | Self::X509Vnc
+            | Self::X509Sasl => 19,
+            Self::Sasl => 20,
+            Self::MsLogonII => 113,
+        }
+    }
+
+    /// Returns a short lowercase name for this method (e.g. `"vnc"`).
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::VncAuth => "vnc",
+            Self::Ra2 => "ra2",
+            Self::Ra2ne => "ra2ne",
+            Self::TlsNone => "tls-none",
+            Self::TlsVnc => "tls-vnc",
+            Self::X509None => "x509-none",
+            Self::X509Vnc => "x509-vnc",
+            Self::Sasl => "sasl",
+            Self::TlsSasl => "tls-sasl",
+            Self::X509Sasl => "x509-sasl",
+            Self::MsLogonII => "ms-logon-ii",
+        }
+    }
+}
+
+/// A phase of the RFB handshake, as reported by `ServerEvent::HandshakeTimeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakePhase {
+    /// Exchanging protocol versions (`ConnectionConfig::version_timeout`).
+    Version,
+    /// Negotiating the security type and authenticating
+    /// (`ConnectionConfig::security_timeout`).
+    Security,
+    /// Waiting for `ClientInit` (`ConnectionConfig::client_init_timeout`).
+    ClientInit,
+}
+
+impl HandshakePhase {
+    /// Returns a short lowercase name for this phase (e.g. `"security"`).
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Version => "version",
+            Self::Security => "security",
+            Self::ClientInit => "client-init",
+        }
+    }
+}
+
+/// What a client and the server negotiated during initialization.
+#[derive(Debug, Clone)]
+pub struct Negotiation {
+    /// The protocol version the client answered with, e.g. `003.008`.
+    pub protocol_version: String,
+    /// The security type the client chose, or `None` if it chose one the server did not
+    /// offer.
+    pub auth_method: Option<AuthMethod>,
+    /// The client's pixel format.
+    pub pixel_format: PixelFormat,
+    /// The encodings from the client's `SetEncodings` list, in its order of preference.
+    pub encodings: Vec<i32>,
+    /// The encoding the server uses for framebuffer updates.
+    pub encoding: i32,
+    /// The pseudo-encodings from `encodings` the server honors (desktop size, quality
+    /// and compression levels, and registered vendor pseudo-encodings).
+    pub pseudo_encodings: Vec<i32>,
+}
+
 /// Enum representing various events that can occur within the VNC server.
 pub enum ServerEvent {
     /// A new client has connected to the VNC server.
     ClientConnected {
         /// The unique identifier for the newly connected client
         client_id: usize,
+        /// The subject of the client's verified TLS certificate (e.g.
+        /// `CN=kiosk-3,O=Example`), if it presented one (see `SecurityConfig::client_ca`)
+        certificate_subject: Option<String>,
     },
     /// A client has disconnected from the VNC server.
     ClientDisconnected {
@@ -112,6 +542,208 @@ pub enum ServerEvent {
         /// The cut text string
         text: String,
     },
+    /// A client completed the security handshake, successfully or not.
+    ///
+    /// Emitted for every connection that reaches authentication, including ones that are
+    /// rejected and never produce `ClientConnected`.
+    AuthAttempt {
+        /// The unique identifier assigned to the connection
+        client_id: usize,
+        /// The remote address of the client (IP:port)
+        address: String,
+        /// The authentication method the client chose
+        method: AuthMethod,
+        /// Whether authentication succeeded
+        success: bool,
+    },
+    /// A connection was dropped because a handshake phase exceeded its timeout (see
+    /// `ConnectionConfig::version_timeout`).
+    HandshakeTimeout {
+        /// The unique identifier assigned to the connection
+        client_id: usize,
+        /// The remote address of the client (IP:port)
+        address: String,
+        /// The phase that timed out
+        phase: HandshakePhase,
+    },
+    /// A client asked to change the framebuffer size (`SetDesktopSize`).
+    ///
+    /// Answer through `reply`; see the [`desktop_size`](crate::desktop_size) module.
+    ResizeRequest {
+        /// The unique identifier of the client that sent the request
+        client_id: usize,
+        /// The requested framebuffer width
+        width: u16,
+        /// The requested framebuffer height
+        height: u16,
+        /// The requested screen layout
+        screens: Vec<Screen>,
+        /// Handle for accepting or rejecting the request
+        reply: ResizeReply,
+    },
+    /// The state of a persistent reverse connection changed.
+    ReverseConnection {
+        /// The viewer host being dialed
+        host: String,
+        /// The viewer port being dialed
+        port: u16,
+        /// The new state of the connection
+        status: ReverseConnectionStatus,
+    },
+    /// A client advertised a vendor pseudo-encoding registered in
+    /// [`ExtensionConfig`](crate::config::ExtensionConfig).
+    ///
+    /// Emitted once per encoding when it first appears in the client's `SetEncodings`
+    /// list. The application can then send the extension's rectangles with
+    /// [`VncServer::send_rect`].
+    PseudoEncoding {
+        /// The unique identifier of the client
+        client_id: usize,
+        /// The pseudo-encoding number
+        encoding: i32,
+    },
+    /// A framebuffer update was sent to a client.
+    ///
+    /// Only emitted when [`UpdateConfig::report_timing`](crate::config::UpdateConfig::report_timing)
+    /// is enabled; see the [`stats`](crate::stats) module.
+    UpdateTiming {
+        /// The unique identifier of the client
+        client_id: usize,
+        /// Pipeline timing of the update
+        timing: UpdateTiming,
+    },
+    /// A client finished initialization, or changed its pixel format or encodings later.
+    ///
+    /// Emitted when the client sends its first `FramebufferUpdateRequest` (by which point
+    /// it has sent its pixel format and encodings), and again on every later
+    /// `SetPixelFormat` or `SetEncodings`.
+    Negotiated {
+        /// The unique identifier of the client
+        client_id: usize,
+        /// What was negotiated
+        negotiation: Negotiation,
+    },
+    /// A client registered an input device or sent its events through the gii extension
+    /// (pen pressure and tilt, multitouch, ...); see the [`gii`](crate::gii) module.
+    ExtendedInput {
+        /// The unique identifier of the client
+        client_id: usize,
+        /// The registration or input event
+        event: GiiEvent,
+    },
+    /// The server started listening on a port chosen by `listen_auto`.
+    Listening {
+        /// The TCP port the server is listening on
+        port: u16,
+        /// The VNC display number (`port - 5900`), if the port is in the display range
+        display: Option<u16>,
+    },
+    /// The server started draining (see [`VncServer::drain`]) and refuses new clients.
+    DrainStarted {
+        /// Number of clients connected when draining started
+        clients: usize,
+        /// How long connected clients may stay
+        grace: Duration,
+    },
+    /// The server is closing a client's connection. `ClientDisconnected` follows once
+    /// it is gone.
+    ClientClosing {
+        /// The unique identifier of the client
+        client_id: usize,
+        /// Why the connection is closed
+        reason: String,
+    },
+    /// Draining finished; no clients are connected.
+    DrainCompleted {
+        /// Number of clients that had to be disconnected forcibly
+        forced: usize,
+    },
+}
+
+/// Lifecycle states of a persistent reverse connection (see
+/// [`VncServer::connect_reverse_persistent`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReverseConnectionStatus {
+    /// A connection attempt started (`attempt` counts from 1 since the last success).
+    Connecting {
+        /// The attempt number
+        attempt: u32,
+    },
+    /// The viewer accepted the connection and the handshake completed.
+    Connected {
+        /// The client ID assigned to this session
+        client_id: usize,
+    },
+    /// A connection attempt failed.
+    Failed {
+        /// The attempt number
+        attempt: u32,
+        /// Description of the error
+        error: String,
+    },
+    /// An established session ended.
+    Disconnected {
+        /// The client ID of the session that ended
+        client_id: usize,
+    },
+    /// The next attempt will start after `delay`.
+    RetryScheduled {
+        /// The attempt number of the next attempt
+        attempt: u32,
+        /// Time until the next attempt
+        delay: Duration,
+    },
+    /// The retry budget is exhausted; no further attempts will be made.
+    GaveUp {
+        /// Number of consecutive failed attempts
+        attempts: u32,
+    },
+    /// The connection was stopped through its [`ReverseConnectionHandle`], or the server
+    /// started draining.
+    Stopped,
+}
+
+impl ReverseConnectionStatus {
+    /// Returns a short lowercase name for this state (e.g. `"retry_scheduled"`).
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Connecting { .. } => "connecting",
+            Self::Connected { .. } => "connected",
+            Self::Failed { .. } => "failed",
+            Self::Disconnected { .. } => "disconnected",
+            Self::RetryScheduled { .. } => "retry_scheduled",
+            Self::GaveUp { .. } => "gave_up",
+            Self::Stopped => "stopped",
+        }
+    }
+}
+
+/// Handle to a persistent reverse connection.
+///
+/// Dropping the handle does not stop the connection; call [`stop`](Self::stop).
+#[derive(Debug, Clone)]
+pub struct ReverseConnectionHandle {
+    stopped: Arc<AtomicBool>,
+    wake: Arc<Notify>,
+}
+
+impl ReverseConnectionHandle {
+    /// Stops reconnecting.
+    ///
+    /// A pending retry is cancelled immediately. A session that is currently connected
+    /// keeps running until the viewer disconnects or it is closed with
+    /// [`VncServer::disconnect_all_clients`]; it is not re-established afterwards.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    /// Returns `true` if [`stop`](Self::stop) has been called.
+    #[must_use]
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
 }
 
 impl VncServer {
@@ -125,7 +757,8 @@ impl VncServer {
     /// * `width` - The width of the VNC framebuffer.
     /// * `height` - The height of the VNC framebuffer.
     /// * `desktop_name` - The name of the desktop to be advertised to clients.
-    /// * `password` - An optional password for client authentication.
+    /// * `password` - An optional password for client authentication, checked by a
+    ///   [`PasswordAuthenticator`] (see [`set_authenticator`](Self::set_authenticator)).
     ///
     /// # Returns
     ///
@@ -138,276 +771,1288 @@ impl VncServer {
         height: u16,
         desktop_name: String,
         password: Option<String>,
+    ) -> (Self, mpsc::UnboundedReceiver<ServerEvent>) {
+        Self::with_framebuffer(Framebuffer::new(width, height), desktop_name, password)
+    }
+
+    /// Creates a new `VncServer` whose framebuffer stores its pixels in `storage`.
+    ///
+    /// With [`StorageFormat::Rgb565`] the server keeps half the memory for its copies of
+    /// the screen, for memory-constrained targets. See
+    /// [`Framebuffer::with_storage`].
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the VNC framebuffer.
+    /// * `height` - The height of the VNC framebuffer.
+    /// * `storage` - The layout of the stored pixels.
+    /// * `desktop_name` - The name of the desktop to be advertised to clients.
+    /// * `password` - An optional password for client authentication, checked by a
+    ///   [`PasswordAuthenticator`] (see [`set_authenticator`](Self::set_authenticator)).
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the `VncServer` instance and its event receiver.
+    #[must_use]
+    pub fn with_storage(
+        width: u16,
+        height: u16,
+        storage: StorageFormat,
+        desktop_name: String,
+        password: Option<String>,
+    ) -> (Self, mpsc::UnboundedReceiver<ServerEvent>) {
+        let framebuffer = Framebuffer::with_storage(width, height, storage);
+        Self::with_framebuffer(framebuffer, desktop_name, password)
+    }
+
+    /// Creates a new `VncServer` that pulls pixels and damage from a `FrameSource`.
+    ///
+    /// The server does not keep its own copy of the screen; clients read the rectangles
+    /// they send directly from `source`. See the [`frame_source`](crate::frame_source)
+    /// module for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source that provides the frame contents and damage.
+    /// * `desktop_name` - The name of the desktop to be advertised to clients.
+    /// * `password` - An optional password for client authentication, checked by a
+    ///   [`PasswordAuthenticator`] (see [`set_authenticator`](Self::set_authenticator)).
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the `VncServer` instance and its event receiver.
+    #[must_use]
+    pub fn with_frame_source(
+        source: Arc<dyn FrameSource>,
+        desktop_name: String,
+        password: Option<String>,
+    ) -> (Self, mpsc::UnboundedReceiver<ServerEvent>) {
+        Self::with_framebuffer(Framebuffer::from_source(source), desktop_name, password)
+    }
+
+    /// Creates a server around an existing framebuffer.
+    fn with_framebuffer(
+        framebuffer: Framebuffer,
+        desktop_name: String,
+        password: Option<String>,
     ) -> (Self, mpsc::UnboundedReceiver<ServerEvent>) {
         let (event_tx, event_rx) = mpsc::unbounded_channel();
 
+        let desktops = HashMap::from([(PRIMARY_DESKTOP, framebuffer.clone())]);
+
         let server = Self {
-            framebuffer: Framebuffer::new(width, height),
-            desktop_name,
-            password,
+            framebuffer,
+            desktop_name: Arc::new(RwLock::new(desktop_name)),
+            authenticator: Arc::new(RwLock::new(Arc::new(PasswordAuthenticator::new(password)))),
             clients: Arc::new(RwLock::new(Vec::new())),
             client_write_streams: Arc::new(RwLock::new(Vec::new())),
             client_tasks: Arc::new(RwLock::new(Vec::new())),
             client_ids: Arc::new(RwLock::new(Vec::new())),
+            client_changes: Arc::new(Notify::new()),
+            client_commands: Arc::new(RwLock::new(HashMap::new())),
+            config: Arc::new(ServerConfig::default()),
+            encode_stats: Arc::new(EncodeStats::default()),
+            encode_pool: None,
+            sessions: Arc::default(),
+            draining: Arc::new(watch::Sender::new(false)),
+            desktops: Arc::new(RwLock::new(desktops)),
+            client_desktops: Arc::new(RwLock::new(HashMap::new())),
+            next_desktop_id: AtomicUsize::new(PRIMARY_DESKTOP + 1),
+            desktop_assigner: None,
+            access_list: Arc::default(),
+            audit: None,
+            led_state: Arc::default(),
             event_tx,
         };
 
-        (server, event_rx)
+        (server, event_rx)
+    }
+
+    /// Returns the server configuration.
+    #[must_use]
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Registers a vendor pseudo-encoding the application understands.
+    ///
+    /// Clients that advertise it produce a `ServerEvent::PseudoEncoding`. Like the rest of
+    /// the configuration, this applies to clients that connect afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - The pseudo-encoding number.
+    pub fn register_pseudo_encoding(&mut self, encoding: i32) {
+        let extensions = &mut Arc::make_mut(&mut self.config).extensions;
+        if !extensions.pseudo_encodings.contains(&encoding) {
+            extensions.pseudo_encodings.push(encoding);
+        }
+    }
+
+    /// Replaces the server configuration.
+    ///
+    /// The configuration is captured by each client when it connects, so the new settings
+    /// apply to clients connecting after this call. If the encoder thread settings
+    /// changed, a new pool of encoder threads is started for those clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The new configuration.
+    pub fn set_config(&mut self, config: ServerConfig) {
+        if config.encoding != self.config.encoding {
+            self.encode_pool = EncodePool::new(&config.encoding).unwrap_or_else(|e| {
+                error!("Failed to start encoder threads, encoding on client tasks: {e}");
+                None
+            });
+        }
+        self.config = Arc::new(config);
+    }
+
+    /// Sets the function that chooses the desktop for incoming connections.
+    ///
+    /// The assigner is called with the peer address of each accepted connection and
+    /// returns a desktop ID. Unknown IDs fall back to the primary desktop. Reverse and
+    /// repeater connections always start on the primary desktop; use
+    /// [`move_client_to_desktop`](Self::move_client_to_desktop) to reassign them.
+    ///
+    /// # Arguments
+    ///
+    /// * `assigner` - The assignment function, or `None` to put all clients on the
+    ///   primary desktop.
+    pub fn set_desktop_assigner(&mut self, assigner: Option<DesktopAssigner>) {
+        self.desktop_assigner = assigner;
+    }
+
+    /// Sets the audit log connections are recorded in.
+    ///
+    /// The log records connects, authentication successes and failures, disconnects and
+    /// clipboard transfers with timestamps and client identity; see the
+    /// [`audit`](crate::audit) module. Like the configuration, it is captured when the
+    /// server starts listening and when a session starts.
+    ///
+    /// # Arguments
+    ///
+    /// * `audit` - The audit log, or `None` to stop auditing.
+    pub fn set_audit_log(&mut self, audit: Option<AuditLog>) {
+        self.audit = audit;
+    }
+
+    /// Adds a virtual desktop.
+    ///
+    /// Each desktop is an independent framebuffer; the application updates it like the
+    /// primary one and receives input from its clients as usual (see
+    /// [`client_desktop`](Self::client_desktop) to tell which desktop a client is on).
+    ///
+    /// # Arguments
+    ///
+    /// * `framebuffer` - The framebuffer backing the desktop.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new desktop.
+    pub async fn add_desktop(&self, framebuffer: Framebuffer) -> usize {
+        let desktop_id = self.next_desktop_id.fetch_add(1, Ordering::Relaxed);
+        self.desktops.write().await.insert(desktop_id, framebuffer);
+        desktop_id
+    }
+
+    /// Removes a virtual desktop, moving its clients to the primary desktop.
+    ///
+    /// Clients that cannot be moved (see
+    /// [`move_client_to_desktop`](Self::move_client_to_desktop)) keep showing the removed
+    /// desktop until they disconnect.
+    ///
+    /// # Arguments
+    ///
+    /// * `desktop_id` - The desktop to remove.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the desktop existed, `false` if it didn't or is the primary desktop.
+    pub async fn remove_desktop(&self, desktop_id: usize) -> bool {
+        if desktop_id == PRIMARY_DESKTOP
+            || self.desktops.write().await.remove(&desktop_id).is_none()
+        {
+            return false;
+        }
+
+        let clients: Vec<usize> = self
+            .client_desktops
+            .read()
+            .await
+            .iter()
+            .filter(|&(_, &desktop)| desktop == desktop_id)
+            .map(|(&client_id, _)| client_id)
+            .collect();
+        for client_id in clients {
+            if !self
+                .move_client_to_desktop(client_id, PRIMARY_DESKTOP)
+                .await
+            {
+                log::warn!(
+                    "Client {client_id} could not be moved off removed desktop {desktop_id}"
+                );
+            }
+        }
+        true
+    }
+
+    /// Returns the framebuffer of a desktop.
+    ///
+    /// # Arguments
+    ///
+    /// * `desktop_id` - The desktop to look up.
+    ///
+    /// # Returns
+    ///
+    /// The desktop's framebuffer, or `None` if there is no such desktop.
+    pub async fn desktop(&self, desktop_id: usize) -> Option<Framebuffer> {
+        self.desktops.read().await.get(&desktop_id).cloned()
+    }
+
+    /// Returns the desktop a client is assigned to.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client to look up.
+    ///
+    /// # Returns
+    ///
+    /// The client's desktop ID, or `None` if the client is not connected.
+    pub async fn client_desktop(&self, client_id: usize) -> Option<usize> {
+        self.client_desktops.read().await.get(&client_id).copied()
+    }
+
+    /// Moves a client to another desktop.
+    ///
+    /// The client is sent the new desktop in full. If the desktop has a different size,
+    /// the client is resized through the `DesktopSize` pseudo-encoding; clients that don't
+    /// support it can only be moved between desktops of the same size.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client to move.
+    /// * `desktop_id` - The desktop to move it to.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the client was moved, `false` if the client or desktop doesn't exist or
+    /// the client cannot be resized to the desktop.
+    pub async fn move_client_to_desktop(&self, client_id: usize, desktop_id: usize) -> bool {
+        let Some(framebuffer) = self.desktop(desktop_id).await else {
+            return false;
+        };
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let command = ClientCommand::SwitchFramebuffer {
+            framebuffer,
+            reply: reply_tx,
+        };
+        if !self.send_client_command(client_id, command).await || !reply_rx.await.unwrap_or(false) {
+            return false;
+        }
+        if let Some(desktop) = self.client_desktops.write().await.get_mut(&client_id) {
+            *desktop = desktop_id;
+        }
+        true
+    }
+
+    /// Pauses or resumes a client's view.
+    ///
+    /// A paused client is shown
+    /// [`UpdateConfig::placeholder`](crate::config::UpdateConfig::placeholder) instead of
+    /// its framebuffer and its key, pointer and clipboard input is dropped, e.g. while the
+    /// host decides whether to let a viewer in (see `ConnectionConfig::start_paused`).
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client to pause or resume.
+    /// * `paused` - `true` to pause, `false` to resume.
+    ///
+    /// # Returns
+    ///
+    /// `false` if no client with this ID is connected.
+    pub async fn set_client_paused(&self, client_id: usize, paused: bool) -> bool {
+        self.send_client_command(client_id, ClientCommand::SetPaused(paused))
+            .await
+    }
+
+    /// Returns the encode and send latency histograms.
+    ///
+    /// Samples from all clients are collected since the server was created or the
+    /// statistics were last reset with [`EncodeStats::reset`].
+    #[must_use]
+    pub fn encode_stats(&self) -> &EncodeStats {
+        &self.encode_stats
+    }
+
+    /// Captures the shared server state needed to run a client session.
+    fn session_context(&self) -> SessionContext {
+        SessionContext {
+            framebuffer: self.framebuffer.clone(),
+            desktop_id: PRIMARY_DESKTOP,
+            client_desktops: self.client_desktops.clone(),
+            config: self.config.clone(),
+            encode_stats: self.encode_stats.clone(),
+            encode_pool: self.encode_pool.clone(),
+            sessions: self.sessions.clone(),
+            audit: self.audit.clone(),
+            led_state: self.led_state.clone(),
+            clients: self.clients.clone(),
+            client_write_streams: self.client_write_streams.clone(),
+            client_tasks: self.client_tasks.clone(),
+            client_ids: self.client_ids.clone(),
+            client_changes: self.client_changes.clone(),
+            client_commands: self.client_commands.clone(),
+            event_tx: self.event_tx.clone(),
+            secure_transport: false,
+            draining: self.draining.subscribe(),
+        }
+    }
+
+    /// Captures the state the accept loop needs, so it can run detached from `&self`.
+    fn acceptor(&self) -> Acceptor {
+        Acceptor {
+            desktop_name: self.desktop_name.clone(),
+            authenticator: self.authenticator.clone(),
+            desktops: self.desktops.clone(),
+            desktop_assigner: self.desktop_assigner.clone(),
+            access_list: self.access_list.clone(),
+            context: self.session_context(),
+        }
+    }
+
+    /// Starts the VNC server, listening for incoming client connections on the specified port.
+    ///
+    /// This function enters an infinite loop, accepting new TCP connections and spawning
+    /// a new asynchronous task to handle each client.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The TCP port on which the server will listen for connections.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the server starts draining (see [`drain`](Self::drain)); until then it
+    /// listens indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if there is an issue binding to the port or accepting connections.
+    pub async fn listen(&self, port: u16) -> Result<(), std::io::Error> {
+        let listener = TcpListener::bind(format!("0.0.0.0:{port}")).await?;
+        log::info!("VNC Server listening on port {port}");
+
+        self.listen_on(listener).await
+    }
+
+    /// Accepts client connections on a listener the caller has already bound.
+    ///
+    /// Binding first lets the caller report bind errors, such as the port being in use,
+    /// before handing the listener over, without releasing the port in between.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The bound listener to accept connections on.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the server starts draining (see [`drain`](Self::drain)); until then it
+    /// listens indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// Currently never returns an error; accept errors are logged and the loop continues.
+    pub async fn listen_on(&self, listener: TcpListener) -> Result<(), std::io::Error> {
+        self.acceptor().run(listener).await
+    }
+
+    /// Listens on the first free port of a range, accepting clients in the background.
+    ///
+    /// Ports are tried in order, so passing `5900..=5999` picks the lowest free VNC
+    /// display, as hosts running many servers side by side need. The chosen port is
+    /// returned and also reported as `ServerEvent::Listening`. Clients are accepted by a
+    /// background task that runs until the server shuts down; configuration changes made
+    /// after this call do not apply to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `ports` - The ports to try, in order.
+    ///
+    /// # Returns
+    ///
+    /// The port the server is listening on.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` of kind `AddrInUse` if every port in the range is
+    /// taken, or the bind error if a port fails for another reason (e.g. permissions).
+    pub async fn listen_auto(
+        &self,
+        ports: std::ops::RangeInclusive<u16>,
+    ) -> Result<u16, std::io::Error> {
+        let mut bound = None;
+        for port in ports.clone() {
+            match TcpListener::bind(format!("0.0.0.0:{port}")).await {
+                Ok(listener) => {
+                    bound = Some((port, listener));
+                    break;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let Some((port, listener)) = bound else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("No free port between {} and {}", ports.start(), ports.end()),
+            ));
+        };
+
+        let display = port.checked_sub(crate::DEFAULT_PORT);
+        match display {
+            Some(display) => log::info!("VNC Server listening on port {port} (display :{display})"),
+            None => log::info!("VNC Server listening on port {port}"),
+        }
+        let _ = self.event_tx.send(ServerEvent::Listening { port, display });
+
+        let acceptor = self.acceptor();
+        let handle = tokio::spawn(async move {
+            if let Err(e) = acceptor.run(listener).await {
+                error!("Listener on port {port} stopped: {e}");
+            }
+        });
+        self.client_tasks.write().await.push(handle);
+
+        Ok(port)
+    }
+
+    /// Listens for viewers over QUIC, running a session for each connection.
+    ///
+    /// Once a connection is established, the server opens one bidirectional stream on it
+    /// and runs the RFB handshake and session over that stream; viewers accept it with
+    /// `Connection::accept_bi`. QUIC is always encrypted, so these sessions are allowed
+    /// under `ConnectionConfig::require_encryption`. The access list, desktop assigner
+    /// and pending-handshake limit apply as for TCP clients. See the
+    /// [`transport`](crate::transport) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The UDP address to listen on.
+    /// * `server_config` - The QUIC configuration, including the server's TLS
+    ///   certificate.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the server starts draining (see [`drain`](Self::drain)); until then it
+    /// listens indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the UDP socket cannot be bound.
+    #[cfg(feature = "quic")]
+    pub async fn listen_quic(
+        &self,
+        addr: SocketAddr,
+        server_config: quinn::ServerConfig,
+    ) -> Result<(), std::io::Error> {
+        let socket = std::net::UdpSocket::bind(addr)?;
+        self.listen_quic_on(socket, server_config).await
+    }
+
+    /// Listens for viewers over QUIC on a UDP socket the caller has already bound.
+    ///
+    /// As with [`listen_on`](Self::listen_on), binding first lets the caller report bind
+    /// errors and learn the port before handing the socket over. Sessions run as for
+    /// [`listen_quic`](Self::listen_quic).
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - The bound UDP socket to accept connections on.
+    /// * `server_config` - The QUIC configuration, including the server's TLS
+    ///   certificate.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the server starts draining (see [`drain`](Self::drain)); until then it
+    /// listens indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the socket cannot be set up for QUIC, or if this is
+    /// not called from within a Tokio runtime.
+    #[cfg(feature = "quic")]
+    pub async fn listen_quic_on(
+        &self,
+        socket: std::net::UdpSocket,
+        server_config: quinn::ServerConfig,
+    ) -> Result<(), std::io::Error> {
+        let runtime = quinn::default_runtime()
+            .ok_or_else(|| std::io::Error::other("no async runtime for the QUIC endpoint"))?;
+        let endpoint = quinn::Endpoint::new(
+            quinn::EndpointConfig::default(),
+            Some(server_config),
+            socket,
+            runtime,
+        )?;
+        log::info!(
+            "VNC Server listening for QUIC on {}",
+            endpoint.local_addr()?
+        );
+
+        self.acceptor().run_quic(endpoint).await
+    }
+
+    /// Serves a read-only MJPEG preview of the framebuffer over HTTP.
+    ///
+    /// Any `GET` request on `addr` is answered with a `multipart/x-mixed-replace` stream of
+    /// JPEG frames that browsers can show in an `<img>` tag. The preview is not
+    /// authenticated, so bind it to a trusted address; the access list and the encryption
+    /// requirements of the configuration set before this call apply. See the
+    /// [`mjpeg`](crate::mjpeg) module for details. Like [`listen`](Self::listen), this
+    /// runs indefinitely.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to serve the preview on, e.g. `127.0.0.1:8080`.
+    /// * `config` - Frame rate, quality and timeout settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the address cannot be bound.
+    #[cfg(feature = "mjpeg")]
+    pub async fn serve_mjpeg(
+        &self,
+        addr: SocketAddr,
+        config: crate::mjpeg::MjpegConfig,
+    ) -> Result<(), std::io::Error> {
+        crate::mjpeg::serve(
+            self.framebuffer.clone(),
+            addr,
+            config,
+            self.access_list.clone(),
+            self.config.clone(),
+        )
+        .await
+    }
+
+    /// Runs a client session over a connection the application established itself.
+    ///
+    /// The RFB handshake and session run over `stream` exactly as for clients accepted by
+    /// [`listen`](Self::listen), on the primary desktop. This is how transports other than
+    /// TCP, such as WebRTC data channels, are plugged in; see the
+    /// [`transport`](crate::transport) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - A reliable, ordered byte stream to the viewer.
+    /// * `peer` - A description of the peer, reported as the address in
+    ///   `ServerEvent::AuthAttempt` and used in logs.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` when the client disconnects.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the handshake fails, the client ID counter
+    /// overflows or the server is draining.
+    pub async fn serve_stream<S: ClientStream>(
+        &self,
+        stream: S,
+        peer: String,
+    ) -> Result<(), std::io::Error> {
+        self.serve_stream_with(stream, peer, self.session_context())
+            .await
+    }
+
+    /// Runs a client session over a connection whose transport is already encrypted.
+    ///
+    /// Identical to [`serve_stream`](Self::serve_stream), except that the session is
+    /// allowed under `ConnectionConfig::require_encryption` whatever the peer's address.
+    /// Use this for TLS tunnels, QUIC streams and similar transports that the
+    /// application terminates itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - An encrypted, reliable, ordered byte stream to the viewer.
+    /// * `peer` - A description of the peer, reported as the address in
+    ///   `ServerEvent::AuthAttempt` and used in logs.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` when the client disconnects.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the handshake fails, the client ID counter
+    /// overflows or the server is draining.
+    pub async fn serve_secure_stream<S: ClientStream>(
+        &self,
+        stream: S,
+        peer: String,
+    ) -> Result<(), std::io::Error> {
+        let mut context = self.session_context();
+        context.secure_transport = true;
+        self.serve_stream_with(stream, peer, context).await
+    }
+
+    /// Runs an application-supplied stream as a session on the primary desktop.
+    async fn serve_stream_with<S: ClientStream>(
+        &self,
+        stream: S,
+        peer: String,
+        context: SessionContext,
+    ) -> Result<(), std::io::Error> {
+        if *context.draining.borrow() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                "Server is draining",
+            ));
+        }
+        let client_id = allocate_client_id()?;
+        let desktop_name = self.desktop_name.read().await.clone();
+        let authenticator = self.authenticator.read().await.clone();
+        Self::handle_client(
+            stream,
+            peer,
+            client_id,
+            desktop_name,
+            authenticator,
+            context,
+            None,
+        )
+        .await
+    }
+
+    /// Handles a newly connected VNC client through its entire lifecycle.
+    ///
+    /// This function performs the VNC handshake, creates a `VncClient` instance and runs
+    /// its session until disconnection.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The connection to the client
+    /// * `remote_host` - Description of the peer, used in events and logs
+    /// * `client_id` - Unique identifier assigned to this client
+    /// * `desktop_name` - Name of the desktop session
+    /// * `authenticator` - Checks the client's credentials
+    /// * `context` - Shared server state for the session
+    /// * `handshake_permit` - Pending-handshake slot, released once the handshake ends
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` when the client disconnects normally, or `Err` if an I/O error occurs or
+    /// the handshake exceeds `ConnectionConfig::handshake_timeout`.
+    #[allow(clippy::too_many_arguments)] // The session needs the connection, its identity and the server state
+    async fn handle_client<S: ClientStream>(
+        stream: S,
+        remote_host: String,
+        client_id: usize,
+        desktop_name: String,
+        authenticator: Arc<dyn Authenticator>,
+        context: SessionContext,
+        handshake_permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<(), std::io::Error> {
+        let (client_event_tx, mut client_event_rx) = mpsc::unbounded_channel();
+
+        let handshake = VncClient::from_stream(
+            client_id,
+            stream,
+            remote_host,
+            context.secure_transport,
+            context.framebuffer.clone(),
+            desktop_name,
+            authenticator,
+            context.config.clone(),
+            client_event_tx,
+        );
+        let handshake_timeout = context.config.connection.handshake_timeout;
+        let result = if handshake_timeout.is_zero() {
+            handshake.await
+        } else {
+            tokio::time::timeout(handshake_timeout, handshake)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "Handshake timed out",
+                    ))
+                })
+        };
+        drop(handshake_permit);
+
+        let client = match result {
+            Ok(client) => client,
+            Err(e) => {
+                Self::forward_handshake_events(&context, client_id, &mut client_event_rx);
+                return Err(e);
+            }
+        };
+
+        Self::run_session(context, client, client_event_rx, "Client").await;
+        Ok(())
+    }
+
+    /// Runs an established client session until the client disconnects.
+    ///
+    /// This registers the client with the framebuffer and the server's client lists,
+    /// spawns its message handler task, forwards its events as `ServerEvent`s, and
+    /// removes it from the server's client lists once it disconnects. Task handles,
+    /// write streams and client IDs are stored to enable cleanup during server shutdown.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - Shared server state
+    /// * `client` - The client, after a completed handshake
+    /// * `client_event_rx` - Receiver for the events produced by `client`
+    /// * `label` - Connection kind used in log messages (e.g. "Client", "Reverse client")
+    async fn run_session(
+        context: SessionContext,
+        mut client: VncClient,
+        mut client_event_rx: mpsc::UnboundedReceiver<ClientEvent>,
+        label: &'static str,
+    ) {
+        let client_id = client.get_client_id();
+        let certificate_subject = client.certificate_subject().map(str::to_string);
+        let address = client.get_remote_host().to_string();
+        client.set_encode_stats(context.encode_stats.clone());
+        client.set_encode_pool(context.encode_pool.clone());
+        client.set_session_store(context.sessions.clone());
+        client.set_audit_log(context.audit.clone());
+
+        // Register client to receive dirty region notifications (standard VNC protocol style)
+        context
+            .framebuffer
+            .register_receiver(client.damage_receiver())
+            .await;
+        if context.config.connection.start_paused {
+            if let Err(e) = client.set_paused(true).await {
+                error!("{label} {client_id} could not be paused: {e}");
+            }
+        }
+
+        // Store the write stream handle for direct socket shutdown
+        let write_stream = client.get_write_stream_handle();
+        context
+            .client_write_streams
+            .write()
+            .await
+            .push(write_stream.clone());
+
+        // Server-wide operations reach the client through its command channel, since the
+        // message handler holds the client's write lock for the whole session.
+        context
+            .client_commands
+            .write()
+            .await
+            .insert(client_id, client.command_sender());
+        // Read after registering, so a concurrent change is either seen here or queued
+        client.set_led_state(*context.led_state.read().await);
+
+        context
+            .client_desktops
+            .write()
+            .await
+            .insert(client_id, context.desktop_id);
+
+        let client_arc = Arc::new(RwLock::new(client));
+        context.clients.write().await.push(client_arc.clone());
+        context.client_ids.write().await.push(client_id);
+        context.client_changes.notify_waiters();
+
+        // Report the handshake (authentication) before the connection
+        Self::forward_handshake_events(&context, client_id, &mut client_event_rx);
+        if let Some(audit) = &context.audit {
+            audit.record(
+                client_id,
+                &address,
+                AuditEvent::Connected {
+                    certificate_subject: certificate_subject.clone(),
+                },
+            );
+        }
+        let _ = context.event_tx.send(ServerEvent::ClientConnected {
+            client_id,
+            certificate_subject,
+        });
+
+        // Spawn task to handle client messages and store handle for joining
+        let client_arc_clone = client_arc.clone();
+        let msg_handle = tokio::spawn(async move {
+            let result = {
+                let mut client = client_arc_clone.write().await;
+                client.handle_messages().await
+            };
+            if let Err(e) = result {
+                error!("{label} {client_id} message handling error: {e}");
+            }
+        });
+
+        // Store the message handler task handle for joining later
+        context.client_tasks.write().await.push(msg_handle);
+
+        // Handle client events
+        while let Some(event) = client_event_rx.recv().await {
+            if !Self::forward_client_event(&context, client_id, event) {
+                break;
+            }
+        }
+
+        // Remove client from list
+        let mut clients_guard = context.clients.write().await;
+        clients_guard.retain(|c| !Arc::ptr_eq(c, &client_arc));
+        drop(clients_guard);
+
+        let mut client_ids_guard = context.client_ids.write().await;
+        client_ids_guard.retain(|&id| id != client_id);
+        drop(client_ids_guard);
+        context.client_changes.notify_waiters();
+
+        context.client_commands.write().await.remove(&client_id);
+        context.client_desktops.write().await.remove(&client_id);
+
+        // Drop the shutdown handle so the socket is closed once the client is gone
+        context
+            .client_write_streams
+            .write()
+            .await
+            .retain(|s| !Arc::ptr_eq(s, &write_stream));
+        drop(write_stream);
+
+        let _ = context
+            .event_tx
+            .send(ServerEvent::ClientDisconnected { client_id });
+        if let Some(audit) = &context.audit {
+            audit.record(client_id, &address, AuditEvent::Disconnected);
+        }
+
+        log::info!("{label} {client_id} disconnected");
+    }
+
+    /// Translates a `ClientEvent` into a `ServerEvent` and sends it to the application,
+    /// recording authentication in the audit log.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the event was `ClientEvent::Disconnected`, `true` otherwise.
+    fn forward_client_event(
+        context: &SessionContext,
+        client_id: usize,
+        event: ClientEvent,
+    ) -> bool {
+        let server_event = match event {
+            ClientEvent::KeyPress { down, key } => ServerEvent::KeyPress {
+                client_id,
+                down,
+                key,
+            },
+            ClientEvent::PointerMove { x, y, button_mask } => ServerEvent::PointerMove {
+                client_id,
+                x,
+                y,
+                button_mask,
+            },
+            ClientEvent::CutText { text } => ServerEvent::CutText { client_id, text },
+            ClientEvent::AuthAttempt {
+                address,
+                method,
+                success,
+            } => {
+                if let Some(audit) = &context.audit {
+                    let event = if success {
+                        AuditEvent::AuthSucceeded { method }
+                    } else {
+                        AuditEvent::AuthFailed { method }
+                    };
+                    audit.record(client_id, &address, event);
+                }
+                ServerEvent::AuthAttempt {
+                    client_id,
+                    address,
+                    method,
+                    success,
+                }
+            }
+            ClientEvent::HandshakeTimeout { address, phase } => ServerEvent::HandshakeTimeout {
+                client_id,
+                address,
+                phase,
+            },
+            ClientEvent::ResizeRequest {
+                width,
+                height,
+                screens,
+                reply,
+            } => ServerEvent::ResizeRequest {
+                client_id,
+                width,
+                height,
+                screens,
+                reply,
+            },
+            ClientEvent::PseudoEncoding { encoding } => ServerEvent::PseudoEncoding {
+                client_id,
+                encoding,
+            },
+            ClientEvent::UpdateTiming { timing } => ServerEvent::UpdateTiming { client_id, timing },
+            ClientEvent::Negotiated { negotiation } => ServerEvent::Negotiated {
+                client_id,
+                negotiation,
+            },
+            ClientEvent::ExtendedInput { event } => ServerEvent::ExtendedInput { client_id, event },
+            ClientEvent::Disconnected => return false,
+        };
+        let _ = context.event_tx.send(server_event);
+        true
+    }
+
+    /// Forwards the events a client produced during its handshake.
+    fn forward_handshake_events(
+        context: &SessionContext,
+        client_id: usize,
+        client_event_rx: &mut mpsc::UnboundedReceiver<ClientEvent>,
+    ) {
+        while let Ok(event) = client_event_rx.try_recv() {
+            Self::forward_client_event(context, client_id, event);
+        }
+    }
+
+    /// Returns a reference to the server's `Framebuffer`.
+    ///
+    /// This allows external components to inspect or modify the framebuffer content.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the `Framebuffer` instance.
+    #[must_use]
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// Resizes the primary desktop's framebuffer, e.g. after a display change.
+    ///
+    /// The content that still fits is kept and the rest is cleared. Clients that
+    /// advertised the `DesktopSize` pseudo-encoding are told the new size and sent the
+    /// whole framebuffer; the others are handled according to
+    /// [`UpdateConfig::resize_policy`](crate::config::UpdateConfig::resize_policy).
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The new width in pixels.
+    /// * `height` - The new height in pixels.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the dimensions are invalid or the framebuffer is backed by
+    /// a `FrameSource`.
+    pub async fn resize(&self, width: u16, height: u16) -> Result<(), String> {
+        self.framebuffer.resize(width, height).await?;
+        log::info!("Framebuffer resized to {width}x{height}");
+        Ok(())
+    }
+
+    /// Returns a mutable reference to the server's `Framebuffer`.
+    ///
+    /// This allows external components to modify the framebuffer, including resizing.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Framebuffer` instance.
+    #[allow(dead_code)]
+    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    /// Sets or clears the password required for new client connections.
+    ///
+    /// This replaces the authenticator with a [`PasswordAuthenticator`]. Clients that are
+    /// already connected are not affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - The new password, or `None` to allow connections without authentication.
+    pub async fn set_password(&self, password: Option<String>) {
+        self.set_authenticator(Arc::new(PasswordAuthenticator::new(password)))
+            .await;
+    }
+
+    /// Sets the authenticator that checks the credentials of new client connections.
+    ///
+    /// The authenticator also decides which security types are offered (see
+    /// [`Authenticator`]); with one that
+    /// [`accepts_usernames`](Authenticator::accepts_usernames), `UltraVNC` viewers can log
+    /// in with an account through MS-Logon II. Clients that are already connected are not
+    /// affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `authenticator` - The authenticator to use from now on.
+    pub async fn set_authenticator(&self, authenticator: Arc<dyn Authenticator>) {
+        *self.authenticator.write().await = authenticator;
+    }
+
+    /// Allows connections from a network.
+    ///
+    /// Once any network is allowed, [`listen`](Self::listen) and
+    /// [`listen_auto`](Self::listen_auto) close connections from hosts outside the allowed
+    /// networks as soon as they are accepted. Denied networks take precedence. Takes effect
+    /// for the next accepted connection, also on listeners that are already running;
+    /// clients that are already connected are not affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - The network to allow, e.g. `"192.168.0.0/16".parse()?`.
+    pub async fn allow_network(&self, network: IpNetwork) {
+        self.access_list.write().await.allow(network);
+    }
+
+    /// Denies connections from a network.
+    ///
+    /// Connections from hosts in a denied network are closed as soon as they are accepted,
+    /// even if the host is also in an allowed network. Like
+    /// [`allow_network`](Self::allow_network), this applies to running listeners.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - The network to deny.
+    pub async fn deny_network(&self, network: IpNetwork) {
+        self.access_list.write().await.deny(network);
+    }
+
+    /// Removes a network added with [`allow_network`](Self::allow_network).
+    ///
+    /// # Returns
+    ///
+    /// `true` if the network was allowed.
+    pub async fn remove_allowed_network(&self, network: IpNetwork) -> bool {
+        self.access_list.write().await.remove_allowed(network)
+    }
+
+    /// Removes a network added with [`deny_network`](Self::deny_network).
+    ///
+    /// # Returns
+    ///
+    /// `true` if the network was denied.
+    pub async fn remove_denied_network(&self, network: IpNetwork) -> bool {
+        self.access_list.write().await.remove_denied(network)
+    }
+
+    /// Returns the current access list.
+    pub async fn access_list(&self) -> AccessList {
+        self.access_list.read().await.clone()
+    }
+
+    /// Replaces the access list, e.g. with one loaded from the application's
+    /// configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_list` - The allowed and denied networks.
+    pub async fn set_access_list(&self, access_list: AccessList) {
+        *self.access_list.write().await = access_list;
+    }
+
+    /// Returns the name of the desktop.
+    pub async fn desktop_name(&self) -> String {
+        self.desktop_name.read().await.clone()
+    }
+
+    /// Renames the desktop.
+    ///
+    /// New clients get the name in `ServerInit`. Connected clients that support the
+    /// `DesktopName` pseudo-encoding are told right away (viewers usually show it in the
+    /// window title); the others keep the name they connected with.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The new desktop name.
+    pub async fn set_desktop_name(&self, name: String) {
+        self.desktop_name.write().await.clone_from(&name);
+        let commands = self.client_commands.read().await;
+        for command_tx in commands.values() {
+            let _ = command_tx.send(ClientCommand::SetDesktopName(name.clone()));
+        }
+    }
+
+    /// Reports the state of the keyboard lock LEDs to viewers.
+    ///
+    /// Clients that support the QEMU or `VMware` LED state pseudo-encoding are told right
+    /// away; clients that connect later are told when they advertise it.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The current Scroll, Num and Caps Lock state.
+    pub async fn set_led_state(&self, state: LedState) {
+        *self.led_state.write().await = Some(state);
+        let commands = self.client_commands.read().await;
+        for command_tx in commands.values() {
+            let _ = command_tx.send(ClientCommand::SetLedState(state));
+        }
+    }
+
+    /// Returns the keyboard lock LED state last reported with
+    /// [`set_led_state`](Self::set_led_state).
+    pub async fn led_state(&self) -> Option<LedState> {
+        *self.led_state.read().await
     }
 
-    /// Starts the VNC server, listening for incoming client connections on the specified port.
+    /// Sends the provided cut text (clipboard) to all currently connected VNC clients.
     ///
-    /// This function enters an infinite loop, accepting new TCP connections and spawning
-    /// a new asynchronous task to handle each client.
+    /// The text is queued on each client's command channel and written by the client's
+    /// own task, subject to the clipboard limits in [`ServerConfig`].
     ///
     /// # Arguments
     ///
-    /// * `port` - The TCP port on which the server will listen for connections.
+    /// * `text` - The string content to be sent as cut text.
     ///
     /// # Returns
     ///
-    /// `Ok(())` if the server starts successfully and listens indefinitely.
+    /// `Ok(())` if the text is successfully queued for sending to all clients.
     ///
     /// # Errors
     ///
-    /// Returns `Err(std::io::Error)` if there is an issue binding to the port or accepting connections.
-    #[allow(clippy::cast_possible_truncation)] // Client ID counter limited to u64::MAX, safe on 64-bit platforms
-    pub async fn listen(&self, port: u16) -> Result<(), std::io::Error> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{port}")).await?;
-        log::info!("VNC Server listening on port {port}");
-
-        loop {
-            match listener.accept().await {
-                Ok((stream, addr)) => {
-                    #[cfg(feature = "debug-logging")]
-                    info!("New VNC client connection from: {addr}");
-
-                    // Safely increment client ID counter and check for overflow
-                    let client_id_raw = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
-                    if client_id_raw == 0 || client_id_raw >= u64::MAX - 1000 {
-                        error!("Client ID counter overflow, rejecting connection from {addr}");
-                        continue;
-                    }
-                    let client_id = client_id_raw as usize;
-
-                    let framebuffer = self.framebuffer.clone();
-                    let desktop_name = self.desktop_name.clone();
-                    let password = self.password.clone();
-                    let clients = self.clients.clone();
-                    let client_write_streams = self.client_write_streams.clone();
-                    let client_tasks = self.client_tasks.clone();
-                    let client_tasks_for_spawn = client_tasks.clone();
-                    let client_ids = self.client_ids.clone();
-                    let server_event_tx = self.event_tx.clone();
-
-                    let handle = tokio::spawn(async move {
-                        if let Err(e) = Self::handle_client(
-                            stream,
-                            client_id,
-                            framebuffer,
-                            desktop_name,
-                            password,
-                            clients,
-                            client_write_streams,
-                            client_tasks_for_spawn,
-                            client_ids,
-                            server_event_tx,
-                        )
-                        .await
-                        {
-                            error!("Client {client_id} error: {e}");
-                        }
-                    });
-
-                    // Store the handle_client task handle for joining later
-                    client_tasks.write().await.push(handle);
-                }
-                Err(e) => {
-                    error!("Error accepting connection: {e}");
-                }
-            }
+    /// Currently always succeeds; clients that disconnect before the text is sent are skipped.
+    pub async fn send_cut_text_to_all(&self, text: String) -> Result<(), std::io::Error> {
+        let commands = self.client_commands.read().await;
+        for command_tx in commands.values() {
+            let _ = command_tx.send(ClientCommand::CutText(text.clone()));
         }
+        Ok(())
     }
 
-    /// Handles a newly connected VNC client through its entire lifecycle.
+    /// Queues a command for a client's message loop.
+    ///
+    /// # Returns
     ///
-    /// This function performs the VNC handshake, creates a `VncClient` instance, spawns
-    /// a message handler task, and processes client events until disconnection. It stores
-    /// all necessary handles (task handles, write streams, client IDs) to enable proper
-    /// cleanup during server shutdown.
+    /// `true` if the client is connected and the command was queued.
+    async fn send_client_command(&self, client_id: usize, command: ClientCommand) -> bool {
+        self.client_commands
+            .read()
+            .await
+            .get(&client_id)
+            .is_some_and(|command_tx| command_tx.send(command).is_ok())
+    }
+
+    /// Overrides the JPEG quality used for a client, regardless of the quality level the
+    /// client requested through pseudo-encodings.
+    ///
+    /// The quality is clamped to 0-100 and takes effect with the next framebuffer update.
+    /// For Tight encoding it is mapped to the nearest VNC quality level.
     ///
     /// # Arguments
     ///
-    /// * `stream` - The TCP stream for the connected client
-    /// * `client_id` - Unique identifier assigned to this client
-    /// * `framebuffer` - The framebuffer to send to the client
-    /// * `desktop_name` - Name of the desktop session
-    /// * `password` - Optional password for authentication
-    /// * `clients` - Shared list of all connected `VncClient` instances
-    /// * `client_write_streams` - Shared list of write stream handles for socket shutdown
-    /// * `client_tasks` - Shared list of task handles for cleanup during shutdown
-    /// * `client_ids` - Shared list of client IDs for fast lookup during shutdown
-    /// * `server_event_tx` - Channel for sending server events (connect/disconnect/input)
+    /// * `client_id` - The client to adjust.
+    /// * `quality` - The JPEG quality, or `None` to return to the client's own setting.
     ///
     /// # Returns
     ///
-    /// `Ok(())` when the client disconnects normally, or `Err` if an I/O error occurs.
-    #[allow(clippy::too_many_arguments)] // VNC protocol handler requires all shared server state
-    async fn handle_client(
-        stream: TcpStream,
-        client_id: usize,
-        framebuffer: Framebuffer,
-        desktop_name: String,
-        password: Option<String>,
-        clients: Arc<RwLock<Vec<Arc<RwLock<VncClient>>>>>,
-        client_write_streams: Arc<
-            RwLock<Vec<Arc<tokio::sync::Mutex<tokio::net::tcp::OwnedWriteHalf>>>>,
-        >,
-        client_tasks: Arc<RwLock<Vec<tokio::task::JoinHandle<()>>>>,
-        client_ids: Arc<RwLock<Vec<usize>>>,
-        server_event_tx: mpsc::UnboundedSender<ServerEvent>,
-    ) -> Result<(), std::io::Error> {
-        let (client_event_tx, mut client_event_rx) = mpsc::unbounded_channel();
-
-        let client = VncClient::new(
-            client_id,
-            stream,
-            framebuffer.clone(),
-            desktop_name,
-            password,
-            client_event_tx,
-        )
-        .await?;
-
-        let client_arc = Arc::new(RwLock::new(client));
-
-        // Register client to receive dirty region notifications (standard VNC protocol style)
-        let regions_arc = client_arc.read().await.get_receiver_handle();
-        let receiver = DirtyRegionReceiver::new(Arc::downgrade(&regions_arc));
-        framebuffer.register_receiver(receiver).await;
-
-        // Store the write stream handle for direct socket shutdown
-        let write_stream_handle = {
-            let client = client_arc.read().await;
-            client.get_write_stream_handle()
-        };
-        client_write_streams.write().await.push(write_stream_handle);
-
-        clients.write().await.push(client_arc.clone());
-        client_ids.write().await.push(client_id);
-
-        let _ = server_event_tx.send(ServerEvent::ClientConnected { client_id });
-
-        // Spawn task to handle client messages and store handle for joining
-        // Note: The message handler holds a write lock for its duration, which means
-        // operations like send_cut_text() will wait for the lock. This is acceptable
-        // since clipboard operations are infrequent and the async lock prevents deadlocks.
-        let client_arc_clone = client_arc.clone();
-        let msg_handle = tokio::spawn(async move {
-            let result = {
-                let mut client = client_arc_clone.write().await;
-                client.handle_messages().await
-            };
-            if let Err(e) = result {
-                error!("Client {client_id} message handling error: {e}");
-            }
-        });
-
-        // Store the message handler task handle for joining later
-        client_tasks.write().await.push(msg_handle);
-
-        // Handle client events
-        while let Some(event) = client_event_rx.recv().await {
-            match event {
-                ClientEvent::KeyPress { down, key } => {
-                    let _ = server_event_tx.send(ServerEvent::KeyPress {
-                        client_id,
-                        down,
-                        key,
-                    });
-                }
-                ClientEvent::PointerMove { x, y, button_mask } => {
-                    let _ = server_event_tx.send(ServerEvent::PointerMove {
-                        client_id,
-                        x,
-                        y,
-                        button_mask,
-                    });
-                }
-                ClientEvent::CutText { text } => {
-                    let _ = server_event_tx.send(ServerEvent::CutText { client_id, text });
-                }
-                ClientEvent::Disconnected => {
-                    break;
-                }
-            }
-        }
-
-        // Remove client from list
-        let mut clients_guard = clients.write().await;
-        clients_guard.retain(|c| !Arc::ptr_eq(c, &client_arc));
-        drop(clients_guard);
-
-        let mut client_ids_guard = client_ids.write().await;
-        client_ids_guard.retain(|&id| id != client_id);
-        drop(client_ids_guard);
-
-        let _ = server_event_tx.send(ServerEvent::ClientDisconnected { client_id });
-
-        log::info!("Client {client_id} disconnected");
-        Ok(())
+    /// `true` if the client was found, `false` otherwise.
+    pub async fn set_client_jpeg_quality(&self, client_id: usize, quality: Option<u8>) -> bool {
+        self.send_client_command(client_id, ClientCommand::SetJpegQuality(quality))
+            .await
     }
 
-    /// Returns a reference to the server's `Framebuffer`.
+    /// Overrides the zlib compression level used for a client, regardless of the
+    /// compression level the client requested through pseudo-encodings.
     ///
-    /// This allows external components to inspect or modify the framebuffer content.
+    /// The level is clamped to 0-9 and takes effect with the next framebuffer update.
+    /// Persistent zlib streams that are already initialized keep their level.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client to adjust.
+    /// * `level` - The compression level, or `None` to return to the client's own setting.
     ///
     /// # Returns
     ///
-    /// A reference to the `Framebuffer` instance.
-    #[must_use]
-    pub fn framebuffer(&self) -> &Framebuffer {
-        &self.framebuffer
+    /// `true` if the client was found, `false` otherwise.
+    pub async fn set_client_compression(&self, client_id: usize, level: Option<u8>) -> bool {
+        self.send_client_command(client_id, ClientCommand::SetCompression(level))
+            .await
     }
 
-    /// Returns a mutable reference to the server's `Framebuffer`.
+    /// Overrides the color reduction applied to a client's updates, e.g. to send one
+    /// monitoring client grayscale while the others keep full color.
     ///
-    /// This allows external components to modify the framebuffer, including resizing.
+    /// The client's screen is sent again in the new colors. In grayscale, JPEG rectangles
+    /// are compressed without chroma.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client to adjust.
+    /// * `mode` - The color reduction, or `None` to return to `UpdateConfig::color_mode`.
     ///
     /// # Returns
     ///
-    /// A mutable reference to the `Framebuffer` instance.
-    #[allow(dead_code)]
-    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
-        &mut self.framebuffer
+    /// `true` if the client was found, `false` otherwise.
+    pub async fn set_client_color_mode(&self, client_id: usize, mode: Option<ColorMode>) -> bool {
+        self.send_client_command(client_id, ClientCommand::SetColorMode(mode))
+            .await
     }
 
-    /// Sends the provided cut text (clipboard) to all currently connected VNC clients.
+    /// Caps a client's update rate, e.g. a few updates per second for a dashboard wall
+    /// while an operator's client keeps a high rate.
+    ///
+    /// The cap is enforced when the client's updates are scheduled: damage arriving
+    /// faster is batched into the next update. It is an upper bound; the client's own
+    /// update requests and the deferral of constant damage
+    /// (`UpdateConfig::max_defer_time`) can keep the rate lower.
     ///
     /// # Arguments
     ///
-    /// * `text` - The string content to be sent as cut text.
+    /// * `client_id` - The client to adjust.
+    /// * `fps` - The maximum number of updates per second (at least 1), or `None` to
+    ///   return to `UpdateConfig::min_update_interval`.
     ///
     /// # Returns
     ///
-    /// `Ok(())` if the text is successfully queued for sending to all clients.
+    /// `true` if the client was found, `false` otherwise.
+    pub async fn set_client_max_fps(&self, client_id: usize, fps: Option<u32>) -> bool {
+        self.send_client_command(client_id, ClientCommand::SetMaxFps(fps))
+            .await
+    }
+
+    /// Adds a pre-encoded rectangle to a client's next framebuffer update.
     ///
-    /// # Errors
+    /// This is a low-level hook for experimenting with vendor or custom encodings without
+    /// changing the server's update batching. The header and payload are written verbatim
+    /// after the rectangles the server encoded itself, and the update is sent as soon as
+    /// the client has requested one. The caller is responsible for sending only encodings
+    /// the client announced in `SetEncodings` and for encoding the payload in the client's
+    /// pixel format; a malformed payload will desynchronize the client.
     ///
-    /// Returns `Err(std::io::Error)` if an error occurs during the sending process to any client.
-    pub async fn send_cut_text_to_all(&self, text: String) -> Result<(), std::io::Error> {
-        // Clone the client list before iterating to avoid holding read lock
-        // This prevents deadlock with client message handlers
-        let clients_snapshot = {
-            let clients = self.clients.read().await;
-            clients.clone()
-        };
+    /// # Arguments
+    ///
+    /// * `client_id` - The client to send the rectangle to.
+    /// * `rect` - The rectangle header (position, size and encoding type).
+    /// * `payload` - The encoded rectangle data.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the client was found, `false` otherwise.
+    pub async fn send_rect(&self, client_id: usize, rect: Rectangle, payload: Vec<u8>) -> bool {
+        self.send_client_command(client_id, ClientCommand::SendRect { rect, payload })
+            .await
+    }
 
-        for client in &clients_snapshot {
-            let mut client_guard = client.write().await;
-            let _ = client_guard.send_cut_text(text.clone()).await;
+    /// Returns the memory currently attributable to a client.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The client to query.
+    ///
+    /// # Returns
+    ///
+    /// The client's memory usage, or `None` if the client is not connected.
+    pub async fn client_memory_usage(&self, client_id: usize) -> Option<ClientMemoryUsage> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if !self
+            .send_client_command(client_id, ClientCommand::QueryMemory(reply_tx))
+            .await
+        {
+            return None;
         }
-        Ok(())
+        reply_rx.await.ok()
     }
 
     /// Establishes a direct reverse VNC connection to a client viewer.
@@ -430,27 +2075,15 @@ impl VncServer {
     /// # Errors
     ///
     /// Returns `Err(std::io::Error)` if the connection fails or a client ID overflow occurs.
-    #[allow(clippy::too_many_lines)] // VNC reverse connection protocol requires complete handshake and error handling
-    #[allow(clippy::cast_possible_truncation)] // Client ID counter limited to u64::MAX, safe on 64-bit platforms
     pub async fn connect_reverse(&self, host: String, port: u16) -> Result<usize, std::io::Error> {
-        // Safely increment client ID counter and check for overflow
-        let client_id_raw = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
-        if client_id_raw == 0 || client_id_raw >= u64::MAX - 1000 {
-            return Err(std::io::Error::other("Client ID counter overflow"));
-        }
-        let client_id = client_id_raw as usize;
+        let client_id = allocate_client_id()?;
 
         #[cfg(feature = "debug-logging")]
         info!("Initiating reverse VNC connection to {host}:{port}");
 
-        let framebuffer = self.framebuffer.clone();
-        let desktop_name = self.desktop_name.clone();
-        let password = self.password.clone();
-        let clients = self.clients.clone();
-        let client_write_streams = self.client_write_streams.clone();
-        let client_tasks = self.client_tasks.clone();
-        let client_ids = self.client_ids.clone();
-        let server_event_tx = self.event_tx.clone();
+        let desktop_name = self.desktop_name.read().await.clone();
+        let authenticator = self.authenticator.read().await.clone();
+        let context = self.session_context();
 
         // Use oneshot channel to wait for connection result before returning
         let (result_tx, result_rx) = tokio::sync::oneshot::channel();
@@ -470,9 +2103,10 @@ impl VncServer {
                     let client_result = VncClient::new(
                         client_id,
                         stream,
-                        framebuffer.clone(),
+                        context.framebuffer.clone(),
                         desktop_name,
-                        password,
+                        authenticator,
+                        context.config.clone(),
                         client_event_tx,
                     )
                     .await;
@@ -492,86 +2126,15 @@ impl VncServer {
 
                             log::info!("Reverse connection {client_id} established");
 
-                            let client_arc = Arc::new(RwLock::new(client));
-
-                            // Register client to receive dirty region notifications
-                            let regions_arc = client_arc.read().await.get_receiver_handle();
-                            let receiver = DirtyRegionReceiver::new(Arc::downgrade(&regions_arc));
-                            framebuffer.register_receiver(receiver).await;
-
-                            // Store the write stream handle for direct socket shutdown
-                            let write_stream_handle = {
-                                let client = client_arc.read().await;
-                                client.get_write_stream_handle()
-                            };
-                            client_write_streams.write().await.push(write_stream_handle);
-
-                            clients.write().await.push(client_arc.clone());
-                            client_ids.write().await.push(client_id);
-
-                            let _ =
-                                server_event_tx.send(ServerEvent::ClientConnected { client_id });
-
-                            // Spawn task to handle client messages
-                            let client_arc_clone = client_arc.clone();
-                            let msg_handle = tokio::spawn(async move {
-                                let result = {
-                                    let mut client = client_arc_clone.write().await;
-                                    client.handle_messages().await
-                                };
-                                if let Err(e) = result {
-                                    error!(
-                                        "Reverse client {client_id} message handling error: {e}"
-                                    );
-                                }
-                            });
-
-                            // Store the message handler task handle
-                            client_tasks.write().await.push(msg_handle);
-
-                            // Handle client events
-                            while let Some(event) = client_event_rx.recv().await {
-                                match event {
-                                    ClientEvent::KeyPress { down, key } => {
-                                        let _ = server_event_tx.send(ServerEvent::KeyPress {
-                                            client_id,
-                                            down,
-                                            key,
-                                        });
-                                    }
-                                    ClientEvent::PointerMove { x, y, button_mask } => {
-                                        let _ = server_event_tx.send(ServerEvent::PointerMove {
-                                            client_id,
-                                            x,
-                                            y,
-                                            button_mask,
-                                        });
-                                    }
-                                    ClientEvent::CutText { text } => {
-                                        let _ = server_event_tx
-                                            .send(ServerEvent::CutText { client_id, text });
-                                    }
-                                    ClientEvent::Disconnected => {
-                                        break;
-                                    }
-                                }
-                            }
-
-                            // Remove client from list
-                            let mut clients_guard = clients.write().await;
-                            clients_guard.retain(|c| !Arc::ptr_eq(c, &client_arc));
-                            drop(clients_guard);
-
-                            let mut client_ids_guard = client_ids.write().await;
-                            client_ids_guard.retain(|&id| id != client_id);
-                            drop(client_ids_guard);
-
-                            let _ =
-                                server_event_tx.send(ServerEvent::ClientDisconnected { client_id });
-
-                            log::info!("Reverse client {client_id} disconnected");
+                            Self::run_session(context, client, client_event_rx, "Reverse client")
+                                .await;
                         }
                         Err(e) => {
+                            Self::forward_handshake_events(
+                                &context,
+                                client_id,
+                                &mut client_event_rx,
+                            );
                             error!("Failed to initialize VNC client for reverse connection: {e}");
                         }
                     }
@@ -593,6 +2156,139 @@ impl VncServer {
         }
     }
 
+    /// Establishes a persistent reverse VNC connection to a client viewer.
+    ///
+    /// Unlike [`connect_reverse`](Self::connect_reverse), this keeps dialing `host:port`
+    /// in the background: failed attempts and dropped sessions are retried after an
+    /// exponentially growing, jittered delay, until the `policy` retry budget is exhausted
+    /// or the connection is stopped. Every state change is reported as a
+    /// `ServerEvent::ReverseConnection`. This suits unattended machines that dial out to a
+    /// central console.
+    ///
+    /// The authenticator is re-read for each attempt, so `set_password` and
+    /// `set_authenticator` apply to reconnects.
+    /// `disconnect_all_clients` also ends the persistent connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The hostname or IP address of the VNC viewer.
+    /// * `port` - The port on which the VNC viewer is listening.
+    /// * `policy` - Backoff and retry budget.
+    ///
+    /// # Returns
+    ///
+    /// A [`ReverseConnectionHandle`] for stopping the connection.
+    pub async fn connect_reverse_persistent(
+        &self,
+        host: String,
+        port: u16,
+        policy: ReconnectPolicy,
+    ) -> ReverseConnectionHandle {
+        let handle = ReverseConnectionHandle {
+            stopped: Arc::new(AtomicBool::new(false)),
+            wake: Arc::new(Notify::new()),
+        };
+
+        let desktop_name = self.desktop_name.clone();
+        let authenticator = self.authenticator.clone();
+        let context = self.session_context();
+        let task_handle = handle.clone();
+
+        let task = tokio::spawn(async move {
+            let notify = |status: ReverseConnectionStatus| {
+                let _ = context.event_tx.send(ServerEvent::ReverseConnection {
+                    host: host.clone(),
+                    port,
+                    status,
+                });
+            };
+            // Consecutive failed attempts since the last successful connection
+            let mut failures: u32 = 0;
+            // A draining server makes no new connections
+            let mut draining = context.draining.clone();
+
+            while !task_handle.is_stopped() && !*draining.borrow() {
+                let attempt = failures + 1;
+                notify(ReverseConnectionStatus::Connecting { attempt });
+
+                let Ok(client_id) = allocate_client_id() else {
+                    error!(
+                        "Client ID counter overflow, stopping reverse connection to {host}:{port}"
+                    );
+                    notify(ReverseConnectionStatus::GaveUp { attempts: failures });
+                    return;
+                };
+                let (client_event_tx, mut client_event_rx) = mpsc::unbounded_channel();
+                let result = match TcpStream::connect(format!("{host}:{port}")).await {
+                    Ok(stream) => {
+                        VncClient::new(
+                            client_id,
+                            stream,
+                            context.framebuffer.clone(),
+                            desktop_name.read().await.clone(),
+                            authenticator.read().await.clone(),
+                            context.config.clone(),
+                            client_event_tx,
+                        )
+                        .await
+                    }
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok(mut client) => {
+                        client.set_connection_metadata(Some(port));
+                        failures = 0;
+                        log::info!("Persistent reverse connection {client_id} established");
+                        notify(ReverseConnectionStatus::Connected { client_id });
+
+                        Self::run_session(
+                            context.clone(),
+                            client,
+                            client_event_rx,
+                            "Reverse client",
+                        )
+                        .await;
+                        notify(ReverseConnectionStatus::Disconnected { client_id });
+                    }
+                    Err(e) => {
+                        Self::forward_handshake_events(&context, client_id, &mut client_event_rx);
+                        error!("Persistent reverse connection to {host}:{port} failed: {e}");
+                        failures += 1;
+                        notify(ReverseConnectionStatus::Failed {
+                            attempt,
+                            error: e.to_string(),
+                        });
+                        if policy.max_retries.is_some_and(|max| failures > max) {
+                            notify(ReverseConnectionStatus::GaveUp { attempts: failures });
+                            return;
+                        }
+                    }
+                }
+
+                if task_handle.is_stopped() || *draining.borrow() {
+                    break;
+                }
+                let delay = policy.delay(failures);
+                notify(ReverseConnectionStatus::RetryScheduled {
+                    attempt: failures + 1,
+                    delay,
+                });
+                tokio::select! {
+                    () = tokio::time::sleep(delay) => {}
+                    () = task_handle.wake.notified() => {}
+                    _ = draining.wait_for(|draining| *draining) => {}
+                }
+            }
+            notify(ReverseConnectionStatus::Stopped);
+        });
+
+        // Stored with the client tasks so that shutdown also ends the retry loop
+        self.client_tasks.write().await.push(task);
+
+        handle
+    }
+
     /// Connects the VNC server to a VNC repeater, establishing a reverse connection.
     ///
     /// This allows a client behind a NAT or firewall to connect to the server through a VNC
@@ -619,29 +2315,17 @@ impl VncServer {
     ///
     /// Returns `Err(std::io::Error)` if a client ID counter overflow occurs, or if there is an issue
     /// connecting to the repeater or handling the client.
-    #[allow(clippy::too_many_lines)] // VNC repeater protocol requires Mode-2 handshake and complete error handling
-    #[allow(clippy::cast_possible_truncation)] // Client ID counter limited to u64::MAX, safe on 64-bit platforms
     pub async fn connect_repeater(
         &self,
         repeater_host: String,
         repeater_port: u16,
         repeater_id: String,
     ) -> Result<usize, std::io::Error> {
-        // Safely increment client ID counter and check for overflow
-        let client_id_raw = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
-        if client_id_raw == 0 || client_id_raw >= u64::MAX - 1000 {
-            return Err(std::io::Error::other("Client ID counter overflow"));
-        }
-        let client_id = client_id_raw as usize;
+        let client_id = allocate_client_id()?;
 
-        let framebuffer = self.framebuffer.clone();
-        let desktop_name = self.desktop_name.clone();
-        let password = self.password.clone();
-        let clients = self.clients.clone();
-        let client_write_streams = self.client_write_streams.clone();
-        let client_tasks = self.client_tasks.clone();
-        let client_ids = self.client_ids.clone();
-        let server_event_tx = self.event_tx.clone();
+        let desktop_name = self.desktop_name.read().await.clone();
+        let authenticator = self.authenticator.read().await.clone();
+        let context = self.session_context();
 
         // Use oneshot channel to wait for connection result before returning
         let (result_tx, result_rx) = tokio::sync::oneshot::channel();
@@ -654,9 +2338,10 @@ impl VncServer {
                 repeater_host,
                 repeater_port,
                 repeater_id,
-                framebuffer.clone(),
+                context.framebuffer.clone(),
                 desktop_name,
-                password,
+                authenticator,
+                context.config.clone(),
                 client_event_tx,
             )
             .await;
@@ -673,83 +2358,10 @@ impl VncServer {
                 Ok(client) => {
                     log::info!("Repeater connection {client_id} established");
 
-                    let client_arc = Arc::new(RwLock::new(client));
-
-                    // Register client to receive dirty region notifications (standard VNC protocol style)
-                    let regions_arc = client_arc.read().await.get_receiver_handle();
-                    let receiver = DirtyRegionReceiver::new(Arc::downgrade(&regions_arc));
-                    framebuffer.register_receiver(receiver).await;
-
-                    // Store the write stream handle for direct socket shutdown
-                    let write_stream_handle = {
-                        let client = client_arc.read().await;
-                        client.get_write_stream_handle()
-                    };
-                    client_write_streams.write().await.push(write_stream_handle);
-
-                    clients.write().await.push(client_arc.clone());
-                    client_ids.write().await.push(client_id);
-
-                    let _ = server_event_tx.send(ServerEvent::ClientConnected { client_id });
-
-                    // Spawn task to handle client messages
-                    // Note: Same write lock behavior as regular clients (see handle_client)
-                    let client_arc_clone = client_arc.clone();
-                    let msg_handle = tokio::spawn(async move {
-                        let result = {
-                            let mut client = client_arc_clone.write().await;
-                            client.handle_messages().await
-                        };
-                        if let Err(e) = result {
-                            error!("Repeater client {client_id} message handling error: {e}");
-                        }
-                    });
-
-                    // Store the message handler task handle
-                    client_tasks.write().await.push(msg_handle);
-
-                    // Handle client events
-                    while let Some(event) = client_event_rx.recv().await {
-                        match event {
-                            ClientEvent::KeyPress { down, key } => {
-                                let _ = server_event_tx.send(ServerEvent::KeyPress {
-                                    client_id,
-                                    down,
-                                    key,
-                                });
-                            }
-                            ClientEvent::PointerMove { x, y, button_mask } => {
-                                let _ = server_event_tx.send(ServerEvent::PointerMove {
-                                    client_id,
-                                    x,
-                                    y,
-                                    button_mask,
-                                });
-                            }
-                            ClientEvent::CutText { text } => {
-                                let _ =
-                                    server_event_tx.send(ServerEvent::CutText { client_id, text });
-                            }
-                            ClientEvent::Disconnected => {
-                                break;
-                            }
-                        }
-                    }
-
-                    // Remove client from list
-                    let mut clients_guard = clients.write().await;
-                    clients_guard.retain(|c| !Arc::ptr_eq(c, &client_arc));
-                    drop(clients_guard);
-
-                    let mut client_ids_guard = client_ids.write().await;
-                    client_ids_guard.retain(|&id| id != client_id);
-                    drop(client_ids_guard);
-
-                    let _ = server_event_tx.send(ServerEvent::ClientDisconnected { client_id });
-
-                    log::info!("Repeater client {client_id} disconnected");
+                    Self::run_session(context, client, client_event_rx, "Repeater client").await;
                 }
                 Err(e) => {
+                    Self::forward_handshake_events(&context, client_id, &mut client_event_rx);
                     error!("Failed to connect to repeater: {e}");
                 }
             }
@@ -890,6 +2502,112 @@ impl VncServer {
         }
     }
 
+    /// Waits until at least one client is connected.
+    ///
+    /// Completes immediately if a client is already connected. Useful for applications
+    /// that only render while someone is watching, without consuming the event stream.
+    ///
+    /// # Returns
+    ///
+    /// The ID of a connected client.
+    pub async fn wait_for_client(&self) -> usize {
+        self.wait_for_clients(|ids| ids.first().copied()).await
+    }
+
+    /// Waits until no clients are connected.
+    ///
+    /// Completes immediately if no client is connected.
+    pub async fn wait_for_no_clients(&self) {
+        self.wait_for_clients(|ids| ids.is_empty().then_some(()))
+            .await;
+    }
+
+    /// Waits until a client has disconnected.
+    ///
+    /// Completes immediately if no client with this ID is connected.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The ID of the client.
+    pub async fn client_closed(&self, client_id: usize) {
+        self.wait_for_clients(|ids| (!ids.contains(&client_id)).then_some(()))
+            .await;
+    }
+
+    /// Waits until `check` returns a value for the connected client IDs, re-checking
+    /// whenever a client connects or disconnects.
+    async fn wait_for_clients<T>(&self, check: impl Fn(&[usize]) -> Option<T>) -> T {
+        loop {
+            // Register for the next change before checking, so none is missed in between
+            let changed = self.client_changes.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            if let Some(value) = check(&self.client_ids.read().await) {
+                return value;
+            }
+            changed.await;
+        }
+    }
+
+    /// Drains the server, e.g. before a restart.
+    ///
+    /// New clients are refused from now on: accept loops return and close their
+    /// listeners, and [`serve_stream`](Self::serve_stream) fails. Connected clients keep
+    /// receiving updates for up to `grace`; then each is sent its pending damage and
+    /// disconnected. Clients that don't go away within a few seconds are disconnected
+    /// forcibly. Draining is reported through `ServerEvent::DrainStarted`, a
+    /// `ServerEvent::ClientClosing` per client and `ServerEvent::DrainCompleted`, and
+    /// cannot be undone.
+    ///
+    /// # Arguments
+    ///
+    /// * `grace` - How long connected clients may stay; the drain ends early once none
+    ///   are left.
+    /// * `reason` - Why clients are disconnected, reported in `ClientClosing` events and
+    ///   logs.
+    pub async fn drain(&self, grace: Duration, reason: &str) {
+        self.draining.send_replace(true);
+        let clients = self.client_ids.read().await.len();
+        log::info!("Draining server: {clients} client(s), grace period {grace:?}");
+        let _ = self
+            .event_tx
+            .send(ServerEvent::DrainStarted { clients, grace });
+
+        let _ = tokio::time::timeout(grace, self.wait_for_no_clients()).await;
+
+        let client_ids = self.client_ids.read().await.clone();
+        for client_id in client_ids {
+            let close = ClientCommand::Close {
+                reason: reason.to_string(),
+            };
+            if self.send_client_command(client_id, close).await {
+                let _ = self.event_tx.send(ServerEvent::ClientClosing {
+                    client_id,
+                    reason: reason.to_string(),
+                });
+            }
+        }
+
+        let closed = tokio::time::timeout(DRAIN_CLOSE_TIMEOUT, self.wait_for_no_clients()).await;
+        let forced = if closed.is_ok() {
+            0
+        } else {
+            let remaining = self.client_ids.read().await.len();
+            log::warn!("{remaining} client(s) did not close in time, disconnecting them");
+            self.disconnect_all_clients().await;
+            remaining
+        };
+        log::info!("Server drained");
+        let _ = self.event_tx.send(ServerEvent::DrainCompleted { forced });
+    }
+
+    /// Returns `true` once [`drain`](Self::drain) has been called.
+    #[must_use]
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
     /// Disconnects all connected clients by cleanly shutting down their tasks and TCP connections.
     ///
     /// This method performs a coordinated shutdown sequence to ensure both halves of each client's
@@ -903,7 +2621,8 @@ impl VncServer {
     ///    client list, causing `VncClient` to drop and automatically close the read half of the
     ///    TCP connection
     /// 4. **Close write halves** - Explicitly calls `shutdown()` on the write halves to close the
-    ///    write side of the TCP connection
+    ///    write side of the TCP connection, bounded in time, since closing a secure stream
+    ///    writes to a peer that may have stopped reading
     ///
     /// After this sequence completes, both sides of the TCP connection are closed and the client
     /// will receive a disconnect notification.
@@ -915,8 +2634,6 @@ impl VncServer {
     ///   invoking this method
     /// - All client IDs, task handles, and write streams are cleared from their respective lists
     pub async fn disconnect_all_clients(&self) {
-        use tokio::io::AsyncWriteExt;
-
         // Get both tasks and write streams
         let (tasks_to_abort, write_streams_to_close) = {
             let mut tasks = self.client_tasks.write().await;
@@ -956,6 +2673,7 @@ impl VncServer {
                 let mut client_ids = self.client_ids.write().await;
                 client_ids.clear();
             }
+            self.client_changes.notify_waiters();
 
             // Step 4: Close all write halves (write half closes, TCP fully closed)
             #[cfg(feature = "debug-logging")]
@@ -965,7 +2683,7 @@ impl VncServer {
             );
             for write_stream_arc in write_streams_to_close {
                 let mut write_stream = write_stream_arc.lock().await;
-                let _ = write_stream.shutdown().await;
+                close_writer(&mut write_stream).await;
             }
         } else {
             // No active tasks, but still clear lists
@@ -976,7 +2694,9 @@ impl VncServer {
             let mut client_ids = self.client_ids.write().await;
             client_ids.clear();
             drop(client_ids);
+            self.client_changes.notify_waiters();
         }
+        self.client_commands.write().await.clear();
 
         #[cfg(feature = "debug-logging")]
         info!("All clients disconnected");
@@ -984,9 +2704,9 @@ impl VncServer {
 
     /// Schedules a copy rectangle operation for all connected clients (standard VNC protocol style).
     ///
-    /// This method iterates through all clients and schedules the specified region to be
-    /// sent using `CopyRect` encoding. This is the equivalent of standard VNC protocol's
-    /// `rfbScheduleCopyRect` function.
+    /// The specified region is sent using `CopyRect` encoding to the clients showing the
+    /// framebuffer, for pixels the framebuffer has already moved. This is the equivalent of
+    /// standard VNC protocol's `rfbScheduleCopyRect` function.
     ///
     /// # Arguments
     ///
@@ -1005,27 +2725,21 @@ impl VncServer {
         dx: i16,
         dy: i16,
     ) {
-        use crate::framebuffer::DirtyRegion;
-
-        let region = DirtyRegion::new(x, y, width, height);
-
-        // Clone client list to avoid holding lock during iteration
-        let clients_snapshot = {
-            let clients = self.clients.read().await;
-            clients.clone()
+        let (fb_width, fb_height) = (self.framebuffer.width(), self.framebuffer.height());
+        let Some(region) = DirtyRegion::new(x, y, width, height).clamp_to(fb_width, fb_height)
+        else {
+            return;
         };
 
-        // Schedule copy for all clients (standard VNC protocol: rfbGetClientIterator pattern)
-        for client_arc in &clients_snapshot {
-            let client = client_arc.read().await;
-            client.schedule_copy_region(region, dx, dy).await;
-        }
+        // Go through the framebuffer's receivers rather than the clients: each client's
+        // lock is held by its message loop for as long as it is connected
+        self.framebuffer.mark_copy_region(region, dx, dy).await;
     }
 
     /// Performs a copy rectangle operation in the framebuffer and schedules it for all clients.
     ///
-    /// This method first copies the specified region within the framebuffer memory,
-    /// then schedules the copy operation to be sent to all connected clients.
+    /// This method copies the specified region within the framebuffer memory, and the
+    /// framebuffer schedules the copy for the clients showing it.
     /// This is the equivalent of standard VNC protocol's `rfbDoCopyRect` function.
     ///
     /// # Arguments
@@ -1053,14 +2767,37 @@ impl VncServer {
         dx: i16,
         dy: i16,
     ) -> Result<(), String> {
-        // Perform actual framebuffer copy
         self.framebuffer
             .do_copy_region(x, y, width, height, dx, dy)
-            .await?;
-
-        // Schedule copy for all clients
-        self.schedule_copy_rect(x, y, width, height, dx, dy).await;
+            .await
+    }
 
-        Ok(())
+    /// Moves pixels within the framebuffer and sends the move to clients as `CopyRect`.
+    ///
+    /// This is the cheap path for scrolling and dragging windows: clients that support
+    /// `CopyRect` copy the pixels they already have instead of receiving them again.
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - The top-left corner `(x, y)` of the pixels to move.
+    /// * `dst` - Where they are moved to; its size is the size of the moved area.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the pixels were moved.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the source or destination rectangle is out of bounds, or
+    /// the framebuffer is backed by a `FrameSource`.
+    pub async fn copy_rect(&self, src: (u16, u16), dst: DirtyRegion) -> Result<(), String> {
+        let offset = |from: u16, to: u16| {
+            i16::try_from(i32::from(from) - i32::from(to))
+                .map_err(|_| format!("Copy offset out of range: from {src:?} to {dst:?}"))
+        };
+        let dx = offset(src.0, dst.x)?;
+        let dy = offset(src.1, dst.y)?;
+        self.do_copy_rect(dst.x, dst.y, dst.width, dst.height, dx, dy)
+            .await
     }
 }
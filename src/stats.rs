@@ -0,0 +1,270 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runtime statistics.
+//!
+//! The server records how long it takes to encode each rectangle and to write each
+//! framebuffer update to the socket. The durations go into fixed-size, lock-free
+//! histograms that can be read at any time through
+//! [`VncServer::encode_stats`](crate::VncServer::encode_stats):
+//!
+//! ```
+//! use rustvncserver::VncServer;
+//!
+//! let (server, _events) = VncServer::new(800, 600, "Desktop".to_string(), None);
+//! let encode = server.encode_stats().encode.summary();
+//! println!("encode p50={:?} p95={:?} p99={:?}", encode.p50, encode.p95, encode.p99);
+//! ```
+//!
+//! Each framebuffer update is also timed through the whole pipeline: how long its damage
+//! waited before encoding started, how long encoding took and how long writing took.
+//! These go into the `queue` and `total` histograms and, when
+//! [`UpdateConfig::report_timing`](crate::config::UpdateConfig::report_timing) is set,
+//! are reported per update as `ServerEvent::UpdateTiming`. A large queue time points at
+//! capture or deferral, a large encode time at the encoder, a large send time at the
+//! network.
+//!
+//! Memory held by a single client is reported by
+//! [`VncServer::client_memory_usage`](crate::VncServer::client_memory_usage).
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Number of linear sub-buckets per power of two. Percentiles are accurate to within
+/// 1/8 (12.5%) of the reported value.
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+/// Enough buckets to cover every `u64` microsecond value.
+const BUCKETS: usize = SUB_BUCKETS + (64 - SUB_BUCKET_BITS as usize) * SUB_BUCKETS;
+
+/// Encode and send latency histograms, shared by all clients of a server.
+#[derive(Debug, Default)]
+pub struct EncodeStats {
+    /// Time spent encoding each rectangle, including reading its pixels and translating
+    /// them to the client's pixel format.
+    pub encode: LatencyHistogram,
+    /// Time spent writing each framebuffer update message to the socket.
+    pub send: LatencyHistogram,
+    /// Time from the oldest damage in an update arriving to encoding of the update
+    /// starting (capture and deferral delay).
+    pub queue: LatencyHistogram,
+    /// Time from the oldest damage in an update arriving to the update being written
+    /// (end-to-end server latency).
+    pub total: LatencyHistogram,
+}
+
+impl EncodeStats {
+    /// Clears all histograms.
+    pub fn reset(&self) {
+        self.encode.reset();
+        self.send.reset();
+        self.queue.reset();
+        self.total.reset();
+    }
+}
+
+/// Pipeline timing of one framebuffer update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateTiming {
+    /// Number of rectangles in the update.
+    pub rects: usize,
+    /// Size of the update message in bytes.
+    pub bytes: usize,
+    /// Time from the oldest damage in the update arriving to encoding starting, or
+    /// `None` if the update carried no tracked damage (e.g. only `CopyRect`s).
+    pub queued: Option<Duration>,
+    /// Time spent encoding the update.
+    pub encode: Duration,
+    /// Time spent writing the update to the client.
+    pub send: Duration,
+}
+
+impl UpdateTiming {
+    /// Returns the time from damage arrival (or encode start, if unknown) until the
+    /// update was written.
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.queued.unwrap_or_default() + self.encode + self.send
+    }
+}
+
+/// Memory attributable to a single client, in bytes.
+///
+/// Buffer sizes are the allocated capacities. Compressor state is estimated from the
+/// number of zlib streams the client has initialized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientMemoryUsage {
+    /// Buffer holding received but not yet processed client messages.
+    pub receive_buffer: usize,
+    /// Buffer the last framebuffer update was assembled in.
+    pub send_buffer: usize,
+    /// Dirty and `CopyRect` regions, and application-encoded rectangles, waiting to be sent.
+    pub queued_damage: usize,
+    /// Clipboard text held back by throttling.
+    pub clipboard: usize,
+    /// Persistent zlib compression streams.
+    pub compressors: usize,
+}
+
+impl ClientMemoryUsage {
+    /// Returns the sum of all categories.
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.receive_buffer
+            + self.send_buffer
+            + self.queued_damage
+            + self.clipboard
+            + self.compressors
+    }
+}
+
+/// A histogram of durations with microsecond resolution.
+///
+/// Values are counted in log-linear buckets (8 per power of two), so recording is a
+/// couple of atomic increments and memory use is fixed regardless of how many samples
+/// are recorded.
+#[derive(Debug)]
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; BUCKETS],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+/// Summary of a [`LatencyHistogram`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    /// Number of recorded samples.
+    pub count: u64,
+    /// Mean of the recorded samples.
+    pub mean: Duration,
+    /// Median.
+    pub p50: Duration,
+    /// 95th percentile.
+    pub p95: Duration,
+    /// 99th percentile.
+    pub p99: Duration,
+    /// Largest recorded sample.
+    pub max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+            max_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// Records one sample.
+    pub fn record(&self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.buckets[bucket_index(micros)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// Returns the number of recorded samples.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the value below which `percentile` percent of the samples fall.
+    ///
+    /// # Arguments
+    ///
+    /// * `percentile` - A percentage between 0.0 and 100.0.
+    ///
+    /// # Returns
+    ///
+    /// The upper bound of the bucket holding that sample (never more than the largest
+    /// recorded sample), or `Duration::ZERO` if nothing has been recorded.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Rank is clamped to [1, count]
+    #[allow(clippy::cast_precision_loss)] // Sample counts far below 2^52
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0) * count as f64).ceil() as u64;
+        let rank = rank.clamp(1, count);
+        let max = self.max_micros.load(Ordering::Relaxed);
+
+        let mut seen = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= rank {
+                return Duration::from_micros(bucket_upper_bound(index).min(max));
+            }
+        }
+        Duration::from_micros(max)
+    }
+
+    /// Returns the sample count, mean, p50, p95, p99 and maximum.
+    #[must_use]
+    pub fn summary(&self) -> LatencySummary {
+        let count = self.count();
+        if count == 0 {
+            return LatencySummary::default();
+        }
+        LatencySummary {
+            count,
+            mean: Duration::from_micros(self.sum_micros.load(Ordering::Relaxed) / count),
+            p50: self.percentile(50.0),
+            p95: self.percentile(95.0),
+            p99: self.percentile(99.0),
+            max: Duration::from_micros(self.max_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Clears all recorded samples.
+    pub fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.sum_micros.store(0, Ordering::Relaxed);
+        self.max_micros.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Returns the bucket a microsecond value is counted in.
+#[allow(clippy::cast_possible_truncation)] // Both casts are of values below 2 * SUB_BUCKETS
+fn bucket_index(micros: u64) -> usize {
+    if micros < SUB_BUCKETS as u64 {
+        return micros as usize;
+    }
+    let msb = micros.ilog2();
+    let shift = msb - SUB_BUCKET_BITS;
+    let sub = (micros >> shift) as usize & (SUB_BUCKETS - 1);
+    SUB_BUCKETS + shift as usize * SUB_BUCKETS + sub
+}
+
+/// Returns the largest microsecond value counted in bucket `index`.
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index - SUB_BUCKETS) / SUB_BUCKETS;
+    let sub = (index - SUB_BUCKETS) % SUB_BUCKETS;
+    let low = ((SUB_BUCKETS + sub) as u64) << shift;
+    low.saturating_add((1u64 << shift) - 1)
+}
@@ -0,0 +1,654 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Server-side Tight encoding for pixel formats `rfb-encodings` can't serve, and for
+//! content that goes through the gradient filter.
+//!
+//! The Tight encoder in `rfb-encodings` writes full-color data as packed RGB24, which
+//! is only valid when the client's format qualifies for the compact `TPIXEL` form
+//! (32 bits per pixel, depth 24, 8 bits per channel), and its palette colors are only
+//! valid for the common byte layouts of that form (see [`uses_rfb_encodings`]). Clients
+//! that negotiated 16 or 8 bits per pixel, or a big-endian or BGR layout, would decode
+//! garbage. For those formats this module encodes the solid, mono, indexed and
+//! full-color subencodings itself, writing every palette entry, fill color and pixel as
+//! a `TPIXEL` in the client's format: `bits_per_pixel / 8` bytes laid out as
+//! `SetPixelFormat` requested, or 3 RGB bytes for the compact form.
+//!
+//! `rfb-encodings` has no gradient filter either, so smooth full-color content (see
+//! [`detect_gradient_image`](crate::analysis::detect_gradient_image)) is encoded here for
+//! every client format. The filter replaces each color channel with its difference from
+//! the prediction `left + above - above_left`, which turns gradients into runs of small
+//! values that zlib compresses well. With the `turbojpeg` feature, JPEG rectangles for
+//! `TPIXEL` clients are encoded here too, following the server's
+//! [`JpegConfig`](crate::config::JpegConfig).
+//!
+//! Compressed data goes through the client's persistent Tight zlib streams, so the
+//! output can be interleaved with rectangles from the `rfb-encodings` encoder.
+//!
+//! Rectangles shared between clients can't depend on what one client's streams hold.
+//! They are compressed with [`FreshStreams`], a new zlib stream for each rectangle, and
+//! their control byte tells the client to reset its streams before decoding them. A
+//! client's persistent streams start over after such a rectangle, on both ends.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use flate2::{Compress, Compression};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::analysis;
+use crate::config::JpegConfig;
+use crate::encoding;
+use crate::encoding::tight::{
+    TightStreamCompressor, STREAM_ID_FULL_COLOR, STREAM_ID_INDEXED, STREAM_ID_MONO,
+};
+#[cfg(feature = "turbojpeg")]
+use crate::jpeg::JpegEncoder;
+use crate::protocol::PixelFormat;
+use crate::rect_encoder;
+use crate::rle_tile;
+use crate::tightpng::{pack_indices, write_compact_length};
+use rfb_encodings::translate;
+
+/// Tight control byte: fill (solid color) subencoding.
+pub(crate) const TIGHT_FILL: u8 = 0x08;
+
+/// Tight control byte: JPEG subencoding.
+const TIGHT_JPEG: u8 = 0x09;
+
+/// Tight control flag: an explicit filter byte follows the control byte.
+const TIGHT_EXPLICIT_FILTER: u8 = 0x04;
+
+/// Tight filter: palette filter (indices into an explicit palette).
+const TIGHT_FILTER_PALETTE: u8 = 0x01;
+
+/// Tight filter: gradient filter (differences from a predicted color).
+const TIGHT_FILTER_GRADIENT: u8 = 0x02;
+
+/// Tight control bits: reset zlib streams 0-3 before decoding the rectangle.
+const TIGHT_RESET_STREAMS: u8 = 0x0F;
+
+/// Zlib stream used for gradient-filtered data; `rfb-encodings` uses only streams 0-2.
+const STREAM_ID_GRADIENT: u8 = 3;
+
+/// Data shorter than this is sent uncompressed and without a length (RFC 6143).
+const TIGHT_MIN_TO_COMPRESS: usize = 12;
+
+/// Maximum width of a Tight rectangle in pixels.
+const TIGHT_MAX_RECT_WIDTH: u16 = 2048;
+
+/// Maximum number of pixels in a Tight rectangle.
+const TIGHT_MAX_RECT_SIZE: usize = 65536;
+
+/// Maximum number of palette entries a Tight palette can hold.
+const MAX_PALETTE_COLORS: usize = 256;
+
+/// How tiles with too many colors for a palette are sent.
+#[derive(Debug, Clone, Copy)]
+pub enum FullColor {
+    /// Zlib-compressed pixels.
+    Zlib,
+    /// Zlib-compressed pixels through the gradient filter.
+    Gradient,
+    /// JPEG, for clients in the compact `TPIXEL` form only.
+    #[cfg(feature = "turbojpeg")]
+    Jpeg {
+        /// JPEG quality (1-100) before the configured clamping.
+        quality: u8,
+        /// Subsampling, DCT and input layout.
+        config: JpegConfig,
+    },
+}
+
+/// Returns whether a Tight rectangle's data, starting with its compression control byte,
+/// is a JPEG image.
+#[must_use]
+pub fn is_jpeg(rect: &[u8]) -> bool {
+    rect.first()
+        .is_some_and(|&control| control >> 4 == TIGHT_JPEG)
+}
+
+/// Returns whether `format` uses the compact 3-byte `TPIXEL` form: red, green and blue
+/// bytes in that order, whatever the format's shifts and byte order.
+#[must_use]
+pub fn uses_tpixel(format: &PixelFormat) -> bool {
+    format.true_colour_flag != 0
+        && format.bits_per_pixel == 32
+        && format.depth == 24
+        && format.red_max == 255
+        && format.green_max == 255
+        && format.blue_max == 255
+}
+
+/// Returns whether `rfb-encodings` writes valid Tight data for `format`.
+///
+/// It writes full-color data as `TPIXEL`s, but palette and fill colors as the low three
+/// bytes of the client's pixel value in memory order. Both agree only for `TPIXEL`
+/// formats with red in the first byte: little-endian with red in the low bits, or
+/// big-endian with red in bits 16-23. Rectangles for all other formats, and those that
+/// need the gradient filter or JPEG, are encoded by [`encode_tight_rects`].
+#[must_use]
+pub fn uses_rfb_encodings(format: &PixelFormat) -> bool {
+    let shifts = (format.red_shift, format.green_shift, format.blue_shift);
+    uses_tpixel(format)
+        && if format.big_endian_flag == 0 {
+            shifts == (0, 8, 16)
+        } else {
+            shifts == (16, 8, 0)
+        }
+}
+
+/// Compresses every rectangle's data with a new zlib stream, for rectangles that are
+/// encoded once and sent to several clients.
+///
+/// Each rectangle's data is then readable without the ones before it, once the client
+/// resets its streams as [`reset_streams`] asks it to.
+pub(crate) struct FreshStreams;
+
+impl TightStreamCompressor for FreshStreams {
+    fn compress_tight_stream(
+        &mut self,
+        _stream_id: u8,
+        level: u8,
+        input: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        let mut stream = Compress::new(Compression::new(u32::from(level)), true);
+        let mut out = BytesMut::new();
+        rect_encoder::deflate(input, &mut stream, &mut out).map_err(|e| e.to_string())?;
+        Ok(out.to_vec())
+    }
+}
+
+/// Sets the control bits of a Tight rectangle that make the client reset all four zlib
+/// streams before decoding it, as rectangles compressed with [`FreshStreams`] need.
+pub(crate) fn reset_streams(rect: &mut [u8]) {
+    if let Some(control) = rect.first_mut() {
+        *control |= TIGHT_RESET_STREAMS;
+    }
+}
+
+/// Encodes an RGBA32 region for a client as Tight rectangles.
+///
+/// Only the parts of the region that look photographic are sent as JPEG (standard VNC
+/// protocol's `DetectSmoothImage`); text and UI around them stay on the lossless palette
+/// and zlib paths. Each part goes to the `rfb-encodings` encoder if it can serve the
+/// client's format, or to [`encode_tight_rects`].
+///
+/// # Arguments
+///
+/// * `data` - RGBA32 pixel data for the region (4 bytes per pixel, row-major).
+/// * `width` - Width of the region in pixels.
+/// * `height` - Height of the region in pixels.
+/// * `quality_level` - VNC quality level (0-9), or 255 for lossless.
+/// * `compression` - VNC compression level (0-9).
+/// * `jpeg` - JPEG quality (1-100) and the server's JPEG configuration; unused without
+///   the `turbojpeg` feature.
+/// * `client_format` - The client's negotiated pixel format.
+/// * `compressor` - The client's persistent Tight zlib streams, or [`FreshStreams`].
+///
+/// # Returns
+///
+/// `(x, y, width, height, payload)` for each rectangle, relative to the region origin.
+#[allow(clippy::too_many_arguments)] // The output depends on all of the client's settings
+#[cfg_attr(not(feature = "turbojpeg"), allow(unused_variables))]
+pub(crate) fn encode_region<C: TightStreamCompressor>(
+    data: &[u8],
+    width: u16,
+    height: u16,
+    quality_level: u8,
+    compression: u8,
+    jpeg: (u8, JpegConfig),
+    client_format: &PixelFormat,
+    compressor: &mut C,
+) -> Vec<(u16, u16, u16, u16, BytesMut)> {
+    let areas = if quality_level < 10 {
+        analysis::smooth_areas(data, width, height, quality_level, client_format)
+    } else {
+        vec![(0, 0, width, height, false)]
+    };
+
+    let mut rects = Vec::new();
+    for (x, y, area_width, area_height, smooth) in areas {
+        let area = if (area_width, area_height) == (width, height) {
+            Cow::Borrowed(data)
+        } else {
+            Cow::Owned(extract_tile(data, width, x, y, area_width, area_height))
+        };
+        let quality_level = if smooth { quality_level } else { 255 };
+
+        // Smooth content that doesn't go out as JPEG is sent through the gradient
+        // filter, which only the local encoder implements
+        let full_color = if (quality_level > 9 || !uses_tpixel(client_format))
+            && analysis::detect_gradient_image(
+                &area,
+                area_width,
+                area_height,
+                compression,
+                client_format,
+            ) {
+            FullColor::Gradient
+        } else {
+            #[cfg(feature = "turbojpeg")]
+            if quality_level < 10 && uses_tpixel(client_format) {
+                FullColor::Jpeg {
+                    quality: jpeg.0,
+                    config: jpeg.1,
+                }
+            } else {
+                FullColor::Zlib
+            }
+            #[cfg(not(feature = "turbojpeg"))]
+            FullColor::Zlib
+        };
+
+        // rfb-encodings only handles the common TPIXEL layouts; other formats, the
+        // gradient filter and configured JPEG are encoded here with every pixel in the
+        // client's format
+        let area_rects =
+            if uses_rfb_encodings(client_format) && matches!(full_color, FullColor::Zlib) {
+                encoding::tight::encode_tight_rects(
+                    &area,
+                    area_width,
+                    area_height,
+                    quality_level,
+                    compression,
+                    client_format,
+                    compressor,
+                )
+            } else {
+                encode_tight_rects(
+                    &area,
+                    area_width,
+                    area_height,
+                    compression,
+                    full_color,
+                    client_format,
+                    compressor,
+                )
+            };
+        rects.extend(
+            area_rects
+                .into_iter()
+                .map(|(rect_x, rect_y, rect_w, rect_h, payload)| {
+                    (x + rect_x, y + rect_y, rect_w, rect_h, payload)
+                }),
+        );
+    }
+    rects
+}
+
+/// Packs the rectangles of a region into one buffer for the encode cache: for each,
+/// its position and size, the length of its payload and the payload.
+pub(crate) fn pack_rects(rects: &[(u16, u16, u16, u16, Bytes)]) -> Bytes {
+    let size = rects.iter().map(|(.., payload)| 12 + payload.len()).sum();
+    let mut packed = BytesMut::with_capacity(size);
+    for (x, y, width, height, payload) in rects {
+        for value in [x, y, width, height] {
+            packed.put_u16(*value);
+        }
+        packed.put_u32(u32::try_from(payload.len()).unwrap_or(u32::MAX));
+        packed.put_slice(payload);
+    }
+    packed.freeze()
+}
+
+/// Unpacks rectangles packed by [`pack_rects`], sharing the buffer.
+pub(crate) fn unpack_rects(mut packed: Bytes) -> Vec<(u16, u16, u16, u16, Bytes)> {
+    let mut rects = Vec::new();
+    while packed.remaining() >= 12 {
+        let (x, y, width, height) = (
+            packed.get_u16(),
+            packed.get_u16(),
+            packed.get_u16(),
+            packed.get_u16(),
+        );
+        let length = (packed.get_u32() as usize).min(packed.len());
+        rects.push((x, y, width, height, packed.split_to(length)));
+    }
+    rects
+}
+
+/// Encodes an RGBA32 region as Tight rectangles in the client's pixel format.
+///
+/// The region is split into tiles no larger than Tight allows; each tile uses the
+/// cheapest of the solid, mono and indexed subencodings, or full color as `full_color`
+/// says.
+///
+/// # Arguments
+///
+/// * `data` - RGBA32 pixel data for the region (4 bytes per pixel, row-major).
+/// * `width` - Width of the region in pixels.
+/// * `height` - Height of the region in pixels.
+/// * `compression` - VNC compression level (0-9).
+/// * `full_color` - How tiles with too many colors for a palette are sent.
+/// * `client_format` - The client's negotiated pixel format.
+/// * `compressor` - The client's persistent Tight zlib streams.
+///
+/// # Returns
+///
+/// `(x, y, width, height, payload)` for each rectangle, with coordinates relative to the
+/// region origin, in the same shape as `rfb-encodings`' `encode_tight_rects`.
+pub fn encode_tight_rects<C: TightStreamCompressor>(
+    data: &[u8],
+    width: u16,
+    height: u16,
+    compression: u8,
+    full_color: FullColor,
+    client_format: &PixelFormat,
+    compressor: &mut C,
+) -> Vec<(u16, u16, u16, u16, BytesMut)> {
+    let zlib_level = zlib_level(compression);
+    let mut rects = Vec::new();
+
+    let mut x = 0;
+    while x < width {
+        let tile_w = (width - x).min(TIGHT_MAX_RECT_WIDTH);
+        let max_rows = u16::try_from(TIGHT_MAX_RECT_SIZE / usize::from(tile_w)).unwrap_or(u16::MAX);
+        let mut y = 0;
+        while y < height {
+            let tile_h = (height - y).min(max_rows);
+            let tile = extract_tile(data, width, x, y, tile_w, tile_h);
+            let payload = encode_tile(
+                &tile,
+                tile_w,
+                zlib_level,
+                full_color,
+                client_format,
+                compressor,
+            );
+            rects.push((x, y, tile_w, tile_h, payload));
+            y += tile_h;
+        }
+        x += tile_w;
+    }
+
+    rects
+}
+
+/// Encodes one tile with the cheapest subencoding.
+fn encode_tile<C: TightStreamCompressor>(
+    tile: &[u8],
+    width: u16,
+    zlib_level: u8,
+    full_color: FullColor,
+    client_format: &PixelFormat,
+    compressor: &mut C,
+) -> BytesMut {
+    let bytes_per_pixel = usize::from(client_format.bits_per_pixel / 8).max(1);
+    // A palette only pays off when indices are smaller than the pixels themselves
+    let max_colors = if bytes_per_pixel > 1 {
+        MAX_PALETTE_COLORS
+    } else {
+        2
+    };
+
+    let mut buf = BytesMut::new();
+    match build_palette(tile, max_colors) {
+        Some((palette, _)) if palette.len() == 1 => {
+            buf.put_u8(TIGHT_FILL << 4);
+            buf.put_slice(&tpixel(palette[0], client_format));
+        }
+        Some((palette, indices)) => {
+            let (stream_id, packed) = if palette.len() == 2 {
+                (
+                    STREAM_ID_MONO,
+                    pack_indices(&indices, usize::from(width), 1),
+                )
+            } else {
+                (STREAM_ID_INDEXED, indices)
+            };
+            buf.put_u8((stream_id | TIGHT_EXPLICIT_FILTER) << 4);
+            buf.put_u8(TIGHT_FILTER_PALETTE);
+            #[allow(clippy::cast_possible_truncation)] // Palette holds at most 256 colors
+            buf.put_u8((palette.len() - 1) as u8);
+            for &color in &palette {
+                buf.put_slice(&tpixel(color, client_format));
+            }
+            compress_data(&mut buf, &packed, zlib_level, stream_id, compressor);
+        }
+        None => match full_color {
+            FullColor::Gradient => {
+                buf.put_u8((STREAM_ID_GRADIENT | TIGHT_EXPLICIT_FILTER) << 4);
+                buf.put_u8(TIGHT_FILTER_GRADIENT);
+                let filtered = gradient_filter(tile, usize::from(width), client_format);
+                compress_data(
+                    &mut buf,
+                    &filtered,
+                    zlib_level,
+                    STREAM_ID_GRADIENT,
+                    compressor,
+                );
+            }
+            #[cfg(feature = "turbojpeg")]
+            FullColor::Jpeg { quality, config } => {
+                match encode_jpeg(tile, width, quality, config) {
+                    Ok(jpeg) => {
+                        buf.put_u8(TIGHT_JPEG << 4);
+                        write_compact_length(&mut buf, jpeg.len());
+                        buf.put_slice(&jpeg);
+                    }
+                    Err(e) => {
+                        log::warn!("JPEG compression failed, sending full color: {e}");
+                        encode_full_color(&mut buf, tile, zlib_level, client_format, compressor);
+                    }
+                }
+            }
+            FullColor::Zlib => {
+                encode_full_color(&mut buf, tile, zlib_level, client_format, compressor);
+            }
+        },
+    }
+    buf
+}
+
+/// Appends a zlib-compressed full-color tile.
+fn encode_full_color<C: TightStreamCompressor>(
+    buf: &mut BytesMut,
+    tile: &[u8],
+    zlib_level: u8,
+    client_format: &PixelFormat,
+    compressor: &mut C,
+) {
+    buf.put_u8(STREAM_ID_FULL_COLOR << 4);
+    let pixels = full_color_pixels(tile, client_format);
+    compress_data(buf, &pixels, zlib_level, STREAM_ID_FULL_COLOR, compressor);
+}
+
+/// Compresses an RGBA32 tile to JPEG.
+#[cfg(feature = "turbojpeg")]
+fn encode_jpeg(
+    tile: &[u8],
+    width: u16,
+    quality: u8,
+    config: JpegConfig,
+) -> Result<Vec<u8>, String> {
+    #[allow(clippy::cast_possible_truncation)] // Tiles hold at most 65536 pixels
+    let height = (tile.len() / 4 / usize::from(width).max(1)) as u16;
+    JpegEncoder::new()?.compress(tile, width, height, quality, &config)
+}
+
+/// Copies a tile out of a row-major RGBA32 region.
+pub(crate) fn extract_tile(
+    data: &[u8],
+    stride: u16,
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+) -> Vec<u8> {
+    let row_bytes = usize::from(width) * 4;
+    let mut tile = Vec::with_capacity(row_bytes * usize::from(height));
+    for row in usize::from(y)..usize::from(y) + usize::from(height) {
+        let start = (row * usize::from(stride) + usize::from(x)) * 4;
+        tile.extend_from_slice(&data[start..start + row_bytes]);
+    }
+    tile
+}
+
+/// Builds a palette of RGBA32 colors and per-pixel indices for a tile.
+///
+/// Returns `None` as soon as more than `max_colors` distinct colors are found.
+#[allow(clippy::cast_possible_truncation)] // Palette indices are bounded by max_colors (<= 256)
+fn build_palette(data: &[u8], max_colors: usize) -> Option<(Vec<[u8; 4]>, Vec<u8>)> {
+    let mut lookup: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut palette = Vec::new();
+    let mut indices = Vec::with_capacity(data.len() / 4);
+
+    for pixel in data.chunks_exact(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = if let Some(&index) = lookup.get(&color) {
+            index
+        } else {
+            if palette.len() == max_colors {
+                return None;
+            }
+            let index = palette.len() as u8;
+            lookup.insert(color, index);
+            palette.push([pixel[0], pixel[1], pixel[2], 0]);
+            index
+        };
+        indices.push(index);
+    }
+
+    Some((palette, indices))
+}
+
+/// Translates one RGBA32 color into a `TPIXEL` in the client's format.
+fn tpixel(color: [u8; 4], client_format: &PixelFormat) -> BytesMut {
+    full_color_pixels(&color, client_format)
+}
+
+/// Translates RGBA32 pixels into `TPIXEL`s: RGB bytes for the compact form, the client's
+/// pixel format otherwise.
+fn full_color_pixels(data: &[u8], client_format: &PixelFormat) -> BytesMut {
+    if uses_tpixel(client_format) {
+        let mut pixels = BytesMut::with_capacity(data.len() / 4 * 3);
+        for pixel in data.chunks_exact(4) {
+            pixels.put_slice(&pixel[..3]);
+        }
+        pixels
+    } else {
+        translate::translate_pixels(data, &PixelFormat::rgba32(), client_format)
+    }
+}
+
+/// Applies the gradient filter to an RGBA32 tile, returning the filtered `TPIXEL`s.
+///
+/// Every channel is predicted from the left, upper and upper-left neighbours as
+/// `left + above - above_left`, clamped to the channel's range, and replaced by its
+/// difference from the prediction modulo the range. Neighbours outside the tile count
+/// as zero.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Differences are masked to the channel range
+fn gradient_filter(tile: &[u8], width: usize, client_format: &PixelFormat) -> Vec<u8> {
+    let compact = uses_tpixel(client_format);
+    let maxes = if compact {
+        [255; 3]
+    } else {
+        [
+            client_format.red_max,
+            client_format.green_max,
+            client_format.blue_max,
+        ]
+    };
+    let shifts = [
+        client_format.red_shift,
+        client_format.green_shift,
+        client_format.blue_shift,
+    ];
+
+    // Channel values in the client's ranges
+    let channels: Vec<[u16; 3]> = if compact {
+        tile.chunks_exact(4)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]].map(u16::from))
+            .collect()
+    } else {
+        let translated = translate::translate_pixels(tile, &PixelFormat::rgba32(), client_format);
+        rle_tile::read_pixels(&translated, client_format)
+            .into_iter()
+            .map(|pixel| [0, 1, 2].map(|c| (pixel >> shifts[c]) as u16 & maxes[c]))
+            .collect()
+    };
+
+    let bytes_per_pixel = if compact {
+        3
+    } else {
+        usize::from(client_format.bits_per_pixel / 8).max(1)
+    };
+    let big_endian = client_format.big_endian_flag != 0;
+    let mut filtered = Vec::with_capacity(channels.len() * bytes_per_pixel);
+    let mut above = vec![[0u16; 3]; width];
+    for row in channels.chunks(width.max(1)) {
+        let mut left = [0u16; 3];
+        let mut above_left = [0u16; 3];
+        for (x, &here) in row.iter().enumerate() {
+            let up = above[x];
+            let diff = [0, 1, 2].map(|c| {
+                let prediction = (i32::from(left[c]) + i32::from(up[c]) - i32::from(above_left[c]))
+                    .clamp(0, i32::from(maxes[c]));
+                (i32::from(here[c]) - prediction) as u16 & maxes[c]
+            });
+            above_left = up;
+            left = here;
+            above[x] = here;
+
+            if compact {
+                filtered.extend(diff.map(|d| d as u8));
+            } else {
+                let value = (0..3).fold(0u32, |value, c| value | u32::from(diff[c]) << shifts[c]);
+                if big_endian {
+                    filtered.extend_from_slice(&value.to_be_bytes()[4 - bytes_per_pixel..]);
+                } else {
+                    filtered.extend_from_slice(&value.to_le_bytes()[..bytes_per_pixel]);
+                }
+            }
+        }
+    }
+    filtered
+}
+
+/// Maps a VNC compression level to the zlib level used for Tight data.
+fn zlib_level(compression: u8) -> u8 {
+    match compression {
+        0 | 1 => 1,
+        2 | 3 => 3,
+        4..=6 => 6,
+        _ => 9,
+    }
+}
+
+/// Appends Tight-compressed data: raw when short, otherwise a compact length and the
+/// output of the given persistent zlib stream.
+fn compress_data<C: TightStreamCompressor>(
+    buf: &mut BytesMut,
+    data: &[u8],
+    zlib_level: u8,
+    stream_id: u8,
+    compressor: &mut C,
+) {
+    if data.len() < TIGHT_MIN_TO_COMPRESS {
+        buf.put_slice(data);
+        return;
+    }
+
+    match compressor.compress_tight_stream(stream_id, zlib_level, data) {
+        Ok(compressed) => {
+            write_compact_length(buf, compressed.len());
+            buf.put_slice(&compressed);
+        }
+        Err(e) => {
+            // The stream state is unknown now; the client will likely desync
+            log::error!("Tight stream {stream_id} compression failed: {e}");
+            write_compact_length(buf, 0);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! TLS handshakes for the `VeNCrypt` security types (requires the `tls` feature).
+//!
+//! Anonymous TLS uses Diffie-Hellman key exchange without certificates, as `TigerVNC`
+//! and other GnuTLS-based viewers expect for `TLSNone` and `TLSVnc`. Anonymous cipher
+//! suites only exist up to TLS 1.2, and OpenSSL needs security level 0 to enable them.
+//!
+//! The X.509 subtypes use the configured server certificate with current cipher
+//! suites, and optionally verify client certificates against a CA bundle.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::OnceLock;
+
+use openssl::dh::Dh;
+use openssl::error::ErrorStack;
+use openssl::ssl::{
+    Ssl, SslAcceptor, SslContext, SslFiletype, SslMethod, SslVerifyMode, SslVersion,
+};
+use openssl::x509::{X509Name, X509NameRef, X509};
+use tokio_openssl::SslStream;
+
+use crate::config::SecurityConfig;
+use crate::transport::ClientStream;
+
+/// Cipher suites with anonymous (EC)DH key exchange and real encryption.
+const ANONYMOUS_CIPHERS: &str = "aNULL:!eNULL:!LOW:!EXPORT:!3DES:@SECLEVEL=0";
+
+/// Builds the context for anonymous TLS handshakes.
+fn build_anonymous_context() -> Result<SslContext, ErrorStack> {
+    let mut builder = SslContext::builder(SslMethod::tls_server())?;
+    // TLS 1.3 has no anonymous cipher suites
+    builder.set_max_proto_version(Some(SslVersion::TLS1_2))?;
+    builder.set_cipher_list(ANONYMOUS_CIPHERS)?;
+    let dh = Dh::get_2048_256()?;
+    builder.set_tmp_dh(&dh)?;
+    Ok(builder.build())
+}
+
+/// Returns the context for anonymous TLS handshakes, built on first use.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if OpenSSL cannot set up anonymous TLS.
+pub(crate) fn anonymous_context() -> Result<&'static SslContext, io::Error> {
+    static CONTEXT: OnceLock<SslContext> = OnceLock::new();
+    if let Some(context) = CONTEXT.get() {
+        return Ok(context);
+    }
+    let context = build_anonymous_context().map_err(io::Error::other)?;
+    Ok(CONTEXT.get_or_init(|| context))
+}
+
+/// Builds the context for the X.509 subtypes from the configured files.
+///
+/// # Returns
+///
+/// `Ok(None)` if no server certificate and key are configured.
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if a file cannot be read or holds invalid PEM data, or
+/// the key doesn't match the certificate.
+pub(crate) fn certificate_context(
+    config: &SecurityConfig,
+) -> Result<Option<SslContext>, io::Error> {
+    let (Some(certificate), Some(private_key)) = (&config.tls_certificate, &config.tls_private_key)
+    else {
+        return Ok(None);
+    };
+
+    let build = || -> Result<SslContext, ErrorStack> {
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+        builder.set_certificate_chain_file(certificate)?;
+        builder.set_private_key_file(private_key, SslFiletype::PEM)?;
+        builder.check_private_key()?;
+        if let Some(client_ca) = &config.client_ca {
+            builder.set_ca_file(client_ca)?;
+            builder.set_client_ca_list(X509Name::load_client_ca_file(client_ca)?);
+            let mut mode = SslVerifyMode::PEER;
+            if config.require_client_certificate {
+                mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+            }
+            builder.set_verify(mode);
+            // Needed for session resumption with client certificates
+            builder.set_session_id_context(b"rustvncserver")?;
+        }
+        Ok(builder.build().into_context())
+    };
+    build().map(Some).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Failed to load TLS certificate {}: {e}",
+                certificate.display()
+            ),
+        )
+    })
+}
+
+/// Runs a server TLS handshake with `context` over `stream`.
+///
+/// # Returns
+///
+/// The encrypted stream, and the subject of the client's certificate if it presented
+/// one (which `context` verified).
+///
+/// # Errors
+///
+/// Returns `Err(std::io::Error)` if the handshake fails, including when the client's
+/// certificate doesn't verify.
+pub(crate) async fn accept(
+    context: &SslContext,
+    stream: Box<dyn ClientStream>,
+) -> Result<(Box<dyn ClientStream>, Option<String>), io::Error> {
+    let ssl = Ssl::new(context).map_err(io::Error::other)?;
+    let mut stream = SslStream::new(ssl, stream).map_err(io::Error::other)?;
+    Pin::new(&mut stream).accept().await.map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            format!("TLS handshake failed: {e}"),
+        )
+    })?;
+    let subject = stream
+        .ssl()
+        .peer_certificate()
+        .map(|certificate: X509| format_name(certificate.subject_name()));
+    Ok((Box::new(stream), subject))
+}
+
+/// Formats a certificate name as comma-separated `KEY=value` pairs, e.g.
+/// `CN=kiosk-3,O=Example`.
+fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry
+                .data()
+                .to_string()
+                .unwrap_or_else(|_| String::from("?"));
+            format!("{key}={value}")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -0,0 +1,196 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Custom transports.
+//!
+//! The server normally accepts TCP connections itself, but a session can run over any
+//! reliable, ordered byte stream that implements Tokio's `AsyncRead` and `AsyncWrite`.
+//! Hand such a stream to [`VncServer::serve_stream`](crate::VncServer::serve_stream) and
+//! the full RFB handshake and session run over it, exactly as for a TCP client.
+//!
+//! # WebRTC data channels (experimental)
+//!
+//! Running RFB over a WebRTC data channel gives peer-to-peer remote access through NATs
+//! without a public relay. Signaling (exchanging the offer, answer and ICE candidates) is
+//! left to the application, since it depends on how the peers find each other.
+//!
+//! Data channels carry messages rather than a byte stream. [`MessageStream`] turns a
+//! channel's outgoing messages (a `Sink<Bytes>`) and incoming messages (a
+//! `Stream<Item = io::Result<Bytes>>`) into the byte stream a session runs over, so any
+//! WebRTC library can be plugged in by adapting its data channel to those two traits:
+//!
+//! ```ignore
+//! let (outgoing, incoming) = data_channel_halves(data_channel); // Sink + Stream
+//! let channel = MessageStream::new(outgoing, incoming);
+//! server.serve_secure_stream(channel, "webrtc:peer-42".to_string()).await?;
+//! ```
+//!
+//! Data channels are encrypted with DTLS, hence `serve_secure_stream`. The channel must
+//! be created as reliable and ordered (the defaults); RFB cannot tolerate lost or
+//! reordered bytes.
+//!
+//! # QUIC
+//!
+//! QUIC carries RFB with built-in TLS encryption, 0/1-RTT connection setup and better
+//! recovery on lossy links than TCP. With the `quic` feature,
+//! [`VncServer::listen_quic`](crate::VncServer::listen_quic) accepts QUIC connections
+//! (via `quinn`), or [`listen_quic_on`](crate::VncServer::listen_quic_on) on a UDP socket
+//! the caller has bound, and runs one session per connection, on a bidirectional stream
+//! the server opens:
+//!
+//! ```ignore
+//! let server_config = quinn::ServerConfig::with_single_cert(certificates, key)?;
+//! server.listen_quic("0.0.0.0:5900".parse()?, server_config).await?;
+//! ```
+//!
+//! The viewer connects and accepts that stream:
+//!
+//! ```ignore
+//! let connection = endpoint.connect(server_addr, "vnc.example.com")?.await?;
+//! let (send, recv) = connection.accept_bi().await?;
+//! ```
+//!
+//! To run the QUIC endpoint yourself, hand the stream to
+//! [`VncServer::serve_secure_stream`](crate::VncServer::serve_secure_stream) rather than
+//! `serve_stream`, so the session counts as encrypted under
+//! `ConnectionConfig::require_encryption`:
+//!
+//! ```ignore
+//! let connection = endpoint.accept().await.unwrap().await?;
+//! let (send, recv) = connection.open_bi().await?;
+//! let peer = connection.remote_address().to_string();
+//! server.serve_secure_stream(tokio::io::join(recv, send), peer).await?;
+//! ```
+//!
+//! Viewers need a matching QUIC transport; standard VNC viewers only speak TCP, so this
+//! suits custom clients or a local TCP-to-QUIC proxy on the viewer side.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::{Buf, Bytes};
+use futures_core::Stream;
+use futures_sink::Sink;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A bidirectional byte stream a client session can run over.
+///
+/// Implemented for every type that is `AsyncRead + AsyncWrite + Send + Sync + Unpin +
+/// 'static`, such as `TcpStream`, TLS streams, Unix sockets or data channel adapters.
+/// Adapters that keep boxed futures internally can store them as
+/// `Pin<Box<dyn Future + Send + Sync>>` to satisfy the `Sync` bound.
+pub trait ClientStream: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static> ClientStream for T {}
+
+/// Largest message a [`MessageStream`] sends by default. 16 KiB is the size every WebRTC
+/// implementation can exchange without fragmentation issues.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024;
+
+/// Adapts a message-oriented channel, such as a WebRTC data channel, to a byte stream.
+///
+/// Bytes written are sent as messages of at most
+/// [`max_message_size`](Self::with_max_message_size) bytes; messages received are read
+/// back to back, ignoring their boundaries. The end of the incoming messages reads as end
+/// of stream, and shutting down closes the sink. See the [module documentation](self).
+pub struct MessageStream<Si, St> {
+    sink: Si,
+    stream: St,
+    /// Unread part of the last message received.
+    pending: Bytes,
+    max_message_size: usize,
+}
+
+impl<Si, St> MessageStream<Si, St> {
+    /// Creates a byte stream over a channel's outgoing and incoming messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Sends messages to the peer.
+    /// * `stream` - Yields the messages received from the peer, in order.
+    pub fn new(sink: Si, stream: St) -> Self {
+        Self {
+            sink,
+            stream,
+            pending: Bytes::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Sets the largest message sent, for channels that accept more (or less) than
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`]. Treated as at least 1.
+    #[must_use]
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size.max(1);
+        self
+    }
+
+    /// Returns the outgoing and incoming message halves.
+    pub fn into_inner(self) -> (Si, St) {
+        (self.sink, self.stream)
+    }
+}
+
+impl<Si: Unpin, St> AsyncRead for MessageStream<Si, St>
+where
+    St: Stream<Item = io::Result<Bytes>> + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.pending.is_empty() {
+            match ready!(Pin::new(&mut this.stream).poll_next(cx)) {
+                Some(Ok(message)) => this.pending = message,
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let n = this.pending.len().min(buf.remaining());
+        buf.put_slice(&this.pending[..n]);
+        this.pending.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<Si, St: Unpin> AsyncWrite for MessageStream<Si, St>
+where
+    Si: Sink<Bytes, Error = io::Error> + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let this = self.get_mut();
+        ready!(Pin::new(&mut this.sink).poll_ready(cx))?;
+        let n = buf.len().min(this.max_message_size);
+        Pin::new(&mut this.sink).start_send(Bytes::copy_from_slice(&buf[..n]))?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().sink).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().sink).poll_close(cx)
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! ZRLE (Zlib Run-Length Encoding, encoding 16), also used for ZYWRLE.
+//!
+//! The rectangle is split into 64x64 tiles, each sent with the cheapest sub-encoding
+//! from [`rle_tile`](crate::rle_tile) ([`encode_tiles`]), and the tile data goes through
+//! the connection's persistent zlib stream, flushed at the end of the rectangle
+//! ([`compress_zlib`](crate::rect_encoder::compress_zlib)):
+//!
+//! ```text
+//! u32  length of the zlib data
+//! u8[] zlib data
+//! ```
+//!
+//! With ZYWRLE, tiles that would be sent raw are replaced by their wavelet coefficients
+//! (see [`zywrle`](crate::zywrle)).
+//!
+//! The ZRLE encoder in `rfb-encodings` writes every pixel as a 3-byte RGB `CPIXEL`
+//! whatever the client's format, so this one is used instead: `CPIXEL`s follow the size
+//! and byte order the client negotiated.
+
+use bytes::BytesMut;
+
+use crate::protocol::PixelFormat;
+use crate::rle_tile::{self, CPixel};
+use crate::zywrle;
+
+/// Width and height of ZRLE tiles.
+const TILE_SIZE: usize = 64;
+
+/// Encodes the tiles of a rectangle with ZRLE, before compression.
+///
+/// The tile data depends only on the pixels and the settings, so it can be shared
+/// between clients before it goes through a connection's zlib stream.
+///
+/// # Arguments
+///
+/// * `data` - The rectangle's pixels, already translated to the client's pixel format.
+/// * `width`, `height` - Size of the rectangle.
+/// * `format` - The client's pixel format.
+/// * `zywrle_level` - ZYWRLE wavelet level (1-3), or 0 for plain ZRLE.
+/// * `tiles` - Receives the tile data.
+pub(crate) fn encode_tiles(
+    data: &[u8],
+    width: u16,
+    height: u16,
+    format: &PixelFormat,
+    zywrle_level: usize,
+    tiles: &mut BytesMut,
+) {
+    let (width, height) = (usize::from(width), usize::from(height));
+    let pixels = rle_tile::read_pixels(data, format);
+    let cpixel = CPixel::new(format);
+    let mut analyzer = zywrle::Analyzer::new(format, zywrle_level);
+
+    tiles.reserve(pixels.len() * cpixel.size() / 2 + 64);
+    let mut tile = Vec::with_capacity(TILE_SIZE * TILE_SIZE);
+    for y in (0..height).step_by(TILE_SIZE) {
+        for x in (0..width).step_by(TILE_SIZE) {
+            let tile_width = (width - x).min(TILE_SIZE);
+            let tile_height = (height - y).min(TILE_SIZE);
+            rle_tile::extract_tile(&pixels, width, (x, y, tile_width, tile_height), &mut tile);
+            let start = tiles.len();
+            rle_tile::encode_tile(tiles, &tile, tile_width, cpixel);
+
+            // ZYWRLE keeps the raw sub-encoding byte and follows it with the tile's
+            // coefficients, encoded as a tile of their own
+            if let Some(analyzer) = analyzer.as_mut() {
+                if tiles[start] == rle_tile::SUBENCODING_RAW {
+                    tiles.truncate(start + 1);
+                    analyzer.analyze(&mut tile, tile_width);
+                    rle_tile::encode_tile(tiles, &tile, tile_width, cpixel);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Experimental Zstandard rectangle encoding.
+//!
+//! A private (unregistered) encoding for deployments where both ends run this stack.
+//! It mirrors the standard Zlib encoding with zstd in place of zlib: pixels are
+//! translated to the client's pixel format exactly as for Raw, then compressed with a
+//! single zstd stream that lives for the whole connection. Each rectangle's payload is
+//!
+//! ```text
+//! u32  length of the compressed data
+//! u8[] zstd stream data, flushed at the end of the rectangle
+//! ```
+//!
+//! so a client feeds every payload into one streaming decompressor and reads exactly
+//! `width * height * bytes_per_pixel` bytes back out. Clients opt in by listing
+//! [`ENCODING_ZSTD`](crate::protocol::ENCODING_ZSTD) in `SetEncodings`.
+
+use bytes::{BufMut, BytesMut};
+use std::io::Write;
+
+/// Persistent zstd compression stream for one client.
+pub struct ZstdStream {
+    encoder: zstd::stream::write::Encoder<'static, Vec<u8>>,
+}
+
+impl ZstdStream {
+    /// Creates a stream for a VNC compression level (0-9).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the zstd context cannot be created.
+    pub fn new(compression_level: u8) -> Result<Self, std::io::Error> {
+        // zstd level 0 means "default" (3); map VNC 0 to the fastest level instead
+        let level = i32::from(compression_level.clamp(1, 9));
+        Ok(Self {
+            encoder: zstd::stream::write::Encoder::new(Vec::new(), level)?,
+        })
+    }
+
+    /// Compresses one rectangle of translated pixel data.
+    ///
+    /// # Returns
+    ///
+    /// The rectangle payload: the compressed length followed by the compressed data.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if compression fails.
+    pub fn encode(&mut self, translated: &[u8]) -> Result<BytesMut, std::io::Error> {
+        self.encoder.write_all(translated)?;
+        self.encoder.flush()?;
+        let compressed = std::mem::take(self.encoder.get_mut());
+
+        let length = u32::try_from(compressed.len())
+            .map_err(|_| std::io::Error::other("Compressed rectangle too large"))?;
+        let mut buf = BytesMut::with_capacity(4 + compressed.len());
+        buf.put_u32(length);
+        buf.put_slice(&compressed);
+        Ok(buf)
+    }
+}
@@ -0,0 +1,527 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! ZYWRLE (Zlib+Wavelet+Run-Length Encoding, encoding 17) wavelet analysis.
+//!
+//! ZYWRLE is ZRLE in which every tile that would be sent raw is replaced by its wavelet
+//! coefficients: the raw sub-encoding byte is followed by the coefficients, themselves
+//! encoded as a ZRLE tile. The client decodes that inner tile and runs the inverse
+//! transform on it.
+//!
+//! The colors go through a reversible color transform (RCT) to Y, U and V, then through
+//! `level` passes of the Piecewise-Linear Haar (`PLHarr`) wavelet, and the high
+//! frequency bands are quantized. The coefficients are packed into pixels of the
+//! client's format by bit position, as the reference implementation does: V in the
+//! red bits, Y in the green bits, U in the blue bits. In 15 and 16 bit formats each
+//! component has only 5 or 6 bits, so the transform masks Y, U and V to the bits that
+//! survive packing.
+//!
+//! `rfb-encodings` only transforms RGBA32 pixels, whose coefficients are then rounded
+//! by the translation to 15/16 bit formats and packed by channel rather than by bit
+//! position, so this one is used instead.
+//!
+//! # Algorithm Attribution
+//!
+//! The ZYWRLE algorithm is Copyright 2006 by Hitachi Systems & Services, Ltd.
+//! (Noriaki Yamazaki, Research & Development Center).
+
+use crate::protocol::PixelFormat;
+
+/// Non-linear quantization filter lookup tables.
+/// These tables implement r=2.0 non-linear quantization (quantize is x^2, dequantize is sqrt(x)).
+/// The tables map input coefficient values [0..255] to quantized-dequantized (filtered) values.
+///
+/// Table selection based on quality level:
+/// - `zywrle_conv`[0]: bi=5, bo=5 r=0.0:PSNR=24.849 (zero everything, highest compression)
+/// - `zywrle_conv`[1]: bi=5, bo=5 r=2.0:PSNR=74.031 (good quality)
+/// - `zywrle_conv`[2]: bi=5, bo=4 r=2.0:PSNR=64.441 (medium quality)
+/// - `zywrle_conv`[3]: bi=5, bo=2 r=2.0:PSNR=43.175 (low quality, highest compression)
+const ZYWRLE_CONV: [[i8; 256]; 4] = [
+    [
+        // bi=5, bo=5 r=0.0:PSNR=24.849
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [
+        // bi=5, bo=5 r=2.0:PSNR=74.031
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 32, 32, 32, 32, 32, 32,
+        32, 32, 32, 32, 32, 32, 32, 32, 32, 32, 32, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 56,
+        56, 56, 56, 56, 56, 56, 56, 56, 64, 64, 64, 64, 64, 64, 64, 64, 72, 72, 72, 72, 72, 72, 72,
+        72, 80, 80, 80, 80, 80, 80, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 96, 96, 96, 96,
+        96, 104, 104, 104, 104, 104, 104, 104, 104, 104, 104, 112, 112, 112, 112, 112, 112, 112,
+        112, 112, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 0, -120, -120, -120, -120,
+        -120, -120, -120, -120, -120, -120, -112, -112, -112, -112, -112, -112, -112, -112, -112,
+        -104, -104, -104, -104, -104, -104, -104, -104, -104, -104, -96, -96, -96, -96, -96, -88,
+        -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -80, -80, -80, -80, -80, -80, -72,
+        -72, -72, -72, -72, -72, -72, -72, -64, -64, -64, -64, -64, -64, -64, -64, -56, -56, -56,
+        -56, -56, -56, -56, -56, -56, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -32,
+        -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [
+        // bi=5, bo=4 r=2.0:PSNR=64.441
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48,
+        48, 48, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 80, 80, 80, 80, 80,
+        80, 80, 80, 80, 80, 80, 80, 80, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 104, 104, 104,
+        104, 104, 104, 104, 104, 104, 104, 104, 112, 112, 112, 112, 112, 112, 112, 112, 112, 120,
+        120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 0, -120, -120, -120, -120, -120,
+        -120, -120, -120, -120, -120, -120, -120, -112, -112, -112, -112, -112, -112, -112, -112,
+        -112, -104, -104, -104, -104, -104, -104, -104, -104, -104, -104, -104, -88, -88, -88, -88,
+        -88, -88, -88, -88, -88, -88, -88, -80, -80, -80, -80, -80, -80, -80, -80, -80, -80, -80,
+        -80, -80, -64, -64, -64, -64, -64, -64, -64, -64, -64, -64, -64, -64, -64, -64, -64, -64,
+        -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48, -48,
+        -48, -48, -48, -48, -48, -48, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [
+        // bi=5, bo=2 r=2.0:PSNR=43.175
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88,
+        88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88,
+        88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 88, 0, -88,
+        -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88,
+        -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88,
+        -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88, -88,
+        -88, -88, -88, -88, -88, -88, -88, -88, -88, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+];
+
+/// Filter parameter tables indexed by [level-1][l][channel].
+/// Maps quality level and wavelet level to the appropriate quantization filter.
+const ZYWRLE_PARAM: [[[usize; 3]; 3]; 3] = [
+    [[0, 2, 0], [0, 0, 0], [0, 0, 0]], // level 1
+    [[0, 3, 0], [1, 1, 1], [0, 0, 0]], // level 2
+    [[0, 3, 0], [2, 2, 2], [1, 1, 1]], // level 3
+];
+
+/// Returns the wavelet level (1-3) for a VNC quality level (0-9, or 255 for unset).
+///
+/// Lower quality levels run more wavelet passes, which quantize more of the image.
+pub(crate) fn level_for_quality(quality_level: u8) -> usize {
+    match quality_level {
+        0..=2 => 3,
+        3..=5 => 2,
+        _ => 1,
+    }
+}
+
+/// How a pixel format holds the three components ZYWRLE transforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    /// 8 bits each in bits 16-23, 8-15 and 0-7 (32 bits per pixel).
+    Rgb888,
+    /// 5, 6 and 5 bits in bits 11-15, 5-10 and 0-4.
+    Rgb565,
+    /// 5 bits each in bits 10-14, 5-9 and 0-4.
+    Rgb555,
+}
+
+impl Layout {
+    /// Returns the masks applied to Y and to U/V so they survive packing.
+    fn masks(self) -> (i32, i32) {
+        match self {
+            Self::Rgb888 => (!0, !0),
+            Self::Rgb565 => (!3, !7),
+            Self::Rgb555 => (!7, !7),
+        }
+    }
+
+    /// Reads the components of a pixel as 8-bit values.
+    #[allow(clippy::cast_possible_wrap)] // Components are masked to 8 bits
+    fn load(self, pixel: u32) -> (i32, i32, i32) {
+        let (r, g, b) = match self {
+            Self::Rgb888 => (pixel >> 16, pixel >> 8, pixel),
+            Self::Rgb565 => (
+                (pixel >> 8) & 0xf8,
+                (pixel >> 3) & 0xfc,
+                (pixel << 3) & 0xf8,
+            ),
+            Self::Rgb555 => (
+                (pixel >> 7) & 0xf8,
+                (pixel >> 2) & 0xf8,
+                (pixel << 3) & 0xf8,
+            ),
+        };
+        ((r & 0xff) as i32, (g & 0xff) as i32, (b & 0xff) as i32)
+    }
+
+    /// Packs 8-bit components into a pixel, dropping the bits the format lacks.
+    #[allow(clippy::cast_sign_loss)] // Coefficients are packed as their byte values
+    fn store(self, r: i8, g: i8, b: i8) -> u32 {
+        let (r, g, b) = (u32::from(r as u8), u32::from(g as u8), u32::from(b as u8));
+        match self {
+            Self::Rgb888 => r << 16 | g << 8 | b,
+            Self::Rgb565 => (r & 0xf8) << 8 | (g & 0xfc) << 3 | b >> 3,
+            Self::Rgb555 => (r & 0xf8) << 7 | (g & 0xf8) << 2 | b >> 3,
+        }
+    }
+}
+
+/// Wavelet analysis of ZRLE tiles for one rectangle.
+pub(crate) struct Analyzer {
+    layout: Layout,
+    level: usize,
+    /// U, Y and V coefficients of the tile's aligned part, row by row.
+    coeffs: Vec<[i8; 3]>,
+    /// The transformed tile being assembled.
+    packed: Vec<u32>,
+}
+
+impl Analyzer {
+    /// Creates an analyzer, or `None` if the format has no ZYWRLE transform.
+    ///
+    /// As in the reference implementation, 8 bit formats are sent as plain ZRLE, and
+    /// 16 bit formats are read as RGB565 if the green maximum exceeds 5 bits and as
+    /// RGB555 otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The client's pixel format.
+    /// * `level` - Number of wavelet passes (1-3); 0 disables the transform.
+    pub(crate) fn new(format: &PixelFormat, level: usize) -> Option<Self> {
+        let layout = match format.bits_per_pixel {
+            32 => Layout::Rgb888,
+            16 if format.green_max > 0x1f => Layout::Rgb565,
+            16 => Layout::Rgb555,
+            _ => return None,
+        };
+        if level == 0 {
+            return None;
+        }
+        Some(Self {
+            layout,
+            level: level.min(3),
+            coeffs: Vec::new(),
+            packed: Vec::new(),
+        })
+    }
+
+    /// Replaces a tile's pixels with its wavelet coefficients.
+    ///
+    /// Only the part of the tile whose size is a multiple of `2^level` is transformed.
+    /// Its coefficients come first, band by band from the finest level, followed by the
+    /// remaining pixels unchanged: the right edge, the bottom edge, then the corner.
+    /// A tile smaller than `2^level` in either direction is left as it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `tile` - The tile's pixels in the client's format, row by row.
+    /// * `width` - Width of the tile in pixels.
+    #[allow(clippy::many_single_char_names)] // Mirrors the variable names of the reference implementation
+    pub(crate) fn analyze(&mut self, tile: &mut [u32], width: usize) {
+        let height = tile.len() / width.max(1);
+        let align = !((1 << self.level) - 1);
+        let (w, h) = (width & align, height & align);
+        if w == 0 || h == 0 {
+            return;
+        }
+
+        let (ymask, uvmask) = self.layout.masks();
+        self.coeffs.clear();
+        for row in tile.chunks_exact(width).take(h) {
+            for &pixel in &row[..w] {
+                let (r, g, b) = self.layout.load(pixel);
+                self.coeffs.push(rgb_to_yuv(r, g, b, ymask, uvmask));
+            }
+        }
+        wavelet(&mut self.coeffs, w, h, self.level);
+
+        self.packed.clear();
+        for l in 0..self.level {
+            let bands: &[usize] = if l == self.level - 1 {
+                &[3, 2, 1, 0]
+            } else {
+                &[3, 2, 1]
+            };
+            for &band in bands {
+                for &[u, y, v] in band_coefficients(&self.coeffs, w, h, l, band) {
+                    self.packed.push(self.layout.store(v, y, u));
+                }
+            }
+        }
+        for row in tile.chunks_exact(width).take(h) {
+            self.packed.extend_from_slice(&row[w..]);
+        }
+        for row in tile.chunks_exact(width).skip(h) {
+            self.packed.extend_from_slice(&row[..w]);
+        }
+        for row in tile.chunks_exact(width).skip(h) {
+            self.packed.extend_from_slice(&row[w..]);
+        }
+        tile.copy_from_slice(&self.packed);
+    }
+}
+
+/// Converts a color to U, Y and V with the RCT, masked to the bits that are packed.
+#[allow(clippy::cast_possible_truncation)] // Y, U and V are in -128..=127
+#[allow(clippy::many_single_char_names)] // Mirrors the variable names of the reference implementation
+fn rgb_to_yuv(r: i32, g: i32, b: i32, ymask: i32, uvmask: i32) -> [i8; 3] {
+    let mut y = (((r + (g << 1) + b) >> 2) - 128) & ymask;
+    let mut u = ((b - g) >> 1) & uvmask;
+    let mut v = ((r - g) >> 1) & uvmask;
+    // Masking can't round -128 further down, so round it up to keep the range symmetric
+    if y == -128 {
+        y -= ymask;
+    }
+    if u == -128 {
+        u -= uvmask;
+    }
+    if v == -128 {
+        v -= uvmask;
+    }
+    [u as i8, y as i8, v as i8]
+}
+
+/// Piecewise-Linear Haar (`PLHarr`) transform of two coefficients: `x0` receives the
+/// low band and `x1` the high band.
+#[allow(clippy::cast_possible_truncation)] // Only the low byte is kept, as in the reference
+fn harr(x0: &mut i8, x1: &mut i8) {
+    let (orig0, orig1) = (i32::from(*x0), i32::from(*x1));
+    let (mut a, mut b) = (orig0, orig1);
+    if (a ^ b) & 0x80 != 0 {
+        // Different signs
+        b += a;
+        if (b ^ orig1) & 0x80 == 0 {
+            a -= b;
+        }
+    } else {
+        // Same sign
+        a -= b;
+        if (a ^ orig0) & 0x80 == 0 {
+            b += a;
+        }
+    }
+    *x0 = b as i8;
+    *x1 = a as i8;
+}
+
+/// Runs one wavelet pass along a line of `size` coefficients, `skip` apart.
+fn wavelet_level(coeffs: &mut [[i8; 3]], start: usize, size: usize, l: usize, skip: usize) {
+    let step = (2 << l) * skip;
+    let offset = (1 << l) * skip;
+    for i in 0..size >> (l + 1) {
+        let low = start + i * step;
+        let (head, tail) = coeffs.split_at_mut(low + offset);
+        for (x0, x1) in head[low].iter_mut().zip(tail[0].iter_mut()) {
+            harr(x0, x1);
+        }
+    }
+}
+
+/// Runs `level` wavelet passes over the rows and columns, quantizing after each.
+fn wavelet(coeffs: &mut [[i8; 3]], width: usize, height: usize, level: usize) {
+    for l in 0..level {
+        for y in (0..height).step_by(1 << l) {
+            wavelet_level(coeffs, y * width, width, l, 1);
+        }
+        for x in (0..width).step_by(1 << l) {
+            wavelet_level(coeffs, x, height, l, width);
+        }
+        filter_wavelet_square(coeffs, width, height, level, l);
+    }
+}
+
+/// Returns the index of the first coefficient of `band` at wavelet level `l`: 0 is the
+/// low band, 1 the horizontal, 2 the vertical and 3 the diagonal high band.
+fn band_start(width: usize, l: usize, band: usize) -> usize {
+    let half = 1 << l;
+    let mut start = 0;
+    if band & 1 != 0 {
+        start += half;
+    }
+    if band & 2 != 0 {
+        start += half * width;
+    }
+    start
+}
+
+/// Iterates over the coefficients of a band, row by row.
+fn band_coefficients(
+    coeffs: &[[i8; 3]],
+    width: usize,
+    height: usize,
+    l: usize,
+    band: usize,
+) -> impl Iterator<Item = &[i8; 3]> {
+    let step = 2 << l;
+    let start = band_start(width, l, band);
+    (0..height / step).flat_map(move |y| {
+        (0..width / step).map(move |x| &coeffs[start + y * step * width + x * step])
+    })
+}
+
+/// Quantizes the high bands of wavelet level `l`.
+#[allow(clippy::cast_sign_loss)] // Coefficients index the tables by their byte values
+fn filter_wavelet_square(
+    coeffs: &mut [[i8; 3]],
+    width: usize,
+    height: usize,
+    level: usize,
+    l: usize,
+) {
+    let tables = ZYWRLE_PARAM[level - 1][l];
+    let step = 2 << l;
+    for band in 1..4 {
+        let start = band_start(width, l, band);
+        for y in 0..height / step {
+            for x in 0..width / step {
+                let coeff = &mut coeffs[start + y * step * width + x * step];
+                for (value, &table) in coeff.iter_mut().zip(&tables) {
+                    *value = ZYWRLE_CONV[table][usize::from(*value as u8)];
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Converts U, Y and V back to a pixel, as the reference implementation's client does.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss
+    )] // Components are clamped to bytes
+    #[allow(clippy::many_single_char_names)] // Mirrors the variable names of the reference implementation
+    fn yuv_to_pixel(layout: Layout, [u, y, v]: [i8; 3]) -> u32 {
+        let (u, y, v) = (i32::from(u) << 1, i32::from(y) + 128, i32::from(v) << 1);
+        let g = (y - ((u + v) >> 2)).clamp(0, 255);
+        let (r, b) = ((v + g).clamp(0, 255), (u + g).clamp(0, 255));
+        layout.store(r as u8 as i8, g as u8 as i8, b as u8 as i8)
+    }
+
+    /// Inverts `Analyzer::analyze` as a client would, after the reference
+    /// implementation's synthesis: `PLHarr` is its own inverse, applied from the coarsest
+    /// level with columns before rows.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss
+    )] // Components are bytes
+    #[allow(clippy::many_single_char_names)] // Mirrors the variable names of the reference implementation
+    fn synthesize(layout: Layout, level: usize, tile: &mut [u32], width: usize) {
+        let height = tile.len() / width;
+        let align = !((1 << level) - 1);
+        let (w, h) = (width & align, height & align);
+        if w == 0 || h == 0 {
+            return;
+        }
+
+        let mut packed = tile.iter().copied();
+        let mut coeffs = vec![[0i8; 3]; w * h];
+        for l in 0..level {
+            let bands: &[usize] = if l == level - 1 {
+                &[3, 2, 1, 0]
+            } else {
+                &[3, 2, 1]
+            };
+            let step = 2 << l;
+            for &band in bands {
+                let start = band_start(w, l, band);
+                for row in 0..h / step {
+                    for col in 0..w / step {
+                        let (v, y, u) = layout.load(packed.next().unwrap());
+                        coeffs[start + row * step * w + col * step] =
+                            [u as u8 as i8, y as u8 as i8, v as u8 as i8];
+                    }
+                }
+            }
+        }
+        for l in (0..level).rev() {
+            for x in (0..w).step_by(1 << l) {
+                wavelet_level(&mut coeffs, x, h, l, w);
+            }
+            for y in (0..h).step_by(1 << l) {
+                wavelet_level(&mut coeffs, y * w, w, l, 1);
+            }
+        }
+
+        let mut pixels = vec![0; tile.len()];
+        for (i, &coeff) in coeffs.iter().enumerate() {
+            pixels[(i / w) * width + i % w] = yuv_to_pixel(layout, coeff);
+        }
+        for row in pixels.chunks_exact_mut(width).take(h) {
+            for pixel in &mut row[w..] {
+                *pixel = packed.next().unwrap();
+            }
+        }
+        for row in pixels.chunks_exact_mut(width).skip(h) {
+            for pixel in &mut row[..w] {
+                *pixel = packed.next().unwrap();
+            }
+        }
+        for row in pixels.chunks_exact_mut(width).skip(h) {
+            for pixel in &mut row[w..] {
+                *pixel = packed.next().unwrap();
+            }
+        }
+        tile.copy_from_slice(&pixels);
+    }
+
+    /// The wavelet passes must not change a flat tile: its high bands are zero, so only
+    /// the color transform, which drops the low bits of Y, U and V, affects the result.
+    #[test]
+    fn flat_16bpp_tiles_round_trip_at_every_level() {
+        for (layout, max) in [(Layout::Rgb565, 0xffff), (Layout::Rgb555, 0x7fff)] {
+            let (ymask, uvmask) = layout.masks();
+            for color in (0..=max).step_by(61).chain([max, 0x4210]) {
+                let (r, g, b) = layout.load(color);
+                let expected = yuv_to_pixel(layout, rgb_to_yuv(r, g, b, ymask, uvmask));
+                for level in 1..=3 {
+                    for (width, height) in [(16, 16), (13, 10)] {
+                        let mut analyzer = Analyzer {
+                            layout,
+                            level,
+                            coeffs: Vec::new(),
+                            packed: Vec::new(),
+                        };
+                        let mut tile = vec![color; width * height];
+                        analyzer.analyze(&mut tile, width);
+                        synthesize(layout, level, &mut tile, width);
+                        // Pixels outside the aligned part are sent as they are
+                        let aligned = !((1 << level) - 1);
+                        for (i, &pixel) in tile.iter().enumerate() {
+                            let inside =
+                                i % width < width & aligned && i / width < height & aligned;
+                            assert_eq!(
+                                pixel,
+                                if inside { expected } else { color },
+                                "{layout:?} color {color:#06x} level {level} {width}x{height}"
+                            );
+                        }
+                    }
+                }
+            }
+            // Grays survive the color transform too
+            let (r, g, b) = layout.load(0x4210);
+            assert_eq!(
+                yuv_to_pixel(layout, rgb_to_yuv(r, g, b, ymask, uvmask)),
+                0x4210
+            );
+        }
+    }
+}
@@ -0,0 +1,38 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The checked-in C header matches the one generated from the `capi` module.
+//!
+//! Run with `UPDATE_C_HEADER=1` to refresh `include/rustvncserver.h`.
+
+#![cfg(feature = "capi")]
+
+use std::path::Path;
+
+#[test]
+fn checked_in_header_is_up_to_date() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/rustvncserver.h"));
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("include/rustvncserver.h");
+
+    if std::env::var_os("UPDATE_C_HEADER").is_some() {
+        std::fs::write(&path, generated).unwrap();
+        return;
+    }
+    let checked_in = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        checked_in == generated,
+        "include/rustvncserver.h is out of date; refresh it with \
+         `UPDATE_C_HEADER=1 cargo test --features capi --test c_header`"
+    );
+}
@@ -0,0 +1,65 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fixtures shared by the integration tests.
+
+use std::sync::Arc;
+
+use rustvncserver::VncServer;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Starts `server` on an ephemeral loopback port and returns the port.
+///
+/// The listener is bound before the server takes it over, so clients can connect as
+/// soon as this returns.
+pub async fn listen(server: &Arc<VncServer>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = Arc::clone(server);
+    tokio::spawn(async move { server.listen_on(listener).await });
+    port
+}
+
+/// Completes the handshake (no authentication) as a client and sends `SetEncodings`
+/// with `encodings`.
+pub async fn connect(port: u16, encodings: &[i32]) -> TcpStream {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+    let mut version = [0u8; 12];
+    stream.read_exact(&mut version).await.unwrap();
+    stream.write_all(b"RFB 003.008\n").await.unwrap();
+    let mut security_types = [0u8; 2];
+    stream.read_exact(&mut security_types).await.unwrap();
+    stream.write_all(&[1]).await.unwrap(); // None
+    let mut security_result = [0u8; 4];
+    stream.read_exact(&mut security_result).await.unwrap();
+    assert_eq!(security_result, [0, 0, 0, 0]);
+    stream.write_all(&[1]).await.unwrap(); // ClientInit, shared
+
+    let mut server_init = [0u8; 24];
+    stream.read_exact(&mut server_init).await.unwrap();
+    let name_len = u32::from_be_bytes(server_init[20..24].try_into().unwrap());
+    let mut name = vec![0u8; name_len as usize];
+    stream.read_exact(&mut name).await.unwrap();
+
+    let count = u16::try_from(encodings.len()).unwrap();
+    let mut message = vec![2, 0];
+    message.extend_from_slice(&count.to_be_bytes());
+    for encoding in encodings {
+        message.extend_from_slice(&encoding.to_be_bytes());
+    }
+    stream.write_all(&message).await.unwrap();
+    stream
+}
@@ -0,0 +1,136 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `MessageStream` over an in-memory message channel, standing in for a data channel.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_sink::Sink;
+use rustvncserver::transport::MessageStream;
+use rustvncserver::VncServer;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// Outgoing messages of one end of the channel.
+struct Outgoing(mpsc::UnboundedSender<Bytes>);
+
+impl Sink<Bytes> for Outgoing {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: Bytes) -> io::Result<()> {
+        self.0
+            .send(message)
+            .map_err(|_| io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Incoming messages of one end of the channel.
+struct Incoming(mpsc::UnboundedReceiver<Bytes>);
+
+impl Stream for Incoming {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx).map(|message| message.map(Ok))
+    }
+}
+
+/// Creates both ends of a message channel.
+fn channel() -> (
+    MessageStream<Outgoing, Incoming>,
+    MessageStream<Outgoing, Incoming>,
+) {
+    let (a_tx, a_rx) = mpsc::unbounded_channel();
+    let (b_tx, b_rx) = mpsc::unbounded_channel();
+    (
+        MessageStream::new(Outgoing(a_tx), Incoming(b_rx)),
+        MessageStream::new(Outgoing(b_tx), Incoming(a_rx)),
+    )
+}
+
+#[tokio::test]
+async fn writes_are_split_into_messages_and_read_back_as_bytes() {
+    let (tx, mut sent) = mpsc::unbounded_channel();
+    let (_, rx) = mpsc::unbounded_channel();
+    let mut stream = MessageStream::new(Outgoing(tx), Incoming(rx)).with_max_message_size(5);
+
+    stream.write_all(b"hello, world").await.unwrap();
+    let mut sizes = Vec::new();
+    while let Ok(message) = sent.try_recv() {
+        sizes.push(message.len());
+    }
+    assert_eq!(sizes, [5, 5, 2]);
+
+    // Reads ignore message boundaries and end with the incoming messages
+    let (tx, rx) = mpsc::unbounded_channel();
+    let (sink, _) = mpsc::unbounded_channel();
+    for message in ["RFB ", "003.", "", "008\n"] {
+        tx.send(Bytes::from(message)).unwrap();
+    }
+    drop(tx);
+    let mut stream = MessageStream::new(Outgoing(sink), Incoming(rx));
+    let mut read = Vec::new();
+    stream.read_to_end(&mut read).await.unwrap();
+    assert_eq!(read, b"RFB 003.008\n");
+}
+
+#[tokio::test]
+async fn session_runs_over_messages() {
+    let (server, _events) = VncServer::new(64, 32, "test".to_string(), None);
+    let server = Arc::new(server);
+    let (server_end, mut viewer) = channel();
+    tokio::spawn(async move {
+        server
+            .serve_secure_stream(server_end, "channel".to_string())
+            .await
+    });
+
+    let mut version = [0u8; 12];
+    tokio::time::timeout(Duration::from_secs(2), viewer.read_exact(&mut version))
+        .await
+        .expect("no protocol version")
+        .unwrap();
+    assert_eq!(&version, b"RFB 003.008\n");
+    viewer.write_all(b"RFB 003.008\n").await.unwrap();
+    let mut security_types = [0u8; 2];
+    viewer.read_exact(&mut security_types).await.unwrap();
+    assert_eq!(security_types, [1, 1]);
+    viewer.write_all(&[1]).await.unwrap();
+    let mut security_result = [0u8; 4];
+    viewer.read_exact(&mut security_result).await.unwrap();
+    assert_eq!(security_result, [0, 0, 0, 0]);
+    viewer.write_all(&[1]).await.unwrap(); // ClientInit, shared
+
+    let mut server_init = [0u8; 24];
+    viewer.read_exact(&mut server_init).await.unwrap();
+    assert_eq!(&server_init[..4], &[0, 64, 0, 32]);
+}
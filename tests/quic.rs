@@ -0,0 +1,68 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! RFB sessions over the QUIC listener.
+
+#![cfg(feature = "quic")]
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+
+use quinn::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustvncserver::{ServerConfig, VncServer};
+
+#[tokio::test]
+async fn quic_session_counts_as_encrypted() {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let certificate = CertificateDer::from(certified.cert);
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+    let server_config =
+        quinn::ServerConfig::with_single_cert(vec![certificate.clone()], key).unwrap();
+
+    // Plain sessions are refused, even from loopback
+    let mut config = ServerConfig::default();
+    config.connection.require_encryption = true;
+    config.connection.encryption_exempt.clear();
+    let (mut server, _events) = VncServer::new(64, 32, "test".to_string(), None);
+    server.set_config(config);
+    let server = Arc::new(server);
+
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = socket.local_addr().unwrap();
+    let listener = Arc::clone(&server);
+    tokio::spawn(async move { listener.listen_quic_on(socket, server_config).await });
+
+    let mut roots = quinn::rustls::RootCertStore::empty();
+    roots.add(certificate).unwrap();
+    let mut endpoint = quinn::Endpoint::client((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+    endpoint.set_default_client_config(
+        quinn::ClientConfig::with_root_certificates(Arc::new(roots)).unwrap(),
+    );
+    let connection = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+    let (mut send, mut recv) = connection.accept_bi().await.unwrap();
+
+    let mut version = [0u8; 12];
+    tokio::time::timeout(Duration::from_secs(2), recv.read_exact(&mut version))
+        .await
+        .expect("no protocol version")
+        .unwrap();
+    assert_eq!(&version, b"RFB 003.008\n");
+    send.write_all(b"RFB 003.008\n").await.unwrap();
+
+    // An empty list would mean the session was refused as unencrypted
+    let mut security_types = [0u8; 2];
+    recv.read_exact(&mut security_types).await.unwrap();
+    assert_eq!(security_types, [1, 1]);
+}
@@ -0,0 +1,85 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tight regions encoded once for several clients.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustvncserver::VncServer;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const WIDTH: u16 = 64;
+const HEIGHT: u16 = 32;
+
+/// Requests the whole framebuffer and returns everything the server sends for it.
+async fn full_update(stream: &mut TcpStream) -> Vec<u8> {
+    let mut message = vec![3, 0, 0, 0, 0, 0];
+    message.extend_from_slice(&WIDTH.to_be_bytes());
+    message.extend_from_slice(&HEIGHT.to_be_bytes());
+    stream.write_all(&message).await.unwrap();
+
+    let mut update = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let timeout = if update.is_empty() { 2000 } else { 300 };
+        match tokio::time::timeout(Duration::from_millis(timeout), stream.read(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => update.extend_from_slice(&buf[..n]),
+            _ => break,
+        }
+    }
+    assert!(!update.is_empty(), "no framebuffer update");
+    update
+}
+
+#[tokio::test]
+async fn clients_with_the_same_settings_share_one_encoding() {
+    let (server, _events) = VncServer::new(WIDTH, HEIGHT, "test".to_string(), None);
+    let server = Arc::new(server);
+
+    // A gradient with some noise, so the region needs zlib
+    let pixels: Vec<u8> = (0..usize::from(WIDTH) * usize::from(HEIGHT))
+        .flat_map(|i| {
+            let (x, y) = (i % usize::from(WIDTH), i / usize::from(WIDTH));
+            [(x * 4) as u8, (y * 8) as u8, (x * y % 7 * 30) as u8, 255]
+        })
+        .collect();
+    server
+        .framebuffer()
+        .update_from_slice(&pixels)
+        .await
+        .unwrap();
+
+    let port = common::listen(&server).await;
+    let mut first = common::connect(port, &[7]).await; // Tight
+    let mut second = common::connect(port, &[7]).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    server.encode_stats().reset();
+
+    let first_update = full_update(&mut first).await;
+    let second_update = full_update(&mut second).await;
+    assert_eq!(first_update, second_update);
+    assert_eq!(server.encode_stats().encode.count(), 1);
+
+    // The first rectangle's control byte resets all four zlib streams
+    assert_eq!(
+        &first_update[12..16],
+        &[0, 0, 0, 7],
+        "expected Tight encoding"
+    );
+    assert_eq!(first_update[16] & 0x0F, 0x0F);
+}
@@ -0,0 +1,105 @@
+// Copyright 2025 Dustin McAfee
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `FramebufferUpdateRequest` handling for requests that reach outside the framebuffer.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustvncserver::VncServer;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const WIDTH: u16 = 64;
+const HEIGHT: u16 = 32;
+
+/// Starts a server on an ephemeral port and completes the handshake (no authentication,
+/// Raw encoding) as a client.
+async fn connect() -> (Arc<VncServer>, TcpStream) {
+    let (server, _events) = VncServer::new(WIDTH, HEIGHT, "test".to_string(), None);
+    let server = Arc::new(server);
+    let port = common::listen(&server).await;
+    let stream = common::connect(port, &[0]).await; // Raw
+    (server, stream)
+}
+
+/// Sends a non-incremental `FramebufferUpdateRequest`.
+async fn request(stream: &mut TcpStream, x: u16, y: u16, width: u16, height: u16) {
+    let mut message = vec![3, 0];
+    for value in [x, y, width, height] {
+        message.extend_from_slice(&value.to_be_bytes());
+    }
+    stream.write_all(&message).await.unwrap();
+}
+
+/// Reads one `FramebufferUpdate` and returns the rectangles' `(x, y, width, height)`.
+async fn read_update(stream: &mut TcpStream) -> Vec<(u16, u16, u16, u16)> {
+    let mut header = [0u8; 4];
+    tokio::time::timeout(Duration::from_secs(2), stream.read_exact(&mut header))
+        .await
+        .expect("no framebuffer update")
+        .unwrap();
+    assert_eq!(header[0], 0, "expected a FramebufferUpdate");
+
+    let mut rects = Vec::new();
+    for _ in 0..u16::from_be_bytes([header[2], header[3]]) {
+        let mut rect = [0u8; 12];
+        stream.read_exact(&mut rect).await.unwrap();
+        let field = |i: usize| u16::from_be_bytes([rect[i], rect[i + 1]]);
+        let (x, y, width, height) = (field(0), field(2), field(4), field(6));
+        assert_eq!(&rect[8..12], &[0, 0, 0, 0], "expected Raw encoding");
+        let mut pixels = vec![0u8; usize::from(width) * usize::from(height) * 4];
+        stream.read_exact(&mut pixels).await.unwrap();
+        rects.push((x, y, width, height));
+    }
+    rects
+}
+
+/// Asserts that the server sends nothing for a while.
+async fn assert_no_update(stream: &mut TcpStream) {
+    let mut byte = [0u8; 1];
+    let read = tokio::time::timeout(Duration::from_millis(300), stream.read(&mut byte)).await;
+    assert!(read.is_err(), "unexpected data from server");
+}
+
+#[tokio::test]
+async fn partially_outside_request_is_clamped() {
+    let (_server, mut stream) = connect().await;
+    request(&mut stream, 48, 16, 100, 100).await;
+    assert_eq!(read_update(&mut stream).await, vec![(48, 16, 16, 16)]);
+}
+
+#[tokio::test]
+async fn zero_size_request_is_ignored() {
+    let (_server, mut stream) = connect().await;
+    request(&mut stream, 10, 10, 0, 0).await;
+    assert_no_update(&mut stream).await;
+
+    // The connection is still usable
+    request(&mut stream, 0, 0, WIDTH, HEIGHT).await;
+    assert_eq!(read_update(&mut stream).await, vec![(0, 0, WIDTH, HEIGHT)]);
+}
+
+#[tokio::test]
+async fn out_of_range_request_is_ignored() {
+    let (_server, mut stream) = connect().await;
+    request(&mut stream, WIDTH, 0, 10, 10).await;
+    request(&mut stream, u16::MAX, u16::MAX, u16::MAX, u16::MAX).await;
+    assert_no_update(&mut stream).await;
+
+    request(&mut stream, 0, 0, WIDTH, HEIGHT).await;
+    assert_eq!(read_update(&mut stream).await, vec![(0, 0, WIDTH, HEIGHT)]);
+}